@@ -0,0 +1,995 @@
+//! Pipeline builder
+//!
+//! Assembles a `CandidatePipeline` stage by stage instead of by hand. Components
+//! are added in execution order within their stage, and `build` checks that the
+//! pipeline is complete before it can serve. Components are held behind `Arc`s,
+//! so a built pipeline can be turned back into a builder and cloned with some
+//! stages substituted, e.g. for an experiment, sharing everything else.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use tonic::async_trait;
+
+use crate::candidate_pipeline::{CandidatePipeline, HasRequestId};
+use crate::dedup::DedupStage;
+use crate::error::PipelineError;
+use crate::error_policy::ErrorPolicies;
+use crate::fan_in::FanInPolicy;
+use crate::filter::{Filter, FilterResult};
+use crate::filter_optimizer::FilterOrderOptimizer;
+use crate::hydrator::Hydrator;
+use crate::kill_switch::KillSwitches;
+use crate::memo::fingerprint;
+use crate::metrics::PipelineMetrics;
+use crate::query_hydrator::{execution_levels, QueryHydrator};
+use crate::retry::{RetryPolicies, RetryPolicy, RetryingHydrator, RetryingSource};
+use crate::scorer::Scorer;
+use crate::selector::Selector;
+use crate::side_effect::{SideEffect, SideEffectInput};
+use crate::side_effect_executor::{SideEffectExecutor, SideEffectExecutorConfig};
+use crate::source::Source;
+use crate::stage_timeout::StageTimeouts;
+
+/// Box each shared component of a stage as the trait object the pipeline holds
+macro_rules! boxed {
+    ($stage:expr) => {
+        $stage.iter().map(|c| Box::new(c.clone()) as _).collect()
+    };
+}
+
+/// Fluent assembly of a `Pipeline`
+pub struct PipelineBuilder<Q, C> {
+    query_hydrators: Vec<Arc<dyn QueryHydrator<Q>>>,
+    sources: Vec<Arc<dyn Source<Q, C>>>,
+    hydrators: Vec<Arc<dyn Hydrator<Q, C>>>,
+    filters: Vec<Arc<dyn Filter<Q, C>>>,
+    scorers: Vec<Arc<dyn Scorer<Q, C>>>,
+    selector: Option<Arc<dyn Selector<Q, C>>>,
+    post_selection_hydrators: Vec<Arc<dyn Hydrator<Q, C>>>,
+    post_selection_filters: Vec<Arc<dyn Filter<Q, C>>>,
+    side_effects: Vec<Arc<dyn SideEffect<Q, C>>>,
+    result_size: Option<usize>,
+    optimize_filters: bool,
+    streaming: bool,
+    dedup: Option<DedupStage<C>>,
+    stage_timeouts: Option<StageTimeouts>,
+    fan_in: Option<FanInPolicy>,
+    error_policies: Option<ErrorPolicies>,
+    retry_policies: RetryPolicies,
+    candidate_id: Option<fn(&C) -> u64>,
+    side_effect_executor: Option<SideEffectExecutorConfig>,
+    metrics: Option<Arc<PipelineMetrics>>,
+    kill_switches: Option<Arc<KillSwitches>>,
+    /// Problems found while assembling, reported by `build`
+    errors: Vec<String>,
+}
+
+impl<Q, C> Clone for PipelineBuilder<Q, C> {
+    fn clone(&self) -> Self {
+        Self {
+            query_hydrators: self.query_hydrators.clone(),
+            sources: self.sources.clone(),
+            hydrators: self.hydrators.clone(),
+            filters: self.filters.clone(),
+            scorers: self.scorers.clone(),
+            selector: self.selector.clone(),
+            post_selection_hydrators: self.post_selection_hydrators.clone(),
+            post_selection_filters: self.post_selection_filters.clone(),
+            side_effects: self.side_effects.clone(),
+            result_size: self.result_size,
+            optimize_filters: self.optimize_filters,
+            streaming: self.streaming,
+            dedup: self.dedup.clone(),
+            stage_timeouts: self.stage_timeouts,
+            fan_in: self.fan_in,
+            error_policies: self.error_policies,
+            retry_policies: self.retry_policies,
+            candidate_id: self.candidate_id,
+            side_effect_executor: self.side_effect_executor,
+            metrics: self.metrics.clone(),
+            kill_switches: self.kill_switches.clone(),
+            errors: self.errors.clone(),
+        }
+    }
+}
+
+impl<Q, C> Default for PipelineBuilder<Q, C>
+where
+    Q: HasRequestId + Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Q, C> PipelineBuilder<Q, C>
+where
+    Q: HasRequestId + Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+{
+    pub fn new() -> Self {
+        Self {
+            query_hydrators: Vec::new(),
+            sources: Vec::new(),
+            hydrators: Vec::new(),
+            filters: Vec::new(),
+            scorers: Vec::new(),
+            selector: None,
+            post_selection_hydrators: Vec::new(),
+            post_selection_filters: Vec::new(),
+            side_effects: Vec::new(),
+            result_size: None,
+            optimize_filters: false,
+            streaming: false,
+            dedup: None,
+            fan_in: None,
+            stage_timeouts: None,
+            error_policies: None,
+            retry_policies: RetryPolicies::default(),
+            candidate_id: None,
+            side_effect_executor: None,
+            metrics: None,
+            kill_switches: None,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn query_hydrator(mut self, hydrator: impl QueryHydrator<Q>) -> Self {
+        self.query_hydrators.push(Arc::new(hydrator));
+        self
+    }
+
+    pub fn source(mut self, source: impl Source<Q, C>) -> Self {
+        self.sources.push(Arc::new(source));
+        self
+    }
+
+    pub fn hydrator(mut self, hydrator: impl Hydrator<Q, C>) -> Self {
+        self.hydrators.push(Arc::new(hydrator));
+        self
+    }
+
+    /// Add a filter after those added so far
+    pub fn filter(mut self, filter: impl Filter<Q, C>) -> Self {
+        self.filters.push(Arc::new(filter));
+        self
+    }
+
+    /// Add a scorer after those added so far
+    pub fn scorer(mut self, scorer: impl Scorer<Q, C> + 'static) -> Self {
+        self.scorers.push(Arc::new(scorer));
+        self
+    }
+
+    /// Set the selector, replacing any set before
+    pub fn selector(mut self, selector: impl Selector<Q, C> + 'static) -> Self {
+        self.selector = Some(Arc::new(selector));
+        self
+    }
+
+    pub fn post_selection_hydrator(mut self, hydrator: impl Hydrator<Q, C>) -> Self {
+        self.post_selection_hydrators.push(Arc::new(hydrator));
+        self
+    }
+
+    pub fn post_selection_filter(mut self, filter: impl Filter<Q, C>) -> Self {
+        self.post_selection_filters.push(Arc::new(filter));
+        self
+    }
+
+    pub fn side_effect(mut self, side_effect: impl SideEffect<Q, C> + 'static) -> Self {
+        self.side_effects.push(Arc::new(side_effect));
+        self
+    }
+
+    pub fn result_size(mut self, size: usize) -> Self {
+        self.result_size = Some(size);
+        self
+    }
+
+    /// Reorder filters within their reorder groups by observed cost and
+    /// selectivity; every built pipeline learns its own order
+    pub fn optimize_filters(mut self) -> Self {
+        self.optimize_filters = true;
+        self
+    }
+
+    /// Hydrate, filter and score each source's candidates as soon as they
+    /// arrive instead of waiting for every source; see `streaming`
+    pub fn streaming(mut self) -> Self {
+        self.streaming = true;
+        self
+    }
+
+    /// Drop duplicate candidates as soon as they are fetched
+    pub fn dedup(mut self, stage: DedupStage<C>) -> Self {
+        self.dedup = Some(stage);
+        self
+    }
+
+    pub fn stage_timeouts(mut self, timeouts: StageTimeouts) -> Self {
+        self.stage_timeouts = Some(timeouts);
+        self
+    }
+
+    /// Stop waiting for slow optional sources at a soft deadline; see `fan_in`
+    pub fn fan_in(mut self, policy: FanInPolicy) -> Self {
+        self.fan_in = Some(policy);
+        self
+    }
+
+    pub fn error_policies(mut self, policies: ErrorPolicies) -> Self {
+        self.error_policies = Some(policies);
+        self
+    }
+
+    /// How to tell candidates apart, so debug requests record their provenance
+    pub fn candidate_id(mut self, id: fn(&C) -> u64) -> Self {
+        self.candidate_id = Some(id);
+        self
+    }
+
+    /// Retry the sources and hydrators of stages with a policy; each component
+    /// of a built pipeline gets its own budget
+    pub fn retry_policies(mut self, policies: RetryPolicies) -> Self {
+        self.retry_policies = policies;
+        self
+    }
+
+    /// Run side effects on a bounded worker pool instead of a task per request
+    pub fn side_effect_executor(mut self, config: SideEffectExecutorConfig) -> Self {
+        self.side_effect_executor = Some(config);
+        self
+    }
+
+    /// Record stage latencies, candidate counts and component errors into
+    /// `metrics`, which variants built from this builder share unless given their own
+    pub fn metrics(mut self, metrics: Arc<PipelineMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Skip components switched off in `switches`, checked on every request
+    pub fn kill_switches(mut self, switches: Arc<KillSwitches>) -> Self {
+        self.kill_switches = Some(switches);
+        self
+    }
+
+    /// Put `source` in place of the source named `name`
+    pub fn replace_source(mut self, name: &str, source: impl Source<Q, C>) -> Self {
+        let replaced = replace(&mut self.sources, name, Arc::new(source), |s| s.name());
+        self.note_missing(replaced, name);
+        self
+    }
+
+    /// Put `hydrator` in place of the hydrator named `name`, before or after selection
+    pub fn replace_hydrator(mut self, name: &str, hydrator: impl Hydrator<Q, C>) -> Self {
+        let hydrator: Arc<dyn Hydrator<Q, C>> = Arc::new(hydrator);
+        let replaced = replace(&mut self.hydrators, name, hydrator.clone(), |h| h.name())
+            | replace(&mut self.post_selection_hydrators, name, hydrator, |h| h.name());
+        self.note_missing(replaced, name);
+        self
+    }
+
+    /// Put `filter` in place of the filter named `name`, before or after selection
+    pub fn replace_filter(mut self, name: &str, filter: impl Filter<Q, C>) -> Self {
+        let filter: Arc<dyn Filter<Q, C>> = Arc::new(filter);
+        let replaced = replace(&mut self.filters, name, filter.clone(), |f| f.name())
+            | replace(&mut self.post_selection_filters, name, filter, |f| f.name());
+        self.note_missing(replaced, name);
+        self
+    }
+
+    /// Put `scorer` in place of the scorer named `name`
+    pub fn replace_scorer(mut self, name: &str, scorer: impl Scorer<Q, C> + 'static) -> Self {
+        let replaced = replace(&mut self.scorers, name, Arc::new(scorer), |s| s.name());
+        self.note_missing(replaced, name);
+        self
+    }
+
+    /// Drop the component named `name` from whichever stages hold it
+    pub fn without(mut self, name: &str) -> Self {
+        let before = self.len();
+        self.query_hydrators.retain(|h| h.name() != name);
+        self.sources.retain(|s| s.name() != name);
+        self.hydrators.retain(|h| h.name() != name);
+        self.filters.retain(|f| f.name() != name);
+        self.scorers.retain(|s| s.name() != name);
+        self.post_selection_hydrators.retain(|h| h.name() != name);
+        self.post_selection_filters.retain(|f| f.name() != name);
+        self.side_effects.retain(|s| s.name() != name);
+        let removed = self.len() < before;
+        self.note_missing(removed, name);
+        self
+    }
+
+    fn len(&self) -> usize {
+        self.query_hydrators.len()
+            + self.sources.len()
+            + self.hydrators.len()
+            + self.filters.len()
+            + self.scorers.len()
+            + self.post_selection_hydrators.len()
+            + self.post_selection_filters.len()
+            + self.side_effects.len()
+    }
+
+    fn note_missing(&mut self, found: bool, name: &str) {
+        if !found {
+            self.errors.push(format!("no component named {}", name));
+        }
+    }
+
+    /// Check that the pipeline is complete and consistent, listing every problem
+    pub fn build(self) -> Result<Pipeline<Q, C>, String> {
+        let mut errors = self.errors.clone();
+        if self.selector.is_none() {
+            errors.push("a selector is required".to_string());
+        }
+        if self.result_size.unwrap_or(0) == 0 {
+            errors.push("a positive result size is required".to_string());
+        }
+        // Logs and the filter optimizer tell components apart by name
+        let stages = [
+            ("query hydrator", names(&self.query_hydrators, |h| h.name())),
+            ("source", names(&self.sources, |s| s.name())),
+            ("hydrator", names(&self.hydrators, |h| h.name())),
+            ("filter", names(&self.filters, |f| f.name())),
+            ("scorer", names(&self.scorers, |s| s.name())),
+            ("post-selection hydrator", names(&self.post_selection_hydrators, |h| h.name())),
+            ("post-selection filter", names(&self.post_selection_filters, |f| f.name())),
+            ("side effect", names(&self.side_effects, |s| s.name())),
+        ];
+        let fingerprint = fingerprint(&(
+            format!("{:?}", stages),
+            self.selector.as_ref().map(|s| s.name()),
+            self.result_size,
+            self.optimize_filters,
+            self.streaming,
+            self.dedup.as_ref().map(|d| d.priority().to_vec()),
+            format!(
+                "{:?}",
+                (self.stage_timeouts, self.fan_in, self.error_policies, self.retry_policies)
+            ),
+        ));
+        for (stage, names) in stages {
+            let mut seen = HashSet::new();
+            for name in names {
+                if !seen.insert(name) {
+                    errors.push(format!("{} {} is added twice", stage, name));
+                }
+            }
+        }
+        if let Some(dedup) = &self.dedup {
+            let sources = names(&self.sources, |s| s.name());
+            for source in dedup.priority().iter().filter(|s| !sources.contains(s)) {
+                errors.push(format!("dedup priority names unknown source {}", source));
+            }
+        }
+        let dependencies: Vec<_> =
+            self.query_hydrators.iter().map(|h| (h.name(), h.dependencies())).collect();
+        let query_hydrator_levels = execution_levels(&dependencies).unwrap_or_else(|e| {
+            errors.push(e);
+            Vec::new()
+        });
+        if !errors.is_empty() {
+            return Err(format!("invalid pipeline: {}", errors.join("; ")));
+        }
+
+        let side_effects = Arc::new(boxed!(self.side_effects));
+        let side_effect_executor = self
+            .side_effect_executor
+            .map(|config| SideEffectExecutor::new(Arc::clone(&side_effects), config))
+            .map(|executor| match &self.kill_switches {
+                Some(switches) => executor.kill_switches(switches.clone()),
+                None => executor,
+            });
+        Ok(Pipeline {
+            query_hydrators: boxed!(self.query_hydrators),
+            query_hydrator_levels,
+            sources: retrying_sources(&self.sources, self.retry_policies.source),
+            hydrators: retrying_hydrators(&self.hydrators, self.retry_policies.hydrator),
+            filters: boxed!(self.filters),
+            scorers: boxed!(self.scorers),
+            selector: Box::new(self.selector.clone().expect("checked above")),
+            post_selection_hydrators: retrying_hydrators(
+                &self.post_selection_hydrators,
+                self.retry_policies.hydrator,
+            ),
+            post_selection_filters: boxed!(self.post_selection_filters),
+            side_effects,
+            side_effect_executor,
+            result_size: self.result_size.expect("checked above"),
+            filter_optimizer: self.optimize_filters.then(FilterOrderOptimizer::new),
+            streaming: self.streaming,
+            dedup: self.dedup.clone(),
+            stage_timeouts: self.stage_timeouts,
+            fan_in: self.fan_in,
+            error_policies: self.error_policies,
+            candidate_id: self.candidate_id,
+            metrics: self.metrics.clone(),
+            kill_switches: self.kill_switches.clone(),
+            fingerprint,
+            spec: self,
+        })
+    }
+}
+
+fn retrying_sources<Q, C>(
+    sources: &[Arc<dyn Source<Q, C>>],
+    policy: Option<RetryPolicy>,
+) -> Vec<Box<dyn Source<Q, C>>>
+where
+    Q: Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+{
+    match policy {
+        Some(policy) => sources
+            .iter()
+            .map(|s| Box::new(RetryingSource::new(s.clone(), policy)) as _)
+            .collect(),
+        None => boxed!(sources),
+    }
+}
+
+fn retrying_hydrators<Q, C>(
+    hydrators: &[Arc<dyn Hydrator<Q, C>>],
+    policy: Option<RetryPolicy>,
+) -> Vec<Box<dyn Hydrator<Q, C>>>
+where
+    Q: Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+{
+    match policy {
+        Some(policy) => hydrators
+            .iter()
+            .map(|h| Box::new(RetryingHydrator::new(h.clone(), policy)) as _)
+            .collect(),
+        None => boxed!(hydrators),
+    }
+}
+
+fn replace<T: ?Sized>(
+    stage: &mut [Arc<T>],
+    name: &str,
+    component: Arc<T>,
+    name_of: impl Fn(&T) -> &'static str,
+) -> bool {
+    match stage.iter_mut().find(|c| name_of(c) == name) {
+        Some(slot) => {
+            *slot = component;
+            true
+        },
+        None => false,
+    }
+}
+
+fn names<T: ?Sized>(
+    stage: &[Arc<T>],
+    name_of: impl Fn(&T) -> &'static str,
+) -> Vec<&'static str> {
+    stage.iter().map(|c| name_of(c)).collect()
+}
+
+
+/// A pipeline assembled by `PipelineBuilder`
+pub struct Pipeline<Q, C> {
+    query_hydrators: Vec<Box<dyn QueryHydrator<Q>>>,
+    query_hydrator_levels: Vec<Vec<usize>>,
+    sources: Vec<Box<dyn Source<Q, C>>>,
+    hydrators: Vec<Box<dyn Hydrator<Q, C>>>,
+    filters: Vec<Box<dyn Filter<Q, C>>>,
+    scorers: Vec<Box<dyn Scorer<Q, C>>>,
+    selector: Box<dyn Selector<Q, C>>,
+    post_selection_hydrators: Vec<Box<dyn Hydrator<Q, C>>>,
+    post_selection_filters: Vec<Box<dyn Filter<Q, C>>>,
+    side_effects: Arc<Vec<Box<dyn SideEffect<Q, C>>>>,
+    result_size: usize,
+    filter_optimizer: Option<FilterOrderOptimizer>,
+    streaming: bool,
+    dedup: Option<DedupStage<C>>,
+    stage_timeouts: Option<StageTimeouts>,
+    fan_in: Option<FanInPolicy>,
+    error_policies: Option<ErrorPolicies>,
+    candidate_id: Option<fn(&C) -> u64>,
+    side_effect_executor: Option<SideEffectExecutor<Q, C>>,
+    metrics: Option<Arc<PipelineMetrics>>,
+    kill_switches: Option<Arc<KillSwitches>>,
+    fingerprint: u64,
+    /// What the pipeline was built from, sharing its components
+    spec: PipelineBuilder<Q, C>,
+}
+
+impl<Q, C> Pipeline<Q, C> {
+    /// A builder holding this pipeline's components, to derive variants from
+    pub fn to_builder(&self) -> PipelineBuilder<Q, C> {
+        self.spec.clone()
+    }
+
+    /// Identifies the pipeline's shape: its components in order and its
+    /// policies. Pipelines built the same way by the same binary share it.
+    pub fn fingerprint(&self) -> u64 {
+        self.fingerprint
+    }
+}
+
+#[async_trait]
+impl<Q, C> CandidatePipeline<Q, C> for Pipeline<Q, C>
+where
+    Q: HasRequestId + Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+{
+    fn query_hydrators(&self) -> &[Box<dyn QueryHydrator<Q>>] {
+        &self.query_hydrators
+    }
+
+    fn query_hydrator_levels(&self) -> Option<&[Vec<usize>]> {
+        Some(&self.query_hydrator_levels)
+    }
+
+    fn sources(&self) -> &[Box<dyn Source<Q, C>>] {
+        &self.sources
+    }
+
+    fn hydrators(&self) -> &[Box<dyn Hydrator<Q, C>>] {
+        &self.hydrators
+    }
+
+    fn filters(&self) -> &[Box<dyn Filter<Q, C>>] {
+        &self.filters
+    }
+
+    fn scorers(&self) -> &[Box<dyn Scorer<Q, C>>] {
+        &self.scorers
+    }
+
+    fn selector(&self) -> &dyn Selector<Q, C> {
+        self.selector.as_ref()
+    }
+
+    fn post_selection_hydrators(&self) -> &[Box<dyn Hydrator<Q, C>>] {
+        &self.post_selection_hydrators
+    }
+
+    fn post_selection_filters(&self) -> &[Box<dyn Filter<Q, C>>] {
+        &self.post_selection_filters
+    }
+
+    fn side_effects(&self) -> Arc<Vec<Box<dyn SideEffect<Q, C>>>> {
+        Arc::clone(&self.side_effects)
+    }
+
+    fn result_size(&self) -> usize {
+        self.result_size
+    }
+
+    fn filter_optimizer(&self) -> Option<&FilterOrderOptimizer> {
+        self.filter_optimizer.as_ref()
+    }
+
+    fn streaming(&self) -> bool {
+        self.streaming
+    }
+
+    fn dedup(&self) -> Option<&DedupStage<C>> {
+        self.dedup.as_ref()
+    }
+
+    fn fan_in_policy(&self) -> Option<&FanInPolicy> {
+        self.fan_in.as_ref()
+    }
+
+    fn stage_timeouts(&self) -> Option<&StageTimeouts> {
+        self.stage_timeouts.as_ref()
+    }
+
+    fn error_policies(&self) -> Option<&ErrorPolicies> {
+        self.error_policies.as_ref()
+    }
+
+    fn candidate_id(&self, candidate: &C) -> Option<u64> {
+        self.candidate_id.map(|id| id(candidate))
+    }
+
+    fn side_effect_executor(&self) -> Option<&SideEffectExecutor<Q, C>> {
+        self.side_effect_executor.as_ref()
+    }
+
+    fn metrics(&self) -> Option<&PipelineMetrics> {
+        self.metrics.as_deref()
+    }
+
+    fn kill_switches(&self) -> Option<&Arc<KillSwitches>> {
+        self.kill_switches.as_ref()
+    }
+}
+
+// Shared components run as themselves, so a pipeline and its variants can hold
+// the same instance
+
+#[async_trait]
+impl<Q, T> QueryHydrator<Q> for Arc<T>
+where
+    Q: Clone + Send + Sync + 'static,
+    T: QueryHydrator<Q> + ?Sized,
+{
+    fn enable(&self, query: &Q) -> bool {
+        (**self).enable(query)
+    }
+
+    async fn hydrate(&self, query: &Q) -> Result<Q, PipelineError> {
+        (**self).hydrate(query).await
+    }
+
+    fn update(&self, query: &mut Q, hydrated: Q) {
+        (**self).update(query, hydrated)
+    }
+
+    fn fallback(&self, query: &Q) -> Option<Q> {
+        (**self).fallback(query)
+    }
+
+    fn dependencies(&self) -> &[&'static str] {
+        (**self).dependencies()
+    }
+
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+}
+
+#[async_trait]
+impl<Q, C, T> Source<Q, C> for Arc<T>
+where
+    Q: Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+    T: Source<Q, C> + ?Sized,
+{
+    fn enable(&self, query: &Q) -> bool {
+        (**self).enable(query)
+    }
+
+    async fn get_candidates(&self, query: &Q) -> Result<Vec<C>, PipelineError> {
+        (**self).get_candidates(query).await
+    }
+
+    fn is_optional(&self) -> bool {
+        (**self).is_optional()
+    }
+
+    fn fallback(&self, query: &Q) -> Option<Vec<C>> {
+        (**self).fallback(query)
+    }
+
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+}
+
+#[async_trait]
+impl<Q, C, T> Hydrator<Q, C> for Arc<T>
+where
+    Q: Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+    T: Hydrator<Q, C> + ?Sized,
+{
+    fn enable(&self, query: &Q) -> bool {
+        (**self).enable(query)
+    }
+
+    async fn hydrate(&self, query: &Q, candidates: &[C]) -> Result<Vec<C>, PipelineError> {
+        (**self).hydrate(query, candidates).await
+    }
+
+    fn update(&self, candidate: &mut C, hydrated: C) {
+        (**self).update(candidate, hydrated)
+    }
+
+    fn update_all(&self, candidates: &mut [C], hydrated: Vec<C>) {
+        (**self).update_all(candidates, hydrated)
+    }
+
+    fn is_optional(&self) -> bool {
+        (**self).is_optional()
+    }
+
+    fn fallback(&self, query: &Q, candidates: &[C]) -> Option<Vec<C>> {
+        (**self).fallback(query, candidates)
+    }
+
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+}
+
+#[async_trait]
+impl<Q, C, T> Filter<Q, C> for Arc<T>
+where
+    Q: Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+    T: Filter<Q, C> + ?Sized,
+{
+    fn enable(&self, query: &Q) -> bool {
+        (**self).enable(query)
+    }
+
+    async fn filter(
+        &self,
+        query: &Q,
+        candidates: Vec<C>,
+    ) -> Result<FilterResult<C>, PipelineError> {
+        (**self).filter(query, candidates).await
+    }
+
+    fn reorder_group(&self) -> Option<&'static str> {
+        (**self).reorder_group()
+    }
+
+    fn is_optional(&self) -> bool {
+        (**self).is_optional()
+    }
+
+    fn fallback(&self, query: &Q, candidates: &[C]) -> Option<FilterResult<C>> {
+        (**self).fallback(query, candidates)
+    }
+
+    fn is_killable(&self) -> bool {
+        (**self).is_killable()
+    }
+
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+}
+
+#[async_trait]
+impl<Q, C, T> Scorer<Q, C> for Arc<T>
+where
+    Q: Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+    T: Scorer<Q, C> + ?Sized,
+{
+    fn enable(&self, query: &Q) -> bool {
+        (**self).enable(query)
+    }
+
+    async fn score(&self, query: &Q, candidates: &[C]) -> Result<Vec<C>, PipelineError> {
+        (**self).score(query, candidates).await
+    }
+
+    fn update(&self, candidate: &mut C, scored: C) {
+        (**self).update(candidate, scored)
+    }
+
+    fn update_all(&self, candidates: &mut [C], scored: Vec<C>) {
+        (**self).update_all(candidates, scored)
+    }
+
+    fn is_optional(&self) -> bool {
+        (**self).is_optional()
+    }
+
+    fn fallback(&self, query: &Q, candidates: &[C]) -> Option<Vec<C>> {
+        (**self).fallback(query, candidates)
+    }
+
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+}
+
+impl<Q, C, T> Selector<Q, C> for Arc<T>
+where
+    Q: Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+    T: Selector<Q, C> + ?Sized,
+{
+    fn select(&self, query: &Q, candidates: Vec<C>) -> Vec<C> {
+        (**self).select(query, candidates)
+    }
+
+    fn enable(&self, query: &Q) -> bool {
+        (**self).enable(query)
+    }
+
+    fn score(&self, candidate: &C) -> f64 {
+        (**self).score(candidate)
+    }
+
+    fn sort(&self, candidates: Vec<C>) -> Vec<C> {
+        (**self).sort(candidates)
+    }
+
+    fn size(&self) -> Option<usize> {
+        (**self).size()
+    }
+
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+}
+
+#[async_trait]
+impl<Q, C, T> SideEffect<Q, C> for Arc<T>
+where
+    Q: Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+    T: SideEffect<Q, C> + ?Sized,
+{
+    fn enable(&self, query: Arc<Q>) -> bool {
+        (**self).enable(query)
+    }
+
+    async fn run(&self, input: Arc<SideEffectInput<Q, C>>) -> Result<(), PipelineError> {
+        (**self).run(input).await
+    }
+
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone)]
+    struct Query;
+
+    impl HasRequestId for Query {
+        fn request_id(&self) -> &str {
+            "test"
+        }
+    }
+
+    struct Numbers;
+
+    #[async_trait]
+    impl Source<Query, i64> for Numbers {
+        async fn get_candidates(&self, _query: &Query) -> Result<Vec<i64>, PipelineError> {
+            Ok((1..=6).collect())
+        }
+    }
+
+    struct DropOdd;
+
+    #[async_trait]
+    impl Filter<Query, i64> for DropOdd {
+        async fn filter(
+            &self,
+            _query: &Query,
+            candidates: Vec<i64>,
+        ) -> Result<FilterResult<i64>, PipelineError> {
+            let (removed, kept) = candidates.into_iter().partition(|c| c % 2 == 1);
+            Ok(FilterResult { kept, removed })
+        }
+    }
+
+    struct DropEven;
+
+    #[async_trait]
+    impl Filter<Query, i64> for DropEven {
+        async fn filter(
+            &self,
+            _query: &Query,
+            candidates: Vec<i64>,
+        ) -> Result<FilterResult<i64>, PipelineError> {
+            let (removed, kept) = candidates.into_iter().partition(|c| c % 2 == 0);
+            Ok(FilterResult { kept, removed })
+        }
+    }
+
+    struct Unchanged;
+
+    #[async_trait]
+    impl Scorer<Query, i64> for Unchanged {
+        async fn score(
+            &self,
+            _query: &Query,
+            candidates: &[i64],
+        ) -> Result<Vec<i64>, PipelineError> {
+            Ok(candidates.to_vec())
+        }
+
+        fn update(&self, _candidate: &mut i64, _scored: i64) {}
+    }
+
+    struct Largest;
+
+    impl Selector<Query, i64> for Largest {
+        fn score(&self, candidate: &i64) -> f64 {
+            *candidate as f64
+        }
+    }
+
+    #[tokio::test]
+    async fn test_variants_substitute_stages_of_a_base_pipeline() {
+        let base = PipelineBuilder::new()
+            .source(Numbers)
+            .filter(DropOdd)
+            .selector(Largest)
+            .result_size(2)
+            .build()
+            .unwrap();
+        let variant = base.to_builder().replace_filter("DropOdd", DropEven).build().unwrap();
+        let unfiltered = base.to_builder().without("DropOdd").build().unwrap();
+
+        assert_eq!(base.execute(Query).await.unwrap().selected_candidates, vec![6, 4]);
+        assert_eq!(variant.execute(Query).await.unwrap().selected_candidates, vec![5, 3]);
+        assert_eq!(unfiltered.execute(Query).await.unwrap().selected_candidates, vec![6, 5]);
+        assert_eq!(base.filters()[0].name(), "DropOdd");
+
+        assert_eq!(base.fingerprint(), base.to_builder().build().unwrap().fingerprint());
+        assert_ne!(base.fingerprint(), variant.fingerprint());
+        assert_ne!(base.fingerprint(), unfiltered.fingerprint());
+    }
+
+    #[test]
+    fn test_build_lists_every_problem() {
+        let error = PipelineBuilder::<Query, i64>::new()
+            .filter(DropOdd)
+            .filter(DropOdd)
+            .replace_scorer("Missing", Unchanged)
+            .build()
+            .err()
+            .unwrap();
+        assert!(error.contains("a selector is required"));
+        assert!(error.contains("a positive result size is required"));
+        assert!(error.contains("filter DropOdd is added twice"));
+        assert!(error.contains("no component named Missing"));
+    }
+
+    #[tokio::test]
+    async fn test_killed_components_are_skipped_until_restored() {
+        let switches = Arc::new(KillSwitches::new());
+        let pipeline = PipelineBuilder::new()
+            .source(Numbers)
+            .filter(DropOdd)
+            .selector(Largest)
+            .result_size(2)
+            .kill_switches(switches.clone())
+            .build()
+            .unwrap();
+        switches.kill("DropOdd", "dropping too much");
+        assert_eq!(pipeline.execute(Query).await.unwrap().selected_candidates, vec![6, 5]);
+
+        switches.restore("DropOdd");
+        assert_eq!(pipeline.execute(Query).await.unwrap().selected_candidates, vec![6, 4]);
+        switches.kill("Numbers", "");
+        assert!(pipeline.execute(Query).await.unwrap().selected_candidates.is_empty());
+    }
+
+    struct Withhold;
+
+    #[async_trait]
+    impl Filter<Query, i64> for Withhold {
+        async fn filter(
+            &self,
+            query: &Query,
+            candidates: Vec<i64>,
+        ) -> Result<FilterResult<i64>, PipelineError> {
+            DropOdd.filter(query, candidates).await
+        }
+
+        fn is_killable(&self) -> bool {
+            false
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unkillable_filters_ignore_kill_switches() {
+        let switches = Arc::new(KillSwitches::new());
+        let pipeline = PipelineBuilder::new()
+            .source(Numbers)
+            .filter(Withhold)
+            .selector(Largest)
+            .result_size(2)
+            .kill_switches(switches.clone())
+            .build()
+            .unwrap();
+        switches.kill("Withhold", "");
+        assert_eq!(pipeline.execute(Query).await.unwrap().selected_candidates, vec![6, 4]);
+    }
+}
@@ -1,16 +1,32 @@
+use crate::dedup::{DedupStage, Deduped};
+use crate::dry_run::{dry_run, DryRun};
+use crate::error::{ErrorKind, PipelineError};
+use crate::fan_in::{FanIn, FanInPolicy};
+use crate::error_policy::{ErrorPolicies, StageError, StageErrors};
 use crate::filter::Filter;
+use crate::filter_optimizer::FilterOrderOptimizer;
 use crate::hydrator::Hydrator;
+use crate::kill_switch::KillSwitches;
+use crate::metrics::PipelineMetrics;
+use crate::provenance::{AuditTrail, Provenance};
 use crate::query_hydrator::QueryHydrator;
 use crate::scorer::Scorer;
 use crate::selector::Selector;
 use crate::side_effect::{SideEffect, SideEffectInput};
+use crate::side_effect_executor::SideEffectExecutor;
 use crate::source::Source;
+use crate::stage_timeout::{until_deadline, Cancellation, StageTimeouts};
+use crate::streaming::run_streaming_stages;
+use crate::trace::{request_span, StageSpan};
 use futures::future::join_all;
 use log::{error, info, warn};
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Instant;
 use tonic::async_trait;
+use tracing::Instrument;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum PipelineStage {
     QueryHydrator,
     Source,
@@ -21,16 +37,47 @@ pub enum PipelineStage {
     Scorer,
 }
 
+impl PipelineStage {
+    /// Stable name of the stage for metrics, e.g. `post_selection_filter`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PipelineStage::QueryHydrator => "query_hydrator",
+            PipelineStage::Source => "source",
+            PipelineStage::Hydrator => "hydrator",
+            PipelineStage::PostSelectionHydrator => "post_selection_hydrator",
+            PipelineStage::Filter => "filter",
+            PipelineStage::PostSelectionFilter => "post_selection_filter",
+            PipelineStage::Scorer => "scorer",
+        }
+    }
+}
+
 pub struct PipelineResult<Q, C> {
     pub retrieved_candidates: Vec<C>,
     pub filtered_candidates: Vec<C>,
     pub selected_candidates: Vec<C>,
     pub query: Arc<Q>,
+    /// Components that failed without failing the request
+    pub errors: Vec<StageError>,
+    /// Provenance of each candidate by id, recorded for debug requests
+    pub provenance: HashMap<u64, Provenance>,
+    /// Whether some sources didn't return in time and the request went on without them
+    pub partial: bool,
 }
 
 /// Provides a stable request identifier for logging/tracing.
 pub trait HasRequestId {
     fn request_id(&self) -> &str;
+
+    /// The user the request is for, attached to its trace
+    fn user_id(&self) -> Option<u64> {
+        None
+    }
+
+    /// Whether to record the provenance of each candidate of this request
+    fn debug(&self) -> bool {
+        false
+    }
 }
 
 #[async_trait]
@@ -50,28 +97,224 @@ where
     fn side_effects(&self) -> Arc<Vec<Box<dyn SideEffect<Q, C>>>>;
     fn result_size(&self) -> usize;
 
-    async fn execute(&self, query: Q) -> PipelineResult<Q, C> {
-        let hydrated_query = self.hydrate_query(query).await;
+    /// Indices into `query_hydrators` grouped into levels run one after another,
+    /// see `query_hydrator::execution_levels`; None runs every hydrator at once
+    fn query_hydrator_levels(&self) -> Option<&[Vec<usize>]> {
+        None
+    }
 
-        let candidates = self.fetch_candidates(&hydrated_query).await;
+    /// Optional deduplication of the candidates fetched, before they are hydrated
+    fn dedup(&self) -> Option<&DedupStage<C>> {
+        None
+    }
 
-        let hydrated_candidates = self.hydrate(&hydrated_query, candidates).await;
+    /// Optional soft deadline after which slow optional sources are abandoned
+    fn fan_in_policy(&self) -> Option<&FanInPolicy> {
+        None
+    }
 
-        let (kept_candidates, mut filtered_candidates) = self
-            .filter(&hydrated_query, hydrated_candidates.clone())
-            .await;
+    /// Optional runtime optimizer used to reorder filters within their reorder groups
+    fn filter_optimizer(&self) -> Option<&FilterOrderOptimizer> {
+        None
+    }
 
-        let scored_candidates = self.score(&hydrated_query, kept_candidates).await;
+    /// Whether `execute` streams each source's candidates through the stages
+    /// before selection as they arrive, see `streaming::run_streaming_stages`
+    fn streaming(&self) -> bool {
+        false
+    }
+
+    /// Optional deadlines for the sources, hydrators, filters and scorers of a request
+    fn stage_timeouts(&self) -> Option<&StageTimeouts> {
+        None
+    }
+
+    /// Optional per-stage handling of failed components; without it they are skipped
+    fn error_policies(&self) -> Option<&ErrorPolicies> {
+        None
+    }
+
+    /// Stable id of `candidate`, without which its provenance isn't recorded
+    fn candidate_id(&self, _candidate: &C) -> Option<u64> {
+        None
+    }
+
+    /// Optional worker pool running side effects; without it each request's
+    /// side effects run on a task of their own
+    fn side_effect_executor(&self) -> Option<&SideEffectExecutor<Q, C>> {
+        None
+    }
+
+    /// Optional per-stage latency, candidate counts and component errors
+    fn metrics(&self) -> Option<&PipelineMetrics> {
+        None
+    }
+
+    /// Optional registry of components switched off at runtime
+    fn kill_switches(&self) -> Option<&Arc<KillSwitches>> {
+        None
+    }
+
+    /// Whether `component` is switched off, which skips it like a disabled one
+    fn killed(&self, component: &str) -> bool {
+        self.kill_switches().is_some_and(|switches| switches.is_killed(component))
+    }
+
+    /// End `stage` with `output` candidates, recording it in its span and the metrics
+    fn finish_stage(&self, stage: StageSpan, output: usize, errors: &StageErrors) {
+        let (name, input) = (stage.stage(), stage.input());
+        let elapsed = stage.finish(output, errors);
+        if let Some(metrics) = self.metrics() {
+            metrics.record_stage(name, elapsed, input, output);
+        }
+    }
+
+    /// When `stage` must finish if it starts now, or None without a timeout
+    fn stage_deadline(&self, stage: PipelineStage) -> Option<tokio::time::Instant> {
+        let timeout = self.stage_timeouts()?.get(stage)?;
+        Some(tokio::time::Instant::now() + timeout)
+    }
+
+    /// Execute the pipeline unless `cancellation` is cancelled first, in which case
+    /// every stage still in flight is dropped, side effects don't run, and None is
+    /// returned.
+    async fn execute_cancellable(
+        &self,
+        query: Q,
+        cancellation: &Cancellation,
+    ) -> Option<Result<PipelineResult<Q, C>, StageError>> {
+        let request_id = query.request_id().to_string();
+        tokio::select! {
+            biased;
+            _ = cancellation.cancelled() => {
+                warn!("request_id={} cancelled", request_id);
+                None
+            },
+            result = self.execute(query) => Some(result),
+        }
+    }
+
+    /// Execute the pipeline, failing only when a component fails in a stage whose
+    /// error policy is `FailRequest`
+    async fn execute(&self, query: Q) -> Result<PipelineResult<Q, C>, StageError> {
+        let span = request_span(&query);
+        let result = if self.streaming() {
+            run_streaming_stages(self, query).instrument(span.clone()).await
+        } else {
+            self.run_stages(query).instrument(span.clone()).await
+        };
+        let errors = match &result {
+            Ok(result) => {
+                span.record("selected", result.selected_candidates.len());
+                span.record("outcome", "ok");
+                &result.errors[..]
+            },
+            Err(err) => {
+                span.record("outcome", "failed_request");
+                std::slice::from_ref(err)
+            },
+        };
+        span.record("errors", errors.len());
+        if let Some(metrics) = self.metrics() {
+            metrics.record_errors(errors);
+        }
+        result
+    }
+
+    /// Run every stage after the sources on `candidates` instead of fetching
+    /// them, tracing each stage; see `dry_run`
+    async fn dry_run(&self, query: Q, candidates: Vec<C>) -> DryRun<Q, C> {
+        dry_run(self, query, candidates).await
+    }
+
+    /// Run every stage of `execute`, tracing each in a span of its own and
+    /// recording the latency and candidate counts of each stage that completes
+    async fn run_stages(&self, query: Q) -> Result<PipelineResult<Q, C>, StageError> {
+        let policies = self.error_policies().copied().unwrap_or_default();
+        let mut errors = StageErrors::new(query.request_id(), policies);
+        let mut trail = AuditTrail::new(query.debug());
+
+        let stage = StageSpan::start(PipelineStage::QueryHydrator, 0, &errors);
+        let hydrated_query = stage.traced(self.hydrate_query(query, &mut errors)).await?;
+        self.finish_stage(stage, 0, &errors);
+
+        let stage = StageSpan::start(PipelineStage::Source, 0, &errors);
+        let Deduped {
+            kept: candidates,
+            removed: duplicates,
+        } = stage
+            .traced(self.fetch_candidates(&hydrated_query, &mut errors, &mut trail))
+            .await?;
+        self.finish_stage(stage, candidates.len(), &errors);
+
+        let input = candidates.len();
+        let stage = StageSpan::start(PipelineStage::Hydrator, input, &errors);
+        let hydrated_candidates = stage
+            .traced(self.hydrate(&hydrated_query, candidates, &mut errors, &mut trail))
+            .await?;
+        self.finish_stage(stage, hydrated_candidates.len(), &errors);
+
+        let input = hydrated_candidates.len();
+        let stage = StageSpan::start(PipelineStage::Filter, input, &errors);
+        let (kept_candidates, filtered_candidates) = stage
+            .traced(self.filter(
+                &hydrated_query,
+                hydrated_candidates.clone(),
+                &mut errors,
+                &mut trail,
+            ))
+            .await?;
+        self.finish_stage(stage, kept_candidates.len(), &errors);
+
+        let input = kept_candidates.len();
+        let stage = StageSpan::start(PipelineStage::Scorer, input, &errors);
+        let scored_candidates = stage
+            .traced(self.score(&hydrated_query, kept_candidates, &mut errors, &mut trail))
+            .await?;
+        self.finish_stage(stage, scored_candidates.len(), &errors);
 
         let selected_candidates = self.select(&hydrated_query, scored_candidates);
+        let mut removed = duplicates;
+        removed.extend(filtered_candidates);
+        let retrieved = (hydrated_candidates, removed);
+        self.run_post_selection(hydrated_query, selected_candidates, retrieved, errors, trail)
+            .await
+    }
 
-        let post_selection_hydrated_candidates = self
-            .hydrate_post_selection(&hydrated_query, selected_candidates)
-            .await;
+    /// Hydrate and filter `selected_candidates`, run side effects and assemble the
+    /// result, given the candidates `retrieved` and those filtered before selection
+    async fn run_post_selection(
+        &self,
+        hydrated_query: Q,
+        selected_candidates: Vec<C>,
+        retrieved: (Vec<C>, Vec<C>),
+        mut errors: StageErrors,
+        mut trail: AuditTrail,
+    ) -> Result<PipelineResult<Q, C>, StageError> {
+        let (hydrated_candidates, mut filtered_candidates) = retrieved;
+        let input = selected_candidates.len();
+        let stage = StageSpan::start(PipelineStage::PostSelectionHydrator, input, &errors);
+        let post_selection_hydrated_candidates = stage
+            .traced(self.hydrate_post_selection(
+                &hydrated_query,
+                selected_candidates,
+                &mut errors,
+                &mut trail,
+            ))
+            .await?;
+        self.finish_stage(stage, post_selection_hydrated_candidates.len(), &errors);
 
-        let (mut final_candidates, post_selection_filtered_candidates) = self
-            .filter_post_selection(&hydrated_query, post_selection_hydrated_candidates)
-            .await;
+        let input = post_selection_hydrated_candidates.len();
+        let stage = StageSpan::start(PipelineStage::PostSelectionFilter, input, &errors);
+        let (mut final_candidates, post_selection_filtered_candidates) = stage
+            .traced(self.filter_post_selection(
+                &hydrated_query,
+                post_selection_hydrated_candidates,
+                &mut errors,
+                &mut trail,
+            ))
+            .await?;
+        self.finish_stage(stage, final_candidates.len(), &errors);
         filtered_candidates.extend(post_selection_filtered_candidates);
 
         final_candidates.truncate(self.result_size());
@@ -83,92 +326,197 @@ where
         });
         self.run_side_effects(input);
 
-        PipelineResult {
+        let partial = errors.errors().iter().any(|e| {
+            e.stage == PipelineStage::Source && e.error.kind == ErrorKind::Timeout
+        });
+        Ok(PipelineResult {
             retrieved_candidates: hydrated_candidates,
             filtered_candidates,
             selected_candidates: final_candidates,
             query: arc_hydrated_query,
-        }
+            errors: errors.into_errors(),
+            provenance: trail.into_records(),
+            partial,
+        })
     }
 
     /// Run all query hydrators in parallel and merge results into the query.
-    async fn hydrate_query(&self, query: Q) -> Q {
+    async fn hydrate_query(&self, query: Q, errors: &mut StageErrors) -> Result<Q, StageError> {
         let request_id = query.request_id().to_string();
-        let hydrators: Vec<_> = self
-            .query_hydrators()
-            .iter()
-            .filter(|h| h.enable(&query))
-            .collect();
-        let hydrate_futures = hydrators.iter().map(|h| h.hydrate(&query));
-        let results = join_all(hydrate_futures).await;
+        let stage = PipelineStage::QueryHydrator;
+        let all_at_once;
+        let levels = match self.query_hydrator_levels() {
+            Some(levels) => levels,
+            None => {
+                all_at_once = [(0..self.query_hydrators().len()).collect()];
+                &all_at_once[..]
+            },
+        };
 
+        // Each level sees the query as updated by the levels before it
         let mut hydrated_query = query;
-        for (hydrator, result) in hydrators.iter().zip(results) {
-            match result {
-                Ok(hydrated) => {
+        for level in levels {
+            let hydrators: Vec<_> = level
+                .iter()
+                .map(|&i| &self.query_hydrators()[i])
+                .filter(|h| !self.killed(h.name()) && h.enable(&hydrated_query))
+                .collect();
+            let hydrate_futures = hydrators.iter().map(|h| h.hydrate(&hydrated_query));
+            let results = join_all(hydrate_futures).await;
+
+            for (hydrator, result) in hydrators.iter().zip(results) {
+                let hydrated = match result {
+                    Ok(hydrated) => Some(hydrated),
+                    Err(err) => {
+                        error!(
+                            "request_id={} stage={:?} component={} failed: {}",
+                            request_id,
+                            stage,
+                            hydrator.name(),
+                            err
+                        );
+                        let fallback = || hydrator.fallback(&hydrated_query);
+                        errors.fail(stage, hydrator.name(), err, fallback)?
+                    },
+                };
+                if let Some(hydrated) = hydrated {
                     hydrator.update(&mut hydrated_query, hydrated);
-                },
-                Err(err) => {
-                    error!(
-                        "request_id={} stage={:?} component={} failed: {}",
-                        request_id,
-                        PipelineStage::QueryHydrator,
-                        hydrator.name(),
-                        err
-                    );
-                },
+                }
             }
         }
-        hydrated_query
+        Ok(hydrated_query)
     }
 
-    /// Run all candidate sources in parallel and collect results.
-    async fn fetch_candidates(&self, query: &Q) -> Vec<C> {
-        let request_id = query.request_id().to_string();
-        let sources: Vec<_> = self.sources().iter().filter(|s| s.enable(query)).collect();
-        let source_futures = sources.iter().map(|s| s.get_candidates(query));
-        let results = join_all(source_futures).await;
+    /// Run all candidate sources in parallel and collect results, without
+    /// duplicates when the pipeline deduplicates them.
+    async fn fetch_candidates(
+        &self,
+        query: &Q,
+        errors: &mut StageErrors,
+        trail: &mut AuditTrail,
+    ) -> Result<Deduped<C>, StageError> {
+        let sources: Vec<&dyn Source<Q, C>> = self
+            .sources()
+            .iter()
+            .filter(|s| !self.killed(s.name()) && s.enable(query))
+            .map(|s| s.as_ref())
+            .collect();
+        let deadline = self.stage_deadline(PipelineStage::Source);
+        let policy = self.fan_in_policy().copied();
+        let mut fan_in = FanIn::start(query, &sources, deadline, policy);
+        let mut results: Vec<_> = sources.iter().map(|_| None).collect();
+        while let Some((index, result)) = fan_in.next().await {
+            results[index] = Some(result);
+        }
 
         let mut collected = Vec::new();
         for (source, result) in sources.iter().zip(results) {
-            match result {
-                Ok(mut candidates) => {
-                    info!(
-                        "request_id={} stage={:?} component={} fetched {} candidates",
-                        request_id,
-                        PipelineStage::Source,
-                        source.name(),
-                        candidates.len()
-                    );
-                    collected.append(&mut candidates);
-                },
-                Err(err) => {
-                    error!(
-                        "request_id={} stage={:?} component={} failed: {}",
-                        request_id,
-                        PipelineStage::Source,
-                        source.name(),
-                        err
-                    );
-                },
+            let result = result.expect("every source finishes or is abandoned");
+            let fetched = self.take_fetched(query, *source, result, errors, trail)?;
+            if let Some(candidates) = fetched {
+                collected.push((source.name(), candidates));
             }
         }
-        collected
+
+        let Some(dedup) = self.dedup() else {
+            return Ok(Deduped {
+                kept: collected.into_iter().flat_map(|(_, candidates)| candidates).collect(),
+                removed: Vec::new(),
+            });
+        };
+        let deduped = dedup.dedup(collected);
+        info!(
+            "request_id={} stage={:?} removed {} duplicate candidates",
+            query.request_id(),
+            PipelineStage::Source,
+            deduped.removed.len()
+        );
+        Ok(deduped)
+    }
+
+    /// The candidates `source` fetched, its fallback's when it failed or timed out
+    /// (`result` None), or None to go on without it
+    fn take_fetched(
+        &self,
+        query: &Q,
+        source: &dyn Source<Q, C>,
+        result: Option<Result<Vec<C>, PipelineError>>,
+        errors: &mut StageErrors,
+        trail: &mut AuditTrail,
+    ) -> Result<Option<Vec<C>>, StageError> {
+        let request_id = query.request_id();
+        let stage = PipelineStage::Source;
+        let candidates = match result {
+            Some(Ok(candidates)) => {
+                info!(
+                    "request_id={} stage={:?} component={} fetched {} candidates",
+                    request_id,
+                    stage,
+                    source.name(),
+                    candidates.len()
+                );
+                Some(candidates)
+            },
+            Some(Err(err)) => {
+                error!(
+                    "request_id={} stage={:?} component={} failed: {}",
+                    request_id,
+                    stage,
+                    source.name(),
+                    err
+                );
+                errors.fail(stage, source.name(), err, || source.fallback(query))?
+            },
+            None => {
+                warn!(
+                    "request_id={} stage={:?} component={} skipped: timed_out",
+                    request_id,
+                    stage,
+                    source.name()
+                );
+                if let Some(metrics) = self.metrics() {
+                    metrics.record_source_timeout(source.name());
+                }
+                let err = PipelineError::timeout();
+                errors.fail(stage, source.name(), err, || source.fallback(query))?
+            },
+        };
+        if let Some(candidates) = &candidates {
+            let name = source.name();
+            let id = |c: &C| self.candidate_id(c);
+            trail.record_all(candidates, id, |p| p.sources.push(name.to_string()));
+        }
+        Ok(candidates)
     }
 
     /// Run all candidate hydrators in parallel and merge results into candidates.
-    async fn hydrate(&self, query: &Q, candidates: Vec<C>) -> Vec<C> {
-        self.run_hydrators(query, candidates, self.hydrators(), PipelineStage::Hydrator)
+    async fn hydrate(
+        &self,
+        query: &Q,
+        candidates: Vec<C>,
+        errors: &mut StageErrors,
+        trail: &mut AuditTrail,
+    ) -> Result<Vec<C>, StageError> {
+        let hydrators = self.hydrators();
+        self.run_hydrators(query, candidates, hydrators, PipelineStage::Hydrator, errors, trail)
             .await
     }
 
     /// Run post-selection candidate hydrators in parallel and merge results into candidates.
-    async fn hydrate_post_selection(&self, query: &Q, candidates: Vec<C>) -> Vec<C> {
+    async fn hydrate_post_selection(
+        &self,
+        query: &Q,
+        candidates: Vec<C>,
+        errors: &mut StageErrors,
+        trail: &mut AuditTrail,
+    ) -> Result<Vec<C>, StageError> {
         self.run_hydrators(
             query,
             candidates,
             self.post_selection_hydrators(),
             PipelineStage::PostSelectionHydrator,
+            errors,
+            trail,
         )
         .await
     }
@@ -180,29 +528,40 @@ where
         mut candidates: Vec<C>,
         hydrators: &[Box<dyn Hydrator<Q, C>>],
         stage: PipelineStage,
-    ) -> Vec<C> {
+        errors: &mut StageErrors,
+        trail: &mut AuditTrail,
+    ) -> Result<Vec<C>, StageError> {
         let request_id = query.request_id().to_string();
-        let hydrators: Vec<_> = hydrators.iter().filter(|h| h.enable(query)).collect();
+        let hydrators: Vec<_> =
+            hydrators.iter().filter(|h| !self.killed(h.name()) && h.enable(query)).collect();
         let expected_len = candidates.len();
-        let hydrate_futures = hydrators.iter().map(|h| h.hydrate(query, &candidates));
+        let deadline = self.stage_deadline(stage);
+        let hydrate_futures = hydrators.iter().map(|h| {
+            until_deadline(deadline.filter(|_| h.is_optional()), h.hydrate(query, &candidates))
+        });
         let results = join_all(hydrate_futures).await;
+        let id = |c: &C| self.candidate_id(c);
         for (hydrator, result) in hydrators.iter().zip(results) {
-            match result {
-                Ok(hydrated) => {
-                    if hydrated.len() == expected_len {
-                        hydrator.update_all(&mut candidates, hydrated);
-                    } else {
-                        warn!(
-                            "request_id={} stage={:?} component={} skipped: length_mismatch expected={} got={}",
-                            request_id,
-                            stage,
-                            hydrator.name(),
-                            expected_len,
-                            hydrated.len()
-                        );
-                    }
+            let name = hydrator.name();
+            let err = match result {
+                Some(Ok(hydrated)) if hydrated.len() == expected_len => {
+                    hydrator.update_all(&mut candidates, hydrated);
+                    trail.record_all(&candidates, id, |p| p.hydrators.push(name.to_string()));
+                    continue;
+                },
+                Some(Ok(hydrated)) => {
+                    let message =
+                        format!("length_mismatch expected={} got={}", expected_len, hydrated.len());
+                    warn!(
+                        "request_id={} stage={:?} component={} skipped: {}",
+                        request_id,
+                        stage,
+                        hydrator.name(),
+                        message
+                    );
+                    PipelineError::invalid_output(message)
                 },
-                Err(err) => {
+                Some(Err(err)) => {
                     error!(
                         "request_id={} stage={:?} component={} failed: {}",
                         request_id,
@@ -210,25 +569,58 @@ where
                         hydrator.name(),
                         err
                     );
+                    err
                 },
+                None => {
+                    warn!(
+                        "request_id={} stage={:?} component={} skipped: timed_out",
+                        request_id,
+                        stage,
+                        hydrator.name()
+                    );
+                    PipelineError::timeout()
+                },
+            };
+            let fallback = || {
+                let hydrated = hydrator.fallback(query, &candidates)?;
+                (hydrated.len() == expected_len).then_some(hydrated)
+            };
+            if let Some(hydrated) = errors.fail(stage, name, err, fallback)? {
+                hydrator.update_all(&mut candidates, hydrated);
+                trail.record_all(&candidates, id, |p| p.hydrators.push(name.to_string()));
             }
         }
-        candidates
+        Ok(candidates)
     }
 
     /// Run all filters sequentially. Each filter partitions candidates into kept and removed.
-    async fn filter(&self, query: &Q, candidates: Vec<C>) -> (Vec<C>, Vec<C>) {
-        self.run_filters(query, candidates, self.filters(), PipelineStage::Filter)
+    async fn filter(
+        &self,
+        query: &Q,
+        candidates: Vec<C>,
+        errors: &mut StageErrors,
+        trail: &mut AuditTrail,
+    ) -> Result<(Vec<C>, Vec<C>), StageError> {
+        let filters = self.filters();
+        self.run_filters(query, candidates, filters, PipelineStage::Filter, errors, trail)
             .await
     }
 
     /// Run post-scoring filters sequentially on already-scored candidates.
-    async fn filter_post_selection(&self, query: &Q, candidates: Vec<C>) -> (Vec<C>, Vec<C>) {
+    async fn filter_post_selection(
+        &self,
+        query: &Q,
+        candidates: Vec<C>,
+        errors: &mut StageErrors,
+        trail: &mut AuditTrail,
+    ) -> Result<(Vec<C>, Vec<C>), StageError> {
         self.run_filters(
             query,
             candidates,
             self.post_selection_filters(),
             PipelineStage::PostSelectionFilter,
+            errors,
+            trail,
         )
         .await
     }
@@ -240,17 +632,45 @@ where
         mut candidates: Vec<C>,
         filters: &[Box<dyn Filter<Q, C>>],
         stage: PipelineStage,
-    ) -> (Vec<C>, Vec<C>) {
+        errors: &mut StageErrors,
+        trail: &mut AuditTrail,
+    ) -> Result<(Vec<C>, Vec<C>), StageError> {
         let request_id = query.request_id().to_string();
         let mut all_removed = Vec::new();
-        for filter in filters.iter().filter(|f| f.enable(query)) {
+        let killed = |f: &dyn Filter<Q, C>| f.is_killable() && self.killed(f.name());
+        let mut enabled: Vec<_> =
+            filters.iter().filter(|f| !killed(f.as_ref()) && f.enable(query)).collect();
+        if let Some(optimizer) = self.filter_optimizer() {
+            let keys: Vec<_> = enabled
+                .iter()
+                .map(|f| (f.name(), f.reorder_group()))
+                .collect();
+            let order = optimizer.order(&keys);
+            enabled = order.into_iter().map(|i| enabled[i]).collect();
+        }
+        let deadline = self.stage_deadline(stage);
+        let id = |c: &C| self.candidate_id(c);
+        for filter in enabled {
+            if candidates.is_empty() {
+                break;
+            }
             let backup = candidates.clone();
-            match filter.filter(query, candidates).await {
-                Ok(result) => {
+            let input_len = candidates.len();
+            let start = Instant::now();
+            let filter_deadline = deadline.filter(|_| filter.is_optional());
+            let result = until_deadline(filter_deadline, filter.filter(query, candidates)).await;
+            let err = match result {
+                Some(Ok(result)) => {
+                    if let Some(optimizer) = self.filter_optimizer() {
+                        let kept_len = result.kept.len();
+                        optimizer.record(filter.name(), input_len, kept_len, start.elapsed());
+                    }
+                    trail.record_filter(filter.name(), &result.kept, &result.removed, id);
                     candidates = result.kept;
                     all_removed.extend(result.removed);
+                    continue;
                 },
-                Err(err) => {
+                Some(Err(err)) => {
                     error!(
                         "request_id={} stage={:?} component={} failed: {}",
                         request_id,
@@ -258,8 +678,26 @@ where
                         filter.name(),
                         err
                     );
-                    candidates = backup;
+                    err
+                },
+                None => {
+                    warn!(
+                        "request_id={} stage={:?} component={} skipped: timed_out",
+                        request_id,
+                        stage,
+                        filter.name()
+                    );
+                    PipelineError::timeout()
+                },
+            };
+            let fallback = || filter.fallback(query, &backup);
+            match errors.fail(stage, filter.name(), err, fallback)? {
+                Some(result) => {
+                    trail.record_filter(filter.name(), &result.kept, &result.removed, id);
+                    candidates = result.kept;
+                    all_removed.extend(result.removed);
                 },
+                None => candidates = backup,
             }
         }
         info!(
@@ -269,41 +707,77 @@ where
             candidates.len(),
             all_removed.len()
         );
-        (candidates, all_removed)
+        Ok((candidates, all_removed))
     }
 
     /// Run all scorers sequentially and apply their results to candidates.
-    async fn score(&self, query: &Q, mut candidates: Vec<C>) -> Vec<C> {
+    async fn score(
+        &self,
+        query: &Q,
+        mut candidates: Vec<C>,
+        errors: &mut StageErrors,
+        trail: &mut AuditTrail,
+    ) -> Result<Vec<C>, StageError> {
         let request_id = query.request_id().to_string();
+        let stage = PipelineStage::Scorer;
         let expected_len = candidates.len();
-        for scorer in self.scorers().iter().filter(|s| s.enable(query)) {
-            match scorer.score(query, &candidates).await {
-                Ok(scored) => {
-                    if scored.len() == expected_len {
-                        scorer.update_all(&mut candidates, scored);
-                    } else {
-                        warn!(
-                            "request_id={} stage={:?} component={} skipped: length_mismatch expected={} got={}",
-                            request_id,
-                            PipelineStage::Scorer,
-                            scorer.name(),
-                            expected_len,
-                            scored.len()
-                        );
-                    }
+        let deadline = self.stage_deadline(stage);
+        let id = |c: &C| self.candidate_id(c);
+        let score = |c: &C| self.selector().score(c);
+        let scorers = self.scorers().iter().filter(|s| !self.killed(s.name()) && s.enable(query));
+        for scorer in scorers {
+            let scorer_deadline = deadline.filter(|_| scorer.is_optional());
+            let result = until_deadline(scorer_deadline, scorer.score(query, &candidates)).await;
+            let err = match result {
+                Some(Ok(scored)) if scored.len() == expected_len => {
+                    let before = trail.scores(&candidates, score);
+                    scorer.update_all(&mut candidates, scored);
+                    trail.record_scores(scorer.name(), &before, &candidates, id, score);
+                    continue;
+                },
+                Some(Ok(scored)) => {
+                    let message =
+                        format!("length_mismatch expected={} got={}", expected_len, scored.len());
+                    warn!(
+                        "request_id={} stage={:?} component={} skipped: {}",
+                        request_id,
+                        stage,
+                        scorer.name(),
+                        message
+                    );
+                    PipelineError::invalid_output(message)
                 },
-                Err(err) => {
+                Some(Err(err)) => {
                     error!(
                         "request_id={} stage={:?} component={} failed: {}",
                         request_id,
-                        PipelineStage::Scorer,
+                        stage,
                         scorer.name(),
                         err
                     );
+                    err
                 },
+                None => {
+                    warn!(
+                        "request_id={} stage={:?} component={} skipped: timed_out",
+                        request_id,
+                        stage,
+                        scorer.name()
+                    );
+                    PipelineError::timeout()
+                },
+            };
+            let fallback = || {
+                let scored = scorer.fallback(query, &candidates)?;
+                (scored.len() == expected_len).then_some(scored)
+            };
+            if let Some(scored) = errors.fail(stage, scorer.name(), err, fallback)? {
+                let before = trail.scores(&candidates, score);
+                scorer.update_all(&mut candidates, scored);
+                trail.record_scores(scorer.name(), &before, &candidates, id, score);
             }
         }
-        candidates
+        Ok(candidates)
     }
 
     /// Select (sort/truncate) candidates using the configured selector
@@ -317,11 +791,17 @@ where
 
     // Run all side effects in parallel
     fn run_side_effects(&self, input: Arc<SideEffectInput<Q, C>>) {
+        if let Some(executor) = self.side_effect_executor() {
+            executor.submit(input);
+            return;
+        }
         let side_effects = self.side_effects();
+        let kill_switches = self.kill_switches().cloned();
         tokio::spawn(async move {
+            let killed = |name| kill_switches.as_ref().is_some_and(|s| s.is_killed(name));
             let futures = side_effects
                 .iter()
-                .filter(|se| se.enable(input.query.clone()))
+                .filter(|se| !killed(se.name()) && se.enable(input.query.clone()))
                 .map(|se| se.run(input.clone()));
             let _ = join_all(futures).await;
         });
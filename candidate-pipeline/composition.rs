@@ -0,0 +1,269 @@
+//! Feed Composition
+//!
+//! Fills a feed from named slices, e.g. in-network and out-of-network posts,
+//! each ranked by its own sub-pipeline. A `SlotPattern` gives every slice a
+//! share of each block of positions, such as 6 in-network, 3 out-of-network
+//! and 1 trending per 10, and the slices' slots are spread evenly through the
+//! block. A position whose slice has run dry goes to the next slice of the
+//! pattern that still has candidates, so the feed is only short when all are.
+
+use std::collections::{HashSet, VecDeque};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use futures::future::join_all;
+use log::warn;
+
+use crate::candidate_pipeline::{CandidatePipeline, HasRequestId};
+use crate::error_policy::StageError;
+
+/// Positions per block for each slice, in order of backfill priority
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SlotPattern {
+    slots: Vec<(String, usize)>,
+}
+
+impl SlotPattern {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_slot(mut self, slice: impl Into<String>, count: usize) -> Self {
+        self.slots.push((slice.into(), count));
+        self
+    }
+
+    pub fn slices(&self) -> impl Iterator<Item = &str> {
+        self.slots.iter().map(|(slice, _)| slice.as_str())
+    }
+
+    /// Index into `slots` of each position of one block, spreading each
+    /// slice's positions as evenly as they go (smooth weighted round robin)
+    fn block(&self) -> Vec<usize> {
+        let total: usize = self.slots.iter().map(|(_, count)| count).sum();
+        let mut current = vec![0i64; self.slots.len()];
+        let mut block = Vec::with_capacity(total);
+        for _ in 0..total {
+            for (weight, (_, count)) in current.iter_mut().zip(&self.slots) {
+                *weight += *count as i64;
+            }
+            let next = (0..current.len())
+                .max_by_key(|&i| (current[i], std::cmp::Reverse(i)))
+                .unwrap_or_default();
+            current[next] -= total as i64;
+            block.push(next);
+        }
+        block
+    }
+}
+
+/// Parses `slice:count` pairs separated by commas, e.g. `in_network:6,out_of_network:4`
+impl FromStr for SlotPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut pattern = SlotPattern::new();
+        for slot in s.split(',').map(str::trim).filter(|slot| !slot.is_empty()) {
+            let (slice, count) = slot
+                .split_once(':')
+                .ok_or_else(|| format!("slot '{}' is not slice:count", slot))?;
+            let count = count
+                .trim()
+                .parse()
+                .map_err(|e| format!("slot '{}': {}", slot, e))?;
+            pattern = pattern.with_slot(slice.trim(), count);
+        }
+        if pattern.slots.iter().all(|(_, count)| *count == 0) {
+            return Err(format!("pattern '{}' has no positions", s));
+        }
+        Ok(pattern)
+    }
+}
+
+/// A served candidate and the slice it was drawn from
+#[derive(Clone, Debug)]
+pub struct ComposedItem<C> {
+    pub slice: String,
+    pub candidate: C,
+}
+
+pub struct ComposedFeed<C> {
+    pub items: Vec<ComposedItem<C>>,
+    /// Errors of every sub-pipeline; a slice whose pipeline failed is empty
+    pub errors: Vec<StageError>,
+}
+
+/// Runs one sub-pipeline per slice and interleaves their results by pattern
+pub struct FeedComposer<Q, C> {
+    pattern: SlotPattern,
+    size: usize,
+    slices: Vec<(String, Arc<dyn CandidatePipeline<Q, C>>)>,
+    candidate_id: Option<fn(&C) -> u64>,
+}
+
+impl<Q, C> FeedComposer<Q, C>
+where
+    Q: HasRequestId + Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+{
+    /// A composer of feeds of up to `size` candidates
+    pub fn new(pattern: SlotPattern, size: usize) -> Self {
+        Self {
+            pattern,
+            size,
+            slices: Vec::new(),
+            candidate_id: None,
+        }
+    }
+
+    /// Rank `slice` with `pipeline`; pattern slices without one stay empty
+    pub fn slice(
+        mut self,
+        slice: impl Into<String>,
+        pipeline: Arc<dyn CandidatePipeline<Q, C>>,
+    ) -> Self {
+        self.slices.push((slice.into(), pipeline));
+        self
+    }
+
+    /// Drop candidates already served by an earlier slice
+    pub fn candidate_id(mut self, id: fn(&C) -> u64) -> Self {
+        self.candidate_id = Some(id);
+        self
+    }
+
+    pub async fn compose(&self, query: Q) -> ComposedFeed<C> {
+        let request_id = query.request_id().to_string();
+        let runs = self.slices.iter().map(|(_, pipeline)| pipeline.execute(query.clone()));
+        let results = join_all(runs).await;
+
+        let mut errors = Vec::new();
+        let mut ranked: Vec<VecDeque<C>> = vec![VecDeque::new(); self.pattern.slots.len()];
+        for ((slice, _), result) in self.slices.iter().zip(results) {
+            let candidates = match result {
+                Ok(result) => {
+                    errors.extend(result.errors);
+                    result.selected_candidates
+                },
+                Err(e) => {
+                    warn!("request_id={} slice={} failed: {}", request_id, slice, e);
+                    errors.push(e);
+                    continue;
+                },
+            };
+            if let Some(i) = self.pattern.slices().position(|s| s == slice) {
+                ranked[i].extend(candidates);
+            }
+        }
+
+        let block = self.pattern.block();
+        if block.is_empty() {
+            return ComposedFeed {
+                items: Vec::new(),
+                errors,
+            };
+        }
+        let mut served = HashSet::new();
+        let mut items = Vec::with_capacity(self.size);
+        for position in 0..self.size {
+            let slot = block[position % block.len()];
+            let backfill = (0..ranked.len()).filter(|&i| i != slot);
+            let drawn = std::iter::once(slot)
+                .chain(backfill)
+                .find_map(|i| self.next_unserved(&mut ranked[i], &mut served).map(|c| (i, c)));
+            let Some((i, candidate)) = drawn else {
+                break;
+            };
+            items.push(ComposedItem {
+                slice: self.pattern.slots[i].0.clone(),
+                candidate,
+            });
+        }
+        ComposedFeed { items, errors }
+    }
+
+    fn next_unserved(&self, ranked: &mut VecDeque<C>, served: &mut HashSet<u64>) -> Option<C> {
+        while let Some(candidate) = ranked.pop_front() {
+            match self.candidate_id {
+                Some(id) if !served.insert(id(&candidate)) => continue,
+                _ => return Some(candidate),
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::PipelineBuilder;
+    use crate::error::PipelineError;
+    use crate::selector::Selector;
+    use crate::source::Source;
+    use tonic::async_trait;
+
+    #[derive(Clone)]
+    struct Query;
+
+    impl HasRequestId for Query {
+        fn request_id(&self) -> &str {
+            "test"
+        }
+    }
+
+    struct Fixed(Vec<i64>);
+
+    #[async_trait]
+    impl Source<Query, i64> for Fixed {
+        async fn get_candidates(&self, _query: &Query) -> Result<Vec<i64>, PipelineError> {
+            Ok(self.0.clone())
+        }
+    }
+
+    struct Descending;
+
+    impl Selector<Query, i64> for Descending {
+        fn score(&self, candidate: &i64) -> f64 {
+            *candidate as f64
+        }
+    }
+
+    fn ranked(candidates: Vec<i64>) -> Arc<dyn CandidatePipeline<Query, i64>> {
+        let pipeline = PipelineBuilder::new()
+            .source(Fixed(candidates))
+            .selector(Descending)
+            .result_size(10)
+            .build()
+            .unwrap();
+        Arc::new(pipeline)
+    }
+
+    #[test]
+    fn test_pattern_spreads_slots_through_the_block() {
+        let pattern: SlotPattern = "in:6, oon:3, trending:1".parse().unwrap();
+        assert_eq!(pattern.block(), vec![0, 1, 0, 0, 1, 0, 2, 0, 1, 0]);
+        assert!("in:6,oon".parse::<SlotPattern>().is_err());
+        assert!("in:0".parse::<SlotPattern>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_composer_interleaves_slices_and_backfills() {
+        let pattern = SlotPattern::new().with_slot("in", 2).with_slot("oon", 1);
+        let composer = FeedComposer::new(pattern, 6)
+            .slice("in", ranked(vec![11, 12, 13, 14]))
+            .slice("oon", ranked(vec![21, 12]))
+            .candidate_id(|c: &i64| *c as u64);
+
+        let feed = composer.compose(Query).await;
+        let served: Vec<(&str, i64)> = feed
+            .items
+            .iter()
+            .map(|item| (item.slice.as_str(), item.candidate))
+            .collect();
+        // 12 was served in-network first, so out-of-network runs dry and its
+        // second slot is backfilled; the feed ends once every slice is empty
+        let expected = vec![("in", 14), ("oon", 21), ("in", 13), ("in", 12), ("in", 11)];
+        assert_eq!(served, expected);
+        assert!(feed.errors.is_empty());
+    }
+}
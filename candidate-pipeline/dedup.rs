@@ -0,0 +1,141 @@
+//! Candidate Deduplication
+//!
+//! Sources often return the same post, e.g. one found both in-network and by
+//! embedding retrieval, or an original and its retweets. A pipeline with a
+//! `DedupStage` drops such duplicates right after fetching, before any work is
+//! spent hydrating them. Candidates are duplicates when the stage's key
+//! function maps them to the same key: the post id, the id of the retweeted
+//! post, or a hash of the text such as `memo::fingerprint`. Of each set of
+//! duplicates, the copy from the highest-priority source is kept.
+//!
+//! Streaming pipelines cannot wait for every source, so there the first copy
+//! to arrive is kept whatever its source.
+
+use std::collections::{HashMap, HashSet};
+
+/// Candidates left after deduplication, and the duplicates dropped
+#[derive(Clone, Debug, PartialEq)]
+pub struct Deduped<C> {
+    pub kept: Vec<C>,
+    pub removed: Vec<C>,
+}
+
+/// Drops candidates fetched more than once across a request's sources
+pub struct DedupStage<C> {
+    key: fn(&C) -> u64,
+    /// Sources whose copies are kept first, highest priority first
+    priority: Vec<&'static str>,
+}
+
+impl<C> Clone for DedupStage<C> {
+    fn clone(&self) -> Self {
+        Self {
+            key: self.key,
+            priority: self.priority.clone(),
+        }
+    }
+}
+
+impl<C> DedupStage<C> {
+    /// Drop candidates with the same `key`, keeping the copy of the source
+    /// added to the pipeline first
+    pub fn new(key: fn(&C) -> u64) -> Self {
+        Self {
+            key,
+            priority: Vec::new(),
+        }
+    }
+
+    /// Keep copies of `sources` over any other's, in the order given; the rest
+    /// rank after them in pipeline order
+    pub fn with_priority(mut self, sources: &[&'static str]) -> Self {
+        self.priority = sources.to_vec();
+        self
+    }
+
+    pub fn priority(&self) -> &[&'static str] {
+        &self.priority
+    }
+
+    /// Deduplicate what each source fetched, given as `(source, candidates)` in
+    /// pipeline order. Kept candidates stay in the order they were fetched.
+    pub fn dedup(&self, fetched: Vec<(&'static str, Vec<C>)>) -> Deduped<C> {
+        let rank = |index: usize, source: &str| {
+            let listed = self.priority.iter().position(|s| *s == source);
+            listed.unwrap_or(self.priority.len() + index)
+        };
+        // The (rank, batch, position) of the copy kept for each key
+        let mut winners: HashMap<u64, (usize, usize, usize)> = HashMap::new();
+        for (batch, (source, candidates)) in fetched.iter().enumerate() {
+            let rank = rank(batch, source);
+            for (position, candidate) in candidates.iter().enumerate() {
+                let copy = (rank, batch, position);
+                let winner = winners.entry((self.key)(candidate)).or_insert(copy);
+                *winner = (*winner).min(copy);
+            }
+        }
+
+        let mut deduped = Deduped {
+            kept: Vec::new(),
+            removed: Vec::new(),
+        };
+        for (batch, (_, candidates)) in fetched.into_iter().enumerate() {
+            for (position, candidate) in candidates.into_iter().enumerate() {
+                let (_, kept_batch, kept_position) = winners[&(self.key)(&candidate)];
+                if (kept_batch, kept_position) == (batch, position) {
+                    deduped.kept.push(candidate);
+                } else {
+                    deduped.removed.push(candidate);
+                }
+            }
+        }
+        deduped
+    }
+
+    /// Deduplicate one batch of a streaming request against the keys `seen` in
+    /// its earlier batches, adding the batch's
+    pub fn dedup_batch(&self, seen: &mut HashSet<u64>, batch: Vec<C>) -> Deduped<C> {
+        let (kept, removed) = batch.into_iter().partition(|c| seen.insert((self.key)(c)));
+        Deduped { kept, removed }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (post id, id of the retweeted post or the post's own)
+    type Post = (u64, u64);
+
+    fn retweeted(post: &Post) -> u64 {
+        post.1
+    }
+
+    #[test]
+    fn test_keeps_the_copy_of_the_highest_priority_source() {
+        let fetched = || {
+            vec![
+                ("InNetwork", vec![(1, 1), (2, 10)]),
+                ("Embedding", vec![(10, 10), (3, 3), (3, 3)]),
+            ]
+        };
+
+        let deduped = DedupStage::new(retweeted).dedup(fetched());
+        assert_eq!(deduped.kept, vec![(1, 1), (2, 10), (3, 3)]);
+        assert_eq!(deduped.removed, vec![(10, 10), (3, 3)]);
+
+        let stage = DedupStage::new(retweeted).with_priority(&["Embedding"]);
+        let deduped = stage.dedup(fetched());
+        assert_eq!(deduped.kept, vec![(1, 1), (10, 10), (3, 3)]);
+        assert_eq!(deduped.removed, vec![(2, 10), (3, 3)]);
+    }
+
+    #[test]
+    fn test_streamed_batches_keep_the_first_copy() {
+        let stage = DedupStage::new(retweeted);
+        let mut seen = HashSet::new();
+        assert_eq!(stage.dedup_batch(&mut seen, vec![(1, 1), (2, 1)]).kept, vec![(1, 1)]);
+        let deduped = stage.dedup_batch(&mut seen, vec![(3, 3), (1, 1)]);
+        assert_eq!((deduped.kept, deduped.removed), (vec![(3, 3)], vec![(1, 1)]));
+    }
+}
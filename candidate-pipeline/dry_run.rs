@@ -0,0 +1,264 @@
+//! Dry Runs
+//!
+//! Runs a pipeline on candidates given by the caller instead of its sources,
+//! e.g. ones recorded from a production request, and reports what each stage
+//! did with them. Stages run as they would for a live request, except that no
+//! side effects run, nothing is recorded in the pipeline's metrics, and the
+//! provenance of every candidate is kept. Replays, golden tests and explaining
+//! a ranking all start from here.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::candidate_pipeline::{CandidatePipeline, HasRequestId, PipelineResult, PipelineStage};
+use crate::error_policy::{StageError, StageErrors};
+use crate::provenance::AuditTrail;
+
+/// What one stage of a dry run did
+#[derive(Clone, Debug)]
+pub struct StageTrace {
+    pub stage: PipelineStage,
+    pub elapsed: Duration,
+    pub candidates_in: usize,
+    pub candidates_out: usize,
+    /// Components of the stage that failed, the one failing the run included
+    pub errors: Vec<StageError>,
+    /// Whether the stage failed the run, ending it
+    pub failed: bool,
+}
+
+/// The outcome of a dry run, with a trace of each stage that ran
+pub struct DryRun<Q, C> {
+    pub stages: Vec<StageTrace>,
+    pub result: Result<PipelineResult<Q, C>, StageError>,
+}
+
+impl<Q, C> DryRun<Q, C> {
+    pub fn stage(&self, stage: PipelineStage) -> Option<&StageTrace> {
+        self.stages.iter().find(|trace| trace.stage == stage)
+    }
+}
+
+/// A stage of a dry run in progress
+struct Step {
+    stage: PipelineStage,
+    started: Instant,
+    input: usize,
+    errors_before: usize,
+}
+
+impl Step {
+    fn start(stage: PipelineStage, input: usize, errors: &StageErrors) -> Self {
+        Self {
+            stage,
+            started: Instant::now(),
+            input,
+            errors_before: errors.errors().len(),
+        }
+    }
+
+    /// Trace the stage's `result`, with `output` candidates when it succeeded
+    fn finish<T>(
+        self,
+        stages: &mut Vec<StageTrace>,
+        result: Result<T, StageError>,
+        output: impl FnOnce(&T) -> usize,
+        errors: &StageErrors,
+    ) -> Result<T, StageError> {
+        let mut failed = errors.errors()[self.errors_before..].to_vec();
+        let candidates_out = match &result {
+            Ok(value) => output(value),
+            Err(err) => {
+                failed.push(err.clone());
+                0
+            },
+        };
+        stages.push(StageTrace {
+            stage: self.stage,
+            elapsed: self.started.elapsed(),
+            candidates_in: self.input,
+            candidates_out,
+            errors: failed,
+            failed: result.is_err(),
+        });
+        result
+    }
+}
+
+/// Run every stage of `pipeline` after its sources on `candidates`
+pub async fn dry_run<P, Q, C>(pipeline: &P, query: Q, candidates: Vec<C>) -> DryRun<Q, C>
+where
+    P: CandidatePipeline<Q, C> + ?Sized,
+    Q: HasRequestId + Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+{
+    let mut stages = Vec::new();
+    let result = run(pipeline, query, candidates, &mut stages).await;
+    DryRun { stages, result }
+}
+
+async fn run<P, Q, C>(
+    pipeline: &P,
+    query: Q,
+    candidates: Vec<C>,
+    stages: &mut Vec<StageTrace>,
+) -> Result<PipelineResult<Q, C>, StageError>
+where
+    P: CandidatePipeline<Q, C> + ?Sized,
+    Q: HasRequestId + Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+{
+    let policies = pipeline.error_policies().copied().unwrap_or_default();
+    let mut errors = StageErrors::new(query.request_id(), policies);
+    let mut trail = AuditTrail::new(true);
+
+    let step = Step::start(PipelineStage::QueryHydrator, 0, &errors);
+    let result = pipeline.hydrate_query(query, &mut errors).await;
+    let query = step.finish(stages, result, |_| 0, &errors)?;
+
+    let step = Step::start(PipelineStage::Hydrator, candidates.len(), &errors);
+    let result = pipeline.hydrate(&query, candidates, &mut errors, &mut trail).await;
+    let hydrated = step.finish(stages, result, Vec::len, &errors)?;
+
+    let step = Step::start(PipelineStage::Filter, hydrated.len(), &errors);
+    let result = pipeline.filter(&query, hydrated.clone(), &mut errors, &mut trail).await;
+    let (kept, mut filtered) = step.finish(stages, result, |(kept, _)| kept.len(), &errors)?;
+
+    let step = Step::start(PipelineStage::Scorer, kept.len(), &errors);
+    let result = pipeline.score(&query, kept, &mut errors, &mut trail).await;
+    let scored = step.finish(stages, result, Vec::len, &errors)?;
+    let selected = pipeline.select(&query, scored);
+
+    let step = Step::start(PipelineStage::PostSelectionHydrator, selected.len(), &errors);
+    let result = pipeline
+        .hydrate_post_selection(&query, selected, &mut errors, &mut trail)
+        .await;
+    let selected = step.finish(stages, result, Vec::len, &errors)?;
+
+    let step = Step::start(PipelineStage::PostSelectionFilter, selected.len(), &errors);
+    let result = pipeline
+        .filter_post_selection(&query, selected, &mut errors, &mut trail)
+        .await;
+    let (mut selected, removed) = step.finish(stages, result, |(kept, _)| kept.len(), &errors)?;
+    filtered.extend(removed);
+    selected.truncate(pipeline.result_size());
+
+    Ok(PipelineResult {
+        retrieved_candidates: hydrated,
+        filtered_candidates: filtered,
+        selected_candidates: selected,
+        query: Arc::new(query),
+        errors: errors.into_errors(),
+        provenance: trail.into_records(),
+        partial: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::PipelineBuilder;
+    use crate::error::PipelineError;
+    use crate::filter::{Filter, FilterResult};
+    use crate::scorer::Scorer;
+    use crate::selector::Selector;
+    use crate::side_effect::{SideEffect, SideEffectInput};
+    use crate::source::Source;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use tonic::async_trait;
+
+    #[derive(Clone)]
+    struct Query;
+
+    impl HasRequestId for Query {
+        fn request_id(&self) -> &str {
+            "test"
+        }
+    }
+
+    struct Unused;
+
+    #[async_trait]
+    impl Source<Query, u64> for Unused {
+        async fn get_candidates(&self, _query: &Query) -> Result<Vec<u64>, PipelineError> {
+            panic!("dry runs don't fetch candidates")
+        }
+    }
+
+    struct DropOdd;
+
+    #[async_trait]
+    impl Filter<Query, u64> for DropOdd {
+        async fn filter(
+            &self,
+            _query: &Query,
+            candidates: Vec<u64>,
+        ) -> Result<FilterResult<u64>, PipelineError> {
+            let (kept, removed) = candidates.into_iter().partition(|c| c % 2 == 0);
+            Ok(FilterResult { kept, removed })
+        }
+    }
+
+    struct Unreachable;
+
+    #[async_trait]
+    impl Scorer<Query, u64> for Unreachable {
+        async fn score(
+            &self,
+            _query: &Query,
+            _candidates: &[u64],
+        ) -> Result<Vec<u64>, PipelineError> {
+            Err(PipelineError::unavailable("model server"))
+        }
+
+        fn update(&self, _candidate: &mut u64, _scored: u64) {}
+    }
+
+    struct Identity;
+
+    impl Selector<Query, u64> for Identity {
+        fn score(&self, candidate: &u64) -> f64 {
+            *candidate as f64
+        }
+    }
+
+    struct Ran(Arc<AtomicBool>);
+
+    #[async_trait]
+    impl SideEffect<Query, u64> for Ran {
+        async fn run(&self, _input: Arc<SideEffectInput<Query, u64>>) -> Result<(), PipelineError> {
+            self.0.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dry_runs_trace_every_stage_after_sources() {
+        let ran = Arc::new(AtomicBool::new(false));
+        let pipeline = PipelineBuilder::new()
+            .source(Unused)
+            .filter(DropOdd)
+            .scorer(Unreachable)
+            .selector(Identity)
+            .side_effect(Ran(ran.clone()))
+            .result_size(10)
+            .build()
+            .unwrap();
+
+        let run = pipeline.dry_run(Query, vec![1, 2, 3, 4]).await;
+        let selected = &run.result.as_ref().unwrap().selected_candidates;
+        assert_eq!(selected, &vec![4, 2]);
+        let stages: Vec<_> = run.stages.iter().map(|t| t.stage).collect();
+        assert_eq!(stages.len(), 6);
+        assert!(!stages.contains(&PipelineStage::Source));
+
+        let filter = run.stage(PipelineStage::Filter).unwrap();
+        assert_eq!((filter.candidates_in, filter.candidates_out), (4, 2));
+        let scorer = run.stage(PipelineStage::Scorer).unwrap();
+        assert_eq!((scorer.errors.len(), scorer.failed), (1, false));
+        assert_eq!(scorer.errors[0].component, "Unreachable");
+
+        tokio::task::yield_now().await;
+        assert!(!ran.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,190 @@
+//! Errors of Pipeline Components
+//!
+//! What sources, hydrators, filters, scorers and side effects return when they
+//! fail. Each error has a kind with a stable name for metrics, says whether the
+//! same call may succeed if made again, and keeps the error that caused it. The
+//! pipeline stamps the stage on errors it records, and the kind maps to a gRPC
+//! status, travelling in the `pipeline-error` metadata of the status.
+
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+use tonic::metadata::{MetadataMap, MetadataValue};
+use tonic::{Code, Status};
+
+use crate::candidate_pipeline::PipelineStage;
+
+/// gRPC metadata key carrying `ErrorKind::as_str`
+pub const ERROR_KIND_METADATA_KEY: &str = "pipeline-error";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// A backend the component calls couldn't be reached or shed the call
+    Unavailable,
+    /// The component ran past its stage's deadline
+    Timeout,
+    /// The query lacks something the component needs
+    InvalidInput,
+    /// The component returned something the pipeline can't use, e.g. the
+    /// wrong number of candidates
+    InvalidOutput,
+    Internal,
+}
+
+impl ErrorKind {
+    /// Stable name of the kind, e.g. `unavailable`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::Unavailable => "unavailable",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::InvalidInput => "invalid_input",
+            ErrorKind::InvalidOutput => "invalid_output",
+            ErrorKind::Internal => "internal",
+        }
+    }
+
+    /// Whether errors of this kind are retryable unless the component says otherwise
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, ErrorKind::Unavailable | ErrorKind::Timeout)
+    }
+
+    pub fn grpc_code(&self) -> Code {
+        match self {
+            ErrorKind::Unavailable => Code::Unavailable,
+            ErrorKind::Timeout => Code::DeadlineExceeded,
+            ErrorKind::InvalidInput => Code::InvalidArgument,
+            ErrorKind::InvalidOutput | ErrorKind::Internal => Code::Internal,
+        }
+    }
+}
+
+/// A failed call to a pipeline component
+#[derive(Clone, Debug)]
+pub struct PipelineError {
+    /// Stage of the component, set by the pipeline when it records the error
+    pub stage: Option<PipelineStage>,
+    pub kind: ErrorKind,
+    /// Whether the same call may succeed if made again after a backoff
+    pub retryable: bool,
+    pub message: String,
+    source: Option<Arc<dyn Error + Send + Sync>>,
+}
+
+impl PipelineError {
+    /// An error of `kind`, retryable if the kind is
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            stage: None,
+            kind,
+            retryable: kind.is_retryable(),
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    pub fn unavailable(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Unavailable, message)
+    }
+
+    pub fn timeout() -> Self {
+        Self::new(ErrorKind::Timeout, "timed_out")
+    }
+
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::InvalidInput, message)
+    }
+
+    pub fn invalid_output(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::InvalidOutput, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Internal, message)
+    }
+
+    pub fn with_retryable(mut self, retryable: bool) -> Self {
+        self.retryable = retryable;
+        self
+    }
+
+    /// Keep `source` as the cause of this error
+    pub fn with_source(mut self, source: impl Error + Send + Sync + 'static) -> Self {
+        self.source = Some(Arc::new(source));
+        self
+    }
+
+    pub fn in_stage(mut self, stage: PipelineStage) -> Self {
+        self.stage = Some(stage);
+        self
+    }
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.kind.as_str(), self.message)?;
+        if let Some(source) = &self.source {
+            write!(f, ": {}", source)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for PipelineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_deref().map(|source| source as &(dyn Error + 'static))
+    }
+}
+
+/// Untyped errors are internal and not retryable
+impl From<String> for PipelineError {
+    fn from(message: String) -> Self {
+        Self::internal(message)
+    }
+}
+
+impl From<&str> for PipelineError {
+    fn from(message: &str) -> Self {
+        Self::internal(message)
+    }
+}
+
+impl From<PipelineError> for Status {
+    fn from(error: PipelineError) -> Self {
+        let mut metadata = MetadataMap::new();
+        metadata.insert(ERROR_KIND_METADATA_KEY, MetadataValue::from_static(error.kind.as_str()));
+        Status::with_metadata(error.kind.grpc_code(), error.to_string(), metadata)
+    }
+}
+
+/// The `ErrorKind::as_str` a status was built from, if any
+pub fn error_kind(status: &Status) -> Option<&str> {
+    status
+        .metadata()
+        .get(ERROR_KIND_METADATA_KEY)
+        .and_then(|kind| kind.to_str().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_carry_kind_retryability_and_cause() {
+        let cause = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused");
+        let error = PipelineError::unavailable("thunder").with_source(cause);
+        assert!(error.retryable);
+        assert_eq!(error.to_string(), "unavailable: thunder: refused");
+        assert!(error.source().is_some());
+
+        let untyped = PipelineError::from("bad response".to_string());
+        assert_eq!(untyped.kind, ErrorKind::Internal);
+        assert!(!untyped.retryable);
+        assert!(!PipelineError::timeout().with_retryable(false).retryable);
+
+        let status = Status::from(PipelineError::timeout().in_stage(PipelineStage::Scorer));
+        assert_eq!(status.code(), Code::DeadlineExceeded);
+        assert_eq!(error_kind(&status), Some("timeout"));
+        assert_eq!(error_kind(&Status::internal("plain")), None);
+    }
+}
@@ -0,0 +1,322 @@
+//! Stage error policies
+//!
+//! What the pipeline does when a component of a stage fails, times out, or
+//! returns the wrong number of candidates. Each stage has its own policy: fail
+//! the whole request, skip the component as if it hadn't run, or use the
+//! component's fallback output in its place. Every error is recorded against
+//! its stage and component, so callers can report a degraded response.
+
+use std::fmt;
+
+use log::{error, info};
+use tonic::Status;
+
+use crate::candidate_pipeline::PipelineStage;
+use crate::error::PipelineError;
+
+/// What happens to a request when a component of a stage fails
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Fail the request with the component's error
+    FailRequest,
+    /// Go on without the component
+    #[default]
+    Skip,
+    /// Use the component's fallback output, or go on without it when it has none
+    Fallback,
+}
+
+/// Error policy of each stage; every stage skips failed components by default
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ErrorPolicies {
+    pub query_hydrator: ErrorPolicy,
+    pub source: ErrorPolicy,
+    /// Covers post-selection hydrators too
+    pub hydrator: ErrorPolicy,
+    /// Covers post-selection filters too
+    pub filter: ErrorPolicy,
+    pub scorer: ErrorPolicy,
+}
+
+impl ErrorPolicies {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_query_hydrator(mut self, policy: ErrorPolicy) -> Self {
+        self.query_hydrator = policy;
+        self
+    }
+
+    pub fn with_source(mut self, policy: ErrorPolicy) -> Self {
+        self.source = policy;
+        self
+    }
+
+    pub fn with_hydrator(mut self, policy: ErrorPolicy) -> Self {
+        self.hydrator = policy;
+        self
+    }
+
+    pub fn with_filter(mut self, policy: ErrorPolicy) -> Self {
+        self.filter = policy;
+        self
+    }
+
+    pub fn with_scorer(mut self, policy: ErrorPolicy) -> Self {
+        self.scorer = policy;
+        self
+    }
+
+    /// Policy of `stage`
+    pub fn get(&self, stage: PipelineStage) -> ErrorPolicy {
+        match stage {
+            PipelineStage::QueryHydrator => self.query_hydrator,
+            PipelineStage::Source => self.source,
+            PipelineStage::Hydrator | PipelineStage::PostSelectionHydrator => self.hydrator,
+            PipelineStage::Filter | PipelineStage::PostSelectionFilter => self.filter,
+            PipelineStage::Scorer => self.scorer,
+        }
+    }
+}
+
+/// How the pipeline dealt with a failed component
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorOutcome {
+    FailedRequest,
+    Skipped,
+    FellBack,
+}
+
+impl ErrorOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorOutcome::FailedRequest => "failed_request",
+            ErrorOutcome::Skipped => "skipped",
+            ErrorOutcome::FellBack => "fell_back",
+        }
+    }
+}
+
+/// A component that failed during a request
+#[derive(Clone, Debug)]
+pub struct StageError {
+    pub stage: PipelineStage,
+    pub component: &'static str,
+    pub error: PipelineError,
+    pub outcome: ErrorOutcome,
+}
+
+impl fmt::Display for StageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "stage={:?} component={} {}: {}",
+            self.stage,
+            self.component,
+            self.outcome.as_str(),
+            self.error
+        )
+    }
+}
+
+impl std::error::Error for StageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// The component's error decides the status, described with its stage and component
+impl From<StageError> for Status {
+    fn from(error: StageError) -> Self {
+        let status = Status::from(error.error.clone());
+        Status::with_metadata(status.code(), error.to_string(), status.metadata().clone())
+    }
+}
+
+/// Applies the error policies of one request and records its errors
+pub struct StageErrors {
+    request_id: String,
+    policies: ErrorPolicies,
+    errors: Vec<StageError>,
+}
+
+impl StageErrors {
+    pub fn new(request_id: impl Into<String>, policies: ErrorPolicies) -> Self {
+        Self {
+            request_id: request_id.into(),
+            policies,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Record that `component` of `stage` failed with `error` and decide what
+    /// happens next: Ok with the fallback output to use in its place, Ok(None)
+    /// to go on without it, or Err to fail the request. `fallback` is only
+    /// called under the `Fallback` policy.
+    pub fn fail<T>(
+        &mut self,
+        stage: PipelineStage,
+        component: &'static str,
+        error: PipelineError,
+        fallback: impl FnOnce() -> Option<T>,
+    ) -> Result<Option<T>, StageError> {
+        let (outcome, output) = match self.policies.get(stage) {
+            ErrorPolicy::FailRequest => (ErrorOutcome::FailedRequest, None),
+            ErrorPolicy::Skip => (ErrorOutcome::Skipped, None),
+            ErrorPolicy::Fallback => match fallback() {
+                Some(output) => (ErrorOutcome::FellBack, Some(output)),
+                None => (ErrorOutcome::Skipped, None),
+            },
+        };
+        let error = StageError {
+            stage,
+            component,
+            error: error.in_stage(stage),
+            outcome,
+        };
+        match outcome {
+            ErrorOutcome::FailedRequest => {
+                error!("request_id={} {}", self.request_id, error);
+                return Err(error);
+            },
+            ErrorOutcome::FellBack => info!("request_id={} {}", self.request_id, error),
+            ErrorOutcome::Skipped => {},
+        }
+        self.errors.push(error);
+        Ok(output)
+    }
+
+    pub fn errors(&self) -> &[StageError] {
+        &self.errors
+    }
+
+    /// Append the failures `other` recorded after those recorded here
+    pub fn merge(&mut self, other: StageErrors) {
+        self.errors.extend(other.errors);
+    }
+
+    pub fn into_errors(self) -> Vec<StageError> {
+        self.errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{Pipeline, PipelineBuilder};
+    use crate::candidate_pipeline::{CandidatePipeline, HasRequestId};
+    use crate::filter::{Filter, FilterResult};
+    use crate::selector::Selector;
+    use crate::source::Source;
+    use tonic::async_trait;
+
+    #[derive(Clone)]
+    struct Query;
+
+    impl HasRequestId for Query {
+        fn request_id(&self) -> &str {
+            "test"
+        }
+    }
+
+    struct Numbers;
+
+    #[async_trait]
+    impl Source<Query, i64> for Numbers {
+        async fn get_candidates(&self, _query: &Query) -> Result<Vec<i64>, PipelineError> {
+            Ok((1..=6).collect())
+        }
+    }
+
+    struct BrokenSource;
+
+    #[async_trait]
+    impl Source<Query, i64> for BrokenSource {
+        async fn get_candidates(&self, _query: &Query) -> Result<Vec<i64>, PipelineError> {
+            Err(PipelineError::unavailable("down"))
+        }
+    }
+
+    /// Fails, falling back to keeping candidates up to 3
+    struct BrokenFilter;
+
+    #[async_trait]
+    impl Filter<Query, i64> for BrokenFilter {
+        async fn filter(
+            &self,
+            _query: &Query,
+            _candidates: Vec<i64>,
+        ) -> Result<FilterResult<i64>, PipelineError> {
+            Err(PipelineError::internal("bug"))
+        }
+
+        fn fallback(&self, _query: &Query, candidates: &[i64]) -> Option<FilterResult<i64>> {
+            let (kept, removed) = candidates.iter().partition(|&&c| c <= 3);
+            Some(FilterResult { kept, removed })
+        }
+    }
+
+    struct Largest;
+
+    impl Selector<Query, i64> for Largest {
+        fn score(&self, candidate: &i64) -> f64 {
+            *candidate as f64
+        }
+    }
+
+    fn pipeline(policies: ErrorPolicies) -> Pipeline<Query, i64> {
+        PipelineBuilder::new()
+            .source(Numbers)
+            .source(BrokenSource)
+            .filter(BrokenFilter)
+            .selector(Largest)
+            .result_size(2)
+            .error_policies(policies)
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_failed_components_are_skipped_by_default() {
+        let result = pipeline(ErrorPolicies::new()).execute(Query).await.unwrap();
+        assert_eq!(result.selected_candidates, vec![6, 5]);
+        let errors: Vec<_> = result
+            .errors
+            .iter()
+            .map(|e| (e.stage, e.component, e.outcome))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (PipelineStage::Source, "BrokenSource", ErrorOutcome::Skipped),
+                (PipelineStage::Filter, "BrokenFilter", ErrorOutcome::Skipped),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_policies_fall_back_or_fail_the_request() {
+        let policies = ErrorPolicies::new()
+            .with_source(ErrorPolicy::Fallback)
+            .with_filter(ErrorPolicy::Fallback);
+        let result = pipeline(policies).execute(Query).await.unwrap();
+        assert_eq!(result.selected_candidates, vec![3, 2]);
+        assert_eq!(result.filtered_candidates, vec![4, 5, 6]);
+        // Without a fallback, the source is skipped
+        let outcomes: Vec<_> = result.errors.iter().map(|e| e.outcome).collect();
+        assert_eq!(outcomes, vec![ErrorOutcome::Skipped, ErrorOutcome::FellBack]);
+
+        let policies = ErrorPolicies::new().with_source(ErrorPolicy::FailRequest);
+        let error = pipeline(policies).execute(Query).await.err().unwrap();
+        assert_eq!(error.component, "BrokenSource");
+        assert_eq!(error.error.stage, Some(PipelineStage::Source));
+        assert_eq!(
+            error.to_string(),
+            "stage=Source component=BrokenSource failed_request: unavailable: down"
+        );
+        let status = Status::from(error);
+        assert_eq!(status.code(), tonic::Code::Unavailable);
+        assert_eq!(crate::error::error_kind(&status), Some("unavailable"));
+    }
+}
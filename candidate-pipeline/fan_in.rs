@@ -0,0 +1,220 @@
+//! Source Fan-in
+//!
+//! How long a request waits for its sources. Without a policy every source is
+//! awaited, optional ones up to the source stage's deadline. With a
+//! `FanInPolicy`, once its soft deadline has passed and enough sources have
+//! returned, optional sources still running are abandoned and the request
+//! proceeds with what it has, so one overloaded source costs a partial
+//! response instead of a slow one. Abandoned sources count as timed out: their
+//! error policy applies, and the response is marked partial.
+
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::stream::{FuturesUnordered, StreamExt};
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+use crate::error::PipelineError;
+use crate::source::Source;
+use crate::stage_timeout::until_deadline;
+
+/// When a request stops waiting for its slowest sources
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FanInPolicy {
+    /// How long to wait for every source, from the start of the stage
+    pub soft_deadline: Duration,
+    /// Sources that must have returned candidates before the rest are abandoned
+    pub min_sources: usize,
+}
+
+impl FanInPolicy {
+    pub fn new(soft_deadline: Duration) -> Self {
+        Self {
+            soft_deadline,
+            min_sources: 1,
+        }
+    }
+
+    pub fn with_min_sources(mut self, min_sources: usize) -> Self {
+        self.min_sources = min_sources;
+        self
+    }
+}
+
+type Fetched<C> = Option<Result<Vec<C>, PipelineError>>;
+
+/// The sources of one request being fetched in parallel
+pub struct FanIn<'a, C> {
+    pending: FuturesUnordered<BoxFuture<'a, (usize, Fetched<C>)>>,
+    /// When to abandon optional sources, once `min_sources` have returned
+    soft_deadline: Option<Instant>,
+    min_sources: usize,
+    returned: usize,
+    abandon: watch::Sender<bool>,
+}
+
+impl<'a, C: Send + 'a> FanIn<'a, C> {
+    /// Start fetching from `sources`, optional ones until `deadline` at most
+    pub fn start<Q>(
+        query: &'a Q,
+        sources: &[&'a dyn Source<Q, C>],
+        deadline: Option<Instant>,
+        policy: Option<FanInPolicy>,
+    ) -> Self
+    where
+        Q: Clone + Send + Sync + 'static,
+        C: Clone + Sync + 'static,
+    {
+        let abandon = watch::channel(false).0;
+        let pending = sources
+            .iter()
+            .copied()
+            .enumerate()
+            .map(|(index, source)| {
+                let mut abandoned = abandon.subscribe();
+                let fetch: BoxFuture<'a, (usize, Fetched<C>)> = Box::pin(async move {
+                    if !source.is_optional() {
+                        return (index, Some(source.get_candidates(query).await));
+                    }
+                    let fetched = until_deadline(deadline, source.get_candidates(query));
+                    tokio::select! {
+                        fetched = fetched => (index, fetched),
+                        _ = abandoned.wait_for(|abandoned| *abandoned) => (index, None),
+                    }
+                });
+                fetch
+            })
+            .collect();
+        Self {
+            pending,
+            soft_deadline: policy.map(|p| Instant::now() + p.soft_deadline),
+            min_sources: policy.map_or(0, |p| p.min_sources),
+            returned: 0,
+            abandon,
+        }
+    }
+
+    /// The next source to finish, by its index in `sources`, with what it
+    /// fetched, or None when it failed to return in time
+    pub async fn next(&mut self) -> Option<(usize, Fetched<C>)> {
+        loop {
+            let cutoff = self
+                .soft_deadline
+                .filter(|_| self.returned >= self.min_sources && !*self.abandon.borrow());
+            let soft_deadline = tokio::time::sleep_until(cutoff.unwrap_or_else(Instant::now));
+            tokio::select! {
+                finished = self.pending.next() => {
+                    if let Some((_, Some(Ok(_)))) = &finished {
+                        self.returned += 1;
+                    }
+                    return finished;
+                },
+                _ = soft_deadline, if cutoff.is_some() => {
+                    self.abandon.send_replace(true);
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::PipelineBuilder;
+    use crate::candidate_pipeline::{CandidatePipeline, HasRequestId, PipelineStage};
+    use crate::error::ErrorKind;
+    use crate::metrics::PipelineMetrics;
+    use crate::selector::Selector;
+    use std::sync::Arc;
+    use tonic::async_trait;
+
+    #[derive(Clone)]
+    struct Query;
+
+    impl HasRequestId for Query {
+        fn request_id(&self) -> &str {
+            "test"
+        }
+    }
+
+    struct Fast;
+
+    #[async_trait]
+    impl Source<Query, u64> for Fast {
+        async fn get_candidates(&self, _query: &Query) -> Result<Vec<u64>, PipelineError> {
+            Ok(vec![1, 2])
+        }
+    }
+
+    struct Overloaded;
+
+    #[async_trait]
+    impl Source<Query, u64> for Overloaded {
+        async fn get_candidates(&self, _query: &Query) -> Result<Vec<u64>, PipelineError> {
+            std::future::pending().await
+        }
+    }
+
+    /// Required, so awaited past the soft deadline
+    struct Slow;
+
+    #[async_trait]
+    impl Source<Query, u64> for Slow {
+        async fn get_candidates(&self, _query: &Query) -> Result<Vec<u64>, PipelineError> {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok(vec![3])
+        }
+
+        fn is_optional(&self) -> bool {
+            false
+        }
+    }
+
+    struct Identity;
+
+    impl Selector<Query, u64> for Identity {
+        fn score(&self, candidate: &u64) -> f64 {
+            *candidate as f64
+        }
+    }
+
+    #[tokio::test]
+    async fn test_overloaded_sources_are_abandoned_at_the_soft_deadline() {
+        let metrics = Arc::new(PipelineMetrics::new("test"));
+        let pipeline = PipelineBuilder::new()
+            .source(Fast)
+            .source(Overloaded)
+            .source(Slow)
+            .selector(Identity)
+            .result_size(10)
+            .fan_in(FanInPolicy::new(Duration::from_millis(10)))
+            .metrics(metrics.clone())
+            .build()
+            .unwrap();
+
+        let result = pipeline.execute(Query).await.unwrap();
+        assert!(result.partial);
+        assert_eq!(result.selected_candidates, vec![3, 2, 1]);
+        let error = &result.errors[0];
+        assert_eq!((error.component, error.error.kind), ("Overloaded", ErrorKind::Timeout));
+        assert_eq!(metrics.source_timeouts("Overloaded"), 1);
+        assert_eq!(metrics.errors(PipelineStage::Source, "Overloaded"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_sources_are_awaited_until_enough_have_returned() {
+        let sources: [&dyn Source<Query, u64>; 2] = [&Overloaded, &Slow];
+        let policy = FanInPolicy::new(Duration::from_millis(10)).with_min_sources(1);
+        let mut fan_in = FanIn::start(&Query, &sources, None, Some(policy));
+
+        let started = Instant::now();
+        let (index, fetched) = fan_in.next().await.unwrap();
+        assert_eq!((index, fetched.unwrap().unwrap()), (1, vec![3]));
+        // Only the soft deadline had passed when the slow source returned
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        let (index, fetched) = fan_in.next().await.unwrap();
+        assert_eq!((index, fetched.is_none()), (0, true));
+        assert!(fan_in.next().await.is_none());
+    }
+}
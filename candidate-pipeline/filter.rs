@@ -1,6 +1,7 @@
 use std::any::Any;
 use tonic::async_trait;
 
+use crate::error::PipelineError;
 use crate::util;
 
 pub struct FilterResult<C> {
@@ -23,7 +24,34 @@ where
     /// Filter candidates by evaluating each against some criteria.
     /// Returns a `FilterResult` containing kept candidates (which continue to the next stage)
     /// and removed candidates (which are excluded from further processing).
-    async fn filter(&self, query: &Q, candidates: Vec<C>) -> Result<FilterResult<C>, String>;
+    async fn filter(&self, query: &Q, candidates: Vec<C>) -> Result<FilterResult<C>, PipelineError>;
+
+    /// Filters that return the same group and are declared next to each other may be
+    /// reordered by the pipeline based on observed cost and selectivity.
+    /// `None` pins the filter to its declared position.
+    fn reorder_group(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Whether the pipeline may skip this filter, keeping every candidate, once
+    /// the stage's deadline passes. Filters that enforce visibility or safety
+    /// should be required, which awaits them regardless.
+    fn is_optional(&self) -> bool {
+        true
+    }
+
+    /// The partition to use when `filter` fails and the stage's error policy is
+    /// `Fallback`. None skips the filter, keeping every candidate, so filters
+    /// that enforce visibility or safety should remove them all instead.
+    fn fallback(&self, _query: &Q, _candidates: &[C]) -> Option<FilterResult<C>> {
+        None
+    }
+
+    /// Whether a kill switch may turn this filter off. Filters that enforce a
+    /// legal requirement shouldn't be switched off for every request at once.
+    fn is_killable(&self) -> bool {
+        true
+    }
 
     /// Returns a stable name for logging/metrics.
     fn name(&self) -> &'static str {
@@ -0,0 +1,163 @@
+//! Runtime filter ordering
+//!
+//! Tracks the observed cost and selectivity of every filter and reorders filters
+//! that declare the same `reorder_group` so that cheap, highly selective filters
+//! run first and expensive ones only see the survivors.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Weight given to the newest observation in the moving averages
+const DEFAULT_SMOOTHING: f64 = 0.1;
+
+/// Observed behaviour of a single filter
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FilterStats {
+    /// Moving average of the wall time spent per input candidate
+    pub cost_per_candidate_ns: f64,
+    /// Moving average of the fraction of input candidates that were kept
+    pub pass_rate: f64,
+    pub samples: u64,
+}
+
+impl FilterStats {
+    /// Expected cost per removed candidate. Lower ranks should run first.
+    pub fn rank(&self) -> f64 {
+        self.cost_per_candidate_ns / (1.0 - self.pass_rate).max(1e-6)
+    }
+}
+
+pub struct FilterOrderOptimizer {
+    stats: Mutex<HashMap<&'static str, FilterStats>>,
+    smoothing: f64,
+}
+
+impl Default for FilterOrderOptimizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FilterOrderOptimizer {
+    pub fn new() -> Self {
+        Self::with_smoothing(DEFAULT_SMOOTHING)
+    }
+
+    pub fn with_smoothing(smoothing: f64) -> Self {
+        Self {
+            stats: Mutex::new(HashMap::new()),
+            smoothing: smoothing.clamp(0.0, 1.0),
+        }
+    }
+
+    /// Record one execution of `filter`.
+    pub fn record(&self, filter: &'static str, input: usize, kept: usize, elapsed: Duration) {
+        if input == 0 {
+            return;
+        }
+        let cost = elapsed.as_nanos() as f64 / input as f64;
+        let pass_rate = kept.min(input) as f64 / input as f64;
+
+        let mut stats = self.stats.lock().unwrap();
+        let entry = stats.entry(filter).or_default();
+        if entry.samples == 0 {
+            entry.cost_per_candidate_ns = cost;
+            entry.pass_rate = pass_rate;
+        } else {
+            entry.cost_per_candidate_ns += self.smoothing * (cost - entry.cost_per_candidate_ns);
+            entry.pass_rate += self.smoothing * (pass_rate - entry.pass_rate);
+        }
+        entry.samples += 1;
+    }
+
+    pub fn stats(&self, filter: &'static str) -> Option<FilterStats> {
+        self.stats.lock().unwrap().get(filter).copied()
+    }
+
+    /// Returns the execution order (indices into the input) for filters given as
+    /// `(name, reorder_group)` pairs.
+    ///
+    /// Only consecutive filters sharing the same `Some(group)` are reordered; a filter
+    /// without a group is a barrier that keeps its declared position. Filters without
+    /// stats yet keep their declared order ahead of measured ones so they get sampled.
+    pub fn order(&self, filters: &[(&'static str, Option<&'static str>)]) -> Vec<usize> {
+        let stats = self.stats.lock().unwrap();
+        let rank = |name: &'static str| stats.get(name).map(|s| s.rank()).unwrap_or(f64::MIN);
+
+        let mut order: Vec<usize> = (0..filters.len()).collect();
+        let mut start = 0;
+        while start < filters.len() {
+            let group = filters[start].1;
+            let mut end = start + 1;
+            if group.is_some() {
+                while end < filters.len() && filters[end].1 == group {
+                    end += 1;
+                }
+                order[start..end].sort_by(|&a, &b| {
+                    rank(filters[a].0)
+                        .partial_cmp(&rank(filters[b].0))
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+            }
+            start = end;
+        }
+        order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cheap_selective_filters_run_first() {
+        let optimizer = FilterOrderOptimizer::new();
+        // expensive, barely selective
+        optimizer.record("Slow", 100, 95, Duration::from_micros(500));
+        // cheap, very selective
+        optimizer.record("Fast", 100, 10, Duration::from_micros(10));
+
+        let filters = [("Slow", Some("safety")), ("Fast", Some("safety"))];
+        assert_eq!(optimizer.order(&filters), vec![1, 0]);
+    }
+
+    #[test]
+    fn test_barriers_and_groups_are_respected() {
+        let optimizer = FilterOrderOptimizer::new();
+        optimizer.record("A", 100, 99, Duration::from_millis(5));
+        optimizer.record("B", 100, 1, Duration::from_micros(1));
+        optimizer.record("D", 100, 1, Duration::from_micros(1));
+
+        let filters = [
+            ("A", Some("g1")),
+            ("B", Some("g1")),
+            ("C", None),
+            ("D", Some("g2")),
+            ("E", Some("g1")),
+        ];
+        // B moves ahead of A; C is fixed; D and E are in different groups
+        assert_eq!(optimizer.order(&filters), vec![1, 0, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_unmeasured_filters_are_sampled_first() {
+        let optimizer = FilterOrderOptimizer::new();
+        optimizer.record("Known", 100, 10, Duration::from_micros(1));
+
+        let filters = [("Known", Some("g")), ("New", Some("g"))];
+        assert_eq!(optimizer.order(&filters), vec![1, 0]);
+    }
+
+    #[test]
+    fn test_moving_average() {
+        let optimizer = FilterOrderOptimizer::with_smoothing(0.5);
+        optimizer.record("F", 10, 10, Duration::from_nanos(100));
+        optimizer.record("F", 10, 0, Duration::from_nanos(300));
+
+        let stats = optimizer.stats("F").unwrap();
+        assert_eq!(stats.samples, 2);
+        assert!((stats.pass_rate - 0.5).abs() < 1e-9);
+        assert!((stats.cost_per_candidate_ns - 20.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,339 @@
+//! Gated Stages
+//!
+//! A gate is a named predicate over the query, e.g. a config flag and a rollout
+//! cohort, wrapped around a component with `Gated`. The gate is evaluated when
+//! the pipeline asks the component whether it is enabled for a request, and
+//! every decision is logged with the request id so experiment analysis can
+//! join requests to the arms they were served.
+
+use std::sync::Arc;
+
+use log::info;
+use tonic::async_trait;
+
+use crate::candidate_pipeline::HasRequestId;
+use crate::error::PipelineError;
+use crate::filter::{Filter, FilterResult};
+use crate::hydrator::Hydrator;
+use crate::query_hydrator::QueryHydrator;
+use crate::scorer::Scorer;
+use crate::side_effect::{SideEffect, SideEffectInput};
+use crate::source::Source;
+
+/// A named predicate deciding per request whether a gated component runs
+pub struct Gate<Q> {
+    name: &'static str,
+    predicate: Arc<dyn Fn(&Q) -> bool + Send + Sync>,
+}
+
+impl<Q> Clone for Gate<Q> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name,
+            predicate: self.predicate.clone(),
+        }
+    }
+}
+
+impl<Q: HasRequestId> Gate<Q> {
+    pub fn new(
+        name: &'static str,
+        predicate: impl Fn(&Q) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name,
+            predicate: Arc::new(predicate),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Whether `component` runs for `query`, logging the decision
+    pub fn open(&self, query: &Q, component: &str) -> bool {
+        let open = (self.predicate)(query);
+        info!(
+            "request_id={} gate={} component={} open={}",
+            query.request_id(),
+            self.name,
+            component,
+            open
+        );
+        open
+    }
+}
+
+/// A component that only runs for requests its gate lets through
+pub struct Gated<T, Q> {
+    gate: Gate<Q>,
+    inner: T,
+}
+
+impl<T, Q> Gated<T, Q> {
+    pub fn new(gate: Gate<Q>, inner: T) -> Self {
+        Self { gate, inner }
+    }
+}
+
+#[async_trait]
+impl<Q, T> QueryHydrator<Q> for Gated<T, Q>
+where
+    Q: HasRequestId + Clone + Send + Sync + 'static,
+    T: QueryHydrator<Q>,
+{
+    fn enable(&self, query: &Q) -> bool {
+        self.gate.open(query, self.inner.name()) && self.inner.enable(query)
+    }
+
+    async fn hydrate(&self, query: &Q) -> Result<Q, PipelineError> {
+        self.inner.hydrate(query).await
+    }
+
+    fn update(&self, query: &mut Q, hydrated: Q) {
+        self.inner.update(query, hydrated)
+    }
+
+    fn fallback(&self, query: &Q) -> Option<Q> {
+        self.inner.fallback(query)
+    }
+
+    fn dependencies(&self) -> &[&'static str] {
+        self.inner.dependencies()
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+}
+
+#[async_trait]
+impl<Q, C, T> Source<Q, C> for Gated<T, Q>
+where
+    Q: HasRequestId + Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+    T: Source<Q, C>,
+{
+    fn enable(&self, query: &Q) -> bool {
+        self.gate.open(query, self.inner.name()) && self.inner.enable(query)
+    }
+
+    async fn get_candidates(&self, query: &Q) -> Result<Vec<C>, PipelineError> {
+        self.inner.get_candidates(query).await
+    }
+
+    fn is_optional(&self) -> bool {
+        self.inner.is_optional()
+    }
+
+    fn fallback(&self, query: &Q) -> Option<Vec<C>> {
+        self.inner.fallback(query)
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+}
+
+#[async_trait]
+impl<Q, C, T> Hydrator<Q, C> for Gated<T, Q>
+where
+    Q: HasRequestId + Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+    T: Hydrator<Q, C>,
+{
+    fn enable(&self, query: &Q) -> bool {
+        self.gate.open(query, self.inner.name()) && self.inner.enable(query)
+    }
+
+    async fn hydrate(&self, query: &Q, candidates: &[C]) -> Result<Vec<C>, PipelineError> {
+        self.inner.hydrate(query, candidates).await
+    }
+
+    fn update(&self, candidate: &mut C, hydrated: C) {
+        self.inner.update(candidate, hydrated)
+    }
+
+    fn update_all(&self, candidates: &mut [C], hydrated: Vec<C>) {
+        self.inner.update_all(candidates, hydrated)
+    }
+
+    fn is_optional(&self) -> bool {
+        self.inner.is_optional()
+    }
+
+    fn fallback(&self, query: &Q, candidates: &[C]) -> Option<Vec<C>> {
+        self.inner.fallback(query, candidates)
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+}
+
+#[async_trait]
+impl<Q, C, T> Filter<Q, C> for Gated<T, Q>
+where
+    Q: HasRequestId + Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+    T: Filter<Q, C>,
+{
+    fn enable(&self, query: &Q) -> bool {
+        self.gate.open(query, self.inner.name()) && self.inner.enable(query)
+    }
+
+    async fn filter(
+        &self,
+        query: &Q,
+        candidates: Vec<C>,
+    ) -> Result<FilterResult<C>, PipelineError> {
+        self.inner.filter(query, candidates).await
+    }
+
+    fn reorder_group(&self) -> Option<&'static str> {
+        self.inner.reorder_group()
+    }
+
+    fn is_optional(&self) -> bool {
+        self.inner.is_optional()
+    }
+
+    fn fallback(&self, query: &Q, candidates: &[C]) -> Option<FilterResult<C>> {
+        self.inner.fallback(query, candidates)
+    }
+
+    fn is_killable(&self) -> bool {
+        self.inner.is_killable()
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+}
+
+#[async_trait]
+impl<Q, C, T> Scorer<Q, C> for Gated<T, Q>
+where
+    Q: HasRequestId + Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+    T: Scorer<Q, C>,
+{
+    fn enable(&self, query: &Q) -> bool {
+        self.gate.open(query, self.inner.name()) && self.inner.enable(query)
+    }
+
+    async fn score(&self, query: &Q, candidates: &[C]) -> Result<Vec<C>, PipelineError> {
+        self.inner.score(query, candidates).await
+    }
+
+    fn update(&self, candidate: &mut C, scored: C) {
+        self.inner.update(candidate, scored)
+    }
+
+    fn update_all(&self, candidates: &mut [C], scored: Vec<C>) {
+        self.inner.update_all(candidates, scored)
+    }
+
+    fn is_optional(&self) -> bool {
+        self.inner.is_optional()
+    }
+
+    fn fallback(&self, query: &Q, candidates: &[C]) -> Option<Vec<C>> {
+        self.inner.fallback(query, candidates)
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+}
+
+#[async_trait]
+impl<Q, C, T> SideEffect<Q, C> for Gated<T, Q>
+where
+    Q: HasRequestId + Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+    T: SideEffect<Q, C>,
+{
+    fn enable(&self, query: Arc<Q>) -> bool {
+        self.gate.open(&query, self.inner.name()) && self.inner.enable(query)
+    }
+
+    async fn run(&self, input: Arc<SideEffectInput<Q, C>>) -> Result<(), PipelineError> {
+        self.inner.run(input).await
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::PipelineBuilder;
+    use crate::candidate_pipeline::CandidatePipeline;
+    use crate::selector::Selector;
+
+    #[derive(Clone)]
+    struct Query {
+        user_id: u64,
+    }
+
+    impl HasRequestId for Query {
+        fn request_id(&self) -> &str {
+            "test"
+        }
+    }
+
+    struct Numbers;
+
+    #[async_trait]
+    impl Source<Query, i64> for Numbers {
+        async fn get_candidates(&self, _query: &Query) -> Result<Vec<i64>, PipelineError> {
+            Ok((1..=4).collect())
+        }
+    }
+
+    struct Negate;
+
+    #[async_trait]
+    impl Scorer<Query, i64> for Negate {
+        async fn score(
+            &self,
+            _query: &Query,
+            candidates: &[i64],
+        ) -> Result<Vec<i64>, PipelineError> {
+            Ok(candidates.iter().map(|c| -c).collect())
+        }
+
+        fn update(&self, candidate: &mut i64, scored: i64) {
+            *candidate = scored;
+        }
+    }
+
+    struct Identity;
+
+    impl Selector<Query, i64> for Identity {
+        fn score(&self, candidate: &i64) -> f64 {
+            *candidate as f64
+        }
+    }
+
+    #[tokio::test]
+    async fn test_gated_scorer_runs_only_for_requests_through_the_gate() {
+        let even_users = Gate::new("even_users", |q: &Query| q.user_id.is_multiple_of(2));
+        assert_eq!(even_users.name(), "even_users");
+        let pipeline = PipelineBuilder::new()
+            .source(Numbers)
+            .scorer(Gated::new(even_users, Negate))
+            .selector(Identity)
+            .result_size(1)
+            .build()
+            .unwrap();
+
+        let result = pipeline.execute(Query { user_id: 2 }).await.unwrap();
+        assert_eq!(result.selected_candidates, vec![-1]);
+        let result = pipeline.execute(Query { user_id: 3 }).await.unwrap();
+        assert_eq!(result.selected_candidates, vec![4]);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::error::PipelineError;
 use crate::util;
 use std::any::Any;
 use tonic::async_trait;
@@ -19,12 +20,25 @@ where
     ///
     /// IMPORTANT: The returned vector must have the same candidates in the same order as the input.
     /// Dropping candidates in a hydrator is not allowed - use a filter stage instead.
-    async fn hydrate(&self, query: &Q, candidates: &[C]) -> Result<Vec<C>, String>;
+    async fn hydrate(&self, query: &Q, candidates: &[C]) -> Result<Vec<C>, PipelineError>;
 
     /// Update a single candidate with the hydrated fields.
     /// Only the fields this hydrator is responsible for should be copied.
     fn update(&self, candidate: &mut C, hydrated: C);
 
+    /// Whether the pipeline may skip this hydrator once the stage's deadline
+    /// passes; required hydrators are awaited regardless
+    fn is_optional(&self) -> bool {
+        true
+    }
+
+    /// Hydrated candidates to use when `hydrate` fails and the stage's error
+    /// policy is `Fallback`, in the same order as `candidates`; None skips the
+    /// hydrator
+    fn fallback(&self, _query: &Q, _candidates: &[C]) -> Option<Vec<C>> {
+        None
+    }
+
     /// Update all candidates with the hydrated fields from `hydrated`.
     /// Default implementation iterates and calls `update` for each pair.
     fn update_all(&self, candidates: &mut [C], hydrated: Vec<C>) {
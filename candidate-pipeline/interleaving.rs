@@ -0,0 +1,236 @@
+//! Team-Draft Interleaving
+//!
+//! Compares two rankings online by serving one feed drawn from both. Each
+//! round a coin decides which arm picks first, then each arm adds its best
+//! candidate not already in the feed, as captains drafting teams would. Every
+//! served candidate is credited to the arm that picked it, so engagement on
+//! the feed says which ranking users prefer, with far less traffic than an
+//! A/B split needs. Coins are seeded from the request id, making a request's
+//! draft reproducible offline.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use log::{info, warn};
+
+use crate::candidate_pipeline::{CandidatePipeline, HasRequestId, PipelineResult};
+use crate::error_policy::StageError;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Arm {
+    Control,
+    Treatment,
+}
+
+impl Arm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Arm::Control => "control",
+            Arm::Treatment => "treatment",
+        }
+    }
+}
+
+/// A served candidate and the arm that picked it
+#[derive(Clone, Debug)]
+pub struct InterleavedItem<C> {
+    pub arm: Arm,
+    pub candidate: C,
+}
+
+pub struct InterleavedFeed<C> {
+    pub items: Vec<InterleavedItem<C>>,
+    /// Errors of both pipelines; an arm whose pipeline failed picks nothing
+    pub errors: Vec<StageError>,
+}
+
+impl<C> InterleavedFeed<C> {
+    /// The arm whose picks were engaged with more, or None on a tie
+    pub fn preferred(&self, engaged: impl Fn(&C) -> bool) -> Option<Arm> {
+        let credit = |arm| {
+            self.items
+                .iter()
+                .filter(|item| item.arm == arm && engaged(&item.candidate))
+                .count()
+        };
+        let (control, treatment) = (credit(Arm::Control), credit(Arm::Treatment));
+        match control.cmp(&treatment) {
+            std::cmp::Ordering::Greater => Some(Arm::Control),
+            std::cmp::Ordering::Less => Some(Arm::Treatment),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+}
+
+/// Merges `control` and `treatment`, both best first, into up to `size`
+/// candidates by team draft, drawing coins from `seed`
+pub fn team_draft<C>(
+    control: Vec<C>,
+    treatment: Vec<C>,
+    size: usize,
+    id: impl Fn(&C) -> u64,
+    seed: u64,
+) -> Vec<InterleavedItem<C>> {
+    let mut coins = seed | 1;
+    let mut flip = move || {
+        // xorshift64
+        coins ^= coins << 13;
+        coins ^= coins >> 7;
+        coins ^= coins << 17;
+        coins & 1 == 1
+    };
+
+    let mut rankings = [control.into_iter(), treatment.into_iter()];
+    let mut picks = [0usize; 2];
+    let mut served = HashSet::new();
+    let mut items = Vec::with_capacity(size);
+    let mut exhausted = [false; 2];
+    while items.len() < size && !(exhausted[0] && exhausted[1]) {
+        // The arm behind picks next; a coin breaks ties
+        let first = match picks[0].cmp(&picks[1]) {
+            std::cmp::Ordering::Less => 0,
+            std::cmp::Ordering::Greater => 1,
+            std::cmp::Ordering::Equal => usize::from(flip()),
+        };
+        let arm = if exhausted[first] { 1 - first } else { first };
+        let pick = rankings[arm].by_ref().find(|c| served.insert(id(c)));
+        let Some(candidate) = pick else {
+            exhausted[arm] = true;
+            continue;
+        };
+        picks[arm] += 1;
+        items.push(InterleavedItem {
+            arm: if arm == 0 { Arm::Control } else { Arm::Treatment },
+            candidate,
+        });
+    }
+    items
+}
+
+/// Serves feeds interleaving a control and a treatment pipeline
+pub struct TeamDraftInterleaver<Q, C> {
+    control: Arc<dyn CandidatePipeline<Q, C>>,
+    treatment: Arc<dyn CandidatePipeline<Q, C>>,
+    size: usize,
+    candidate_id: fn(&C) -> u64,
+}
+
+impl<Q, C> TeamDraftInterleaver<Q, C>
+where
+    Q: HasRequestId + Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+{
+    /// Candidates are told apart by `candidate_id`, so that one ranked by both
+    /// arms is served once
+    pub fn new(
+        control: Arc<dyn CandidatePipeline<Q, C>>,
+        treatment: Arc<dyn CandidatePipeline<Q, C>>,
+        size: usize,
+        candidate_id: fn(&C) -> u64,
+    ) -> Self {
+        Self {
+            control,
+            treatment,
+            size,
+            candidate_id,
+        }
+    }
+
+    pub async fn interleave(&self, query: Q) -> InterleavedFeed<C> {
+        let request_id = query.request_id().to_string();
+        let (control, treatment) = futures::join!(
+            self.control.execute(query.clone()),
+            self.treatment.execute(query)
+        );
+
+        let mut errors = Vec::new();
+        let control = ranked(&request_id, Arm::Control, control, &mut errors);
+        let treatment = ranked(&request_id, Arm::Treatment, treatment, &mut errors);
+
+        let mut hasher = DefaultHasher::new();
+        request_id.hash(&mut hasher);
+        let items = team_draft(control, treatment, self.size, self.candidate_id, hasher.finish());
+        let treated = items.iter().filter(|item| item.arm == Arm::Treatment).count();
+        info!(
+            "request_id={} interleaved control={} treatment={}",
+            request_id,
+            items.len() - treated,
+            treated
+        );
+        InterleavedFeed { items, errors }
+    }
+}
+
+/// The candidates an arm ranked, or none when its pipeline failed
+fn ranked<Q, C>(
+    request_id: &str,
+    arm: Arm,
+    result: Result<PipelineResult<Q, C>, StageError>,
+    errors: &mut Vec<StageError>,
+) -> Vec<C> {
+    match result {
+        Ok(result) => {
+            errors.extend(result.errors);
+            result.selected_candidates
+        },
+        Err(e) => {
+            warn!("request_id={} arm={} failed: {}", request_id, arm.as_str(), e);
+            errors.push(e);
+            Vec::new()
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draft(control: Vec<u64>, treatment: Vec<u64>, size: usize, seed: u64) -> Vec<(Arm, u64)> {
+        team_draft(control, treatment, size, |c| *c, seed)
+            .into_iter()
+            .map(|item| (item.arm, item.candidate))
+            .collect()
+    }
+
+    #[test]
+    fn test_team_draft_alternates_and_skips_shared_picks() {
+        for seed in 0..16 {
+            let feed = draft(vec![1, 2, 3, 4], vec![2, 5, 1, 6], 6, seed);
+            let ids: HashSet<u64> = feed.iter().map(|(_, c)| *c).collect();
+            assert_eq!(ids.len(), 6);
+            // Each round both arms pick once, best unserved candidate first
+            for round in feed.chunks(2) {
+                assert_ne!(round[0].0, round[1].0);
+            }
+            let control: Vec<u64> = feed
+                .iter()
+                .filter(|(arm, _)| *arm == Arm::Control)
+                .map(|(_, c)| *c)
+                .collect();
+            assert!(control.windows(2).all(|w| w[0] < w[1]));
+        }
+
+        // Once an arm runs dry the other fills the feed
+        let feed = draft(vec![1], vec![2, 3, 4], 4, 7);
+        assert_eq!(feed.len(), 4);
+        let treated = feed.iter().filter(|(arm, _)| *arm == Arm::Treatment).count();
+        assert_eq!(treated, 3);
+    }
+
+    #[test]
+    fn test_preferred_arm_follows_engagement() {
+        let items = draft(vec![1, 2], vec![3, 4], 4, 1)
+            .into_iter()
+            .map(|(arm, candidate)| InterleavedItem { arm, candidate })
+            .collect();
+        let feed = InterleavedFeed {
+            items,
+            errors: Vec::new(),
+        };
+        assert_eq!(feed.preferred(|c| *c >= 3), Some(Arm::Treatment));
+        assert_eq!(feed.preferred(|c| *c == 1), Some(Arm::Control));
+        assert_eq!(feed.preferred(|c| *c == 1 || *c == 3), None);
+    }
+}
@@ -0,0 +1,159 @@
+//! Kill Switches
+//!
+//! A registry of components switched off by name, given to pipelines with
+//! `PipelineBuilder::kill_switches`. Pipelines consult it on every request
+//! before asking a query hydrator, source, hydrator, filter, scorer or side
+//! effect whether it is enabled, so a misbehaving component can be turned off
+//! for all requests at once, without a deploy, and back on just as quickly.
+//! Filters that aren't `is_killable` keep running while switched off.
+//! Every flip is counted by component and new state for metrics.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+/// A component switched off
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KillSwitch {
+    pub component: String,
+    /// Why it was switched off, for whoever switches it back on
+    #[serde(default)]
+    pub reason: String,
+    #[serde(default)]
+    pub killed_at_ms: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct KillSwitches {
+    killed: RwLock<BTreeMap<String, KillSwitch>>,
+    /// Flips by component and the state flipped to
+    flips: Mutex<BTreeMap<(String, &'static str), u64>>,
+}
+
+impl KillSwitches {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Switch `component` off; false when it already was, keeping the reason
+    /// it was switched off for
+    pub fn kill(&self, component: &str, reason: &str) -> bool {
+        self.insert(KillSwitch {
+            component: component.to_string(),
+            reason: reason.to_string(),
+            killed_at_ms: now_ms(),
+        })
+    }
+
+    /// Switch `component` back on; false when it wasn't off
+    pub fn restore(&self, component: &str) -> bool {
+        let restored = self.killed.write().unwrap().remove(component).is_some();
+        if restored {
+            self.count_flip(component, "restored");
+        }
+        restored
+    }
+
+    /// Switch off exactly the components of `switches`, e.g. as loaded from a
+    /// file, restoring the rest
+    pub fn replace(&self, switches: Vec<KillSwitch>) {
+        let keep: Vec<&str> = switches.iter().map(|s| s.component.as_str()).collect();
+        for switch in self.switches() {
+            if !keep.contains(&switch.component.as_str()) {
+                self.restore(&switch.component);
+            }
+        }
+        for switch in switches {
+            self.insert(switch);
+        }
+    }
+
+    pub fn is_killed(&self, component: &str) -> bool {
+        self.killed.read().unwrap().contains_key(component)
+    }
+
+    /// The components switched off, by name
+    pub fn switches(&self) -> Vec<KillSwitch> {
+        self.killed.read().unwrap().values().cloned().collect()
+    }
+
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# HELP kill_switch_active Components switched off");
+        let _ = writeln!(out, "# TYPE kill_switch_active gauge");
+        for component in self.killed.read().unwrap().keys() {
+            let _ = writeln!(out, "kill_switch_active{{component=\"{}\"}} 1", component);
+        }
+        let _ = writeln!(out, "# HELP kill_switch_changes_total Kill switch flips by new state");
+        let _ = writeln!(out, "# TYPE kill_switch_changes_total counter");
+        for ((component, state), count) in self.flips.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "kill_switch_changes_total{{component=\"{}\",state=\"{}\"}} {}",
+                component, state, count
+            );
+        }
+        out
+    }
+
+    fn insert(&self, switch: KillSwitch) -> bool {
+        let component = switch.component.clone();
+        let mut killed = self.killed.write().unwrap();
+        if killed.contains_key(&component) {
+            return false;
+        }
+        killed.insert(component.clone(), switch);
+        drop(killed);
+        self.count_flip(&component, "killed");
+        true
+    }
+
+    fn count_flip(&self, component: &str, state: &'static str) {
+        *self.flips.lock().unwrap().entry((component.to_string(), state)).or_default() += 1;
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_switches_flip_and_count_changes() {
+        let switches = KillSwitches::new();
+        assert!(switches.kill("RecencyScorer", "scores spiking"));
+        assert!(!switches.kill("RecencyScorer", "again"), "already off");
+        assert!(switches.is_killed("RecencyScorer"));
+        assert_eq!(switches.switches()[0].reason, "scores spiking");
+
+        assert!(switches.restore("RecencyScorer"));
+        assert!(!switches.restore("RecencyScorer"));
+        assert!(!switches.is_killed("RecencyScorer"));
+
+        switches.kill("GeoFilter", "");
+        switches.replace(vec![KillSwitch {
+            component: "ThunderSource".to_string(),
+            reason: String::new(),
+            killed_at_ms: 0,
+        }]);
+        let killed: Vec<_> = switches.switches().into_iter().map(|s| s.component).collect();
+        assert_eq!(killed, ["ThunderSource"]);
+
+        let text = switches.to_prometheus();
+        assert!(text.contains("kill_switch_active{component=\"ThunderSource\"} 1\n"));
+        assert!(!text.contains("kill_switch_active{component=\"GeoFilter\"}"));
+        let flips = "kill_switch_changes_total{component=\"RecencyScorer\",state=\"restored\"} 1";
+        assert!(text.contains(flips));
+        let flips = "kill_switch_changes_total{component=\"GeoFilter\",state=\"killed\"} 1";
+        assert!(text.contains(flips));
+    }
+}
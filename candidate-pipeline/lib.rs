@@ -1,9 +1,28 @@
+pub mod builder;
 pub mod candidate_pipeline;
+pub mod composition;
+pub mod dedup;
+pub mod dry_run;
+pub mod error;
+pub mod error_policy;
+pub mod fan_in;
 pub mod filter;
+pub mod filter_optimizer;
+pub mod gate;
 pub mod hydrator;
+pub mod interleaving;
+pub mod kill_switch;
+pub mod memo;
+pub mod metrics;
+pub mod provenance;
 pub mod query_hydrator;
+pub mod retry;
 pub mod scorer;
 pub mod selector;
 pub mod side_effect;
+pub mod side_effect_executor;
 pub mod source;
+pub mod stage_timeout;
+pub mod streaming;
+pub mod trace;
 pub mod util;
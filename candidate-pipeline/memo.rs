@@ -0,0 +1,476 @@
+//! Memoized Components
+//!
+//! Sources and hydrators whose output only depends on part of the query, e.g.
+//! the viewer's following list or the ids they have already seen, can be
+//! wrapped to reuse their output for a short time. Each wrapper is given a
+//! fingerprint of the query fields its component reads; requests with the same
+//! fingerprint within the TTL are served from the wrapper's cache, and a query
+//! without one always calls through. Candidate hydrators are memoized per
+//! candidate, so a request only hydrates the candidates that missed.
+//!
+//! Caches are bounded: once full, the oldest entries are evicted first. Hits
+//! and misses are counted per component and exported with `PipelineMetrics`.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tonic::async_trait;
+
+use crate::error::PipelineError;
+use crate::hydrator::Hydrator;
+use crate::metrics::PipelineMetrics;
+use crate::query_hydrator::QueryHydrator;
+use crate::source::Source;
+
+/// A stable fingerprint of `value` for use as a memoization key
+pub fn fingerprint(value: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// How long a memoized component reuses its outputs, and how many it keeps
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemoConfig {
+    /// How long an output may be reused
+    pub ttl: Duration,
+    /// Outputs kept at most; for candidate hydrators, one per candidate
+    pub capacity: usize,
+}
+
+impl Default for MemoConfig {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(30),
+            capacity: 10_000,
+        }
+    }
+}
+
+impl MemoConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+}
+
+/// Lookups in one component's cache since startup
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl MemoStats {
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+struct Entries<K, V> {
+    values: HashMap<K, (Instant, V)>,
+    /// Keys by insertion time, oldest first; stale when the key was stored again
+    order: VecDeque<(K, Instant)>,
+}
+
+/// A bounded cache whose entries expire a fixed time after they were stored
+struct MemoCache<K, V> {
+    config: MemoConfig,
+    entries: Mutex<Entries<K, V>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> MemoCache<K, V> {
+    fn new(config: MemoConfig) -> Self {
+        Self {
+            config,
+            entries: Mutex::new(Entries {
+                values: HashMap::new(),
+                order: VecDeque::new(),
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    fn get(&self, key: &K) -> Option<V> {
+        let entries = self.entries.lock().unwrap();
+        let value = entries
+            .values
+            .get(key)
+            .filter(|(stored, _)| stored.elapsed() < self.config.ttl)
+            .map(|(_, value)| value.clone());
+        let counter = if value.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+
+    fn insert(&self, key: K, value: V) {
+        if self.config.capacity == 0 {
+            return;
+        }
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        let Entries { values, order } = &mut *entries;
+        while let Some((oldest, stored)) = order.front() {
+            let expired = now.duration_since(*stored) >= self.config.ttl;
+            if !expired && values.len() < self.config.capacity {
+                break;
+            }
+            if values.get(oldest).is_some_and(|(at, _)| at == stored) {
+                values.remove(oldest);
+            }
+            order.pop_front();
+        }
+        order.push_back((key.clone(), now));
+        values.insert(key, (now, value));
+    }
+
+    fn stats(&self) -> MemoStats {
+        MemoStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Counts lookups in `metrics` when given, under the component's name
+fn record(metrics: &Option<Arc<PipelineMetrics>>, component: &'static str, hits: u64, misses: u64) {
+    if let Some(metrics) = metrics {
+        metrics.record_memo(component, hits, misses);
+    }
+}
+
+/// A source whose candidates are reused for queries with the same fingerprint
+pub struct MemoizedSource<S, Q, C> {
+    inner: S,
+    key: fn(&Q) -> Option<u64>,
+    cache: MemoCache<u64, Vec<C>>,
+    metrics: Option<Arc<PipelineMetrics>>,
+}
+
+impl<S, Q, C: Clone> MemoizedSource<S, Q, C> {
+    /// Memoize `inner` by `key`, the fingerprint of the query fields it reads
+    pub fn new(inner: S, key: fn(&Q) -> Option<u64>, config: MemoConfig) -> Self {
+        Self {
+            inner,
+            key,
+            cache: MemoCache::new(config),
+            metrics: None,
+        }
+    }
+
+    pub fn with_metrics(mut self, metrics: Arc<PipelineMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn stats(&self) -> MemoStats {
+        self.cache.stats()
+    }
+}
+
+#[async_trait]
+impl<S, Q, C> Source<Q, C> for MemoizedSource<S, Q, C>
+where
+    Q: Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+    S: Source<Q, C>,
+{
+    fn enable(&self, query: &Q) -> bool {
+        self.inner.enable(query)
+    }
+
+    async fn get_candidates(&self, query: &Q) -> Result<Vec<C>, PipelineError> {
+        let Some(key) = (self.key)(query) else {
+            return self.inner.get_candidates(query).await;
+        };
+        if let Some(candidates) = self.cache.get(&key) {
+            record(&self.metrics, self.name(), 1, 0);
+            return Ok(candidates);
+        }
+        record(&self.metrics, self.name(), 0, 1);
+        let candidates = self.inner.get_candidates(query).await?;
+        self.cache.insert(key, candidates.clone());
+        Ok(candidates)
+    }
+
+    fn is_optional(&self) -> bool {
+        self.inner.is_optional()
+    }
+
+    fn fallback(&self, query: &Q) -> Option<Vec<C>> {
+        self.inner.fallback(query)
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+}
+
+/// A query hydrator whose output is reused for queries with the same fingerprint
+pub struct MemoizedQueryHydrator<H, Q> {
+    inner: H,
+    key: fn(&Q) -> Option<u64>,
+    cache: MemoCache<u64, Q>,
+    metrics: Option<Arc<PipelineMetrics>>,
+}
+
+impl<H, Q: Clone> MemoizedQueryHydrator<H, Q> {
+    /// Memoize `inner` by `key`, the fingerprint of the query fields it reads
+    pub fn new(inner: H, key: fn(&Q) -> Option<u64>, config: MemoConfig) -> Self {
+        Self {
+            inner,
+            key,
+            cache: MemoCache::new(config),
+            metrics: None,
+        }
+    }
+
+    pub fn with_metrics(mut self, metrics: Arc<PipelineMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn stats(&self) -> MemoStats {
+        self.cache.stats()
+    }
+}
+
+#[async_trait]
+impl<H, Q> QueryHydrator<Q> for MemoizedQueryHydrator<H, Q>
+where
+    Q: Clone + Send + Sync + 'static,
+    H: QueryHydrator<Q>,
+{
+    fn enable(&self, query: &Q) -> bool {
+        self.inner.enable(query)
+    }
+
+    async fn hydrate(&self, query: &Q) -> Result<Q, PipelineError> {
+        let Some(key) = (self.key)(query) else {
+            return self.inner.hydrate(query).await;
+        };
+        if let Some(hydrated) = self.cache.get(&key) {
+            record(&self.metrics, self.name(), 1, 0);
+            return Ok(hydrated);
+        }
+        record(&self.metrics, self.name(), 0, 1);
+        let hydrated = self.inner.hydrate(query).await?;
+        self.cache.insert(key, hydrated.clone());
+        Ok(hydrated)
+    }
+
+    fn update(&self, query: &mut Q, hydrated: Q) {
+        self.inner.update(query, hydrated)
+    }
+
+    fn fallback(&self, query: &Q) -> Option<Q> {
+        self.inner.fallback(query)
+    }
+
+    fn dependencies(&self) -> &[&'static str] {
+        self.inner.dependencies()
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+}
+
+/// A candidate hydrator whose output for each candidate is reused for queries
+/// with the same fingerprint; only candidates that miss are hydrated
+pub struct MemoizedHydrator<H, Q, C> {
+    inner: H,
+    key: fn(&Q) -> Option<u64>,
+    candidate_id: fn(&C) -> u64,
+    cache: MemoCache<(u64, u64), C>,
+    metrics: Option<Arc<PipelineMetrics>>,
+}
+
+impl<H, Q, C: Clone> MemoizedHydrator<H, Q, C> {
+    /// Memoize `inner` by `key`, the fingerprint of the query fields it reads,
+    /// telling candidates apart by `candidate_id`
+    pub fn new(
+        inner: H,
+        key: fn(&Q) -> Option<u64>,
+        candidate_id: fn(&C) -> u64,
+        config: MemoConfig,
+    ) -> Self {
+        Self {
+            inner,
+            key,
+            candidate_id,
+            cache: MemoCache::new(config),
+            metrics: None,
+        }
+    }
+
+    pub fn with_metrics(mut self, metrics: Arc<PipelineMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn stats(&self) -> MemoStats {
+        self.cache.stats()
+    }
+}
+
+#[async_trait]
+impl<H, Q, C> Hydrator<Q, C> for MemoizedHydrator<H, Q, C>
+where
+    Q: Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+    H: Hydrator<Q, C>,
+{
+    fn enable(&self, query: &Q) -> bool {
+        self.inner.enable(query)
+    }
+
+    async fn hydrate(&self, query: &Q, candidates: &[C]) -> Result<Vec<C>, PipelineError> {
+        let Some(key) = (self.key)(query) else {
+            return self.inner.hydrate(query, candidates).await;
+        };
+        let id = self.candidate_id;
+        let mut hydrated: Vec<Option<C>> = candidates
+            .iter()
+            .map(|c| self.cache.get(&(key, id(c))))
+            .collect();
+        let missed: Vec<C> = candidates
+            .iter()
+            .zip(&hydrated)
+            .filter(|(_, cached)| cached.is_none())
+            .map(|(c, _)| c.clone())
+            .collect();
+        let hits = (candidates.len() - missed.len()) as u64;
+        record(&self.metrics, self.name(), hits, missed.len() as u64);
+        if missed.is_empty() {
+            return Ok(hydrated.into_iter().flatten().collect());
+        }
+
+        let fetched = self.inner.hydrate(query, &missed).await?;
+        if fetched.len() != missed.len() {
+            // Let the pipeline report the mismatch
+            return Ok(fetched);
+        }
+        let mut fetched = fetched.into_iter();
+        for (candidate, slot) in candidates.iter().zip(hydrated.iter_mut()) {
+            if slot.is_none() {
+                let output = fetched.next().expect("one output per missed candidate");
+                self.cache.insert((key, id(candidate)), output.clone());
+                *slot = Some(output);
+            }
+        }
+        Ok(hydrated.into_iter().flatten().collect())
+    }
+
+    fn update(&self, candidate: &mut C, hydrated: C) {
+        self.inner.update(candidate, hydrated)
+    }
+
+    fn update_all(&self, candidates: &mut [C], hydrated: Vec<C>) {
+        self.inner.update_all(candidates, hydrated)
+    }
+
+    fn is_optional(&self) -> bool {
+        self.inner.is_optional()
+    }
+
+    fn fallback(&self, query: &Q, candidates: &[C]) -> Option<Vec<C>> {
+        self.inner.fallback(query, candidates)
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone)]
+    struct Query {
+        user_id: u64,
+    }
+
+    fn by_user(query: &Query) -> Option<u64> {
+        Some(fingerprint(&query.user_id))
+    }
+
+    /// Hydrates candidates with the number of the call that hydrated them
+    #[derive(Default)]
+    struct Calls(AtomicU64);
+
+    #[async_trait]
+    impl Hydrator<Query, (u64, u64)> for Calls {
+        async fn hydrate(
+            &self,
+            _query: &Query,
+            candidates: &[(u64, u64)],
+        ) -> Result<Vec<(u64, u64)>, PipelineError> {
+            let call = self.0.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(candidates.iter().map(|(id, _)| (*id, call)).collect())
+        }
+
+        fn update(&self, candidate: &mut (u64, u64), hydrated: (u64, u64)) {
+            *candidate = hydrated;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hydrator_only_hydrates_candidates_that_missed() {
+        let metrics = Arc::new(PipelineMetrics::new("test"));
+        let id = |candidate: &(u64, u64)| candidate.0;
+        let hydrator = MemoizedHydrator::new(Calls::default(), by_user, id, MemoConfig::new())
+            .with_metrics(metrics.clone());
+        let viewer = Query { user_id: 1 };
+
+        let first = hydrator.hydrate(&viewer, &[(1, 0), (2, 0)]).await.unwrap();
+        assert_eq!(first, vec![(1, 1), (2, 1)]);
+        let second = hydrator.hydrate(&viewer, &[(2, 0), (3, 0)]).await.unwrap();
+        assert_eq!(second, vec![(2, 1), (3, 2)]);
+        // Another viewer's fingerprint misses
+        let other = hydrator.hydrate(&Query { user_id: 2 }, &[(1, 0)]).await.unwrap();
+        assert_eq!(other, vec![(1, 3)]);
+
+        assert_eq!(hydrator.stats(), MemoStats { hits: 1, misses: 4 });
+        assert_eq!(metrics.memo("Calls"), hydrator.stats());
+        let hits = "pipeline_memo_lookups_total{pipeline=\"test\",component=\"Calls\",\
+                    result=\"hit\"} 1";
+        assert!(metrics.to_prometheus().contains(hits));
+    }
+
+    #[test]
+    fn test_cache_expires_and_evicts_oldest_entries() {
+        let cache = MemoCache::new(MemoConfig::new().with_capacity(2));
+        cache.insert(1, "a");
+        cache.insert(2, "b");
+        cache.insert(3, "c");
+        assert_eq!((cache.get(&1), cache.get(&2), cache.get(&3)), (None, Some("b"), Some("c")));
+        assert_eq!(cache.stats().hit_rate(), 2.0 / 3.0);
+
+        let cache = MemoCache::new(MemoConfig::new().with_ttl(Duration::ZERO));
+        cache.insert(1, "a");
+        assert_eq!(cache.get(&1), None);
+    }
+}
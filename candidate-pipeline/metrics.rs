@@ -0,0 +1,416 @@
+//! Pipeline Metrics
+//!
+//! Latency and candidate counts of every stage of a pipeline, and failures of
+//! its components, recorded as requests execute and rendered in the Prometheus
+//! text format. Latencies go into fixed-bucket histograms so that quantiles can
+//! be aggregated across replicas. Failures are counted by component name and
+//! error kind, so a dashboard can tell a flaky source from a slow scorer.
+//! Sources timing out and memoized components' cache hits and misses are
+//! counted here too.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::candidate_pipeline::PipelineStage;
+use crate::error::ErrorKind;
+use crate::error_policy::StageError;
+use crate::memo::MemoStats;
+
+/// Upper bounds of the latency histogram buckets, in milliseconds
+pub const LATENCY_BUCKETS_MS: [f64; 12] =
+    [1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0, 500.0, 1000.0, 2000.0, 5000.0];
+
+/// Stages in execution order, as they are rendered
+const STAGES: [PipelineStage; 7] = [
+    PipelineStage::QueryHydrator,
+    PipelineStage::Source,
+    PipelineStage::Hydrator,
+    PipelineStage::Filter,
+    PipelineStage::Scorer,
+    PipelineStage::PostSelectionHydrator,
+    PipelineStage::PostSelectionFilter,
+];
+
+/// Totals of one stage since startup
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StageStats {
+    /// Requests that completed the stage
+    pub count: u64,
+    pub latency: Duration,
+    pub candidates_in: u64,
+    pub candidates_out: u64,
+}
+
+#[derive(Default)]
+struct StageMetrics {
+    stats: StageStats,
+    /// Observations per bucket of `LATENCY_BUCKETS_MS`, not cumulative
+    buckets: [u64; LATENCY_BUCKETS_MS.len()],
+}
+
+pub struct PipelineMetrics {
+    pipeline: String,
+    stages: Mutex<HashMap<PipelineStage, StageMetrics>>,
+    errors: Mutex<HashMap<(PipelineStage, &'static str, ErrorKind), u64>>,
+    memo: Mutex<HashMap<&'static str, MemoStats>>,
+    source_timeouts: Mutex<HashMap<&'static str, u64>>,
+}
+
+impl PipelineMetrics {
+    /// Metrics labelled `pipeline="{pipeline}"`
+    pub fn new(pipeline: impl Into<String>) -> Self {
+        Self {
+            pipeline: pipeline.into(),
+            stages: Mutex::new(HashMap::new()),
+            errors: Mutex::new(HashMap::new()),
+            memo: Mutex::new(HashMap::new()),
+            source_timeouts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record one run of `stage` turning `input` candidates into `output`
+    pub fn record_stage(
+        &self,
+        stage: PipelineStage,
+        elapsed: Duration,
+        input: usize,
+        output: usize,
+    ) {
+        let elapsed_ms = elapsed.as_secs_f64() * 1000.0;
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|&le| elapsed_ms <= le)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+
+        let mut stages = self.stages.lock().unwrap();
+        let entry = stages.entry(stage).or_default();
+        entry.stats.count += 1;
+        entry.stats.latency += elapsed;
+        entry.stats.candidates_in += input as u64;
+        entry.stats.candidates_out += output as u64;
+        if let Some(count) = entry.buckets.get_mut(bucket) {
+            *count += 1;
+        }
+    }
+
+    /// Count the components that failed during a request
+    pub fn record_errors(&self, errors: &[StageError]) {
+        if errors.is_empty() {
+            return;
+        }
+        let mut counts = self.errors.lock().unwrap();
+        for error in errors {
+            *counts.entry((error.stage, error.component, error.error.kind)).or_default() += 1;
+        }
+    }
+
+    /// Count lookups in the cache of a memoized component
+    pub fn record_memo(&self, component: &'static str, hits: u64, misses: u64) {
+        let mut memo = self.memo.lock().unwrap();
+        let stats = memo.entry(component).or_default();
+        stats.hits += hits;
+        stats.misses += misses;
+    }
+
+    /// Count a source the request went on without, at its deadline or the
+    /// fan-in policy's soft deadline
+    pub fn record_source_timeout(&self, source: &'static str) {
+        *self.source_timeouts.lock().unwrap().entry(source).or_default() += 1;
+    }
+
+    pub fn pipeline(&self) -> &str {
+        &self.pipeline
+    }
+
+    /// Totals of every stage that has run, in execution order
+    pub fn stages(&self) -> Vec<(PipelineStage, StageStats)> {
+        let stages = self.stages.lock().unwrap();
+        STAGES
+            .iter()
+            .filter_map(|stage| stages.get(stage).map(|m| (*stage, m.stats)))
+            .collect()
+    }
+
+    /// Failures by stage, component and kind, in execution order
+    pub fn error_counts(&self) -> Vec<(PipelineStage, &'static str, ErrorKind, u64)> {
+        let mut errors: Vec<_> = self
+            .errors
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(&(stage, component, kind), &count)| (stage, component, kind, count))
+            .collect();
+        errors.sort_by_key(|&(stage, component, kind, _)| {
+            let order = STAGES.iter().position(|s| *s == stage);
+            (order, component, kind.as_str())
+        });
+        errors
+    }
+
+    /// Timeouts by source, sorted by source
+    pub fn source_timeout_counts(&self) -> Vec<(&'static str, u64)> {
+        let mut timeouts: Vec<_> =
+            self.source_timeouts.lock().unwrap().iter().map(|(&s, &n)| (s, n)).collect();
+        timeouts.sort();
+        timeouts
+    }
+
+    pub fn stage(&self, stage: PipelineStage) -> Option<StageStats> {
+        self.stages.lock().unwrap().get(&stage).map(|m| m.stats)
+    }
+
+    /// Failures of `component` in `stage`, of every kind
+    pub fn errors(&self, stage: PipelineStage, component: &str) -> u64 {
+        let counts = self.errors.lock().unwrap();
+        counts
+            .iter()
+            .filter(|((s, c, _), _)| *s == stage && *c == component)
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    pub fn source_timeouts(&self, source: &str) -> u64 {
+        self.source_timeouts.lock().unwrap().get(source).copied().unwrap_or(0)
+    }
+
+    /// Cache lookups of the memoized `component`
+    pub fn memo(&self, component: &str) -> MemoStats {
+        self.memo.lock().unwrap().get(component).copied().unwrap_or_default()
+    }
+
+    /// Cache lookups of every memoized component together
+    pub fn memo_total(&self) -> MemoStats {
+        self.memo.lock().unwrap().values().fold(MemoStats::default(), |total, stats| MemoStats {
+            hits: total.hits + stats.hits,
+            misses: total.misses + stats.misses,
+        })
+    }
+
+    /// Render every metric in the Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let stages: Vec<_> = {
+            let stages = self.stages.lock().unwrap();
+            STAGES
+                .iter()
+                .filter_map(|stage| stages.get(stage).map(|m| (stage.as_str(), m.stats, m.buckets)))
+                .collect()
+        };
+
+        out.push_str("# HELP pipeline_stage_latency_ms Time spent in each pipeline stage\n");
+        out.push_str("# TYPE pipeline_stage_latency_ms histogram\n");
+        for (stage, stats, buckets) in &stages {
+            let labels = format!("pipeline=\"{}\",stage=\"{}\"", self.pipeline, stage);
+            let mut cumulative = 0;
+            for (le, count) in LATENCY_BUCKETS_MS.iter().zip(buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "pipeline_stage_latency_ms_bucket{{{},le=\"{}\"}} {}",
+                    labels, le, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "pipeline_stage_latency_ms_bucket{{{},le=\"+Inf\"}} {}",
+                labels, stats.count
+            );
+            let sum_ms = stats.latency.as_secs_f64() * 1000.0;
+            let _ = writeln!(out, "pipeline_stage_latency_ms_sum{{{}}} {:.3}", labels, sum_ms);
+            let _ = writeln!(out, "pipeline_stage_latency_ms_count{{{}}} {}", labels, stats.count);
+        }
+
+        let candidates_in = stages.iter().map(|(stage, stats, _)| (*stage, stats.candidates_in));
+        self.write_stage_counter(
+            &mut out,
+            "candidates_in",
+            "Candidates entering each pipeline stage",
+            candidates_in,
+        );
+        let candidates_out = stages.iter().map(|(stage, stats, _)| (*stage, stats.candidates_out));
+        self.write_stage_counter(
+            &mut out,
+            "candidates_out",
+            "Candidates leaving each pipeline stage",
+            candidates_out,
+        );
+
+        out.push_str("\n# HELP pipeline_component_errors_total Failed pipeline components\n");
+        out.push_str("# TYPE pipeline_component_errors_total counter\n");
+        for (stage, component, kind, count) in self.error_counts() {
+            let _ = writeln!(
+                out,
+                "pipeline_component_errors_total{{pipeline=\"{}\",stage=\"{}\",component=\"{}\",\
+                 kind=\"{}\"}} {}",
+                self.pipeline,
+                stage.as_str(),
+                component,
+                kind.as_str(),
+                count
+            );
+        }
+
+        out.push_str("\n# HELP pipeline_source_timeouts_total Sources requests went on without\n");
+        out.push_str("# TYPE pipeline_source_timeouts_total counter\n");
+        for (source, count) in self.source_timeout_counts() {
+            let _ = writeln!(
+                out,
+                "pipeline_source_timeouts_total{{pipeline=\"{}\",source=\"{}\"}} {}",
+                self.pipeline, source, count
+            );
+        }
+
+        let mut memo: Vec<_> = self.memo.lock().unwrap().iter().map(|(&c, &s)| (c, s)).collect();
+        memo.sort_by_key(|&(component, _)| component);
+        out.push_str("\n# HELP pipeline_memo_lookups_total Cache lookups of memoized components\n");
+        out.push_str("# TYPE pipeline_memo_lookups_total counter\n");
+        for (component, stats) in memo {
+            for (result, count) in [("hit", stats.hits), ("miss", stats.misses)] {
+                let _ = writeln!(
+                    out,
+                    "pipeline_memo_lookups_total{{pipeline=\"{}\",component=\"{}\",\
+                     result=\"{}\"}} {}",
+                    self.pipeline, component, result, count
+                );
+            }
+        }
+        out
+    }
+
+    fn write_stage_counter<'a>(
+        &self,
+        out: &mut String,
+        name: &str,
+        help: &str,
+        values: impl Iterator<Item = (&'a str, u64)>,
+    ) {
+        let _ = writeln!(out, "\n# HELP pipeline_stage_{}_total {}", name, help);
+        let _ = writeln!(out, "# TYPE pipeline_stage_{}_total counter", name);
+        for (stage, value) in values {
+            let _ = writeln!(
+                out,
+                "pipeline_stage_{}_total{{pipeline=\"{}\",stage=\"{}\"}} {}",
+                name, self.pipeline, stage, value
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::PipelineBuilder;
+    use crate::candidate_pipeline::{CandidatePipeline, HasRequestId};
+    use crate::error::PipelineError;
+    use crate::filter::{Filter, FilterResult};
+    use crate::scorer::Scorer;
+    use crate::selector::Selector;
+    use crate::source::Source;
+    use std::sync::Arc;
+    use tonic::async_trait;
+
+    #[derive(Clone)]
+    struct Query;
+
+    impl HasRequestId for Query {
+        fn request_id(&self) -> &str {
+            "test"
+        }
+    }
+
+    struct Numbers;
+
+    #[async_trait]
+    impl Source<Query, u64> for Numbers {
+        async fn get_candidates(&self, _query: &Query) -> Result<Vec<u64>, PipelineError> {
+            Ok((1..=6).collect())
+        }
+    }
+
+    struct DropOdd;
+
+    #[async_trait]
+    impl Filter<Query, u64> for DropOdd {
+        async fn filter(
+            &self,
+            _query: &Query,
+            candidates: Vec<u64>,
+        ) -> Result<FilterResult<u64>, PipelineError> {
+            let (kept, removed) = candidates.into_iter().partition(|c| c % 2 == 0);
+            Ok(FilterResult { kept, removed })
+        }
+    }
+
+    struct Unreachable;
+
+    #[async_trait]
+    impl Scorer<Query, u64> for Unreachable {
+        async fn score(
+            &self,
+            _query: &Query,
+            _candidates: &[u64],
+        ) -> Result<Vec<u64>, PipelineError> {
+            Err(PipelineError::unavailable("model server"))
+        }
+
+        fn update(&self, _candidate: &mut u64, _scored: u64) {}
+    }
+
+    struct Identity;
+
+    impl Selector<Query, u64> for Identity {
+        fn score(&self, candidate: &u64) -> f64 {
+            *candidate as f64
+        }
+
+        fn size(&self) -> Option<usize> {
+            Some(2)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stages_record_latency_counts_and_errors() {
+        let metrics = Arc::new(PipelineMetrics::new("test"));
+        let pipeline = PipelineBuilder::new()
+            .source(Numbers)
+            .filter(DropOdd)
+            .scorer(Unreachable)
+            .selector(Identity)
+            .result_size(10)
+            .metrics(metrics.clone())
+            .build()
+            .unwrap();
+
+        for _ in 0..2 {
+            pipeline.execute(Query).await.unwrap();
+        }
+
+        let source = metrics.stage(PipelineStage::Source).unwrap();
+        assert_eq!((source.count, source.candidates_in, source.candidates_out), (2, 0, 12));
+        let filter = metrics.stage(PipelineStage::Filter).unwrap();
+        assert_eq!((filter.candidates_in, filter.candidates_out), (12, 6));
+        // The selector passes two candidates a request to post-selection stages
+        let post = metrics.stage(PipelineStage::PostSelectionFilter).unwrap();
+        assert_eq!((post.candidates_in, post.candidates_out), (4, 4));
+        assert_eq!(metrics.errors(PipelineStage::Scorer, "Unreachable"), 2);
+        let stages: Vec<_> = metrics.stages().into_iter().map(|(stage, _)| stage).collect();
+        assert_eq!(stages.first(), Some(&PipelineStage::QueryHydrator));
+        assert_eq!(stages.len(), 7);
+        let error = (PipelineStage::Scorer, "Unreachable", ErrorKind::Unavailable, 2);
+        assert_eq!(metrics.error_counts(), vec![error]);
+
+        let text = metrics.to_prometheus();
+        let count = "pipeline_stage_latency_ms_count{pipeline=\"test\",stage=\"filter\"} 2";
+        assert!(text.contains(count));
+        let inf = "pipeline_stage_latency_ms_bucket{pipeline=\"test\",stage=\"source\",\
+                   le=\"+Inf\"} 2";
+        assert!(text.contains(inf));
+        let out = "pipeline_stage_candidates_out_total{pipeline=\"test\",stage=\"filter\"} 6";
+        assert!(text.contains(out));
+        let errors = "pipeline_component_errors_total{pipeline=\"test\",stage=\"scorer\",\
+                      component=\"Unreachable\",kind=\"unavailable\"} 2";
+        assert!(text.contains(errors));
+    }
+}
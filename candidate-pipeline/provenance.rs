@@ -0,0 +1,277 @@
+//! Candidate provenance
+//!
+//! An audit trail of how each candidate of a debug request got where it did:
+//! the sources that produced it, the hydrators that touched it, its selection
+//! score before and after each scorer, and the verdict of each filter that
+//! evaluated it. Recording costs a map entry per candidate and stage, so it is
+//! only done for requests whose query asks for it and pipelines that can tell
+//! candidates apart.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// How a candidate made its way through the pipeline
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// Sources that produced the candidate, usually one
+    pub sources: Vec<String>,
+    /// Hydrators whose fields were applied, in the order they were
+    pub hydrators: Vec<String>,
+    pub scores: Vec<ScoreStep>,
+    pub filters: Vec<FilterStep>,
+}
+
+/// The candidate's selection score around one scorer
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScoreStep {
+    pub scorer: String,
+    pub before: f64,
+    pub after: f64,
+}
+
+/// One filter's verdict on the candidate
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FilterStep {
+    pub filter: String,
+    pub removed: bool,
+}
+
+/// Provenance of every candidate of one request, by candidate id
+#[derive(Debug, Default)]
+pub struct AuditTrail {
+    enabled: bool,
+    records: HashMap<u64, Provenance>,
+}
+
+impl AuditTrail {
+    /// A trail recording nothing unless `enabled`
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            records: HashMap::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Update the provenance of each of `candidates` that has an id
+    pub fn record_all<C>(
+        &mut self,
+        candidates: &[C],
+        id: impl Fn(&C) -> Option<u64>,
+        update: impl Fn(&mut Provenance),
+    ) {
+        if !self.enabled {
+            return;
+        }
+        for id in candidates.iter().filter_map(id) {
+            update(self.records.entry(id).or_default());
+        }
+    }
+
+    /// Note `filter`'s verdict on the candidates it kept and removed
+    pub fn record_filter<C>(
+        &mut self,
+        filter: &str,
+        kept: &[C],
+        removed: &[C],
+        id: impl Fn(&C) -> Option<u64>,
+    ) {
+        for (candidates, removed) in [(kept, false), (removed, true)] {
+            self.record_all(candidates, &id, |p| {
+                p.filters.push(FilterStep {
+                    filter: filter.to_string(),
+                    removed,
+                })
+            });
+        }
+    }
+
+    /// Selection scores of `candidates`, or none while the trail is off
+    pub fn scores<C>(&self, candidates: &[C], score: impl Fn(&C) -> f64) -> Vec<f64> {
+        if !self.enabled {
+            return Vec::new();
+        }
+        candidates.iter().map(score).collect()
+    }
+
+    /// Note the selection scores of `candidates` before and after `scorer`
+    pub fn record_scores<C>(
+        &mut self,
+        scorer: &str,
+        before: &[f64],
+        candidates: &[C],
+        id: impl Fn(&C) -> Option<u64>,
+        score: impl Fn(&C) -> f64,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        for (candidate, &before) in candidates.iter().zip(before) {
+            if let Some(id) = id(candidate) {
+                self.records.entry(id).or_default().scores.push(ScoreStep {
+                    scorer: scorer.to_string(),
+                    before,
+                    after: score(candidate),
+                });
+            }
+        }
+    }
+
+    /// Append the steps `other` recorded after those recorded here
+    pub fn merge(&mut self, other: AuditTrail) {
+        for (id, later) in other.records {
+            let provenance = self.records.entry(id).or_default();
+            provenance.sources.extend(later.sources);
+            provenance.hydrators.extend(later.hydrators);
+            provenance.scores.extend(later.scores);
+            provenance.filters.extend(later.filters);
+        }
+    }
+
+    pub fn into_records(self) -> HashMap<u64, Provenance> {
+        self.records
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::PipelineBuilder;
+    use crate::candidate_pipeline::{CandidatePipeline, HasRequestId};
+    use crate::error::PipelineError;
+    use crate::filter::{Filter, FilterResult};
+    use crate::hydrator::Hydrator;
+    use crate::scorer::Scorer;
+    use crate::selector::Selector;
+    use crate::source::Source;
+    use tonic::async_trait;
+
+    #[derive(Clone)]
+    struct Query {
+        debug: bool,
+    }
+
+    impl HasRequestId for Query {
+        fn request_id(&self) -> &str {
+            "test"
+        }
+
+        fn debug(&self) -> bool {
+            self.debug
+        }
+    }
+
+    #[derive(Clone)]
+    struct Candidate {
+        id: u64,
+        score: f64,
+    }
+
+    struct Numbers;
+
+    #[async_trait]
+    impl Source<Query, Candidate> for Numbers {
+        async fn get_candidates(&self, _query: &Query) -> Result<Vec<Candidate>, PipelineError> {
+            Ok((1..=4).map(|id| Candidate { id, score: 0.0 }).collect())
+        }
+    }
+
+    struct Touch;
+
+    #[async_trait]
+    impl Hydrator<Query, Candidate> for Touch {
+        async fn hydrate(
+            &self,
+            _query: &Query,
+            candidates: &[Candidate],
+        ) -> Result<Vec<Candidate>, PipelineError> {
+            Ok(candidates.to_vec())
+        }
+
+        fn update(&self, _candidate: &mut Candidate, _hydrated: Candidate) {}
+    }
+
+    struct DropOdd;
+
+    #[async_trait]
+    impl Filter<Query, Candidate> for DropOdd {
+        async fn filter(
+            &self,
+            _query: &Query,
+            candidates: Vec<Candidate>,
+        ) -> Result<FilterResult<Candidate>, PipelineError> {
+            let (removed, kept) = candidates.into_iter().partition(|c| c.id % 2 == 1);
+            Ok(FilterResult { kept, removed })
+        }
+    }
+
+    struct Tenfold;
+
+    #[async_trait]
+    impl Scorer<Query, Candidate> for Tenfold {
+        async fn score(
+            &self,
+            _query: &Query,
+            candidates: &[Candidate],
+        ) -> Result<Vec<Candidate>, PipelineError> {
+            let scored = candidates.iter().map(|c| Candidate {
+                id: c.id,
+                score: c.id as f64 * 10.0,
+            });
+            Ok(scored.collect())
+        }
+
+        fn update(&self, candidate: &mut Candidate, scored: Candidate) {
+            candidate.score = scored.score;
+        }
+    }
+
+    struct ByScore;
+
+    impl Selector<Query, Candidate> for ByScore {
+        fn score(&self, candidate: &Candidate) -> f64 {
+            candidate.score
+        }
+    }
+
+    #[tokio::test]
+    async fn test_debug_requests_record_candidate_provenance() {
+        let pipeline = PipelineBuilder::new()
+            .source(Numbers)
+            .hydrator(Touch)
+            .filter(DropOdd)
+            .scorer(Tenfold)
+            .selector(ByScore)
+            .result_size(10)
+            .candidate_id(|c: &Candidate| c.id)
+            .build()
+            .unwrap();
+
+        let result = pipeline.execute(Query { debug: true }).await.unwrap();
+        let kept = &result.provenance[&2];
+        assert_eq!(kept.sources, vec!["Numbers"]);
+        assert_eq!(kept.hydrators, vec!["Touch"]);
+        let verdict = FilterStep {
+            filter: "DropOdd".to_string(),
+            removed: false,
+        };
+        assert_eq!(kept.filters, vec![verdict]);
+        let step = ScoreStep {
+            scorer: "Tenfold".to_string(),
+            before: 0.0,
+            after: 20.0,
+        };
+        assert_eq!(kept.scores, vec![step]);
+
+        let removed = &result.provenance[&1];
+        assert!(removed.filters[0].removed);
+        assert!(removed.scores.is_empty());
+
+        let result = pipeline.execute(Query { debug: false }).await.unwrap();
+        assert!(result.provenance.is_empty());
+    }
+}
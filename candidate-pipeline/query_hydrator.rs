@@ -1,6 +1,7 @@
 use std::any::Any;
 use tonic::async_trait;
 
+use crate::error::PipelineError;
 use crate::util;
 
 #[async_trait]
@@ -15,13 +16,210 @@ where
 
     /// Hydrate the query by performing async operations.
     /// Returns a new query with this hydrator's fields populated.
-    async fn hydrate(&self, query: &Q) -> Result<Q, String>;
+    async fn hydrate(&self, query: &Q) -> Result<Q, PipelineError>;
 
     /// Update the query with the hydrated fields.
     /// Only the fields this hydrator is responsible for should be copied.
     fn update(&self, query: &mut Q, hydrated: Q);
 
+    /// Hydrated fields to use when `hydrate` fails and the stage's error policy
+    /// is `Fallback`, e.g. defaults; None skips the hydrator
+    fn fallback(&self, _query: &Q) -> Option<Q> {
+        None
+    }
+
+    /// Names of the query hydrators whose fields this one reads; it runs once
+    /// they have all updated the query, or were disabled or skipped
+    fn dependencies(&self) -> &[&'static str] {
+        &[]
+    }
+
     fn name(&self) -> &'static str {
         util::short_type_name(std::any::type_name::<Self>())
     }
 }
+
+/// Group query hydrators, given as `(name, dependencies)` in pipeline order, into
+/// levels that run one after another, each after every hydrator it depends on.
+/// Hydrators within a level are independent and run in parallel.
+pub fn execution_levels(hydrators: &[(&str, &[&str])]) -> Result<Vec<Vec<usize>>, String> {
+    let position = |name: &str| hydrators.iter().position(|(n, _)| *n == name);
+    let mut dependencies = Vec::with_capacity(hydrators.len());
+    for (name, depends_on) in hydrators {
+        let mut indices = Vec::with_capacity(depends_on.len());
+        for dependency in depends_on.iter() {
+            match position(dependency) {
+                Some(index) => indices.push(index),
+                None => {
+                    return Err(format!(
+                        "query hydrator {} depends on unknown query hydrator {}",
+                        name, dependency
+                    ))
+                },
+            }
+        }
+        dependencies.push(indices);
+    }
+
+    let mut level_of: Vec<Option<usize>> = vec![None; hydrators.len()];
+    let mut levels: Vec<Vec<usize>> = Vec::new();
+    while level_of.iter().any(Option::is_none) {
+        let ready: Vec<usize> = (0..hydrators.len())
+            .filter(|&i| level_of[i].is_none())
+            .filter(|&i| dependencies[i].iter().all(|&d| level_of[d].is_some()))
+            .collect();
+        if ready.is_empty() {
+            return Err(cycle(hydrators, &dependencies, &level_of));
+        }
+        for &i in &ready {
+            level_of[i] = Some(levels.len());
+        }
+        levels.push(ready);
+    }
+    Ok(levels)
+}
+
+/// Describe a dependency cycle among the hydrators not yet placed in a level
+fn cycle(
+    hydrators: &[(&str, &[&str])],
+    dependencies: &[Vec<usize>],
+    level_of: &[Option<usize>],
+) -> String {
+    // Every unplaced hydrator depends on another unplaced one, so following
+    // those dependencies must come back to a hydrator already on the path
+    let first = level_of.iter().position(Option::is_none);
+    let mut path = vec![first.expect("a hydrator is unplaced")];
+    loop {
+        let last = *path.last().expect("the path is never empty");
+        let next = dependencies[last]
+            .iter()
+            .copied()
+            .find(|&d| level_of[d].is_none())
+            .expect("an unplaced hydrator has an unplaced dependency");
+        if let Some(start) = path.iter().position(|&i| i == next) {
+            let names: Vec<&str> = path[start..]
+                .iter()
+                .chain(std::iter::once(&next))
+                .map(|&i| hydrators[i].0)
+                .collect();
+            return format!("query hydrators depend on each other: {}", names.join(" -> "));
+        }
+        path.push(next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::PipelineBuilder;
+    use crate::candidate_pipeline::{CandidatePipeline, HasRequestId};
+    use crate::selector::Selector;
+    use crate::source::Source;
+
+    #[derive(Clone, Default)]
+    struct Query {
+        following: Vec<u64>,
+        clusters: usize,
+    }
+
+    impl HasRequestId for Query {
+        fn request_id(&self) -> &str {
+            "test"
+        }
+    }
+
+    struct Following;
+
+    #[async_trait]
+    impl QueryHydrator<Query> for Following {
+        async fn hydrate(&self, _query: &Query) -> Result<Query, PipelineError> {
+            tokio::task::yield_now().await;
+            Ok(Query {
+                following: vec![1, 2, 3],
+                ..Default::default()
+            })
+        }
+
+        fn update(&self, query: &mut Query, hydrated: Query) {
+            query.following = hydrated.following;
+        }
+    }
+
+    struct Clusters;
+
+    #[async_trait]
+    impl QueryHydrator<Query> for Clusters {
+        async fn hydrate(&self, query: &Query) -> Result<Query, PipelineError> {
+            Ok(Query {
+                clusters: query.following.len(),
+                ..Default::default()
+            })
+        }
+
+        fn update(&self, query: &mut Query, hydrated: Query) {
+            query.clusters = hydrated.clusters;
+        }
+
+        fn dependencies(&self) -> &[&'static str] {
+            &["Following"]
+        }
+    }
+
+    struct Empty;
+
+    #[async_trait]
+    impl Source<Query, u64> for Empty {
+        async fn get_candidates(&self, _query: &Query) -> Result<Vec<u64>, PipelineError> {
+            Ok(Vec::new())
+        }
+    }
+
+    struct Identity;
+
+    impl Selector<Query, u64> for Identity {
+        fn score(&self, candidate: &u64) -> f64 {
+            *candidate as f64
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hydrators_see_the_fields_of_their_dependencies() {
+        let pipeline = PipelineBuilder::new()
+            .query_hydrator(Clusters)
+            .query_hydrator(Following)
+            .source(Empty)
+            .selector(Identity)
+            .result_size(10)
+            .build()
+            .unwrap();
+
+        let result = pipeline.execute(Query::default()).await.unwrap();
+        assert_eq!(result.query.clusters, 3);
+    }
+
+    #[test]
+    fn test_independent_hydrators_share_a_level() {
+        let hydrators: [(&str, &[&str]); 4] = [
+            ("Preferences", &["Following"]),
+            ("Following", &[]),
+            ("Clusters", &["Following", "Preferences"]),
+            ("Features", &[]),
+        ];
+        assert_eq!(execution_levels(&hydrators).unwrap(), vec![vec![1, 3], vec![0], vec![2]]);
+    }
+
+    #[test]
+    fn test_cycles_and_unknown_dependencies_are_rejected() {
+        let hydrators: [(&str, &[&str]); 3] =
+            [("Features", &[]), ("Following", &["Clusters"]), ("Clusters", &["Following"])];
+        assert_eq!(
+            execution_levels(&hydrators).unwrap_err(),
+            "query hydrators depend on each other: Following -> Clusters -> Following"
+        );
+        let hydrators: [(&str, &[&str]); 1] = [("Clusters", &["Following"])];
+        assert_eq!(
+            execution_levels(&hydrators).unwrap_err(),
+            "query hydrator Clusters depends on unknown query hydrator Following"
+        );
+    }
+}
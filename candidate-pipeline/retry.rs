@@ -0,0 +1,486 @@
+//! Retries and hedged requests
+//!
+//! Wrappers that make network-backed sources and hydrators resilient. A call
+//! that fails with a retryable error is made again after an exponential backoff
+//! with jitter. Retries draw on a budget that only refills as calls are made,
+//! so a failing backend sees a bounded share of extra load instead of every
+//! call multiplied. A call that runs slower than a percentile of recent calls
+//! can be hedged: a second call is made and whichever finishes first wins.
+//! The stage's deadline still bounds the whole, dropping any attempt in flight.
+
+use std::collections::hash_map::RandomState;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use log::warn;
+use tokio::time::Instant;
+use tonic::async_trait;
+
+use crate::error::PipelineError;
+use crate::hydrator::Hydrator;
+use crate::source::Source;
+
+/// Latencies kept per component to place hedging percentiles
+const LATENCY_WINDOW: usize = 256;
+
+/// How a component's calls are retried and hedged
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Calls made at most per request, the first included
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+    /// Share of each backoff randomized away, in `[0, 1]`
+    pub jitter: f64,
+    /// Retries earned per call made
+    pub budget_ratio: f64,
+    /// Retries banked at most, and available from the start
+    pub budget_reserve: f64,
+    pub hedge: Option<HedgePolicy>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(200),
+            multiplier: 2.0,
+            jitter: 0.2,
+            budget_ratio: 0.1,
+            budget_reserve: 10.0,
+            hedge: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    pub fn with_backoff(mut self, initial: Duration, max: Duration, multiplier: f64) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self.multiplier = multiplier;
+        self
+    }
+
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn with_budget(mut self, ratio: f64, reserve: f64) -> Self {
+        self.budget_ratio = ratio;
+        self.budget_reserve = reserve;
+        self
+    }
+
+    pub fn with_hedge(mut self, hedge: HedgePolicy) -> Self {
+        self.hedge = Some(hedge);
+        self
+    }
+
+    /// Backoff before the retry following `attempt` (1-based), before jitter
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = self.multiplier.powi(attempt.saturating_sub(1) as i32);
+        self.initial_backoff.mul_f64(factor).min(self.max_backoff)
+    }
+}
+
+/// When a slow call gets a second, hedged call
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HedgePolicy {
+    /// Hedge calls still running past this percentile of recent latencies, in `(0, 1]`
+    pub percentile: f64,
+    /// Calls observed before hedging starts
+    pub min_samples: usize,
+}
+
+impl HedgePolicy {
+    pub fn new(percentile: f64) -> Self {
+        Self {
+            percentile,
+            min_samples: 20,
+        }
+    }
+
+    pub fn with_min_samples(mut self, min_samples: usize) -> Self {
+        self.min_samples = min_samples;
+        self
+    }
+}
+
+/// Retry policy of each stage backed by the network; stages without one make
+/// each call once
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RetryPolicies {
+    pub source: Option<RetryPolicy>,
+    /// Covers post-selection hydrators too
+    pub hydrator: Option<RetryPolicy>,
+}
+
+impl RetryPolicies {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_source(mut self, policy: RetryPolicy) -> Self {
+        self.source = Some(policy);
+        self
+    }
+
+    pub fn with_hydrator(mut self, policy: RetryPolicy) -> Self {
+        self.hydrator = Some(policy);
+        self
+    }
+}
+
+/// Retries and hedges the calls of one component, sharing its budget and
+/// latencies across requests
+pub struct Retrier {
+    policy: RetryPolicy,
+    budget: Mutex<f64>,
+    latencies: Mutex<VecDeque<Duration>>,
+}
+
+impl Retrier {
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self {
+            policy,
+            budget: Mutex::new(policy.budget_reserve),
+            latencies: Mutex::new(VecDeque::with_capacity(LATENCY_WINDOW)),
+        }
+    }
+
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    /// Make the call `call` produces, retrying retryable errors while attempts
+    /// and budget last
+    pub async fn call<T, F, Fut>(&self, component: &str, call: F) -> Result<T, PipelineError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, PipelineError>>,
+    {
+        self.deposit();
+        let mut attempt = 1;
+        loop {
+            let error = match self.attempt(&call).await {
+                Ok(output) => return Ok(output),
+                Err(error) => error,
+            };
+            if !error.retryable || attempt >= self.policy.max_attempts || !self.withdraw() {
+                return Err(error);
+            }
+            let backoff = jittered(self.policy.backoff(attempt), self.policy.jitter);
+            warn!(
+                "component={} retrying attempt={} after {:?}: {}",
+                component, attempt, backoff, error
+            );
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+        }
+    }
+
+    /// One attempt, hedged once it runs past the hedging percentile
+    async fn attempt<T, F, Fut>(&self, call: &F) -> Result<T, PipelineError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, PipelineError>>,
+    {
+        let start = Instant::now();
+        let first = call();
+        let result = match self.hedge_delay() {
+            None => first.await,
+            Some(delay) => {
+                tokio::pin!(first);
+                tokio::select! {
+                    result = &mut first => result,
+                    _ = tokio::time::sleep(delay) => {
+                        if self.withdraw() {
+                            let hedged = call();
+                            tokio::pin!(hedged);
+                            tokio::select! {
+                                result = &mut first => result,
+                                result = &mut hedged => result,
+                            }
+                        } else {
+                            first.await
+                        }
+                    },
+                }
+            },
+        };
+        self.record(start.elapsed());
+        result
+    }
+
+    fn hedge_delay(&self) -> Option<Duration> {
+        let hedge = self.policy.hedge?;
+        let latencies = self.latencies.lock().unwrap();
+        if latencies.len() < hedge.min_samples.max(1) {
+            return None;
+        }
+        let mut sorted: Vec<Duration> = latencies.iter().copied().collect();
+        sorted.sort_unstable();
+        let rank = (hedge.percentile.clamp(0.0, 1.0) * sorted.len() as f64).ceil() as usize;
+        Some(sorted[rank.clamp(1, sorted.len()) - 1])
+    }
+
+    fn record(&self, latency: Duration) {
+        let mut latencies = self.latencies.lock().unwrap();
+        if latencies.len() == LATENCY_WINDOW {
+            latencies.pop_front();
+        }
+        latencies.push_back(latency);
+    }
+
+    fn deposit(&self) {
+        let mut budget = self.budget.lock().unwrap();
+        *budget = (*budget + self.policy.budget_ratio).min(self.policy.budget_reserve);
+    }
+
+    /// Take one retry or hedge from the budget, if it has one
+    fn withdraw(&self) -> bool {
+        let mut budget = self.budget.lock().unwrap();
+        if *budget < 1.0 {
+            return false;
+        }
+        *budget -= 1.0;
+        true
+    }
+}
+
+/// `backoff` less a random share of up to `jitter` of it
+fn jittered(backoff: Duration, jitter: f64) -> Duration {
+    let random = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+    backoff.mul_f64(1.0 - jitter.clamp(0.0, 1.0) * random)
+}
+
+/// A source whose calls are retried and hedged
+pub struct RetryingSource<S> {
+    inner: S,
+    retrier: Retrier,
+}
+
+impl<S> RetryingSource<S> {
+    pub fn new(inner: S, policy: RetryPolicy) -> Self {
+        Self {
+            inner,
+            retrier: Retrier::new(policy),
+        }
+    }
+}
+
+#[async_trait]
+impl<Q, C, S> Source<Q, C> for RetryingSource<S>
+where
+    Q: Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+    S: Source<Q, C>,
+{
+    fn enable(&self, query: &Q) -> bool {
+        self.inner.enable(query)
+    }
+
+    async fn get_candidates(&self, query: &Q) -> Result<Vec<C>, PipelineError> {
+        let call = || self.inner.get_candidates(query);
+        self.retrier.call(self.inner.name(), call).await
+    }
+
+    fn is_optional(&self) -> bool {
+        self.inner.is_optional()
+    }
+
+    fn fallback(&self, query: &Q) -> Option<Vec<C>> {
+        self.inner.fallback(query)
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+}
+
+/// A hydrator whose calls are retried and hedged
+pub struct RetryingHydrator<H> {
+    inner: H,
+    retrier: Retrier,
+}
+
+impl<H> RetryingHydrator<H> {
+    pub fn new(inner: H, policy: RetryPolicy) -> Self {
+        Self {
+            inner,
+            retrier: Retrier::new(policy),
+        }
+    }
+}
+
+#[async_trait]
+impl<Q, C, H> Hydrator<Q, C> for RetryingHydrator<H>
+where
+    Q: Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+    H: Hydrator<Q, C>,
+{
+    fn enable(&self, query: &Q) -> bool {
+        self.inner.enable(query)
+    }
+
+    async fn hydrate(&self, query: &Q, candidates: &[C]) -> Result<Vec<C>, PipelineError> {
+        let call = || self.inner.hydrate(query, candidates);
+        self.retrier.call(self.inner.name(), call).await
+    }
+
+    fn update(&self, candidate: &mut C, hydrated: C) {
+        self.inner.update(candidate, hydrated)
+    }
+
+    fn update_all(&self, candidates: &mut [C], hydrated: Vec<C>) {
+        self.inner.update_all(candidates, hydrated)
+    }
+
+    fn is_optional(&self) -> bool {
+        self.inner.is_optional()
+    }
+
+    fn fallback(&self, query: &Q, candidates: &[C]) -> Option<Vec<C>> {
+        self.inner.fallback(query, candidates)
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::PipelineBuilder;
+    use crate::candidate_pipeline::{CandidatePipeline, HasRequestId};
+    use crate::selector::Selector;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Clone)]
+    struct Query;
+
+    impl HasRequestId for Query {
+        fn request_id(&self) -> &str {
+            "test"
+        }
+    }
+
+    /// Fails its first `failures` calls with `error`, and sleeps through call `slow_call`
+    struct Flaky {
+        calls: Arc<AtomicU32>,
+        failures: u32,
+        error: PipelineError,
+        slow_call: Option<u32>,
+    }
+
+    impl Flaky {
+        fn new(failures: u32, error: PipelineError) -> Self {
+            Self {
+                calls: Arc::new(AtomicU32::new(0)),
+                failures,
+                error,
+                slow_call: None,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Source<Query, i64> for Flaky {
+        async fn get_candidates(&self, _query: &Query) -> Result<Vec<i64>, PipelineError> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            if Some(call) == self.slow_call {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+            if call < self.failures {
+                return Err(self.error.clone());
+            }
+            Ok(vec![call as i64])
+        }
+    }
+
+    struct Largest;
+
+    impl Selector<Query, i64> for Largest {
+        fn score(&self, candidate: &i64) -> f64 {
+            *candidate as f64
+        }
+    }
+
+    fn fast() -> RetryPolicy {
+        let backoff = Duration::from_millis(1);
+        RetryPolicy::new().with_backoff(backoff, backoff, 2.0)
+    }
+
+    #[tokio::test]
+    async fn test_retries_retryable_errors_only() {
+        let flaky = Flaky::new(2, PipelineError::unavailable("down"));
+        let calls = flaky.calls.clone();
+        let pipeline = PipelineBuilder::new()
+            .source(flaky)
+            .selector(Largest)
+            .result_size(1)
+            .retry_policies(RetryPolicies::new().with_source(fast()))
+            .build()
+            .unwrap();
+        let result = pipeline.execute(Query).await.unwrap();
+        assert_eq!(result.selected_candidates, vec![2]);
+        assert!(result.errors.is_empty());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        let source = RetryingSource::new(Flaky::new(2, PipelineError::internal("bug")), fast());
+        assert!(source.get_candidates(&Query).await.is_err());
+        assert_eq!(source.inner.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_budget_bounds_retries() {
+        let policy = fast().with_max_attempts(5).with_budget(0.0, 1.0);
+        let source = RetryingSource::new(Flaky::new(100, PipelineError::timeout()), policy);
+        assert!(source.get_candidates(&Query).await.is_err());
+        assert!(source.get_candidates(&Query).await.is_err());
+        // One retry in the budget, spent by the first request
+        assert_eq!(source.inner.calls.load(Ordering::SeqCst), 3);
+
+        assert_eq!(fast().with_jitter(0.0).backoff(3), Duration::from_millis(1));
+        let policy = RetryPolicy::new();
+        assert_eq!(policy.backoff(1), Duration::from_millis(10));
+        assert_eq!(policy.backoff(3), Duration::from_millis(40));
+        assert_eq!(policy.backoff(10), Duration::from_millis(200));
+        assert!(jittered(Duration::from_millis(100), 0.5) >= Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn test_slow_calls_are_hedged() {
+        let flaky = Flaky {
+            slow_call: Some(1),
+            ..Flaky::new(0, PipelineError::timeout())
+        };
+        let policy = fast().with_hedge(HedgePolicy::new(0.5).with_min_samples(1));
+        let source = RetryingSource::new(flaky, policy);
+        // The first call has no latencies to hedge against
+        assert_eq!(source.get_candidates(&Query).await.unwrap(), vec![0]);
+
+        let start = Instant::now();
+        assert_eq!(source.get_candidates(&Query).await.unwrap(), vec![2]);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(source.inner.calls.load(Ordering::SeqCst), 3);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::error::PipelineError;
 use crate::util;
 use tonic::async_trait;
 
@@ -18,12 +19,25 @@ where
     ///
     /// IMPORTANT: The returned vector must have the same candidates in the same order as the input.
     /// Dropping candidates in a scorer is not allowed - use a filter stage instead.
-    async fn score(&self, query: &Q, candidates: &[C]) -> Result<Vec<C>, String>;
+    async fn score(&self, query: &Q, candidates: &[C]) -> Result<Vec<C>, PipelineError>;
 
     /// Update a single candidate with the scored fields.
     /// Only the fields this scorer is responsible for should be copied.
     fn update(&self, candidate: &mut C, scored: C);
 
+    /// Whether the pipeline may skip this scorer once the stage's deadline
+    /// passes; required scorers are awaited regardless
+    fn is_optional(&self) -> bool {
+        true
+    }
+
+    /// Scored candidates to use when `score` fails and the stage's error policy
+    /// is `Fallback`, e.g. with a default score, in the same order as
+    /// `candidates`; None skips the scorer
+    fn fallback(&self, _query: &Q, _candidates: &[C]) -> Option<Vec<C>> {
+        None
+    }
+
     /// Update all candidates with the scored fields from `scored`.
     /// Default implementation iterates and calls `update` for each pair.
     fn update_all(&self, candidates: &mut [C], scored: Vec<C>) {
@@ -1,3 +1,4 @@
+use crate::error::PipelineError;
 use crate::util;
 use std::sync::Arc;
 use tonic::async_trait;
@@ -20,7 +21,7 @@ where
         true
     }
 
-    async fn run(&self, input: Arc<SideEffectInput<Q, C>>) -> Result<(), String>;
+    async fn run(&self, input: Arc<SideEffectInput<Q, C>>) -> Result<(), PipelineError>;
 
     fn name(&self) -> &'static str {
         util::short_type_name(std::any::type_name::<Self>())
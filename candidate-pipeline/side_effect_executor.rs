@@ -0,0 +1,268 @@
+//! Side effects off the critical path
+//!
+//! Side effects such as impression logging and cache writes don't change the
+//! response, so a pipeline with an executor hands them to a bounded queue and
+//! returns. A fixed pool of workers drains the queue, retrying side effects
+//! that fail with retryable errors. When the queue is full the request's side
+//! effects are dropped and counted rather than waited for, so a slow sink can
+//! neither add latency to responses nor grow memory without bound.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use futures::future::join_all;
+use log::warn;
+use tokio::sync::{mpsc, Mutex};
+
+use crate::kill_switch::KillSwitches;
+use crate::retry::{Retrier, RetryPolicy};
+use crate::side_effect::{SideEffect, SideEffectInput};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SideEffectExecutorConfig {
+    /// Requests whose side effects may wait for a worker at once
+    pub queue_capacity: usize,
+    pub workers: usize,
+    pub retry: RetryPolicy,
+}
+
+impl Default for SideEffectExecutorConfig {
+    fn default() -> Self {
+        Self {
+            queue_capacity: 1024,
+            workers: 4,
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+impl SideEffectExecutorConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_queue_capacity(mut self, queue_capacity: usize) -> Self {
+        self.queue_capacity = queue_capacity;
+        self
+    }
+
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+}
+
+/// Side effect runs since startup, by outcome
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SideEffectStats {
+    /// Requests whose side effects were queued
+    pub submitted: u64,
+    /// Requests whose side effects were dropped because the queue was full
+    pub dropped: u64,
+    pub succeeded: u64,
+    /// Side effects that still failed after their retries
+    pub failed: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    submitted: AtomicU64,
+    dropped: AtomicU64,
+    succeeded: AtomicU64,
+    failed: AtomicU64,
+}
+
+type Job<Q, C> = Arc<SideEffectInput<Q, C>>;
+
+/// Runs a pipeline's side effects on a worker pool behind a bounded queue
+pub struct SideEffectExecutor<Q, C> {
+    side_effects: Arc<Vec<Box<dyn SideEffect<Q, C>>>>,
+    retriers: Arc<Vec<Retrier>>,
+    config: SideEffectExecutorConfig,
+    /// Started on the first submission, which runs inside the runtime
+    queue: OnceLock<mpsc::Sender<Job<Q, C>>>,
+    counters: Arc<Counters>,
+    kill_switches: Option<Arc<KillSwitches>>,
+}
+
+impl<Q, C> SideEffectExecutor<Q, C>
+where
+    Q: Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+{
+    pub fn new(
+        side_effects: Arc<Vec<Box<dyn SideEffect<Q, C>>>>,
+        config: SideEffectExecutorConfig,
+    ) -> Self {
+        let retriers = side_effects.iter().map(|_| Retrier::new(config.retry)).collect();
+        Self {
+            side_effects,
+            retriers: Arc::new(retriers),
+            config,
+            queue: OnceLock::new(),
+            counters: Arc::new(Counters::default()),
+            kill_switches: None,
+        }
+    }
+
+    /// Skip side effects switched off in `switches`
+    pub fn kill_switches(mut self, switches: Arc<KillSwitches>) -> Self {
+        self.kill_switches = Some(switches);
+        self
+    }
+
+    /// Queue the side effects of one request, or drop them when the queue is full
+    pub fn submit(&self, input: Arc<SideEffectInput<Q, C>>) {
+        match self.queue.get_or_init(|| self.start()).try_send(input) {
+            Ok(()) => {
+                self.counters.submitted.fetch_add(1, Ordering::Relaxed);
+            },
+            Err(e) => {
+                self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                warn!("Dropping side effects, queue unavailable: {}", e);
+            },
+        }
+    }
+
+    pub fn stats(&self) -> SideEffectStats {
+        SideEffectStats {
+            submitted: self.counters.submitted.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+            succeeded: self.counters.succeeded.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+        }
+    }
+
+    fn start(&self) -> mpsc::Sender<Job<Q, C>> {
+        let (sender, receiver) = mpsc::channel(self.config.queue_capacity.max(1));
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..self.config.workers.max(1) {
+            let receiver = receiver.clone();
+            let side_effects = self.side_effects.clone();
+            let retriers = self.retriers.clone();
+            let counters = self.counters.clone();
+            let kill_switches = self.kill_switches.clone();
+            tokio::spawn(async move {
+                loop {
+                    let Some(input) = receiver.lock().await.recv().await else {
+                        break;
+                    };
+                    let kill_switches = kill_switches.as_deref();
+                    run_all(&side_effects, &retriers, &counters, kill_switches, input).await;
+                }
+            });
+        }
+        sender
+    }
+}
+
+async fn run_all<Q, C>(
+    side_effects: &[Box<dyn SideEffect<Q, C>>],
+    retriers: &[Retrier],
+    counters: &Counters,
+    kill_switches: Option<&KillSwitches>,
+    input: Arc<SideEffectInput<Q, C>>,
+) where
+    Q: Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+{
+    let runs = side_effects
+        .iter()
+        .zip(retriers)
+        .filter(|(se, _)| !kill_switches.is_some_and(|s| s.is_killed(se.name())))
+        .filter(|(se, _)| se.enable(input.query.clone()))
+        .map(|(se, retrier)| async {
+            let result = retrier.call(se.name(), || se.run(input.clone())).await;
+            (se.name(), result)
+        });
+    for (name, result) in join_all(runs).await {
+        match result {
+            Ok(()) => {
+                counters.succeeded.fetch_add(1, Ordering::Relaxed);
+            },
+            Err(e) => {
+                counters.failed.fetch_add(1, Ordering::Relaxed);
+                warn!("side_effect={} failed: {}", name, e);
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::PipelineError;
+    use std::time::Duration;
+    use tokio::sync::Semaphore;
+    use tonic::async_trait;
+
+    /// Waits for a permit per run, failing its first run with a retryable error
+    struct Blocking {
+        permits: Arc<Semaphore>,
+        waiting: Arc<AtomicU64>,
+        runs: AtomicU64,
+    }
+
+    #[async_trait]
+    impl SideEffect<(), u64> for Blocking {
+        async fn run(&self, _input: Arc<SideEffectInput<(), u64>>) -> Result<(), PipelineError> {
+            self.waiting.fetch_add(1, Ordering::SeqCst);
+            self.permits.acquire().await.unwrap().forget();
+            if self.runs.fetch_add(1, Ordering::SeqCst) == 0 {
+                return Err(PipelineError::unavailable("sink"));
+            }
+            Ok(())
+        }
+    }
+
+    fn input() -> Arc<SideEffectInput<(), u64>> {
+        Arc::new(SideEffectInput {
+            query: Arc::new(()),
+            selected_candidates: vec![1, 2],
+        })
+    }
+
+    #[tokio::test]
+    async fn test_overflow_is_dropped_and_failures_retried() {
+        let permits = Arc::new(Semaphore::new(0));
+        let waiting = Arc::new(AtomicU64::new(0));
+        let side_effect = Blocking {
+            permits: permits.clone(),
+            waiting: waiting.clone(),
+            runs: AtomicU64::new(0),
+        };
+        let side_effects: Vec<Box<dyn SideEffect<(), u64>>> = vec![Box::new(side_effect)];
+        let retry = RetryPolicy::new().with_backoff(
+            Duration::from_millis(1),
+            Duration::from_millis(1),
+            1.0,
+        );
+        let config = SideEffectExecutorConfig::new()
+            .with_queue_capacity(1)
+            .with_workers(1)
+            .with_retry(retry.with_budget(1.0, 10.0));
+        let executor = SideEffectExecutor::new(Arc::new(side_effects), config);
+
+        // One request held by the worker, one queued, the rest dropped
+        executor.submit(input());
+        while waiting.load(Ordering::SeqCst) == 0 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        for _ in 0..3 {
+            executor.submit(input());
+        }
+        let stats = executor.stats();
+        assert_eq!((stats.submitted, stats.dropped), (2, 2));
+
+        permits.add_permits(3);
+        while executor.stats().succeeded < 2 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        assert_eq!(executor.stats().failed, 0);
+    }
+}
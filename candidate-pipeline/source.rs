@@ -1,6 +1,7 @@
 use std::any::Any;
 use tonic::async_trait;
 
+use crate::error::PipelineError;
 use crate::util;
 
 #[async_trait]
@@ -14,7 +15,19 @@ where
         true
     }
 
-    async fn get_candidates(&self, query: &Q) -> Result<Vec<C>, String>;
+    async fn get_candidates(&self, query: &Q) -> Result<Vec<C>, PipelineError>;
+
+    /// Whether the pipeline may go on without this source once the stage's
+    /// deadline passes; required sources are awaited regardless
+    fn is_optional(&self) -> bool {
+        true
+    }
+
+    /// Candidates to use when `get_candidates` fails and the stage's error
+    /// policy is `Fallback`, e.g. from a cache; None skips the source
+    fn fallback(&self, _query: &Q) -> Option<Vec<C>> {
+        None
+    }
 
     fn name(&self) -> &'static str {
         util::short_type_name(std::any::type_name::<Self>())
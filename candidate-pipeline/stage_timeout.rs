@@ -0,0 +1,336 @@
+//! Stage deadlines and cancellation
+//!
+//! A pipeline may give each stage a timeout. Every component of the stage must
+//! finish within it: parallel components share one deadline, and sequential
+//! ones get whatever time the components before them left. Optional components
+//! still running at the deadline are dropped and skipped with a warning, while
+//! required ones are awaited regardless. A request can also be cancelled as a
+//! whole, which drops every stage future still in flight.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+use crate::candidate_pipeline::PipelineStage;
+
+/// Timeout of each stage; stages without one run to completion
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StageTimeouts {
+    pub source: Option<Duration>,
+    /// Covers post-selection hydrators too
+    pub hydrator: Option<Duration>,
+    /// Covers post-selection filters too
+    pub filter: Option<Duration>,
+    pub scorer: Option<Duration>,
+}
+
+impl StageTimeouts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_source(mut self, timeout: Duration) -> Self {
+        self.source = Some(timeout);
+        self
+    }
+
+    pub fn with_hydrator(mut self, timeout: Duration) -> Self {
+        self.hydrator = Some(timeout);
+        self
+    }
+
+    pub fn with_filter(mut self, timeout: Duration) -> Self {
+        self.filter = Some(timeout);
+        self
+    }
+
+    pub fn with_scorer(mut self, timeout: Duration) -> Self {
+        self.scorer = Some(timeout);
+        self
+    }
+
+    /// Timeout of `stage`, if it has one
+    pub fn get(&self, stage: PipelineStage) -> Option<Duration> {
+        match stage {
+            PipelineStage::QueryHydrator => None,
+            PipelineStage::Source => self.source,
+            PipelineStage::Hydrator | PipelineStage::PostSelectionHydrator => self.hydrator,
+            PipelineStage::Filter | PipelineStage::PostSelectionFilter => self.filter,
+            PipelineStage::Scorer => self.scorer,
+        }
+    }
+}
+
+/// Await `future` until `deadline`, or to completion without one. None when
+/// the deadline passed first, in which case `future` is dropped.
+pub async fn until_deadline<F: Future>(deadline: Option<Instant>, future: F) -> Option<F::Output> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, future).await.ok(),
+        None => Some(future.await),
+    }
+}
+
+/// Cancels one pipeline request; clones share the same state
+#[derive(Clone, Debug)]
+pub struct Cancellation(Arc<watch::Sender<bool>>);
+
+impl Default for Cancellation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cancellation {
+    pub fn new() -> Self {
+        Self(Arc::new(watch::channel(false).0))
+    }
+
+    pub fn cancel(&self) {
+        self.0.send_replace(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Resolves once `cancel` is called
+    pub async fn cancelled(&self) {
+        let mut cancelled = self.0.subscribe();
+        // The sender lives in `self`, so waiting can't fail
+        let _ = cancelled.wait_for(|cancelled| *cancelled).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::candidate_pipeline::{CandidatePipeline, HasRequestId};
+    use crate::error::PipelineError;
+    use crate::filter::{Filter, FilterResult};
+    use crate::hydrator::Hydrator;
+    use crate::query_hydrator::QueryHydrator;
+    use crate::scorer::Scorer;
+    use crate::selector::Selector;
+    use crate::side_effect::SideEffect;
+    use crate::source::Source;
+    use tonic::async_trait;
+
+    const SLOW: Duration = Duration::from_secs(5);
+
+    #[derive(Clone)]
+    struct Query;
+
+    impl HasRequestId for Query {
+        fn request_id(&self) -> &str {
+            "test"
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Candidate {
+        id: i64,
+        score: f64,
+    }
+
+    /// Yields `ids` after `delay`
+    struct TestSource {
+        ids: Vec<i64>,
+        delay: Duration,
+    }
+
+    #[async_trait]
+    impl Source<Query, Candidate> for TestSource {
+        async fn get_candidates(&self, _query: &Query) -> Result<Vec<Candidate>, PipelineError> {
+            tokio::time::sleep(self.delay).await;
+            Ok(self.ids.iter().map(|&id| Candidate { id, score: 0.0 }).collect())
+        }
+    }
+
+    /// Removes `id` after `delay`
+    struct TestFilter {
+        id: i64,
+        delay: Duration,
+        optional: bool,
+    }
+
+    #[async_trait]
+    impl Filter<Query, Candidate> for TestFilter {
+        async fn filter(
+            &self,
+            _query: &Query,
+            candidates: Vec<Candidate>,
+        ) -> Result<FilterResult<Candidate>, PipelineError> {
+            tokio::time::sleep(self.delay).await;
+            let (removed, kept) = candidates.into_iter().partition(|c| c.id == self.id);
+            Ok(FilterResult { kept, removed })
+        }
+
+        fn is_optional(&self) -> bool {
+            self.optional
+        }
+    }
+
+    /// Scores candidates by their id times `sign`, after `delay`
+    struct TestScorer {
+        sign: f64,
+        delay: Duration,
+    }
+
+    #[async_trait]
+    impl Scorer<Query, Candidate> for TestScorer {
+        async fn score(
+            &self,
+            _query: &Query,
+            candidates: &[Candidate],
+        ) -> Result<Vec<Candidate>, PipelineError> {
+            tokio::time::sleep(self.delay).await;
+            let scored = candidates.iter().map(|c| Candidate {
+                id: c.id,
+                score: c.id as f64 * self.sign,
+            });
+            Ok(scored.collect())
+        }
+
+        fn update(&self, candidate: &mut Candidate, scored: Candidate) {
+            candidate.score = scored.score;
+        }
+    }
+
+    struct ByScore;
+
+    impl Selector<Query, Candidate> for ByScore {
+        fn score(&self, candidate: &Candidate) -> f64 {
+            candidate.score
+        }
+    }
+
+    struct TestPipeline {
+        sources: Vec<Box<dyn Source<Query, Candidate>>>,
+        filters: Vec<Box<dyn Filter<Query, Candidate>>>,
+        scorers: Vec<Box<dyn Scorer<Query, Candidate>>>,
+        timeouts: Option<StageTimeouts>,
+    }
+
+    #[async_trait]
+    impl CandidatePipeline<Query, Candidate> for TestPipeline {
+        fn query_hydrators(&self) -> &[Box<dyn QueryHydrator<Query>>] {
+            &[]
+        }
+        fn sources(&self) -> &[Box<dyn Source<Query, Candidate>>] {
+            &self.sources
+        }
+        fn hydrators(&self) -> &[Box<dyn Hydrator<Query, Candidate>>] {
+            &[]
+        }
+        fn filters(&self) -> &[Box<dyn Filter<Query, Candidate>>] {
+            &self.filters
+        }
+        fn scorers(&self) -> &[Box<dyn Scorer<Query, Candidate>>] {
+            &self.scorers
+        }
+        fn selector(&self) -> &dyn Selector<Query, Candidate> {
+            &ByScore
+        }
+        fn post_selection_hydrators(&self) -> &[Box<dyn Hydrator<Query, Candidate>>] {
+            &[]
+        }
+        fn post_selection_filters(&self) -> &[Box<dyn Filter<Query, Candidate>>] {
+            &[]
+        }
+        fn side_effects(&self) -> Arc<Vec<Box<dyn SideEffect<Query, Candidate>>>> {
+            Arc::new(Vec::new())
+        }
+        fn result_size(&self) -> usize {
+            10
+        }
+        fn stage_timeouts(&self) -> Option<&StageTimeouts> {
+            self.timeouts.as_ref()
+        }
+    }
+
+    fn pipeline(timeouts: Option<StageTimeouts>) -> TestPipeline {
+        let filter = |id, delay, optional| -> Box<dyn Filter<Query, Candidate>> {
+            Box::new(TestFilter { id, delay, optional })
+        };
+        TestPipeline {
+            sources: vec![
+                Box::new(TestSource { ids: vec![1, 2, 3, 4], delay: Duration::ZERO }),
+                Box::new(TestSource { ids: vec![99], delay: SLOW }),
+            ],
+            // The required filter overruns the stage, leaving the other no time
+            filters: vec![
+                filter(1, Duration::from_millis(30), false),
+                filter(2, SLOW, true),
+            ],
+            scorers: vec![
+                Box::new(TestScorer { sign: 1.0, delay: Duration::ZERO }),
+                Box::new(TestScorer { sign: -1.0, delay: SLOW }),
+            ],
+            timeouts,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_skips_slow_optional_components() {
+        let timeouts = StageTimeouts::new()
+            .with_source(Duration::from_millis(50))
+            .with_filter(Duration::from_millis(10))
+            .with_scorer(Duration::from_millis(50));
+        let start = Instant::now();
+        let result = pipeline(Some(timeouts)).execute(Query).await.unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let ids: Vec<i64> = result.selected_candidates.iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![4, 3, 2]);
+        assert_eq!(result.filtered_candidates, vec![Candidate { id: 1, score: 0.0 }]);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_request_drops_stages_in_flight() {
+        let cancellation = Cancellation::new();
+        let cancel = {
+            let cancellation = cancellation.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                cancellation.cancel();
+            }
+        };
+        let start = Instant::now();
+        let pipeline = pipeline(None);
+        let (result, _) = tokio::join!(pipeline.execute_cancellable(Query, &cancellation), cancel);
+        assert!(result.is_none());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_deadline_drops_slow_futures() {
+        let deadline = Some(Instant::now() + Duration::from_millis(20));
+        assert_eq!(until_deadline(deadline, async { 1 }).await, Some(1));
+        let slow = tokio::time::sleep(Duration::from_secs(5));
+        assert_eq!(until_deadline(deadline, slow).await, None);
+        assert_eq!(until_deadline(None, async { 2 }).await, Some(2));
+
+        let timeout = Duration::from_millis(5);
+        let timeouts = StageTimeouts::new().with_filter(timeout);
+        assert_eq!(timeouts.get(PipelineStage::PostSelectionFilter), Some(timeout));
+        assert_eq!(timeouts.get(PipelineStage::Scorer), None);
+    }
+
+    #[tokio::test]
+    async fn test_cancellation_is_shared_by_clones() {
+        let cancellation = Cancellation::new();
+        let waiter = {
+            let cancellation = cancellation.clone();
+            tokio::spawn(async move { cancellation.cancelled().await })
+        };
+        assert!(!cancellation.is_cancelled());
+        cancellation.cancel();
+        waiter.await.unwrap();
+        assert!(cancellation.is_cancelled());
+        // Already cancelled tokens resolve right away
+        cancellation.cancelled().await;
+    }
+}
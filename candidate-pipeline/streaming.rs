@@ -0,0 +1,360 @@
+//! Streaming Execution
+//!
+//! An alternative to running each stage over the whole candidate set. Every
+//! source's candidates are hydrated, filtered and scored as soon as the source
+//! returns them, flowing between stages over channels, while the selector
+//! keeps the best candidates scored so far. When one source is much slower
+//! than the rest, the others' candidates are ranked by the time it returns
+//! rather than after. Post-selection stages run once selection is complete.
+//!
+//! Hydrators, filters and scorers before selection see one source's batch at
+//! a time, so streaming suits pipelines whose components judge candidates on
+//! their own; those comparing candidates with each other, e.g. deduplication,
+//! belong after selection. Only the selector's `size()` best candidates
+//! scored so far are kept, so its `select` must choose among them. Spans and
+//! metrics of the stages before selection are recorded per batch.
+
+use std::collections::HashSet;
+
+use tokio::sync::mpsc;
+
+use crate::candidate_pipeline::{CandidatePipeline, HasRequestId, PipelineResult, PipelineStage};
+use crate::error_policy::{ErrorPolicies, StageError, StageErrors};
+use crate::fan_in::FanIn;
+use crate::provenance::AuditTrail;
+use crate::selector::Selector;
+use crate::source::Source;
+use crate::trace::StageSpan;
+
+/// Failures and provenance recorded by one of a request's concurrent stages,
+/// merged into the request's once every stage is done
+struct Lane {
+    errors: StageErrors,
+    trail: AuditTrail,
+}
+
+impl Lane {
+    fn new(request_id: &str, policies: ErrorPolicies, debug: bool) -> Self {
+        Self {
+            errors: StageErrors::new(request_id, policies),
+            trail: AuditTrail::new(debug),
+        }
+    }
+}
+
+/// The candidates scored so far that can still be selected
+struct TopK<'a, Q, C> {
+    selector: &'a dyn Selector<Q, C>,
+    limit: Option<usize>,
+    candidates: Vec<C>,
+}
+
+impl<'a, Q, C> TopK<'a, Q, C>
+where
+    Q: Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+{
+    fn new(selector: &'a dyn Selector<Q, C>, limit: Option<usize>) -> Self {
+        Self {
+            selector,
+            limit,
+            candidates: Vec::new(),
+        }
+    }
+
+    fn extend(&mut self, batch: Vec<C>) {
+        self.candidates.extend(batch);
+        // Sort once the backlog doubles rather than on every batch
+        if let Some(limit) = self.limit {
+            if self.candidates.len() > 2 * limit.max(1) {
+                let mut best = self.selector.sort(std::mem::take(&mut self.candidates));
+                best.truncate(limit);
+                self.candidates = best;
+            }
+        }
+    }
+}
+
+/// Run the stages of `execute` with candidates streaming from each source
+/// through hydration, filtering and scoring into selection
+pub async fn run_streaming_stages<P, Q, C>(
+    pipeline: &P,
+    query: Q,
+) -> Result<PipelineResult<Q, C>, StageError>
+where
+    P: CandidatePipeline<Q, C> + ?Sized,
+    Q: HasRequestId + Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+{
+    let policies = pipeline.error_policies().copied().unwrap_or_default();
+    let request_id = query.request_id().to_string();
+    let debug = query.debug();
+    let mut errors = StageErrors::new(request_id.as_str(), policies);
+    let mut trail = AuditTrail::new(debug);
+
+    let stage = StageSpan::start(PipelineStage::QueryHydrator, 0, &errors);
+    let query = stage.traced(pipeline.hydrate_query(query, &mut errors)).await?;
+    pipeline.finish_stage(stage, 0, &errors);
+
+    let sources: Vec<&dyn Source<Q, C>> = pipeline
+        .sources()
+        .iter()
+        .filter(|s| !pipeline.killed(s.name()) && s.enable(&query))
+        .map(|s| s.as_ref())
+        .collect();
+    // Room for a batch from every source, so a slow stage never holds up fetching
+    let capacity = sources.len().max(1);
+    let (fetched_tx, fetched_rx) = mpsc::channel(capacity);
+    let (kept_tx, kept_rx) = mpsc::channel(capacity);
+    let mut lanes: [Lane; 3] = std::array::from_fn(|_| Lane::new(&request_id, policies, debug));
+    let [fetching, filtering, scoring] = &mut lanes;
+    let (fetched, filtered, scored) = futures::join!(
+        fetch(pipeline, &query, sources, fetched_tx, fetching),
+        hydrate_and_filter(pipeline, &query, fetched_rx, kept_tx, filtering),
+        score(pipeline, &query, kept_rx, scoring),
+    );
+    let mut removed = fetched?;
+    let (retrieved, filtered) = filtered?;
+    removed.extend(filtered);
+    let selected = pipeline.select(&query, scored?);
+
+    for lane in lanes {
+        errors.merge(lane.errors);
+        trail.merge(lane.trail);
+    }
+    pipeline
+        .run_post_selection(query, selected, (retrieved, removed), errors, trail)
+        .await
+}
+
+/// Send each source's candidates on as soon as it returns them, returning the
+/// duplicates of candidates sent earlier when the pipeline deduplicates them
+async fn fetch<P, Q, C>(
+    pipeline: &P,
+    query: &Q,
+    sources: Vec<&dyn Source<Q, C>>,
+    batches: mpsc::Sender<Vec<C>>,
+    lane: &mut Lane,
+) -> Result<Vec<C>, StageError>
+where
+    P: CandidatePipeline<Q, C> + ?Sized,
+    Q: HasRequestId + Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+{
+    let deadline = pipeline.stage_deadline(PipelineStage::Source);
+    let policy = pipeline.fan_in_policy().copied();
+    let mut fan_in = FanIn::start(query, &sources, deadline, policy);
+
+    let mut seen = HashSet::new();
+    let mut duplicates = Vec::new();
+    let stage = StageSpan::start(PipelineStage::Source, 0, &lane.errors);
+    let streamed = stage
+        .traced(async {
+            let mut streamed = 0;
+            while let Some((index, result)) = fan_in.next().await {
+                let source = sources[index];
+                let (errors, trail) = (&mut lane.errors, &mut lane.trail);
+                let Some(mut candidates) =
+                    pipeline.take_fetched(query, source, result, errors, trail)?
+                else {
+                    continue;
+                };
+                if let Some(dedup) = pipeline.dedup() {
+                    let deduped = dedup.dedup_batch(&mut seen, candidates);
+                    duplicates.extend(deduped.removed);
+                    candidates = deduped.kept;
+                }
+                streamed += candidates.len();
+                if batches.send(candidates).await.is_err() {
+                    // A later stage failed the request
+                    break;
+                }
+            }
+            Ok(streamed)
+        })
+        .await?;
+    pipeline.finish_stage(stage, streamed, &lane.errors);
+    Ok(duplicates)
+}
+
+/// Hydrate and filter each batch, returning every candidate retrieved and
+/// every one removed
+async fn hydrate_and_filter<P, Q, C>(
+    pipeline: &P,
+    query: &Q,
+    mut batches: mpsc::Receiver<Vec<C>>,
+    kept: mpsc::Sender<Vec<C>>,
+    lane: &mut Lane,
+) -> Result<(Vec<C>, Vec<C>), StageError>
+where
+    P: CandidatePipeline<Q, C> + ?Sized,
+    Q: HasRequestId + Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+{
+    let mut retrieved = Vec::new();
+    let mut removed = Vec::new();
+    while let Some(batch) = batches.recv().await {
+        let stage = StageSpan::start(PipelineStage::Hydrator, batch.len(), &lane.errors);
+        let hydrated = stage
+            .traced(pipeline.hydrate(query, batch, &mut lane.errors, &mut lane.trail))
+            .await?;
+        pipeline.finish_stage(stage, hydrated.len(), &lane.errors);
+        retrieved.extend(hydrated.iter().cloned());
+
+        let stage = StageSpan::start(PipelineStage::Filter, hydrated.len(), &lane.errors);
+        let (batch_kept, batch_removed) = stage
+            .traced(pipeline.filter(query, hydrated, &mut lane.errors, &mut lane.trail))
+            .await?;
+        pipeline.finish_stage(stage, batch_kept.len(), &lane.errors);
+        removed.extend(batch_removed);
+        if kept.send(batch_kept).await.is_err() {
+            break;
+        }
+    }
+    Ok((retrieved, removed))
+}
+
+/// Score each batch, keeping the candidates the selector may still select
+async fn score<P, Q, C>(
+    pipeline: &P,
+    query: &Q,
+    mut batches: mpsc::Receiver<Vec<C>>,
+    lane: &mut Lane,
+) -> Result<Vec<C>, StageError>
+where
+    P: CandidatePipeline<Q, C> + ?Sized,
+    Q: HasRequestId + Clone + Send + Sync + 'static,
+    C: Clone + Send + Sync + 'static,
+{
+    let selector = pipeline.selector();
+    let limit = if selector.enable(query) { selector.size() } else { None };
+    let mut top = TopK::new(selector, limit);
+    while let Some(batch) = batches.recv().await {
+        let stage = StageSpan::start(PipelineStage::Scorer, batch.len(), &lane.errors);
+        let scored = stage
+            .traced(pipeline.score(query, batch, &mut lane.errors, &mut lane.trail))
+            .await?;
+        pipeline.finish_stage(stage, scored.len(), &lane.errors);
+        top.extend(scored);
+    }
+    Ok(top.candidates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::PipelineBuilder;
+    use crate::error::PipelineError;
+    use crate::filter::{Filter, FilterResult};
+    use crate::scorer::Scorer;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::Notify;
+    use tonic::async_trait;
+
+    #[derive(Clone)]
+    struct Query;
+
+    impl HasRequestId for Query {
+        fn request_id(&self) -> &str {
+            "test"
+        }
+    }
+
+    struct Fast;
+
+    #[async_trait]
+    impl Source<Query, u64> for Fast {
+        async fn get_candidates(&self, _query: &Query) -> Result<Vec<u64>, PipelineError> {
+            Ok(vec![1, 2, 3, 4])
+        }
+    }
+
+    /// Returns only once something has been scored
+    struct Slow(Arc<Notify>);
+
+    #[async_trait]
+    impl Source<Query, u64> for Slow {
+        async fn get_candidates(&self, _query: &Query) -> Result<Vec<u64>, PipelineError> {
+            self.0.notified().await;
+            Ok(vec![10, 11])
+        }
+    }
+
+    struct DropOdd;
+
+    #[async_trait]
+    impl Filter<Query, u64> for DropOdd {
+        async fn filter(
+            &self,
+            _query: &Query,
+            candidates: Vec<u64>,
+        ) -> Result<FilterResult<u64>, PipelineError> {
+            let (kept, removed) = candidates.into_iter().partition(|c| c % 2 == 0);
+            Ok(FilterResult { kept, removed })
+        }
+    }
+
+    struct Scored(Arc<Notify>);
+
+    #[async_trait]
+    impl Scorer<Query, u64> for Scored {
+        async fn score(
+            &self,
+            _query: &Query,
+            candidates: &[u64],
+        ) -> Result<Vec<u64>, PipelineError> {
+            self.0.notify_one();
+            Ok(candidates.to_vec())
+        }
+
+        fn update(&self, _candidate: &mut u64, _scored: u64) {}
+    }
+
+    struct TopTwo;
+
+    impl Selector<Query, u64> for TopTwo {
+        fn score(&self, candidate: &u64) -> f64 {
+            *candidate as f64
+        }
+
+        fn size(&self) -> Option<usize> {
+            Some(2)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fast_sources_are_scored_before_slow_ones_return() {
+        let scored = Arc::new(Notify::new());
+        let pipeline = PipelineBuilder::new()
+            .source(Fast)
+            .source(Slow(scored.clone()))
+            .filter(DropOdd)
+            .scorer(Scored(scored))
+            .selector(TopTwo)
+            .result_size(10)
+            .streaming()
+            .build()
+            .unwrap();
+
+        // Waiting for every source first would never finish
+        let execution = pipeline.execute(Query);
+        let result = tokio::time::timeout(Duration::from_secs(5), execution)
+            .await
+            .expect("the fast source's batch was scored while the slow source waited")
+            .unwrap();
+        assert_eq!(result.selected_candidates, vec![10, 4]);
+        assert_eq!(result.retrieved_candidates.len(), 6);
+        let mut filtered = result.filtered_candidates;
+        filtered.sort();
+        assert_eq!(filtered, vec![1, 3, 11]);
+    }
+
+    #[test]
+    fn test_top_k_keeps_the_best_scored_so_far() {
+        let mut top = TopK::new(&TopTwo, Some(2));
+        top.extend(vec![5, 1]);
+        top.extend(vec![7, 2, 9]);
+        assert_eq!(top.candidates, vec![9, 7]);
+    }
+}
@@ -0,0 +1,237 @@
+//! Pipeline Tracing
+//!
+//! Every request executes inside a `pipeline` span carrying its request and
+//! user ids, and every stage inside a `stage` child span recording how many
+//! candidates went in and came out, how many of its components failed and how
+//! the stage ended. A subscriber exporting spans, e.g. to Jaeger, then shows
+//! where each request spent its time.
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use tracing::field::Empty;
+use tracing::{Instrument, Span};
+
+use crate::candidate_pipeline::{HasRequestId, PipelineStage};
+use crate::error_policy::{StageError, StageErrors};
+
+/// The root span of a request
+pub fn request_span(query: &impl HasRequestId) -> Span {
+    tracing::info_span!(
+        "pipeline",
+        request_id = query.request_id(),
+        user_id = query.user_id(),
+        selected = Empty,
+        errors = Empty,
+        outcome = Empty,
+    )
+}
+
+/// One stage of a request, from start to finish
+pub struct StageSpan {
+    stage: PipelineStage,
+    span: Span,
+    started: Instant,
+    input: usize,
+    /// Components of earlier stages that had failed when the stage started
+    errors_before: usize,
+}
+
+impl StageSpan {
+    /// Start `stage` on `input` candidates, after the failures in `errors`
+    pub fn start(stage: PipelineStage, input: usize, errors: &StageErrors) -> Self {
+        let span = tracing::info_span!(
+            "stage",
+            stage = stage.as_str(),
+            candidates_in = input,
+            candidates_out = Empty,
+            errors = Empty,
+            outcome = Empty,
+        );
+        Self {
+            stage,
+            span,
+            started: Instant::now(),
+            input,
+            errors_before: errors.errors().len(),
+        }
+    }
+
+    pub fn stage(&self) -> PipelineStage {
+        self.stage
+    }
+
+    pub fn input(&self) -> usize {
+        self.input
+    }
+
+    /// Run the stage inside its span, marking the span when it fails the request
+    pub async fn traced<T>(
+        &self,
+        stage: impl Future<Output = Result<T, StageError>>,
+    ) -> Result<T, StageError> {
+        let result = stage.instrument(self.span.clone()).await;
+        if let Err(err) = &result {
+            self.span.record("outcome", "failed_request");
+            tracing::error!(parent: &self.span, error = %err, "stage failed");
+        }
+        result
+    }
+
+    /// End the stage with `output` candidates, returning how long it took
+    pub fn finish(self, output: usize, errors: &StageErrors) -> Duration {
+        let failed = errors.errors().len().saturating_sub(self.errors_before);
+        self.span.record("candidates_out", output);
+        self.span.record("errors", failed);
+        self.span.record("outcome", if failed == 0 { "ok" } else { "degraded" });
+        self.started.elapsed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::PipelineBuilder;
+    use crate::candidate_pipeline::CandidatePipeline;
+    use crate::error::PipelineError;
+    use crate::selector::Selector;
+    use crate::source::Source;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use tonic::async_trait;
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::subscriber::Subscriber;
+    use tracing::{Event, Metadata};
+
+    struct Recorded {
+        name: String,
+        parent: Option<u64>,
+        fields: HashMap<String, String>,
+    }
+
+    /// Keeps every span, with ids counting from 1 in order of creation
+    #[derive(Clone, Default)]
+    struct Spans {
+        spans: Arc<Mutex<Vec<Recorded>>>,
+        current: Arc<Mutex<Vec<u64>>>,
+    }
+
+    struct Fields<'a>(&'a mut HashMap<String, String>);
+
+    impl tracing::field::Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            let value = format!("{:?}", value).trim_matches('"').to_string();
+            self.0.insert(field.name().to_string(), value);
+        }
+    }
+
+    impl Subscriber for Spans {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+            let mut fields = HashMap::new();
+            attributes.record(&mut Fields(&mut fields));
+            let parent = self.current.lock().unwrap().last().copied();
+            let mut spans = self.spans.lock().unwrap();
+            spans.push(Recorded {
+                name: attributes.metadata().name().to_string(),
+                parent,
+                fields,
+            });
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, span: &Id, values: &Record<'_>) {
+            let mut spans = self.spans.lock().unwrap();
+            let fields = &mut spans[span.into_u64() as usize - 1].fields;
+            values.record(&mut Fields(fields));
+        }
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, _event: &Event<'_>) {}
+
+        fn enter(&self, span: &Id) {
+            self.current.lock().unwrap().push(span.into_u64());
+        }
+
+        fn exit(&self, _span: &Id) {
+            self.current.lock().unwrap().pop();
+        }
+    }
+
+    #[derive(Clone)]
+    struct Query;
+
+    impl HasRequestId for Query {
+        fn request_id(&self) -> &str {
+            "req-1"
+        }
+
+        fn user_id(&self) -> Option<u64> {
+            Some(7)
+        }
+    }
+
+    struct Numbers;
+
+    #[async_trait]
+    impl Source<Query, u64> for Numbers {
+        async fn get_candidates(&self, _query: &Query) -> Result<Vec<u64>, PipelineError> {
+            Ok(vec![1, 2, 3])
+        }
+    }
+
+    struct Unreachable;
+
+    #[async_trait]
+    impl Source<Query, u64> for Unreachable {
+        async fn get_candidates(&self, _query: &Query) -> Result<Vec<u64>, PipelineError> {
+            Err(PipelineError::unavailable("index"))
+        }
+    }
+
+    struct Identity;
+
+    impl Selector<Query, u64> for Identity {
+        fn score(&self, candidate: &u64) -> f64 {
+            *candidate as f64
+        }
+    }
+
+    #[tokio::test]
+    async fn test_requests_trace_a_span_per_stage() {
+        let pipeline = PipelineBuilder::new()
+            .source(Numbers)
+            .source(Unreachable)
+            .selector(Identity)
+            .result_size(10)
+            .build()
+            .unwrap();
+        let spans = Spans::default();
+        let _guard = tracing::subscriber::set_default(spans.clone());
+
+        pipeline.execute(Query).await.unwrap();
+
+        let spans = spans.spans.lock().unwrap();
+        let root = &spans[0];
+        assert_eq!((root.name.as_str(), root.parent), ("pipeline", None));
+        let root = &root.fields;
+        assert_eq!(root["request_id"], "req-1");
+        assert_eq!(root["user_id"], "7");
+        assert_eq!(root["selected"], "3");
+
+        let stages: HashMap<&str, &HashMap<String, String>> = spans
+            .iter()
+            .filter(|span| span.name == "stage" && span.parent == Some(1))
+            .map(|span| (span.fields["stage"].as_str(), &span.fields))
+            .collect();
+        assert_eq!(stages.len(), 7);
+        let source = stages["source"];
+        assert_eq!(source["candidates_out"], "3");
+        assert_eq!((source["errors"].as_str(), source["outcome"].as_str()), ("1", "degraded"));
+        assert_eq!(stages["filter"]["outcome"], "ok");
+    }
+}
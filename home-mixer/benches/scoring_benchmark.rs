@@ -3,7 +3,30 @@
 // Run with: cargo bench
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId};
+use home_mixer::candidate_pipeline::candidate::PostCandidate;
+use home_mixer::candidate_pipeline::shared_candidates::{ScoreOverlay, SharedCandidates};
 use home_mixer::params;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts allocations, to report how many each scoring path makes
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
 
 /// Benchmark score calculation using raw weights
 fn score_calculation_benchmark(c: &mut Criterion) {
@@ -101,12 +124,80 @@ fn author_diversity_benchmark(c: &mut Criterion) {
     group.finish();
 }
 
+/// Candidates with the text, ancestors and names a hydrated request carries
+fn hydrated_candidates(n: usize) -> Vec<PostCandidate> {
+    (0..n)
+        .map(|i| PostCandidate {
+            tweet_id: i as i64,
+            author_id: (i % 97) as u64,
+            tweet_text: format!("post {} with enough text to look like a real one", i),
+            ancestors: vec![i as u64 + 1, i as u64 + 2],
+            author_screen_name: Some(format!("author_{}", i % 97)),
+            language_code: Some("en".to_string()),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// One way of batching, scoring and splitting a request's candidates
+type ScoringPath = fn(&[PostCandidate]) -> Vec<PostCandidate>;
+
+/// Batch a request, score it and split its scores back out, copying candidates
+/// the way the batched scorer did before they were shared
+fn score_by_copying(candidates: &[PostCandidate]) -> Vec<PostCandidate> {
+    let request = candidates.to_vec();
+    let mut batch = Vec::new();
+    batch.extend(request.clone());
+    let scored: Vec<PostCandidate> = batch
+        .iter()
+        .map(|c| {
+            let mut scored = c.clone();
+            scored.phoenix_scores.favorite_score = Some(0.5);
+            scored
+        })
+        .collect();
+    scored[..candidates.len()].to_vec()
+}
+
+/// The same through shared candidates and a score overlay
+fn score_by_sharing(candidates: &[PostCandidate]) -> Vec<PostCandidate> {
+    let request = SharedCandidates::from_slice(candidates);
+    let mut batch = SharedCandidates::default();
+    batch.extend_from(&request);
+    let mut overlay = ScoreOverlay::with_len(batch.len());
+    for scores in overlay.phoenix_scores.iter_mut() {
+        scores.favorite_score = Some(0.5);
+    }
+    overlay.slice(0..candidates.len()).into_scored()
+}
+
+/// Benchmark candidate copies on the scoring path of a 1,000-candidate request
+fn candidate_sharing_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Candidate Sharing");
+    let candidates = hydrated_candidates(1000);
+
+    let paths: [(&str, ScoringPath); 2] = [("copy", score_by_copying), ("share", score_by_sharing)];
+    for (name, path) in paths {
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        black_box(path(&candidates));
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        println!("Candidate Sharing/{}: {} allocations per request", name, allocations);
+
+        group.bench_with_input(BenchmarkId::new(name, 1000), &candidates, |b, candidates| {
+            b.iter(|| black_box(path(candidates)));
+        });
+    }
+
+    group.finish();
+}
+
 criterion_group!(
     benches,
     score_calculation_benchmark,
     weight_validation_benchmark,
     freshness_decay_benchmark,
-    author_diversity_benchmark
+    author_diversity_benchmark,
+    candidate_sharing_benchmark
 );
 
 criterion_main!(benches);
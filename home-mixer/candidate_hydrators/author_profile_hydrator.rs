@@ -0,0 +1,87 @@
+use crate::candidate_pipeline::candidate::PostCandidate;
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::personalization::author_profiles::AuthorProfileStore;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::hydrator::Hydrator;
+use std::sync::Arc;
+use tonic::async_trait;
+
+/// Fills `author_quality` on each candidate from the author profile store
+pub struct AuthorProfileHydrator {
+    pub store: Arc<AuthorProfileStore>,
+}
+
+impl AuthorProfileHydrator {
+    pub fn new(store: Arc<AuthorProfileStore>) -> Self {
+        Self { store }
+    }
+}
+
+#[async_trait]
+impl Hydrator<ScoredPostsQuery, PostCandidate> for AuthorProfileHydrator {
+    async fn hydrate(
+        &self,
+        _query: &ScoredPostsQuery,
+        candidates: &[PostCandidate],
+    ) -> Result<Vec<PostCandidate>, PipelineError> {
+        let hydrated_candidates = candidates
+            .iter()
+            .map(|c| PostCandidate {
+                author_quality: self.store.quality(c.author_id),
+                ..Default::default()
+            })
+            .collect();
+
+        Ok(hydrated_candidates)
+    }
+
+    fn update(&self, candidate: &mut PostCandidate, hydrated: PostCandidate) {
+        candidate.author_quality = hydrated.author_quality;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::personalization::engagement_events::{EngagementEvent, EngagementEventType};
+
+    #[tokio::test]
+    async fn test_author_quality_is_hydrated() {
+        let store = Arc::new(AuthorProfileStore::new(100));
+        for user_id in 1..=3 {
+            store.record(&EngagementEvent {
+                user_id,
+                tweet_id: 2,
+                author_id: Some(7),
+                event_type: EngagementEventType::ReportSpam,
+                media_kind: None,
+                timestamp_ms: 0,
+                dwell_ms: None,
+                topics: Vec::new(),
+            });
+        }
+        let hydrator = AuthorProfileHydrator::new(store);
+        let mut candidates = vec![
+            PostCandidate {
+                author_id: 7,
+                ..Default::default()
+            },
+            PostCandidate {
+                author_id: 8,
+                ..Default::default()
+            },
+        ];
+
+        let hydrated = hydrator
+            .hydrate(&ScoredPostsQuery::default(), &candidates)
+            .await
+            .unwrap();
+        for (candidate, hydrated) in candidates.iter_mut().zip(hydrated) {
+            hydrator.update(candidate, hydrated);
+        }
+
+        assert_eq!(candidates[0].author_quality.unwrap().report_rate, 0.03);
+        assert!(candidates[0].author_quality.unwrap().spam_flagged);
+        assert!(candidates[1].author_quality.is_none());
+    }
+}
@@ -0,0 +1,129 @@
+use crate::candidate_pipeline::candidate::{Entitlements, PostCandidate};
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::hydrator::Hydrator;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tonic::async_trait;
+
+/// Resolves whether the viewer is entitled to each candidate.
+///
+/// Implementations must return one entry per candidate, in input order.
+#[async_trait]
+pub trait EntitlementProvider: Send + Sync {
+    async fn get_entitlements(
+        &self,
+        query: &ScoredPostsQuery,
+        candidates: &[PostCandidate],
+    ) -> Result<Vec<Entitlements>, String>;
+}
+
+/// Default provider: a post is subscriber-only when it carries a `subscription_author_id`,
+/// and the viewer is entitled when they subscribe to that author.
+pub struct SubscribedUsersEntitlementProvider;
+
+#[async_trait]
+impl EntitlementProvider for SubscribedUsersEntitlementProvider {
+    async fn get_entitlements(
+        &self,
+        query: &ScoredPostsQuery,
+        candidates: &[PostCandidate],
+    ) -> Result<Vec<Entitlements>, String> {
+        let subscribed_user_ids: HashSet<u64> = query
+            .user_features
+            .subscribed_user_ids
+            .iter()
+            .map(|id| *id as u64)
+            .collect();
+
+        let entitlements = candidates
+            .iter()
+            .map(|c| match c.subscription_author_id {
+                Some(author_id) => Entitlements {
+                    subscriber_only: true,
+                    viewer_entitled: author_id == query.user_id as u64
+                        || subscribed_user_ids.contains(&author_id),
+                },
+                None => Entitlements {
+                    subscriber_only: false,
+                    viewer_entitled: true,
+                },
+            })
+            .collect();
+
+        Ok(entitlements)
+    }
+}
+
+pub struct EntitlementHydrator {
+    pub provider: Arc<dyn EntitlementProvider>,
+}
+
+impl EntitlementHydrator {
+    pub fn new(provider: Arc<dyn EntitlementProvider>) -> Self {
+        Self { provider }
+    }
+}
+
+#[async_trait]
+impl Hydrator<ScoredPostsQuery, PostCandidate> for EntitlementHydrator {
+    async fn hydrate(
+        &self,
+        query: &ScoredPostsQuery,
+        candidates: &[PostCandidate],
+    ) -> Result<Vec<PostCandidate>, PipelineError> {
+        let entitlements = self
+            .provider
+            .get_entitlements(query, candidates)
+            .await
+            .map_err(PipelineError::unavailable)?;
+
+        let hydrated_candidates = entitlements
+            .into_iter()
+            .map(|entitlements| PostCandidate {
+                entitlements: Some(entitlements),
+                ..Default::default()
+            })
+            .collect();
+
+        Ok(hydrated_candidates)
+    }
+
+    fn update(&self, candidate: &mut PostCandidate, hydrated: PostCandidate) {
+        candidate.entitlements = hydrated.entitlements;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_subscribed_users_provider() {
+        let mut query = ScoredPostsQuery {
+            user_id: 1,
+            ..Default::default()
+        };
+        query.user_features.subscribed_user_ids = vec![100];
+
+        let candidates = vec![
+            PostCandidate {
+                subscription_author_id: Some(100),
+                ..Default::default()
+            },
+            PostCandidate {
+                subscription_author_id: Some(200),
+                ..Default::default()
+            },
+            PostCandidate::default(),
+        ];
+
+        let hydrator = EntitlementHydrator::new(Arc::new(SubscribedUsersEntitlementProvider));
+        let hydrated = hydrator.hydrate(&query, &candidates).await.unwrap();
+        let entitlements: Vec<_> = hydrated.iter().map(|c| c.entitlements.unwrap()).collect();
+
+        assert!(entitlements[0].subscriber_only && entitlements[0].viewer_entitled);
+        assert!(entitlements[1].subscriber_only && !entitlements[1].viewer_entitled);
+        assert!(!entitlements[2].subscriber_only && entitlements[2].viewer_entitled);
+    }
+}
@@ -0,0 +1,101 @@
+use crate::candidate_pipeline::candidate::PostCandidate;
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::personalization::exploration::is_exploratory;
+use crate::personalization::topic_extractor::TopicExtractor;
+use crate::personalization::user_clusters::UserClusteringService;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::hydrator::Hydrator;
+use std::sync::Arc;
+use tonic::async_trait;
+
+/// Flags candidates outside the viewer's learned interests as `exploratory`.
+/// Hydrators run side by side, so topics the candidate doesn't already carry are
+/// extracted here too; candidates without topics are never exploratory.
+pub struct ExplorationHydrator {
+    pub clustering: Arc<UserClusteringService>,
+    pub extractor: Arc<TopicExtractor>,
+}
+
+impl ExplorationHydrator {
+    pub fn new(clustering: Arc<UserClusteringService>, extractor: Arc<TopicExtractor>) -> Self {
+        Self {
+            clustering,
+            extractor,
+        }
+    }
+}
+
+#[async_trait]
+impl Hydrator<ScoredPostsQuery, PostCandidate> for ExplorationHydrator {
+    async fn hydrate(
+        &self,
+        query: &ScoredPostsQuery,
+        candidates: &[PostCandidate],
+    ) -> Result<Vec<PostCandidate>, PipelineError> {
+        let profile = self.clustering.get_user_cluster(query.user_id as u64).await;
+        let interests = query.user_interest_topics.as_ref();
+        let texts: Vec<&str> = candidates.iter().map(|c| c.tweet_text.as_str()).collect();
+        let extracted = self.extractor.extract_batch(&texts).await;
+
+        let hydrated_candidates = candidates
+            .iter()
+            .zip(extracted)
+            .map(|(c, extracted)| {
+                let topics = c.topics.as_deref().unwrap_or(&extracted);
+                PostCandidate {
+                    exploratory: Some(is_exploratory(topics, &profile, interests)),
+                    ..Default::default()
+                }
+            })
+            .collect();
+
+        Ok(hydrated_candidates)
+    }
+
+    fn update(&self, candidate: &mut PostCandidate, hydrated: PostCandidate) {
+        candidate.exploratory = hydrated.exploratory;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::personalization::user_clusters::ClusterProfile;
+
+    #[tokio::test]
+    async fn test_exploratory_is_hydrated_from_profile() {
+        let clustering = Arc::new(UserClusteringService::new(1));
+        let profile = ClusterProfile {
+            topic_affinity: [("sports".to_string(), 0.5)].into_iter().collect(),
+            ..Default::default()
+        };
+        clustering.assign_user_cluster(7, profile).await;
+        let hydrator = ExplorationHydrator::new(clustering, Arc::new(TopicExtractor::new()));
+        let query = ScoredPostsQuery {
+            user_id: 7,
+            ..Default::default()
+        };
+        let candidate = |topic: &str| PostCandidate {
+            topics: Some(vec![topic.to_string()]),
+            ..Default::default()
+        };
+        let untagged = |text: &str| PostCandidate {
+            tweet_text: text.to_string(),
+            ..Default::default()
+        };
+        let mut candidates = vec![
+            candidate("sports"),
+            candidate("science"),
+            PostCandidate::default(),
+            untagged("Election night coverage"),
+        ];
+
+        let hydrated = hydrator.hydrate(&query, &candidates).await.unwrap();
+        for (candidate, hydrated) in candidates.iter_mut().zip(hydrated) {
+            hydrator.update(candidate, hydrated);
+        }
+
+        let flags: Vec<Option<bool>> = candidates.iter().map(|c| c.exploratory).collect();
+        assert_eq!(flags, vec![Some(false), Some(true), Some(false), Some(true)]);
+    }
+}
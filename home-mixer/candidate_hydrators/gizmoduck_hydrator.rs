@@ -3,6 +3,7 @@ use crate::candidate_pipeline::query::ScoredPostsQuery;
 use crate::clients::gizmoduck_client::GizmoduckClient;
 use std::sync::Arc;
 use tonic::async_trait;
+use xai_candidate_pipeline::error::PipelineError;
 use xai_candidate_pipeline::hydrator::Hydrator;
 
 pub struct GizmoduckCandidateHydrator {
@@ -22,7 +23,7 @@ impl Hydrator<ScoredPostsQuery, PostCandidate> for GizmoduckCandidateHydrator {
         &self,
         _query: &ScoredPostsQuery,
         candidates: &[PostCandidate],
-    ) -> Result<Vec<PostCandidate>, String> {
+    ) -> Result<Vec<PostCandidate>, PipelineError> {
         let client = &self.gizmoduck_client;
 
         let author_ids: Vec<_> = candidates.iter().map(|c| c.author_id).collect();
@@ -37,7 +38,7 @@ impl Hydrator<ScoredPostsQuery, PostCandidate> for GizmoduckCandidateHydrator {
         user_ids_to_fetch.dedup();
 
         let users = client.get_users(user_ids_to_fetch).await;
-        let users = users.map_err(|e| e.to_string())?;
+        let users = users.map_err(|e| PipelineError::unavailable(e.to_string()))?;
 
         let mut hydrated_candidates = Vec::with_capacity(candidates.len());
 
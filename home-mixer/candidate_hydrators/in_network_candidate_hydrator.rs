@@ -2,6 +2,7 @@ use crate::candidate_pipeline::candidate::PostCandidate;
 use crate::candidate_pipeline::query::ScoredPostsQuery;
 use std::collections::HashSet;
 use tonic::async_trait;
+use xai_candidate_pipeline::error::PipelineError;
 use xai_candidate_pipeline::hydrator::Hydrator;
 
 pub struct InNetworkCandidateHydrator;
@@ -13,7 +14,7 @@ impl Hydrator<ScoredPostsQuery, PostCandidate> for InNetworkCandidateHydrator {
         &self,
         query: &ScoredPostsQuery,
         candidates: &[PostCandidate],
-    ) -> Result<Vec<PostCandidate>, String> {
+    ) -> Result<Vec<PostCandidate>, PipelineError> {
         let viewer_id = query.user_id as u64;
         let followed_ids: HashSet<u64> = query
             .user_features
@@ -1,6 +1,17 @@
-pub mod core_data_candidate_hydrator;
-pub mod gizmoduck_hydrator;
-pub mod in_network_candidate_hydrator;
-pub mod subscription_hydrator;
-pub mod vf_candidate_hydrator;
-pub mod video_duration_candidate_hydrator;
+//! Candidate hydrators
+//!
+//! Note: Most hydrators require internal clients and are disabled for open-source compatibility.
+
+pub mod author_profile_hydrator;
+pub mod entitlement_hydrator;
+pub mod exploration_hydrator;
+pub mod related_post_hydrator;
+pub mod topic_hydrator;
+
+// The following modules require internal clients and are commented out for open-source builds:
+// pub mod core_data_candidate_hydrator;
+// pub mod gizmoduck_hydrator;
+// pub mod in_network_candidate_hydrator;
+// pub mod subscription_hydrator;
+// pub mod vf_candidate_hydrator;
+// pub mod video_duration_candidate_hydrator;
@@ -0,0 +1,130 @@
+use crate::candidate_pipeline::candidate::{PostCandidate, RelatedPost};
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::hydrator::Hydrator;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tonic::async_trait;
+
+/// Looks up parent / quoted posts by id (Thunder, TES, or a local store)
+#[async_trait]
+pub trait RelatedPostProvider: Send + Sync {
+    /// Returns the posts that were found; missing ids are simply absent.
+    async fn get_posts(&self, tweet_ids: &[u64]) -> Result<HashMap<u64, RelatedPost>, String>;
+}
+
+/// Provider backed by a fixed map, for tests and local development
+#[derive(Default)]
+pub struct StaticRelatedPostProvider {
+    pub posts: HashMap<u64, RelatedPost>,
+}
+
+#[async_trait]
+impl RelatedPostProvider for StaticRelatedPostProvider {
+    async fn get_posts(&self, tweet_ids: &[u64]) -> Result<HashMap<u64, RelatedPost>, String> {
+        Ok(tweet_ids
+            .iter()
+            .filter_map(|id| self.posts.get(id).map(|p| (*id, p.clone())))
+            .collect())
+    }
+}
+
+/// Hydrates the parent (replied-to) and quoted post onto each candidate so filters can
+/// make context-aware decisions, e.g. an innocuous quote of an NSFW post.
+pub struct RelatedPostHydrator {
+    pub provider: Arc<dyn RelatedPostProvider>,
+}
+
+impl RelatedPostHydrator {
+    pub fn new(provider: Arc<dyn RelatedPostProvider>) -> Self {
+        Self { provider }
+    }
+}
+
+#[async_trait]
+impl Hydrator<ScoredPostsQuery, PostCandidate> for RelatedPostHydrator {
+    async fn hydrate(
+        &self,
+        _query: &ScoredPostsQuery,
+        candidates: &[PostCandidate],
+    ) -> Result<Vec<PostCandidate>, PipelineError> {
+        let mut tweet_ids: Vec<u64> = candidates
+            .iter()
+            .flat_map(|c| c.in_reply_to_tweet_id.into_iter().chain(c.quoted_tweet_id))
+            .collect();
+        tweet_ids.sort_unstable();
+        tweet_ids.dedup();
+
+        let posts = if tweet_ids.is_empty() {
+            HashMap::new()
+        } else {
+            self.provider.get_posts(&tweet_ids).await?
+        };
+
+        let hydrated_candidates = candidates
+            .iter()
+            .map(|c| PostCandidate {
+                parent_post: c.in_reply_to_tweet_id.and_then(|id| posts.get(&id).cloned()),
+                quoted_post: c.quoted_tweet_id.and_then(|id| posts.get(&id).cloned()),
+                ..Default::default()
+            })
+            .collect();
+
+        Ok(hydrated_candidates)
+    }
+
+    fn update(&self, candidate: &mut PostCandidate, hydrated: PostCandidate) {
+        candidate.parent_post = hydrated.parent_post;
+        candidate.quoted_post = hydrated.quoted_post;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::candidate_pipeline::candidate::CandidateHelpers;
+
+    #[tokio::test]
+    async fn test_hydrates_parent_and_quoted_posts() {
+        let parent = RelatedPost {
+            tweet_id: 10,
+            author_id: 1,
+            text: "parent".to_string(),
+            labels: vec![],
+        };
+        let quoted = RelatedPost {
+            tweet_id: 20,
+            author_id: 2,
+            text: "quoted".to_string(),
+            labels: vec!["adult_content".to_string()],
+        };
+        let provider = StaticRelatedPostProvider {
+            posts: [(10, parent.clone()), (20, quoted.clone())].into_iter().collect(),
+        };
+
+        let candidates = vec![
+            PostCandidate {
+                in_reply_to_tweet_id: Some(10),
+                quoted_tweet_id: Some(20),
+                ..Default::default()
+            },
+            PostCandidate {
+                quoted_tweet_id: Some(30),
+                ..Default::default()
+            },
+            PostCandidate::default(),
+        ];
+
+        let hydrator = RelatedPostHydrator::new(Arc::new(provider));
+        let hydrated = hydrator
+            .hydrate(&ScoredPostsQuery::default(), &candidates)
+            .await
+            .unwrap();
+
+        assert_eq!(hydrated[0].parent_post.as_ref(), Some(&parent));
+        assert_eq!(hydrated[0].quoted_post.as_ref(), Some(&quoted));
+        assert_eq!(hydrated[0].context_posts().count(), 2);
+        assert!(hydrated[1].quoted_post.is_none());
+        assert_eq!(hydrated[2].context_posts().count(), 0);
+    }
+}
@@ -3,6 +3,7 @@ use crate::candidate_pipeline::query::ScoredPostsQuery;
 use crate::clients::tweet_entity_service_client::TESClient;
 use std::sync::Arc;
 use tonic::async_trait;
+use xai_candidate_pipeline::error::PipelineError;
 use xai_candidate_pipeline::hydrator::Hydrator;
 
 pub struct SubscriptionHydrator {
@@ -22,13 +23,13 @@ impl Hydrator<ScoredPostsQuery, PostCandidate> for SubscriptionHydrator {
         &self,
         _query: &ScoredPostsQuery,
         candidates: &[PostCandidate],
-    ) -> Result<Vec<PostCandidate>, String> {
+    ) -> Result<Vec<PostCandidate>, PipelineError> {
         let client = &self.tes_client;
 
         let tweet_ids = candidates.iter().map(|c| c.tweet_id).collect::<Vec<_>>();
 
         let post_features = client.get_subscription_author_ids(tweet_ids.clone()).await;
-        let post_features = post_features.map_err(|e| e.to_string())?;
+        let post_features = post_features.map_err(|e| PipelineError::unavailable(e.to_string()))?;
 
         let mut hydrated_candidates = Vec::with_capacity(candidates.len());
         for tweet_id in tweet_ids {
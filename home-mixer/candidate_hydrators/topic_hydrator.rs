@@ -0,0 +1,66 @@
+use crate::candidate_pipeline::candidate::PostCandidate;
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::personalization::topic_extractor::TopicExtractor;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::hydrator::Hydrator;
+use std::sync::Arc;
+use tonic::async_trait;
+
+/// Fills `topics` on each candidate from its text
+pub struct TopicHydrator {
+    pub extractor: Arc<TopicExtractor>,
+}
+
+impl TopicHydrator {
+    pub fn new(extractor: Arc<TopicExtractor>) -> Self {
+        Self { extractor }
+    }
+}
+
+#[async_trait]
+impl Hydrator<ScoredPostsQuery, PostCandidate> for TopicHydrator {
+    async fn hydrate(
+        &self,
+        _query: &ScoredPostsQuery,
+        candidates: &[PostCandidate],
+    ) -> Result<Vec<PostCandidate>, PipelineError> {
+        let texts: Vec<&str> = candidates.iter().map(|c| c.tweet_text.as_str()).collect();
+        let topics = self.extractor.extract_batch(&texts).await;
+
+        let hydrated_candidates = topics
+            .into_iter()
+            .map(|topics| PostCandidate {
+                topics: Some(topics),
+                ..Default::default()
+            })
+            .collect();
+
+        Ok(hydrated_candidates)
+    }
+
+    fn update(&self, candidate: &mut PostCandidate, hydrated: PostCandidate) {
+        candidate.topics = hydrated.topics;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_topics_are_hydrated() {
+        let hydrator = TopicHydrator::new(Arc::new(TopicExtractor::new()));
+        let mut candidate = PostCandidate {
+            tweet_text: "Election night coverage".to_string(),
+            ..Default::default()
+        };
+
+        let hydrated = hydrator
+            .hydrate(&ScoredPostsQuery::default(), std::slice::from_ref(&candidate))
+            .await
+            .unwrap();
+        hydrator.update(&mut candidate, hydrated.into_iter().next().unwrap());
+
+        assert_eq!(candidate.topics, Some(vec!["politics".to_string()]));
+    }
+}
@@ -4,6 +4,7 @@ use futures::future::join;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tonic::async_trait;
+use xai_candidate_pipeline::error::PipelineError;
 use xai_candidate_pipeline::hydrator::Hydrator;
 use xai_twittercontext_proto::GetTwitterContextViewer;
 use xai_twittercontext_proto::TwitterContextViewer;
@@ -46,7 +47,7 @@ impl Hydrator<ScoredPostsQuery, PostCandidate> for VFCandidateHydrator {
         &self,
         query: &ScoredPostsQuery,
         candidates: &[PostCandidate],
-    ) -> Result<Vec<PostCandidate>, String> {
+    ) -> Result<Vec<PostCandidate>, PipelineError> {
         let context = query.get_viewer();
         let user_id = query.user_id;
         let client = &self.vf_client;
@@ -1,5 +1,6 @@
 //! Post candidate data structures
 
+use crate::personalization::author_profiles::AuthorQuality;
 use crate::proto::{FilteredReason, ServedType};
 use std::collections::HashMap;
 
@@ -26,6 +27,48 @@ pub struct PostCandidate {
     pub retweeted_screen_name: Option<String>,
     pub visibility_reason: Option<FilteredReason>,
     pub subscription_author_id: Option<u64>,
+    pub entitlements: Option<Entitlements>,
+    /// Set when a subscriber-only post is served as a preview to a non-subscriber
+    pub subscription_preview: Option<bool>,
+    /// Country codes the post is withheld in ("XX" = everywhere)
+    pub withheld_countries: Vec<String>,
+    pub language_code: Option<String>,
+    /// Content labels from the media / safety pipelines (e.g. "adult_content")
+    pub labels: Vec<String>,
+    /// 0.0 (benign) - 1.0 (toxic)
+    pub toxicity_score: Option<f64>,
+    pub quoted_tweet_id: Option<u64>,
+    /// Post this candidate replies to, hydrated for context-aware filtering
+    pub parent_post: Option<RelatedPost>,
+    /// Post this candidate quotes, hydrated for context-aware filtering
+    pub quoted_post: Option<RelatedPost>,
+    /// Topics extracted from the post text by `TopicHydrator`
+    pub topics: Option<Vec<String>>,
+    /// Report, block and engagement history of the author, from `AuthorProfileHydrator`
+    pub author_quality: Option<AuthorQuality>,
+    /// Outside the viewer's learned interests, from `ExplorationHydrator`
+    pub exploratory: Option<bool>,
+    /// By an author similar to the viewer they don't follow, from `SimilarAuthorsSource`
+    pub discovery: Option<bool>,
+}
+
+/// Minimal view of a parent or quoted post
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RelatedPost {
+    pub tweet_id: u64,
+    pub author_id: u64,
+    pub text: String,
+    /// Content labels from the media / safety pipelines (e.g. "adult_content")
+    pub labels: Vec<String>,
+}
+
+/// Viewer entitlements for a single post
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Entitlements {
+    /// Post is only fully visible to subscribers of its author
+    pub subscriber_only: bool,
+    /// Viewer may see the full post
+    pub viewer_entitled: bool,
 }
 
 #[derive(Clone, Debug, Default)]
@@ -54,6 +97,7 @@ pub struct PhoenixScores {
 
 pub trait CandidateHelpers {
     fn get_screen_names(&self) -> HashMap<u64, String>;
+    fn context_posts(&self) -> impl Iterator<Item = &RelatedPost>;
 }
 
 impl CandidateHelpers for PostCandidate {
@@ -69,4 +113,8 @@ impl CandidateHelpers for PostCandidate {
         }
         screen_names
     }
+
+    fn context_posts(&self) -> impl Iterator<Item = &RelatedPost> {
+        self.parent_post.iter().chain(self.quoted_post.iter())
+    }
 }
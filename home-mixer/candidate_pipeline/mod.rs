@@ -3,3 +3,4 @@ pub mod candidate_features;
 pub mod phoenix_candidate_pipeline;
 pub mod query;
 pub mod query_features;
+pub mod shared_candidates;
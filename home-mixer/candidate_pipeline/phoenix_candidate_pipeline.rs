@@ -2,52 +2,506 @@
 //!
 //! This is the main pipeline that orchestrates candidate retrieval, filtering, and scoring.
 
+use crate::candidate_hydrators::author_profile_hydrator::AuthorProfileHydrator;
+use crate::candidate_hydrators::entitlement_hydrator::{
+    EntitlementHydrator, EntitlementProvider, SubscribedUsersEntitlementProvider,
+};
+use crate::candidate_hydrators::exploration_hydrator::ExplorationHydrator;
+use crate::candidate_hydrators::related_post_hydrator::{
+    RelatedPostHydrator, RelatedPostProvider,
+};
+use crate::candidate_hydrators::topic_hydrator::TopicHydrator;
 use crate::candidate_pipeline::candidate::PostCandidate;
 use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::config::{CohortContext, Metrics, DIVERSITY_BOOST_FEATURE, GEO_FILTER_FEATURE};
+use crate::filters::author_list_filter::{AuthorListFilter, AuthorListStore};
+use crate::filters::content_quality_filters::{
+    EngagementBaitFilter, NSFWContentFilter, SpamBotFilter,
+};
+use crate::filters::geo_filter::{GeoEligibilityFilter, LanguageMismatchScorer};
+use crate::filters::ineligible_subscription_filter::IneligibleSubscriptionFilter;
+use crate::filters::muted_topic_filter::MutedTopicFilter;
+use crate::filters::toxicity_filter::ToxicityFilter;
 use crate::params;
+use crate::personalization::embedding_store::EmbeddingStore;
+use crate::personalization::exploration::ExplorationTracker;
+use crate::personalization::session_store::SessionStore;
+use crate::personalization::topic_extractor::TopicExtractor;
+use crate::personalization::user_clusters::UserClusteringService;
+use crate::query_hydrators::experiments_query_hydrator::ExperimentsQueryHydrator;
+use crate::query_hydrators::filter_overrides_query_hydrator::FilterOverridesQueryHydrator;
+use crate::query_hydrators::following_query_hydrator::{FollowingListCache, FollowingQueryHydrator};
+use crate::query_hydrators::locale_params_query_hydrator::LocaleParamsQueryHydrator;
+use crate::query_hydrators::user_interest_topics_query_hydrator::{
+    EngagementHistoryProvider, UserInterestTopicsQueryHydrator,
+};
+use crate::query_hydrators::weight_preset_query_hydrator::WeightPresetQueryHydrator;
+use crate::scorers::author_affinity_scorer::AuthorAffinityScorer;
+use crate::scorers::diversity_boost_scorer::DiversityBoostScorer;
+use crate::scorers::final_score_scorer::FinalScoreScorer;
+use crate::scorers::session_diversity_scorer::SessionDiversityScorer;
+use crate::scorers::time_of_day_scorer::TimeOfDayScorer;
+use crate::scorers::weighted_scorer::WeightedScorer;
+use crate::selectors::exploration_selector::ExplorationSelector;
+use crate::sources::similar_authors_source::{AuthorPostsProvider, SimilarAuthorsSource};
+use crate::side_effects::experiment_exposure_side_effect::ExperimentExposureSideEffect;
+use crate::side_effects::exploration_side_effect::RecordExplorationSideEffect;
+use crate::side_effects::served_impressions_side_effect::{
+    open_impression_sink, ServedImpressionsSideEffect,
+};
+use crate::side_effects::session_side_effect::RecordSessionSideEffect;
+use crate::side_effects::timeline_export_side_effect::{
+    open_timeline_export_sink, TimelineExportSideEffect,
+};
+use crate::util::config_watcher::ConfigWatcher;
+use crate::util::sticky_buckets::{self, StickyBuckets};
+use candidate_pipeline::builder::{Pipeline, PipelineBuilder};
 use candidate_pipeline::candidate_pipeline::CandidatePipeline;
-use candidate_pipeline::filter::Filter;
-use candidate_pipeline::hydrator::Hydrator;
-use candidate_pipeline::query_hydrator::QueryHydrator;
-use candidate_pipeline::scorer::Scorer;
+use candidate_pipeline::composition::FeedComposer;
+use candidate_pipeline::dedup::DedupStage;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::error_policy::{ErrorPolicies, ErrorPolicy};
+use candidate_pipeline::fan_in::FanInPolicy;
+use candidate_pipeline::filter::{Filter, FilterResult};
+use candidate_pipeline::gate::{Gate, Gated};
+use candidate_pipeline::interleaving::TeamDraftInterleaver;
+use candidate_pipeline::kill_switch::KillSwitches;
+use candidate_pipeline::metrics::PipelineMetrics;
+use candidate_pipeline::retry::{HedgePolicy, RetryPolicies, RetryPolicy};
 use candidate_pipeline::selector::Selector;
-use candidate_pipeline::side_effect::SideEffect;
-use candidate_pipeline::source::Source;
-use std::sync::Arc;
+use candidate_pipeline::side_effect_executor::SideEffectExecutorConfig;
+use candidate_pipeline::stage_timeout::StageTimeouts;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 use tonic::async_trait;
 
 /// Phoenix Candidate Pipeline implementation
-pub struct PhoenixCandidatePipeline {
-    query_hydrators: Vec<Box<dyn QueryHydrator<ScoredPostsQuery>>>,
-    sources: Vec<Box<dyn Source<ScoredPostsQuery, PostCandidate>>>,
-    hydrators: Vec<Box<dyn Hydrator<ScoredPostsQuery, PostCandidate>>>,
-    filters: Vec<Box<dyn Filter<ScoredPostsQuery, PostCandidate>>>,
-    scorers: Vec<Box<dyn Scorer<ScoredPostsQuery, PostCandidate>>>,
-    selector: Box<dyn Selector<ScoredPostsQuery, PostCandidate>>,
-    post_selection_hydrators: Vec<Box<dyn Hydrator<ScoredPostsQuery, PostCandidate>>>,
-    post_selection_filters: Vec<Box<dyn Filter<ScoredPostsQuery, PostCandidate>>>,
-    side_effects: Arc<Vec<Box<dyn SideEffect<ScoredPostsQuery, PostCandidate>>>>,
-}
-
-impl PhoenixCandidatePipeline {
-    /// Create a production pipeline configuration
-    pub async fn prod() -> Self {
-        // For open-source compatibility, we create a minimal pipeline
-        // In production, this would include real client connections
-        PhoenixCandidatePipeline {
-            query_hydrators: vec![],
-            sources: vec![],
-            hydrators: vec![],
-            filters: vec![],
-            scorers: vec![],
-            selector: Box::new(TopKSelector::new(params::RESULT_SIZE)),
-            post_selection_hydrators: vec![],
-            post_selection_filters: vec![],
-            side_effects: Arc::new(vec![]),
+pub type PhoenixCandidatePipeline = Pipeline<ScoredPostsQuery, PostCandidate>;
+
+/// Stages of the production pipeline over `services`; experiments derive
+/// variants from a clone. Safety filters run in the modes the viewer's
+/// overrides resolve to under the config in use, and posts withheld in the
+/// viewer's country are dropped while the geo filter is enabled. Posts in
+/// other languages than the request's are downranked by
+/// `language_mismatch_penalty`. Subscriber-only posts the viewer isn't
+/// entitled to, as `services.entitlements` resolves it, are dropped, or served as previews when
+/// `show_subscription_previews` is set. Recent posts by similar authors the
+/// viewer doesn't follow are added when `services` has embeddings for them.
+/// Posts are tagged with their topics,
+/// and those on topics the viewer muted through personalization are dropped,
+/// and with an engagement history the viewer's interest topics are derived
+/// from it, so the diversity boost finds posts outside them. With
+/// exploration on, a share of each timeline is reserved for posts outside the
+/// viewer's learned interests, and posts are weighted with the preset of the
+/// viewer's cluster and adjusted to the viewer's local time of day. Posts of
+/// followed authors are reweighted by how much the viewer interacts with them
+/// when author affinities are learned. Later
+/// pages of a session are diversified against the earlier ones. Posts are
+/// ranked by their weighted score once every scorer has adjusted it.
+pub fn prod_builder(services: &ProdServices) -> PipelineBuilder<ScoredPostsQuery, PostCandidate> {
+    // For open-source compatibility, we create a minimal pipeline
+    // In production, this would include real client connections
+    let extractor = Arc::new(TopicExtractor::new());
+    let builder = PipelineBuilder::new()
+        .query_hydrator(FilterOverridesQueryHydrator::new(services.config.clone()))
+        .hydrator(EntitlementHydrator::new(services.entitlements.clone()))
+        .hydrator(TopicHydrator::new(extractor.clone()))
+        .filter(AuthorListFilter::new(services.author_lists.clone()))
+        .filter(NSFWContentFilter::new())
+        .filter(SpamBotFilter::new())
+        .filter(EngagementBaitFilter::new())
+        .filter(ToxicityFilter::new().with_metrics(prod_filter_metrics()))
+        .filter(Gated::new(
+            geo_filter_gate(services.config.clone()),
+            GeoEligibilityFilter,
+        ))
+        .filter(IneligibleSubscriptionFilter::default().with_config(services.config.clone()))
+        .scorer(WeightedScorer::new())
+        .scorer(LanguageMismatchScorer::new(services.config.clone()))
+        .scorer(SessionDiversityScorer::new(services.sessions.clone()))
+        .scorer(Gated::new(
+            diversity_boost_gate(services.config.clone()),
+            DiversityBoostScorer::new(services.config.clone()),
+        ));
+    let builder = match services.similar_authors() {
+        Some(source) => builder.source(source),
+        None => builder,
+    };
+    let builder = match &services.related_posts {
+        Some(provider) => builder.hydrator(RelatedPostHydrator::new(provider.clone())),
+        None => builder,
+    };
+    let builder = match &services.engagement_history {
+        Some(history) => builder.query_hydrator(UserInterestTopicsQueryHydrator::new(
+            extractor.clone(),
+            history.clone(),
+        )),
+        None => builder,
+    };
+    let builder = match &services.clustering {
+        Some(clustering) => builder
+            .query_hydrator(WeightPresetQueryHydrator::new(clustering.clone()))
+            .filter(MutedTopicFilter::new(clustering.clone()))
+            .scorer(TimeOfDayScorer::new(clustering.clone())),
+        None => builder,
+    };
+    let builder = match &services.following {
+        Some(cache) => builder.query_hydrator(FollowingQueryHydrator {
+            cache: cache.clone(),
+        }),
+        None => builder,
+    };
+    let author_affinity = services.clustering.as_ref().and_then(|c| c.author_affinity());
+    let builder = match author_affinity {
+        Some(store) => builder.scorer(AuthorAffinityScorer::new(store.clone())),
+        None => builder,
+    };
+    let author_profiles = services.clustering.as_ref().and_then(|c| c.author_profiles());
+    let builder = match author_profiles {
+        Some(store) => builder.hydrator(AuthorProfileHydrator::new(store.clone())),
+        None => builder,
+    };
+    let builder = match services.exploration() {
+        Some((clustering, tracker)) => builder
+            .hydrator(ExplorationHydrator::new(clustering.clone(), extractor))
+            .selector(ExplorationSelector::new(params::RESULT_SIZE, tracker.config().quota)),
+        None => builder.selector(TopKSelector::new(params::RESULT_SIZE)),
+    };
+    // Scorers adjust the weighted score, which becomes the final score last
+    builder
+        .scorer(FinalScoreScorer)
+        .result_size(params::RESULT_SIZE)
+        .optimize_filters()
+        .stage_timeouts(
+            StageTimeouts::new()
+                .with_source(Duration::from_millis(params::SOURCE_TIMEOUT_MS))
+                .with_hydrator(Duration::from_millis(params::HYDRATOR_TIMEOUT_MS))
+                .with_filter(Duration::from_millis(params::FILTER_TIMEOUT_MS))
+                .with_scorer(Duration::from_millis(params::SCORER_TIMEOUT_MS)),
+        )
+        .fan_in(FanInPolicy::new(Duration::from_millis(params::SOURCE_SOFT_DEADLINE_MS)))
+        // Failed filters are skipped, except safety filters, whose fallback removes everything
+        .error_policies(ErrorPolicies::new().with_filter(ErrorPolicy::Fallback))
+        .retry_policies(
+            RetryPolicies::new()
+                .with_source(network_retry_policy())
+                .with_hydrator(network_retry_policy()),
+        )
+        .candidate_id(|candidate: &PostCandidate| candidate.tweet_id as u64)
+        .dedup(DedupStage::new(|candidate: &PostCandidate| candidate.tweet_id as u64))
+        .side_effect_executor(
+            SideEffectExecutorConfig::new()
+                .with_workers(params::SIDE_EFFECT_WORKERS)
+                .with_queue_capacity(params::SIDE_EFFECT_QUEUE_CAPACITY),
+        )
+        .metrics(prod_metrics())
+        .kill_switches(prod_kill_switches())
+}
+
+/// Stage metrics shared by every pipeline built from `prod_builder`
+pub fn prod_metrics() -> Arc<PipelineMetrics> {
+    static METRICS: OnceLock<Arc<PipelineMetrics>> = OnceLock::new();
+    METRICS
+        .get_or_init(|| Arc::new(PipelineMetrics::new("phoenix")))
+        .clone()
+}
+
+/// Content filter counts of the production pipelines, e.g. `toxicity_filtered`
+pub fn prod_filter_metrics() -> Arc<Metrics> {
+    static METRICS: OnceLock<Arc<Metrics>> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new).clone()
+}
+
+/// Kill switches of the production pipelines, flipped at `/admin/kill_switches`
+pub fn prod_kill_switches() -> Arc<KillSwitches> {
+    static SWITCHES: OnceLock<Arc<KillSwitches>> = OnceLock::new();
+    SWITCHES.get_or_init(|| Arc::new(KillSwitches::new())).clone()
+}
+
+fn network_retry_policy() -> RetryPolicy {
+    RetryPolicy::new()
+        .with_max_attempts(params::RETRY_MAX_ATTEMPTS)
+        .with_hedge(HedgePolicy::new(params::HEDGE_PERCENTILE))
+}
+
+/// Opens for viewers in the diversity boost rollout while the boost is enabled,
+/// as of the config in use, unless the request's feature overrides force it;
+/// wrap `DiversityBoostScorer` in `Gated` with it
+pub fn diversity_boost_gate(config: Arc<ConfigWatcher>) -> Gate<ScoredPostsQuery> {
+    Gate::new(DIVERSITY_BOOST_FEATURE, move |query: &ScoredPostsQuery| {
+        if let Some(forced) = query.feature_overrides.flag(DIVERSITY_BOOST_FEATURE) {
+            return forced;
+        }
+        let ctx = CohortContext::new(query.user_id as u64, query.country_code.as_str());
+        config.config().should_use_diversity_boost(&ctx)
+    })
+}
+
+/// Opens while `enable_geo_filter` is set in the config in use; wrap
+/// `GeoEligibilityFilter` in `Gated` with it. Withholding is a legal
+/// requirement, so feature overrides can't turn it off.
+pub fn geo_filter_gate(config: Arc<ConfigWatcher>) -> Gate<ScoredPostsQuery> {
+    Gate::new(GEO_FILTER_FEATURE, move |_: &ScoredPostsQuery| {
+        config.config().safety.enable_geo_filter
+    })
+}
+
+/// Opens for viewers assigned `variant` of `experiment`; wrap a filter or
+/// scorer in `Gated` with it to run it for that variant only
+pub fn experiment_gate(experiment: &'static str, variant: &'static str) -> Gate<ScoredPostsQuery> {
+    Gate::new(experiment, move |query: &ScoredPostsQuery| {
+        query.experiment(experiment) == Some(variant)
+    })
+}
+
+/// State the production pipelines share with the rest of the server, which
+/// reloads, edits and learns into it while they serve
+#[derive(Clone)]
+pub struct ProdServices {
+    /// The config reloaded on SIGHUP, at `/admin/config/reload` and as
+    /// schedules start and end
+    pub config: Arc<ConfigWatcher>,
+    /// The operator author lists edited at `/admin/authors`
+    pub author_lists: Arc<AuthorListStore>,
+    /// The personalization the events endpoints learn into, when enabled
+    pub clustering: Option<Arc<UserClusteringService>>,
+    /// Where the parent and quoted posts the safety filters check are looked up;
+    /// without one only the candidates themselves are checked
+    pub related_posts: Option<Arc<dyn RelatedPostProvider>>,
+    /// What each request id chain has been served so far
+    pub sessions: Arc<SessionStore>,
+    /// The viewer's following lists, kept fresh by `/api/follow_events`
+    pub following: Option<Arc<FollowingListCache>>,
+    /// Where the viewer's recent engagements are looked up to derive their
+    /// interest topics; without one they have none
+    pub engagement_history: Option<Arc<dyn EngagementHistoryProvider>>,
+    /// Embeddings of users and authors, for the similar authors source
+    pub embeddings: Option<Arc<EmbeddingStore>>,
+    /// Where the similar authors' recent posts are fetched from
+    pub author_posts: Option<Arc<dyn AuthorPostsProvider>>,
+    /// Who may see subscriber-only posts; by default the author's subscribers
+    /// and the author
+    pub entitlements: Arc<dyn EntitlementProvider>,
+}
+
+impl ProdServices {
+    /// Over `config`, with empty author lists and no personalization
+    pub fn new(config: Arc<ConfigWatcher>) -> Self {
+        Self {
+            config,
+            author_lists: Arc::new(AuthorListStore::new()),
+            clustering: None,
+            related_posts: None,
+            following: None,
+            engagement_history: None,
+            embeddings: None,
+            author_posts: None,
+            sessions: Arc::new(SessionStore::new(
+                params::MAX_SESSIONS,
+                Duration::from_secs(params::SESSION_IDLE_TIMEOUT_SECS),
+            )),
+            entitlements: Arc::new(SubscribedUsersEntitlementProvider),
+        }
+    }
+
+    pub fn with_author_lists(mut self, author_lists: Arc<AuthorListStore>) -> Self {
+        self.author_lists = author_lists;
+        self
+    }
+
+    pub fn with_clustering(mut self, clustering: Arc<UserClusteringService>) -> Self {
+        self.clustering = Some(clustering);
+        self
+    }
+
+    pub fn with_related_posts(mut self, provider: Arc<dyn RelatedPostProvider>) -> Self {
+        self.related_posts = Some(provider);
+        self
+    }
+
+    pub fn with_following(mut self, following: Arc<FollowingListCache>) -> Self {
+        self.following = Some(following);
+        self
+    }
+
+    pub fn with_engagement_history(mut self, history: Arc<dyn EngagementHistoryProvider>) -> Self {
+        self.engagement_history = Some(history);
+        self
+    }
+
+    pub fn with_sessions(mut self, sessions: Arc<SessionStore>) -> Self {
+        self.sessions = sessions;
+        self
+    }
+
+    /// Add recent posts by similar authors the viewer doesn't follow, looked up
+    /// in `embeddings` and fetched from `posts`
+    pub fn with_similar_authors(
+        mut self,
+        embeddings: Arc<EmbeddingStore>,
+        posts: Arc<dyn AuthorPostsProvider>,
+    ) -> Self {
+        self.embeddings = Some(embeddings);
+        self.author_posts = Some(posts);
+        self
+    }
+
+    pub fn with_entitlements(mut self, provider: Arc<dyn EntitlementProvider>) -> Self {
+        self.entitlements = provider;
+        self
+    }
+
+    /// The similar authors source, when embeddings and a posts provider are set
+    fn similar_authors(&self) -> Option<SimilarAuthorsSource> {
+        let embeddings = self.embeddings.as_ref()?;
+        let posts = self.author_posts.as_ref()?;
+        Some(SimilarAuthorsSource::new(embeddings.clone(), posts.clone()))
+    }
+
+    /// The personalization and its exploration tracker, when exploration is on
+    fn exploration(&self) -> Option<(&Arc<UserClusteringService>, &Arc<ExplorationTracker>)> {
+        let clustering = self.clustering.as_ref()?;
+        Some((clustering, clustering.exploration()?))
+    }
+}
+
+/// Create a production pipeline configuration, assigning and logging
+/// experiments, resolving locale overrides, recording each page in its session,
+/// remembering the exploratory posts
+/// served so engagement on them is learned from faster, and publishing served
+/// impressions to `IMPRESSIONS_SINK` and exporting served timelines to
+/// `TIMELINE_EXPORT_DIR` when they are set. Sticky experiments' variants are
+/// saved to `STICKY_BUCKETS_PATH` when it is set. Experiments and overrides
+/// follow the config `services` watches; sinks are opened with the config
+/// in use at startup.
+pub async fn prod(services: &ProdServices) -> PhoenixCandidatePipeline {
+    let config = services.config.config();
+    let impressions_sink = config.impressions.sink.clone();
+    let export = config.timeline_export.clone();
+    let sticky_buckets = match &config.features.sticky_buckets_path {
+        Some(path) => match StickyBuckets::load(path) {
+            Ok(buckets) => {
+                let buckets = Arc::new(buckets);
+                buckets.clone().spawn_saver(sticky_buckets::SAVE_INTERVAL);
+                buckets
+            },
+            Err(e) => {
+                log::warn!("Not keeping sticky buckets in {}: {}", path, e);
+                Arc::new(StickyBuckets::new())
+            },
+        },
+        None => Arc::new(StickyBuckets::new()),
+    };
+    let watcher = services.config.clone();
+    let mut builder = prod_builder(services)
+        .query_hydrator(
+            ExperimentsQueryHydrator::new(watcher.clone()).with_sticky_buckets(sticky_buckets),
+        )
+        .query_hydrator(LocaleParamsQueryHydrator::new(watcher))
+        .side_effect(ExperimentExposureSideEffect)
+        .side_effect(RecordSessionSideEffect {
+            store: services.sessions.clone(),
+        });
+    if let Some((_, tracker)) = services.exploration() {
+        builder = builder.side_effect(RecordExplorationSideEffect {
+            tracker: tracker.clone(),
+        });
+    }
+    if let Some(dir) = &export.dir {
+        match open_timeline_export_sink(dir, &export) {
+            Ok(sink) => builder = builder.side_effect(TimelineExportSideEffect { sink }),
+            Err(e) => log::warn!("Not exporting served timelines to {}: {}", dir, e),
+        }
+    }
+    if let Some(spec) = impressions_sink {
+        match open_impression_sink(&spec) {
+            Ok(sink) => builder = builder.side_effect(ServedImpressionsSideEffect { sink }),
+            Err(e) => log::warn!("Not publishing served impressions to {}: {}", spec, e),
+        }
+    }
+    builder.build().expect("the production pipeline is complete")
+}
+
+/// A feed composed of in-network, out-of-network and discovery slices, each
+/// ranked by the production pipeline limited to its posts. Discovery posts,
+/// by similar authors the viewer doesn't follow, only fill discovery slots.
+/// There is no trending pipeline in this build, and no discovery one without
+/// similar authors, so their slots are backfilled from the other slices.
+pub fn prod_composer(services: &ProdServices) -> FeedComposer<ScoredPostsQuery, PostCandidate> {
+    let pattern = params::FEED_SLOT_PATTERN
+        .parse()
+        .expect("FEED_SLOT_PATTERN is a valid slot pattern");
+    let composer = FeedComposer::new(pattern, params::RESULT_SIZE)
+        .slice("in_network", slice_pipeline(services, FeedSlice::InNetwork))
+        .slice("out_of_network", slice_pipeline(services, FeedSlice::OutOfNetwork));
+    let composer = match services.similar_authors() {
+        Some(_) => composer.slice("discovery", slice_pipeline(services, FeedSlice::Discovery)),
+        None => composer,
+    };
+    composer.candidate_id(|candidate: &PostCandidate| candidate.tweet_id as u64)
+}
+
+fn slice_pipeline(
+    services: &ProdServices,
+    slice: FeedSlice,
+) -> Arc<dyn CandidatePipeline<ScoredPostsQuery, PostCandidate>> {
+    let pipeline = prod_builder(services)
+        .filter(SliceFilter { slice })
+        .build()
+        .expect("the production pipeline is complete");
+    Arc::new(pipeline)
+}
+
+/// Interleaves the production ranking with `treatment`, usually a variant of
+/// `prod_builder`, crediting each served post to the arm that picked it
+pub fn prod_interleaver(
+    services: &ProdServices,
+    treatment: PipelineBuilder<ScoredPostsQuery, PostCandidate>,
+) -> Result<TeamDraftInterleaver<ScoredPostsQuery, PostCandidate>, String> {
+    Ok(TeamDraftInterleaver::new(
+        Arc::new(prod_builder(services).build()?),
+        Arc::new(treatment.build()?),
+        params::RESULT_SIZE,
+        |candidate: &PostCandidate| candidate.tweet_id as u64,
+    ))
+}
+
+#[derive(Clone, Copy)]
+enum FeedSlice {
+    InNetwork,
+    OutOfNetwork,
+    Discovery,
+}
+
+/// Keeps the candidates of one slice of a composed feed
+struct SliceFilter {
+    slice: FeedSlice,
+}
+
+impl SliceFilter {
+    fn holds(&self, candidate: &PostCandidate) -> bool {
+        let in_network = candidate.in_network.unwrap_or(false);
+        let discovery = candidate.discovery.unwrap_or(false);
+        match self.slice {
+            FeedSlice::InNetwork => in_network,
+            FeedSlice::OutOfNetwork => !in_network && !discovery,
+            FeedSlice::Discovery => discovery,
         }
     }
 }
 
+#[async_trait]
+impl Filter<ScoredPostsQuery, PostCandidate> for SliceFilter {
+    async fn filter(
+        &self,
+        _query: &ScoredPostsQuery,
+        candidates: Vec<PostCandidate>,
+    ) -> Result<FilterResult<PostCandidate>, PipelineError> {
+        let (kept, removed) = candidates.into_iter().partition(|c| self.holds(c));
+        Ok(FilterResult { kept, removed })
+    }
+}
+
 /// Simple top-K selector
 struct TopKSelector {
     k: usize,
@@ -69,45 +523,823 @@ impl Selector<ScoredPostsQuery, PostCandidate> for TopKSelector {
     }
 }
 
-#[async_trait]
-impl CandidatePipeline<ScoredPostsQuery, PostCandidate> for PhoenixCandidatePipeline {
-    fn query_hydrators(&self) -> &[Box<dyn QueryHydrator<ScoredPostsQuery>>] {
-        &self.query_hydrators
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{CohortSelector, Config};
+    use crate::experiments::{Experiment, ExperimentAssignments, FeatureOverrides, TREATMENT};
+    use crate::candidate_hydrators::related_post_hydrator::StaticRelatedPostProvider;
+    use crate::candidate_pipeline::candidate::{Entitlements, PhoenixScores, RelatedPost};
+    use crate::candidate_pipeline::query_features::{
+        FilterOverride, SafetyFilterKind, UserPreferences,
+    };
+    use crate::filters::author_list_filter::AuthorListKind;
+    use crate::personalization::author_affinity::AuthorAffinityStore;
+    use crate::personalization::author_profiles::AuthorProfileStore;
+    use crate::personalization::embedding_store::HnswConfig;
+    use crate::personalization::engagement_events::{EngagementEvent, EngagementEventType};
+    use crate::personalization::exploration::ExplorationConfig;
+    use crate::personalization::topic_muting::TopicMuteConfig;
+    use crate::personalization::user_clusters::ClusterProfile;
+    use crate::util::snowflake;
+    use candidate_pipeline::composition::SlotPattern;
+
+    /// Phoenix predicts a like with probability `favorite`, so `favorite` is
+    /// its weighted score under the default weights
+    fn liked(favorite: f64) -> PhoenixScores {
+        PhoenixScores {
+            favorite_score: Some(favorite),
+            ..Default::default()
+        }
     }
 
-    fn sources(&self) -> &[Box<dyn Source<ScoredPostsQuery, PostCandidate>>] {
-        &self.sources
+    #[test]
+    fn test_diversity_boost_gate_follows_flag_and_rollout() {
+        let mut config = Config::default();
+        let cohort = CohortSelector {
+            rollout_percent: Some(50),
+            ..Default::default()
+        };
+        config.features.cohorts.insert(DIVERSITY_BOOST_FEATURE.to_string(), cohort);
+        let query = |user_id| ScoredPostsQuery {
+            user_id,
+            ..Default::default()
+        };
+
+        let watcher = Arc::new(ConfigWatcher::new(config.clone()));
+        let gate = diversity_boost_gate(watcher.clone());
+        assert!(!gate.open(&query(9), "DiversityBoostScorer"));
+
+        // Reloads apply to the gate built before them
+        config.safety.enable_diversity_boost = true;
+        watcher.apply(Ok(config)).unwrap();
+        assert!(gate.open(&query(9), "DiversityBoostScorer"));
+        assert!(!gate.open(&query(12), "DiversityBoostScorer"));
+
+        // Forced either way for a single request
+        let forced = |user_id, variant| {
+            let overrides = format!("{}={}", DIVERSITY_BOOST_FEATURE, variant);
+            ScoredPostsQuery {
+                feature_overrides: Arc::new(FeatureOverrides::parse(&overrides).unwrap()),
+                ..query(user_id)
+            }
+        };
+        assert!(gate.open(&forced(12, "on"), "DiversityBoostScorer"));
+        assert!(!gate.open(&forced(9, "off"), "DiversityBoostScorer"));
+    }
+
+    #[test]
+    fn test_experiment_gate_opens_for_its_variant() {
+        let experiments = [Experiment::ab("bait_filter_v2")];
+        let query = |user_id: u64| ScoredPostsQuery {
+            experiments: Arc::new(ExperimentAssignments::assign(
+                &experiments,
+                &CohortContext::new(user_id, "US"),
+            )),
+            ..Default::default()
+        };
+        let gate = experiment_gate("bait_filter_v2", TREATMENT);
+        let opened: Vec<_> = (0..100).filter(|&id| gate.open(&query(id), "BaitFilterV2")).collect();
+        assert!(!opened.is_empty() && opened.len() < 100);
+        let treated = query(opened[0]);
+        assert!(gate.open(&treated, "BaitFilterV2"));
+        assert_eq!(treated.experiments.exposures().len(), 1);
+        assert!(!experiment_gate("other", TREATMENT).open(&treated, "Other"));
+    }
+
+    #[tokio::test]
+    async fn test_prod_follows_the_shared_config() {
+        let watcher = Arc::new(ConfigWatcher::new(Config::default()));
+        let pipeline = prod(&ProdServices::new(watcher.clone())).await;
+        let query = || ScoredPostsQuery {
+            user_id: 7,
+            country_code: "BR".to_string(),
+            request_id: "shared-config".to_string(),
+            ..Default::default()
+        };
+        let result = pipeline.execute(query()).await.unwrap();
+        assert_eq!(result.query.locale_params.overrides.spam_max_report_rate, None);
+
+        // A reload of the server's watcher reaches the pipeline built before it
+        let mut config = Config::default();
+        config.locale_overrides.countries.insert(
+            "BR".to_string(),
+            serde_json::from_str(r#"{"spam_max_report_rate": 0.01}"#).unwrap(),
+        );
+        watcher.apply(Ok(config)).unwrap();
+        let result = pipeline.execute(query()).await.unwrap();
+        assert_eq!(result.query.locale_params.overrides.spam_max_report_rate, Some(0.01));
+    }
+
+    #[tokio::test]
+    async fn test_prod_drops_authors_denied_at_runtime() {
+        let config = Arc::new(ConfigWatcher::new(Config::default()));
+        let author_lists = Arc::new(AuthorListStore::new());
+        let services = ProdServices::new(config).with_author_lists(author_lists.clone());
+        let pipeline = prod(&services).await;
+        async fn served(pipeline: &PhoenixCandidatePipeline) -> Vec<i64> {
+            let candidates = (1..=3)
+                .map(|id| PostCandidate {
+                    tweet_id: id,
+                    author_id: 100 + id as u64,
+                    score: Some(1.0),
+                    ..Default::default()
+                })
+                .collect();
+            let query = ScoredPostsQuery {
+                user_id: 7,
+                ..Default::default()
+            };
+            let result = pipeline.dry_run(query, candidates).await.result.unwrap();
+            result.selected_candidates.iter().map(|c| c.tweet_id).collect()
+        }
+        assert_eq!(served(&pipeline).await.len(), 3);
+
+        // Takedowns apply from the next request on
+        author_lists.insert(AuthorListKind::Deny, 102, None, None).unwrap();
+        assert!(!served(&pipeline).await.contains(&2));
+    }
+
+    #[tokio::test]
+    async fn test_prod_filters_in_the_viewers_modes() {
+        let mut config = Config::default();
+        config.safety.user_disableable_filters = vec![SafetyFilterKind::Spam];
+        let watcher = Arc::new(ConfigWatcher::new(config));
+        let pipeline = prod(&ProdServices::new(watcher.clone())).await;
+        async fn served(
+            pipeline: &PhoenixCandidatePipeline,
+            overrides: &[(SafetyFilterKind, FilterOverride)],
+        ) -> Vec<i64> {
+            let texts = ["Claim your free bitcoin", "You won't believe this", "Good morning"];
+            let candidates = texts
+                .iter()
+                .zip(1..)
+                .map(|(text, id)| PostCandidate {
+                    tweet_id: id,
+                    tweet_text: text.to_string(),
+                    ..Default::default()
+                })
+                .collect();
+            let query = ScoredPostsQuery {
+                user_id: 7,
+                user_preferences: Some(UserPreferences {
+                    filter_overrides: overrides.iter().copied().collect(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            let result = pipeline.dry_run(query, candidates).await.result.unwrap();
+            result.selected_candidates.iter().map(|c| c.tweet_id).collect()
+        }
+        assert_eq!(served(&pipeline, &[]).await, vec![3]);
+        let overrides = [
+            (SafetyFilterKind::Spam, FilterOverride::Disabled),
+            (SafetyFilterKind::EngagementBait, FilterOverride::Disabled),
+        ];
+        let mut ids = served(&pipeline, &overrides).await;
+        ids.sort();
+        // Only the filter the config lets viewers disable is off
+        assert_eq!(ids, vec![1, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_prod_counts_toxic_posts_filtered() {
+        let config = Arc::new(ConfigWatcher::new(Config::default()));
+        let pipeline = prod(&ProdServices::new(config)).await;
+        let candidates = ["kill yourself", "Good morning"]
+            .iter()
+            .zip(1..)
+            .map(|(text, id)| PostCandidate {
+                tweet_id: id,
+                tweet_text: text.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        let filtered =
+            || prod_filter_metrics().toxicity_filtered.load(std::sync::atomic::Ordering::Relaxed);
+        let before = filtered();
+
+        let result = pipeline.dry_run(ScoredPostsQuery::default(), candidates).await;
+        let selected = result.result.unwrap().selected_candidates;
+        assert_eq!(selected.iter().map(|c| c.tweet_id).collect::<Vec<_>>(), vec![2]);
+        assert!(filtered() > before);
+    }
+
+    #[tokio::test]
+    async fn test_prod_resolves_entitlements_with_the_services_provider() {
+        /// Entitles the viewer to every post of author 200
+        struct GrantedAuthor;
+
+        #[async_trait]
+        impl EntitlementProvider for GrantedAuthor {
+            async fn get_entitlements(
+                &self,
+                _query: &ScoredPostsQuery,
+                candidates: &[PostCandidate],
+            ) -> Result<Vec<Entitlements>, String> {
+                let entitlements = candidates
+                    .iter()
+                    .map(|c| Entitlements {
+                        subscriber_only: c.subscription_author_id.is_some(),
+                        viewer_entitled: c.subscription_author_id == Some(200),
+                    })
+                    .collect();
+                Ok(entitlements)
+            }
+        }
+        async fn served(services: &ProdServices) -> Vec<i64> {
+            let pipeline = prod(services).await;
+            let candidates = [7, 200, 300]
+                .into_iter()
+                .zip(1..)
+                .map(|(author_id, tweet_id)| PostCandidate {
+                    tweet_id,
+                    author_id,
+                    subscription_author_id: Some(author_id),
+                    ..Default::default()
+                })
+                .collect();
+            let query = ScoredPostsQuery {
+                user_id: 7,
+                ..Default::default()
+            };
+            let result = pipeline.dry_run(query, candidates).await.result.unwrap();
+            let mut ids: Vec<i64> = result.selected_candidates.iter().map(|c| c.tweet_id).collect();
+            ids.sort();
+            ids
+        }
+        let services = ProdServices::new(Arc::new(ConfigWatcher::new(Config::default())));
+
+        // By default only the viewer's own post is theirs to see
+        assert_eq!(served(&services).await, vec![1]);
+        let services = services.with_entitlements(Arc::new(GrantedAuthor));
+        assert_eq!(served(&services).await, vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test_prod_checks_the_quoted_post() {
+        let spam = RelatedPost {
+            tweet_id: 50,
+            text: "Claim your free bitcoin".to_string(),
+            ..Default::default()
+        };
+        let provider = StaticRelatedPostProvider {
+            posts: [(50, spam)].into_iter().collect(),
+        };
+        let config = Arc::new(ConfigWatcher::new(Config::default()));
+        let services = ProdServices::new(config).with_related_posts(Arc::new(provider));
+        let pipeline = prod(&services).await;
+        let candidates = vec![
+            PostCandidate {
+                tweet_id: 1,
+                quoted_tweet_id: Some(50),
+                ..Default::default()
+            },
+            PostCandidate {
+                tweet_id: 2,
+                in_reply_to_tweet_id: Some(50),
+                ..Default::default()
+            },
+        ];
+        let query = ScoredPostsQuery {
+            user_id: 7,
+            ..Default::default()
+        };
+
+        let result = pipeline.dry_run(query, candidates).await.result.unwrap();
+        let ids: Vec<i64> = result.selected_candidates.iter().map(|c| c.tweet_id).collect();
+        assert_eq!(ids, vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test_prod_drops_authors_reported_as_spam() {
+        let store = Arc::new(AuthorProfileStore::new(100));
+        let clustering = UserClusteringService::new(1).with_author_profiles(store.clone());
+        let config = Arc::new(ConfigWatcher::new(Config::default()));
+        let services = ProdServices::new(config).with_clustering(Arc::new(clustering));
+        let pipeline = prod(&services).await;
+        for user_id in 1..=3 {
+            store.record(&EngagementEvent {
+                user_id,
+                tweet_id: 10,
+                author_id: Some(101),
+                event_type: EngagementEventType::ReportSpam,
+                media_kind: None,
+                timestamp_ms: 0,
+                dwell_ms: None,
+                topics: Vec::new(),
+            });
+        }
+        let candidates = (1..=2)
+            .map(|id| PostCandidate {
+                tweet_id: id,
+                author_id: 100 + id as u64,
+                ..Default::default()
+            })
+            .collect();
+        let query = ScoredPostsQuery {
+            user_id: 7,
+            ..Default::default()
+        };
+
+        let result = pipeline.dry_run(query, candidates).await.result.unwrap();
+        let ids: Vec<i64> = result.selected_candidates.iter().map(|c| c.tweet_id).collect();
+        assert_eq!(ids, vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test_prod_reserves_slots_for_exploration() {
+        let config = Arc::new(ConfigWatcher::new(Config::default()));
+        let tracker = ExplorationTracker::new(ExplorationConfig::default());
+        let clustering = UserClusteringService::new(1).with_exploration(Arc::new(tracker));
+        let services = ProdServices::new(config).with_clustering(Arc::new(clustering));
+        let pipeline = prod(&services).await;
+        let size = params::RESULT_SIZE as i64;
+        let candidates = (1..=size + 5)
+            .map(|id| PostCandidate {
+                tweet_id: id,
+                phoenix_scores: liked((size + 6 - id) as f64),
+                ..Default::default()
+            })
+            .chain(std::iter::once(PostCandidate {
+                tweet_id: 0,
+                tweet_text: "Election night coverage".to_string(),
+                ..Default::default()
+            }))
+            .collect();
+        let query = ScoredPostsQuery {
+            user_id: 7,
+            ..Default::default()
+        };
+
+        let result = pipeline.dry_run(query, candidates).await.result.unwrap();
+        let selected = &result.selected_candidates;
+        assert_eq!(selected.len(), params::RESULT_SIZE);
+        assert_eq!(selected.last().map(|c| c.tweet_id), Some(0));
+        assert_eq!(selected.last().and_then(|c| c.exploratory), Some(true));
     }
 
-    fn hydrators(&self) -> &[Box<dyn Hydrator<ScoredPostsQuery, PostCandidate>>] {
-        &self.hydrators
+    #[tokio::test]
+    async fn test_prod_drops_withheld_posts_while_the_geo_filter_is_enabled() {
+        for enabled in [true, false] {
+            let mut config = Config::default();
+            config.safety.enable_geo_filter = enabled;
+            let services = ProdServices::new(Arc::new(ConfigWatcher::new(config)));
+            let pipeline = prod(&services).await;
+            let candidates = vec![PostCandidate {
+                tweet_id: 1,
+                withheld_countries: vec!["DE".to_string()],
+                ..Default::default()
+            }];
+            let query = ScoredPostsQuery {
+                user_id: 7,
+                country_code: "DE".to_string(),
+                ..Default::default()
+            };
+
+            let result = pipeline.dry_run(query, candidates).await.result.unwrap();
+            assert_eq!(result.selected_candidates.is_empty(), enabled);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prod_downranks_posts_in_other_languages() {
+        let mut config = Config::default();
+        config.safety.language_mismatch_penalty = 0.5;
+        let services = ProdServices::new(Arc::new(ConfigWatcher::new(config)));
+        let pipeline = prod(&services).await;
+        let candidate = |tweet_id, language: &str, favorite| PostCandidate {
+            tweet_id,
+            language_code: Some(language.to_string()),
+            phoenix_scores: liked(favorite),
+            ..Default::default()
+        };
+        let candidates = vec![candidate(1, "ja", 1.5), candidate(2, "en", 1.0)];
+        let query = ScoredPostsQuery {
+            user_id: 7,
+            language_code: "en-US".to_string(),
+            ..Default::default()
+        };
+
+        let result = pipeline.dry_run(query, candidates).await.result.unwrap();
+        let ids: Vec<i64> = result.selected_candidates.iter().map(|c| c.tweet_id).collect();
+        assert_eq!(ids, vec![2, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_kill_switches_cannot_turn_the_geo_filter_off() {
+        let mut config = Config::default();
+        config.safety.enable_geo_filter = true;
+        let services = ProdServices::new(Arc::new(ConfigWatcher::new(config)));
+        let switches = Arc::new(KillSwitches::new());
+        switches.kill("GeoEligibilityFilter", "");
+        let pipeline = prod_builder(&services).kill_switches(switches).build().unwrap();
+        let candidates = vec![PostCandidate {
+            tweet_id: 1,
+            withheld_countries: vec!["DE".to_string()],
+            ..Default::default()
+        }];
+        let query = ScoredPostsQuery {
+            user_id: 7,
+            country_code: "DE".to_string(),
+            ..Default::default()
+        };
+
+        let result = pipeline.dry_run(query, candidates).await.result.unwrap();
+        assert!(result.selected_candidates.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_prod_diversifies_later_pages_of_a_session() {
+        let services = ProdServices::new(Arc::new(ConfigWatcher::new(Config::default())));
+        let pipeline = prod(&services).await;
+        let candidate = |tweet_id, author_id, favorite| PostCandidate {
+            tweet_id,
+            author_id,
+            phoenix_scores: liked(favorite),
+            ..Default::default()
+        };
+        let first = ScoredPostsQuery {
+            user_id: 7,
+            request_id: "r1".to_string(),
+            ..Default::default()
+        };
+        services.sessions.record_served(&first, &[candidate(1, 100, 1.0)]);
+
+        async fn served(pipeline: &PhoenixCandidatePipeline, query: ScoredPostsQuery) -> Vec<i64> {
+            let candidates = vec![
+                PostCandidate {
+                    tweet_id: 2,
+                    author_id: 100,
+                    phoenix_scores: liked(2.0),
+                    ..Default::default()
+                },
+                PostCandidate {
+                    tweet_id: 3,
+                    author_id: 200,
+                    phoenix_scores: liked(1.5),
+                    ..Default::default()
+                },
+            ];
+            let result = pipeline.dry_run(query, candidates).await.result.unwrap();
+            result.selected_candidates.iter().map(|c| c.tweet_id).collect()
+        }
+        let fresh = ScoredPostsQuery {
+            request_id: "r2".to_string(),
+            ..first.clone()
+        };
+        assert_eq!(served(&pipeline, fresh).await, vec![2, 3]);
+        let next = ScoredPostsQuery {
+            request_id: "r3".to_string(),
+            previous_request_id: Some("r1".to_string()),
+            ..first
+        };
+        assert_eq!(served(&pipeline, next).await, vec![3, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_prod_ranks_with_the_viewers_weight_preset() {
+        let clustering = Arc::new(UserClusteringService::new(1));
+        let video_watcher = ClusterProfile {
+            weight_preset: Some("video_heavy".to_string()),
+            ..Default::default()
+        };
+        clustering.assign_user_cluster(8, video_watcher).await;
+        let config = Arc::new(ConfigWatcher::new(Config::default()));
+        let services = ProdServices::new(config).with_clustering(clustering);
+        let pipeline = prod(&services).await;
+        async fn served(pipeline: &PhoenixCandidatePipeline, user_id: i64) -> Vec<i64> {
+            let candidates = vec![
+                PostCandidate {
+                    tweet_id: 1,
+                    phoenix_scores: liked(1.0),
+                    ..Default::default()
+                },
+                PostCandidate {
+                    tweet_id: 2,
+                    video_duration_ms: Some(params::MIN_VIDEO_DURATION_MS * 10),
+                    phoenix_scores: PhoenixScores {
+                        vqv_score: Some(1.0),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            ];
+            let query = ScoredPostsQuery {
+                user_id,
+                ..Default::default()
+            };
+            let result = pipeline.dry_run(query, candidates).await.result.unwrap();
+            result.selected_candidates.iter().map(|c| c.tweet_id).collect()
+        }
+
+        assert_eq!(served(&pipeline, 7).await, vec![1, 2]);
+        // Quality views count four times as much for the video watcher's cluster
+        assert_eq!(served(&pipeline, 8).await, vec![2, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_prod_favours_long_form_posts_in_the_viewers_evening() {
+        let clustering = Arc::new(UserClusteringService::new(1));
+        let config = Arc::new(ConfigWatcher::new(Config::default()));
+        let services = ProdServices::new(config).with_clustering(clustering);
+        let pipeline = prod(&services).await;
+        let now = chrono::Utc::now();
+        let (short, long) = (
+            snowflake::from_timestamp(now.timestamp_millis() - 1_000),
+            snowflake::from_timestamp(now.timestamp_millis() - 2_000),
+        );
+        let served = |utc_offset_minutes| {
+            let candidates = vec![
+                PostCandidate {
+                    tweet_id: short,
+                    tweet_text: "Good morning".to_string(),
+                    phoenix_scores: liked(1.1),
+                    ..Default::default()
+                },
+                PostCandidate {
+                    tweet_id: long,
+                    tweet_text: "word ".repeat(120),
+                    phoenix_scores: liked(1.0),
+                    ..Default::default()
+                },
+            ];
+            let query = ScoredPostsQuery {
+                user_id: 7,
+                utc_offset_minutes,
+                ..Default::default()
+            };
+            let run = pipeline.dry_run(query, candidates);
+            async move {
+                let result = run.await.result.unwrap();
+                result.selected_candidates.iter().map(|c| c.tweet_id).collect::<Vec<_>>()
+            }
+        };
+        // The offset that makes it 20:00 for the viewer
+        let hours = (20 - chrono::Timelike::hour(&now) as i32).rem_euclid(24);
+        let evening = if hours > 12 { hours - 24 } else { hours } * 60;
+
+        assert_eq!(served(None).await, vec![short, long]);
+        assert_eq!(served(Some(evening)).await, vec![long, short]);
     }
 
-    fn filters(&self) -> &[Box<dyn Filter<ScoredPostsQuery, PostCandidate>>] {
-        &self.filters
+    #[tokio::test]
+    async fn test_prod_favours_followed_authors_the_viewer_interacts_with() {
+        let affinity = Arc::new(AuthorAffinityStore::new(100, 10, Duration::from_secs(86_400)));
+        let clustering = UserClusteringService::new(1).with_author_affinity(affinity.clone());
+        let config = Arc::new(ConfigWatcher::new(Config::default()));
+        let services = ProdServices::new(config).with_clustering(Arc::new(clustering));
+        let pipeline = prod(&services).await;
+        for tweet_id in 1..=3 {
+            affinity.record(&EngagementEvent {
+                user_id: 7,
+                tweet_id,
+                author_id: Some(101),
+                event_type: EngagementEventType::Reply,
+                media_kind: None,
+                timestamp_ms: chrono::Utc::now().timestamp_millis(),
+                dwell_ms: None,
+                topics: Vec::new(),
+            });
+        }
+        let candidates = vec![
+            PostCandidate {
+                tweet_id: 1,
+                author_id: 102,
+                phoenix_scores: liked(1.1),
+                ..Default::default()
+            },
+            PostCandidate {
+                tweet_id: 2,
+                author_id: 101,
+                phoenix_scores: liked(1.0),
+                ..Default::default()
+            },
+        ];
+        let mut query = ScoredPostsQuery {
+            user_id: 7,
+            ..Default::default()
+        };
+        query.user_features.followed_user_ids = vec![101, 102];
+
+        let result = pipeline.dry_run(query, candidates).await.result.unwrap();
+        let ids: Vec<i64> = result.selected_candidates.iter().map(|c| c.tweet_id).collect();
+        assert_eq!(ids, vec![2, 1]);
     }
 
-    fn scorers(&self) -> &[Box<dyn Scorer<ScoredPostsQuery, PostCandidate>>] {
-        &self.scorers
+    #[tokio::test]
+    async fn test_prod_drops_posts_on_topics_the_viewer_muted() {
+        let clustering = UserClusteringService::new(1).with_topic_muting(TopicMuteConfig {
+            threshold: 0.5,
+            ..Default::default()
+        });
+        let clustering = Arc::new(clustering);
+        let config = Arc::new(ConfigWatcher::new(Config::default()));
+        let services = ProdServices::new(config).with_clustering(clustering.clone());
+        let pipeline = prod(&services).await;
+        clustering
+            .record_event(&EngagementEvent {
+                user_id: 7,
+                tweet_id: 10,
+                author_id: None,
+                event_type: EngagementEventType::NotInterested,
+                media_kind: None,
+                timestamp_ms: chrono::Utc::now().timestamp_millis(),
+                dwell_ms: None,
+                topics: vec!["politics".to_string()],
+            })
+            .await;
+        let candidates = ["Election night coverage", "NBA playoffs preview"]
+            .iter()
+            .zip(1..)
+            .map(|(text, tweet_id)| PostCandidate {
+                tweet_id,
+                tweet_text: text.to_string(),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        let served = |user_id| {
+            let query = ScoredPostsQuery {
+                user_id,
+                ..Default::default()
+            };
+            let run = pipeline.dry_run(query, candidates.clone());
+            async move {
+                let selected = run.await.result.unwrap().selected_candidates;
+                let mut ids: Vec<i64> = selected.iter().map(|c| c.tweet_id).collect();
+                ids.sort();
+                ids
+            }
+        };
+
+        assert_eq!(served(8).await, vec![1, 2]);
+        assert_eq!(served(7).await, vec![2]);
     }
 
-    fn selector(&self) -> &dyn Selector<ScoredPostsQuery, PostCandidate> {
-        self.selector.as_ref()
+    #[tokio::test]
+    async fn test_prod_ranks_with_the_locale_weight_overrides() {
+        let mut config = Config::default();
+        config.locale_overrides.countries.insert(
+            "BR".to_string(),
+            serde_json::from_str(r#"{"weights": {"reply": 40.0}}"#).unwrap(),
+        );
+        let services = ProdServices::new(Arc::new(ConfigWatcher::new(config)));
+        let pipeline = prod(&services).await;
+        async fn served(pipeline: &PhoenixCandidatePipeline, country_code: &str) -> Vec<i64> {
+            let candidates = vec![
+                PostCandidate {
+                    tweet_id: 1,
+                    phoenix_scores: liked(1.0),
+                    ..Default::default()
+                },
+                PostCandidate {
+                    tweet_id: 2,
+                    phoenix_scores: PhoenixScores {
+                        reply_score: Some(0.03),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            ];
+            let query = ScoredPostsQuery {
+                user_id: 7,
+                country_code: country_code.to_string(),
+                ..Default::default()
+            };
+            let result = pipeline.dry_run(query, candidates).await.result.unwrap();
+            result.selected_candidates.iter().map(|c| c.tweet_id).collect()
+        }
+
+        assert_eq!(served(&pipeline, "US").await, vec![1, 2]);
+        assert_eq!(served(&pipeline, "BR").await, vec![2, 1]);
     }
 
-    fn post_selection_hydrators(&self) -> &[Box<dyn Hydrator<ScoredPostsQuery, PostCandidate>>] {
-        &self.post_selection_hydrators
+    #[tokio::test]
+    async fn test_prod_serves_boosted_posts_first_while_the_boost_is_on() {
+        for enabled in [false, true] {
+            let mut config = Config::default();
+            config.safety.enable_diversity_boost = enabled;
+            config.safety.diversity_boost_multiplier = 1.5;
+            let services = ProdServices::new(Arc::new(ConfigWatcher::new(config)));
+            let pipeline = prod(&services).await;
+            let candidates = vec![
+                PostCandidate {
+                    tweet_id: 1,
+                    phoenix_scores: liked(1.2),
+                    exploratory: Some(false),
+                    ..Default::default()
+                },
+                PostCandidate {
+                    tweet_id: 2,
+                    phoenix_scores: liked(1.0),
+                    exploratory: Some(true),
+                    ..Default::default()
+                },
+            ];
+            let query = ScoredPostsQuery {
+                user_id: 7,
+                ..Default::default()
+            };
+
+            let result = pipeline.dry_run(query, candidates).await.result.unwrap();
+            let ids: Vec<i64> = result.selected_candidates.iter().map(|c| c.tweet_id).collect();
+            assert_eq!(ids, if enabled { vec![2, 1] } else { vec![1, 2] });
+        }
     }
 
-    fn post_selection_filters(&self) -> &[Box<dyn Filter<ScoredPostsQuery, PostCandidate>>] {
-        &self.post_selection_filters
+    #[tokio::test]
+    async fn test_prod_boosts_posts_outside_the_viewers_interest_topics() {
+        struct SportsFan;
+
+        #[async_trait]
+        impl EngagementHistoryProvider for SportsFan {
+            async fn recent_engaged_texts(
+                &self,
+                _user_id: i64,
+                _limit: usize,
+            ) -> Result<Vec<String>, String> {
+                Ok(vec!["Great NBA game tonight".to_string()])
+            }
+        }
+        let mut config = Config::default();
+        config.safety.enable_diversity_boost = true;
+        config.safety.diversity_boost_multiplier = 1.5;
+        let services = ProdServices::new(Arc::new(ConfigWatcher::new(config)));
+        async fn served(services: &ProdServices) -> Vec<i64> {
+            let pipeline = prod(services).await;
+            let candidates = vec![
+                PostCandidate {
+                    tweet_id: 1,
+                    tweet_text: "NBA playoffs preview".to_string(),
+                    phoenix_scores: liked(1.2),
+                    ..Default::default()
+                },
+                PostCandidate {
+                    tweet_id: 2,
+                    tweet_text: "Election night coverage".to_string(),
+                    phoenix_scores: liked(1.0),
+                    ..Default::default()
+                },
+            ];
+            let query = ScoredPostsQuery {
+                user_id: 7,
+                ..Default::default()
+            };
+            let result = pipeline.dry_run(query, candidates).await.result.unwrap();
+            result.selected_candidates.iter().map(|c| c.tweet_id).collect()
+        }
+
+        // Without personalization, only the history tells what is outside the bubble
+        assert_eq!(served(&services).await, vec![1, 2]);
+        let services = services.with_engagement_history(Arc::new(SportsFan));
+        assert_eq!(served(&services).await, vec![2, 1]);
     }
 
-    fn side_effects(&self) -> Arc<Vec<Box<dyn SideEffect<ScoredPostsQuery, PostCandidate>>>> {
-        Arc::clone(&self.side_effects)
+    #[tokio::test]
+    async fn test_prod_composer_serves_similar_authors_in_discovery_slots() {
+        struct OnePostPerAuthor;
+
+        #[async_trait]
+        impl AuthorPostsProvider for OnePostPerAuthor {
+            async fn recent_posts(&self, author_ids: &[u64]) -> Result<Vec<PostCandidate>, String> {
+                Ok(author_ids
+                    .iter()
+                    .map(|author_id| PostCandidate {
+                        tweet_id: *author_id as i64 * 100,
+                        author_id: *author_id,
+                        ..Default::default()
+                    })
+                    .collect())
+            }
+        }
+        let mut embeddings = EmbeddingStore::new(2, HnswConfig::default());
+        embeddings.insert_user(7, vec![1.0, 0.0]).unwrap();
+        embeddings.insert_author(10, &[1.0, 0.1]).unwrap();
+        embeddings.insert_author(20, &[1.0, 0.2]).unwrap();
+        let config = Arc::new(ConfigWatcher::new(Config::default()));
+        let services = ProdServices::new(config)
+            .with_similar_authors(Arc::new(embeddings), Arc::new(OnePostPerAuthor));
+        let mut query = ScoredPostsQuery {
+            user_id: 7,
+            ..Default::default()
+        };
+        query.user_features.followed_user_ids = vec![10];
+
+        let feed = prod_composer(&services).compose(query.clone()).await;
+        let served: Vec<(&str, i64)> =
+            feed.items.iter().map(|item| (item.slice.as_str(), item.candidate.tweet_id)).collect();
+        assert_eq!(served, vec![("discovery", 2000)]);
+
+        // The ranked timeline takes them in too
+        let result = prod(&services).await.execute(query).await.unwrap();
+        let ids: Vec<i64> = result.selected_candidates.iter().map(|c| c.tweet_id).collect();
+        assert_eq!(ids, vec![2000]);
     }
 
-    fn result_size(&self) -> usize {
-        params::RESULT_SIZE
+    #[test]
+    fn test_feed_slot_pattern_is_valid() {
+        let pattern: SlotPattern = params::FEED_SLOT_PATTERN.parse().unwrap();
+        let slices: Vec<&str> = pattern.slices().collect();
+        assert_eq!(slices, vec!["in_network", "out_of_network", "discovery", "trending"]);
     }
 }
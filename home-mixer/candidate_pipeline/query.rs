@@ -1,11 +1,15 @@
 //! Scored posts query types
 
-use crate::candidate_pipeline::query_features::UserFeatures;
+use crate::candidate_pipeline::query_features::{SafetyFilterKind, UserFeatures, UserPreferences};
+use crate::config::{FilterMode, LocaleParams, SafetyConfig, SafetyFilterModes};
+use crate::experiments::{ExperimentAssignments, FeatureOverrides};
 use crate::proto::{
     GetTwitterContextViewer, ImpressionBloomFilterEntry, TwitterContextViewer, UserActionSequence,
 };
 use crate::util::request_util::generate_request_id;
 use candidate_pipeline::candidate_pipeline::HasRequestId;
+use std::collections::HashSet;
+use std::sync::Arc;
 
 
 #[derive(Clone, Default, Debug)]
@@ -21,6 +25,29 @@ pub struct ScoredPostsQuery {
     pub bloom_filter_entries: Vec<ImpressionBloomFilterEntry>,
     pub user_action_sequence: Option<UserActionSequence>,
     pub user_features: UserFeatures,
+    pub user_preferences: Option<UserPreferences>,
+    /// Per-request safety filter modes, populated by `FilterOverridesQueryHydrator`
+    pub safety_filter_modes: Option<SafetyFilterModes>,
+    /// Topics the viewer engages with most, populated by `UserInterestTopicsQueryHydrator`
+    pub user_interest_topics: Option<HashSet<String>>,
+    /// Weight set of the viewer's cluster, populated by `WeightPresetQueryHydrator`
+    pub weight_preset: Option<String>,
+    /// Viewer's UTC offset in minutes, from the client timezone / locale
+    pub utc_offset_minutes: Option<i32>,
+    /// `request_id` of the page this request continues; pages of one chain are
+    /// diversified against each other
+    pub previous_request_id: Option<String>,
+    /// Experiment variants of the viewer, populated by `ExperimentsQueryHydrator`
+    /// and shared by clones, so exposures recorded on any are seen by all
+    pub experiments: Arc<ExperimentAssignments>,
+    /// Variants a trusted caller forced for this request, applied to
+    /// `experiments` and to flags gated on their name
+    pub feature_overrides: Arc<FeatureOverrides>,
+    /// Weights and thresholds overridden for the viewer's country and
+    /// language, populated by `LocaleParamsQueryHydrator`
+    pub locale_params: Arc<LocaleParams>,
+    /// Record candidate provenance for this request
+    pub debug: bool,
     pub request_id: String,
 }
 
@@ -50,9 +77,36 @@ impl ScoredPostsQuery {
             bloom_filter_entries,
             user_action_sequence: None,
             user_features: UserFeatures::default(),
+            user_preferences: None,
+            safety_filter_modes: None,
+            user_interest_topics: None,
+            weight_preset: None,
+            utc_offset_minutes: None,
+            previous_request_id: None,
+            experiments: Arc::default(),
+            feature_overrides: Arc::default(),
+            locale_params: Arc::default(),
+            debug: false,
             request_id,
         }
     }
+
+    /// Effective mode of a safety filter for this request. The production pipelines
+    /// always hydrate the modes from the config in use; queries run outside them
+    /// fall back to the default policy applied to the viewer's preferences.
+    pub fn filter_mode(&self, kind: SafetyFilterKind) -> FilterMode {
+        match &self.safety_filter_modes {
+            Some(modes) => modes.get(kind),
+            None => SafetyConfig::default().resolve_mode(kind, self.user_preferences.as_ref()),
+        }
+    }
+
+    /// The viewer's variant of `experiment`, or None when they aren't in it.
+    /// Counts as an exposure, so only read it where the variant changes
+    /// behavior.
+    pub fn experiment(&self, experiment: &str) -> Option<&str> {
+        self.experiments.variant(experiment)
+    }
 }
 
 impl GetTwitterContextViewer for ScoredPostsQuery {
@@ -70,4 +124,12 @@ impl HasRequestId for ScoredPostsQuery {
     fn request_id(&self) -> &str {
         &self.request_id
     }
+
+    fn user_id(&self) -> Option<u64> {
+        Some(self.user_id as u64)
+    }
+
+    fn debug(&self) -> bool {
+        self.debug
+    }
 }
@@ -1,4 +1,7 @@
+use crate::personalization::user_clusters::ContentType;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
 #[serde(rename_all = "camelCase")]
@@ -8,4 +11,46 @@ pub struct UserFeatures {
     pub muted_user_ids: Vec<i64>,
     pub followed_user_ids: Vec<i64>,
     pub subscribed_user_ids: Vec<i64>,
+}
+
+/// Viewer-controlled content preferences
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct UserPreferences {
+    pub show_sensitive_media: Option<bool>,
+    #[serde(default)]
+    pub toxicity_sensitivity: ToxicitySensitivity,
+    /// Per-filter overrides; only honored where `SafetyConfig` policy allows
+    #[serde(default)]
+    pub filter_overrides: HashMap<SafetyFilterKind, FilterOverride>,
+    /// Interests picked during onboarding; seeds cold-start personalization
+    #[serde(default)]
+    pub declared_interests: Vec<ContentType>,
+}
+
+/// Safety filters a viewer may override
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum SafetyFilterKind {
+    Nsfw,
+    Spam,
+    EngagementBait,
+    Toxicity,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FilterOverride {
+    Disabled,
+    Strict,
+}
+
+/// How aggressively toxic content is hidden from the viewer
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ToxicitySensitivity {
+    Off,
+    #[default]
+    Standard,
+    Strict,
 }
\ No newline at end of file
@@ -0,0 +1,190 @@
+//! Shared candidates and score overlays
+//!
+//! Scorers read a request's candidates but only write a handful of score
+//! fields. Passing `PostCandidate`s by value copies every text, ancestor list
+//! and related post whenever a batch is assembled, split or served from a
+//! cache. `SharedCandidates` keeps candidates behind `Arc`s, so batching only
+//! copies pointers, and scorers write into a `ScoreOverlay` holding one column
+//! per score field instead of into candidate copies. A scorer hands the overlay
+//! back to the pipeline as score-only candidates via `ScoreOverlay::into_scored`.
+
+use crate::candidate_pipeline::candidate::{PhoenixScores, PostCandidate};
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Candidates shared behind `Arc`s; clones and slices copy pointers only
+#[derive(Clone, Debug, Default)]
+pub struct SharedCandidates {
+    posts: Vec<Arc<PostCandidate>>,
+}
+
+impl SharedCandidates {
+    /// Share copies of `candidates`, the only deep copy made
+    pub fn from_slice(candidates: &[PostCandidate]) -> Self {
+        candidates.iter().cloned().map(Arc::new).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.posts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.posts.is_empty()
+    }
+
+    pub fn posts(&self) -> &[Arc<PostCandidate>] {
+        &self.posts
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PostCandidate> {
+        self.posts.iter().map(|post| post.as_ref())
+    }
+
+    pub fn slice(&self, range: Range<usize>) -> Self {
+        self.posts[range].iter().cloned().collect()
+    }
+
+    /// Append the candidates of `other`, sharing them
+    pub fn extend_from(&mut self, other: &SharedCandidates) {
+        self.posts.extend(other.posts.iter().cloned());
+    }
+}
+
+impl From<Vec<PostCandidate>> for SharedCandidates {
+    fn from(candidates: Vec<PostCandidate>) -> Self {
+        candidates.into_iter().map(Arc::new).collect()
+    }
+}
+
+impl FromIterator<Arc<PostCandidate>> for SharedCandidates {
+    fn from_iter<I: IntoIterator<Item = Arc<PostCandidate>>>(iter: I) -> Self {
+        Self {
+            posts: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// Fields written by Phoenix scoring, one column per field, parallel to the
+/// candidates they were computed for
+#[derive(Clone, Debug, Default)]
+pub struct ScoreOverlay {
+    pub phoenix_scores: Vec<PhoenixScores>,
+    pub prediction_request_ids: Vec<Option<u64>>,
+    pub last_scored_at_ms: Vec<Option<u64>>,
+}
+
+impl ScoreOverlay {
+    /// An overlay of `len` unscored candidates
+    pub fn with_len(len: usize) -> Self {
+        Self {
+            phoenix_scores: vec![PhoenixScores::default(); len],
+            prediction_request_ids: vec![None; len],
+            last_scored_at_ms: vec![None; len],
+        }
+    }
+
+    /// The scores `candidates` already carry
+    pub fn of(candidates: impl ExactSizeIterator<Item = impl AsRef<PostCandidate>>) -> Self {
+        let mut overlay = Self::default();
+        overlay.phoenix_scores.reserve(candidates.len());
+        for candidate in candidates {
+            let candidate = candidate.as_ref();
+            overlay.phoenix_scores.push(candidate.phoenix_scores.clone());
+            overlay.prediction_request_ids.push(candidate.prediction_request_id);
+            overlay.last_scored_at_ms.push(candidate.last_scored_at_ms);
+        }
+        overlay
+    }
+
+    pub fn len(&self) -> usize {
+        self.phoenix_scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.phoenix_scores.is_empty()
+    }
+
+    /// Copy the scores at `from` of `other` into `index`
+    pub fn copy_from(&mut self, index: usize, other: &ScoreOverlay, from: usize) {
+        self.phoenix_scores[index] = other.phoenix_scores[from].clone();
+        self.prediction_request_ids[index] = other.prediction_request_ids[from];
+        self.last_scored_at_ms[index] = other.last_scored_at_ms[from];
+    }
+
+    pub fn slice(&self, range: Range<usize>) -> Self {
+        Self {
+            phoenix_scores: self.phoenix_scores[range.clone()].to_vec(),
+            prediction_request_ids: self.prediction_request_ids[range.clone()].to_vec(),
+            last_scored_at_ms: self.last_scored_at_ms[range].to_vec(),
+        }
+    }
+
+    /// Score-only candidates, for a `Scorer` to return; their other fields are
+    /// defaults, which own no allocations
+    pub fn into_scored(self) -> Vec<PostCandidate> {
+        self.phoenix_scores
+            .into_iter()
+            .zip(self.prediction_request_ids)
+            .zip(self.last_scored_at_ms)
+            .map(|((phoenix_scores, prediction_request_id), last_scored_at_ms)| {
+                PostCandidate {
+                    phoenix_scores,
+                    prediction_request_id,
+                    last_scored_at_ms,
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+}
+
+impl AsRef<PostCandidate> for PostCandidate {
+    fn as_ref(&self) -> &PostCandidate {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(tweet_id: i64, favorite_score: f64) -> PostCandidate {
+        let mut candidate = PostCandidate {
+            tweet_id,
+            tweet_text: "a post long enough to be worth sharing".to_string(),
+            ancestors: vec![1, 2, 3],
+            ..Default::default()
+        };
+        candidate.phoenix_scores.favorite_score = Some(favorite_score);
+        candidate
+    }
+
+    #[test]
+    fn test_batches_share_candidates_instead_of_copying_them() {
+        let first = SharedCandidates::from(vec![candidate(1, 0.1), candidate(2, 0.2)]);
+        let second = SharedCandidates::from(vec![candidate(3, 0.3)]);
+        let mut batch = first.clone();
+        batch.extend_from(&second);
+
+        assert_eq!(batch.len(), 3);
+        assert!(Arc::ptr_eq(&batch.posts()[2], &second.posts()[0]));
+        let split = batch.slice(1..3);
+        assert!(Arc::ptr_eq(&split.posts()[0], &first.posts()[1]));
+        let ids: Vec<i64> = split.iter().map(|c| c.tweet_id).collect();
+        assert_eq!(ids, vec![2, 3]);
+    }
+
+    #[test]
+    fn test_overlay_columns_become_score_only_candidates() {
+        let candidates = SharedCandidates::from(vec![candidate(1, 0.1), candidate(2, 0.2)]);
+        let existing = ScoreOverlay::of(candidates.posts().iter());
+        let mut overlay = ScoreOverlay::with_len(2);
+        overlay.copy_from(1, &existing, 1);
+        overlay.prediction_request_ids[0] = Some(7);
+
+        let scored = overlay.slice(0..2).into_scored();
+        assert_eq!(scored[0].prediction_request_id, Some(7));
+        assert_eq!(scored[1].phoenix_scores.favorite_score, Some(0.2));
+        assert!(scored[1].tweet_text.is_empty() && scored[1].ancestors.is_empty());
+    }
+}
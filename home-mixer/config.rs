@@ -1,26 +1,56 @@
 // Copyright 2026 X.AI Corp.
 // Production-ready configuration and metrics system
 
+use crate::candidate_pipeline::query_features::{
+    FilterOverride, SafetyFilterKind, ToxicitySensitivity, UserPreferences,
+};
+use crate::experiments::{self, Experiment, ExperimentAssignments};
+use crate::personalization::exploration::ExplorationConfig;
+use crate::personalization::privacy::PrivacyConfig;
+use crate::personalization::user_clusters::ClusterProfile;
+use crate::scorers::weight_sets::WeightSet;
+use crate::util::config_schedule::{self, ConfigSchedule};
+use crate::util::logging::LogFormat;
+use hdrhistogram::Histogram;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
 // ============================================================
 // CONFIGURATION
 // ============================================================
 
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
 pub struct Config {
     pub caching: CachingConfig,
     pub batching: BatchingConfig,
     pub personalization: PersonalizationConfig,
     pub safety: SafetyConfig,
+    pub following: FollowingConfig,
+    pub impressions: ImpressionsConfig,
     pub features: FeatureFlags,
     pub metrics: MetricsConfig,
+    pub logging: LoggingConfig,
+    pub profiling: ProfilingConfig,
+    pub admin: AdminConfig,
+    pub auth: AuthConfig,
+    pub alerting: AlertingConfig,
+    pub debug_traces: DebugTracesConfig,
+    pub decision_sampling: DecisionSamplingConfig,
+    pub timeline_export: TimelineExportConfig,
+    pub locale_overrides: LocaleOverrides,
+    pub kill_switches: KillSwitchConfig,
+    pub schedules: Vec<ConfigSchedule>,
+    /// Schedules whose overrides this config was read with
+    #[serde(default)]
+    pub active_schedules: Vec<String>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct CachingConfig {
     pub enabled: bool,
     pub user_cache_size: usize,
@@ -30,7 +60,7 @@ pub struct CachingConfig {
     pub enable_cache_warming: bool,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct BatchingConfig {
     pub enabled: bool,
     pub max_batch_size: usize,
@@ -38,15 +68,95 @@ pub struct BatchingConfig {
     pub max_concurrent_batches: usize,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct PersonalizationConfig {
     pub enabled: bool,
     pub num_clusters: usize,
     pub enable_auto_refresh: bool,
     pub refresh_interval_hours: u64,
+    /// Upper bound on the random delay added to each scheduled refresh
+    pub refresh_jitter_secs: u64,
+    /// Relative distance improvement needed to move a user to another cluster on refresh
+    pub cluster_switch_margin: f64,
+    /// `WeightSet` name per cluster id (e.g. `{"3": "video_heavy"}`)
+    pub cluster_weight_presets: HashMap<usize, String>,
+    /// File path or http(s) URL serving `UserFeatures` for cluster refreshes
+    pub features_source: Option<String>,
+    /// http(s) URL serving `{url}/{user_id}` as the texts of the posts the user
+    /// last engaged with, from which their interest topics are derived; used
+    /// whether or not personalization is enabled
+    pub engagement_history_source: Option<String>,
+    /// Directory of `users.npy`/`users.ids` and `authors.npy`/`authors.ids`
+    /// embeddings, from which similar authors are found for the discovery slice
+    pub embeddings_dir: Option<String>,
+    /// http(s) URL of the Thunder HTTP port the similar authors' posts are
+    /// fetched from; no similar authors are served unless both are set
+    pub author_posts_source: Option<String>,
+    /// Directory for persisted cluster assignments, or `sled://{dir}` for the sled
+    /// store; in-memory only when unset
+    pub cluster_store_path: Option<String>,
+    pub snapshot_interval_secs: u64,
+    /// EWMA smoothing factor applied per ingested engagement event
+    pub event_ewma_alpha: f64,
+    /// Engagement events before a cold-start profile joins a learned cluster
+    pub cold_start_graduation_events: u64,
+    /// Viewers tracked by the author affinity store (least recently used evicted)
+    pub affinity_max_users: u64,
+    /// Authors kept per viewer; the weakest affinities are evicted first
+    pub affinity_max_authors_per_user: usize,
+    pub affinity_half_life_days: f64,
+    /// Authors tracked by the author quality profile store (least recently used evicted)
+    pub author_profile_max_authors: u64,
+    /// Decayed "not interested" count at which a topic is muted for the user
+    pub topic_mute_threshold: f64,
+    pub topic_mute_half_life_days: f64,
+    /// Differential-privacy budget for shared aggregate stats and exports
+    pub stats_epsilon: f64,
+    /// Clusters smaller than this are left out of shared aggregates
+    pub stats_min_cluster_size: usize,
+    /// Share of each timeline reserved for posts outside the user's learned interests
+    pub exploration_quota: f64,
+    /// Learning-rate factor for engagement on explored posts
+    pub exploration_learning_boost: f64,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+impl PersonalizationConfig {
+    pub fn privacy(&self) -> PrivacyConfig {
+        PrivacyConfig {
+            epsilon: self.stats_epsilon,
+            min_cluster_size: self.stats_min_cluster_size,
+        }
+    }
+    
+    pub fn exploration(&self) -> ExplorationConfig {
+        ExplorationConfig {
+            quota: self.exploration_quota,
+            learning_boost: self.exploration_learning_boost,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct FollowingConfig {
+    /// http(s) URL serving `{url}/{user_id}` following lists; no cache when unset
+    pub source: Option<String>,
+    /// How long a cached following list is served before it is fetched again
+    pub cache_ttl_secs: u64,
+    /// How long an expired list is kept to serve while the source is down
+    pub cache_stale_secs: u64,
+    /// Users whose lists are cached (least recently used evicted)
+    pub cache_max_users: u64,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct ImpressionsConfig {
+    /// Where served impressions are published: `kafka://{brokers}/{topic}` or a
+    /// file path; not published when unset
+    pub sink: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct SafetyConfig {
     pub enable_nsfw_filter: bool,
     pub nsfw_strict_mode: bool,
@@ -54,20 +164,314 @@ pub struct SafetyConfig {
     pub enable_engagement_bait_filter: bool,
     pub enable_diversity_boost: bool,
     pub diversity_boost_multiplier: f64,
+    /// File backing the operator author allow/deny lists (in-memory only when unset)
+    pub author_list_path: Option<String>,
+    /// Serve subscriber-only posts to non-subscribers as previews instead of dropping them
+    pub show_subscription_previews: bool,
+    /// Drop posts withheld in the viewer's country
+    pub enable_geo_filter: bool,
+    /// Score multiplier for posts not in the request language (1.0 disables downranking)
+    pub language_mismatch_penalty: f64,
+    pub enable_toxicity_filter: bool,
+    /// Filters viewers may turn off for themselves. Tightening is always allowed.
+    pub user_disableable_filters: Vec<SafetyFilterKind>,
+}
+
+/// Effective strength of a safety filter for one request
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilterMode {
+    Off,
+    #[default]
+    Standard,
+    Strict,
+}
+
+/// Resolved modes of every viewer-overridable safety filter
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SafetyFilterModes {
+    pub nsfw: FilterMode,
+    pub spam: FilterMode,
+    pub engagement_bait: FilterMode,
+    pub toxicity: FilterMode,
+}
+
+impl SafetyFilterModes {
+    pub fn resolve(safety: &SafetyConfig, preferences: Option<&UserPreferences>) -> Self {
+        Self {
+            nsfw: safety.resolve_mode(SafetyFilterKind::Nsfw, preferences),
+            spam: safety.resolve_mode(SafetyFilterKind::Spam, preferences),
+            engagement_bait: safety.resolve_mode(SafetyFilterKind::EngagementBait, preferences),
+            toxicity: safety.resolve_mode(SafetyFilterKind::Toxicity, preferences),
+        }
+    }
+
+    pub fn get(&self, kind: SafetyFilterKind) -> FilterMode {
+        match kind {
+            SafetyFilterKind::Nsfw => self.nsfw,
+            SafetyFilterKind::Spam => self.spam,
+            SafetyFilterKind::EngagementBait => self.engagement_bait,
+            SafetyFilterKind::Toxicity => self.toxicity,
+        }
+    }
+}
+
+impl From<ToxicitySensitivity> for FilterMode {
+    fn from(sensitivity: ToxicitySensitivity) -> Self {
+        match sensitivity {
+            ToxicitySensitivity::Off => FilterMode::Off,
+            ToxicitySensitivity::Standard => FilterMode::Standard,
+            ToxicitySensitivity::Strict => FilterMode::Strict,
+        }
+    }
 }
 
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+/// Rollouts and experiments. Each `*_rollout_percent` is shorthand for a
+/// rollout experiment named after its feature, used unless `experiments`
+/// configures that name.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
 pub struct FeatureFlags {
     pub caching_rollout_percent: u8,
     pub batching_rollout_percent: u8,
     pub personalization_rollout_percent: u8,
+    /// Finer-grained rollout targeting per feature name (see the `*_FEATURE`
+    /// constants). A feature with a cohort is limited to it on top of its percent.
+    #[serde(default)]
+    pub cohorts: HashMap<String, CohortSelector>,
+    #[serde(default)]
+    pub experiments: Vec<Experiment>,
+    /// Where sticky experiments' variants are saved across restarts; kept in
+    /// memory only when unset
+    #[serde(default)]
+    pub sticky_buckets_path: Option<String>,
+    /// Bearer token callers present to force flags and experiment variants for
+    /// a single request; no request may force them when unset
+    #[serde(default, skip_serializing)]
+    pub override_token: Option<String>,
+}
+
+pub const CACHING_FEATURE: &str = "caching";
+pub const BATCHING_FEATURE: &str = "batching";
+pub const PERSONALIZATION_FEATURE: &str = "personalization";
+pub const DIVERSITY_BOOST_FEATURE: &str = "diversity_boost";
+pub const GEO_FILTER_FEATURE: &str = "geo_filter";
+
+/// What a rollout decision can be based on for one request
+#[derive(Clone, Debug, Default)]
+pub struct CohortContext {
+    pub user_id: u64,
+    pub country_code: String,
+    /// The user's learned cluster, when personalization has one
+    pub cluster_id: Option<usize>,
+    pub diversity_preference: Option<f64>,
+}
+
+impl CohortContext {
+    pub fn new(user_id: u64, country_code: impl Into<String>) -> Self {
+        Self {
+            user_id,
+            country_code: country_code.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_cluster(mut self, profile: &ClusterProfile) -> Self {
+        self.cluster_id = Some(profile.cluster_id);
+        self.diversity_preference = Some(profile.diversity_preference);
+        self
+    }
+}
+
+/// Rollout targeting that composes user-id buckets, cluster membership and country.
+/// Every condition that is set must hold; unset conditions match everyone.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct CohortSelector {
+    /// Share of users included, by a hash of their id with `salt`
+    pub rollout_percent: Option<u8>,
+    /// Mixed into the rollout hash; defaults to the feature or experiment
+    /// name, so each rollout picks its own users
+    pub salt: Option<String>,
+    /// Learned clusters included
+    pub cluster_ids: Vec<usize>,
+    /// Only users whose cluster wants at least this much variety
+    pub min_diversity_preference: Option<f64>,
+    /// ISO country codes included (case-insensitive)
+    pub countries: Vec<String>,
+}
+
+impl CohortSelector {
+    /// Whether `ctx` is in the cohort of the feature or experiment `name`
+    pub fn matches(&self, name: &str, ctx: &CohortContext) -> bool {
+        if let Some(percent) = self.rollout_percent {
+            if !is_in_rollout(self.salt.as_deref().unwrap_or(name), ctx.user_id, percent) {
+                return false;
+            }
+        }
+        if !self.cluster_ids.is_empty()
+            && !ctx.cluster_id.is_some_and(|id| self.cluster_ids.contains(&id))
+        {
+            return false;
+        }
+        if let Some(min) = self.min_diversity_preference {
+            if !ctx.diversity_preference.is_some_and(|d| d >= min) {
+                return false;
+            }
+        }
+        self.countries.is_empty()
+            || self
+                .countries
+                .iter()
+                .any(|c| c.eq_ignore_ascii_case(ctx.country_code.trim()))
+    }
+}
+
+/// Parses `FEATURE_COHORTS`, a JSON object of feature name to `CohortSelector`
+fn parse_cohorts(value: &str) -> HashMap<String, CohortSelector> {
+    serde_json::from_str(value).unwrap_or_else(|e| {
+        log::warn!("Ignoring invalid FEATURE_COHORTS: {}", e);
+        HashMap::new()
+    })
+}
+
+/// Weights and filter thresholds a locale may set instead of the global ones;
+/// unset ones keep the global value
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct ParamOverrides {
+    /// Scoring weights by `WeightSet` field name, e.g. `{"reply": 20.0}`
+    pub weights: BTreeMap<String, f64>,
+    /// Author report rate above which the spam filter removes posts; strict
+    /// mode uses the lower of this and its own
+    pub spam_max_report_rate: Option<f64>,
+    /// Toxicity score at which posts are removed; strict mode uses the lower
+    /// of this and its own
+    pub toxicity_threshold: Option<f64>,
+}
+
+impl ParamOverrides {
+    /// `base` with the overridden weights
+    pub fn weight_set(&self, base: &WeightSet) -> WeightSet {
+        let mut set = base.clone();
+        for (name, weight) in &self.weights {
+            set.set(name, *weight);
+        }
+        set
+    }
+
+    /// Layer `other` on top, recording `source` for each setting it sets
+    fn merge(
+        &mut self,
+        other: &ParamOverrides,
+        source: &str,
+        sources: &mut BTreeMap<String, String>,
+    ) {
+        for (name, weight) in &other.weights {
+            self.weights.insert(name.clone(), *weight);
+            sources.insert(format!("weights.{}", name), source.to_string());
+        }
+        if let Some(rate) = other.spam_max_report_rate {
+            self.spam_max_report_rate = Some(rate);
+            sources.insert("spam_max_report_rate".to_string(), source.to_string());
+        }
+        if let Some(threshold) = other.toxicity_threshold {
+            self.toxicity_threshold = Some(threshold);
+            sources.insert("toxicity_threshold".to_string(), source.to_string());
+        }
+    }
+
+    fn validate(&self, locale: &str, errors: &mut Vec<String>) {
+        let known = WeightSet::default();
+        let (_, negative) = known.by_action();
+        for (name, weight) in &self.weights {
+            let negative_action = negative.iter().any(|(action, _)| action == name);
+            if known.get(name).is_none() {
+                errors.push(format!("{} overrides unknown weight {}", locale, name));
+            } else if !weight.is_finite() {
+                errors.push(format!("{} weight {} must be finite", locale, name));
+            } else if (negative_action && *weight > 0.0) || (!negative_action && *weight < 0.0) {
+                errors.push(format!(
+                    "{} weight {} has the wrong sign for its action",
+                    locale, name
+                ));
+            }
+        }
+        let thresholds = [self.spam_max_report_rate, self.toxicity_threshold];
+        if !thresholds.iter().flatten().all(|t| (0.0..=1.0).contains(t)) {
+            errors.push(format!("{} thresholds must be in [0, 1]", locale));
+        }
+    }
+}
+
+/// Overrides by ISO country code and by language code, from
+/// `LOCALE_OVERRIDES`, e.g. `{"countries": {"BR": {"spam_max_report_rate": 0.01}}}`.
+/// A request's country overrides win over its language's.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct LocaleOverrides {
+    pub countries: BTreeMap<String, ParamOverrides>,
+    pub languages: BTreeMap<String, ParamOverrides>,
+}
+
+impl LocaleOverrides {
+    /// The overrides for a request from `country_code` in `language_code`
+    /// (both case-insensitive)
+    pub fn resolve(&self, country_code: &str, language_code: &str) -> LocaleParams {
+        let mut params = LocaleParams::default();
+        let layers = [
+            ("language", &self.languages, language_code),
+            ("country", &self.countries, country_code),
+        ];
+        for (kind, overrides, code) in layers {
+            let code = code.trim();
+            let found = overrides.iter().find(|(key, _)| key.eq_ignore_ascii_case(code));
+            if let Some((key, overrides)) = found.filter(|_| !code.is_empty()) {
+                let source = format!("{} {}", kind, key);
+                params.overrides.merge(overrides, &source, &mut params.sources);
+            }
+        }
+        params
+    }
+
+    fn validate(&self, errors: &mut Vec<String>) {
+        for (country, overrides) in &self.countries {
+            overrides.validate(&format!("country {}", country), errors);
+        }
+        for (language, overrides) in &self.languages {
+            overrides.validate(&format!("language {}", language), errors);
+        }
+    }
+}
+
+/// The overrides that apply to one request, and where each came from
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LocaleParams {
+    pub overrides: ParamOverrides,
+    /// Override source by setting, e.g. `"spam_max_report_rate": "country BR"`
+    pub sources: BTreeMap<String, String>,
+}
+
+/// Parses `CLUSTER_WEIGHT_PRESETS`, a JSON object of cluster id to `WeightSet` name
+fn parse_weight_presets(value: &str) -> HashMap<usize, String> {
+    serde_json::from_str(value).unwrap_or_else(|e| {
+        log::warn!("Ignoring invalid CLUSTER_WEIGHT_PRESETS: {}", e);
+        HashMap::new()
+    })
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct MetricsConfig {
     pub enabled: bool,
     pub port: u16,
     pub enable_tracing: bool,
+    /// Push metrics over OTLP as well as serving them on `/metrics`
+    pub enable_otlp_metrics: bool,
+    /// OTLP collector for traces and metrics; `OTEL_EXPORTER_OTLP_ENDPOINT` if unset
+    pub otlp_endpoint: Option<String>,
+    pub otlp_export_interval_secs: u64,
+    /// Where metrics are pushed besides `/metrics`: `prometheus` (nowhere) or
+    /// `dogstatsd://{host}:{port}`
+    pub sink: String,
+    pub sink_flush_interval_secs: u64,
 }
 
 impl Default for CachingConfig {
@@ -101,6 +505,27 @@ impl Default for PersonalizationConfig {
             num_clusters: 100,
             enable_auto_refresh: false,
             refresh_interval_hours: 24,
+            refresh_jitter_secs: 600,
+            cluster_switch_margin: 0.1,
+            cluster_weight_presets: HashMap::new(),
+            features_source: None,
+            engagement_history_source: None,
+            embeddings_dir: None,
+            author_posts_source: None,
+            cluster_store_path: None,
+            snapshot_interval_secs: 300,
+            event_ewma_alpha: 0.05,
+            cold_start_graduation_events: 20,
+            affinity_max_users: 1_000_000,
+            affinity_max_authors_per_user: 200,
+            affinity_half_life_days: 14.0,
+            author_profile_max_authors: 5_000_000,
+            topic_mute_threshold: 3.0,
+            topic_mute_half_life_days: 30.0,
+            stats_epsilon: 1.0,
+            stats_min_cluster_size: 20,
+            exploration_quota: 0.1,
+            exploration_learning_boost: 3.0,
         }
     }
 }
@@ -114,6 +539,255 @@ impl Default for SafetyConfig {
             enable_engagement_bait_filter: true,
             enable_diversity_boost: false,
             diversity_boost_multiplier: 1.3,
+            author_list_path: None,
+            show_subscription_previews: false,
+            enable_geo_filter: true,
+            language_mismatch_penalty: 1.0,
+            enable_toxicity_filter: true,
+            user_disableable_filters: default_user_disableable_filters(),
+        }
+    }
+}
+
+fn default_user_disableable_filters() -> Vec<SafetyFilterKind> {
+    vec![SafetyFilterKind::EngagementBait, SafetyFilterKind::Toxicity]
+}
+
+/// Parses a comma-separated list such as `engagementBait,toxicity`
+fn parse_filter_kinds(value: &str) -> Vec<SafetyFilterKind> {
+    value
+        .split(',')
+        .filter_map(|kind| serde_json::from_value(kind.trim().into()).ok())
+        .collect()
+}
+
+/// Parses a comma-separated list of component names such as `GeoFilter,RecencyScorer`
+fn parse_components(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+impl SafetyConfig {
+    /// Mode configured globally for `kind`, before any viewer override
+    pub fn base_mode(&self, kind: SafetyFilterKind) -> FilterMode {
+        let (enabled, strict) = match kind {
+            SafetyFilterKind::Nsfw => (self.enable_nsfw_filter, self.nsfw_strict_mode),
+            SafetyFilterKind::Spam => (self.enable_spam_filter, false),
+            SafetyFilterKind::EngagementBait => (self.enable_engagement_bait_filter, false),
+            SafetyFilterKind::Toxicity => (self.enable_toxicity_filter, false),
+        };
+        match (enabled, strict) {
+            (false, _) => FilterMode::Off,
+            (true, false) => FilterMode::Standard,
+            (true, true) => FilterMode::Strict,
+        }
+    }
+
+    /// Mode for `kind` after applying the viewer's preferences where policy allows.
+    /// Globally disabled filters stay off; viewers may always tighten, but may only
+    /// disable filters listed in `user_disableable_filters`.
+    pub fn resolve_mode(
+        &self,
+        kind: SafetyFilterKind,
+        preferences: Option<&UserPreferences>,
+    ) -> FilterMode {
+        let base = self.base_mode(kind);
+        if base == FilterMode::Off {
+            return FilterMode::Off;
+        }
+        let Some(preferences) = preferences else {
+            return base;
+        };
+
+        let requested = match preferences.filter_overrides.get(&kind) {
+            Some(FilterOverride::Disabled) => FilterMode::Off,
+            Some(FilterOverride::Strict) => FilterMode::Strict,
+            None if kind == SafetyFilterKind::Toxicity => preferences.toxicity_sensitivity.into(),
+            None => return base,
+        };
+        match requested {
+            FilterMode::Off if self.user_disableable_filters.contains(&kind) => FilterMode::Off,
+            FilterMode::Off => base,
+            FilterMode::Strict => FilterMode::Strict,
+            FilterMode::Standard => base,
+        }
+    }
+}
+
+impl Default for FollowingConfig {
+    fn default() -> Self {
+        Self {
+            source: None,
+            cache_ttl_secs: 300,
+            cache_stale_secs: 86_400,
+            cache_max_users: 1_000_000,
+        }
+    }
+}
+
+/// Health alerts posted to a webhook; off without one
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct AlertingConfig {
+    /// Where notifications are posted as JSON, e.g. a Slack incoming webhook
+    #[serde(skip_serializing)]
+    pub webhook_url: Option<String>,
+    pub interval_secs: u64,
+    /// Least time between two announcements that an alert fired
+    pub cooldown_secs: u64,
+    /// Most failed components per request
+    pub max_error_rate: f64,
+    /// Lowest hit rate of each cache
+    pub min_cache_hit_rate: f64,
+}
+
+impl Default for AlertingConfig {
+    fn default() -> Self {
+        Self {
+            webhook_url: None,
+            interval_secs: 30,
+            cooldown_secs: 15 * 60,
+            max_error_rate: 0.05,
+            min_cache_hit_rate: 0.5,
+        }
+    }
+}
+
+/// Provenance captured for the users support adds at `/admin/traces`
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct DebugTracesConfig {
+    /// Most traces kept, across users
+    pub capacity: usize,
+}
+
+impl Default for DebugTracesConfig {
+    fn default() -> Self {
+        Self { capacity: 100 }
+    }
+}
+
+/// Pipeline components switched off by name, see `/admin/kill_switches`
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct KillSwitchConfig {
+    /// Switched off at startup; the admin API can switch them back on until restart
+    pub components: Vec<String>,
+    /// Where switches flipped at the admin API are kept; in memory when unset
+    pub path: Option<String>,
+}
+
+/// Filter and scorer decisions of sampled requests, for offline analysis
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct DecisionSamplingConfig {
+    /// `kafka://{brokers}/{topic}` or a directory for Parquet files; off when unset
+    pub sink: Option<String>,
+    /// One request in this many is sampled
+    pub sample_every: u64,
+}
+
+impl Default for DecisionSamplingConfig {
+    fn default() -> Self {
+        Self {
+            sink: None,
+            sample_every: 1000,
+        }
+    }
+}
+
+/// Served timelines written as Parquet files for training and offline evaluation
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct TimelineExportConfig {
+    /// Directory the files are written to (with the `parquet` feature); off when unset
+    pub dir: Option<String>,
+    /// A file is written once its rows take about this many bytes uncompressed
+    pub max_file_bytes: u64,
+    /// A file is written once its first row is this old
+    pub max_file_age_secs: u64,
+    /// `s3://{bucket}/{prefix}` or `gs://{bucket}/{prefix}` written files are
+    /// uploaded to (with the `object-store` feature); kept locally when unset
+    pub upload_url: Option<String>,
+    /// Move uploaded files to `uploaded/` rather than deleting them
+    pub keep_uploaded: bool,
+}
+
+impl Default for TimelineExportConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            max_file_bytes: 128 * 1024 * 1024,
+            max_file_age_secs: 600,
+            upload_url: None,
+            keep_uploaded: false,
+        }
+    }
+}
+
+/// The `/debug/pprof` endpoints, served with the `profiling` feature
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct ProfilingConfig {
+    pub enabled: bool,
+}
+
+/// The operator endpoints: the author lists, the kill switches, per-user traces,
+/// support access to personalization profiles and `/debug/pprof`
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct AdminConfig {
+    /// Bearer token the endpoints require; they refuse every request without one
+    #[serde(skip_serializing)]
+    pub token: Option<String>,
+}
+
+/// Callers of the endpoints reading or writing a user's data, see `UserAuth`
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct AuthConfig {
+    /// Key user tokens are signed with; no user is accepted without one
+    #[serde(skip_serializing)]
+    pub user_token_secret: Option<String>,
+    /// Bearer token of backends acting for any user, e.g. the events pipeline
+    #[serde(skip_serializing)]
+    pub service_token: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct LoggingConfig {
+    pub format: LogFormat,
+    /// `RUST_LOG` style directives, e.g. `info,home_mixer::scorers=debug`
+    pub levels: String,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Text,
+            levels: "info".to_string(),
+        }
+    }
+}
+
+impl LoggingConfig {
+    /// Read on its own, so logging can start before the rest of the config
+    pub fn from_source(source: &ConfigSource) -> Self {
+        Self {
+            format: env_string(source, "LOG_FORMAT")
+                .map_or(LogFormat::Text, |v| LogFormat::parse(&v)),
+            levels: env_string(source, "RUST_LOG").unwrap_or_else(|| "info".to_string()),
+        }
+    }
+}
+
+impl MetricsConfig {
+    pub fn from_source(source: &ConfigSource) -> Self {
+        Self {
+            enabled: env_bool(source, "METRICS_ENABLED", true),
+            port: env_u16(source, "METRICS_PORT", 9090),
+            enable_tracing: env_bool(source, "ENABLE_TRACING", false),
+            enable_otlp_metrics: env_bool(source, "ENABLE_OTLP_METRICS", false),
+            otlp_endpoint: env_string(source, "OTLP_ENDPOINT"),
+            otlp_export_interval_secs: env_u64(source, "OTLP_EXPORT_INTERVAL_SECS", 60),
+            sink: env_string(source, "METRICS_SINK").unwrap_or_else(|| "prometheus".to_string()),
+            sink_flush_interval_secs: env_u64(source, "METRICS_SINK_FLUSH_INTERVAL_SECS", 10),
         }
     }
 }
@@ -124,106 +798,615 @@ impl Default for MetricsConfig {
             enabled: true,
             port: 9090,
             enable_tracing: false,
+            enable_otlp_metrics: false,
+            otlp_endpoint: None,
+            otlp_export_interval_secs: 60,
+            sink: "prometheus".to_string(),
+            sink_flush_interval_secs: 10,
         }
     }
 }
 
 impl Config {
+    /// The config `ConfigSource::load` reads, or the environment's alone when
+    /// `CONFIG_FILE` can't be read
     pub fn from_env() -> Self {
+        let source = ConfigSource::load().unwrap_or_else(|e| {
+            log::warn!("Ignoring config file: {}", e);
+            ConfigSource::default()
+        });
+        Self::from_source_at(&source, chrono::Utc::now())
+    }
+
+    /// Read and validate the config, rejecting settings that don't parse
+    pub fn load() -> Result<Self, String> {
+        let source = ConfigSource::load()?;
+        let config = Self::from_source_at(&source, chrono::Utc::now());
+        let invalid = source.invalid();
+        if !invalid.is_empty() {
+            return Err(invalid.join("; "));
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// `load`, also rejecting settings no component reads, e.g. misspelled
+    /// keys, and listing every problem found
+    pub fn load_strict() -> Result<Self, String> {
+        let source = ConfigSource::load()?;
+        let config = Self::from_source_at(&source, chrono::Utc::now());
+        config.check_strict(&source)?;
+        Ok(config)
+    }
+
+    /// Everything strict loading rejects about this config, read from `source`:
+    /// values that don't parse or are out of range, unknown settings, and
+    /// `CONFIG_SCHEDULES` overrides that would be rejected once applied
+    pub fn check_strict(&self, source: &ConfigSource) -> Result<(), String> {
+        let mut errors = source.invalid();
+        errors.extend(source.unknown());
+        if let Err(e) = self.validate() {
+            errors.extend(e.split("; ").map(str::to_string));
+        }
+        for schedule in &self.schedules {
+            for key in schedule.overrides.keys().filter(|key| !source.is_read(key)) {
+                errors.push(format!(
+                    "config schedule {} overrides unknown setting {}{}",
+                    schedule.name,
+                    key,
+                    source.did_you_mean(key)
+                ));
+            }
+            let scheduled = source.with_overrides(schedule.overrides.clone());
+            let validated = Self::from_source(&scheduled).validate();
+            let mut scheduled_errors = scheduled.invalid();
+            if let Err(e) = validated {
+                scheduled_errors.extend(e.split("; ").map(str::to_string));
+            }
+            for e in scheduled_errors {
+                if !errors.contains(&e) {
+                    errors.push(format!("config schedule {}: {}", schedule.name, e));
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+
+    /// JSON schema of the config, as `/admin/config` serves it
+    pub fn json_schema() -> serde_json::Value {
+        serde_json::to_value(schemars::schema_for!(Config)).unwrap_or_default()
+    }
+
+    pub fn from_source(source: &ConfigSource) -> Self {
         Self {
             caching: CachingConfig {
-                enabled: env_bool("ENABLE_PHOENIX_CACHING", false),
-                user_cache_size: env_usize("CACHE_SIZE", 10_000_000),
-                trending_cache_size: env_usize("TRENDING_CACHE_SIZE", 100_000),
-                trending_ttl_secs: env_u64("TRENDING_TTL_SECS", 300),
-                user_cache_ttl_secs: env_u64("CACHE_TTL_SECS", 3600),
-                enable_cache_warming: env_bool("ENABLE_CACHE_WARMING", false),
+                enabled: env_bool(source, "ENABLE_PHOENIX_CACHING", false),
+                user_cache_size: env_usize(source, "CACHE_SIZE", 10_000_000),
+                trending_cache_size: env_usize(source, "TRENDING_CACHE_SIZE", 100_000),
+                trending_ttl_secs: env_u64(source, "TRENDING_TTL_SECS", 300),
+                user_cache_ttl_secs: env_u64(source, "CACHE_TTL_SECS", 3600),
+                enable_cache_warming: env_bool(source, "ENABLE_CACHE_WARMING", false),
             },
             batching: BatchingConfig {
-                enabled: env_bool("ENABLE_PHOENIX_BATCHING", false),
-                max_batch_size: env_usize("BATCH_SIZE", 128),
-                max_wait_time_ms: env_u64("BATCH_TIMEOUT_MS", 5),
-                max_concurrent_batches: env_usize("MAX_CONCURRENT_BATCHES", 4),
+                enabled: env_bool(source, "ENABLE_PHOENIX_BATCHING", false),
+                max_batch_size: env_usize(source, "BATCH_SIZE", 128),
+                max_wait_time_ms: env_u64(source, "BATCH_TIMEOUT_MS", 5),
+                max_concurrent_batches: env_usize(source, "MAX_CONCURRENT_BATCHES", 4),
             },
             personalization: PersonalizationConfig {
-                enabled: env_bool("ENABLE_PERSONALIZATION", false),
-                num_clusters: env_usize("NUM_USER_CLUSTERS", 100),
-                enable_auto_refresh: env_bool("AUTO_REFRESH_CLUSTERS", false),
-                refresh_interval_hours: env_u64("CLUSTER_REFRESH_HOURS", 24),
+                enabled: env_bool(source, "ENABLE_PERSONALIZATION", false),
+                num_clusters: env_usize(source, "NUM_USER_CLUSTERS", 100),
+                enable_auto_refresh: env_bool(source, "AUTO_REFRESH_CLUSTERS", false),
+                refresh_interval_hours: env_u64(source, "CLUSTER_REFRESH_HOURS", 24),
+                refresh_jitter_secs: env_u64(source, "CLUSTER_REFRESH_JITTER_SECS", 600),
+                cluster_switch_margin: env_f64(source, "CLUSTER_SWITCH_MARGIN", 0.1),
+                cluster_weight_presets: env_string(source, "CLUSTER_WEIGHT_PRESETS")
+                    .map(|v| parse_weight_presets(&v))
+                    .unwrap_or_default(),
+                features_source: env_string(source, "USER_FEATURES_SOURCE"),
+                engagement_history_source: env_string(source, "ENGAGEMENT_HISTORY_SOURCE"),
+                embeddings_dir: env_string(source, "EMBEDDINGS_DIR"),
+                author_posts_source: env_string(source, "AUTHOR_POSTS_SOURCE"),
+                cluster_store_path: env_string(source, "CLUSTER_STORE_PATH"),
+                snapshot_interval_secs: env_u64(source, "CLUSTER_SNAPSHOT_SECS", 300),
+                event_ewma_alpha: env_f64(source, "EVENT_EWMA_ALPHA", 0.05),
+                cold_start_graduation_events: env_u64(source, "COLD_START_GRADUATION_EVENTS", 20),
+                affinity_max_users: env_u64(source, "AUTHOR_AFFINITY_MAX_USERS", 1_000_000),
+                affinity_max_authors_per_user: env_usize(
+                    source,
+                    "AUTHOR_AFFINITY_MAX_AUTHORS",
+                    200,
+                ),
+                affinity_half_life_days: env_f64(source, "AUTHOR_AFFINITY_HALF_LIFE_DAYS", 14.0),
+                author_profile_max_authors: env_u64(
+                    source,
+                    "AUTHOR_PROFILE_MAX_AUTHORS",
+                    5_000_000,
+                ),
+                topic_mute_threshold: env_f64(source, "TOPIC_MUTE_THRESHOLD", 3.0),
+                topic_mute_half_life_days: env_f64(source, "TOPIC_MUTE_HALF_LIFE_DAYS", 30.0),
+                stats_epsilon: env_f64(source, "STATS_DP_EPSILON", 1.0),
+                stats_min_cluster_size: env_usize(source, "STATS_MIN_CLUSTER_SIZE", 20),
+                exploration_quota: env_f64(source, "EXPLORATION_QUOTA", 0.1),
+                exploration_learning_boost: env_f64(source, "EXPLORATION_LEARNING_BOOST", 3.0),
             },
             safety: SafetyConfig {
-                enable_nsfw_filter: env_bool("ENABLE_NSFW_FILTER", true),
-                nsfw_strict_mode: env_bool("NSFW_STRICT_MODE", true),
-                enable_spam_filter: env_bool("ENABLE_SPAM_FILTER", true),
-                enable_engagement_bait_filter: env_bool("ENABLE_ENGAGEMENT_BAIT_FILTER", true),
-                enable_diversity_boost: env_bool("ENABLE_DIVERSITY_BOOST", false),
-                diversity_boost_multiplier: env_f64("DIVERSITY_BOOST_MULTIPLIER", 1.3),
+                enable_nsfw_filter: env_bool(source, "ENABLE_NSFW_FILTER", true),
+                nsfw_strict_mode: env_bool(source, "NSFW_STRICT_MODE", true),
+                enable_spam_filter: env_bool(source, "ENABLE_SPAM_FILTER", true),
+                enable_engagement_bait_filter: env_bool(
+                    source,
+                    "ENABLE_ENGAGEMENT_BAIT_FILTER",
+                    true,
+                ),
+                enable_diversity_boost: env_bool(source, "ENABLE_DIVERSITY_BOOST", false),
+                diversity_boost_multiplier: env_f64(source, "DIVERSITY_BOOST_MULTIPLIER", 1.3),
+                author_list_path: env_string(source, "AUTHOR_LIST_PATH"),
+                show_subscription_previews: env_bool(source, "SHOW_SUBSCRIPTION_PREVIEWS", false),
+                enable_geo_filter: env_bool(source, "ENABLE_GEO_FILTER", true),
+                language_mismatch_penalty: env_f64(source, "LANGUAGE_MISMATCH_PENALTY", 1.0),
+                enable_toxicity_filter: env_bool(source, "ENABLE_TOXICITY_FILTER", true),
+                user_disableable_filters: env_string(source, "USER_DISABLEABLE_FILTERS")
+                    .map(|v| parse_filter_kinds(&v))
+                    .unwrap_or_else(default_user_disableable_filters),
+            },
+            following: FollowingConfig {
+                source: env_string(source, "FOLLOWING_SOURCE"),
+                cache_ttl_secs: env_u64(source, "FOLLOWING_CACHE_TTL_SECS", 300),
+                cache_stale_secs: env_u64(source, "FOLLOWING_CACHE_STALE_SECS", 86_400),
+                cache_max_users: env_u64(source, "FOLLOWING_CACHE_MAX_USERS", 1_000_000),
+            },
+            impressions: ImpressionsConfig {
+                sink: env_string(source, "IMPRESSIONS_SINK"),
             },
             features: FeatureFlags {
-                caching_rollout_percent: env_u8("CACHING_ROLLOUT_PERCENT", 0),
-                batching_rollout_percent: env_u8("BATCHING_ROLLOUT_PERCENT", 0),
-                personalization_rollout_percent: env_u8("PERSONALIZATION_ROLLOUT_PERCENT", 0),
+                caching_rollout_percent: env_u8(source, "CACHING_ROLLOUT_PERCENT", 0),
+                batching_rollout_percent: env_u8(source, "BATCHING_ROLLOUT_PERCENT", 0),
+                personalization_rollout_percent: env_u8(
+                    source,
+                    "PERSONALIZATION_ROLLOUT_PERCENT",
+                    0,
+                ),
+                cohorts: env_string(source, "FEATURE_COHORTS")
+                    .map(|v| parse_cohorts(&v))
+                    .unwrap_or_default(),
+                experiments: source.json("EXPERIMENTS"),
+                sticky_buckets_path: env_string(source, "STICKY_BUCKETS_PATH"),
+                override_token: env_string(source, "FEATURE_OVERRIDES_TOKEN"),
+            },
+            metrics: MetricsConfig::from_source(source),
+            logging: LoggingConfig::from_source(source),
+            profiling: ProfilingConfig {
+                enabled: env_bool(source, "ENABLE_PROFILING", false),
+            },
+            admin: AdminConfig {
+                token: env_string(source, "ADMIN_TOKEN"),
+            },
+            auth: AuthConfig {
+                user_token_secret: env_string(source, "USER_TOKEN_SECRET"),
+                service_token: env_string(source, "SERVICE_TOKEN"),
+            },
+            alerting: AlertingConfig {
+                webhook_url: env_string(source, "ALERT_WEBHOOK_URL"),
+                interval_secs: env_u64(source, "ALERT_INTERVAL_SECS", 30).max(1),
+                cooldown_secs: env_u64(source, "ALERT_COOLDOWN_SECS", 15 * 60),
+                max_error_rate: env_f64(source, "ALERT_MAX_ERROR_RATE", 0.05),
+                min_cache_hit_rate: env_f64(source, "ALERT_MIN_CACHE_HIT_RATE", 0.5),
             },
-            metrics: MetricsConfig {
-                enabled: env_bool("METRICS_ENABLED", true),
-                port: env_u16("METRICS_PORT", 9090),
-                enable_tracing: env_bool("ENABLE_TRACING", false),
+            debug_traces: DebugTracesConfig {
+                capacity: env_usize(source, "DEBUG_TRACE_CAPACITY", 100),
             },
+            decision_sampling: DecisionSamplingConfig {
+                sink: env_string(source, "DECISION_SINK"),
+                sample_every: env_u64(source, "DECISION_SAMPLE_EVERY", 1000),
+            },
+            timeline_export: TimelineExportConfig {
+                dir: env_string(source, "TIMELINE_EXPORT_DIR"),
+                max_file_bytes: env_u64(source, "TIMELINE_EXPORT_MAX_FILE_BYTES", 128 << 20),
+                max_file_age_secs: env_u64(source, "TIMELINE_EXPORT_MAX_FILE_AGE_SECS", 600),
+                upload_url: env_string(source, "TIMELINE_EXPORT_UPLOAD_URL"),
+                keep_uploaded: env_bool(source, "TIMELINE_EXPORT_KEEP_UPLOADED", false),
+            },
+            locale_overrides: source.json("LOCALE_OVERRIDES"),
+            kill_switches: KillSwitchConfig {
+                components: env_string(source, "KILL_SWITCHES")
+                    .map(|v| parse_components(&v))
+                    .unwrap_or_default(),
+                path: env_string(source, "KILL_SWITCH_PATH"),
+            },
+            schedules: source.json("CONFIG_SCHEDULES"),
+            active_schedules: Vec::new(),
+        }
+    }
+
+    /// The config `source` sets with the overrides of the `CONFIG_SCHEDULES`
+    /// active at `now`
+    pub fn from_source_at(source: &ConfigSource, now: chrono::DateTime<chrono::Utc>) -> Self {
+        let config = Self::from_source(source);
+        let active = config_schedule::active(&config.schedules, now);
+        if active.is_empty() {
+            return config;
+        }
+        let scheduled = source.with_overrides(config_schedule::overrides(&config.schedules, now));
+        let config = Self::from_source(&scheduled);
+        // Overrides that don't parse are reported with the file's settings
+        let mut invalid = source.invalid.lock().unwrap();
+        for e in scheduled.invalid() {
+            if !invalid.contains(&e) {
+                invalid.push(e);
+            }
+        }
+        Self {
+            active_schedules: active,
+            ..config
         }
     }
     
+    /// Reject settings no component could run with, naming each
+    pub fn validate(&self) -> Result<(), String> {
+        let mut errors = Vec::new();
+        let mut check = |ok: bool, message: &str| {
+            if !ok {
+                errors.push(message.to_string());
+            }
+        };
+        let features = &self.features;
+        check(
+            [
+                features.caching_rollout_percent,
+                features.batching_rollout_percent,
+                features.personalization_rollout_percent,
+            ]
+            .iter()
+            .all(|percent| *percent <= 100),
+            "rollout percents must be at most 100",
+        );
+        check(
+            features.cohorts.values().all(|c| c.rollout_percent.is_none_or(|p| p <= 100)),
+            "cohort rollout percents must be at most 100",
+        );
+        let personalization = &self.personalization;
+        check(personalization.num_clusters > 0, "NUM_USER_CLUSTERS must be positive");
+        check(
+            personalization.cluster_switch_margin >= 0.0,
+            "CLUSTER_SWITCH_MARGIN must not be negative",
+        );
+        check(
+            personalization.event_ewma_alpha > 0.0 && personalization.event_ewma_alpha <= 1.0,
+            "EVENT_EWMA_ALPHA must be in (0, 1]",
+        );
+        check(
+            (0.0..=1.0).contains(&personalization.exploration_quota),
+            "EXPLORATION_QUOTA must be in [0, 1]",
+        );
+        check(personalization.stats_epsilon > 0.0, "STATS_DP_EPSILON must be positive");
+        check(
+            self.safety.diversity_boost_multiplier > 0.0,
+            "DIVERSITY_BOOST_MULTIPLIER must be positive",
+        );
+        check(
+            (0.0..=1.0).contains(&self.safety.language_mismatch_penalty),
+            "LANGUAGE_MISMATCH_PENALTY must be in [0, 1]",
+        );
+        check(
+            (0.0..=1.0).contains(&self.alerting.max_error_rate)
+                && (0.0..=1.0).contains(&self.alerting.min_cache_hit_rate),
+            "alert rates must be in [0, 1]",
+        );
+        let export = &self.timeline_export;
+        check(
+            export.max_file_bytes > 0 && export.max_file_age_secs > 0,
+            "TIMELINE_EXPORT_MAX_FILE_BYTES and TIMELINE_EXPORT_MAX_FILE_AGE_SECS must be positive",
+        );
+        check(
+            export.upload_url.as_deref().is_none_or(|url| {
+                url.starts_with("s3://") || url.starts_with("gs://")
+            }),
+            "TIMELINE_EXPORT_UPLOAD_URL must be an s3:// or gs:// URL",
+        );
+        if let Err(e) = experiments::validate(&features.experiments) {
+            errors.push(e);
+        }
+        self.locale_overrides.validate(&mut errors);
+        config_schedule::validate(&self.schedules, &mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+
+    /// The experiments in effect: those configured, then a rollout for each
+    /// feature percent whose name isn't configured
+    pub fn experiments(&self) -> Vec<Experiment> {
+        let features = &self.features;
+        let rollouts = [
+            (CACHING_FEATURE, features.caching_rollout_percent),
+            (BATCHING_FEATURE, features.batching_rollout_percent),
+            (PERSONALIZATION_FEATURE, features.personalization_rollout_percent),
+        ]
+        .into_iter()
+        .filter(|(name, _)| !features.experiments.iter().any(|e| e.name == *name))
+        .map(|(name, percent)| Experiment::rollout(name, percent));
+        features.experiments.iter().cloned().chain(rollouts).collect()
+    }
+
+    /// The variant of `experiment` `ctx` is assigned, if any, regardless of
+    /// sticky buckets
+    pub fn experiment_variant(&self, experiment: &str, ctx: &CohortContext) -> Option<String> {
+        ExperimentAssignments::assign(&self.experiments(), ctx)
+            .variants()
+            .get(experiment)
+            .cloned()
+    }
+
+    fn in_rollout(&self, feature: &str, user_id: u64) -> bool {
+        self.experiment_variant(feature, &CohortContext::new(user_id, "")).is_some()
+    }
+
     pub fn should_use_caching(&self, user_id: u64) -> bool {
-        self.caching.enabled && is_in_rollout(user_id, self.features.caching_rollout_percent)
+        self.caching.enabled && self.in_rollout(CACHING_FEATURE, user_id)
     }
     
     pub fn should_use_batching(&self, user_id: u64) -> bool {
-        self.batching.enabled && is_in_rollout(user_id, self.features.batching_rollout_percent)
+        self.batching.enabled && self.in_rollout(BATCHING_FEATURE, user_id)
     }
     
     pub fn should_use_personalization(&self, user_id: u64) -> bool {
-        self.personalization.enabled && is_in_rollout(user_id, self.features.personalization_rollout_percent)
+        self.personalization.enabled && self.in_rollout(PERSONALIZATION_FEATURE, user_id)
+    }
+    
+    /// Whether `ctx` falls in the cohort configured for `feature`; true when the
+    /// feature has no cohort
+    pub fn in_cohort(&self, feature: &str, ctx: &CohortContext) -> bool {
+        self.features
+            .cohorts
+            .get(feature)
+            .is_none_or(|cohort| cohort.matches(feature, ctx))
+    }
+    
+    /// Cohort-aware variant of `should_use_personalization`
+    pub fn should_use_personalization_for(&self, ctx: &CohortContext) -> bool {
+        self.should_use_personalization(ctx.user_id) && self.in_cohort(PERSONALIZATION_FEATURE, ctx)
+    }
+    
+    pub fn should_use_diversity_boost(&self, ctx: &CohortContext) -> bool {
+        self.safety.enable_diversity_boost && self.in_cohort(DIVERSITY_BOOST_FEATURE, ctx)
     }
 }
 
-fn is_in_rollout(user_id: u64, percent: u8) -> bool {
+/// Whether `user_id` is among the `percent` of users a rollout salted with
+/// `salt` includes; rollouts with different salts include unrelated users
+fn is_in_rollout(salt: &str, user_id: u64, percent: u8) -> bool {
     if percent >= 100 { return true; }
     if percent == 0 { return false; }
-    (user_id % 100) < percent as u64
+    experiments::hash(salt, "rollout", user_id) % 100 < percent as u64
+}
+
+/// Where settings are read from: the environment, overridden by the
+/// `KEY=VALUE` lines of the file `CONFIG_FILE` names, if any. Settings are
+/// changed at runtime by editing the file and reloading.
+#[derive(Debug, Default)]
+pub struct ConfigSource {
+    file: HashMap<String, String>,
+    /// Settings whose value didn't parse, which are read as their default
+    invalid: Mutex<Vec<String>>,
+    /// Keys looked up so far; after a config is read, every setting there is
+    read: Mutex<BTreeSet<String>>,
+}
+
+impl ConfigSource {
+    pub fn load() -> Result<Self, String> {
+        match std::env::var("CONFIG_FILE").ok().filter(|path| !path.is_empty()) {
+            Some(path) => {
+                let text = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?;
+                Self::parse(&text).map_err(|e| format!("{}: {}", path, e))
+            },
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// `KEY=VALUE` lines; blank lines and lines starting with `#` are skipped,
+    /// and values may be quoted
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut file = HashMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected KEY=VALUE", number + 1))?;
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+            file.insert(key.trim().to_string(), value.to_string());
+        }
+        Ok(Self {
+            file,
+            ..Self::default()
+        })
+    }
+
+    /// The settings of this source with `overrides` in place of theirs
+    pub fn with_overrides(&self, overrides: BTreeMap<String, String>) -> Self {
+        let mut file = self.file.clone();
+        file.extend(overrides);
+        Self {
+            file,
+            ..Self::default()
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.read.lock().unwrap().insert(key.to_string());
+        self.file.get(key).cloned().or_else(|| std::env::var(key).ok())
+    }
+
+    /// The value of `key`, or `default` when unset or unparseable
+    fn value<T: std::str::FromStr>(&self, key: &str, default: T) -> T {
+        let Some(value) = self.get(key) else {
+            return default;
+        };
+        value.parse().unwrap_or_else(|_| {
+            let expected = std::any::type_name::<T>();
+            let e = format!("{}={:?} doesn't parse as {}", key, value, expected);
+            self.invalid.lock().unwrap().push(e);
+            default
+        })
+    }
+
+    /// The JSON value of `key`, or the default when unset or invalid
+    fn json<T: serde::de::DeserializeOwned + Default>(&self, key: &str) -> T {
+        let Some(value) = self.get(key).filter(|v| !v.is_empty()) else {
+            return T::default();
+        };
+        serde_json::from_str(&value).unwrap_or_else(|e| {
+            self.invalid.lock().unwrap().push(format!("{} doesn't parse: {}", key, e));
+            T::default()
+        })
+    }
+
+    /// Settings read so far whose value didn't parse
+    pub fn invalid(&self) -> Vec<String> {
+        self.invalid.lock().unwrap().clone()
+    }
+
+    /// Settings set but never read, once a config has been read: every such
+    /// key of the file, and environment variables a typo away from a setting.
+    /// Other variables are left alone, since the environment holds more than
+    /// settings.
+    fn unknown(&self) -> Vec<String> {
+        let mut unknown: Vec<String> = self
+            .file
+            .keys()
+            .filter(|key| !self.is_read(key))
+            .map(|key| format!("unknown setting {} in CONFIG_FILE{}", key, self.did_you_mean(key)))
+            .collect();
+        unknown.sort();
+        let read = self.read.lock().unwrap().clone();
+        for (name, _) in std::env::vars() {
+            let typo = read.iter().any(|key| strsim::levenshtein(key, &name) <= 2);
+            if typo && !read.contains(&name) && !self.file.contains_key(&name) {
+                unknown.push(format!("unknown setting {}{}", name, self.did_you_mean(&name)));
+            }
+        }
+        unknown
+    }
+
+    fn is_read(&self, key: &str) -> bool {
+        self.read.lock().unwrap().contains(key)
+    }
+
+    /// ` (did you mean KEY?)` for the setting read closest to `key`, if any is close
+    fn did_you_mean(&self, key: &str) -> String {
+        let read = self.read.lock().unwrap();
+        read.iter()
+            .map(|setting| (strsim::levenshtein(key, setting), setting))
+            .filter(|(distance, _)| *distance <= 3)
+            .min()
+            .map_or_else(String::new, |(_, setting)| format!(" (did you mean {}?)", setting))
+    }
+}
+
+fn env_bool(source: &ConfigSource, key: &str, default: bool) -> bool {
+    source.value(key, default)
 }
 
-fn env_bool(key: &str, default: bool) -> bool {
-    std::env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+fn env_usize(source: &ConfigSource, key: &str, default: usize) -> usize {
+    source.value(key, default)
 }
 
-fn env_usize(key: &str, default: usize) -> usize {
-    std::env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+fn env_u64(source: &ConfigSource, key: &str, default: u64) -> u64 {
+    source.value(key, default)
 }
 
-fn env_u64(key: &str, default: u64) -> u64 {
-    std::env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+fn env_u8(source: &ConfigSource, key: &str, default: u8) -> u8 {
+    source.value(key, default)
 }
 
-fn env_u8(key: &str, default: u8) -> u8 {
-    std::env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+fn env_u16(source: &ConfigSource, key: &str, default: u16) -> u16 {
+    source.value(key, default)
 }
 
-fn env_u16(key: &str, default: u16) -> u16 {
-    std::env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+fn env_f64(source: &ConfigSource, key: &str, default: f64) -> f64 {
+    source.value(key, default)
 }
 
-fn env_f64(key: &str, default: f64) -> f64 {
-    std::env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+fn env_string(source: &ConfigSource, key: &str) -> Option<String> {
+    source.get(key).filter(|v| !v.is_empty())
 }
 
 // ============================================================
 // METRICS
 // ============================================================
 
-#[derive(Default)]
+/// Quantiles exported for each `Distribution`
+const EXPORTED_QUANTILES: [f64; 3] = [0.5, 0.95, 0.99];
+
+/// Observations of one measurement, in an HDR histogram accurate to 3
+/// significant digits, plus their exact sum
+pub struct Distribution {
+    histogram: Mutex<Histogram<u64>>,
+    sum: AtomicU64,
+}
+
+impl Distribution {
+    /// Values above `max` are recorded as `max`
+    pub fn new(max: u64) -> Self {
+        let histogram = Histogram::new_with_bounds(1, max, 3).expect("valid histogram bounds");
+        Self {
+            histogram: Mutex::new(histogram),
+            sum: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, value: u64) {
+        self.histogram.lock().unwrap().saturating_record(value);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.histogram.lock().unwrap().len()
+    }
+
+    pub fn sum(&self) -> u64 {
+        self.sum.load(Ordering::Relaxed)
+    }
+
+    pub fn mean(&self) -> f64 {
+        let count = self.count();
+        if count == 0 { 0.0 } else { self.sum() as f64 / count as f64 }
+    }
+
+    /// The value at `quantile` (0.0 - 1.0), 0 before any observation
+    pub fn quantile(&self, quantile: f64) -> u64 {
+        self.histogram.lock().unwrap().value_at_quantile(quantile)
+    }
+
+    /// Render as a Prometheus summary
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} summary", name);
+        for quantile in EXPORTED_QUANTILES {
+            let value = self.quantile(quantile);
+            let _ = writeln!(out, "{}{{quantile=\"{}\"}} {}", name, quantile, value);
+        }
+        let _ = writeln!(out, "{}_sum {}", name, self.sum());
+        let _ = writeln!(out, "{}_count {}\n", name, self.count());
+    }
+}
+
 pub struct Metrics {
     // Latency
-    pub feed_latency_sum_ms: AtomicU64,
-    pub feed_latency_count: AtomicU64,
+    pub feed_latency_ms: Distribution,
     
     // Throughput
     pub requests_total: AtomicU64,
@@ -235,22 +1418,41 @@ pub struct Metrics {
     pub cache_misses: AtomicU64,
     
     // Batching
-    pub batch_size_sum: AtomicU64,
-    pub batch_count: AtomicU64,
+    pub batch_size: Distribution,
     
     // GPU
-    pub gpu_inference_time_sum_ms: AtomicU64,
-    pub gpu_inference_count: AtomicU64,
+    pub gpu_inference_time_ms: Distribution,
     
     // Safety filters
     pub nsfw_filtered: AtomicU64,
     pub spam_filtered: AtomicU64,
     pub clickbait_filtered: AtomicU64,
+    pub toxicity_filtered: AtomicU64,
     
     // Personalization
     pub personalized_requests: AtomicU64,
 }
 
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            feed_latency_ms: Distribution::new(60_000),
+            requests_total: AtomicU64::new(0),
+            requests_success: AtomicU64::new(0),
+            requests_error: AtomicU64::new(0),
+            cache_hits: AtomicU64::new(0),
+            cache_misses: AtomicU64::new(0),
+            batch_size: Distribution::new(100_000),
+            gpu_inference_time_ms: Distribution::new(60_000),
+            nsfw_filtered: AtomicU64::new(0),
+            spam_filtered: AtomicU64::new(0),
+            clickbait_filtered: AtomicU64::new(0),
+            toxicity_filtered: AtomicU64::new(0),
+            personalized_requests: AtomicU64::new(0),
+        }
+    }
+}
+
 impl Metrics {
     pub fn new() -> Arc<Self> {
         Arc::new(Self::default())
@@ -258,8 +1460,7 @@ impl Metrics {
     
     pub fn record_request(&self, latency_ms: u64, success: bool) {
         self.requests_total.fetch_add(1, Ordering::Relaxed);
-        self.feed_latency_sum_ms.fetch_add(latency_ms, Ordering::Relaxed);
-        self.feed_latency_count.fetch_add(1, Ordering::Relaxed);
+        self.feed_latency_ms.record(latency_ms);
         
         if success {
             self.requests_success.fetch_add(1, Ordering::Relaxed);
@@ -277,22 +1478,28 @@ impl Metrics {
     }
     
     pub fn record_batch(&self, size: usize) {
-        self.batch_size_sum.fetch_add(size as u64, Ordering::Relaxed);
-        self.batch_count.fetch_add(1, Ordering::Relaxed);
+        self.batch_size.record(size as u64);
+    }
+    
+    pub fn record_gpu_inference(&self, time_ms: u64) {
+        self.gpu_inference_time_ms.record(time_ms);
     }
     
     pub fn record_filter(&self, filter_type: FilterType) {
+        self.record_filtered(filter_type, 1);
+    }
+    
+    pub fn record_filtered(&self, filter_type: FilterType, count: u64) {
         match filter_type {
-            FilterType::Nsfw => self.nsfw_filtered.fetch_add(1, Ordering::Relaxed),
-            FilterType::Spam => self.spam_filtered.fetch_add(1, Ordering::Relaxed),
-            FilterType::Clickbait => self.clickbait_filtered.fetch_add(1, Ordering::Relaxed),
+            FilterType::Nsfw => self.nsfw_filtered.fetch_add(count, Ordering::Relaxed),
+            FilterType::Spam => self.spam_filtered.fetch_add(count, Ordering::Relaxed),
+            FilterType::Clickbait => self.clickbait_filtered.fetch_add(count, Ordering::Relaxed),
+            FilterType::Toxicity => self.toxicity_filtered.fetch_add(count, Ordering::Relaxed),
         };
     }
     
     pub fn avg_latency_ms(&self) -> f64 {
-        let sum = self.feed_latency_sum_ms.load(Ordering::Relaxed);
-        let count = self.feed_latency_count.load(Ordering::Relaxed);
-        if count == 0 { 0.0 } else { sum as f64 / count as f64 }
+        self.feed_latency_ms.mean()
     }
     
     pub fn cache_hit_rate(&self) -> f64 {
@@ -303,9 +1510,7 @@ impl Metrics {
     }
     
     pub fn avg_batch_size(&self) -> f64 {
-        let sum = self.batch_size_sum.load(Ordering::Relaxed);
-        let count = self.batch_count.load(Ordering::Relaxed);
-        if count == 0 { 0.0 } else { sum as f64 / count as f64 }
+        self.batch_size.mean()
     }
     
     pub fn error_rate(&self) -> f64 {
@@ -315,12 +1520,17 @@ impl Metrics {
     }
     
     pub fn to_prometheus(&self) -> String {
-        format!(
-            r#"# HELP feed_latency_ms Average feed generation latency
-# TYPE feed_latency_ms gauge
-feed_latency_ms {:.2}
-
-# HELP requests_total Total number of requests
+        let mut out = String::new();
+        self.feed_latency_ms.render(&mut out, "feed_latency_ms", "Feed generation latency");
+        self.batch_size.render(&mut out, "batch_size", "Candidates per inference batch");
+        self.gpu_inference_time_ms.render(
+            &mut out,
+            "gpu_inference_time_ms",
+            "GPU inference time per batch",
+        );
+        let _ = write!(
+            out,
+            r#"# HELP requests_total Total number of requests
 # TYPE requests_total counter
 requests_total {}
 
@@ -328,10 +1538,6 @@ requests_total {}
 # TYPE cache_hit_rate gauge
 cache_hit_rate {:.4}
 
-# HELP avg_batch_size Average batch size
-# TYPE avg_batch_size gauge
-avg_batch_size {:.2}
-
 # HELP error_rate Error rate
 # TYPE error_rate gauge
 error_rate {:.6}
@@ -347,16 +1553,20 @@ spam_filtered {}
 # HELP clickbait_filtered Total clickbait content filtered
 # TYPE clickbait_filtered counter
 clickbait_filtered {}
+
+# HELP toxicity_filtered Total toxic content filtered
+# TYPE toxicity_filtered counter
+toxicity_filtered {}
 "#,
-            self.avg_latency_ms(),
             self.requests_total.load(Ordering::Relaxed),
             self.cache_hit_rate(),
-            self.avg_batch_size(),
             self.error_rate(),
             self.nsfw_filtered.load(Ordering::Relaxed),
             self.spam_filtered.load(Ordering::Relaxed),
             self.clickbait_filtered.load(Ordering::Relaxed),
-        )
+            self.toxicity_filtered.load(Ordering::Relaxed),
+        );
+        out
     }
 }
 
@@ -364,6 +1574,7 @@ pub enum FilterType {
     Nsfw,
     Spam,
     Clickbait,
+    Toxicity,
 }
 
 // ============================================================
@@ -423,20 +1634,76 @@ mod tests {
         assert!(config.safety.enable_nsfw_filter);
     }
     
+    #[test]
+    fn test_cohort_selector_composes_conditions() {
+        let config = Config {
+            safety: SafetyConfig {
+                enable_diversity_boost: true,
+                ..Default::default()
+            },
+            features: FeatureFlags {
+                cohorts: parse_cohorts(
+                    r#"{"diversity_boost": {"rollout_percent": 50,
+                        "min_diversity_preference": 0.7, "countries": ["us", "GB"]}}"#,
+                ),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let profile = ClusterProfile {
+            cluster_id: 3,
+            diversity_preference: 0.8,
+            ..Default::default()
+        };
+        let ctx = CohortContext::new(9, "US").with_cluster(&profile);
+        assert!(config.should_use_diversity_boost(&ctx));
+
+        let low_diversity = CohortContext {
+            diversity_preference: Some(0.5),
+            ..ctx.clone()
+        };
+        let outside_bucket = CohortContext {
+            user_id: 12,
+            ..ctx.clone()
+        };
+        let other_country = CohortContext {
+            country_code: "FR".to_string(),
+            ..ctx.clone()
+        };
+        assert!(!config.should_use_diversity_boost(&low_diversity));
+        assert!(!config.should_use_diversity_boost(&outside_bucket));
+        assert!(!config.should_use_diversity_boost(&other_country));
+
+        // Features without a cohort are unrestricted
+        assert!(config.in_cohort(CACHING_FEATURE, &other_country));
+
+        let clusters = CohortSelector {
+            cluster_ids: vec![3, 4],
+            ..Default::default()
+        };
+        assert!(clusters.matches(DIVERSITY_BOOST_FEATURE, &ctx));
+        assert!(!clusters.matches(DIVERSITY_BOOST_FEATURE, &CohortContext {
+            cluster_id: None,
+            ..ctx
+        }));
+    }
+    
     #[test]
     fn test_rollout_logic() {
-        // User 0-9 should be in 10% rollout
-        for user_id in 0..10u64 {
-            assert!(is_in_rollout(user_id, 10));
-        }
-        // User 10-99 should NOT be in 10% rollout
-        for user_id in 10..100u64 {
-            assert!(!is_in_rollout(user_id, 10));
-        }
+        let included = |salt| {
+            (0..10_000u64)
+                .filter(|&user_id| is_in_rollout(salt, user_id, 10))
+                .collect::<std::collections::HashSet<_>>()
+        };
+        let caching = included(CACHING_FEATURE);
+        assert!(caching.len().abs_diff(1000) < 100);
+        // Rollouts of different features include unrelated users, about 10% of 10%
+        let shared = caching.intersection(&included(BATCHING_FEATURE)).count();
+        assert!(shared.abs_diff(100) < 40, "{} users in both", shared);
         // 100% rollout
-        assert!(is_in_rollout(999, 100));
+        assert!(is_in_rollout(CACHING_FEATURE, 999, 100));
         // 0% rollout
-        assert!(!is_in_rollout(0, 0));
+        assert!(!is_in_rollout(CACHING_FEATURE, 0, 0));
     }
     
     #[test]
@@ -453,6 +1720,127 @@ mod tests {
         assert!((metrics.avg_latency_ms() - 60.0).abs() < 0.01);
     }
     
+    #[test]
+    fn test_latency_quantiles_are_exported() {
+        let metrics = Metrics::new();
+        
+        for latency_ms in 1..=100 {
+            metrics.record_request(latency_ms, true);
+        }
+        metrics.record_batch(64);
+        
+        assert_eq!(metrics.feed_latency_ms.quantile(0.5), 50);
+        assert_eq!(metrics.feed_latency_ms.quantile(0.99), 99);
+        assert_eq!(metrics.avg_batch_size(), 64.0);
+        
+        let text = metrics.to_prometheus();
+        assert!(text.contains("# TYPE feed_latency_ms summary"));
+        assert!(text.contains("feed_latency_ms{quantile=\"0.95\"} 95"));
+        assert!(text.contains("feed_latency_ms_sum 5050\nfeed_latency_ms_count 100"));
+        assert!(text.contains("batch_size_count 1"));
+        assert!(text.contains("requests_total 100"));
+    }
+    
+    #[test]
+    fn test_config_file_settings_are_validated() {
+        let source = ConfigSource::parse(
+            "# rollout\nCACHING_ROLLOUT_PERCENT=25\n\nFEATURE_COHORTS=\"{}\"\n",
+        )
+        .unwrap();
+        let config = Config::from_source(&source);
+        assert_eq!(config.features.caching_rollout_percent, 25);
+        assert!(config.validate().is_ok());
+
+        let source = ConfigSource::parse("EXPLORATION_QUOTA=1.5\nNUM_USER_CLUSTERS=0").unwrap();
+        let e = Config::from_source(&source).validate().unwrap_err();
+        assert_eq!(e, "NUM_USER_CLUSTERS must be positive; EXPLORATION_QUOTA must be in [0, 1]");
+        let source = ConfigSource::parse("TIMELINE_EXPORT_UPLOAD_URL=ftp://exports").unwrap();
+        let e = Config::from_source(&source).validate().unwrap_err();
+        assert_eq!(e, "TIMELINE_EXPORT_UPLOAD_URL must be an s3:// or gs:// URL");
+
+        let source = ConfigSource::parse("CACHING_ROLLOUT_PERCENT=300").unwrap();
+        assert_eq!(Config::from_source(&source).features.caching_rollout_percent, 0);
+        assert_eq!(source.invalid(), ["CACHING_ROLLOUT_PERCENT=\"300\" doesn't parse as u8"]);
+        assert!(ConfigSource::parse("CACHING_ROLLOUT_PERCENT").is_err());
+    }
+
+    #[test]
+    fn test_strict_loading_rejects_unknown_settings() {
+        std::env::set_var("DEBUG_TRACES_CAPACITY", "10");
+        let source = ConfigSource::parse(concat!(
+            "ENABLE_PHENIX_CACHING=true\n",
+            "CACHING_ROLLOUT_PERCENT=300\n",
+            r#"CONFIG_SCHEDULES=[{"name": "live", "cron": "0 18 * * 6", "duration_mins": 60,"#,
+            r#" "overrides": {"ENABLE_DIVERSTY_BOOST": "true", "EXPLORATION_QUOTA": "2"}}]"#,
+        ))
+        .unwrap();
+        let config = Config::from_source(&source);
+        let e = config.check_strict(&source).unwrap_err();
+        std::env::remove_var("DEBUG_TRACES_CAPACITY");
+        let errors: Vec<_> = e.split("; ").collect();
+        assert_eq!(
+            errors,
+            [
+                "CACHING_ROLLOUT_PERCENT=\"300\" doesn't parse as u8",
+                "unknown setting ENABLE_PHENIX_CACHING in CONFIG_FILE \
+                 (did you mean ENABLE_PHOENIX_CACHING?)",
+                "unknown setting DEBUG_TRACES_CAPACITY (did you mean DEBUG_TRACE_CAPACITY?)",
+                "config schedule live overrides unknown setting ENABLE_DIVERSTY_BOOST \
+                 (did you mean ENABLE_DIVERSITY_BOOST?)",
+                "config schedule live: EXPLORATION_QUOTA must be in [0, 1]",
+            ]
+        );
+
+        let source = ConfigSource::parse("ENABLE_PHOENIX_CACHING=true").unwrap();
+        assert!(Config::from_source(&source).check_strict(&source).is_ok());
+        let schema = Config::json_schema();
+        let caching = &schema["definitions"]["CachingConfig"]["properties"];
+        assert_eq!(caching["enabled"]["type"], "boolean");
+    }
+
+    #[test]
+    fn test_experiments_override_rollout_percents() {
+        let source = ConfigSource::parse(concat!(
+            "CACHING_ROLLOUT_PERCENT=100\n",
+            r#"EXPERIMENTS=[{"name": "batching", "variants": [{"name": "enabled"}]}]"#,
+        ))
+        .unwrap();
+        let mut config = Config::from_source(&source);
+        config.caching.enabled = true;
+        config.batching.enabled = true;
+        assert!(config.should_use_caching(5));
+        assert!(config.should_use_batching(5));
+        assert!(!config.should_use_personalization(5));
+        let names: Vec<_> = config.experiments().into_iter().map(|e| e.name).collect();
+        assert_eq!(names, ["batching", "caching", "personalization"]);
+
+        let source = ConfigSource::parse("EXPERIMENTS=[{\"name\": 1}]").unwrap();
+        assert!(Config::from_source(&source).features.experiments.is_empty());
+        assert_eq!(source.invalid().len(), 1);
+    }
+
+    #[test]
+    fn test_locale_overrides_are_validated() {
+        let source = ConfigSource::parse(concat!(
+            r#"LOCALE_OVERRIDES={"countries": {"BR": {"weights": {"report": 5.0, "likes": 1.0}}},"#,
+            r#" "languages": {"ja": {"toxicity_threshold": 1.5}}}"#,
+        ))
+        .unwrap();
+        let e = Config::from_source(&source).validate().unwrap_err();
+        assert_eq!(
+            e,
+            "country BR overrides unknown weight likes; \
+             country BR weight report has the wrong sign for its action; \
+             language ja thresholds must be in [0, 1]"
+        );
+
+        // Unknown settings are rejected when read, leaving no overrides
+        let source = ConfigSource::parse(r#"LOCALE_OVERRIDES={"countries": {"BR": {"x": 1}}}"#)
+            .unwrap();
+        assert_eq!(Config::from_source(&source).locale_overrides, LocaleOverrides::default());
+        assert_eq!(source.invalid().len(), 1);
+    }
+
     #[test]
     fn test_cache_hit_rate() {
         let metrics = Metrics::new();
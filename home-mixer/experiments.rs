@@ -0,0 +1,579 @@
+//! Experiments
+//!
+//! An experiment splits users between named variants, e.g. `control` and
+//! `treatment`, by weight. Users are assigned by hashing their id with the
+//! experiment's salt, so a user keeps their variant across requests and
+//! replicas, and experiments with different salts split users independently.
+//! Whether a user is in an experiment at all and which variant they get are
+//! hashed separately, so raising an experiment's allocation adds users
+//! without moving those already in it.
+//!
+//! Experiments sharing a `layer` are mutually exclusive: a layer hashes each
+//! user into one bucket, and its experiments take consecutive, disjoint
+//! ranges of those buckets sized by their allocations, in config order, so
+//! concurrent experiments on the same surface never share a treatment
+//! population. Users of a `sticky` experiment keep the first variant they're
+//! assigned, remembered by `StickyBuckets`, while they're eligible and the
+//! experiment still has it, so reweighting or resalting it moves only new
+//! users.
+//!
+//! Experiments are configured with `EXPERIMENTS` and assigned per request by
+//! `ExperimentsQueryHydrator`. Filters and scorers read the viewer's variant
+//! with `query.experiment("bait_filter_v2")`, which also records that the
+//! request was exposed to the experiment; `ExperimentExposureSideEffect` logs
+//! the exposures once the request is served, so analysis only counts users
+//! whose feed the experiment could have changed.
+//!
+//! Trusted callers can force variants for a single request with
+//! `FeatureOverrides`, e.g. to reproduce a user's feed under a candidate
+//! configuration. Forced variants replace the assigned ones for that request
+//! only and are never logged as exposures.
+
+use crate::config::{CohortContext, CohortSelector};
+use crate::util::sticky_buckets::StickyBuckets;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::Mutex;
+
+pub const CONTROL: &str = "control";
+pub const TREATMENT: &str = "treatment";
+/// The only variant of a rollout
+pub const ENABLED: &str = "enabled";
+/// Forced variant taking a request out of an experiment, or a flag off
+pub const OFF: &str = "off";
+/// Forced variant turning a flag on; the `enabled` variant of experiments
+pub const ON: &str = "on";
+
+/// Hash buckets users are spread over, for allocations to 0.01%
+const BUCKETS: u64 = 10_000;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Variant {
+    pub name: String,
+    /// Share of the experiment's users, relative to the other variants
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Experiment {
+    pub name: String,
+    /// Mixed into the hashes; defaults to the name. Changing it reshuffles users.
+    #[serde(default)]
+    pub salt: Option<String>,
+    /// Percent of eligible users in the experiment; the rest get no variant
+    #[serde(default = "default_allocation")]
+    pub allocation_percent: f64,
+    pub variants: Vec<Variant>,
+    /// Only users in the cohort are eligible; everyone is when unset
+    #[serde(default)]
+    pub cohort: Option<CohortSelector>,
+    /// Experiments in the same layer never share users
+    #[serde(default)]
+    pub layer: Option<String>,
+    /// Users keep the first variant they're assigned while eligible and the
+    /// experiment still has it
+    #[serde(default)]
+    pub sticky: bool,
+}
+
+fn default_allocation() -> f64 {
+    100.0
+}
+
+impl Experiment {
+    /// `control` and `treatment`, equally weighted, for every user
+    pub fn ab(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            salt: None,
+            allocation_percent: 100.0,
+            variants: [CONTROL, TREATMENT]
+                .into_iter()
+                .map(|name| Variant {
+                    name: name.to_string(),
+                    weight: 1,
+                })
+                .collect(),
+            cohort: None,
+            layer: None,
+            sticky: false,
+        }
+    }
+
+    /// `percent` of users get the `enabled` variant, the rest none
+    pub fn rollout(name: &str, percent: u8) -> Self {
+        Self {
+            allocation_percent: percent as f64,
+            variants: vec![Variant {
+                name: ENABLED.to_string(),
+                weight: 1,
+            }],
+            ..Self::ab(name)
+        }
+    }
+
+    fn salt(&self) -> &str {
+        self.salt.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Buckets of its layer, or of its own hash, the experiment allocates
+    fn allocated(&self) -> u64 {
+        (self.allocation_percent * (BUCKETS / 100) as f64).round() as u64
+    }
+
+    fn is_eligible(&self, ctx: &CohortContext) -> bool {
+        self.cohort.as_ref().is_none_or(|cohort| cohort.matches(self.salt(), ctx))
+    }
+
+    /// The variant `ctx`'s user is assigned, or None when they aren't in the
+    /// experiment, as if it were the first of its layer; see
+    /// `ExperimentAssignments::assign` for layers
+    pub fn assign(&self, ctx: &CohortContext) -> Option<&str> {
+        self.assign_from(0, ctx)
+    }
+
+    /// `assign` for an experiment whose buckets start at `first` of its layer
+    fn assign_from(&self, first: u64, ctx: &CohortContext) -> Option<&str> {
+        if !self.is_eligible(ctx) {
+            return None;
+        }
+        let bucket = match &self.layer {
+            Some(layer) => (hash(layer, "layer", ctx.user_id) % BUCKETS).checked_sub(first)?,
+            None => hash(self.salt(), "allocation", ctx.user_id) % BUCKETS,
+        };
+        if bucket >= self.allocated() {
+            return None;
+        }
+        let total: u64 = self.variants.iter().map(|v| v.weight as u64).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut point = hash(self.salt(), "variant", ctx.user_id) % total;
+        for variant in &self.variants {
+            if point < variant.weight as u64 {
+                return Some(&variant.name);
+            }
+            point -= variant.weight as u64;
+        }
+        None
+    }
+
+    /// `assigned`, unless `ctx`'s user got another variant of this sticky
+    /// experiment before that it still has. Dropping the allocation to 0
+    /// ends the experiment for everyone.
+    fn stick(
+        &self,
+        buckets: &StickyBuckets,
+        ctx: &CohortContext,
+        assigned: Option<&str>,
+    ) -> Option<String> {
+        if self.allocation_percent == 0.0 || !self.is_eligible(ctx) {
+            return None;
+        }
+        let kept = buckets.get(&self.name, ctx.user_id).filter(|kept| {
+            self.variants.iter().any(|v| v.name == *kept && v.weight > 0)
+        });
+        if kept.is_some() {
+            return kept;
+        }
+        let assigned = assigned?;
+        buckets.record(&self.name, ctx.user_id, assigned);
+        Some(assigned.to_string())
+    }
+}
+
+/// Reject experiments that can't be assigned, naming each
+pub fn validate(experiments: &[Experiment]) -> Result<(), String> {
+    let mut errors = Vec::new();
+    let mut names = HashSet::new();
+    let mut layers: BTreeMap<&str, f64> = BTreeMap::new();
+    for experiment in experiments {
+        let name = &experiment.name;
+        if name.is_empty() {
+            errors.push("experiment names must not be empty".to_string());
+        } else if !names.insert(name) {
+            errors.push(format!("experiment {} is defined twice", name));
+        }
+        if !(0.0..=100.0).contains(&experiment.allocation_percent) {
+            errors.push(format!("experiment {} allocation must be in [0, 100]", name));
+        }
+        if experiment.variants.iter().all(|v| v.weight == 0) {
+            errors.push(format!("experiment {} needs a variant with positive weight", name));
+        }
+        let mut variants = HashSet::new();
+        if !experiment.variants.iter().all(|v| variants.insert(&v.name)) {
+            errors.push(format!("experiment {} has duplicate variants", name));
+        }
+        if let Some(layer) = &experiment.layer {
+            *layers.entry(layer).or_default() += experiment.allocation_percent;
+        }
+    }
+    for (layer, allocated) in layers {
+        if allocated > 100.0 {
+            errors.push(format!("experiments in layer {} allocate over 100% of users", layer));
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("; "))
+    }
+}
+
+/// FNV-1a of the salt, purpose and user id, with a final mix so nearby ids
+/// land in unrelated buckets. Unlike `DefaultHasher`, stable across releases.
+pub(crate) fn hash(salt: &str, purpose: &str, user_id: u64) -> u64 {
+    let bytes = salt
+        .bytes()
+        .chain([b':'])
+        .chain(purpose.bytes())
+        .chain([b':'])
+        .chain(user_id.to_le_bytes());
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash
+}
+
+/// A request's exposure to an experiment
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Exposure {
+    pub experiment: String,
+    pub variant: String,
+}
+
+/// Variants forced for one request, by experiment or flag name; `off` takes
+/// the request out of the experiment or turns the flag off, and `on` stands
+/// for `enabled`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FeatureOverrides(BTreeMap<String, String>);
+
+impl FeatureOverrides {
+    /// `name=variant` pairs separated by commas, as in the `x-feature-overrides`
+    /// header, e.g. `caching=off,bait_filter_v2=treatment`
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let pairs = text.split(',').map(str::trim).filter(|pair| !pair.is_empty());
+        let mut overrides = BTreeMap::new();
+        for pair in pairs {
+            let (name, variant) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("feature override {:?} must be name=variant", pair))?;
+            overrides.insert(name.trim().to_string(), variant.trim().to_string());
+        }
+        Self::from_map(overrides)
+    }
+
+    pub fn from_map(overrides: BTreeMap<String, String>) -> Result<Self, String> {
+        for (name, variant) in &overrides {
+            if name.is_empty() || variant.is_empty() {
+                let pair = format!("{}={}", name, variant);
+                return Err(format!("feature override {:?} needs a name and variant", pair));
+            }
+        }
+        let normalized = overrides.into_iter().map(|(name, variant)| {
+            let variant = if variant == ON { ENABLED.to_string() } else { variant };
+            (name, variant)
+        });
+        Ok(Self(normalized.collect()))
+    }
+
+    /// `other`'s overrides in place of these
+    pub fn extend(&mut self, other: Self) {
+        self.0.extend(other.0);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Whether flag `name` is forced on or off; None when not overridden
+    pub fn flag(&self, name: &str) -> Option<bool> {
+        self.0.get(name).map(|variant| variant != OFF)
+    }
+
+    pub fn overrides(&self) -> &BTreeMap<String, String> {
+        &self.0
+    }
+}
+
+/// The variants one request was assigned, and those a component read
+#[derive(Debug, Default)]
+pub struct ExperimentAssignments {
+    variants: BTreeMap<String, String>,
+    exposed: Mutex<BTreeSet<String>>,
+    /// Experiments whose variant was forced, which aren't exposures
+    forced: BTreeSet<String>,
+}
+
+impl ExperimentAssignments {
+    /// The variant `ctx`'s user is assigned of each experiment, experiments of
+    /// a layer taking its buckets in order
+    pub fn assign(experiments: &[Experiment], ctx: &CohortContext) -> Self {
+        Self::assign_with(experiments, ctx, None)
+    }
+
+    /// `assign`, keeping the variants `buckets` remembers for sticky experiments
+    pub fn assign_sticky(
+        experiments: &[Experiment],
+        ctx: &CohortContext,
+        buckets: &StickyBuckets,
+    ) -> Self {
+        Self::assign_with(experiments, ctx, Some(buckets))
+    }
+
+    fn assign_with(
+        experiments: &[Experiment],
+        ctx: &CohortContext,
+        buckets: Option<&StickyBuckets>,
+    ) -> Self {
+        let mut taken: HashMap<&str, u64> = HashMap::new();
+        let mut variants = BTreeMap::new();
+        for experiment in experiments {
+            let first = match &experiment.layer {
+                Some(layer) => {
+                    let taken = taken.entry(layer).or_default();
+                    *taken += experiment.allocated();
+                    *taken - experiment.allocated()
+                },
+                None => 0,
+            };
+            let assigned = experiment.assign_from(first, ctx);
+            let variant = match buckets.filter(|_| experiment.sticky) {
+                Some(buckets) => experiment.stick(buckets, ctx, assigned),
+                None => assigned.map(String::from),
+            };
+            if let Some(variant) = variant {
+                variants.insert(experiment.name.clone(), variant);
+            }
+        }
+        Self {
+            variants,
+            ..Self::default()
+        }
+    }
+
+    /// These assignments with the variants `overrides` forces
+    pub fn with_overrides(mut self, overrides: &FeatureOverrides) -> Self {
+        for (experiment, variant) in overrides.overrides() {
+            if variant == OFF {
+                self.variants.remove(experiment);
+            } else {
+                self.variants.insert(experiment.clone(), variant.clone());
+            }
+            self.forced.insert(experiment.clone());
+        }
+        self
+    }
+
+    /// The variant of `experiment` the request is in, recording an exposure;
+    /// None when it isn't in the experiment
+    pub fn variant(&self, experiment: &str) -> Option<&str> {
+        let variant = self.variants.get(experiment)?;
+        self.exposed.lock().unwrap().insert(experiment.to_string());
+        Some(variant)
+    }
+
+    /// Every assigned variant, by experiment, without recording exposures
+    pub fn variants(&self) -> &BTreeMap<String, String> {
+        &self.variants
+    }
+
+    /// Experiments a component read the assigned variant of, by name
+    pub fn exposures(&self) -> Vec<Exposure> {
+        self.exposed
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|experiment| !self.forced.contains(*experiment))
+            .map(|experiment| Exposure {
+                experiment: experiment.clone(),
+                variant: self.variants[experiment].clone(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn share(experiment: &Experiment, variant: &str) -> f64 {
+        let users = 20_000;
+        let count = (0..users)
+            .filter(|&user_id| {
+                experiment.assign(&CohortContext::new(user_id, "US")) == Some(variant)
+            })
+            .count();
+        count as f64 / users as f64
+    }
+
+    #[test]
+    fn test_assignment_is_weighted_and_stable_under_ramp_up() {
+        let mut experiment = Experiment::ab("bait_filter_v2");
+        experiment.variants[1].weight = 3;
+        assert!((share(&experiment, CONTROL) - 0.25).abs() < 0.02);
+        assert!((share(&experiment, TREATMENT) - 0.75).abs() < 0.02);
+
+        experiment.allocation_percent = 10.0;
+        let before: Vec<_> = (0..1000u64)
+            .map(|user_id| experiment.assign(&CohortContext::new(user_id, "US")).map(String::from))
+            .collect();
+        assert!((share(&experiment, CONTROL) + share(&experiment, TREATMENT) - 0.1).abs() < 0.01);
+        experiment.allocation_percent = 50.0;
+        for (user_id, variant) in before.into_iter().enumerate() {
+            let ctx = CohortContext::new(user_id as u64, "US");
+            if variant.is_some() {
+                assert_eq!(experiment.assign(&ctx), variant.as_deref(), "kept their variant");
+            }
+        }
+
+        // Another salt splits users independently
+        let other = Experiment {
+            salt: Some("reshuffled".to_string()),
+            ..Experiment::ab("bait_filter_v2")
+        };
+        let moved = (0..1000u64)
+            .filter(|&user_id| {
+                let ctx = CohortContext::new(user_id, "US");
+                experiment.assign(&ctx) != other.assign(&ctx)
+            })
+            .count();
+        assert!(moved > 300);
+
+        experiment.cohort = Some(CohortSelector {
+            countries: vec!["GB".to_string()],
+            ..Default::default()
+        });
+        assert_eq!(share(&experiment, TREATMENT), 0.0);
+    }
+
+    #[test]
+    fn test_experiments_of_a_layer_never_share_users() {
+        let layered = |name: &str, allocation_percent| Experiment {
+            allocation_percent,
+            layer: Some("ranking".to_string()),
+            ..Experiment::ab(name)
+        };
+        let experiments = [layered("a", 30.0), Experiment::ab("b"), layered("c", 50.0)];
+        let (mut a, mut c) = (0usize, 0usize);
+        for user_id in 0..10_000 {
+            let ctx = CohortContext::new(user_id, "US");
+            let assignments = ExperimentAssignments::assign(&experiments, &ctx);
+            let variants = assignments.variants();
+            assert!(!(variants.contains_key("a") && variants.contains_key("c")), "{}", user_id);
+            assert!(variants.contains_key("b"), "other layers are independent");
+            a += variants.contains_key("a") as usize;
+            c += variants.contains_key("c") as usize;
+        }
+        assert!(a.abs_diff(3000) < 200, "{} users in a", a);
+        assert!(c.abs_diff(5000) < 200, "{} users in c", c);
+
+        let mut invalid = experiments.to_vec();
+        invalid.push(layered("d", 25.0));
+        assert_eq!(
+            validate(&invalid).unwrap_err(),
+            "experiments in layer ranking allocate over 100% of users"
+        );
+    }
+
+    #[test]
+    fn test_sticky_users_keep_their_first_variant() {
+        let buckets = StickyBuckets::new();
+        let assign = |experiment: &Experiment| -> Vec<Option<String>> {
+            (0..100)
+                .map(|user_id| {
+                    let ctx = CohortContext::new(user_id, "US");
+                    let experiments = std::slice::from_ref(experiment);
+                    let assignments =
+                        ExperimentAssignments::assign_sticky(experiments, &ctx, &buckets);
+                    assignments.variants().get("bait_filter_v2").cloned()
+                })
+                .collect()
+        };
+        let mut experiment = Experiment {
+            sticky: true,
+            ..Experiment::ab("bait_filter_v2")
+        };
+        let first = assign(&experiment);
+
+        // Reweighting and resalting move no one already assigned
+        experiment.variants[0].weight = 9;
+        experiment.salt = Some("reshuffled".to_string());
+        assert_eq!(assign(&experiment), first);
+        let unstuck = (0..100).map(|user_id| {
+            experiment.assign(&CohortContext::new(user_id, "US")).map(String::from)
+        });
+        assert_ne!(unstuck.collect::<Vec<_>>(), first);
+
+        // Users of a removed variant are reassigned, and no allocation ends it
+        experiment.variants.retain(|v| v.name == CONTROL);
+        assert!(assign(&experiment).iter().all(|v| v.as_deref() == Some(CONTROL)));
+        experiment.allocation_percent = 0.0;
+        assert!(assign(&experiment).iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn test_only_read_variants_are_exposures() {
+        let experiments = [Experiment::rollout("caching", 100), Experiment::ab("bait_filter_v2")];
+        let assignments = ExperimentAssignments::assign(&experiments, &CohortContext::new(7, "US"));
+        assert_eq!(assignments.variants().len(), 2);
+        assert!(assignments.exposures().is_empty());
+
+        assert_eq!(assignments.variant("caching"), Some(ENABLED));
+        assert_eq!(assignments.variant("missing"), None);
+        assert_eq!(
+            assignments.exposures(),
+            [Exposure {
+                experiment: "caching".to_string(),
+                variant: ENABLED.to_string(),
+            }]
+        );
+
+        assert!(validate(&experiments).is_ok());
+        let mut invalid = experiments.to_vec();
+        invalid.push(Experiment::rollout("caching", 10));
+        invalid[1].variants[0].name = TREATMENT.to_string();
+        assert_eq!(
+            validate(&invalid).unwrap_err(),
+            "experiment bait_filter_v2 has duplicate variants; experiment caching is defined twice"
+        );
+    }
+
+    #[test]
+    fn test_forced_variants_replace_assignments_without_exposures() {
+        let overrides =
+            FeatureOverrides::parse(" caching=off, bait_filter_v2=treatment,new=on").unwrap();
+        assert_eq!(overrides.flag("caching"), Some(false));
+        assert_eq!(overrides.flag("new"), Some(true));
+        assert_eq!(overrides.flag("batching"), None);
+
+        let experiments = [
+            Experiment::rollout("caching", 100),
+            Experiment::rollout("batching", 100),
+            Experiment::ab("bait_filter_v2"),
+        ];
+        let ctx = CohortContext::new(7, "US");
+        let assignments =
+            ExperimentAssignments::assign(&experiments, &ctx).with_overrides(&overrides);
+        assert_eq!(assignments.variant("caching"), None);
+        assert_eq!(assignments.variant("bait_filter_v2"), Some(TREATMENT));
+        assert_eq!(assignments.variant("new"), Some(ENABLED));
+        assert_eq!(assignments.variant("batching"), Some(ENABLED));
+        let exposed: Vec<_> = assignments.exposures().into_iter().map(|e| e.experiment).collect();
+        assert_eq!(exposed, ["batching"]);
+
+        assert!(FeatureOverrides::parse("caching").is_err());
+        assert!(FeatureOverrides::parse("=on").is_err());
+    }
+}
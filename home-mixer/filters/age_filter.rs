@@ -9,6 +9,7 @@ use crate::util::snowflake;
 use moka::sync::Cache;
 use std::time::Duration;
 use tonic::async_trait;
+use xai_candidate_pipeline::error::PipelineError;
 use xai_candidate_pipeline::filter::{Filter, FilterResult};
 
 /// Optimized age filter with timestamp caching
@@ -71,7 +72,7 @@ impl Filter<ScoredPostsQuery, PostCandidate> for AgeFilter {
         &self,
         _query: &ScoredPostsQuery,
         candidates: Vec<PostCandidate>,
-    ) -> Result<FilterResult<PostCandidate>, String> {
+    ) -> Result<FilterResult<PostCandidate>, PipelineError> {
         let (kept, removed): (Vec<_>, Vec<_>) = candidates
             .into_iter()
             .partition(|c| self.is_within_age(c.tweet_id));
@@ -0,0 +1,365 @@
+//! Operator-managed author allow/deny lists
+//!
+//! Lets on-call operators drop (or explicitly exempt) authors at runtime via the
+//! admin HTTP API, so emergency takedowns don't need a code change or restart.
+//! Entries may carry a TTL and the lists are persisted to disk on every change.
+
+use crate::candidate_pipeline::candidate::PostCandidate;
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::filters::ELIGIBILITY_FILTER_GROUP;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::filter::{Filter, FilterResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use tonic::async_trait;
+
+/// Which operator list an entry belongs to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthorListKind {
+    Allow,
+    Deny,
+}
+
+/// A single allow/deny entry
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuthorListEntry {
+    pub author_id: u64,
+    /// Free-form operator note (ticket, incident, ...)
+    #[serde(default)]
+    pub reason: Option<String>,
+    pub created_at_ms: i64,
+    /// Entry is ignored once this time has passed. `None` means no expiry.
+    #[serde(default)]
+    pub expires_at_ms: Option<i64>,
+}
+
+impl AuthorListEntry {
+    pub fn is_expired(&self, now_ms: i64) -> bool {
+        self.expires_at_ms.is_some_and(|expires| expires <= now_ms)
+    }
+}
+
+/// On-disk representation of both lists
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AuthorListSnapshot {
+    #[serde(default)]
+    pub allow: Vec<AuthorListEntry>,
+    #[serde(default)]
+    pub deny: Vec<AuthorListEntry>,
+}
+
+#[derive(Default)]
+struct AuthorLists {
+    allow: HashMap<u64, AuthorListEntry>,
+    deny: HashMap<u64, AuthorListEntry>,
+}
+
+impl AuthorLists {
+    fn list(&self, kind: AuthorListKind) -> &HashMap<u64, AuthorListEntry> {
+        match kind {
+            AuthorListKind::Allow => &self.allow,
+            AuthorListKind::Deny => &self.deny,
+        }
+    }
+
+    fn list_mut(&mut self, kind: AuthorListKind) -> &mut HashMap<u64, AuthorListEntry> {
+        match kind {
+            AuthorListKind::Allow => &mut self.allow,
+            AuthorListKind::Deny => &mut self.deny,
+        }
+    }
+
+    fn prune_expired(&mut self, now_ms: i64) {
+        self.allow.retain(|_, e| !e.is_expired(now_ms));
+        self.deny.retain(|_, e| !e.is_expired(now_ms));
+    }
+
+    fn snapshot(&self) -> AuthorListSnapshot {
+        let mut allow: Vec<_> = self.allow.values().cloned().collect();
+        let mut deny: Vec<_> = self.deny.values().cloned().collect();
+        allow.sort_by_key(|e| e.author_id);
+        deny.sort_by_key(|e| e.author_id);
+        AuthorListSnapshot { allow, deny }
+    }
+}
+
+/// Shared, optionally disk-backed store for the operator author lists
+#[derive(Default)]
+pub struct AuthorListStore {
+    lists: RwLock<AuthorLists>,
+    path: Option<PathBuf>,
+    /// Held from a change until its lists are written, so writes land in the
+    /// order the changes were made and never share the temporary file
+    persist_lock: Mutex<()>,
+}
+
+impl AuthorListStore {
+    /// In-memory store (nothing is persisted)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the store from `path`, starting empty if the file does not exist yet.
+    /// Every subsequent mutation is written back to the same file.
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let snapshot = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice::<AuthorListSnapshot>(&bytes)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => AuthorListSnapshot::default(),
+            Err(e) => return Err(e),
+        };
+
+        let mut lists = AuthorLists::default();
+        for entry in snapshot.allow {
+            lists.allow.insert(entry.author_id, entry);
+        }
+        for entry in snapshot.deny {
+            lists.deny.insert(entry.author_id, entry);
+        }
+        lists.prune_expired(now_ms());
+
+        Ok(Self {
+            lists: RwLock::new(lists),
+            path: Some(path),
+            persist_lock: Mutex::new(()),
+        })
+    }
+
+    /// Add (or replace) an entry. `ttl_secs = None` keeps the entry until removed.
+    pub fn insert(
+        &self,
+        kind: AuthorListKind,
+        author_id: u64,
+        ttl_secs: Option<u64>,
+        reason: Option<String>,
+    ) -> std::io::Result<AuthorListEntry> {
+        let now = now_ms();
+        let entry = AuthorListEntry {
+            author_id,
+            reason,
+            created_at_ms: now,
+            expires_at_ms: ttl_secs.map(|ttl| now.saturating_add((ttl as i64).saturating_mul(1000))),
+        };
+
+        let _persisting = self.persist_lock.lock().unwrap();
+        let snapshot = {
+            let mut lists = self.lists.write().unwrap();
+            lists.prune_expired(now);
+            lists.list_mut(kind).insert(author_id, entry.clone());
+            lists.snapshot()
+        };
+        self.persist(&snapshot)?;
+        Ok(entry)
+    }
+
+    /// Remove an entry. Returns whether the author was on the list.
+    pub fn remove(&self, kind: AuthorListKind, author_id: u64) -> std::io::Result<bool> {
+        let _persisting = self.persist_lock.lock().unwrap();
+        let (removed, snapshot) = {
+            let mut lists = self.lists.write().unwrap();
+            lists.prune_expired(now_ms());
+            let removed = lists.list_mut(kind).remove(&author_id).is_some();
+            (removed, lists.snapshot())
+        };
+        if removed {
+            self.persist(&snapshot)?;
+        }
+        Ok(removed)
+    }
+
+    pub fn contains(&self, kind: AuthorListKind, author_id: u64) -> bool {
+        let lists = self.lists.read().unwrap();
+        lists
+            .list(kind)
+            .get(&author_id)
+            .is_some_and(|e| !e.is_expired(now_ms()))
+    }
+
+    /// An author is blocked when denied and not explicitly allowed.
+    pub fn is_blocked(&self, author_id: u64) -> bool {
+        self.contains(AuthorListKind::Deny, author_id)
+            && !self.contains(AuthorListKind::Allow, author_id)
+    }
+
+    /// Current non-expired entries of both lists
+    pub fn snapshot(&self) -> AuthorListSnapshot {
+        let now = now_ms();
+        let mut snapshot = self.lists.read().unwrap().snapshot();
+        snapshot.allow.retain(|e| !e.is_expired(now));
+        snapshot.deny.retain(|e| !e.is_expired(now));
+        snapshot
+    }
+
+    fn persist(&self, snapshot: &AuthorListSnapshot) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let bytes = serde_json::to_vec_pretty(snapshot)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        // Write to a sibling file and rename so a crash never leaves a torn list behind
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, bytes)?;
+        std::fs::rename(&tmp, path)
+    }
+}
+
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+/// Removes candidates whose author (or retweeted author) is on the operator denylist
+pub struct AuthorListFilter {
+    store: Arc<AuthorListStore>,
+}
+
+impl AuthorListFilter {
+    pub fn new(store: Arc<AuthorListStore>) -> Self {
+        Self { store }
+    }
+
+    fn is_blocked(&self, candidate: &PostCandidate) -> bool {
+        self.store.is_blocked(candidate.author_id)
+            || candidate
+                .retweeted_user_id
+                .is_some_and(|id| self.store.is_blocked(id))
+    }
+}
+
+#[async_trait]
+impl Filter<ScoredPostsQuery, PostCandidate> for AuthorListFilter {
+    fn reorder_group(&self) -> Option<&'static str> {
+        Some(ELIGIBILITY_FILTER_GROUP)
+    }
+
+    // Denied authors must never be served, however slow the request
+    fn is_optional(&self) -> bool {
+        false
+    }
+
+    // Nothing can be served when the denylist couldn't be checked
+    fn fallback(
+        &self,
+        _query: &ScoredPostsQuery,
+        candidates: &[PostCandidate],
+    ) -> Option<FilterResult<PostCandidate>> {
+        Some(FilterResult {
+            kept: Vec::new(),
+            removed: candidates.to_vec(),
+        })
+    }
+
+    async fn filter(
+        &self,
+        _query: &ScoredPostsQuery,
+        candidates: Vec<PostCandidate>,
+    ) -> Result<FilterResult<PostCandidate>, PipelineError> {
+        let (removed, kept): (Vec<_>, Vec<_>) =
+            candidates.into_iter().partition(|c| self.is_blocked(c));
+
+        Ok(FilterResult { kept, removed })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(tweet_id: i64, author_id: u64) -> PostCandidate {
+        PostCandidate {
+            tweet_id,
+            author_id,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_denied_authors_are_removed() {
+        let store = Arc::new(AuthorListStore::new());
+        store.insert(AuthorListKind::Deny, 100, None, None).unwrap();
+
+        let mut retweet = candidate(3, 300);
+        retweet.retweeted_user_id = Some(100);
+
+        let filter = AuthorListFilter::new(store);
+        let result = filter
+            .filter(
+                &ScoredPostsQuery::default(),
+                vec![candidate(1, 100), candidate(2, 200), retweet],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(result.kept.len(), 1);
+        assert_eq!(result.kept[0].tweet_id, 2);
+        assert_eq!(result.removed.len(), 2);
+    }
+
+    #[test]
+    fn test_allow_overrides_deny() {
+        let store = AuthorListStore::new();
+        store.insert(AuthorListKind::Deny, 100, None, None).unwrap();
+        assert!(store.is_blocked(100));
+
+        store.insert(AuthorListKind::Allow, 100, None, None).unwrap();
+        assert!(!store.is_blocked(100));
+
+        assert!(store.remove(AuthorListKind::Allow, 100).unwrap());
+        assert!(store.is_blocked(100));
+    }
+
+    #[test]
+    fn test_expired_entries_are_ignored() {
+        let store = AuthorListStore::new();
+        store.insert(AuthorListKind::Deny, 100, Some(0), None).unwrap();
+
+        assert!(!store.is_blocked(100));
+        assert!(store.snapshot().deny.is_empty());
+    }
+
+    #[test]
+    fn test_lists_survive_reload() {
+        let path = std::env::temp_dir().join(format!("author_lists_{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let store = AuthorListStore::load(&path).unwrap();
+        store
+            .insert(AuthorListKind::Deny, 42, Some(3600), Some("incident".to_string()))
+            .unwrap();
+        drop(store);
+
+        let reloaded = AuthorListStore::load(&path).unwrap();
+        assert!(reloaded.is_blocked(42));
+        assert_eq!(reloaded.snapshot().deny[0].reason.as_deref(), Some("incident"));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_concurrent_changes_are_all_persisted() {
+        let path =
+            std::env::temp_dir().join(format!("author_lists_race_{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let store = Arc::new(AuthorListStore::load(&path).unwrap());
+        let writers: Vec<_> = (0..8)
+            .map(|author_id| {
+                let store = store.clone();
+                std::thread::spawn(move || {
+                    store.insert(AuthorListKind::Deny, author_id, None, None).unwrap();
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let reloaded = AuthorListStore::load(&path).unwrap();
+        assert_eq!(reloaded.snapshot().deny.len(), 8);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::candidate_pipeline::candidate::PostCandidate;
 use crate::candidate_pipeline::query::ScoredPostsQuery;
 use tonic::async_trait;
+use xai_candidate_pipeline::error::PipelineError;
 use xai_candidate_pipeline::filter::{Filter, FilterResult};
 
 // Remove candidates that are blocked or muted by the viewer
@@ -12,7 +13,7 @@ impl Filter<ScoredPostsQuery, PostCandidate> for AuthorSocialgraphFilter {
         &self,
         query: &ScoredPostsQuery,
         candidates: Vec<PostCandidate>,
-    ) -> Result<FilterResult<PostCandidate>, String> {
+    ) -> Result<FilterResult<PostCandidate>, PipelineError> {
         let viewer_blocked_user_ids = query.user_features.blocked_user_ids.clone();
         let viewer_muted_user_ids = query.user_features.muted_user_ids.clone();
 
@@ -2,85 +2,81 @@
 // Content Safety & Quality Filters
 // Addressing Real User Complaints: NSFW, Spam, Quality Issues
 
-use crate::candidate_pipeline::candidate::PostCandidate;
+use crate::candidate_pipeline::candidate::{CandidateHelpers, PostCandidate};
 use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::candidate_pipeline::query_features::SafetyFilterKind;
+use crate::config::FilterMode;
+use crate::filters::ELIGIBILITY_FILTER_GROUP;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::filter::{Filter, FilterResult};
 use std::collections::HashSet;
 use tonic::async_trait;
-use xai_candidate_pipeline::filter::{Filter, FilterResult};
+
+/// Content label the media pipeline puts on adult content
+pub const ADULT_CONTENT_LABEL: &str = "adult_content";
+
+/// The viewer asked for this content to be hidden, so a safety filter that
+/// fails or times out removes every candidate rather than serving them unchecked
+fn remove_all(candidates: &[PostCandidate]) -> Option<FilterResult<PostCandidate>> {
+    Some(FilterResult {
+        kept: Vec::new(),
+        removed: candidates.to_vec(),
+    })
+}
 
 /// NSFW/Adult Content Filter
-/// 
+///
 /// ADDRESSES USER COMPLAINT #1: "Porn showing up when I didn't ask for it"
-/// 
+///
 /// This filter removes adult content unless user has explicitly opted in.
 /// Uses multi-signal detection:
-/// 1. Content label from media pipeline
+/// 1. Content labels from the media pipeline
 /// 2. Text-based detection (keywords, patterns)
-/// 3. User's content preferences
-/// 4. Author's content rating
+/// 3. The same checks on the parent and quoted post, e.g. a clean quote of an NSFW post
+/// 4. User's content preferences
+///
+/// In strict mode adult content is removed even for viewers who opted in.
 pub struct NSFWContentFilter {
-    /// Use strict filtering by default
-    strict_mode: bool,
-    
     /// Blocked keywords for text analysis
     blocked_keywords: HashSet<String>,
 }
 
+impl Default for NSFWContentFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl NSFWContentFilter {
-    pub fn new(strict_mode: bool) -> Self {
-        // Load blocked keywords from configuration
-        let blocked_keywords = Self::load_nsfw_keywords();
-        
+    pub fn new() -> Self {
         Self {
-            strict_mode,
-            blocked_keywords,
+            blocked_keywords: Self::load_nsfw_keywords(),
         }
     }
-    
+
     fn load_nsfw_keywords() -> HashSet<String> {
         // In production, load from secure configuration
-        // Here's a minimal example
-        vec![
-            "nsfw".to_string(),
-            "18+".to_string(),
-            // ... extensive keyword list in production
-        ]
-        .into_iter()
-        .collect()
-    }
-    
+        ["nsfw", "18+"].into_iter().map(String::from).collect()
+    }
+
     fn is_nsfw_content(&self, candidate: &PostCandidate) -> bool {
-        // Check 1: Explicit content label from media pipeline
-        if candidate.content_labels.contains("adult_content") {
-            return true;
-        }
-        
-        // Check 2: Author has adult content rating
-        if candidate.author_content_rating == Some("adult") {
-            return true;
-        }
-        
-        // Check 3: Sensitive media flag
-        if candidate.has_sensitive_media.unwrap_or(false) {
+        self.is_nsfw(&candidate.labels, &candidate.tweet_text)
+            || candidate
+                .context_posts()
+                .any(|post| self.is_nsfw(&post.labels, &post.text))
+    }
+
+    fn is_nsfw(&self, labels: &[String], text: &str) -> bool {
+        if labels.iter().any(|l| l == ADULT_CONTENT_LABEL) {
             return true;
         }
-        
-        // Check 4: Text-based detection (fallback)
-        if let Some(text) = &candidate.text {
-            let text_lower = text.to_lowercase();
-            for keyword in &self.blocked_keywords {
-                if text_lower.contains(keyword) {
-                    return true;
-                }
-            }
-        }
-        
-        false
+        let text_lower = text.to_lowercase();
+        self.blocked_keywords.iter().any(|k| text_lower.contains(k.as_str()))
     }
-    
-    fn user_allows_nsfw(&self, query: &ScoredPostsQuery) -> bool {
-        // Check user's content preferences
-        query.user_preferences
+
+    fn user_allows_nsfw(query: &ScoredPostsQuery) -> bool {
+        query
+            .user_preferences
             .as_ref()
             .and_then(|prefs| prefs.show_sensitive_media)
             .unwrap_or(false)
@@ -89,332 +85,421 @@ impl NSFWContentFilter {
 
 #[async_trait]
 impl Filter<ScoredPostsQuery, PostCandidate> for NSFWContentFilter {
+    fn reorder_group(&self) -> Option<&'static str> {
+        Some(ELIGIBILITY_FILTER_GROUP)
+    }
+
+    fn is_optional(&self) -> bool {
+        false
+    }
+
+    fn fallback(
+        &self,
+        _query: &ScoredPostsQuery,
+        candidates: &[PostCandidate],
+    ) -> Option<FilterResult<PostCandidate>> {
+        remove_all(candidates)
+    }
+
+    fn enable(&self, query: &ScoredPostsQuery) -> bool {
+        query.filter_mode(SafetyFilterKind::Nsfw) != FilterMode::Off
+    }
+
     async fn filter(
         &self,
         query: &ScoredPostsQuery,
         candidates: Vec<PostCandidate>,
-    ) -> Result<FilterResult<PostCandidate>, String> {
-        let user_opted_in = self.user_allows_nsfw(query);
-        
+    ) -> Result<FilterResult<PostCandidate>, PipelineError> {
+        let strict = query.filter_mode(SafetyFilterKind::Nsfw) == FilterMode::Strict;
+        let user_opted_in = Self::user_allows_nsfw(query) && !strict;
+
         let (kept, removed): (Vec<_>, Vec<_>) = candidates
             .into_iter()
-            .partition(|candidate| {
-                let is_nsfw = self.is_nsfw_content(candidate);
-                
-                if is_nsfw {
-                    // If NSFW, only keep if user explicitly opted in
-                    user_opted_in && !self.strict_mode
-                } else {
-                    // Keep all non-NSFW content
-                    true
-                }
-            });
-        
-        log::info!(
-            "NSFW filter: kept {} tweets, removed {} NSFW tweets (user_opted_in: {})",
-            kept.len(),
+            .partition(|c| user_opted_in || !self.is_nsfw_content(c));
+
+        log::debug!(
+            "NSFW filter: removed {} NSFW tweets (user_opted_in: {})",
             removed.len(),
             user_opted_in
         );
-        
+
         Ok(FilterResult { kept, removed })
     }
 }
 
 /// Engagement Bait Filter
-/// 
+///
 /// ADDRESSES USER COMPLAINT #2: "Stop showing me clickbait and rage bait"
-/// 
+///
 /// Detects and penalizes:
 /// - "You won't believe..." patterns
 /// - "This will shock you..." patterns
 /// - Excessive emoji usage
-/// - Fake urgency ("BREAKING:", "URGENT:")
+/// - Fake urgency and shouting (mostly capitals)
 /// - Engagement farming ("Like and RT if...")
 pub struct EngagementBaitFilter {
     /// Patterns that indicate engagement bait
     bait_patterns: Vec<String>,
-    
+
     /// Threshold for emoji density (emojis per character)
     max_emoji_density: f64,
 }
 
+impl Default for EngagementBaitFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl EngagementBaitFilter {
     pub fn new() -> Self {
         Self {
-            bait_patterns: vec![
-                "you won't believe".to_string(),
-                "this will shock you".to_string(),
-                "number 7 will".to_string(),
-                "doctors hate".to_string(),
-                "like and retweet".to_string(),
-                "like and rt".to_string(),
-                "thread 🧵".to_string(), // Often used for engagement farming
-                "let that sink in".to_string(),
-                "read that again".to_string(),
-            ],
+            bait_patterns: [
+                "you won't believe",
+                "this will shock you",
+                "number 7 will",
+                "doctors hate",
+                "like and retweet",
+                "like and rt",
+                "thread 🧵", // Often used for engagement farming
+                "let that sink in",
+                "read that again",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
             max_emoji_density: 0.15, // 15% of text is emojis = suspicious
         }
     }
-    
+
     fn is_engagement_bait(&self, candidate: &PostCandidate) -> bool {
-        if let Some(text) = &candidate.text {
-            let text_lower = text.to_lowercase();
-            
-            // Check for bait patterns
-            for pattern in &self.bait_patterns {
-                if text_lower.contains(pattern) {
-                    return true;
-                }
-            }
-            
-            // Check emoji density
-            let emoji_count = text.chars().filter(|c| self.is_emoji(*c)).count();
-            let emoji_density = emoji_count as f64 / text.len() as f64;
-            
-            if emoji_density > self.max_emoji_density {
-                return true;
-            }
-            
-            // Check for excessive capitalization
-            let caps_count = text.chars().filter(|c| c.is_uppercase()).count();
-            let caps_ratio = caps_count as f64 / text.len() as f64;
-            
-            if caps_ratio > 0.5 && text.len() > 20 {
-                return true; // MORE THAN HALF IS CAPS = SHOUTING
-            }
+        let text = &candidate.tweet_text;
+        let text_lower = text.to_lowercase();
+        if self.bait_patterns.iter().any(|p| text_lower.contains(p.as_str())) {
+            return true;
         }
-        
-        false
+
+        let chars = text.chars().count();
+        if chars == 0 {
+            return false;
+        }
+        let emoji_count = text.chars().filter(|c| Self::is_emoji(*c)).count();
+        if emoji_count as f64 / chars as f64 > self.max_emoji_density {
+            return true;
+        }
+
+        // MORE THAN HALF IS CAPS = SHOUTING
+        let caps_count = text.chars().filter(|c| c.is_uppercase()).count();
+        caps_count as f64 / chars as f64 > 0.5 && chars > 20
     }
-    
-    fn is_emoji(&self, c: char) -> bool {
+
+    fn is_emoji(c: char) -> bool {
         // Simplified emoji detection
         // In production, use proper Unicode emoji ranges
         let code = c as u32;
-        (code >= 0x1F600 && code <= 0x1F64F) || // Emoticons
-        (code >= 0x1F300 && code <= 0x1F5FF) || // Misc Symbols
-        (code >= 0x1F680 && code <= 0x1F6FF) || // Transport
-        (code >= 0x2600 && code <= 0x26FF)      // Misc symbols
+        (0x1F600..=0x1F64F).contains(&code) // Emoticons
+            || (0x1F300..=0x1F5FF).contains(&code) // Misc Symbols
+            || (0x1F680..=0x1F6FF).contains(&code) // Transport
+            || (0x2600..=0x26FF).contains(&code) // Misc symbols
     }
 }
 
 #[async_trait]
 impl Filter<ScoredPostsQuery, PostCandidate> for EngagementBaitFilter {
+    fn reorder_group(&self) -> Option<&'static str> {
+        Some(ELIGIBILITY_FILTER_GROUP)
+    }
+
+    fn is_optional(&self) -> bool {
+        false
+    }
+
+    fn fallback(
+        &self,
+        _query: &ScoredPostsQuery,
+        candidates: &[PostCandidate],
+    ) -> Option<FilterResult<PostCandidate>> {
+        remove_all(candidates)
+    }
+
+    fn enable(&self, query: &ScoredPostsQuery) -> bool {
+        query.filter_mode(SafetyFilterKind::EngagementBait) != FilterMode::Off
+    }
+
     async fn filter(
         &self,
         _query: &ScoredPostsQuery,
         candidates: Vec<PostCandidate>,
-    ) -> Result<FilterResult<PostCandidate>, String> {
+    ) -> Result<FilterResult<PostCandidate>, PipelineError> {
         let (kept, removed): (Vec<_>, Vec<_>) = candidates
             .into_iter()
             .partition(|c| !self.is_engagement_bait(c));
-        
-        log::info!(
-            "Engagement bait filter: removed {} clickbait tweets",
-            removed.len()
-        );
-        
+
+        log::debug!("Engagement bait filter: removed {} clickbait tweets", removed.len());
+
         Ok(FilterResult { kept, removed })
     }
 }
 
+/// Author report rates above which `SpamBotFilter` removes posts
+pub const STANDARD_MAX_REPORT_RATE: f64 = 0.03;
+pub const STRICT_MAX_REPORT_RATE: f64 = 0.01;
+
 /// Spam & Bot Detection Filter
-/// 
+///
 /// ADDRESSES USER COMPLAINT #3: "Fake crypto giveaways and reply bots everywhere"
-/// 
-/// Detects:
-/// - Crypto scam patterns
-/// - Impersonation attempts
-/// - Suspicious account age + activity
-/// - Copy-paste spam
+///
+/// Detects crypto scam and copy-paste spam patterns in the post and in the post
+/// it quotes, which it spreads. A reply is not spam for answering spam. Authors
+/// enough viewers flagged as spam, or too many viewers reported, are spam
+/// whatever they post, per `author_quality` from `AuthorProfileHydrator`.
 pub struct SpamBotFilter {
     /// Known spam patterns
     spam_patterns: Vec<String>,
 }
 
+impl Default for SpamBotFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl SpamBotFilter {
     pub fn new() -> Self {
         Self {
-            spam_patterns: vec![
-                "send me".to_string(),
-                "claim your".to_string(),
-                "free bitcoin".to_string(),
-                "double your crypto".to_string(),
-                "limited time offer".to_string(),
-                "click here now".to_string(),
-                "exclusive offer".to_string(),
-                "act now".to_string(),
-            ],
+            spam_patterns: [
+                "send me",
+                "claim your",
+                "free bitcoin",
+                "double your crypto",
+                "limited time offer",
+                "click here now",
+                "exclusive offer",
+                "act now",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
         }
     }
-    
-    fn is_spam(&self, candidate: &PostCandidate) -> bool {
-        // Check 1: Known spam patterns
-        if let Some(text) = &candidate.text {
-            let text_lower = text.to_lowercase();
-            for pattern in &self.spam_patterns {
-                if text_lower.contains(pattern) {
-                    return true;
-                }
-            }
-        }
-        
-        // Check 2: Suspicious author metrics
-        if let Some(follower_count) = candidate.author_follower_count {
-            if let Some(following_count) = candidate.author_following_count {
-                // Suspicious: Following 10x more than followers
-                if following_count > follower_count * 10 && follower_count < 100 {
-                    return true;
-                }
-            }
+
+    /// Report rate above which an author's posts are spam; strict mode (viewer
+    /// opt-in) flags reported authors more aggressively. A locale's override
+    /// replaces the standard rate and caps the strict one.
+    pub fn max_report_rate(query: &ScoredPostsQuery, strict: bool) -> f64 {
+        let locale = query.locale_params.overrides.spam_max_report_rate;
+        match (locale, strict) {
+            (Some(rate), true) => rate.min(STRICT_MAX_REPORT_RATE),
+            (Some(rate), false) => rate,
+            (None, true) => STRICT_MAX_REPORT_RATE,
+            (None, false) => STANDARD_MAX_REPORT_RATE,
         }
-        
-        // Check 3: Account age vs activity
-        if let Some(account_age_days) = candidate.author_account_age_days {
-            if let Some(tweet_count) = candidate.author_tweet_count {
-                // New account (<30 days) with tons of tweets (>100/day)
-                let tweets_per_day = tweet_count as f64 / account_age_days as f64;
-                if account_age_days < 30 && tweets_per_day > 100.0 {
-                    return true;
-                }
+    }
+
+    fn is_spam(&self, candidate: &PostCandidate, max_report_rate: f64) -> bool {
+        if let Some(quality) = &candidate.author_quality {
+            if quality.spam_flagged || quality.report_rate > max_report_rate {
+                return true;
             }
         }
-        
-        // Check 4: Verified impersonation
-        if candidate.is_verified_impersonation.unwrap_or(false) {
-            return true;
-        }
-        
-        false
+        self.matches_pattern(&candidate.tweet_text)
+            || candidate
+                .quoted_post
+                .as_ref()
+                .is_some_and(|post| self.matches_pattern(&post.text))
+    }
+
+    fn matches_pattern(&self, text: &str) -> bool {
+        let text_lower = text.to_lowercase();
+        self.spam_patterns.iter().any(|p| text_lower.contains(p.as_str()))
     }
 }
 
 #[async_trait]
 impl Filter<ScoredPostsQuery, PostCandidate> for SpamBotFilter {
-    async fn filter(
+    fn reorder_group(&self) -> Option<&'static str> {
+        Some(ELIGIBILITY_FILTER_GROUP)
+    }
+
+    fn is_optional(&self) -> bool {
+        false
+    }
+
+    fn fallback(
         &self,
         _query: &ScoredPostsQuery,
-        candidates: Vec<PostCandidate>,
-    ) -> Result<FilterResult<PostCandidate>, String> {
-        let (kept, removed): (Vec<_>, Vec<_>) = candidates
-            .into_iter()
-            .partition(|c| !self.is_spam(c));
-        
-        log::info!(
-            "Spam bot filter: removed {} spam/bot tweets",
-            removed.len()
-        );
-        
-        Ok(FilterResult { kept, removed })
+        candidates: &[PostCandidate],
+    ) -> Option<FilterResult<PostCandidate>> {
+        remove_all(candidates)
     }
-}
-
-/// Echo Chamber Diversification Scorer
-/// 
-/// ADDRESSES USER COMPLAINT #4: "Algorithm only shows me opinions I agree with"
-/// 
-/// Boosts content from outside user's typical interests
-/// to encourage diverse perspectives
-pub struct DiversityBoostScorer {
-    /// How much to boost diverse content (multiplier)
-    diversity_boost: f64,
-}
 
-impl DiversityBoostScorer {
-    pub fn new(diversity_boost: f64) -> Self {
-        Self { diversity_boost }
-    }
-    
-    fn is_outside_bubble(&self, candidate: &PostCandidate, query: &ScoredPostsQuery) -> bool {
-        // Check if author is outside user's typical network
-        if let Some(user_typical_topics) = &query.user_interest_topics {
-            if let Some(tweet_topics) = &candidate.topics {
-                // Count topic overlap
-                let overlap: usize = tweet_topics
-                    .iter()
-                    .filter(|topic| user_typical_topics.contains(*topic))
-                    .count();
-                
-                // If less than 30% overlap, it's diverse content
-                let overlap_ratio = overlap as f64 / tweet_topics.len() as f64;
-                return overlap_ratio < 0.3;
-            }
-        }
-        
-        false
+    fn enable(&self, query: &ScoredPostsQuery) -> bool {
+        query.filter_mode(SafetyFilterKind::Spam) != FilterMode::Off
     }
-}
 
-#[async_trait]
-impl Scorer<ScoredPostsQuery, PostCandidate> for DiversityBoostScorer {
-    async fn score(
+    async fn filter(
         &self,
         query: &ScoredPostsQuery,
-        candidates: &[PostCandidate],
-    ) -> Result<Vec<PostCandidate>, String> {
-        let scored = candidates
-            .iter()
-            .map(|c| {
-                let boost = if self.is_outside_bubble(c, query) {
-                    self.diversity_boost
-                } else {
-                    1.0
-                };
-                
-                PostCandidate {
-                    diversity_boost: Some(boost),
-                    ..Default::default()
-                }
-            })
-            .collect();
-        
-        Ok(scored)
-    }
-    
-    fn update(&self, candidate: &mut PostCandidate, scored: PostCandidate) {
-        if let Some(boost) = scored.diversity_boost {
-            // Apply boost to final score
-            if let Some(current_score) = candidate.weighted_score {
-                candidate.weighted_score = Some(current_score * boost);
-            }
-        }
+        candidates: Vec<PostCandidate>,
+    ) -> Result<FilterResult<PostCandidate>, PipelineError> {
+        let strict = query.filter_mode(SafetyFilterKind::Spam) == FilterMode::Strict;
+        let max_report_rate = Self::max_report_rate(query, strict);
+        let (kept, removed): (Vec<_>, Vec<_>) = candidates
+            .into_iter()
+            .partition(|c| !self.is_spam(c, max_report_rate));
+
+        log::debug!("Spam bot filter: removed {} spam/bot tweets", removed.len());
+
+        Ok(FilterResult { kept, removed })
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    
+    use crate::candidate_pipeline::candidate::RelatedPost;
+    use crate::candidate_pipeline::query_features::{FilterOverride, UserPreferences};
+    use crate::config::{LocaleParams, SafetyConfig, SafetyFilterModes};
+    use crate::personalization::author_profiles::AuthorQuality;
+    use std::sync::Arc;
+
+    fn post(text: &str) -> PostCandidate {
+        PostCandidate {
+            tweet_text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn test_nsfw_detection() {
-        let filter = NSFWContentFilter::new(true);
-        
-        let mut candidate = PostCandidate::default();
-        candidate.has_sensitive_media = Some(true);
-        
-        assert!(filter.is_nsfw_content(&candidate));
-    }
-    
+        let filter = NSFWContentFilter::new();
+        let labeled = PostCandidate {
+            labels: vec![ADULT_CONTENT_LABEL.to_string()],
+            ..Default::default()
+        };
+
+        assert!(filter.is_nsfw_content(&labeled));
+        assert!(filter.is_nsfw_content(&post("NSFW, you've been warned")));
+        assert!(!filter.is_nsfw_content(&post("Sunset over the bay")));
+    }
+
+    #[test]
+    fn test_nsfw_context_is_checked() {
+        let filter = NSFWContentFilter::new();
+        let labeled = RelatedPost {
+            labels: vec![ADULT_CONTENT_LABEL.to_string()],
+            ..Default::default()
+        };
+        let quote = PostCandidate {
+            quoted_post: Some(labeled.clone()),
+            ..post("lol look at this")
+        };
+        let reply = PostCandidate {
+            parent_post: Some(labeled),
+            ..post("lol look at this")
+        };
+
+        assert!(filter.is_nsfw_content(&quote));
+        assert!(filter.is_nsfw_content(&reply));
+    }
+
     #[test]
     fn test_engagement_bait_detection() {
         let filter = EngagementBaitFilter::new();
-        
-        let mut candidate = PostCandidate::default();
-        candidate.text = Some("You won't believe what happened next!".to_string());
-        
-        assert!(filter.is_engagement_bait(&candidate));
+
+        assert!(filter.is_engagement_bait(&post("You won't believe what happened next!")));
+        assert!(filter.is_engagement_bait(&post("THIS IS THE BIGGEST NEWS OF THE YEAR")));
+        assert!(!filter.is_engagement_bait(&post("Quarterly results are out")));
     }
-    
+
     #[test]
     fn test_spam_detection() {
         let filter = SpamBotFilter::new();
-        
-        let mut candidate = PostCandidate::default();
-        candidate.text = Some("Send me Bitcoin and I'll double it!".to_string());
-        candidate.author_follower_count = Some(10);
-        candidate.author_following_count = Some(5000);
-        
-        assert!(filter.is_spam(&candidate));
+        let is_spam = |c: &PostCandidate| filter.is_spam(c, STANDARD_MAX_REPORT_RATE);
+
+        assert!(is_spam(&post("Send me Bitcoin and I'll double it!")));
+        assert!(!is_spam(&post("Bitcoin is up today")));
+
+        let spam = RelatedPost {
+            text: "Claim your free bitcoin".to_string(),
+            ..Default::default()
+        };
+        let quote = PostCandidate {
+            quoted_post: Some(spam.clone()),
+            ..post("Giving back to the community")
+        };
+        let reply = PostCandidate {
+            parent_post: Some(spam),
+            ..post("This is a scam, don't click")
+        };
+        assert!(is_spam(&quote));
+        assert!(!is_spam(&reply), "replying to spam isn't spam");
+    }
+
+    #[test]
+    fn test_spam_detection_from_author_quality() {
+        let filter = SpamBotFilter::new();
+        let reported = |quality| PostCandidate {
+            author_quality: Some(quality),
+            ..post("Good morning")
+        };
+        let candidate = reported(AuthorQuality {
+            report_rate: 0.02,
+            ..Default::default()
+        });
+
+        assert!(!filter.is_spam(&candidate, STANDARD_MAX_REPORT_RATE));
+        assert!(filter.is_spam(&candidate, STRICT_MAX_REPORT_RATE));
+        let flagged = reported(AuthorQuality {
+            spam_flagged: true,
+            ..Default::default()
+        });
+        assert!(filter.is_spam(&flagged, STANDARD_MAX_REPORT_RATE));
+    }
+
+    #[test]
+    fn test_locale_override_tightens_report_rate() {
+        let mut query = ScoredPostsQuery::default();
+        assert_eq!(SpamBotFilter::max_report_rate(&query, false), STANDARD_MAX_REPORT_RATE);
+
+        let mut params = LocaleParams::default();
+        params.overrides.spam_max_report_rate = Some(0.015);
+        query.locale_params = Arc::new(params);
+        assert_eq!(SpamBotFilter::max_report_rate(&query, false), 0.015);
+        assert_eq!(SpamBotFilter::max_report_rate(&query, true), STRICT_MAX_REPORT_RATE);
+    }
+
+    #[tokio::test]
+    async fn test_modes_follow_the_viewers_overrides() {
+        let safety = SafetyConfig {
+            nsfw_strict_mode: false,
+            ..Default::default()
+        };
+        let query = |overrides: &[(SafetyFilterKind, FilterOverride)]| {
+            let preferences = UserPreferences {
+                show_sensitive_media: Some(true),
+                filter_overrides: overrides.iter().copied().collect(),
+                ..Default::default()
+            };
+            ScoredPostsQuery {
+                safety_filter_modes: Some(SafetyFilterModes::resolve(&safety, Some(&preferences))),
+                user_preferences: Some(preferences),
+                ..Default::default()
+            }
+        };
+        let nsfw = NSFWContentFilter::new();
+        let kept = |query| {
+            let nsfw = &nsfw;
+            async move { nsfw.filter(&query, vec![post("nsfw")]).await.unwrap().kept.len() }
+        };
+
+        // Opted in, so only strict mode hides adult content
+        assert_eq!(kept(query(&[])).await, 1);
+        assert_eq!(kept(query(&[(SafetyFilterKind::Nsfw, FilterOverride::Strict)])).await, 0);
+
+        let bait = EngagementBaitFilter::new();
+        assert!(bait.enable(&query(&[])));
+        let disabled = query(&[(SafetyFilterKind::EngagementBait, FilterOverride::Disabled)]);
+        assert!(!bait.enable(&disabled));
     }
 }
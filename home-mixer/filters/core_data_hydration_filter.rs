@@ -1,6 +1,7 @@
 use crate::candidate_pipeline::candidate::PostCandidate;
 use crate::candidate_pipeline::query::ScoredPostsQuery;
 use tonic::async_trait;
+use xai_candidate_pipeline::error::PipelineError;
 use xai_candidate_pipeline::filter::{Filter, FilterResult};
 
 pub struct CoreDataHydrationFilter;
@@ -11,7 +12,7 @@ impl Filter<ScoredPostsQuery, PostCandidate> for CoreDataHydrationFilter {
         &self,
         _query: &ScoredPostsQuery,
         candidates: Vec<PostCandidate>,
-    ) -> Result<FilterResult<PostCandidate>, String> {
+    ) -> Result<FilterResult<PostCandidate>, PipelineError> {
         let (kept, removed) = candidates
             .into_iter()
             .partition(|c| c.author_id != 0 && !c.tweet_text.trim().is_empty());
@@ -2,6 +2,7 @@ use crate::candidate_pipeline::candidate::PostCandidate;
 use crate::candidate_pipeline::query::ScoredPostsQuery;
 use std::collections::HashMap;
 use tonic::async_trait;
+use xai_candidate_pipeline::error::PipelineError;
 use xai_candidate_pipeline::filter::{Filter, FilterResult};
 
 /// Keeps only the highest-scored candidate per branch of a conversation tree
@@ -13,7 +14,7 @@ impl Filter<ScoredPostsQuery, PostCandidate> for DedupConversationFilter {
         &self,
         _query: &ScoredPostsQuery,
         candidates: Vec<PostCandidate>,
-    ) -> Result<FilterResult<PostCandidate>, String> {
+    ) -> Result<FilterResult<PostCandidate>, PipelineError> {
         let mut kept: Vec<PostCandidate> = Vec::new();
         let mut removed: Vec<PostCandidate> = Vec::new();
         let mut best_per_convo: HashMap<u64, (usize, f64)> = HashMap::new();
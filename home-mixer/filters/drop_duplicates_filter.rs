@@ -2,6 +2,7 @@ use crate::candidate_pipeline::candidate::PostCandidate;
 use crate::candidate_pipeline::query::ScoredPostsQuery;
 use std::collections::HashSet;
 use tonic::async_trait;
+use xai_candidate_pipeline::error::PipelineError;
 use xai_candidate_pipeline::filter::{Filter, FilterResult};
 
 pub struct DropDuplicatesFilter;
@@ -12,7 +13,7 @@ impl Filter<ScoredPostsQuery, PostCandidate> for DropDuplicatesFilter {
         &self,
         _query: &ScoredPostsQuery,
         candidates: Vec<PostCandidate>,
-    ) -> Result<FilterResult<PostCandidate>, String> {
+    ) -> Result<FilterResult<PostCandidate>, PipelineError> {
         let mut seen_ids = HashSet::new();
         let mut kept = Vec::new();
         let mut removed = Vec::new();
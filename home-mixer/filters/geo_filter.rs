@@ -0,0 +1,225 @@
+//! Geo / locale eligibility
+//!
+//! Legal-compliance deployments must not serve posts that are withheld in the
+//! viewer's country. Optionally, posts written in a language unrelated to the
+//! request locale can be downranked.
+
+use crate::candidate_pipeline::candidate::PostCandidate;
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::filters::ELIGIBILITY_FILTER_GROUP;
+use crate::util::config_watcher::ConfigWatcher;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::filter::{Filter, FilterResult};
+use candidate_pipeline::scorer::Scorer;
+use std::sync::Arc;
+use tonic::async_trait;
+
+/// Country code meaning "withheld in every country"
+const WITHHELD_EVERYWHERE: &str = "XX";
+
+/// Language codes that carry no language information (undetermined / no linguistic content)
+const NEUTRAL_LANGUAGES: [&str; 2] = ["und", "zxx"];
+
+/// Removes candidates withheld in the viewer's request country
+pub struct GeoEligibilityFilter;
+
+impl GeoEligibilityFilter {
+    fn is_withheld(candidate: &PostCandidate, country_code: &str) -> bool {
+        candidate.withheld_countries.iter().any(|withheld| {
+            withheld.eq_ignore_ascii_case(WITHHELD_EVERYWHERE)
+                || withheld.eq_ignore_ascii_case(country_code)
+        })
+    }
+}
+
+#[async_trait]
+impl Filter<ScoredPostsQuery, PostCandidate> for GeoEligibilityFilter {
+    fn reorder_group(&self) -> Option<&'static str> {
+        Some(ELIGIBILITY_FILTER_GROUP)
+    }
+
+    // Withholding is a legal requirement, so a timeout mustn't serve withheld posts
+    fn is_optional(&self) -> bool {
+        false
+    }
+
+    // Nor may an error, so nothing is served unchecked
+    fn fallback(
+        &self,
+        _query: &ScoredPostsQuery,
+        candidates: &[PostCandidate],
+    ) -> Option<FilterResult<PostCandidate>> {
+        Some(FilterResult {
+            kept: Vec::new(),
+            removed: candidates.to_vec(),
+        })
+    }
+
+    // Nor may operators switch it off for everyone; `enable_geo_filter` is the
+    // deliberate way to
+    fn is_killable(&self) -> bool {
+        false
+    }
+
+    async fn filter(
+        &self,
+        query: &ScoredPostsQuery,
+        candidates: Vec<PostCandidate>,
+    ) -> Result<FilterResult<PostCandidate>, PipelineError> {
+        let country_code = query.country_code.trim();
+
+        let (removed, kept): (Vec<_>, Vec<_>) = candidates
+            .into_iter()
+            .partition(|c| Self::is_withheld(c, country_code));
+
+        Ok(FilterResult { kept, removed })
+    }
+}
+
+/// Downranks candidates whose language doesn't match the request locale.
+///
+/// Multiplies `weighted_score`, and the final score when one is already set, by
+/// `language_mismatch_penalty`, so it must run after the weighted scorer.
+pub struct LanguageMismatchScorer {
+    /// Source of `language_mismatch_penalty`, read per request
+    config: Arc<ConfigWatcher>,
+}
+
+impl LanguageMismatchScorer {
+    pub fn new(config: Arc<ConfigWatcher>) -> Self {
+        Self { config }
+    }
+
+    fn penalty(&self) -> f64 {
+        self.config.config().safety.language_mismatch_penalty
+    }
+
+    fn is_mismatch(candidate: &PostCandidate, request_language: &str) -> bool {
+        let Some(language) = candidate.language_code.as_deref() else {
+            return false;
+        };
+        let language = primary_subtag(language);
+        let request_language = primary_subtag(request_language);
+        if language.is_empty() || request_language.is_empty() {
+            return false;
+        }
+        if NEUTRAL_LANGUAGES.iter().any(|l| l.eq_ignore_ascii_case(language)) {
+            return false;
+        }
+        !language.eq_ignore_ascii_case(request_language)
+    }
+}
+
+/// "en-US" / "en_GB" -> "en"
+fn primary_subtag(code: &str) -> &str {
+    code.trim().split(['-', '_']).next().unwrap_or_default()
+}
+
+#[async_trait]
+impl Scorer<ScoredPostsQuery, PostCandidate> for LanguageMismatchScorer {
+    fn enable(&self, query: &ScoredPostsQuery) -> bool {
+        self.penalty() < 1.0 && !query.language_code.is_empty()
+    }
+
+    async fn score(
+        &self,
+        query: &ScoredPostsQuery,
+        candidates: &[PostCandidate],
+    ) -> Result<Vec<PostCandidate>, PipelineError> {
+        let penalty = self.penalty();
+        let scored = candidates
+            .iter()
+            .map(|c| {
+                let multiplier = if Self::is_mismatch(c, &query.language_code) {
+                    penalty
+                } else {
+                    1.0
+                };
+                PostCandidate {
+                    weighted_score: c.weighted_score.map(|s| s * multiplier),
+                    score: c.score.map(|s| s * multiplier),
+                    ..Default::default()
+                }
+            })
+            .collect();
+
+        Ok(scored)
+    }
+
+    fn update(&self, candidate: &mut PostCandidate, scored: PostCandidate) {
+        candidate.weighted_score = scored.weighted_score;
+        candidate.score = scored.score;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn scorer(penalty: f64) -> LanguageMismatchScorer {
+        let mut config = Config::default();
+        config.safety.language_mismatch_penalty = penalty;
+        LanguageMismatchScorer::new(Arc::new(ConfigWatcher::new(config)))
+    }
+
+    fn candidate(tweet_id: i64, withheld: &[&str], language: Option<&str>) -> PostCandidate {
+        PostCandidate {
+            tweet_id,
+            withheld_countries: withheld.iter().map(|c| c.to_string()).collect(),
+            language_code: language.map(|l| l.to_string()),
+            weighted_score: Some(10.0),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_withheld_candidates_are_removed() {
+        let query = ScoredPostsQuery {
+            country_code: "de".to_string(),
+            ..Default::default()
+        };
+        let candidates = vec![
+            candidate(1, &["DE", "FR"], None),
+            candidate(2, &["FR"], None),
+            candidate(3, &["XX"], None),
+            candidate(4, &[], None),
+        ];
+
+        let result = GeoEligibilityFilter.filter(&query, candidates).await.unwrap();
+
+        let kept: Vec<_> = result.kept.iter().map(|c| c.tweet_id).collect();
+        assert_eq!(kept, vec![2, 4]);
+        assert_eq!(result.removed.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_language_mismatch_is_downranked() {
+        let query = ScoredPostsQuery {
+            language_code: "en-US".to_string(),
+            ..Default::default()
+        };
+        let candidates = vec![
+            candidate(1, &[], Some("en")),
+            candidate(2, &[], Some("ja")),
+            candidate(3, &[], Some("und")),
+            candidate(4, &[], None),
+        ];
+
+        let scorer = scorer(0.5);
+        assert!(scorer.enable(&query));
+        let scored = scorer.score(&query, &candidates).await.unwrap();
+        let scores: Vec<_> = scored.iter().map(|c| c.weighted_score.unwrap()).collect();
+
+        assert_eq!(scores, vec![10.0, 5.0, 10.0, 10.0]);
+    }
+
+    #[test]
+    fn test_disabled_without_penalty() {
+        let query = ScoredPostsQuery {
+            language_code: "en".to_string(),
+            ..Default::default()
+        };
+        assert!(!scorer(1.0).enable(&query));
+    }
+}
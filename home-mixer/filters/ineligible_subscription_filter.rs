@@ -1,19 +1,59 @@
-use crate::candidate_pipeline::candidate::PostCandidate;
+use crate::candidate_pipeline::candidate::{Entitlements, PostCandidate};
 use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::filters::ELIGIBILITY_FILTER_GROUP;
+use crate::util::config_watcher::ConfigWatcher;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::filter::{Filter, FilterResult};
 use std::collections::HashSet;
+use std::sync::Arc;
 use tonic::async_trait;
-use xai_candidate_pipeline::filter::{Filter, FilterResult};
 
 /// Filters out subscription-only posts from authors the viewer is not subscribed to.
-pub struct IneligibleSubscriptionFilter;
+///
+/// Uses the hydrated `entitlements` when present and falls back to the viewer's
+/// subscribed user ids otherwise, under which authors see their own posts. With
+/// `show_previews` set, ineligible posts are kept and marked with
+/// `subscription_preview` instead of being dropped.
+#[derive(Default)]
+pub struct IneligibleSubscriptionFilter {
+    pub show_previews: bool,
+    /// When set, `show_subscription_previews` of the config in use replaces
+    /// `show_previews` on each request
+    config: Option<Arc<ConfigWatcher>>,
+}
+
+impl IneligibleSubscriptionFilter {
+    pub fn new(show_previews: bool) -> Self {
+        Self {
+            show_previews,
+            config: None,
+        }
+    }
+
+    pub fn with_config(mut self, config: Arc<ConfigWatcher>) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    fn show_previews(&self) -> bool {
+        match &self.config {
+            Some(config) => config.config().safety.show_subscription_previews,
+            None => self.show_previews,
+        }
+    }
+}
 
 #[async_trait]
 impl Filter<ScoredPostsQuery, PostCandidate> for IneligibleSubscriptionFilter {
+    fn reorder_group(&self) -> Option<&'static str> {
+        Some(ELIGIBILITY_FILTER_GROUP)
+    }
+
     async fn filter(
         &self,
         query: &ScoredPostsQuery,
         candidates: Vec<PostCandidate>,
-    ) -> Result<FilterResult<PostCandidate>, String> {
+    ) -> Result<FilterResult<PostCandidate>, PipelineError> {
         let subscribed_user_ids: HashSet<u64> = query
             .user_features
             .subscribed_user_ids
@@ -21,14 +61,131 @@ impl Filter<ScoredPostsQuery, PostCandidate> for IneligibleSubscriptionFilter {
             .map(|id| *id as u64)
             .collect();
 
-        let (kept, removed): (Vec<_>, Vec<_>) =
-            candidates
-                .into_iter()
-                .partition(|candidate| match candidate.subscription_author_id {
-                    Some(author_id) => subscribed_user_ids.contains(&author_id),
-                    None => true,
-                });
+        let is_eligible = |candidate: &PostCandidate| match candidate.entitlements {
+            Some(Entitlements {
+                subscriber_only,
+                viewer_entitled,
+            }) => !subscriber_only || viewer_entitled,
+            None => match candidate.subscription_author_id {
+                Some(author_id) => {
+                    author_id == query.user_id as u64 || subscribed_user_ids.contains(&author_id)
+                },
+                None => true,
+            },
+        };
+
+        let show_previews = self.show_previews();
+        let mut kept = Vec::with_capacity(candidates.len());
+        let mut removed = Vec::new();
+        for mut candidate in candidates {
+            if is_eligible(&candidate) {
+                kept.push(candidate);
+            } else if show_previews {
+                candidate.subscription_preview = Some(true);
+                kept.push(candidate);
+            } else {
+                removed.push(candidate);
+            }
+        }
 
         Ok(FilterResult { kept, removed })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn candidates() -> Vec<PostCandidate> {
+        vec![
+            PostCandidate {
+                tweet_id: 1,
+                entitlements: Some(Entitlements {
+                    subscriber_only: true,
+                    viewer_entitled: false,
+                }),
+                ..Default::default()
+            },
+            PostCandidate {
+                tweet_id: 2,
+                entitlements: Some(Entitlements {
+                    subscriber_only: true,
+                    viewer_entitled: true,
+                }),
+                ..Default::default()
+            },
+            PostCandidate {
+                tweet_id: 3,
+                subscription_author_id: Some(100),
+                ..Default::default()
+            },
+            PostCandidate {
+                tweet_id: 4,
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_drops_ineligible_posts() {
+        let filter = IneligibleSubscriptionFilter::new(false);
+        let result = filter
+            .filter(&ScoredPostsQuery::default(), candidates())
+            .await
+            .unwrap();
+
+        let kept: Vec<_> = result.kept.iter().map(|c| c.tweet_id).collect();
+        let removed: Vec<_> = result.removed.iter().map(|c| c.tweet_id).collect();
+        assert_eq!(kept, vec![2, 4]);
+        assert_eq!(removed, vec![1, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_preview_mode_marks_instead_of_dropping() {
+        let filter = IneligibleSubscriptionFilter::new(true);
+        let result = filter
+            .filter(&ScoredPostsQuery::default(), candidates())
+            .await
+            .unwrap();
+
+        assert!(result.removed.is_empty());
+        let previews: Vec<_> = result
+            .kept
+            .iter()
+            .filter(|c| c.subscription_preview == Some(true))
+            .map(|c| c.tweet_id)
+            .collect();
+        assert_eq!(previews, vec![1, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_previews_follow_the_config_in_use() {
+        let mut config = Config::default();
+        config.safety.show_subscription_previews = true;
+        let watcher = Arc::new(ConfigWatcher::new(config));
+        let filter = IneligibleSubscriptionFilter::new(false).with_config(watcher.clone());
+
+        let removed = |result: FilterResult<PostCandidate>| result.removed.len();
+        let query = ScoredPostsQuery::default();
+        assert_eq!(removed(filter.filter(&query, candidates()).await.unwrap()), 0);
+
+        // Reloaded without previews, ineligible posts are dropped again
+        watcher.apply(Ok(Config::default())).unwrap();
+        assert_eq!(removed(filter.filter(&query, candidates()).await.unwrap()), 2);
+    }
+
+    #[tokio::test]
+    async fn test_authors_see_their_own_posts_without_entitlements() {
+        let filter = IneligibleSubscriptionFilter::new(false);
+        let query = ScoredPostsQuery {
+            user_id: 100,
+            ..Default::default()
+        };
+        let result = filter.filter(&query, candidates()).await.unwrap();
+
+        let kept: Vec<_> = result.kept.iter().map(|c| c.tweet_id).collect();
+        assert_eq!(kept, vec![2, 3, 4]);
+        assert!(result.kept.iter().all(|c| c.subscription_preview.is_none()));
+    }
+}
@@ -2,16 +2,26 @@
 //!
 //! Note: Many filters require internal clients and are disabled for open-source compatibility.
 
+pub mod author_list_filter;
+pub mod content_quality_filters;
+pub mod geo_filter;
+pub mod ineligible_subscription_filter;
+pub mod muted_topic_filter;
+pub mod toxicity_filter;
+
+/// Reorder group for independent per-candidate eligibility filters.
+/// Filters in this group don't depend on each other's output, so the pipeline may run
+/// them in whatever order is cheapest.
+pub const ELIGIBILITY_FILTER_GROUP: &str = "eligibility";
+
 // The following modules require internal clients and are commented out for open-source builds.
 // In a production environment, these would be enabled with proper client connections.
 
 // pub mod age_filter;
 // pub mod author_socialgraph_filter;
-// pub mod content_quality_filters;
 // pub mod core_data_hydration_filter;
 // pub mod dedup_conversation_filter;
 // pub mod drop_duplicates_filter;
-// pub mod ineligible_subscription_filter;
 // pub mod muted_keyword_filter;
 // pub mod previously_seen_posts_filter;
 // pub mod previously_served_posts_filter;
@@ -2,6 +2,7 @@ use crate::candidate_pipeline::candidate::PostCandidate;
 use crate::candidate_pipeline::query::ScoredPostsQuery;
 use std::sync::Arc;
 use tonic::async_trait;
+use xai_candidate_pipeline::error::PipelineError;
 use xai_candidate_pipeline::filter::{Filter, FilterResult};
 use xai_post_text::{MatchTweetGroup, TokenSequence, TweetTokenizer, UserMutes};
 
@@ -25,7 +26,7 @@ impl Filter<ScoredPostsQuery, PostCandidate> for MutedKeywordFilter {
         &self,
         query: &ScoredPostsQuery,
         candidates: Vec<PostCandidate>,
-    ) -> Result<FilterResult<PostCandidate>, String> {
+    ) -> Result<FilterResult<PostCandidate>, PipelineError> {
         let muted_keywords = query.user_features.muted_keywords.clone();
 
         if muted_keywords.is_empty() {
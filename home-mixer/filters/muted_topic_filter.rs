@@ -0,0 +1,95 @@
+//! Removes candidates about topics the viewer has muted through repeated
+//! "not interested" feedback (see `personalization::topic_muting`)
+
+use crate::candidate_pipeline::candidate::PostCandidate;
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::filters::ELIGIBILITY_FILTER_GROUP;
+use crate::personalization::user_clusters::UserClusteringService;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::filter::{Filter, FilterResult};
+use std::sync::Arc;
+use tonic::async_trait;
+
+pub struct MutedTopicFilter {
+    clustering_service: Arc<UserClusteringService>,
+}
+
+impl MutedTopicFilter {
+    pub fn new(clustering_service: Arc<UserClusteringService>) -> Self {
+        Self { clustering_service }
+    }
+}
+
+#[async_trait]
+impl Filter<ScoredPostsQuery, PostCandidate> for MutedTopicFilter {
+    fn reorder_group(&self) -> Option<&'static str> {
+        Some(ELIGIBILITY_FILTER_GROUP)
+    }
+
+    async fn filter(
+        &self,
+        query: &ScoredPostsQuery,
+        candidates: Vec<PostCandidate>,
+    ) -> Result<FilterResult<PostCandidate>, PipelineError> {
+        let muted = self.clustering_service.muted_topics(query.user_id as u64).await;
+        if muted.is_empty() {
+            return Ok(FilterResult {
+                kept: candidates,
+                removed: Vec::new(),
+            });
+        }
+
+        let (removed, kept): (Vec<_>, Vec<_>) = candidates.into_iter().partition(|c| {
+            c.topics
+                .as_ref()
+                .is_some_and(|topics| topics.iter().any(|t| muted.contains(t)))
+        });
+
+        Ok(FilterResult { kept, removed })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::personalization::engagement_events::{EngagementEvent, EngagementEventType};
+    use crate::personalization::topic_muting::TopicMuteConfig;
+
+    #[tokio::test]
+    async fn test_filters_muted_topics_only() {
+        let service = UserClusteringService::new(1).with_topic_muting(TopicMuteConfig {
+            threshold: 0.5,
+            ..Default::default()
+        });
+        service
+            .record_event(&EngagementEvent {
+                user_id: 7,
+                tweet_id: 1,
+                author_id: None,
+                event_type: EngagementEventType::NotInterested,
+                media_kind: None,
+                timestamp_ms: chrono::Utc::now().timestamp_millis(),
+                dwell_ms: None,
+                topics: vec!["crypto".to_string()],
+            })
+            .await;
+        let filter = MutedTopicFilter::new(Arc::new(service));
+
+        let candidate = |tweet_id, topic: &str| PostCandidate {
+            tweet_id,
+            topics: Some(vec![topic.to_string()]),
+            ..Default::default()
+        };
+        let query = ScoredPostsQuery {
+            user_id: 7,
+            ..Default::default()
+        };
+        let result = filter
+            .filter(&query, vec![candidate(1, "crypto"), candidate(2, "sports")])
+            .await
+            .unwrap();
+
+        assert_eq!(result.removed[0].tweet_id, 1);
+        assert_eq!(result.kept[0].tweet_id, 2);
+    }
+}
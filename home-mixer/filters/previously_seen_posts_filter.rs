@@ -3,6 +3,7 @@ use crate::candidate_pipeline::query::ScoredPostsQuery;
 use crate::util::bloom_filter::BloomFilter;
 use crate::util::candidates_util::get_related_post_ids;
 use tonic::async_trait;
+use xai_candidate_pipeline::error::PipelineError;
 use xai_candidate_pipeline::filter::{Filter, FilterResult};
 
 /// Filter out previously seen posts using a Bloom Filter and
@@ -15,7 +16,7 @@ impl Filter<ScoredPostsQuery, PostCandidate> for PreviouslySeenPostsFilter {
         &self,
         query: &ScoredPostsQuery,
         candidates: Vec<PostCandidate>,
-    ) -> Result<FilterResult<PostCandidate>, String> {
+    ) -> Result<FilterResult<PostCandidate>, PipelineError> {
         let bloom_filters = query
             .bloom_filter_entries
             .iter()
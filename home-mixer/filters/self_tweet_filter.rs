@@ -1,6 +1,7 @@
 use crate::candidate_pipeline::candidate::PostCandidate;
 use crate::candidate_pipeline::query::ScoredPostsQuery;
 use tonic::async_trait;
+use xai_candidate_pipeline::error::PipelineError;
 use xai_candidate_pipeline::filter::{Filter, FilterResult};
 
 /// Filter that removes tweets where the author is the viewer.
@@ -12,7 +13,7 @@ impl Filter<ScoredPostsQuery, PostCandidate> for SelfTweetFilter {
         &self,
         query: &ScoredPostsQuery,
         candidates: Vec<PostCandidate>,
-    ) -> Result<FilterResult<PostCandidate>, String> {
+    ) -> Result<FilterResult<PostCandidate>, PipelineError> {
         let viewer_id = query.user_id as u64;
         let (kept, removed): (Vec<_>, Vec<_>) = candidates
             .into_iter()
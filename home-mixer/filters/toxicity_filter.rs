@@ -0,0 +1,322 @@
+//! Toxicity Filter
+//!
+//! Scores each candidate for toxicity (lexicon match, optionally combined with a
+//! classifier) and removes it when the score crosses the threshold for the
+//! viewer's sensitivity setting.
+
+use crate::candidate_pipeline::candidate::PostCandidate;
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::candidate_pipeline::query_features::SafetyFilterKind;
+use crate::config::{FilterMode, FilterType, Metrics};
+use crate::filters::ELIGIBILITY_FILTER_GROUP;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::filter::{Filter, FilterResult};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tonic::async_trait;
+
+/// Threshold used for `FilterMode::Standard`
+pub const STANDARD_THRESHOLD: f64 = 0.7;
+
+/// Threshold used for `FilterMode::Strict`
+pub const STRICT_THRESHOLD: f64 = 0.4;
+
+/// Model-backed toxicity scoring
+#[async_trait]
+pub trait ToxicityClassifier: Send + Sync {
+    /// Returns one score in [0, 1] per input text, in order.
+    async fn classify(&self, texts: &[&str]) -> Result<Vec<f64>, String>;
+}
+
+pub struct ToxicityFilter {
+    /// Lowercase term or phrase -> weight in [0, 1]
+    lexicon: HashMap<String, f64>,
+    classifier: Option<Arc<dyn ToxicityClassifier>>,
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl Default for ToxicityFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ToxicityFilter {
+    pub fn new() -> Self {
+        Self {
+            lexicon: Self::default_lexicon(),
+            classifier: None,
+            metrics: None,
+        }
+    }
+
+    pub fn with_classifier(mut self, classifier: Arc<dyn ToxicityClassifier>) -> Self {
+        self.classifier = Some(classifier);
+        self
+    }
+
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn with_lexicon(mut self, lexicon: HashMap<String, f64>) -> Self {
+        self.lexicon = lexicon
+            .into_iter()
+            .map(|(term, weight)| (term.to_lowercase(), weight.clamp(0.0, 1.0)))
+            .collect();
+        self
+    }
+
+    fn default_lexicon() -> HashMap<String, f64> {
+        // In production, load from the trust & safety term list
+        [
+            ("idiot", 0.4),
+            ("moron", 0.4),
+            ("stupid", 0.3),
+            ("loser", 0.3),
+            ("scum", 0.6),
+            ("trash human", 0.7),
+            ("kill yourself", 0.95),
+            ("kys", 0.9),
+        ]
+        .into_iter()
+        .map(|(term, weight)| (term.to_string(), weight))
+        .collect()
+    }
+
+    pub fn threshold(mode: FilterMode) -> Option<f64> {
+        match mode {
+            FilterMode::Off => None,
+            FilterMode::Standard => Some(STANDARD_THRESHOLD),
+            FilterMode::Strict => Some(STRICT_THRESHOLD),
+        }
+    }
+
+    /// Threshold for `query`'s mode; a locale's override replaces the standard
+    /// threshold and caps the strict one
+    pub fn threshold_for(query: &ScoredPostsQuery) -> Option<f64> {
+        let mode = query.filter_mode(SafetyFilterKind::Toxicity);
+        let threshold = Self::threshold(mode)?;
+        Some(match query.locale_params.overrides.toxicity_threshold {
+            Some(locale) if mode == FilterMode::Strict => locale.min(threshold),
+            Some(locale) => locale,
+            None => threshold,
+        })
+    }
+
+    /// Noisy-OR of the weights of every lexicon entry found in `text`
+    pub fn lexicon_score(&self, text: &str) -> f64 {
+        let normalized = text
+            .to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|t| !t.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if normalized.is_empty() {
+            return 0.0;
+        }
+        let padded = format!(" {} ", normalized);
+
+        let benign = self
+            .lexicon
+            .iter()
+            .filter(|(term, _)| padded.contains(&format!(" {} ", term)))
+            .fold(1.0, |acc, (_, weight)| acc * (1.0 - weight));
+        1.0 - benign
+    }
+
+    async fn score_candidates(&self, candidates: &[PostCandidate]) -> Vec<f64> {
+        let lexicon_scores: Vec<f64> = candidates
+            .iter()
+            .map(|c| self.lexicon_score(&c.tweet_text))
+            .collect();
+
+        let Some(classifier) = &self.classifier else {
+            return lexicon_scores;
+        };
+
+        let texts: Vec<&str> = candidates.iter().map(|c| c.tweet_text.as_str()).collect();
+        match classifier.classify(&texts).await {
+            Ok(model_scores) if model_scores.len() == lexicon_scores.len() => lexicon_scores
+                .into_iter()
+                .zip(model_scores)
+                .map(|(lexicon, model)| lexicon.max(model.clamp(0.0, 1.0)))
+                .collect(),
+            Ok(model_scores) => {
+                log::warn!(
+                    "Toxicity classifier returned {} scores for {} candidates, using lexicon only",
+                    model_scores.len(),
+                    lexicon_scores.len()
+                );
+                lexicon_scores
+            },
+            Err(err) => {
+                log::warn!("Toxicity classifier failed, using lexicon only: {}", err);
+                lexicon_scores
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl Filter<ScoredPostsQuery, PostCandidate> for ToxicityFilter {
+    fn reorder_group(&self) -> Option<&'static str> {
+        Some(ELIGIBILITY_FILTER_GROUP)
+    }
+
+    // The viewer asked for toxic posts to be hidden, so a timeout mustn't let them through
+    fn is_optional(&self) -> bool {
+        false
+    }
+
+    // Likewise an error mustn't, so nothing is served unchecked
+    fn fallback(
+        &self,
+        _query: &ScoredPostsQuery,
+        candidates: &[PostCandidate],
+    ) -> Option<FilterResult<PostCandidate>> {
+        Some(FilterResult {
+            kept: Vec::new(),
+            removed: candidates.to_vec(),
+        })
+    }
+
+    fn enable(&self, query: &ScoredPostsQuery) -> bool {
+        Self::threshold_for(query).is_some()
+    }
+
+    async fn filter(
+        &self,
+        query: &ScoredPostsQuery,
+        candidates: Vec<PostCandidate>,
+    ) -> Result<FilterResult<PostCandidate>, PipelineError> {
+        let Some(threshold) = Self::threshold_for(query) else {
+            return Ok(FilterResult {
+                kept: candidates,
+                removed: Vec::new(),
+            });
+        };
+
+        let scores = self.score_candidates(&candidates).await;
+
+        let mut kept = Vec::with_capacity(candidates.len());
+        let mut removed = Vec::new();
+        for (mut candidate, score) in candidates.into_iter().zip(scores) {
+            candidate.toxicity_score = Some(score);
+            if score >= threshold {
+                removed.push(candidate);
+            } else {
+                kept.push(candidate);
+            }
+        }
+
+        if let Some(metrics) = &self.metrics {
+            metrics.record_filtered(FilterType::Toxicity, removed.len() as u64);
+        }
+
+        Ok(FilterResult { kept, removed })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::candidate_pipeline::query_features::{ToxicitySensitivity, UserPreferences};
+    use crate::config::LocaleParams;
+    use std::sync::atomic::Ordering;
+
+    fn query(sensitivity: ToxicitySensitivity) -> ScoredPostsQuery {
+        ScoredPostsQuery {
+            user_preferences: Some(UserPreferences {
+                toxicity_sensitivity: sensitivity,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn candidates() -> Vec<PostCandidate> {
+        ["What a lovely day", "You are such an idiot", "Just kys, loser"]
+            .iter()
+            .enumerate()
+            .map(|(i, text)| PostCandidate {
+                tweet_id: i as i64,
+                tweet_text: text.to_string(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    struct FixedClassifier(f64);
+
+    #[async_trait]
+    impl ToxicityClassifier for FixedClassifier {
+        async fn classify(&self, texts: &[&str]) -> Result<Vec<f64>, String> {
+            Ok(vec![self.0; texts.len()])
+        }
+    }
+
+    #[test]
+    fn test_lexicon_score() {
+        let filter = ToxicityFilter::new();
+        assert_eq!(filter.lexicon_score("What a lovely day"), 0.0);
+        assert!((filter.lexicon_score("You IDIOT!") - 0.4).abs() < 1e-9);
+        // Whole words only
+        assert_eq!(filter.lexicon_score("idiotic"), 0.0);
+        assert!(filter.lexicon_score("kill yourself, moron") > 0.95);
+    }
+
+    #[tokio::test]
+    async fn test_sensitivity_levels() {
+        let metrics = Metrics::new();
+        let filter = ToxicityFilter::new().with_metrics(metrics.clone());
+
+        let standard = filter
+            .filter(&query(ToxicitySensitivity::Standard), candidates())
+            .await
+            .unwrap();
+        assert_eq!(standard.kept.len(), 2);
+
+        let strict = filter
+            .filter(&query(ToxicitySensitivity::Strict), candidates())
+            .await
+            .unwrap();
+        assert_eq!(strict.kept.len(), 1);
+        assert_eq!(strict.kept[0].toxicity_score, Some(0.0));
+
+        assert!(!filter.enable(&query(ToxicitySensitivity::Off)));
+        assert_eq!(metrics.toxicity_filtered.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn test_locale_threshold_overrides_standard_and_caps_strict() {
+        let filter = ToxicityFilter::new();
+        let mut params = LocaleParams::default();
+        params.overrides.toxicity_threshold = Some(0.35);
+        let localized = |sensitivity| ScoredPostsQuery {
+            locale_params: Arc::new(params.clone()),
+            ..query(sensitivity)
+        };
+
+        let threshold = |sensitivity| ToxicityFilter::threshold_for(&localized(sensitivity));
+        assert_eq!(threshold(ToxicitySensitivity::Standard), Some(0.35));
+        assert_eq!(threshold(ToxicitySensitivity::Strict), Some(0.35));
+        assert_eq!(threshold(ToxicitySensitivity::Off), None);
+        let result = filter
+            .filter(&localized(ToxicitySensitivity::Standard), candidates())
+            .await
+            .unwrap();
+        assert_eq!(result.kept.len(), 1, "the 0.4 insult is removed too");
+    }
+
+    #[tokio::test]
+    async fn test_classifier_score_is_combined() {
+        let filter = ToxicityFilter::new().with_classifier(Arc::new(FixedClassifier(0.8)));
+        let result = filter
+            .filter(&query(ToxicitySensitivity::Standard), candidates())
+            .await
+            .unwrap();
+        assert!(result.kept.is_empty());
+    }
+}
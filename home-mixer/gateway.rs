@@ -0,0 +1,155 @@
+//! JSON Gateway to `GetScoredPosts`
+//!
+//! Serves `POST /v1/scored_posts` for web frontends and scripts without a gRPC
+//! client. The body is the JSON form of `ScoredPostsQuery`, unset fields taking
+//! their proto defaults, and the response that of `ScoredPostsResponse`. The
+//! request goes through the same handler as gRPC calls, its headers as
+//! metadata, so `authorization` and `x-feature-overrides` work alike and both
+//! are validated the same way. Failures get the HTTP status of their gRPC code
+//! and a body naming the code, the pipeline error kind if any, and the message.
+
+use crate::proto::{self, scored_posts_service_server::ScoredPostsService};
+use crate::server::HomeMixerServer;
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{Json, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::Router;
+use candidate_pipeline::error::error_kind;
+use serde::Serialize;
+use std::sync::Arc;
+use tonic::metadata::{AsciiMetadataKey, AsciiMetadataValue, MetadataMap};
+use tonic::{Code, Request, Status};
+
+/// Routes of the gateway, answered by `server`
+pub fn router(server: Arc<HomeMixerServer>) -> Router {
+    Router::new()
+        .route("/v1/scored_posts", post(scored_posts))
+        .with_state(server)
+}
+
+async fn scored_posts(
+    State(server): State<Arc<HomeMixerServer>>,
+    headers: HeaderMap,
+    query: Result<Json<proto::ScoredPostsQuery>, JsonRejection>,
+) -> Response {
+    let Json(query) = match query {
+        Ok(query) => query,
+        Err(rejection) => return error_response(Status::invalid_argument(rejection.body_text())),
+    };
+    let mut request = Request::new(query);
+    *request.metadata_mut() = metadata(&headers);
+    match server.get_scored_posts(request).await {
+        Ok(response) => Json(response.into_inner()).into_response(),
+        Err(status) => error_response(status),
+    }
+}
+
+/// The ASCII headers of a request as gRPC metadata (tonic is on an older `http`
+/// than axum, so the maps can't simply be converted)
+fn metadata(headers: &HeaderMap) -> MetadataMap {
+    let mut metadata = MetadataMap::new();
+    for (name, value) in headers {
+        let key = AsciiMetadataKey::from_bytes(name.as_str().as_bytes());
+        let value = AsciiMetadataValue::try_from(value.as_bytes());
+        if let (Ok(key), Ok(value)) = (key, value) {
+            metadata.append(key, value);
+        }
+    }
+    metadata
+}
+
+/// Body of an HTTP error response
+#[derive(Debug, Serialize)]
+struct ErrorBody {
+    /// The gRPC code, e.g. `InvalidArgument`
+    code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kind: Option<String>,
+    message: String,
+}
+
+fn error_response(status: Status) -> Response {
+    let body = ErrorBody {
+        code: format!("{:?}", status.code()),
+        kind: error_kind(&status).map(str::to_string),
+        message: status.message().to_string(),
+    };
+    (http_status(status.code()), Json(body)).into_response()
+}
+
+/// The HTTP status a gRPC code stands for, as gRPC-HTTP transcoders map them
+pub fn http_status(code: Code) -> StatusCode {
+    match code {
+        Code::Ok => StatusCode::OK,
+        Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => {
+            StatusCode::BAD_REQUEST
+        },
+        Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+        Code::PermissionDenied => StatusCode::FORBIDDEN,
+        Code::NotFound => StatusCode::NOT_FOUND,
+        Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+        Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+        Code::Cancelled => StatusCode::from_u16(499).expect("499 is a valid status"),
+        Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+        Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+        Code::Unknown | Code::Internal | Code::DataLoss => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::candidate_pipeline::phoenix_candidate_pipeline::ProdServices;
+    use crate::config::Config;
+    use crate::util::config_watcher::ConfigWatcher;
+    use candidate_pipeline::error::PipelineError;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_errors_keep_their_code_and_kind() {
+        let response = error_response(PipelineError::timeout().into());
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+
+        let denied = Status::permission_denied("feature overrides are for trusted callers only");
+        assert_eq!(error_response(denied).status(), StatusCode::FORBIDDEN);
+        assert_eq!(http_status(Code::Cancelled).as_u16(), 499);
+    }
+
+    #[tokio::test]
+    async fn test_gateway_validates_like_grpc() {
+        let services = ProdServices::new(Arc::new(ConfigWatcher::new(Config::default())));
+        let app = router(Arc::new(HomeMixerServer::new(services).await));
+        let call = |body: &str, authorization: Option<&str>| {
+            let mut request = axum::http::Request::post("/v1/scored_posts")
+                .header("content-type", "application/json");
+            if let Some(authorization) = authorization {
+                request = request.header("authorization", authorization);
+            }
+            let request = request.body(axum::body::Body::from(body.to_string())).unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                (status, serde_json::from_slice::<serde_json::Value>(&body).unwrap())
+            }
+        };
+
+        let (status, response) = call(r#"{"viewer_id": 7, "debug": true}"#, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(response["algorithm_version"]["weight_set_hash"].is_string());
+
+        let (status, error) = call("{}", None).await;
+        assert_eq!((status, &error["code"]), (StatusCode::BAD_REQUEST, &"InvalidArgument".into()));
+        assert!(error.get("kind").is_none());
+        let (status, _) = call(r#"{"viewer_id": "seven"}"#, None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let overriding = r#"{"viewer_id": 7, "feature_overrides": {"caching": "off"}}"#;
+        let (status, error) = call(overriding, Some("Bearer guess")).await;
+        assert_eq!((status, &error["code"]), (StatusCode::FORBIDDEN, &"PermissionDenied".into()));
+    }
+}
@@ -2,14 +2,21 @@
 //!
 //! This crate provides the ranking algorithm for the "For You" timeline.
 
+pub mod candidate_hydrators;
 pub mod candidate_pipeline;
 pub mod config;
+pub mod experiments;
 pub mod filters;
+pub mod gateway;
 pub mod params;
 pub mod personalization;
 pub mod proto;
+pub mod query_hydrators;
 pub mod scorers;
+pub mod selectors;
 pub mod server;
+pub mod side_effects;
+pub mod sources;
 pub mod util;
 
 // Re-exports for convenience
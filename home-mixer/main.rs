@@ -5,23 +5,100 @@
 
 use anyhow::Result;
 use axum::{
-    extract::Json,
+    extract::{Extension, Json, Path, State},
+    http::StatusCode,
     response::IntoResponse,
-    routing::{get, post},
+    routing::{delete, get, post, put},
     Router,
 };
-use clap::Parser;
-use log::info;
+use clap::{Parser, Subcommand};
+use log::{error, info, warn};
 use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
 
-use home_mixer::params;
+use home_mixer::candidate_pipeline::phoenix_candidate_pipeline::{self, ProdServices};
+use home_mixer::filters::author_list_filter::{AuthorListKind, AuthorListStore};
+use home_mixer::personalization::author_affinity::AuthorAffinityStore;
+use home_mixer::personalization::author_profiles::AuthorProfileStore;
+use home_mixer::personalization::cluster_store::open_cluster_store;
+use home_mixer::personalization::cold_start::ColdStartProfiler;
+use home_mixer::personalization::engagement_events::EngagementEvent;
+use home_mixer::personalization::embedding_store::{EmbeddingStore, HnswConfig};
+use home_mixer::personalization::exploration::ExplorationTracker;
+use home_mixer::personalization::io;
+use home_mixer::personalization::privacy::private_cluster_aggregates;
+use home_mixer::personalization::topic_muting::TopicMuteConfig;
+use home_mixer::personalization::user_clusters::{UserClusteringService, REFRESH_IN_PROGRESS};
+use home_mixer::personalization::user_features_provider::provider_from_source;
+use home_mixer::query_hydrators::following_query_hydrator::{
+    ApiFollowingListProvider, FollowEvent, FollowingCacheConfig, FollowingListCache,
+};
+use home_mixer::query_hydrators::user_interest_topics_query_hydrator::ApiEngagementHistoryProvider;
+use home_mixer::scorers::weight_sets::WeightSetRegistry;
+use home_mixer::sources::similar_authors_source::ThunderAuthorPostsProvider;
+#[cfg(feature = "otlp")]
+use home_mixer::config::MetricsConfig;
+use home_mixer::config::{ConfigSource, LoggingConfig};
+use home_mixer::util::admin_auth::admin_only;
+use home_mixer::util::alerting;
+use home_mixer::util::config_watcher::ConfigWatcher;
+use home_mixer::util::debug_traces::DebugTraceStore;
+use home_mixer::util::kill_switch_store::KillSwitchStore;
+use home_mixer::util::logging::{self, LogLevels};
+use home_mixer::util::metrics_sink;
+use home_mixer::util::user_auth::{authenticated, Caller, UserAuth};
+#[cfg(feature = "otlp")]
+use home_mixer::util::observability;
+#[cfg(feature = "profiling")]
+use home_mixer::util::profiling;
+use home_mixer::proto::{self, scored_posts_service_server::ScoredPostsServiceServer};
+use home_mixer::{gateway, params, Config, HomeMixerServer};
 
 #[derive(Parser, Debug)]
 #[command(about = "HomeMixer Server - X's For You Algorithm")]
 struct Args {
     #[arg(long, default_value = "8080")]
     port: u16,
+
+    /// Port of the gRPC ScoredPostsService; thunder serves gRPC on 50051
+    #[arg(long, default_value = "50052")]
+    grpc_port: u16,
+
+    /// Refuse to start, or to reload, with settings no component reads, such
+    /// as misspelled keys, or values that don't parse, instead of using the
+    /// defaults
+    #[arg(long)]
+    strict_config: bool,
+
+    /// Load and validate the config strictly, then exit; fails with every
+    /// problem found, for checking configs before rollout
+    #[arg(long)]
+    validate_config: bool,
+
+    /// Print the JSON schema of the config and exit
+    #[arg(long)]
+    config_schema: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Offline maintenance tasks; the server runs when no subcommand is given
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Cluster users from a features file and persist the result to CLUSTER_STORE_PATH
+    ImportFeatures { path: PathBuf },
+    /// Write the cluster assignments in CLUSTER_STORE_PATH to a CSV file
+    ExportClusters {
+        path: PathBuf,
+        /// Write differentially private per-cluster aggregates instead of per-user rows
+        #[arg(long)]
+        aggregate: bool,
+    },
+    /// Check the scoring weights and weight sets, as the server does at startup
+    ValidateWeights,
 }
 
 #[derive(Debug, Serialize)]
@@ -67,6 +144,56 @@ struct ScoreResponse {
     tier: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct LogLevelsBody {
+    /// `RUST_LOG` style directives, e.g. `info,home_mixer::scorers=debug`
+    levels: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AuthorListRequest {
+    author_id: u64,
+    #[serde(default)]
+    ttl_secs: Option<u64>,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct KillSwitchRequest {
+    #[serde(default)]
+    reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TracedUserRequest {
+    user_id: u64,
+    #[serde(default)]
+    ttl_secs: Option<u64>,
+}
+
+/// `POST /api/events` accepts a single event or a batch, attributed to the
+/// calling user unless a service sends them
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum EventsRequest {
+    Batch(Vec<EngagementEvent>),
+    Single(EngagementEvent),
+}
+
+#[derive(Debug, Serialize)]
+struct EventsResponse {
+    applied: usize,
+    ignored: usize,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum FollowEventsRequest {
+    Batch(Vec<FollowEvent>),
+    Single(FollowEvent),
+}
+
 #[derive(Debug, Serialize)]
 struct ScoreBreakdown {
     reply_contribution: f64,
@@ -85,6 +212,15 @@ async fn health() -> impl IntoResponse {
     })
 }
 
+/// Stage latency, candidate counts and component errors of the ranking
+/// pipeline, its kill switches, and the config version
+async fn metrics(State(config): State<Arc<ConfigWatcher>>) -> impl IntoResponse {
+    phoenix_candidate_pipeline::prod_metrics().to_prometheus()
+        + &phoenix_candidate_pipeline::prod_filter_metrics().to_prometheus()
+        + &phoenix_candidate_pipeline::prod_kill_switches().to_prometheus()
+        + &config.to_prometheus()
+}
+
 async fn get_weights() -> impl IntoResponse {
     Json(WeightsResponse {
         reply: params::REPLY_WEIGHT,
@@ -129,11 +265,11 @@ async fn calculate_score(Json(req): Json<ScoreRequest>) -> impl IntoResponse {
     };
 
     // Determine tier
-    let tier = if score >= 30.0 {
+    let tier = if score >= params::VIRAL_POTENTIAL_THRESHOLD {
         "VIRAL_POTENTIAL"
-    } else if score >= 15.0 {
+    } else if score >= params::GOOD_THRESHOLD {
         "GOOD"
-    } else if score >= 5.0 {
+    } else if score >= params::AVERAGE_THRESHOLD {
         "AVERAGE"
     } else {
         "LOW"
@@ -155,10 +291,453 @@ async fn calculate_score(Json(req): Json<ScoreRequest>) -> impl IntoResponse {
     })
 }
 
+async fn list_authors(State(store): State<Arc<AuthorListStore>>) -> impl IntoResponse {
+    Json(store.snapshot())
+}
+
+async fn add_author(
+    State(store): State<Arc<AuthorListStore>>,
+    Path(kind): Path<AuthorListKind>,
+    Json(req): Json<AuthorListRequest>,
+) -> impl IntoResponse {
+    match store.insert(kind, req.author_id, req.ttl_secs, req.reason) {
+        Ok(entry) => {
+            info!("Added author {} to {:?} list", entry.author_id, kind);
+            (StatusCode::OK, Json(Some(entry)))
+        },
+        Err(e) => {
+            error!("Failed to persist author list: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(None))
+        },
+    }
+}
+
+async fn remove_author(
+    State(store): State<Arc<AuthorListStore>>,
+    Path((kind, author_id)): Path<(AuthorListKind, u64)>,
+) -> impl IntoResponse {
+    match store.remove(kind, author_id) {
+        Ok(true) => {
+            info!("Removed author {} from {:?} list", author_id, kind);
+            StatusCode::NO_CONTENT
+        },
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            error!("Failed to persist author list: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        },
+    }
+}
+
+async fn get_config(State(config): State<Arc<ConfigWatcher>>) -> impl IntoResponse {
+    Json(config.current().as_ref().clone())
+}
+
+async fn reload_config(State(config): State<Arc<ConfigWatcher>>) -> impl IntoResponse {
+    match config.reload() {
+        Ok(outcome) => (StatusCode::OK, Json(outcome)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn list_traced_users(State(traces): State<Arc<DebugTraceStore>>) -> impl IntoResponse {
+    Json(traces.users())
+}
+
+async fn add_traced_user(
+    State(traces): State<Arc<DebugTraceStore>>,
+    Json(req): Json<TracedUserRequest>,
+) -> impl IntoResponse {
+    let user = traces.add_user(req.user_id, req.ttl_secs);
+    info!("Tracing requests of user {}", user.user_id);
+    Json(user)
+}
+
+async fn remove_traced_user(
+    State(traces): State<Arc<DebugTraceStore>>,
+    Path(user_id): Path<u64>,
+) -> impl IntoResponse {
+    if traces.remove_user(user_id) {
+        info!("Stopped tracing requests of user {}", user_id);
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+async fn get_traces(
+    State(traces): State<Arc<DebugTraceStore>>,
+    Path(user_id): Path<u64>,
+) -> impl IntoResponse {
+    Json(traces.traces(user_id))
+}
+
+async fn list_kill_switches(State(store): State<Arc<KillSwitchStore>>) -> impl IntoResponse {
+    Json(store.switches())
+}
+
+async fn kill_component(
+    State(store): State<Arc<KillSwitchStore>>,
+    Path(component): Path<String>,
+    body: Option<Json<KillSwitchRequest>>,
+) -> impl IntoResponse {
+    let req = body.map(|Json(req)| req).unwrap_or_default();
+    match store.kill(&component, &req.reason) {
+        Ok(killed) => {
+            if killed {
+                warn!("Switched off component {}: {}", component, req.reason);
+            }
+            let switch = store.switches().into_iter().find(|s| s.component == component);
+            (StatusCode::OK, Json(switch))
+        },
+        Err(e) => {
+            error!("Failed to persist kill switches: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(None))
+        },
+    }
+}
+
+async fn restore_component(
+    State(store): State<Arc<KillSwitchStore>>,
+    Path(component): Path<String>,
+) -> impl IntoResponse {
+    match store.restore(&component) {
+        Ok(true) => {
+            info!("Switched component {} back on", component);
+            StatusCode::NO_CONTENT
+        },
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            error!("Failed to persist kill switches: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        },
+    }
+}
+
+async fn ingest_events(
+    State(clustering): State<Option<Arc<UserClusteringService>>>,
+    Extension(caller): Extension<Caller>,
+    Json(req): Json<EventsRequest>,
+) -> impl IntoResponse {
+    let Some(clustering) = clustering else {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(None));
+    };
+    let mut events = match req {
+        EventsRequest::Batch(events) => events,
+        EventsRequest::Single(event) => vec![event],
+    };
+    if let Caller::User(user_id) = caller {
+        for event in &mut events {
+            event.user_id = user_id;
+        }
+    }
+
+    let mut applied = 0;
+    for event in &events {
+        if clustering.record_event(event).await.is_some() {
+            applied += 1;
+        }
+    }
+
+    let response = EventsResponse {
+        applied,
+        ignored: events.len() - applied,
+    };
+    (StatusCode::OK, Json(Some(response)))
+}
+
+async fn ingest_follow_events(
+    State(following): State<Option<Arc<FollowingListCache>>>,
+    Extension(caller): Extension<Caller>,
+    Json(req): Json<FollowEventsRequest>,
+) -> impl IntoResponse {
+    let Some(following) = following else {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(None));
+    };
+    let mut events = match req {
+        FollowEventsRequest::Batch(events) => events,
+        FollowEventsRequest::Single(event) => vec![event],
+    };
+    if let Caller::User(user_id) = caller {
+        for event in &mut events {
+            event.follower_id = user_id as i64;
+        }
+    }
+    for event in &events {
+        following.apply_follow_event(event);
+    }
+    let response = EventsResponse {
+        applied: events.len(),
+        ignored: 0,
+    };
+    (StatusCode::OK, Json(Some(response)))
+}
+
+async fn get_following_cache_stats(
+    State(following): State<Option<Arc<FollowingListCache>>>,
+) -> impl IntoResponse {
+    match following {
+        Some(following) => (StatusCode::OK, Json(Some(following.stats()))),
+        None => (StatusCode::SERVICE_UNAVAILABLE, Json(None)),
+    }
+}
+
+async fn get_log_levels(State(levels): State<LogLevels>) -> impl IntoResponse {
+    Json(LogLevelsBody { levels: levels.current() })
+}
+
+async fn set_log_levels(
+    State(levels): State<LogLevels>,
+    Json(req): Json<LogLevelsBody>,
+) -> impl IntoResponse {
+    match levels.set(&req.levels) {
+        Ok(()) => {
+            info!("Log levels set to {}", req.levels);
+            (StatusCode::OK, Json(req)).into_response()
+        },
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn get_personalization(
+    State(clustering): State<Option<Arc<UserClusteringService>>>,
+    Extension(caller): Extension<Caller>,
+    Path(user_id): Path<u64>,
+) -> impl IntoResponse {
+    let Some(clustering) = clustering else {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(None));
+    };
+    if !caller.may_act_for(user_id) {
+        return (StatusCode::FORBIDDEN, Json(None));
+    }
+    match clustering.explain(user_id).await {
+        Some(report) => (StatusCode::OK, Json(Some(report))),
+        None => (StatusCode::NOT_FOUND, Json(None)),
+    }
+}
+
+async fn reset_personalization(
+    State(clustering): State<Option<Arc<UserClusteringService>>>,
+    Extension(caller): Extension<Caller>,
+    Path(user_id): Path<u64>,
+) -> impl IntoResponse {
+    let Some(clustering) = clustering else {
+        return StatusCode::SERVICE_UNAVAILABLE;
+    };
+    if !caller.may_act_for(user_id) {
+        return StatusCode::FORBIDDEN;
+    }
+    match clustering.reset_user(user_id).await {
+        Ok(true) => {
+            info!("Reset personalization for user {}", user_id);
+            StatusCode::NO_CONTENT
+        },
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            error!("Failed to reset personalization for user {}: {}", user_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        },
+    }
+}
+
+async fn refresh_clusters(
+    State(clustering): State<Option<Arc<UserClusteringService>>>,
+) -> impl IntoResponse {
+    let Some(clustering) = clustering.filter(|c| c.has_features_provider()) else {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(None));
+    };
+    match clustering.refresh_from_provider().await {
+        Ok(stats) => (StatusCode::OK, Json(Some(stats))),
+        Err(e) if e == REFRESH_IN_PROGRESS => (StatusCode::CONFLICT, Json(None)),
+        Err(e) => {
+            error!("On-demand cluster refresh failed: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(None))
+        },
+    }
+}
+
+fn log_progress(action: &str, progress: io::Progress) {
+    match progress.total_bytes.filter(|total| *total > 0) {
+        Some(total) => info!(
+            "{} {} rows ({:.1}%)",
+            action,
+            progress.rows,
+            100.0 * progress.bytes as f64 / total as f64
+        ),
+        None => info!("{} {} rows", action, progress.rows),
+    }
+}
+
+async fn run_command(command: Command, config: &Config) -> Result<()> {
+    let stored_clustering = || -> Result<UserClusteringService> {
+        let store_path = config
+            .personalization
+            .cluster_store_path
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("CLUSTER_STORE_PATH must be set"))?;
+        Ok(UserClusteringService::new(config.personalization.num_clusters)
+            .with_switch_margin(config.personalization.cluster_switch_margin)
+            .with_weight_presets(config.personalization.cluster_weight_presets.clone())
+            .with_store(open_cluster_store(store_path).map_err(anyhow::Error::msg)?))
+    };
+
+    match command {
+        Command::ValidateWeights => {
+            params::validate(&WeightSetRegistry::default())
+                .map_err(|e| anyhow::anyhow!("invalid weights: {}", e))?;
+            info!("Weights are valid");
+        },
+        Command::ImportFeatures { path } => {
+            let clustering = stored_clustering()?;
+            let report = io::import_user_features(&path, |p| log_progress("Read", p))
+                .map_err(anyhow::Error::msg)?;
+            for e in &report.errors {
+                error!("Rejected {}", e);
+            }
+            info!(
+                "Imported {} users ({} rows rejected)",
+                report.features.len(),
+                report.rows_rejected
+            );
+            // Previous assignments let returning users keep their cluster
+            clustering.load_from_store().await.map_err(anyhow::Error::msg)?;
+            let result = clustering.refresh_clusters(report.features).await;
+            info!(
+                "Clustered into {} clusters (inertia {:.3}, converged: {}, churn {:?})",
+                result.centroids.len(),
+                result.inertia,
+                result.converged,
+                clustering.cluster_stats().await.churn_rate
+            );
+            clustering.snapshot().await.map_err(anyhow::Error::msg)?;
+        },
+        Command::ExportClusters { path, aggregate: true } => {
+            let clustering = stored_clustering()?;
+            clustering.load_from_store().await.map_err(anyhow::Error::msg)?;
+            let assignments = clustering.assignments().await;
+            let privacy = config.personalization.privacy();
+            let aggregates = private_cluster_aggregates(&assignments, &privacy);
+            io::export_cluster_aggregates(&aggregates, &path).map_err(anyhow::Error::msg)?;
+            info!(
+                "Exported {} cluster aggregates (epsilon {}) to {}",
+                aggregates.len(),
+                privacy.epsilon,
+                path.display()
+            );
+        },
+        Command::ExportClusters { path, aggregate: false } => {
+            let clustering = stored_clustering()?;
+            clustering.load_from_store().await.map_err(anyhow::Error::msg)?;
+            let assignments = clustering.assignments().await;
+            let written =
+                io::export_cluster_assignments(&assignments, &path, |p| log_progress("Wrote", p))
+                    .map_err(anyhow::Error::msg)?;
+            info!("Exported {} assignments to {}", written, path.display());
+        },
+    }
+    Ok(())
+}
+
+async fn get_cluster_stats(
+    State(clustering): State<Option<Arc<UserClusteringService>>>,
+) -> impl IntoResponse {
+    match clustering {
+        Some(clustering) => (StatusCode::OK, Json(Some(clustering.private_cluster_stats().await))),
+        None => (StatusCode::SERVICE_UNAVAILABLE, Json(None)),
+    }
+}
+
+/// Counts allocations for `/debug/pprof/heap`
+#[cfg(feature = "profiling")]
+#[global_allocator]
+static ALLOCATOR: profiling::CountingAllocator = profiling::CountingAllocator;
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    env_logger::init();
     let args = Args::parse();
+    if args.config_schema {
+        println!("{}", serde_json::to_string_pretty(&Config::json_schema())?);
+        return Ok(());
+    }
+    if args.validate_config {
+        Config::load_strict().map_err(|e| anyhow::anyhow!("invalid config: {}", e))?;
+        println!("Config OK");
+        return Ok(());
+    }
+
+    // Logging starts before the rest of the config is checked, so problems
+    // with it are logged
+    let source = ConfigSource::load().map_err(anyhow::Error::msg)?;
+    let logging_config = LoggingConfig::from_source(&source);
+    #[allow(unused_mut)]
+    let mut layers = Vec::new();
+    #[cfg(feature = "otlp")]
+    let trace_export = {
+        let metrics_config = MetricsConfig::from_source(&source);
+        metrics_config.enable_tracing.then(|| {
+            let endpoint = metrics_config.otlp_endpoint.as_deref();
+            observability::tracing_layer("home-mixer", endpoint).map(|layer| layers.push(layer))
+        })
+    };
+    let log_levels = logging::init(logging_config.format, &logging_config.levels, layers)
+        .map_err(|e| anyhow::anyhow!("failed to start logging: {}", e))?;
+    #[cfg(feature = "otlp")]
+    match trace_export {
+        Some(Ok(())) => info!("Exporting pipeline traces over OTLP"),
+        Some(Err(e)) => error!("Failed to start trace export: {}", e),
+        None => {},
+    }
+    let config = Config::from_source_at(&source, chrono::Utc::now());
+    if args.strict_config {
+        config.check_strict(&source).map_err(|e| anyhow::anyhow!("invalid config: {}", e))?;
+    }
+    for e in source.invalid() {
+        warn!("Using the default for {}", e);
+    }
+    config.validate().map_err(|e| anyhow::anyhow!("invalid config: {}", e))?;
+
+    if let Some(command) = args.command {
+        return run_command(command, &config).await;
+    }
+    let weight_sets = WeightSetRegistry::default();
+    params::validate(&weight_sets).map_err(|e| anyhow::anyhow!("invalid weights: {}", e))?;
+
+    #[cfg(feature = "otlp")]
+    let _meter_provider = {
+        let endpoint = config.metrics.otlp_endpoint.as_deref();
+        if config.metrics.enable_otlp_metrics {
+            let interval =
+                std::time::Duration::from_secs(config.metrics.otlp_export_interval_secs);
+            let metrics = phoenix_candidate_pipeline::prod_metrics();
+            match observability::init_metrics("home-mixer", endpoint, interval, metrics) {
+                Ok(provider) => {
+                    info!("Exporting pipeline metrics over OTLP");
+                    Some(provider)
+                },
+                Err(e) => {
+                    error!("Failed to start metrics export: {}", e);
+                    None
+                },
+            }
+        } else {
+            None
+        }
+    };
+    #[cfg(not(feature = "otlp"))]
+    if config.metrics.enable_tracing || config.metrics.enable_otlp_metrics {
+        warn!("home-mixer was built without the otlp feature; not exporting over OTLP");
+    }
+
+    if config.metrics.sink != "prometheus" {
+        let sink = metrics_sink::open_metrics_sink(&config.metrics.sink)
+            .map_err(anyhow::Error::msg)?;
+        metrics_sink::spawn_flusher(
+            sink,
+            phoenix_candidate_pipeline::prod_metrics(),
+            std::time::Duration::from_secs(config.metrics.sink_flush_interval_secs),
+        );
+        info!("Flushing pipeline metrics to {}", config.metrics.sink);
+    }
 
     info!("Starting HomeMixer server on port {}", args.port);
     info!("Algorithm weights loaded from params.rs");
@@ -167,19 +746,306 @@ async fn main() -> Result<()> {
     info!("  Bookmark weight: {}", params::BOOKMARK_WEIGHT);
     info!("  Report weight: {}", params::REPORT_WEIGHT);
 
+    let author_lists = Arc::new(match &config.safety.author_list_path {
+        Some(path) => AuthorListStore::load(path)?,
+        None => AuthorListStore::new(),
+    });
+
+    // Serves the config reloaded on SIGHUP, at /admin/config/reload, and as
+    // CONFIG_SCHEDULES start and end
+    let config_watcher =
+        Arc::new(ConfigWatcher::new(config.clone()).with_strict_loading(args.strict_config));
+    #[cfg(unix)]
+    if let Err(e) = config_watcher.clone().spawn_sighup_reloader() {
+        error!("Failed to listen for SIGHUP; reload at /admin/config/reload: {}", e);
+    }
+    config_watcher.clone().spawn_scheduler();
+    for schedule in &config.active_schedules {
+        info!("Config schedule {} is active", schedule);
+    }
+
+    let debug_traces = Arc::new(DebugTraceStore::new(config.debug_traces.capacity));
+
+    let prod_switches = phoenix_candidate_pipeline::prod_kill_switches();
+    let kill_switches = Arc::new(match &config.kill_switches.path {
+        Some(path) => KillSwitchStore::load(prod_switches, path)?,
+        None => KillSwitchStore::new(prod_switches),
+    });
+    kill_switches.kill_configured(&config.kill_switches.components);
+    for switch in kill_switches.switches() {
+        warn!("Component {} is switched off: {}", switch.component, switch.reason);
+    }
+
+    let clustering = if config.personalization.enabled {
+        for (cluster_id, preset) in &config.personalization.cluster_weight_presets {
+            if weight_sets.get(preset).is_none() {
+                warn!("Unknown weight preset '{}' for cluster {}", preset, cluster_id);
+            }
+        }
+        let mut clustering = UserClusteringService::new(config.personalization.num_clusters)
+            .with_event_alpha(config.personalization.event_ewma_alpha)
+            .with_switch_margin(config.personalization.cluster_switch_margin)
+            .with_weight_presets(config.personalization.cluster_weight_presets.clone())
+            .with_cold_start(
+                Arc::new(ColdStartProfiler::new()),
+                config.personalization.cold_start_graduation_events,
+            )
+            .with_author_affinity(Arc::new(AuthorAffinityStore::new(
+                config.personalization.affinity_max_users,
+                config.personalization.affinity_max_authors_per_user,
+                std::time::Duration::from_secs_f64(
+                    config.personalization.affinity_half_life_days * 86_400.0,
+                ),
+            )))
+            .with_author_profiles(Arc::new(AuthorProfileStore::new(
+                config.personalization.author_profile_max_authors,
+            )))
+            .with_privacy(config.personalization.privacy())
+            .with_topic_muting(TopicMuteConfig {
+                threshold: config.personalization.topic_mute_threshold,
+                half_life: std::time::Duration::from_secs_f64(
+                    config.personalization.topic_mute_half_life_days * 86_400.0,
+                ),
+            });
+        if let Some(path) = &config.personalization.cluster_store_path {
+            let store = open_cluster_store(path).map_err(anyhow::Error::msg)?;
+            clustering = clustering.with_store(store);
+        }
+        if config.personalization.exploration_quota > 0.0 {
+            let tracker = ExplorationTracker::new(config.personalization.exploration());
+            clustering = clustering.with_exploration(Arc::new(tracker));
+        }
+        if let Some(source) = &config.personalization.features_source {
+            let provider = provider_from_source(source).map_err(anyhow::Error::msg)?;
+            clustering = clustering.with_features_provider(provider);
+        }
+        let clustering = Arc::new(clustering);
+        match clustering.load_from_store().await {
+            Ok(restored) => info!("Restored {} user cluster assignments", restored),
+            Err(e) => error!("Failed to restore user clusters: {}", e),
+        }
+        clustering.clone().spawn_snapshotter(std::time::Duration::from_secs(
+            config.personalization.snapshot_interval_secs,
+        ));
+        if config.personalization.enable_auto_refresh {
+            if !clustering.has_features_provider() {
+                error!("AUTO_REFRESH_CLUSTERS is set but USER_FEATURES_SOURCE is not");
+            }
+            let interval_secs = config.personalization.refresh_interval_hours * 3600;
+            clustering.clone().spawn_cluster_refresher(
+                std::time::Duration::from_secs(interval_secs),
+                std::time::Duration::from_secs(config.personalization.refresh_jitter_secs),
+            );
+        }
+        Some(clustering)
+    } else {
+        None
+    };
+
+    // Following lists are cached in front of the upstream, invalidated by follow events
+    let following = match &config.following.source {
+        Some(source) => {
+            let provider = ApiFollowingListProvider::new(source, std::time::Duration::from_secs(5))
+                .map_err(anyhow::Error::msg)?;
+            let cache_config = FollowingCacheConfig {
+                ttl: std::time::Duration::from_secs(config.following.cache_ttl_secs),
+                stale_ttl: std::time::Duration::from_secs(config.following.cache_stale_secs),
+                max_users: config.following.cache_max_users,
+            };
+            info!("Caching following lists from {}", source);
+            Some(Arc::new(FollowingListCache::new(Arc::new(provider), cache_config)))
+        }
+        None => None,
+    };
+
+    if let Some(webhook_url) = &config.alerting.webhook_url {
+        alerting::spawn_monitor(
+            config.alerting.clone(),
+            webhook_url.clone(),
+            phoenix_candidate_pipeline::prod_metrics(),
+            following.clone(),
+        );
+        info!("Alerting on health every {}s", config.alerting.interval_secs);
+    }
+
+    // Scored posts over HTTP, ranked with the config and author lists the
+    // other endpoints reload and edit
+    let mut services =
+        ProdServices::new(config_watcher.clone()).with_author_lists(author_lists.clone());
+    if let Some(clustering) = &clustering {
+        services = services.with_clustering(clustering.clone());
+    }
+    if let Some(following) = &following {
+        services = services.with_following(following.clone());
+    }
+    let similar_authors = (
+        &config.personalization.embeddings_dir,
+        &config.personalization.author_posts_source,
+    );
+    match similar_authors {
+        (Some(dir), Some(source)) => {
+            let embeddings = EmbeddingStore::load_dir(dir, HnswConfig::default())
+                .map_err(anyhow::Error::msg)?;
+            info!("Finding similar authors among {} in {}", embeddings.num_authors(), dir);
+            let posts = ThunderAuthorPostsProvider::new(source, std::time::Duration::from_secs(2))
+                .map_err(anyhow::Error::msg)?;
+            services = services.with_similar_authors(Arc::new(embeddings), Arc::new(posts));
+        },
+        (None, None) => {},
+        _ => warn!("Similar authors need both EMBEDDINGS_DIR and AUTHOR_POSTS_SOURCE"),
+    }
+    if let Some(source) = &config.personalization.engagement_history_source {
+        let provider =
+            ApiEngagementHistoryProvider::new(source, std::time::Duration::from_secs(5))
+                .map_err(anyhow::Error::msg)?;
+        services = services.with_engagement_history(Arc::new(provider));
+        info!("Deriving interest topics from engagement histories at {}", source);
+    }
+    let scored_posts = Arc::new(
+        HomeMixerServer::new(services)
+            .await
+            .with_debug_traces(debug_traces.clone()),
+    );
+
+    let admin_token = config.admin.token.as_deref();
+    if admin_token.is_none() {
+        warn!("ADMIN_TOKEN is not set; admin endpoints refuse every request");
+    }
+    let user_auth = UserAuth::new(config.auth.user_token_secret.as_deref());
+    let service_auth = user_auth.clone().with_service_token(config.auth.service_token.as_deref());
+
     // Build router
-    let app = Router::new()
+    #[allow(unused_mut)]
+    let mut app = Router::new()
         .route("/health", get(health))
         .route("/ready", get(health))
         .route("/api/weights", get(get_weights))
-        .route("/api/score", post(calculate_score));
+        .route("/api/score", post(calculate_score))
+        .merge(admin_only(
+            Router::new()
+                .route("/admin/authors", get(list_authors))
+                .route("/admin/authors/:list", post(add_author))
+                .route("/admin/authors/:list/:author_id", delete(remove_author))
+                .with_state(author_lists),
+            admin_token,
+        ))
+        // Events are learned as the user they're from, who must be the caller
+        // unless a backend sends them
+        .merge(authenticated(
+            Router::new()
+                .route("/api/events", post(ingest_events))
+                .with_state(clustering.clone()),
+            service_auth.clone(),
+        ))
+        .merge(admin_only(
+            Router::new()
+                .route("/admin/personalization/refresh", post(refresh_clusters))
+                .route("/admin/personalization/stats", get(get_cluster_stats))
+                .with_state(clustering.clone()),
+            admin_token,
+        ))
+        // Profiles are personal data, shown to and reset by their user, or by
+        // support with the admin token
+        .merge(authenticated(
+            Router::new()
+                .route(
+                    "/api/personalization/:user_id",
+                    get(get_personalization).delete(reset_personalization),
+                )
+                .with_state(clustering),
+            user_auth.clone().with_service_token(admin_token),
+        ))
+        // Follows are applied as the user making them, who must be the caller
+        // unless a backend sends them
+        .merge(authenticated(
+            Router::new()
+                .route("/api/follow_events", post(ingest_follow_events))
+                .with_state(following.clone()),
+            service_auth.clone(),
+        ))
+        .merge(admin_only(
+            Router::new()
+                .route("/admin/following/stats", get(get_following_cache_stats))
+                .with_state(following),
+            admin_token,
+        ))
+        .merge(admin_only(
+            Router::new()
+                .route("/admin/log_levels", get(get_log_levels).put(set_log_levels))
+                .with_state(log_levels),
+            admin_token,
+        ))
+        .merge(admin_only(
+            Router::new()
+                .route("/admin/traces", get(list_traced_users).post(add_traced_user))
+                .route("/admin/traces/:user_id", get(get_traces).delete(remove_traced_user))
+                .with_state(debug_traces),
+            admin_token,
+        ))
+        .merge(admin_only(
+            Router::new()
+                .route("/admin/kill_switches", get(list_kill_switches))
+                .route(
+                    "/admin/kill_switches/:component",
+                    put(kill_component).delete(restore_component),
+                )
+                .with_state(kill_switches),
+            admin_token,
+        ))
+        .merge(
+            Router::new()
+                .route("/metrics", get(metrics))
+                .with_state(config_watcher.clone()),
+        )
+        .merge(admin_only(
+            Router::new()
+                .route("/admin/config", get(get_config))
+                .route("/admin/config/reload", post(reload_config))
+                .with_state(config_watcher),
+            admin_token,
+        ))
+        .merge(gateway::router(scored_posts.clone()));
+
+    if config.profiling.enabled {
+        #[cfg(feature = "profiling")]
+        match admin_token {
+            Some(token) => {
+                app = app.merge(profiling::router(token));
+                info!("Serving profiles at /debug/pprof");
+            },
+            None => error!("ENABLE_PROFILING needs ADMIN_TOKEN; not serving profiles"),
+        }
+        #[cfg(not(feature = "profiling"))]
+        warn!("home-mixer was built without the profiling feature; not serving profiles");
+    }
 
     // Start server
     let addr: SocketAddr = format!("0.0.0.0:{}", args.port).parse()?;
     info!("Server listening on {}", addr);
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    let http = axum::serve(listener, app).with_graceful_shutdown(async {
+        let _ = tokio::signal::ctrl_c().await;
+    });
+
+    // Reflection lets grpcurl and the like call the service without the .proto files
+    let reflection = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(proto::FILE_DESCRIPTOR_SET)
+        .build()?;
+    let grpc_addr: SocketAddr = format!("0.0.0.0:{}", args.grpc_port).parse()?;
+    info!("gRPC server listening on {}", grpc_addr);
+    let grpc = tonic::transport::Server::builder()
+        .add_service(ScoredPostsServiceServer::from_arc(scored_posts))
+        .add_service(reflection)
+        .serve_with_shutdown(grpc_addr, async {
+            let _ = tokio::signal::ctrl_c().await;
+        });
+
+    tokio::select! {
+        served = grpc => served?,
+        served = http => served?,
+    }
 
+    info!("HomeMixer server terminated");
     Ok(())
 }
@@ -1,6 +1,8 @@
 //! Global parameters and constants for HomeMixer
 //! Updated January 2026 based on real X algorithm behavior analysis
 
+use crate::scorers::weight_sets::{WeightSet, WeightSetRegistry, DEFAULT_WEIGHT_SET};
+
 /// Maximum gRPC message size (16MB)
 pub const MAX_GRPC_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
 
@@ -10,6 +12,35 @@ pub const RESULT_SIZE: usize = 100;
 /// Maximum post age in seconds (7 days)
 pub const MAX_POST_AGE: u64 = 7 * 24 * 60 * 60;
 
+/// Per-stage deadlines of the Phoenix pipeline (milliseconds); optional components
+/// still running at their stage's deadline are skipped
+pub const SOURCE_TIMEOUT_MS: u64 = 200;
+pub const HYDRATOR_TIMEOUT_MS: u64 = 150;
+pub const FILTER_TIMEOUT_MS: u64 = 100;
+pub const SCORER_TIMEOUT_MS: u64 = 300;
+
+/// Once this many milliseconds have passed and a source has returned, optional
+/// sources still running are abandoned and the response is marked partial
+pub const SOURCE_SOFT_DEADLINE_MS: u64 = 120;
+
+/// Calls made at most per request by sources and hydrators, retries included;
+/// calls slower than the hedging percentile of recent ones are hedged
+pub const RETRY_MAX_ATTEMPTS: u32 = 2;
+pub const HEDGE_PERCENTILE: f64 = 0.95;
+
+/// Side effects run on this many workers behind a queue of this many requests;
+/// requests arriving with the queue full have their side effects dropped
+pub const SIDE_EFFECT_WORKERS: usize = 4;
+pub const SIDE_EFFECT_QUEUE_CAPACITY: usize = 4096;
+
+/// Request id chains remembered for session diversity, each forgotten this many
+/// seconds after its last page
+pub const MAX_SESSIONS: u64 = 1_000_000;
+pub const SESSION_IDLE_TIMEOUT_SECS: u64 = 30 * 60;
+
+/// Positions per slice in each block of a composed feed (see `FeedComposer`)
+pub const FEED_SLOT_PATTERN: &str = "in_network:6,out_of_network:2,discovery:1,trending:1";
+
 /// Minimum video duration for VQV weight eligibility (milliseconds)
 pub const MIN_VIDEO_DURATION_MS: i32 = 2000;
 
@@ -76,3 +107,169 @@ pub const NEGATIVE_WEIGHTS_SUM: f64 =
     NOT_INTERESTED_WEIGHT.abs() + BLOCK_AUTHOR_WEIGHT.abs() + MUTE_AUTHOR_WEIGHT.abs() + REPORT_WEIGHT.abs();
 
 pub const NEGATIVE_SCORES_OFFSET: f64 = 0.0;
+
+// Score tiers reported by /api/score, by lowest score
+pub const VIRAL_POTENTIAL_THRESHOLD: f64 = 30.0;
+pub const GOOD_THRESHOLD: f64 = 15.0;
+pub const AVERAGE_THRESHOLD: f64 = 5.0;
+
+// ============================================================================
+// Validation
+// ============================================================================
+
+/// Weights of actions a user takes on posts they like, by name
+const POSITIVE_WEIGHTS: [(&str, f64); 16] = [
+    ("FAVORITE_WEIGHT", FAVORITE_WEIGHT),
+    ("REPLY_WEIGHT", REPLY_WEIGHT),
+    ("RETWEET_WEIGHT", RETWEET_WEIGHT),
+    ("PHOTO_EXPAND_WEIGHT", PHOTO_EXPAND_WEIGHT),
+    ("CLICK_WEIGHT", CLICK_WEIGHT),
+    ("PROFILE_CLICK_WEIGHT", PROFILE_CLICK_WEIGHT),
+    ("VQV_WEIGHT", VQV_WEIGHT),
+    ("SHARE_WEIGHT", SHARE_WEIGHT),
+    ("SHARE_VIA_DM_WEIGHT", SHARE_VIA_DM_WEIGHT),
+    ("SHARE_VIA_COPY_LINK_WEIGHT", SHARE_VIA_COPY_LINK_WEIGHT),
+    ("DWELL_WEIGHT", DWELL_WEIGHT),
+    ("QUOTE_WEIGHT", QUOTE_WEIGHT),
+    ("QUOTED_CLICK_WEIGHT", QUOTED_CLICK_WEIGHT),
+    ("CONT_DWELL_TIME_WEIGHT", CONT_DWELL_TIME_WEIGHT),
+    ("FOLLOW_AUTHOR_WEIGHT", FOLLOW_AUTHOR_WEIGHT),
+    ("BOOKMARK_WEIGHT", BOOKMARK_WEIGHT),
+];
+
+/// Weights of actions a user takes on posts they don't want, by name
+const NEGATIVE_WEIGHTS: [(&str, f64); 4] = [
+    ("NOT_INTERESTED_WEIGHT", NOT_INTERESTED_WEIGHT),
+    ("BLOCK_AUTHOR_WEIGHT", BLOCK_AUTHOR_WEIGHT),
+    ("MUTE_AUTHOR_WEIGHT", MUTE_AUTHOR_WEIGHT),
+    ("REPORT_WEIGHT", REPORT_WEIGHT),
+];
+
+/// Check the weights above and every set in `weight_sets`, listing each
+/// violation. Scores from weights failing these would be silently wrong, so
+/// the server refuses to start on them.
+pub fn validate(weight_sets: &WeightSetRegistry) -> Result<(), String> {
+    let mut errors = Vec::new();
+    let others = [
+        ("AUTHOR_REPLY_BONUS", AUTHOR_REPLY_BONUS),
+        ("IN_NETWORK_WEIGHT", IN_NETWORK_WEIGHT),
+        ("OON_WEIGHT_FACTOR", OON_WEIGHT_FACTOR),
+        ("AUTHOR_DIVERSITY_DECAY", AUTHOR_DIVERSITY_DECAY),
+        ("FRESHNESS_DECAY_HOURS", FRESHNESS_DECAY_HOURS),
+        ("NEGATIVE_SCORES_OFFSET", NEGATIVE_SCORES_OFFSET),
+    ];
+    let all = POSITIVE_WEIGHTS.iter().chain(&NEGATIVE_WEIGHTS).chain(&others);
+    for (name, value) in all {
+        if !value.is_finite() {
+            errors.push(format!("{} is {}", name, value));
+        }
+    }
+    check_signs("", &POSITIVE_WEIGHTS, &NEGATIVE_WEIGHTS, &mut errors);
+
+    let positive_sum: f64 = POSITIVE_WEIGHTS.iter().map(|(_, w)| w).sum();
+    let negative_sum: f64 = NEGATIVE_WEIGHTS.iter().map(|(_, w)| w.abs()).sum();
+    if (WEIGHTS_SUM - positive_sum).abs() > 1e-9 {
+        errors.push(format!(
+            "WEIGHTS_SUM is {} but the positive weights sum to {}",
+            WEIGHTS_SUM, positive_sum
+        ));
+    }
+    if (NEGATIVE_WEIGHTS_SUM - negative_sum).abs() > 1e-9 {
+        errors.push(format!(
+            "NEGATIVE_WEIGHTS_SUM is {} but the negative weights sum to {}",
+            NEGATIVE_WEIGHTS_SUM, negative_sum
+        ));
+    }
+
+    if weight_sets.resolve(None) != &WeightSet::default() {
+        errors.push(format!("weight set {} differs from the weights above", DEFAULT_WEIGHT_SET));
+    }
+    for (name, set) in weight_sets.sets() {
+        let (positive, negative) = set.by_action();
+        let prefix = format!("weight set {}: ", name);
+        for (weight, value) in positive.iter().chain(&negative) {
+            if !value.is_finite() {
+                errors.push(format!("{}{} is {}", prefix, weight, value));
+            }
+        }
+        check_signs(&prefix, &positive, &negative, &mut errors);
+        // Negative scores are offset by NEGATIVE_WEIGHTS_SUM whatever the set
+        let set_negative_sum: f64 = negative.iter().map(|(_, w)| w.abs()).sum();
+        if set_negative_sum > NEGATIVE_WEIGHTS_SUM {
+            errors.push(format!(
+                "{}negative weights sum to {}, more than NEGATIVE_WEIGHTS_SUM",
+                prefix, set_negative_sum
+            ));
+        }
+    }
+
+    let tiers = [VIRAL_POTENTIAL_THRESHOLD, GOOD_THRESHOLD, AVERAGE_THRESHOLD, 0.0];
+    if !tiers.windows(2).all(|pair| pair[0] > pair[1]) {
+        errors.push(format!(
+            "tier thresholds must decrease and stay positive, got {:?}",
+            &tiers[..3]
+        ));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("; "))
+    }
+}
+
+fn check_signs(
+    prefix: &str,
+    positive: &[(&str, f64)],
+    negative: &[(&str, f64)],
+    errors: &mut Vec<String>,
+) {
+    for (name, value) in positive {
+        if *value < 0.0 {
+            errors.push(format!("{}{} is negative but rewards a positive action", prefix, name));
+        }
+    }
+    for (name, value) in negative {
+        if *value > 0.0 {
+            errors.push(format!("{}{} is positive but rewards a negative action", prefix, name));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shipped_weights_are_valid() {
+        assert_eq!(validate(&WeightSetRegistry::default()), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_lists_broken_weight_sets() {
+        let mut weight_sets = WeightSetRegistry::default();
+        weight_sets.register(
+            "broken",
+            WeightSet {
+                reply: -1.0,
+                report: 10.0,
+                block_author: f64::NAN,
+                ..WeightSet::default()
+            },
+        );
+        weight_sets.register(
+            "harsh",
+            WeightSet {
+                report: -1000.0,
+                ..WeightSet::default()
+            },
+        );
+        assert_eq!(
+            validate(&weight_sets).unwrap_err(),
+            "weight set broken: block_author is NaN; \
+             weight set broken: reply is negative but rewards a positive action; \
+             weight set broken: report is positive but rewards a negative action; \
+             weight set harsh: negative weights sum to 1274, more than NEGATIVE_WEIGHTS_SUM"
+        );
+    }
+}
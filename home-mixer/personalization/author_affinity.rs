@@ -0,0 +1,171 @@
+//! Per-(viewer, author) interaction affinity
+//!
+//! Following an account says little about whether the viewer still reads it.
+//! Every engagement event with a known author nudges an EWMA affinity for that
+//! pair; affinities also decay with a half-life so authors the viewer stopped
+//! engaging with drift back towards neutral. Both dimensions are bounded: viewers
+//! are evicted least-recently-used and each viewer keeps only their strongest
+//! authors.
+
+use crate::personalization::engagement_events::EngagementEvent;
+use moka::sync::Cache;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Affinity {
+    /// Smoothed engagement signal in [-1, 1]
+    score: f64,
+    updated_ms: i64,
+}
+
+impl Affinity {
+    fn decayed(&self, now_ms: i64, half_life_ms: f64) -> f64 {
+        let elapsed_ms = (now_ms - self.updated_ms).max(0) as f64;
+        self.score * 0.5f64.powf(elapsed_ms / half_life_ms)
+    }
+}
+
+pub struct AuthorAffinityStore {
+    users: Cache<u64, Arc<Mutex<HashMap<u64, Affinity>>>>,
+    max_authors_per_user: usize,
+    half_life_ms: f64,
+    alpha: f64,
+}
+
+impl AuthorAffinityStore {
+    pub fn new(max_users: u64, max_authors_per_user: usize, half_life: Duration) -> Self {
+        Self {
+            users: Cache::builder().max_capacity(max_users).build(),
+            max_authors_per_user: max_authors_per_user.max(1),
+            half_life_ms: half_life.as_millis().max(1) as f64,
+            alpha: 0.2,
+        }
+    }
+
+    /// EWMA smoothing factor per interaction
+    pub fn with_alpha(mut self, alpha: f64) -> Self {
+        self.alpha = alpha.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Fold an event into the viewer's affinity for its author. Returns false when the
+    /// event has no author or carries no signal.
+    pub fn record(&self, event: &EngagementEvent) -> bool {
+        let (Some(author_id), Some(signal)) = (event.author_id, event.signal()) else {
+            return false;
+        };
+        let authors = self
+            .users
+            .get_with(event.user_id, || Arc::new(Mutex::new(HashMap::new())));
+        let mut authors = authors.lock().unwrap();
+
+        let affinity = authors.entry(author_id).or_default();
+        let decayed = affinity.decayed(event.timestamp_ms, self.half_life_ms);
+        *affinity = Affinity {
+            score: (1.0 - self.alpha) * decayed + self.alpha * signal,
+            updated_ms: affinity.updated_ms.max(event.timestamp_ms),
+        };
+
+        if authors.len() > self.max_authors_per_user {
+            let now_ms = event.timestamp_ms;
+            let weakest = authors
+                .iter()
+                .filter(|(id, _)| **id != author_id)
+                .min_by(|a, b| {
+                    let a = a.1.decayed(now_ms, self.half_life_ms).abs();
+                    let b = b.1.decayed(now_ms, self.half_life_ms).abs();
+                    a.total_cmp(&b)
+                })
+                .map(|(id, _)| *id);
+            if let Some(weakest) = weakest {
+                authors.remove(&weakest);
+            }
+        }
+        true
+    }
+
+    /// Viewer's current affinity for `author_id` in [-1, 1]; 0 when they never
+    /// interacted
+    pub fn affinity(&self, user_id: u64, author_id: u64, now_ms: i64) -> f64 {
+        self.users
+            .get(&user_id)
+            .and_then(|authors| {
+                let authors = authors.lock().unwrap();
+                authors
+                    .get(&author_id)
+                    .map(|a| a.decayed(now_ms, self.half_life_ms))
+            })
+            .unwrap_or(0.0)
+    }
+
+    /// Decayed affinities for many authors at once, for scoring a page of candidates
+    pub fn affinities(&self, user_id: u64, author_ids: &[u64], now_ms: i64) -> HashMap<u64, f64> {
+        let Some(authors) = self.users.get(&user_id) else {
+            return HashMap::new();
+        };
+        let authors = authors.lock().unwrap();
+        author_ids
+            .iter()
+            .filter_map(|id| {
+                authors
+                    .get(id)
+                    .map(|a| (*id, a.decayed(now_ms, self.half_life_ms)))
+            })
+            .collect()
+    }
+
+    /// Forget everything recorded for a viewer
+    pub fn reset_user(&self, user_id: u64) {
+        self.users.invalidate(&user_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::personalization::engagement_events::EngagementEventType;
+
+    const DAY_MS: i64 = 86_400_000;
+
+    fn reply(author_id: u64, timestamp_ms: i64) -> EngagementEvent {
+        EngagementEvent {
+            user_id: 1,
+            tweet_id: 100,
+            author_id: Some(author_id),
+            event_type: EngagementEventType::Reply,
+            media_kind: None,
+            timestamp_ms,
+            dwell_ms: None,
+            topics: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_affinity_grows_decays_and_is_bounded() {
+        let store = AuthorAffinityStore::new(10, 2, Duration::from_millis(DAY_MS as u64))
+            .with_alpha(0.5);
+
+        assert!(store.record(&reply(10, 0)));
+        assert!(store.record(&reply(10, 0)));
+        assert!((store.affinity(1, 10, 0) - 0.75).abs() < 1e-9);
+        // One half-life later
+        assert!((store.affinity(1, 10, DAY_MS) - 0.375).abs() < 1e-9);
+        assert_eq!(store.affinity(1, 99, 0), 0.0);
+
+        // A third author evicts the weakest of the other two
+        store.record(&reply(20, 0));
+        store.record(&reply(30, 0));
+        let affinities = store.affinities(1, &[10, 20, 30], 0);
+        assert_eq!(affinities.len(), 2);
+        assert!(affinities.contains_key(&10) && affinities.contains_key(&30));
+
+        let mut no_author = reply(10, 0);
+        no_author.author_id = None;
+        assert!(!store.record(&no_author));
+
+        store.reset_user(1);
+        assert_eq!(store.affinity(1, 10, 0), 0.0);
+    }
+}
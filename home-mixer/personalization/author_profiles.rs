@@ -0,0 +1,235 @@
+//! Author-level quality signals aggregated from the events stream
+//!
+//! Per-candidate heuristics (follower ratios, a single post's text) are easy to
+//! game and say nothing about how viewers actually react to an author. This store
+//! counts impressions per author, and the distinct viewers who reported, spam
+//! reported or blocked them, plus engagement on their recent posts, and derives
+//! smoothed rates that filters and scorers read from the hydrated
+//! `PostCandidate::author_quality`. Repeating a report doesn't count again, so a
+//! single account can't get an author filtered.
+
+use crate::personalization::engagement_events::{EngagementEvent, EngagementEventType};
+use moka::sync::Cache;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// Impressions assumed before any are observed, so a single report on a brand-new
+/// author doesn't read as a 100% report rate
+const PRIOR_IMPRESSIONS: f64 = 100.0;
+
+/// Recent posts per author kept for the median engagement
+const MAX_TRACKED_POSTS: usize = 50;
+
+/// Viewers remembered per author and kind of event; past that each further
+/// event counts, the author having long been flagged by then
+const MAX_TRACKED_VIEWERS: usize = 1024;
+
+/// Distinct viewers who must report an author as spam before it can be
+/// flagged, so one viewer can't do it
+const MIN_SPAM_REPORTERS_TO_FLAG: u64 = 3;
+
+/// Spam reporters per impression at which an author is flagged
+const SPAM_REPORT_RATE_TO_FLAG: f64 = 0.01;
+
+/// Derived quality view of an author, hydrated onto candidates
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AuthorQuality {
+    pub impressions: u64,
+    /// Viewers who reported the author (any reason) per impression
+    pub report_rate: f64,
+    /// Viewers who blocked the author per impression
+    pub block_rate: f64,
+    /// Median positive engagements per recent post
+    pub median_engagement: f64,
+    /// Enough viewers reported the author as spam
+    pub spam_flagged: bool,
+}
+
+/// The distinct viewers behind one kind of event, up to `MAX_TRACKED_VIEWERS`
+#[derive(Debug, Default)]
+struct DistinctViewers {
+    seen: HashSet<u64>,
+    /// Events from viewers beyond those remembered
+    overflow: u64,
+}
+
+impl DistinctViewers {
+    fn insert(&mut self, user_id: u64) {
+        if self.seen.len() < MAX_TRACKED_VIEWERS {
+            self.seen.insert(user_id);
+        } else if !self.seen.contains(&user_id) {
+            self.overflow += 1;
+        }
+    }
+
+    fn count(&self) -> u64 {
+        self.seen.len() as u64 + self.overflow
+    }
+}
+
+#[derive(Debug, Default)]
+struct AuthorCounters {
+    impressions: u64,
+    reporters: DistinctViewers,
+    spam_reporters: DistinctViewers,
+    blockers: DistinctViewers,
+    /// Positive engagements per recent post, oldest first
+    post_engagements: VecDeque<(u64, u32)>,
+}
+
+impl AuthorCounters {
+    fn record(&mut self, event: &EngagementEvent) {
+        match event.event_type {
+            EngagementEventType::Impression => self.impressions += 1,
+            EngagementEventType::Report => self.reporters.insert(event.user_id),
+            EngagementEventType::ReportSpam => {
+                self.reporters.insert(event.user_id);
+                self.spam_reporters.insert(event.user_id);
+            },
+            EngagementEventType::Block => self.blockers.insert(event.user_id),
+            _ if event.signal().is_some_and(|s| s > 0.0) => self.record_engagement(event.tweet_id),
+            _ => {},
+        }
+    }
+
+    fn record_engagement(&mut self, tweet_id: u64) {
+        if let Some((_, count)) = self.post_engagements.iter_mut().find(|(id, _)| *id == tweet_id) {
+            *count += 1;
+            return;
+        }
+        if self.post_engagements.len() == MAX_TRACKED_POSTS {
+            self.post_engagements.pop_front();
+        }
+        self.post_engagements.push_back((tweet_id, 1));
+    }
+
+    fn quality(&self) -> AuthorQuality {
+        let exposure = self.impressions as f64 + PRIOR_IMPRESSIONS;
+        let spam_reporters = self.spam_reporters.count();
+        let spam_report_rate = spam_reporters as f64 / exposure;
+
+        let mut engagements: Vec<u32> = self.post_engagements.iter().map(|(_, c)| *c).collect();
+        engagements.sort_unstable();
+        let median_engagement = match engagements.len() {
+            0 => 0.0,
+            n if n % 2 == 1 => engagements[n / 2] as f64,
+            n => (engagements[n / 2 - 1] + engagements[n / 2]) as f64 / 2.0,
+        };
+
+        AuthorQuality {
+            impressions: self.impressions,
+            report_rate: self.reporters.count() as f64 / exposure,
+            block_rate: self.blockers.count() as f64 / exposure,
+            median_engagement,
+            spam_flagged: spam_reporters >= MIN_SPAM_REPORTERS_TO_FLAG
+                && spam_report_rate >= SPAM_REPORT_RATE_TO_FLAG,
+        }
+    }
+}
+
+pub struct AuthorProfileStore {
+    authors: Cache<u64, Arc<Mutex<AuthorCounters>>>,
+}
+
+impl AuthorProfileStore {
+    pub fn new(max_authors: u64) -> Self {
+        Self {
+            authors: Cache::builder().max_capacity(max_authors).build(),
+        }
+    }
+
+    /// Count an event against its post's author. Returns false when the event has
+    /// no author.
+    pub fn record(&self, event: &EngagementEvent) -> bool {
+        let Some(author_id) = event.author_id else {
+            return false;
+        };
+        let counters = self
+            .authors
+            .get_with(author_id, || Arc::new(Mutex::new(AuthorCounters::default())));
+        counters.lock().unwrap().record(event);
+        true
+    }
+
+    pub fn quality(&self, author_id: u64) -> Option<AuthorQuality> {
+        self.authors
+            .get(&author_id)
+            .map(|counters| counters.lock().unwrap().quality())
+    }
+
+    pub fn qualities(&self, author_ids: &[u64]) -> HashMap<u64, AuthorQuality> {
+        author_ids
+            .iter()
+            .filter_map(|id| self.quality(*id).map(|q| (*id, q)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(event_type: EngagementEventType, tweet_id: u64) -> EngagementEvent {
+        reported_by(1, event_type, tweet_id)
+    }
+
+    fn reported_by(
+        user_id: u64,
+        event_type: EngagementEventType,
+        tweet_id: u64,
+    ) -> EngagementEvent {
+        EngagementEvent {
+            user_id,
+            tweet_id,
+            author_id: Some(9),
+            event_type,
+            media_kind: None,
+            timestamp_ms: 0,
+            dwell_ms: None,
+            topics: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_rates_median_and_spam_flag() {
+        let store = AuthorProfileStore::new(100);
+        for _ in 0..100 {
+            store.record(&event(EngagementEventType::Impression, 1));
+        }
+        store.record(&event(EngagementEventType::Report, 1));
+        store.record(&event(EngagementEventType::Block, 1));
+        for (tweet_id, likes) in [(1, 1), (2, 5), (3, 2)] {
+            for _ in 0..likes {
+                store.record(&event(EngagementEventType::Like, tweet_id));
+            }
+        }
+
+        let quality = store.quality(9).unwrap();
+        assert_eq!(quality.impressions, 100);
+        assert!((quality.report_rate - 0.005).abs() < 1e-9);
+        assert!((quality.block_rate - 0.005).abs() < 1e-9);
+        assert_eq!(quality.median_engagement, 2.0);
+        assert!(!quality.spam_flagged);
+
+        store.record(&reported_by(2, EngagementEventType::ReportSpam, 1));
+        store.record(&reported_by(3, EngagementEventType::ReportSpam, 1));
+        assert!(!store.quality(9).unwrap().spam_flagged);
+        store.record(&reported_by(4, EngagementEventType::ReportSpam, 1));
+        assert!(store.quality(9).unwrap().spam_flagged);
+        assert!(store.quality(10).is_none());
+    }
+
+    #[test]
+    fn test_repeated_reports_from_one_viewer_count_once() {
+        let store = AuthorProfileStore::new(100);
+        for _ in 0..50 {
+            store.record(&event(EngagementEventType::ReportSpam, 1));
+            store.record(&event(EngagementEventType::Block, 1));
+        }
+
+        let quality = store.quality(9).unwrap();
+        assert!((quality.report_rate - 0.01).abs() < 1e-9);
+        assert!((quality.block_rate - 0.01).abs() < 1e-9);
+        assert!(!quality.spam_flagged);
+    }
+}
@@ -0,0 +1,228 @@
+//! Durable storage for user cluster assignments and centroids
+//!
+//! `FileClusterStore` is a small log-structured store: a JSON snapshot of the full
+//! state plus an append-only log of assignment upserts made since that snapshot.
+//! Loading replays the log over the snapshot; compaction folds the log back into a
+//! fresh snapshot. Larger deployments use `SledClusterStore`, a keyed on-disk
+//! store, with the `sled` feature.
+
+use crate::personalization::user_clusters::{ClusterProfile, PersistedCentroids};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tonic::async_trait;
+
+/// Everything the clustering service needs to resume after a restart
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StoredClusters {
+    #[serde(default)]
+    pub assignments: HashMap<u64, ClusterProfile>,
+    #[serde(default)]
+    pub centroids: PersistedCentroids,
+}
+
+#[async_trait]
+pub trait ClusterStore: Send + Sync {
+    /// Full persisted state; empty if nothing has been written yet
+    async fn load(&self) -> Result<StoredClusters, String>;
+
+    /// Record a single assignment change
+    async fn put_assignment(&self, user_id: u64, profile: &ClusterProfile) -> Result<(), String>;
+
+    /// Forget a user's assignment
+    async fn delete_assignment(&self, user_id: u64) -> Result<(), String>;
+
+    /// Replace the persisted state with `state`. Callers must not put or delete
+    /// assignments until it returns, or those entries may be lost.
+    async fn write_snapshot(&self, state: &StoredClusters) -> Result<(), String>;
+
+    /// Reclaim space used by superseded entries
+    async fn compact(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Store named by `spec`: `sled://{dir}`, or a directory for `FileClusterStore`
+pub fn open_cluster_store(spec: &str) -> Result<Arc<dyn ClusterStore>, String> {
+    match spec.strip_prefix("sled://") {
+        #[cfg(feature = "sled")]
+        Some(dir) => Ok(Arc::new(super::sled_cluster_store::SledClusterStore::open(dir)?)),
+        #[cfg(not(feature = "sled"))]
+        Some(_) => Err("home-mixer was built without sled support".to_string()),
+        None => match FileClusterStore::open(spec) {
+            Ok(store) => Ok(Arc::new(store)),
+            Err(e) => Err(format!("{}: {}", spec, e)),
+        },
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct LogEntry {
+    user_id: u64,
+    /// `None` is a tombstone left by `delete_assignment`
+    profile: Option<ClusterProfile>,
+}
+
+/// Snapshot + append-only log in a single directory
+pub struct FileClusterStore {
+    snapshot_path: PathBuf,
+    log_path: PathBuf,
+    /// Serializes writers so appends never interleave with a snapshot swap
+    write_lock: Mutex<()>,
+}
+
+impl FileClusterStore {
+    const SNAPSHOT_FILE: &'static str = "clusters.snapshot.json";
+    const LOG_FILE: &'static str = "clusters.log";
+
+    /// Opens (creating if needed) the store rooted at `dir`
+    pub fn open(dir: impl AsRef<Path>) -> std::io::Result<Self> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        Ok(Self {
+            snapshot_path: dir.join(Self::SNAPSHOT_FILE),
+            log_path: dir.join(Self::LOG_FILE),
+            write_lock: Mutex::new(()),
+        })
+    }
+
+    async fn read_state(&self) -> Result<StoredClusters, String> {
+        let mut state = match tokio::fs::read(&self.snapshot_path).await {
+            Ok(bytes) => serde_json::from_slice::<StoredClusters>(&bytes)
+                .map_err(|e| format!("corrupt cluster snapshot: {}", e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => StoredClusters::default(),
+            Err(e) => return Err(e.to_string()),
+        };
+
+        let log = match tokio::fs::read_to_string(&self.log_path).await {
+            Ok(log) => log,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.to_string()),
+        };
+        for line in log.lines().filter(|l| !l.trim().is_empty()) {
+            match serde_json::from_str::<LogEntry>(line) {
+                Ok(LogEntry {
+                    user_id,
+                    profile: Some(profile),
+                }) => {
+                    state.assignments.insert(user_id, profile);
+                },
+                Ok(LogEntry { user_id, profile: None }) => {
+                    state.assignments.remove(&user_id);
+                },
+                // A crash mid-append can leave a torn last line; skip it
+                Err(e) => log::warn!("Skipping unreadable cluster log entry: {}", e),
+            }
+        }
+
+        Ok(state)
+    }
+
+    async fn replace_snapshot(&self, state: &StoredClusters) -> Result<(), String> {
+        let bytes = serde_json::to_vec(state).map_err(|e| e.to_string())?;
+        let tmp = self.snapshot_path.with_extension("tmp");
+        tokio::fs::write(&tmp, bytes).await.map_err(|e| e.to_string())?;
+        tokio::fs::rename(&tmp, &self.snapshot_path)
+            .await
+            .map_err(|e| e.to_string())?;
+        // The snapshot now covers everything in the log
+        match tokio::fs::remove_file(&self.log_path).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    async fn append(&self, entry: &LogEntry) -> Result<(), String> {
+        let mut line = serde_json::to_vec(entry).map_err(|e| e.to_string())?;
+        line.push(b'\n');
+
+        let _guard = self.write_lock.lock().await;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.log_path)
+            .await
+            .map_err(|e| e.to_string())?;
+        file.write_all(&line).await.map_err(|e| e.to_string())?;
+        file.flush().await.map_err(|e| e.to_string())
+    }
+}
+
+#[async_trait]
+impl ClusterStore for FileClusterStore {
+    async fn load(&self) -> Result<StoredClusters, String> {
+        self.read_state().await
+    }
+
+    async fn put_assignment(&self, user_id: u64, profile: &ClusterProfile) -> Result<(), String> {
+        self.append(&LogEntry {
+            user_id,
+            profile: Some(profile.clone()),
+        })
+        .await
+    }
+
+    async fn delete_assignment(&self, user_id: u64) -> Result<(), String> {
+        self.append(&LogEntry {
+            user_id,
+            profile: None,
+        })
+        .await
+    }
+
+    async fn write_snapshot(&self, state: &StoredClusters) -> Result<(), String> {
+        let _guard = self.write_lock.lock().await;
+        self.replace_snapshot(state).await
+    }
+
+    async fn compact(&self) -> Result<(), String> {
+        let _guard = self.write_lock.lock().await;
+        let state = self.read_state().await?;
+        self.replace_snapshot(&state).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_log_replay_and_compaction() {
+        let dir = std::env::temp_dir().join(format!("cluster_store_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = FileClusterStore::open(&dir).unwrap();
+
+        let state = StoredClusters {
+            assignments: [(1, ClusterProfile::default())].into_iter().collect(),
+            centroids: PersistedCentroids {
+                centroids: vec![vec![0.5; 8]],
+                ..Default::default()
+            },
+        };
+        store.write_snapshot(&state).await.unwrap();
+
+        let profile = ClusterProfile {
+            cluster_id: 7,
+            ..Default::default()
+        };
+        store.put_assignment(2, &profile).await.unwrap();
+        store.put_assignment(1, &profile).await.unwrap();
+        store.put_assignment(3, &profile).await.unwrap();
+        store.delete_assignment(3).await.unwrap();
+
+        let loaded = FileClusterStore::open(&dir).unwrap().load().await.unwrap();
+        assert_eq!(loaded.assignments.len(), 2);
+        assert_eq!(loaded.assignments[&1].cluster_id, 7);
+        assert_eq!(loaded.centroids.centroids.len(), 1);
+
+        store.compact().await.unwrap();
+        assert!(!dir.join(FileClusterStore::LOG_FILE).exists());
+        assert_eq!(store.load().await.unwrap().assignments[&2].cluster_id, 7);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
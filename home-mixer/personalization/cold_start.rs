@@ -0,0 +1,222 @@
+//! Cold-start personalization
+//!
+//! Brand-new users have no engagement history, so their first profile is inferred
+//! from what we do know at signup: request locale, optionally declared interests,
+//! and the make-up of the accounts they followed first. The profile is marked
+//! `cold_start` until enough engagement events arrive to place the user in a
+//! learned cluster (see `UserClusteringService::record_event`).
+
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::personalization::user_clusters::{ClusterProfile, ContentType};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tonic::async_trait;
+
+/// Most content types kept on an inferred profile
+const MAX_PREFERRED_TYPES: usize = 5;
+
+/// Follows needed before the following list outweighs the defaults
+const FOLLOWING_PRIOR_WEIGHT: f64 = 5.0;
+
+/// What an author typically posts, derived from their recent posts
+#[derive(Clone, Debug, Default)]
+pub struct AuthorContentMix {
+    pub video_share: f64,
+    pub image_share: f64,
+    pub text_share: f64,
+    pub content_types: Vec<ContentType>,
+}
+
+/// Content mix of followed authors (backed by Thunder's recent posts per author)
+#[async_trait]
+pub trait FollowingCompositionProvider: Send + Sync {
+    async fn author_mixes(&self, author_ids: &[u64]) -> Result<Vec<AuthorContentMix>, String>;
+}
+
+pub struct ColdStartProfiler {
+    composition: Option<Arc<dyn FollowingCompositionProvider>>,
+    /// Only the first follows are sampled; they're the signup choices
+    max_follows_sampled: usize,
+}
+
+impl Default for ColdStartProfiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ColdStartProfiler {
+    pub fn new() -> Self {
+        Self {
+            composition: None,
+            max_follows_sampled: 50,
+        }
+    }
+
+    pub fn with_composition_provider(
+        mut self,
+        provider: Arc<dyn FollowingCompositionProvider>,
+    ) -> Self {
+        self.composition = Some(provider);
+        self
+    }
+
+    /// Regional content priors, keyed by country and falling back to language
+    fn locale_priors(country_code: &str, language_code: &str) -> Vec<ContentType> {
+        use ContentType::*;
+        match country_code.to_ascii_lowercase().as_str() {
+            "us" | "ca" => return vec![News, Sports, Entertainment],
+            "gb" | "ie" => return vec![News, Sports, Politics],
+            "in" | "pk" => return vec![Sports, Entertainment, News],
+            "jp" | "kr" => return vec![Entertainment, Gaming, Technology],
+            "br" | "ar" | "mx" => return vec![Sports, Entertainment],
+            _ => {},
+        }
+        match language_code.to_ascii_lowercase().split(['-', '_']).next() {
+            Some("es") | Some("pt") => vec![Sports, Entertainment],
+            Some("ja") | Some("ko") => vec![Entertainment, Gaming],
+            Some("en") => vec![News, Entertainment],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Initial profile for a user we have no learned cluster for
+    pub async fn infer(&self, query: &ScoredPostsQuery) -> ClusterProfile {
+        let mut profile = ClusterProfile {
+            cold_start: true,
+            ..Default::default()
+        };
+
+        let mixes = self.following_mixes(query).await;
+
+        // Declared interests first, then what the first follows post, then locale priors
+        let mut type_scores: HashMap<ContentType, f64> = HashMap::new();
+        let declared = query
+            .user_preferences
+            .as_ref()
+            .map(|prefs| prefs.declared_interests.as_slice())
+            .unwrap_or_default();
+        for content_type in declared {
+            *type_scores.entry(content_type.clone()).or_default() += 100.0;
+        }
+        for content_type in mixes.iter().flat_map(|m| &m.content_types) {
+            *type_scores.entry(content_type.clone()).or_default() += 1.0;
+        }
+        let priors = Self::locale_priors(&query.country_code, &query.language_code);
+        for (rank, content_type) in priors.into_iter().enumerate() {
+            *type_scores.entry(content_type).or_default() += 0.5 / (rank + 1) as f64;
+        }
+
+        if !type_scores.is_empty() {
+            let mut ranked: Vec<(ContentType, f64)> = type_scores.into_iter().collect();
+            ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            profile.preferred_content_types = ranked
+                .into_iter()
+                .take(MAX_PREFERRED_TYPES)
+                .map(|(content_type, _)| content_type)
+                .collect();
+        }
+
+        // Blend media preferences towards the following list as it grows
+        if !mixes.is_empty() {
+            let n = mixes.len() as f64;
+            let weight = n / (n + FOLLOWING_PRIOR_WEIGHT);
+            let mean = |f: fn(&AuthorContentMix) -> f64| mixes.iter().map(f).sum::<f64>() / n;
+            profile.video_preference =
+                (1.0 - weight) * profile.video_preference + weight * mean(|m| m.video_share);
+            profile.image_preference =
+                (1.0 - weight) * profile.image_preference + weight * mean(|m| m.image_share);
+            profile.text_preference =
+                (1.0 - weight) * profile.text_preference + weight * mean(|m| m.text_share);
+        }
+
+        profile
+    }
+
+    async fn following_mixes(&self, query: &ScoredPostsQuery) -> Vec<AuthorContentMix> {
+        let Some(provider) = &self.composition else {
+            return Vec::new();
+        };
+        let author_ids: Vec<u64> = query
+            .user_features
+            .followed_user_ids
+            .iter()
+            .take(self.max_follows_sampled)
+            .map(|id| *id as u64)
+            .collect();
+        if author_ids.is_empty() {
+            return Vec::new();
+        }
+        match provider.author_mixes(&author_ids).await {
+            Ok(mixes) => mixes,
+            Err(e) => {
+                log::warn!("Following composition lookup failed: {}", e);
+                Vec::new()
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::candidate_pipeline::query_features::{UserFeatures, UserPreferences};
+
+    struct VideoHeavyFollows;
+
+    #[async_trait]
+    impl FollowingCompositionProvider for VideoHeavyFollows {
+        async fn author_mixes(&self, author_ids: &[u64]) -> Result<Vec<AuthorContentMix>, String> {
+            Ok(author_ids
+                .iter()
+                .map(|_| AuthorContentMix {
+                    video_share: 1.0,
+                    image_share: 0.0,
+                    text_share: 0.0,
+                    content_types: vec![ContentType::Gaming],
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_infers_from_locale_interests_and_follows() {
+        let profiler =
+            ColdStartProfiler::new().with_composition_provider(Arc::new(VideoHeavyFollows));
+        let query = ScoredPostsQuery {
+            country_code: "JP".to_string(),
+            user_preferences: Some(UserPreferences {
+                declared_interests: vec![ContentType::Food],
+                ..Default::default()
+            }),
+            user_features: UserFeatures {
+                followed_user_ids: (1..=5).collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let profile = profiler.infer(&query).await;
+
+        assert!(profile.cold_start);
+        assert_eq!(profile.preferred_content_types[0], ContentType::Food);
+        assert_eq!(profile.preferred_content_types[1], ContentType::Gaming);
+        assert!(profile.preferred_content_types.contains(&ContentType::Entertainment));
+        // 5 follows carry half the weight against the 0.5 default
+        assert!((profile.video_preference - 0.75).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_language_fallback_without_follows() {
+        let query = ScoredPostsQuery {
+            language_code: "es-419".to_string(),
+            ..Default::default()
+        };
+        let profile = ColdStartProfiler::new().infer(&query).await;
+        assert_eq!(
+            profile.preferred_content_types,
+            vec![ContentType::Sports, ContentType::Entertainment]
+        );
+        assert_eq!(profile.video_preference, 0.5);
+    }
+}
@@ -0,0 +1,538 @@
+//! User and author embeddings with approximate nearest-neighbour lookup
+//!
+//! Author embeddings are indexed in an HNSW graph so "authors similar to this
+//! viewer" can be answered without scanning every author. User embeddings are only
+//! ever looked up by id and live in a plain map. Both are trained offline and
+//! exported as `.npy` matrices, one row per id, with a sidecar `.ids` file listing
+//! the ids in row order.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::path::Path;
+
+/// HNSW tuning knobs
+#[derive(Clone, Debug)]
+pub struct HnswConfig {
+    /// Links kept per node on the upper layers; layer 0 keeps twice as many
+    pub max_connections: usize,
+    /// Beam width while inserting
+    pub ef_construction: usize,
+    /// Minimum beam width while searching
+    pub ef_search: usize,
+}
+
+impl Default for HnswConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 16,
+            ef_construction: 100,
+            ef_search: 64,
+        }
+    }
+}
+
+/// Distance paired with a node index, ordered by distance
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Neighbor {
+    distance: f32,
+    node: usize,
+}
+
+impl Eq for Neighbor {}
+
+impl PartialOrd for Neighbor {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Neighbor {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then_with(|| self.node.cmp(&other.node))
+    }
+}
+
+/// Hierarchical navigable small world graph over cosine distance
+pub struct HnswIndex {
+    config: HnswConfig,
+    dims: usize,
+    ids: Vec<u64>,
+    /// Unit-normalized vectors, indexed by node
+    vectors: Vec<Vec<f32>>,
+    /// Neighbour lists per node per layer
+    links: Vec<Vec<Vec<usize>>>,
+    nodes_by_id: HashMap<u64, usize>,
+    entry_point: Option<usize>,
+    level_multiplier: f64,
+}
+
+impl HnswIndex {
+    pub fn new(dims: usize, config: HnswConfig) -> Self {
+        let level_multiplier = 1.0 / (config.max_connections.max(2) as f64).ln();
+        Self {
+            config,
+            dims,
+            ids: Vec::new(),
+            vectors: Vec::new(),
+            links: Vec::new(),
+            nodes_by_id: HashMap::new(),
+            entry_point: None,
+            level_multiplier,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn contains(&self, id: u64) -> bool {
+        self.nodes_by_id.contains_key(&id)
+    }
+
+    /// Layer for a new node, drawn from an exponential distribution. Hashing the id
+    /// keeps index builds reproducible.
+    fn random_level(&self, id: u64) -> usize {
+        let mut x = id.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        x ^= x >> 31;
+        let uniform = ((x >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+        (-uniform.ln() * self.level_multiplier) as usize
+    }
+
+    fn max_links(&self, level: usize) -> usize {
+        if level == 0 {
+            self.config.max_connections * 2
+        } else {
+            self.config.max_connections
+        }
+    }
+
+    fn distance(&self, query: &[f32], node: usize) -> f32 {
+        1.0 - dot(query, &self.vectors[node])
+    }
+
+    fn top_level(&self) -> usize {
+        self.entry_point.map_or(0, |ep| self.links[ep].len() - 1)
+    }
+
+    /// Greedy walk towards `query` on a single layer
+    fn closest_on_layer(&self, query: &[f32], mut current: usize, level: usize) -> usize {
+        let mut best = self.distance(query, current);
+        loop {
+            let mut improved = false;
+            for &neighbor in &self.links[current][level] {
+                let distance = self.distance(query, neighbor);
+                if distance < best {
+                    best = distance;
+                    current = neighbor;
+                    improved = true;
+                }
+            }
+            if !improved {
+                return current;
+            }
+        }
+    }
+
+    /// Beam search on one layer; returns up to `ef` nodes, closest first
+    fn search_layer(&self, query: &[f32], entry: usize, ef: usize, level: usize) -> Vec<Neighbor> {
+        let start = Neighbor {
+            distance: self.distance(query, entry),
+            node: entry,
+        };
+        let mut visited = HashSet::from([entry]);
+        let mut candidates = BinaryHeap::from([Reverse(start)]);
+        let mut results = BinaryHeap::from([start]);
+
+        while let Some(Reverse(candidate)) = candidates.pop() {
+            let worst = results.peek().map_or(f32::INFINITY, |n| n.distance);
+            if candidate.distance > worst && results.len() >= ef {
+                break;
+            }
+            for &node in &self.links[candidate.node][level] {
+                if !visited.insert(node) {
+                    continue;
+                }
+                let neighbor = Neighbor {
+                    distance: self.distance(query, node),
+                    node,
+                };
+                let worst = results.peek().map_or(f32::INFINITY, |n| n.distance);
+                if results.len() < ef || neighbor.distance < worst {
+                    candidates.push(Reverse(neighbor));
+                    results.push(neighbor);
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+
+        results.into_sorted_vec()
+    }
+
+    /// Drop the furthest links of `node` on `level` beyond the layer's capacity
+    fn prune(&mut self, node: usize, level: usize) {
+        let max_links = self.max_links(level);
+        if self.links[node][level].len() <= max_links {
+            return;
+        }
+        let mut scored: Vec<Neighbor> = self.links[node][level]
+            .iter()
+            .map(|&other| Neighbor {
+                distance: 1.0 - dot(&self.vectors[node], &self.vectors[other]),
+                node: other,
+            })
+            .collect();
+        scored.sort_unstable();
+        self.links[node][level] = scored.into_iter().take(max_links).map(|n| n.node).collect();
+    }
+
+    pub fn insert(&mut self, id: u64, vector: &[f32]) -> Result<(), String> {
+        if vector.len() != self.dims {
+            return Err(format!(
+                "embedding for {} has {} dims, expected {}",
+                id,
+                vector.len(),
+                self.dims
+            ));
+        }
+        if self.contains(id) {
+            return Err(format!("duplicate embedding for {}", id));
+        }
+
+        let vector = normalize(vector);
+        let node = self.ids.len();
+        let level = self.random_level(id);
+        self.ids.push(id);
+        self.vectors.push(vector.clone());
+        self.links.push(vec![Vec::new(); level + 1]);
+        self.nodes_by_id.insert(id, node);
+
+        let Some(mut entry) = self.entry_point else {
+            self.entry_point = Some(node);
+            return Ok(());
+        };
+
+        let top_level = self.top_level();
+        for layer in (level + 1..=top_level).rev() {
+            entry = self.closest_on_layer(&vector, entry, layer);
+        }
+        for layer in (0..=level.min(top_level)).rev() {
+            let found = self.search_layer(&vector, entry, self.config.ef_construction, layer);
+            let neighbors: Vec<usize> = found
+                .iter()
+                .take(self.max_links(layer))
+                .map(|n| n.node)
+                .collect();
+            for &neighbor in &neighbors {
+                self.links[neighbor][layer].push(node);
+                self.prune(neighbor, layer);
+            }
+            self.links[node][layer] = neighbors;
+            entry = found[0].node;
+        }
+
+        if level > top_level {
+            self.entry_point = Some(node);
+        }
+        Ok(())
+    }
+
+    /// Up to `k` ids most similar to `query` as `(id, cosine similarity)`, most similar
+    /// first, skipping ids in `exclude`
+    pub fn search(&self, query: &[f32], k: usize, exclude: &HashSet<u64>) -> Vec<(u64, f32)> {
+        let Some(mut entry) = self.entry_point else {
+            return Vec::new();
+        };
+        if k == 0 || query.len() != self.dims {
+            return Vec::new();
+        }
+
+        let query = normalize(query);
+        for layer in (1..=self.top_level()).rev() {
+            entry = self.closest_on_layer(&query, entry, layer);
+        }
+        // Widen the beam so excluded ids don't starve the result
+        let ef = self.config.ef_search.max(k + exclude.len()).min(self.len());
+        self.search_layer(&query, entry, ef, 0)
+            .into_iter()
+            .map(|n| (self.ids[n.node], 1.0 - n.distance))
+            .filter(|(id, _)| !exclude.contains(id))
+            .take(k)
+            .collect()
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn normalize(vector: &[f32]) -> Vec<f32> {
+    let norm = dot(vector, vector).sqrt();
+    if norm == 0.0 {
+        return vector.to_vec();
+    }
+    vector.iter().map(|x| x / norm).collect()
+}
+
+/// Row-major `f32` matrix decoded from a `.npy` file
+struct NpyMatrix {
+    rows: usize,
+    cols: usize,
+    data: Vec<f32>,
+}
+
+/// Decode a 2-D little-endian float32/float64 C-order `.npy` file
+fn parse_npy(bytes: &[u8]) -> Result<NpyMatrix, String> {
+    if bytes.len() < 10 || &bytes[..6] != b"\x93NUMPY" {
+        return Err("not an .npy file".to_string());
+    }
+    let (header_len, header_start) = match bytes[6] {
+        1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+        2 | 3 if bytes.len() >= 12 => {
+            let len = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]);
+            (len as usize, 12)
+        },
+        version => return Err(format!("unsupported .npy version {}", version)),
+    };
+    let data_start = header_start + header_len;
+    let header = bytes
+        .get(header_start..data_start)
+        .and_then(|h| std::str::from_utf8(h).ok())
+        .ok_or_else(|| "truncated .npy header".to_string())?;
+
+    if header.contains("'fortran_order': True") {
+        return Err(".npy arrays must be C-ordered".to_string());
+    }
+    let width = if header.contains("'descr': '<f4'") {
+        4
+    } else if header.contains("'descr': '<f8'") {
+        8
+    } else {
+        return Err(format!("unsupported .npy dtype in header {}", header.trim()));
+    };
+    let shape: Vec<usize> = header
+        .split_once("'shape': (")
+        .and_then(|(_, rest)| rest.split_once(')'))
+        .map(|(dims, _)| dims.split(',').filter_map(|d| d.trim().parse().ok()).collect())
+        .unwrap_or_default();
+    let [rows, cols] = shape[..] else {
+        return Err(format!("expected a 2-D array, got shape {:?}", shape));
+    };
+
+    let payload = &bytes[data_start..];
+    if payload.len() != rows * cols * width {
+        return Err(format!(
+            ".npy payload is {} bytes, expected {}",
+            payload.len(),
+            rows * cols * width
+        ));
+    }
+    let data = if width == 4 {
+        payload
+            .chunks_exact(4)
+            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect()
+    } else {
+        payload
+            .chunks_exact(8)
+            .map(|c| f64::from_le_bytes(c.try_into().unwrap()) as f32)
+            .collect()
+    };
+    Ok(NpyMatrix { rows, cols, data })
+}
+
+/// Load `(id, embedding)` pairs from an embeddings matrix and its id list (one id
+/// per line, in row order). Parquet exports must be converted to `.npy` first.
+pub fn load_embeddings(
+    vectors_path: &Path,
+    ids_path: &Path,
+) -> Result<Vec<(u64, Vec<f32>)>, String> {
+    let matrix = match vectors_path.extension().and_then(|e| e.to_str()) {
+        Some("npy") => {
+            let bytes = std::fs::read(vectors_path)
+                .map_err(|e| format!("{}: {}", vectors_path.display(), e))?;
+            parse_npy(&bytes).map_err(|e| format!("{}: {}", vectors_path.display(), e))?
+        },
+        Some("parquet") => {
+            return Err(format!(
+                "{}: parquet embeddings are not supported by this build; export to .npy",
+                vectors_path.display()
+            ))
+        },
+        _ => return Err(format!("{}: unknown embeddings format", vectors_path.display())),
+    };
+
+    let ids: Vec<u64> = std::fs::read_to_string(ids_path)
+        .map_err(|e| format!("{}: {}", ids_path.display(), e))?
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.trim().parse::<u64>().map_err(|e| format!("bad id {:?}: {}", l, e)))
+        .collect::<Result<_, _>>()?;
+    if ids.len() != matrix.rows {
+        return Err(format!(
+            "{} ids for {} embedding rows in {}",
+            ids.len(),
+            matrix.rows,
+            vectors_path.display()
+        ));
+    }
+
+    Ok(ids
+        .into_iter()
+        .zip(matrix.data.chunks_exact(matrix.cols.max(1)).map(<[f32]>::to_vec))
+        .collect())
+}
+
+/// User embeddings plus an ANN index over author embeddings, in a shared space
+pub struct EmbeddingStore {
+    dims: usize,
+    users: HashMap<u64, Vec<f32>>,
+    authors: HnswIndex,
+}
+
+impl EmbeddingStore {
+    pub fn new(dims: usize, config: HnswConfig) -> Self {
+        Self {
+            dims,
+            users: HashMap::new(),
+            authors: HnswIndex::new(dims, config),
+        }
+    }
+
+    /// Load `users.npy`/`users.ids` and `authors.npy`/`authors.ids` from `dir`
+    pub fn load_dir(dir: impl AsRef<Path>, config: HnswConfig) -> Result<Self, String> {
+        let dir = dir.as_ref();
+        let users = load_embeddings(&dir.join("users.npy"), &dir.join("users.ids"))?;
+        let authors = load_embeddings(&dir.join("authors.npy"), &dir.join("authors.ids"))?;
+        let dims = users
+            .first()
+            .or(authors.first())
+            .map_or(0, |(_, embedding)| embedding.len());
+
+        let mut store = Self::new(dims, config);
+        for (user_id, embedding) in users {
+            store.insert_user(user_id, embedding)?;
+        }
+        for (author_id, embedding) in authors {
+            store.insert_author(author_id, &embedding)?;
+        }
+        Ok(store)
+    }
+
+    pub fn dims(&self) -> usize {
+        self.dims
+    }
+
+    pub fn num_users(&self) -> usize {
+        self.users.len()
+    }
+
+    pub fn num_authors(&self) -> usize {
+        self.authors.len()
+    }
+
+    pub fn insert_user(&mut self, user_id: u64, embedding: Vec<f32>) -> Result<(), String> {
+        if embedding.len() != self.dims {
+            return Err(format!(
+                "embedding for user {} has {} dims, expected {}",
+                user_id,
+                embedding.len(),
+                self.dims
+            ));
+        }
+        self.users.insert(user_id, embedding);
+        Ok(())
+    }
+
+    pub fn insert_author(&mut self, author_id: u64, embedding: &[f32]) -> Result<(), String> {
+        self.authors.insert(author_id, embedding)
+    }
+
+    pub fn user_embedding(&self, user_id: u64) -> Option<&[f32]> {
+        self.users.get(&user_id).map(Vec::as_slice)
+    }
+
+    /// Authors closest to the user's embedding that aren't in `followed` (nor the
+    /// user themselves), most similar first
+    pub fn similar_unfollowed_authors(
+        &self,
+        user_id: u64,
+        followed: &HashSet<u64>,
+        k: usize,
+    ) -> Vec<(u64, f32)> {
+        let Some(embedding) = self.user_embedding(user_id) else {
+            return Vec::new();
+        };
+        let mut exclude = followed.clone();
+        exclude.insert(user_id);
+        self.authors.search(embedding, k, &exclude)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(angle: f32) -> Vec<f32> {
+        vec![angle.cos(), angle.sin()]
+    }
+
+    #[test]
+    fn test_hnsw_matches_brute_force() {
+        let mut index = HnswIndex::new(2, HnswConfig::default());
+        for id in 0..500u64 {
+            index.insert(id, &unit(id as f32 * 0.0125)).unwrap();
+        }
+        assert!(index.insert(3, &unit(0.0)).is_err());
+
+        // id 80 sits at angle 1.0; the query leans slightly towards 81
+        let found = index.search(&unit(1.002), 3, &HashSet::new());
+        let ids: Vec<u64> = found.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, vec![80, 81, 79]);
+        assert!(found[0].1 > 0.999);
+
+        let excluded = index.search(&unit(1.002), 1, &HashSet::from([80, 81]));
+        assert_eq!(excluded[0].0, 79);
+    }
+
+    #[test]
+    fn test_similar_unfollowed_authors_and_npy_loading() {
+        let dir = std::env::temp_dir().join(format!("embeddings_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let write_npy = |name: &str, rows: &[Vec<f32>]| {
+            let header = format!(
+                "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, 2), }}\n",
+                rows.len()
+            );
+            let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+            bytes.extend((header.len() as u16).to_le_bytes());
+            bytes.extend(header.as_bytes());
+            bytes.extend(rows.iter().flatten().flat_map(|x| x.to_le_bytes()));
+            std::fs::write(dir.join(name), bytes).unwrap();
+        };
+        write_npy("users.npy", &[unit(0.0)]);
+        std::fs::write(dir.join("users.ids"), "1\n").unwrap();
+        write_npy("authors.npy", &[unit(0.1), unit(0.2), unit(3.0), unit(0.0)]);
+        std::fs::write(dir.join("authors.ids"), "10\n20\n30\n1\n").unwrap();
+
+        let store = EmbeddingStore::load_dir(&dir, HnswConfig::default()).unwrap();
+        assert_eq!(store.num_authors(), 4);
+
+        // The user's own account and followed authors are never suggested
+        let similar = store.similar_unfollowed_authors(1, &HashSet::from([10]), 2);
+        assert_eq!(similar.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![20, 30]);
+        assert!(store.similar_unfollowed_authors(2, &HashSet::new(), 2).is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,223 @@
+//! Online engagement events
+//!
+//! Each event nudges the viewer's `ClusterProfile` with an exponentially weighted
+//! moving average, so personalization reacts within a session instead of waiting
+//! for the nightly cluster refresh.
+
+use crate::personalization::user_clusters::ClusterProfile;
+use chrono::{TimeZone, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EngagementEventType {
+    Like,
+    Reply,
+    Repost,
+    /// Viewer lingered on the post; see `dwell_ms`
+    Dwell,
+    VideoComplete,
+    NotInterested,
+    /// Post was shown; carries no signal but is the denominator for author rates
+    Impression,
+    Report,
+    /// Report with spam as the reason
+    ReportSpam,
+    /// Viewer blocked the post's author
+    Block,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaKind {
+    Video,
+    Image,
+    Text,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EngagementEvent {
+    pub user_id: u64,
+    pub tweet_id: u64,
+    /// Author of the engaged post; feeds `AuthorAffinityStore`
+    #[serde(default)]
+    pub author_id: Option<u64>,
+    pub event_type: EngagementEventType,
+    /// Media type of the engaged post, when known
+    #[serde(default)]
+    pub media_kind: Option<MediaKind>,
+    pub timestamp_ms: i64,
+    #[serde(default)]
+    pub dwell_ms: Option<u64>,
+    /// Topics of the engaged post (see `TopicExtractor`)
+    #[serde(default)]
+    pub topics: Vec<String>,
+}
+
+/// Dwell shorter than this is treated as a scroll-past, not engagement
+const MIN_MEANINGFUL_DWELL_MS: u64 = 2_000;
+
+/// Number of hours kept in `peak_activity_hours`
+const PEAK_HOURS: usize = 12;
+
+/// Topics kept in `topic_affinity`; the weakest are evicted first
+const MAX_TRACKED_TOPICS: usize = 50;
+
+impl EngagementEvent {
+    /// Signed engagement strength in [-1, 1]; `None` for events that carry no signal
+    pub fn signal(&self) -> Option<f64> {
+        match self.event_type {
+            EngagementEventType::Like => Some(0.5),
+            EngagementEventType::Reply => Some(1.0),
+            EngagementEventType::Repost => Some(0.75),
+            EngagementEventType::VideoComplete => Some(0.5),
+            EngagementEventType::Dwell => self
+                .dwell_ms
+                .filter(|ms| *ms >= MIN_MEANINGFUL_DWELL_MS)
+                .map(|_| 0.25),
+            EngagementEventType::NotInterested
+            | EngagementEventType::Report
+            | EngagementEventType::ReportSpam
+            | EngagementEventType::Block => Some(-1.0),
+            EngagementEventType::Impression => None,
+        }
+    }
+
+    fn hour_of_day(&self) -> Option<usize> {
+        Utc.timestamp_millis_opt(self.timestamp_ms)
+            .single()
+            .map(|ts| ts.hour() as usize)
+    }
+}
+
+fn ewma(current: f64, target: f64, alpha: f64) -> f64 {
+    (1.0 - alpha) * current + alpha * target
+}
+
+impl ClusterProfile {
+    /// Fold one engagement event into the profile with smoothing factor `alpha`.
+    /// Returns false when the event carried no signal and nothing changed.
+    pub fn apply_event(&mut self, event: &EngagementEvent, alpha: f64) -> bool {
+        let Some(signal) = event.signal() else {
+            return false;
+        };
+        let alpha = alpha.clamp(0.0, 1.0);
+        let positive = signal > 0.0;
+        self.observed_events += 1;
+
+        // Media preference moves towards 1 on engagement, towards 0 on rejection
+        let media_kind = match event.event_type {
+            EngagementEventType::VideoComplete => Some(MediaKind::Video),
+            _ => event.media_kind,
+        };
+        let target = if positive { 1.0 } else { 0.0 };
+        match media_kind {
+            Some(MediaKind::Video) => {
+                self.video_preference = ewma(self.video_preference, target, alpha)
+            },
+            Some(MediaKind::Image) => {
+                self.image_preference = ewma(self.image_preference, target, alpha)
+            },
+            Some(MediaKind::Text) => {
+                self.text_preference = ewma(self.text_preference, target, alpha)
+            },
+            None => {},
+        }
+
+        self.engagement_multiplier = ewma(self.engagement_multiplier, 1.0 + signal, alpha);
+        self.negative_feedback_rate =
+            ewma(self.negative_feedback_rate, if positive { 0.0 } else { 1.0 }, alpha);
+
+        if positive {
+            if let Some(hour) = event.hour_of_day() {
+                self.record_activity_hour(hour, alpha);
+            }
+        }
+
+        for topic in &event.topics {
+            let affinity = self.topic_affinity.entry(topic.clone()).or_default();
+            *affinity = ewma(*affinity, signal, alpha);
+        }
+        if self.topic_affinity.len() > MAX_TRACKED_TOPICS {
+            let mut by_strength: Vec<(String, f64)> = self.topic_affinity.drain().collect();
+            by_strength.sort_by(|a, b| b.1.abs().total_cmp(&a.1.abs()));
+            by_strength.truncate(MAX_TRACKED_TOPICS);
+            self.topic_affinity = by_strength.into_iter().collect();
+        }
+
+        true
+    }
+
+    fn record_activity_hour(&mut self, hour: usize, alpha: f64) {
+        if self.hourly_activity.len() != 24 {
+            // Seed the histogram from the existing peak hours
+            let mut seeded = vec![0.0; 24];
+            let peaks: Vec<usize> = self
+                .peak_activity_hours
+                .iter()
+                .map(|h| *h as usize)
+                .filter(|h| *h < 24)
+                .collect();
+            for h in &peaks {
+                seeded[*h] = 1.0 / peaks.len() as f64;
+            }
+            self.hourly_activity = seeded;
+        }
+
+        for (h, activity) in self.hourly_activity.iter_mut().enumerate() {
+            *activity = ewma(*activity, if h == hour { 1.0 } else { 0.0 }, alpha);
+        }
+
+        let mut hours: Vec<usize> = (0..24).filter(|h| self.hourly_activity[*h] > 0.0).collect();
+        hours.sort_by(|a, b| self.hourly_activity[*b].total_cmp(&self.hourly_activity[*a]));
+        hours.truncate(PEAK_HOURS);
+        hours.sort_unstable();
+        self.peak_activity_hours = hours.into_iter().map(|h| h as u8).collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(event_type: EngagementEventType, media_kind: Option<MediaKind>) -> EngagementEvent {
+        EngagementEvent {
+            user_id: 1,
+            tweet_id: 10,
+            author_id: None,
+            event_type,
+            media_kind,
+            // 1970-01-01T03:00:00Z
+            timestamp_ms: 3 * 3_600_000,
+            dwell_ms: None,
+            topics: vec!["sports".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_positive_and_negative_events() {
+        let mut profile = ClusterProfile::default();
+
+        assert!(profile.apply_event(&event(EngagementEventType::VideoComplete, None), 0.5));
+        assert!((profile.video_preference - 0.75).abs() < 1e-9);
+        assert!((profile.engagement_multiplier - 1.25).abs() < 1e-9);
+        assert!(profile.peak_activity_hours.contains(&3));
+
+        let not_interested = event(EngagementEventType::NotInterested, Some(MediaKind::Image));
+        assert!(profile.apply_event(&not_interested, 0.5));
+        assert!((profile.image_preference - 0.25).abs() < 1e-9);
+        assert!(profile.negative_feedback_rate > 0.5);
+        assert!(profile.engagement_multiplier < 1.0);
+        // +0.5 signal then -1.0 signal at alpha 0.5
+        assert!((profile.topic_affinity["sports"] + 0.375).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_short_dwell_is_ignored() {
+        let mut profile = ClusterProfile::default();
+        let mut dwell = event(EngagementEventType::Dwell, Some(MediaKind::Text));
+        dwell.dwell_ms = Some(500);
+        assert!(!profile.apply_event(&dwell, 0.5));
+        assert_eq!(profile.text_preference, 0.5);
+    }
+}
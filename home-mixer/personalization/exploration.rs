@@ -0,0 +1,204 @@
+//! Exploration outside a user's learned interests
+//!
+//! A share of each timeline (`quota`) is reserved for candidates whose topics mostly
+//! fall outside what the user is known to like. The served exploratory posts are
+//! remembered for `attribution_window`; positive engagement on one of them is folded
+//! into the profile with a larger learning rate, so interests found through
+//! exploration are picked up quickly instead of being drowned out by the existing
+//! preferences.
+
+use crate::candidate_pipeline::candidate::PostCandidate;
+use crate::personalization::engagement_events::EngagementEvent;
+use crate::personalization::user_clusters::ClusterProfile;
+use moka::sync::Cache;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Share of a post's topics that may be preferred ones for it to count as exploration
+const MAX_PREFERRED_OVERLAP: f64 = 0.3;
+
+#[derive(Clone, Debug)]
+pub struct ExplorationConfig {
+    /// Share of each timeline reserved for exploratory candidates
+    pub quota: f64,
+    /// Factor applied to the event learning rate for engagement on explored posts
+    pub learning_boost: f64,
+    /// How long after serving an engagement is still credited to exploration
+    pub attribution_window: Duration,
+    /// Served (user, post) pairs remembered at once; the oldest are evicted
+    pub max_tracked: u64,
+}
+
+impl Default for ExplorationConfig {
+    fn default() -> Self {
+        Self {
+            quota: 0.1,
+            learning_boost: 3.0,
+            attribution_window: Duration::from_secs(86_400),
+            max_tracked: 10_000_000,
+        }
+    }
+}
+
+/// Whether a post with `topics` lies outside the user's preferences: the topics
+/// they have positive affinity for plus any request-time `interests`. Posts without
+/// topics can't be placed and never count.
+pub fn is_exploratory(
+    topics: &[String],
+    profile: &ClusterProfile,
+    interests: Option<&HashSet<String>>,
+) -> bool {
+    if topics.is_empty() {
+        return false;
+    }
+    let preferred = topics
+        .iter()
+        .filter(|topic| {
+            profile.topic_affinity.get(*topic).is_some_and(|a| *a > 0.0)
+                || interests.is_some_and(|i| i.contains(*topic))
+        })
+        .count();
+    (preferred as f64 / topics.len() as f64) < MAX_PREFERRED_OVERLAP
+}
+
+/// How exploratory posts have fared since startup
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct ExplorationStats {
+    pub served: u64,
+    pub engaged: u64,
+}
+
+impl ExplorationStats {
+    pub fn engagement_rate(&self) -> f64 {
+        if self.served == 0 {
+            0.0
+        } else {
+            self.engaged as f64 / self.served as f64
+        }
+    }
+}
+
+/// Remembers which (user, post) pairs were served as exploration
+pub struct ExplorationTracker {
+    served: Cache<(u64, u64), ()>,
+    served_count: AtomicU64,
+    engaged_count: AtomicU64,
+    config: ExplorationConfig,
+}
+
+impl ExplorationTracker {
+    pub fn new(config: ExplorationConfig) -> Self {
+        Self {
+            served: Cache::builder()
+                .max_capacity(config.max_tracked)
+                .time_to_live(config.attribution_window)
+                .build(),
+            served_count: AtomicU64::new(0),
+            engaged_count: AtomicU64::new(0),
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &ExplorationConfig {
+        &self.config
+    }
+
+    /// Remember the exploratory candidates among those served to `user_id`
+    pub fn record_served(&self, user_id: u64, served: &[PostCandidate]) {
+        for candidate in served.iter().filter(|c| c.exploratory == Some(true)) {
+            self.served.insert((user_id, candidate.tweet_id as u64), ());
+            self.served_count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether `event` is positive engagement on a post served as exploration. Each
+    /// served post is credited at most once.
+    pub fn record_outcome(&self, event: &EngagementEvent) -> bool {
+        if !event.signal().is_some_and(|s| s > 0.0) {
+            return false;
+        }
+        if self.served.remove(&(event.user_id, event.tweet_id)).is_none() {
+            return false;
+        }
+        self.engaged_count.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// Learning rate for `event` given the base rate `alpha`
+    pub fn learning_rate(&self, event: &EngagementEvent, alpha: f64) -> f64 {
+        if self.record_outcome(event) {
+            (alpha * self.config.learning_boost).min(1.0)
+        } else {
+            alpha
+        }
+    }
+
+    pub fn stats(&self) -> ExplorationStats {
+        ExplorationStats {
+            served: self.served_count.load(Ordering::Relaxed),
+            engaged: self.engaged_count.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::personalization::engagement_events::EngagementEventType;
+
+    fn topics(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_exploratory_means_mostly_unpreferred_topics() {
+        let profile = ClusterProfile {
+            topic_affinity: [("sports".to_string(), 0.6), ("crypto".to_string(), -0.4)]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        let interests: HashSet<String> = ["news".to_string()].into_iter().collect();
+
+        assert!(!is_exploratory(&topics(&["sports"]), &profile, None));
+        assert!(!is_exploratory(&topics(&["news", "science"]), &profile, Some(&interests)));
+        assert!(is_exploratory(&topics(&["crypto", "science"]), &profile, Some(&interests)));
+        assert!(!is_exploratory(&[], &profile, None));
+    }
+
+    #[test]
+    fn test_engagement_on_explored_posts_is_credited_once() {
+        let tracker = ExplorationTracker::new(ExplorationConfig::default());
+        let served = vec![
+            PostCandidate {
+                tweet_id: 10,
+                exploratory: Some(true),
+                ..Default::default()
+            },
+            PostCandidate {
+                tweet_id: 11,
+                exploratory: Some(false),
+                ..Default::default()
+            },
+        ];
+        tracker.record_served(1, &served);
+
+        let like = |tweet_id| EngagementEvent {
+            user_id: 1,
+            tweet_id,
+            author_id: None,
+            event_type: EngagementEventType::Like,
+            media_kind: None,
+            timestamp_ms: 0,
+            dwell_ms: None,
+            topics: Vec::new(),
+        };
+        assert_eq!(tracker.learning_rate(&like(11), 0.1), 0.1);
+        assert!((tracker.learning_rate(&like(10), 0.1) - 0.3).abs() < 1e-9);
+        assert_eq!(tracker.learning_rate(&like(10), 0.1), 0.1);
+        assert_eq!(tracker.stats(), ExplorationStats { served: 1, engaged: 1 });
+        assert_eq!(tracker.stats().engagement_rate(), 1.0);
+    }
+}
@@ -0,0 +1,132 @@
+//! Personalization introspection
+//!
+//! Explains which profile signals are currently shaping a user's ranking, for the
+//! self-service `GET /api/personalization/{user_id}` endpoint and support tooling.
+
+use crate::personalization::user_clusters::{ClusterProfile, ContentType};
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// Topics listed in each of `top_topics` / `suppressed_topics`
+const MAX_TOPICS_REPORTED: usize = 10;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TopicWeight {
+    pub topic: String,
+    pub affinity: f64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ContentPreferences {
+    pub preferred_content_types: Vec<ContentType>,
+    pub video: f64,
+    pub image: f64,
+    pub text: f64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct NegativeSignals {
+    pub negative_feedback_rate: f64,
+    /// Factor applied to negative-action weights (not interested, block, mute, report)
+    pub negative_weight_multiplier: f64,
+    /// Topics the user has pushed back on, strongest first
+    pub suppressed_topics: Vec<TopicWeight>,
+    /// Topics filtered out entirely after repeated "not interested" feedback
+    pub muted_topics: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PersonalizationReport {
+    pub user_id: u64,
+    pub cluster_id: usize,
+    pub cold_start: bool,
+    pub observed_events: u64,
+    pub top_topics: Vec<TopicWeight>,
+    pub content_preferences: ContentPreferences,
+    pub negative_signals: NegativeSignals,
+    pub profile: ClusterProfile,
+}
+
+impl PersonalizationReport {
+    pub fn new(user_id: u64, profile: ClusterProfile, muted_topics: HashSet<String>) -> Self {
+        let mut muted_topics: Vec<String> = muted_topics.into_iter().collect();
+        muted_topics.sort();
+
+        let mut topics: Vec<TopicWeight> = profile
+            .topic_affinity
+            .iter()
+            .map(|(topic, affinity)| TopicWeight {
+                topic: topic.clone(),
+                affinity: *affinity,
+            })
+            .collect();
+        topics.sort_by(|a, b| b.affinity.total_cmp(&a.affinity).then(a.topic.cmp(&b.topic)));
+
+        let top_topics = topics
+            .iter()
+            .filter(|t| t.affinity > 0.0)
+            .take(MAX_TOPICS_REPORTED)
+            .cloned()
+            .collect();
+        let suppressed_topics = topics
+            .iter()
+            .rev()
+            .filter(|t| t.affinity < 0.0)
+            .take(MAX_TOPICS_REPORTED)
+            .cloned()
+            .collect();
+
+        Self {
+            user_id,
+            cluster_id: profile.cluster_id,
+            cold_start: profile.cold_start,
+            observed_events: profile.observed_events,
+            top_topics,
+            content_preferences: ContentPreferences {
+                preferred_content_types: profile.preferred_content_types.clone(),
+                video: profile.video_preference,
+                image: profile.image_preference,
+                text: profile.text_preference,
+            },
+            negative_signals: NegativeSignals {
+                negative_feedback_rate: profile.negative_feedback_rate,
+                negative_weight_multiplier: profile.negative_weight_multiplier(),
+                suppressed_topics,
+                muted_topics,
+            },
+            profile,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_splits_liked_and_suppressed_topics() {
+        let profile = ClusterProfile {
+            negative_feedback_rate: 0.1,
+            topic_affinity: [("sports", 0.6), ("news", 0.2), ("politics", -0.4), ("crypto", -0.8)]
+                .into_iter()
+                .map(|(t, a)| (t.to_string(), a))
+                .collect(),
+            ..Default::default()
+        };
+
+        let muted = HashSet::from(["crypto".to_string()]);
+        let report = PersonalizationReport::new(1, profile, muted);
+
+        let top: Vec<_> = report.top_topics.iter().map(|t| t.topic.as_str()).collect();
+        let suppressed: Vec<_> = report
+            .negative_signals
+            .suppressed_topics
+            .iter()
+            .map(|t| t.topic.as_str())
+            .collect();
+        assert_eq!(top, vec!["sports", "news"]);
+        assert_eq!(suppressed, vec!["crypto", "politics"]);
+        assert_eq!(report.negative_signals.negative_weight_multiplier, 2.0);
+        assert_eq!(report.negative_signals.muted_topics, vec!["crypto"]);
+    }
+}
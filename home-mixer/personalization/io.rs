@@ -0,0 +1,394 @@
+//! Bulk import of `UserFeatures` and export of cluster assignments
+//!
+//! Offline jobs hand features over as CSV with a header row. List-valued columns
+//! (`preferred_content_types`, `peak_hours`) hold `|`-separated values. Files are
+//! streamed line by line so multi-million-row exports never sit in memory as text,
+//! and invalid rows are counted and skipped rather than failing the whole import.
+
+use crate::personalization::privacy::ClusterAggregate;
+use crate::personalization::user_clusters::{ClusterProfile, ContentType, UserFeatures};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Columns every features file must have, in any order
+pub const FEATURE_COLUMNS: [&str; 11] = [
+    "user_id",
+    "preferred_content_types",
+    "video_engagement_rate",
+    "image_engagement_rate",
+    "text_engagement_rate",
+    "avg_post_age_hours",
+    "diversity_score",
+    "overall_engagement_rate",
+    "peak_hours",
+    "avg_session_duration_min",
+    "negative_feedback_rate",
+];
+
+/// Columns written by `export_cluster_assignments`
+pub const ASSIGNMENT_COLUMNS: [&str; 10] = [
+    "user_id",
+    "cluster_id",
+    "cold_start",
+    "observed_events",
+    "video_preference",
+    "image_preference",
+    "text_preference",
+    "engagement_multiplier",
+    "negative_feedback_rate",
+    "preferred_content_types",
+];
+
+/// Columns written by `export_cluster_aggregates`
+pub const AGGREGATE_COLUMNS: [&str; 6] = [
+    "cluster_id",
+    "users",
+    "video_preference",
+    "image_preference",
+    "text_preference",
+    "diversity_preference",
+];
+
+/// Rows between progress callbacks
+pub const PROGRESS_INTERVAL: u64 = 100_000;
+
+/// Row-level errors kept on the report; the rest are only counted
+const MAX_REPORTED_ERRORS: usize = 20;
+
+const LIST_SEPARATOR: char = '|';
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FeatureFormat {
+    Csv,
+    Parquet,
+}
+
+impl FeatureFormat {
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => Ok(Self::Csv),
+            Some("parquet") => Ok(Self::Parquet),
+            _ => Err(format!("{}: expected a .csv or .parquet file", path.display())),
+        }
+    }
+}
+
+/// Progress of a long-running import or export
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Progress {
+    pub rows: u64,
+    pub bytes: u64,
+    /// File size for imports, `None` for exports
+    pub total_bytes: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub features: Vec<UserFeatures>,
+    pub rows_read: u64,
+    pub rows_rejected: u64,
+    /// The first few row errors, as `line N: reason`
+    pub errors: Vec<String>,
+}
+
+/// Import features from `path`, calling `on_progress` every `PROGRESS_INTERVAL` rows
+pub fn import_user_features(
+    path: &Path,
+    mut on_progress: impl FnMut(Progress),
+) -> Result<ImportReport, String> {
+    if FeatureFormat::from_path(path)? == FeatureFormat::Parquet {
+        return Err(format!(
+            "{}: parquet is not supported by this build; export the job as CSV",
+            path.display()
+        ));
+    }
+
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let total_bytes = file.metadata().ok().map(|m| m.len());
+    let mut lines = BufReader::new(file).lines();
+
+    let header = lines
+        .next()
+        .ok_or_else(|| format!("{}: empty file", path.display()))?
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    let columns = column_indices(&split_csv_line(&header))
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let mut report = ImportReport::default();
+    let mut progress = Progress {
+        bytes: header.len() as u64 + 1,
+        total_bytes,
+        ..Default::default()
+    };
+    for (index, line) in lines.enumerate() {
+        let line = line.map_err(|e| format!("{}: {}", path.display(), e))?;
+        progress.bytes += line.len() as u64 + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        report.rows_read += 1;
+        match parse_feature_row(&split_csv_line(&line), &columns) {
+            Ok(features) => report.features.push(features),
+            Err(e) => {
+                report.rows_rejected += 1;
+                if report.errors.len() < MAX_REPORTED_ERRORS {
+                    // +2: one for the header, one for 1-based line numbers
+                    report.errors.push(format!("line {}: {}", index + 2, e));
+                }
+            },
+        }
+
+        if report.rows_read % PROGRESS_INTERVAL == 0 {
+            progress.rows = report.rows_read;
+            on_progress(progress);
+        }
+    }
+
+    progress.rows = report.rows_read;
+    on_progress(progress);
+    Ok(report)
+}
+
+/// Write `assignments` to `path` as CSV, ordered by user id. Returns rows written.
+pub fn export_cluster_assignments(
+    assignments: &HashMap<u64, ClusterProfile>,
+    path: &Path,
+    mut on_progress: impl FnMut(Progress),
+) -> Result<u64, String> {
+    let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut writer = BufWriter::new(file);
+    let io_err = |e: std::io::Error| format!("{}: {}", path.display(), e);
+
+    let mut progress = Progress::default();
+    let header = ASSIGNMENT_COLUMNS.join(",");
+    writeln!(writer, "{}", header).map_err(io_err)?;
+    progress.bytes += header.len() as u64 + 1;
+
+    let mut user_ids: Vec<&u64> = assignments.keys().collect();
+    user_ids.sort_unstable();
+    for user_id in user_ids {
+        let profile = &assignments[user_id];
+        let content_types: Vec<String> = profile
+            .preferred_content_types
+            .iter()
+            .map(|t| format!("{:?}", t))
+            .collect();
+        let row = format!(
+            "{},{},{},{},{},{},{},{},{},{}",
+            user_id,
+            profile.cluster_id,
+            profile.cold_start,
+            profile.observed_events,
+            profile.video_preference,
+            profile.image_preference,
+            profile.text_preference,
+            profile.engagement_multiplier,
+            profile.negative_feedback_rate,
+            content_types.join(&LIST_SEPARATOR.to_string()),
+        );
+        writeln!(writer, "{}", row).map_err(io_err)?;
+        progress.rows += 1;
+        progress.bytes += row.len() as u64 + 1;
+        if progress.rows % PROGRESS_INTERVAL == 0 {
+            on_progress(progress);
+        }
+    }
+
+    writer.flush().map_err(io_err)?;
+    on_progress(progress);
+    Ok(progress.rows)
+}
+
+/// Write differentially private per-cluster aggregates (see `privacy`) to `path`
+pub fn export_cluster_aggregates(
+    aggregates: &[ClusterAggregate],
+    path: &Path,
+) -> Result<(), String> {
+    let io_err = |e: std::io::Error| format!("{}: {}", path.display(), e);
+    let mut writer = BufWriter::new(File::create(path).map_err(io_err)?);
+    writeln!(writer, "{}", AGGREGATE_COLUMNS.join(",")).map_err(io_err)?;
+    for a in aggregates {
+        writeln!(
+            writer,
+            "{},{},{:.4},{:.4},{:.4},{:.4}",
+            a.cluster_id,
+            a.users,
+            a.video_preference,
+            a.image_preference,
+            a.text_preference,
+            a.diversity_preference
+        )
+        .map_err(io_err)?;
+    }
+    writer.flush().map_err(io_err)
+}
+
+/// Split one CSV record, honouring double-quoted fields with `""` escapes
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.trim_end_matches('\r').chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            },
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Position of each required column in the header
+fn column_indices(header: &[String]) -> Result<HashMap<&'static str, usize>, String> {
+    let missing: Vec<&str> = FEATURE_COLUMNS
+        .iter()
+        .copied()
+        .filter(|column| !header.iter().any(|h| h.trim() == *column))
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!("missing columns: {}", missing.join(", ")));
+    }
+    Ok(FEATURE_COLUMNS
+        .iter()
+        .map(|column| (*column, header.iter().position(|h| h.trim() == *column).unwrap()))
+        .collect())
+}
+
+fn parse_feature_row(
+    fields: &[String],
+    columns: &HashMap<&'static str, usize>,
+) -> Result<UserFeatures, String> {
+    let field = |name: &str| -> Result<&str, String> {
+        fields
+            .get(columns[name])
+            .map(|f| f.trim())
+            .ok_or_else(|| format!("missing value for {}", name))
+    };
+    let number = |name: &str| -> Result<f64, String> {
+        let value = field(name)?;
+        value
+            .parse::<f64>()
+            .ok()
+            .filter(|v| v.is_finite() && *v >= 0.0)
+            .ok_or_else(|| format!("{} must be a non-negative number, got {:?}", name, value))
+    };
+    let rate = |name: &str| -> Result<f64, String> {
+        let value = number(name)?;
+        if value > 1.0 {
+            return Err(format!("{} must be within [0, 1], got {}", name, value));
+        }
+        Ok(value)
+    };
+    let list = |name: &str| -> Result<Vec<String>, String> {
+        Ok(field(name)?
+            .split(LIST_SEPARATOR)
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+            .collect())
+    };
+
+    let user_id = field("user_id")?;
+    let user_id = user_id
+        .parse::<u64>()
+        .map_err(|_| format!("user_id must be an unsigned integer, got {:?}", user_id))?;
+    let preferred_content_types = list("preferred_content_types")?
+        .into_iter()
+        .map(|name| {
+            serde_json::from_value::<ContentType>(serde_json::Value::String(name.clone()))
+                .map_err(|_| format!("unknown content type {:?}", name))
+        })
+        .collect::<Result<_, _>>()?;
+    let peak_hours = list("peak_hours")?
+        .into_iter()
+        .map(|hour| {
+            hour.parse::<u8>()
+                .ok()
+                .filter(|h| *h < 24)
+                .ok_or_else(|| format!("peak_hours must be within 0-23, got {:?}", hour))
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(UserFeatures {
+        user_id,
+        preferred_content_types,
+        video_engagement_rate: rate("video_engagement_rate")?,
+        image_engagement_rate: rate("image_engagement_rate")?,
+        text_engagement_rate: rate("text_engagement_rate")?,
+        avg_post_age_hours: number("avg_post_age_hours")?,
+        diversity_score: rate("diversity_score")?,
+        overall_engagement_rate: rate("overall_engagement_rate")?,
+        peak_hours,
+        avg_session_duration_min: number("avg_session_duration_min")?,
+        negative_feedback_rate: rate("negative_feedback_rate")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import_validates_rows_and_export_round_trips() {
+        let dir = std::env::temp_dir().join(format!("personalization_io_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let features_path = dir.join("features.csv");
+
+        // Columns deliberately out of order; the header decides placement
+        let header = "peak_hours,user_id,preferred_content_types,video_engagement_rate,\
+            image_engagement_rate,text_engagement_rate,avg_post_age_hours,diversity_score,\
+            overall_engagement_rate,avg_session_duration_min,negative_feedback_rate";
+        let rows = [
+            "\"19|20|21\",1,Sports|News,0.8,0.1,0.3,6,0.4,0.2,12.5,0.01",
+            "9,2,Gaming,1.5,0.1,0.3,6,0.4,0.2,12.5,0.01",
+            "9,3,Knitting,0.5,0.1,0.3,6,0.4,0.2,12.5,0.01",
+            "",
+            "25,4,Food,0.5,0.1,0.3,6,0.4,0.2,12.5,0.01",
+        ];
+        std::fs::write(&features_path, format!("{}\n{}\n", header, rows.join("\n"))).unwrap();
+
+        let mut updates = 0;
+        let report = import_user_features(&features_path, |_| updates += 1).unwrap();
+        assert_eq!(updates, 1);
+        assert_eq!(report.rows_read, 4);
+        assert_eq!(report.rows_rejected, 3);
+        assert!(report.errors[0].starts_with("line 3: video_engagement_rate"));
+        assert_eq!(report.features[0].peak_hours, vec![19, 20, 21]);
+        assert_eq!(
+            report.features[0].preferred_content_types,
+            vec![ContentType::Sports, ContentType::News]
+        );
+
+        std::fs::write(&features_path, "user_id,peak_hours\n1,9\n").unwrap();
+        let err = import_user_features(&features_path, |_| {}).unwrap_err();
+        assert!(err.contains("missing columns: preferred_content_types"));
+        assert!(import_user_features(&dir.join("features.parquet"), |_| {}).is_err());
+
+        let assignments_path = dir.join("assignments.csv");
+        let assignments = HashMap::from([
+            (2, ClusterProfile::default()),
+            (1, ClusterProfile {
+                cluster_id: 4,
+                ..Default::default()
+            }),
+        ]);
+        let written = export_cluster_assignments(&assignments, &assignments_path, |_| {});
+        assert_eq!(written, Ok(2));
+        let exported = std::fs::read_to_string(&assignments_path).unwrap();
+        let lines: Vec<&str> = exported.lines().collect();
+        assert_eq!(lines[0], ASSIGNMENT_COLUMNS.join(","));
+        assert!(lines[1].starts_with("1,4,false,0,"));
+        assert!(lines[2].ends_with(",Other"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1 +1,17 @@
-pub mod user_clusters;
\ No newline at end of file
+pub mod author_affinity;
+pub mod author_profiles;
+pub mod cluster_store;
+pub mod cold_start;
+pub mod embedding_store;
+pub mod engagement_events;
+pub mod exploration;
+pub mod introspection;
+pub mod io;
+pub mod privacy;
+pub mod session_store;
+#[cfg(feature = "sled")]
+pub mod sled_cluster_store;
+pub mod topic_extractor;
+pub mod topic_muting;
+pub mod user_clusters;
+pub mod user_features_provider;
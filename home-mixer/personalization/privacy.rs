@@ -0,0 +1,193 @@
+//! Differentially private personalization aggregates
+//!
+//! Dashboards get cluster sizes and per-cluster preference means with Laplace
+//! noise calibrated to `epsilon`, and clusters whose noisy size is below
+//! `min_cluster_size` are suppressed entirely. Each user belongs to exactly one
+//! cluster and every per-user value is bounded to [0, 1], so each count and each
+//! sum has sensitivity 1; the budget is split evenly across the released columns.
+//! Noise is drawn from the OS CSPRNG, so it can't be predicted and subtracted.
+
+use crate::personalization::user_clusters::{ClusterProfile, ClusterStats};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug)]
+pub struct PrivacyConfig {
+    /// Total privacy budget per release; smaller is noisier
+    pub epsilon: f64,
+    /// Clusters with fewer (noisy) users than this are not released
+    pub min_cluster_size: usize,
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        Self {
+            epsilon: 1.0,
+            min_cluster_size: 20,
+        }
+    }
+}
+
+/// Uniform sample in (0, 1)
+fn uniform() -> f64 {
+    ((OsRng.next_u64() >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+}
+
+/// Sample from Laplace(0, scale)
+pub fn laplace_noise(scale: f64) -> f64 {
+    let u = uniform() - 0.5;
+    -scale * u.signum() * (1.0 - 2.0 * u.abs()).ln()
+}
+
+/// Count with noise for a query of sensitivity 1
+fn noisy_count(count: usize, epsilon: f64) -> f64 {
+    (count as f64 + laplace_noise(1.0 / epsilon)).max(0.0)
+}
+
+/// `ClusterStats` safe to share outside the team
+#[derive(Clone, Debug, Serialize)]
+pub struct PrivateClusterStats {
+    pub num_clusters: usize,
+    pub total_users: usize,
+    /// Noisy size per cluster; `None` where the cluster was suppressed
+    pub cluster_sizes: Vec<Option<usize>>,
+    pub suppressed_clusters: usize,
+    pub epsilon: f64,
+}
+
+impl ClusterStats {
+    /// Noisy, small-cluster-suppressed copy. Inertia is dropped: it depends on the
+    /// exact position of every user.
+    pub fn privatize(&self, config: &PrivacyConfig) -> PrivateClusterStats {
+        // Sizes and the total are two releases over the same users
+        let epsilon = config.epsilon / 2.0;
+        let cluster_sizes: Vec<Option<usize>> = self
+            .cluster_sizes
+            .iter()
+            .map(|size| {
+                let noisy = noisy_count(*size, epsilon).round() as usize;
+                (noisy >= config.min_cluster_size).then_some(noisy)
+            })
+            .collect();
+        PrivateClusterStats {
+            num_clusters: self.num_clusters,
+            total_users: noisy_count(self.total_users, epsilon).round() as usize,
+            suppressed_clusters: cluster_sizes.iter().filter(|s| s.is_none()).count(),
+            cluster_sizes,
+            epsilon: config.epsilon,
+        }
+    }
+}
+
+/// Noisy per-cluster means for analysis exports
+#[derive(Clone, Debug, Serialize)]
+pub struct ClusterAggregate {
+    pub cluster_id: usize,
+    pub users: usize,
+    pub video_preference: f64,
+    pub image_preference: f64,
+    pub text_preference: f64,
+    pub diversity_preference: f64,
+}
+
+/// Released columns per cluster: the count plus four preference sums
+const AGGREGATE_COLUMNS: f64 = 5.0;
+
+/// Per-cluster aggregates over `assignments`, ordered by cluster id, with small
+/// clusters left out
+pub fn private_cluster_aggregates(
+    assignments: &HashMap<u64, ClusterProfile>,
+    config: &PrivacyConfig,
+) -> Vec<ClusterAggregate> {
+    let mut sums: HashMap<usize, (usize, [f64; 4])> = HashMap::new();
+    for profile in assignments.values() {
+        let (count, totals) = sums.entry(profile.cluster_id).or_default();
+        *count += 1;
+        let values = [
+            profile.video_preference,
+            profile.image_preference,
+            profile.text_preference,
+            profile.diversity_preference,
+        ];
+        for (total, value) in totals.iter_mut().zip(values) {
+            *total += value.clamp(0.0, 1.0);
+        }
+    }
+
+    let epsilon = config.epsilon / AGGREGATE_COLUMNS;
+    let mut aggregates: Vec<ClusterAggregate> = sums
+        .into_iter()
+        .filter_map(|(cluster_id, (count, totals))| {
+            let users = noisy_count(count, epsilon);
+            if users < config.min_cluster_size as f64 {
+                return None;
+            }
+            let mean = |total: f64| {
+                ((total + laplace_noise(1.0 / epsilon)) / users).clamp(0.0, 1.0)
+            };
+            Some(ClusterAggregate {
+                cluster_id,
+                users: users.round() as usize,
+                video_preference: mean(totals[0]),
+                image_preference: mean(totals[1]),
+                text_preference: mean(totals[2]),
+                diversity_preference: mean(totals[3]),
+            })
+        })
+        .collect();
+    aggregates.sort_by_key(|a| a.cluster_id);
+    aggregates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_laplace_noise_is_centered_with_expected_spread() {
+        let samples: Vec<f64> = (0..20_000).map(|_| laplace_noise(2.0)).collect();
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        let mean_abs = samples.iter().map(|s| s.abs()).sum::<f64>() / samples.len() as f64;
+        assert!(mean.abs() < 0.1, "mean {}", mean);
+        // E|X| equals the scale for a Laplace distribution
+        assert!((mean_abs - 2.0).abs() < 0.15, "mean |x| {}", mean_abs);
+    }
+
+    #[test]
+    fn test_small_clusters_are_suppressed() {
+        // A huge budget makes the noise negligible so the assertions are exact
+        let config = PrivacyConfig {
+            epsilon: 1e9,
+            min_cluster_size: 3,
+        };
+        let stats = ClusterStats {
+            total_users: 12,
+            cluster_sizes: vec![10, 2],
+            num_clusters: 2,
+            inertia: Some(1.0),
+            churn_rate: None,
+        };
+        let private = stats.privatize(&config);
+        assert_eq!(private.cluster_sizes, vec![Some(10), None]);
+        assert_eq!(private.suppressed_clusters, 1);
+        assert_eq!(private.total_users, 12);
+
+        let assignments: HashMap<u64, ClusterProfile> = (0..5)
+            .map(|user_id| {
+                let profile = ClusterProfile {
+                    cluster_id: if user_id < 4 { 1 } else { 2 },
+                    video_preference: 0.25,
+                    ..Default::default()
+                };
+                (user_id, profile)
+            })
+            .collect();
+        let aggregates = private_cluster_aggregates(&assignments, &config);
+        assert_eq!(aggregates.len(), 1);
+        assert_eq!(aggregates[0].cluster_id, 1);
+        assert_eq!(aggregates[0].users, 4);
+        assert!((aggregates[0].video_preference - 0.25).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,182 @@
+//! Per-session record of what has already been served
+//!
+//! A session is a request_id chain: each page is requested with the
+//! `previous_request_id` of the page before it, and every request in the chain
+//! shares one record. A request without one starts a new session; it ends after
+//! `idle_timeout` without requests. Subsequent pages consult it to avoid
+//! repeating authors and topics and to decay near-duplicates of posts the viewer
+//! has already seen.
+
+use crate::candidate_pipeline::candidate::PostCandidate;
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use moka::sync::Cache;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Word n-gram size used for near-duplicate signatures
+const SHINGLE_SIZE: usize = 3;
+
+/// Signatures kept per session for near-duplicate checks
+const MAX_SIGNATURES: usize = 500;
+
+/// What a session has been served so far
+#[derive(Debug, Default)]
+pub struct SessionState {
+    pub served_tweet_ids: HashSet<i64>,
+    pub author_counts: HashMap<u64, usize>,
+    pub topic_counts: HashMap<String, usize>,
+    signatures: VecDeque<HashSet<u64>>,
+}
+
+impl SessionState {
+    pub fn record(&mut self, candidate: &PostCandidate) {
+        if !self.served_tweet_ids.insert(candidate.tweet_id) {
+            return;
+        }
+        *self.author_counts.entry(candidate.author_id).or_default() += 1;
+        for topic in candidate.topics.iter().flatten() {
+            *self.topic_counts.entry(topic.clone()).or_default() += 1;
+        }
+        let signature = text_signature(&candidate.tweet_text);
+        if !signature.is_empty() {
+            if self.signatures.len() == MAX_SIGNATURES {
+                self.signatures.pop_front();
+            }
+            self.signatures.push_back(signature);
+        }
+    }
+
+    /// Highest Jaccard similarity between `text` and anything served this session
+    pub fn max_similarity(&self, text: &str) -> f64 {
+        let signature = text_signature(text);
+        if signature.is_empty() {
+            return 0.0;
+        }
+        self.signatures
+            .iter()
+            .map(|served| jaccard(&signature, served))
+            .fold(0.0, f64::max)
+    }
+}
+
+/// Hashed word shingles of the normalized text
+fn text_signature(text: &str) -> HashSet<u64> {
+    let words: Vec<String> = text
+        .split_whitespace()
+        .map(|w| {
+            w.chars()
+                .filter(|c| c.is_alphanumeric())
+                .collect::<String>()
+                .to_lowercase()
+        })
+        .filter(|w| !w.is_empty())
+        .collect();
+
+    words
+        .windows(SHINGLE_SIZE.min(words.len()).max(1))
+        .map(|shingle| {
+            let mut hasher = DefaultHasher::new();
+            shingle.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect()
+}
+
+fn jaccard(a: &HashSet<u64>, b: &HashSet<u64>) -> f64 {
+    let intersection = a.intersection(b).count();
+    let union = a.len() + b.len() - intersection;
+    if union == 0 {
+        0.0
+    } else {
+        intersection as f64 / union as f64
+    }
+}
+
+pub struct SessionStore {
+    sessions: Cache<String, Arc<Mutex<SessionState>>>,
+}
+
+impl SessionStore {
+    pub fn new(max_sessions: u64, idle_timeout: Duration) -> Self {
+        Self {
+            sessions: Cache::builder()
+                .max_capacity(max_sessions)
+                .time_to_idle(idle_timeout)
+                .build(),
+        }
+    }
+
+    /// Scoped to the viewer, so a request id of someone else's chain finds nothing
+    fn session_key(user_id: i64, request_id: &str) -> String {
+        format!("{}:{}", user_id, request_id)
+    }
+
+    /// The session `query` continues, if it names a request that was served
+    pub fn get(&self, query: &ScoredPostsQuery) -> Option<Arc<Mutex<SessionState>>> {
+        let previous = query.previous_request_id.as_deref()?;
+        self.sessions.get(&Self::session_key(query.user_id, previous))
+    }
+
+    /// Record the candidates served in response to `query` in the session it
+    /// continues, or a new one, which later pages reach by its `request_id`
+    pub fn record_served(&self, query: &ScoredPostsQuery, served: &[PostCandidate]) {
+        let session = self.get(query).unwrap_or_default();
+        self.sessions
+            .insert(Self::session_key(query.user_id, &query.request_id), session.clone());
+        let mut state = session.lock().unwrap();
+        for candidate in served {
+            state.record(candidate);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(tweet_id: i64, author_id: u64, text: &str) -> PostCandidate {
+        PostCandidate {
+            tweet_id,
+            author_id,
+            tweet_text: text.to_string(),
+            topics: Some(vec!["sports".to_string()]),
+            ..Default::default()
+        }
+    }
+
+    fn page(user_id: i64, request_id: &str, previous: Option<&str>) -> ScoredPostsQuery {
+        ScoredPostsQuery {
+            user_id,
+            request_id: request_id.to_string(),
+            previous_request_id: previous.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sessions_follow_the_request_id_chain() {
+        let store = SessionStore::new(100, Duration::from_secs(60));
+        let first = page(1, "r1", None);
+        let second = page(1, "r2", Some("r1"));
+        let third = page(1, "r3", Some("r2"));
+
+        assert!(store.get(&first).is_none());
+        store.record_served(&first, &[candidate(1, 10, "the home team wins the final")]);
+        // Recording the same post twice does not double count
+        store.record_served(&second, &[candidate(1, 10, "the home team wins the final")]);
+
+        let session = store.get(&third).unwrap();
+        let state = session.lock().unwrap();
+        assert_eq!(state.author_counts[&10], 1);
+        assert_eq!(state.topic_counts["sports"], 1);
+        assert!(state.max_similarity("The home team WINS the final!") > 0.99);
+        assert!(state.max_similarity("completely unrelated words here") < 0.01);
+
+        // A fresh request starts over, and other viewers can't join the chain
+        assert!(store.get(&page(1, "r4", None)).is_none());
+        assert!(store.get(&page(2, "r4", Some("r1"))).is_none());
+    }
+}
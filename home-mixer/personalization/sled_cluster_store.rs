@@ -0,0 +1,143 @@
+//! sled-backed cluster store
+//!
+//! Keeps each assignment under its user id in an `assignments` tree and the
+//! centroids under a single key, so every upsert and delete is one keyed write
+//! and there is no log to replay or compact. Writes reach disk within sled's
+//! flush interval; snapshots are flushed before they return.
+
+use crate::personalization::cluster_store::{ClusterStore, StoredClusters};
+use crate::personalization::user_clusters::ClusterProfile;
+use serde::de::DeserializeOwned;
+use std::collections::HashSet;
+use std::path::Path;
+use tonic::async_trait;
+
+const ASSIGNMENTS_TREE: &str = "assignments";
+const CENTROIDS_KEY: &str = "centroids";
+
+pub struct SledClusterStore {
+    db: sled::Db,
+    assignments: sled::Tree,
+}
+
+impl SledClusterStore {
+    /// Opens (creating if needed) the database in `dir`
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, String> {
+        let dir = dir.as_ref();
+        let db = sled::open(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let assignments = db.open_tree(ASSIGNMENTS_TREE).map_err(|e| e.to_string())?;
+        Ok(Self { db, assignments })
+    }
+
+    fn read_state(db: &sled::Db, assignments: &sled::Tree) -> Result<StoredClusters, String> {
+        let mut state = StoredClusters::default();
+        if let Some(bytes) = db.get(CENTROIDS_KEY).map_err(|e| e.to_string())? {
+            state.centroids = decode(&bytes)?;
+        }
+        for entry in assignments.iter() {
+            let (key, value) = entry.map_err(|e| e.to_string())?;
+            let key: [u8; 8] = key
+                .as_ref()
+                .try_into()
+                .map_err(|_| format!("corrupt cluster assignment key {:?}", key))?;
+            state.assignments.insert(u64::from_be_bytes(key), decode(&value)?);
+        }
+        Ok(state)
+    }
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, String> {
+    serde_json::from_slice(bytes).map_err(|e| format!("corrupt cluster store entry: {}", e))
+}
+
+#[async_trait]
+impl ClusterStore for SledClusterStore {
+    async fn load(&self) -> Result<StoredClusters, String> {
+        let (db, assignments) = (self.db.clone(), self.assignments.clone());
+        tokio::task::spawn_blocking(move || Self::read_state(&db, &assignments))
+            .await
+            .map_err(|e| e.to_string())?
+    }
+
+    async fn put_assignment(&self, user_id: u64, profile: &ClusterProfile) -> Result<(), String> {
+        let value = serde_json::to_vec(profile).map_err(|e| e.to_string())?;
+        self.assignments
+            .insert(user_id.to_be_bytes(), value)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    async fn delete_assignment(&self, user_id: u64) -> Result<(), String> {
+        self.assignments
+            .remove(user_id.to_be_bytes())
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    async fn write_snapshot(&self, state: &StoredClusters) -> Result<(), String> {
+        let centroids = serde_json::to_vec(&state.centroids).map_err(|e| e.to_string())?;
+        let mut batch = sled::Batch::default();
+        let mut user_ids = HashSet::with_capacity(state.assignments.len());
+        for (user_id, profile) in &state.assignments {
+            let value = serde_json::to_vec(profile).map_err(|e| e.to_string())?;
+            batch.insert(&user_id.to_be_bytes(), value);
+            user_ids.insert(*user_id);
+        }
+        // Scanning for stale keys and writing the batch block on disk
+        let (db, assignments) = (self.db.clone(), self.assignments.clone());
+        tokio::task::spawn_blocking(move || {
+            for entry in assignments.iter().keys() {
+                let key = entry.map_err(|e| e.to_string())?;
+                let stale = <[u8; 8]>::try_from(key.as_ref())
+                    .map_or(true, |id| !user_ids.contains(&u64::from_be_bytes(id)));
+                if stale {
+                    batch.remove(key);
+                }
+            }
+            assignments.apply_batch(batch).map_err(|e| e.to_string())?;
+            db.insert(CENTROIDS_KEY, centroids).map_err(|e| e.to_string())?;
+            db.flush().map(|_| ()).map_err(|e| e.to_string())
+        })
+        .await
+        .map_err(|e| e.to_string())?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::personalization::user_clusters::PersistedCentroids;
+
+    #[tokio::test]
+    async fn test_upserts_deletes_and_snapshots_survive_reopening() {
+        let dir = std::env::temp_dir().join(format!("sled_cluster_store_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let profile = |cluster_id| ClusterProfile {
+            cluster_id,
+            ..Default::default()
+        };
+        {
+            let store = SledClusterStore::open(&dir).unwrap();
+            let state = StoredClusters {
+                assignments: [(1, profile(0)), (2, profile(0))].into_iter().collect(),
+                centroids: PersistedCentroids {
+                    centroids: vec![vec![0.5; 8]],
+                    ..Default::default()
+                },
+            };
+            store.put_assignment(9, &profile(3)).await.unwrap();
+            // The snapshot replaces every assignment, including 9
+            store.write_snapshot(&state).await.unwrap();
+            store.put_assignment(1, &profile(7)).await.unwrap();
+            store.delete_assignment(2).await.unwrap();
+            store.db.flush_async().await.unwrap();
+        }
+
+        let loaded = SledClusterStore::open(&dir).unwrap().load().await.unwrap();
+        assert_eq!(loaded.assignments.len(), 1);
+        assert_eq!(loaded.assignments[&1].cluster_id, 7);
+        assert_eq!(loaded.centroids.centroids.len(), 1);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
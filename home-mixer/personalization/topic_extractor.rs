@@ -0,0 +1,193 @@
+//! Topic extraction for posts
+//!
+//! Keyword and hashtag matching against a small taxonomy, optionally merged with
+//! the output of an embedding-based `TopicClassifier`. The same extractor is used
+//! for candidates (`TopicHydrator`) and for the viewer's engagement history
+//! (`UserInterestTopicsQueryHydrator`), so both sides share one topic vocabulary.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+use tonic::async_trait;
+
+/// Prefix for hashtags that don't map to a taxonomy topic
+pub const HASHTAG_TOPIC_PREFIX: &str = "hashtag:";
+
+/// Embedding / model-backed topic classification
+#[async_trait]
+pub trait TopicClassifier: Send + Sync {
+    /// Returns the topics for each input text, in order.
+    async fn classify(&self, texts: &[&str]) -> Result<Vec<Vec<String>>, String>;
+}
+
+pub struct TopicExtractor {
+    /// Lowercase keyword (or hashtag without '#') -> topic
+    keywords: HashMap<String, String>,
+    classifier: Option<Arc<dyn TopicClassifier>>,
+}
+
+impl Default for TopicExtractor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TopicExtractor {
+    pub fn new() -> Self {
+        Self {
+            keywords: Self::default_taxonomy(),
+            classifier: None,
+        }
+    }
+
+    pub fn with_classifier(mut self, classifier: Arc<dyn TopicClassifier>) -> Self {
+        self.classifier = Some(classifier);
+        self
+    }
+
+    /// Replace the taxonomy: topic -> keywords
+    pub fn with_taxonomy(mut self, taxonomy: HashMap<String, Vec<String>>) -> Self {
+        self.keywords = taxonomy
+            .into_iter()
+            .flat_map(|(topic, keywords)| {
+                keywords
+                    .into_iter()
+                    .map(move |k| (k.to_lowercase(), topic.clone()))
+            })
+            .collect();
+        self
+    }
+
+    fn default_taxonomy() -> HashMap<String, String> {
+        // In production, load from the topic taxonomy service
+        let taxonomy: &[(&str, &[&str])] = &[
+            ("sports", &["nba", "nfl", "soccer", "football", "basketball", "worldcup"]),
+            ("technology", &["ai", "rust", "programming", "startup", "software", "gpu"]),
+            ("politics", &["election", "senate", "congress", "vote", "policy"]),
+            ("entertainment", &["movie", "music", "netflix", "album", "concert"]),
+            ("gaming", &["gaming", "esports", "playstation", "xbox", "nintendo"]),
+            ("news", &["breaking", "headline", "reporting"]),
+            ("food", &["recipe", "cooking", "restaurant", "foodie"]),
+            ("travel", &["travel", "flight", "vacation", "wanderlust"]),
+            ("fashion", &["fashion", "outfit", "ootd", "style"]),
+            ("education", &["learning", "university", "course", "study"]),
+        ];
+        taxonomy
+            .iter()
+            .flat_map(|(topic, keywords)| {
+                keywords.iter().map(|k| (k.to_string(), topic.to_string()))
+            })
+            .collect()
+    }
+
+    /// Keyword / hashtag topics for one text, sorted and de-duplicated
+    pub fn extract(&self, text: &str) -> Vec<String> {
+        let mut topics = BTreeSet::new();
+        for token in text.split_whitespace() {
+            let is_hashtag = token.starts_with('#');
+            let word: String = token
+                .trim_start_matches('#')
+                .chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_')
+                .collect::<String>()
+                .to_lowercase();
+            if word.is_empty() {
+                continue;
+            }
+            match self.keywords.get(&word) {
+                Some(topic) => {
+                    topics.insert(topic.clone());
+                },
+                None if is_hashtag => {
+                    topics.insert(format!("{}{}", HASHTAG_TOPIC_PREFIX, word));
+                },
+                None => {},
+            }
+        }
+        topics.into_iter().collect()
+    }
+
+    /// Topics for a batch of texts, merging in the classifier's output when configured.
+    /// Classifier failures degrade to keyword-only topics.
+    pub async fn extract_batch(&self, texts: &[&str]) -> Vec<Vec<String>> {
+        let mut topics: Vec<Vec<String>> = texts.iter().map(|t| self.extract(t)).collect();
+
+        let Some(classifier) = &self.classifier else {
+            return topics;
+        };
+        match classifier.classify(texts).await {
+            Ok(model_topics) if model_topics.len() == topics.len() => {
+                for (keyword_topics, extra) in topics.iter_mut().zip(model_topics) {
+                    let merged: BTreeSet<String> =
+                        keyword_topics.drain(..).chain(extra).collect();
+                    keyword_topics.extend(merged);
+                }
+            },
+            Ok(model_topics) => log::warn!(
+                "Topic classifier returned {} results for {} texts, using keywords only",
+                model_topics.len(),
+                texts.len()
+            ),
+            Err(err) => log::warn!("Topic classifier failed, using keywords only: {}", err),
+        }
+        topics
+    }
+
+    /// The `top_k` most frequent topics across a user's engaged posts
+    pub fn aggregate_interests(topic_lists: &[Vec<String>], top_k: usize) -> HashSet<String> {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for topic in topic_lists.iter().flatten() {
+            *counts.entry(topic.as_str()).or_default() += 1;
+        }
+        let mut ranked: Vec<(&str, usize)> = counts.into_iter().collect();
+        // Most frequent first, ties broken by name so results are stable
+        ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        ranked
+            .into_iter()
+            .take(top_k)
+            .map(|(topic, _)| topic.to_string())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedClassifier;
+
+    #[async_trait]
+    impl TopicClassifier for FixedClassifier {
+        async fn classify(&self, texts: &[&str]) -> Result<Vec<Vec<String>>, String> {
+            Ok(vec![vec!["science".to_string()]; texts.len()])
+        }
+    }
+
+    #[test]
+    fn test_keywords_and_hashtags() {
+        let extractor = TopicExtractor::new();
+        assert_eq!(
+            extractor.extract("Watching the NBA finals #basketball #GoWarriors!"),
+            vec!["hashtag:gowarriors".to_string(), "sports".to_string()]
+        );
+        assert!(extractor.extract("nothing to see here").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_classifier_topics_are_merged() {
+        let extractor = TopicExtractor::new().with_classifier(Arc::new(FixedClassifier));
+        let topics = extractor.extract_batch(&["new gpu benchmarks"]).await;
+        assert_eq!(topics, vec![vec!["science".to_string(), "technology".to_string()]]);
+    }
+
+    #[test]
+    fn test_aggregate_interests() {
+        let history = vec![
+            vec!["sports".to_string(), "news".to_string()],
+            vec!["sports".to_string()],
+            vec!["technology".to_string(), "news".to_string()],
+        ];
+        let interests = TopicExtractor::aggregate_interests(&history, 2);
+        assert_eq!(interests.len(), 2);
+        assert!(interests.contains("sports") && interests.contains("news"));
+    }
+}
@@ -0,0 +1,133 @@
+//! Topics muted implicitly through repeated "not interested" feedback
+//!
+//! Each "not interested" on a post adds one unit of rejection weight to every topic
+//! of that post; the weight halves every `half_life`. A topic is muted while its
+//! decayed weight is at or above `threshold`, so a burst of rejections mutes it
+//! quickly and the mute lifts on its own once the user stops pushing back.
+//! Positive engagement with a topic takes weight off again.
+
+use crate::personalization::engagement_events::{EngagementEvent, EngagementEventType};
+use crate::personalization::user_clusters::ClusterProfile;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::time::Duration;
+
+/// Weight below which a rejection entry is dropped from the profile
+const MIN_TRACKED_WEIGHT: f64 = 0.05;
+
+#[derive(Clone, Debug)]
+pub struct TopicMuteConfig {
+    /// Decayed rejection weight at which a topic becomes muted
+    pub threshold: f64,
+    pub half_life: Duration,
+}
+
+impl Default for TopicMuteConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 3.0,
+            half_life: Duration::from_secs(30 * 86_400),
+        }
+    }
+}
+
+/// Accumulated "not interested" weight for one topic
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TopicRejection {
+    pub weight: f64,
+    pub updated_ms: i64,
+}
+
+impl TopicRejection {
+    pub fn decayed(&self, now_ms: i64, half_life: Duration) -> f64 {
+        let elapsed_ms = (now_ms - self.updated_ms).max(0) as f64;
+        let half_life_ms = half_life.as_millis().max(1) as f64;
+        self.weight * 0.5f64.powf(elapsed_ms / half_life_ms)
+    }
+}
+
+impl ClusterProfile {
+    /// Update rejection weights for the topics of `event`. Returns whether anything
+    /// changed.
+    pub fn record_topic_feedback(
+        &mut self,
+        event: &EngagementEvent,
+        config: &TopicMuteConfig,
+    ) -> bool {
+        let delta = match (event.event_type, event.signal()) {
+            (EngagementEventType::NotInterested, _) => 1.0,
+            (_, Some(signal)) if signal > 0.0 => -signal,
+            _ => return false,
+        };
+
+        let mut changed = false;
+        for topic in &event.topics {
+            if delta < 0.0 && !self.topic_rejections.contains_key(topic) {
+                continue;
+            }
+            let rejection = self.topic_rejections.entry(topic.clone()).or_default();
+            let weight = rejection.decayed(event.timestamp_ms, config.half_life) + delta;
+            *rejection = TopicRejection {
+                weight: weight.max(0.0),
+                updated_ms: rejection.updated_ms.max(event.timestamp_ms),
+            };
+            changed = true;
+        }
+
+        let now_ms = event.timestamp_ms;
+        self.topic_rejections
+            .retain(|_, r| r.decayed(now_ms, config.half_life) >= MIN_TRACKED_WEIGHT);
+        changed
+    }
+
+    /// Topics currently muted for this user
+    pub fn muted_topics(&self, now_ms: i64, config: &TopicMuteConfig) -> HashSet<String> {
+        self.topic_rejections
+            .iter()
+            .filter(|(_, r)| r.decayed(now_ms, config.half_life) >= config.threshold)
+            .map(|(topic, _)| topic.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY_MS: i64 = 86_400_000;
+
+    fn event(event_type: EngagementEventType, timestamp_ms: i64) -> EngagementEvent {
+        EngagementEvent {
+            user_id: 1,
+            tweet_id: 2,
+            author_id: None,
+            event_type,
+            media_kind: None,
+            timestamp_ms,
+            dwell_ms: None,
+            topics: vec!["crypto".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_repeated_rejections_mute_until_decayed() {
+        let config = TopicMuteConfig {
+            threshold: 2.0,
+            half_life: Duration::from_millis(DAY_MS as u64),
+        };
+        let mut profile = ClusterProfile::default();
+
+        profile.record_topic_feedback(&event(EngagementEventType::NotInterested, 0), &config);
+        assert!(profile.muted_topics(0, &config).is_empty());
+        profile.record_topic_feedback(&event(EngagementEventType::NotInterested, 0), &config);
+        assert!(profile.muted_topics(0, &config).contains("crypto"));
+
+        // One half-life later the weight has dropped to 1.0
+        assert!(profile.muted_topics(DAY_MS, &config).is_empty());
+
+        // Engaging with the topic again takes weight off
+        profile.record_topic_feedback(&event(EngagementEventType::Reply, 0), &config);
+        assert!(profile.muted_topics(0, &config).is_empty());
+        assert!(!profile.record_topic_feedback(&event(EngagementEventType::Dwell, 0), &config));
+    }
+}
@@ -0,0 +1,153 @@
+//! Sources of `UserFeatures` for the periodic cluster refresh
+
+use crate::personalization::io;
+use crate::personalization::user_clusters::UserFeatures;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::async_trait;
+
+#[async_trait]
+pub trait UserFeaturesProvider: Send + Sync {
+    /// Features for every user that should take part in the next refresh
+    async fn fetch_user_features(&self) -> Result<Vec<UserFeatures>, String>;
+}
+
+/// Reads features from a file: CSV (see `personalization::io`), a JSON array, or one
+/// JSON object per line
+pub struct FileUserFeaturesProvider {
+    path: PathBuf,
+}
+
+impl FileUserFeaturesProvider {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl UserFeaturesProvider for FileUserFeaturesProvider {
+    async fn fetch_user_features(&self) -> Result<Vec<UserFeatures>, String> {
+        if self.path.extension().is_some_and(|e| e == "csv" || e == "parquet") {
+            let path = self.path.clone();
+            let report = tokio::task::spawn_blocking(move || {
+                io::import_user_features(&path, |progress| {
+                    log::debug!("Read {} feature rows", progress.rows)
+                })
+            })
+            .await
+            .map_err(|e| e.to_string())??;
+            if report.rows_rejected > 0 {
+                log::warn!(
+                    "{}: rejected {} of {} rows, e.g. {:?}",
+                    self.path.display(),
+                    report.rows_rejected,
+                    report.rows_read,
+                    report.errors.first()
+                );
+            }
+            return Ok(report.features);
+        }
+
+        let contents = tokio::fs::read_to_string(&self.path)
+            .await
+            .map_err(|e| format!("{}: {}", self.path.display(), e))?;
+
+        if contents.trim_start().starts_with('[') {
+            return serde_json::from_str(&contents)
+                .map_err(|e| format!("{}: {}", self.path.display(), e));
+        }
+        contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                serde_json::from_str(line)
+                    .map_err(|e| format!("{}:{}: {}", self.path.display(), i + 1, e))
+            })
+            .collect()
+    }
+}
+
+/// Fetches features from an analytics HTTP endpoint returning a JSON array
+pub struct ApiUserFeaturesProvider {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl ApiUserFeaturesProvider {
+    pub fn new(url: impl Into<String>, timeout: Duration) -> Result<Self, String> {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            client,
+            url: url.into(),
+        })
+    }
+}
+
+#[async_trait]
+impl UserFeaturesProvider for ApiUserFeaturesProvider {
+    async fn fetch_user_features(&self) -> Result<Vec<UserFeatures>, String> {
+        self.client
+            .get(&self.url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("{}: {}", self.url, e))?
+            .json()
+            .await
+            .map_err(|e| format!("{}: {}", self.url, e))
+    }
+}
+
+/// File path or `http(s)://` URL -> provider
+pub fn provider_from_source(source: &str) -> Result<Arc<dyn UserFeaturesProvider>, String> {
+    if source.starts_with("http://") || source.starts_with("https://") {
+        let provider = ApiUserFeaturesProvider::new(source, Duration::from_secs(300))?;
+        Ok(Arc::new(provider))
+    } else {
+        Ok(Arc::new(FileUserFeaturesProvider::new(source)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_provider_reads_json_lines() {
+        let path = std::env::temp_dir().join(format!("user_features_{}.jsonl", std::process::id()));
+        let line = |user_id: u64| {
+            serde_json::json!({
+                "user_id": user_id,
+                "preferred_content_types": ["Sports"],
+                "video_engagement_rate": 0.5,
+                "image_engagement_rate": 0.1,
+                "text_engagement_rate": 0.2,
+                "avg_post_age_hours": 12.0,
+                "diversity_score": 0.4,
+                "overall_engagement_rate": 0.3,
+                "peak_hours": [20],
+                "avg_session_duration_min": 4.0,
+                "negative_feedback_rate": 0.0,
+            })
+            .to_string()
+        };
+        std::fs::write(&path, format!("{}\n\n{}\n", line(1), line(2))).unwrap();
+
+        let features = FileUserFeaturesProvider::new(&path)
+            .fetch_user_features()
+            .await
+            .unwrap();
+        assert_eq!(features.iter().map(|f| f.user_id).collect::<Vec<_>>(), vec![1, 2]);
+
+        std::fs::write(&path, "[]").unwrap();
+        let empty = FileUserFeaturesProvider::new(&path).fetch_user_features().await.unwrap();
+        assert!(empty.is_empty());
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -1,189 +1,61 @@
-//! Mock proto structures for open-source compatibility
-//! 
-//! This module provides mock implementations of internal X.AI proto types
-//! to allow the project to compile without proprietary dependencies.
-
+//! gRPC types of the home mixer and the recsys model
+//!
+//! Generated from `protos/home_mixer.proto` and `protos/recsys.proto` by the
+//! build script, along with the `ScoredPostsService` server and client.
+//! `FILE_DESCRIPTOR_SET` describes both files for gRPC reflection, so tools
+//! like grpcurl can call the service without a copy of the definitions.
+
+use crate::config;
+use candidate_pipeline::provenance;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-
-// ============================================================================
-// Home Mixer Proto Types
-// ============================================================================
-
-/// File descriptor set for gRPC reflection (empty placeholder)
-pub const FILE_DESCRIPTOR_SET: &[u8] = &[];
-
-/// Served type enum
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
-#[repr(i32)]
-pub enum ServedType {
-    #[default]
-    Unknown = 0,
-    InNetwork = 1,
-    OutOfNetwork = 2,
-    Promoted = 3,
-}
-
-/// Impression bloom filter entry
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
-pub struct ImpressionBloomFilterEntry {
-    pub filter_data: Vec<u8>,
-    pub num_bits: i32,
-    pub num_hashes: i32,
-}
-
-/// Scored post response
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
-pub struct ScoredPostsResponse {
-    pub scored_posts: Vec<ScoredPost>,
-}
-
-/// Individual scored post
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
-pub struct ScoredPost {
-    pub tweet_id: u64,
-    pub author_id: u64,
-    pub retweeted_tweet_id: u64,
-    pub retweeted_user_id: u64,
-    pub in_reply_to_tweet_id: u64,
-    pub score: f32,
-    pub in_network: bool,
-    pub served_type: i32,
-    pub last_scored_timestamp_ms: u64,
-    pub prediction_request_id: u64,
-    pub ancestors: Vec<u64>,
-    pub screen_names: HashMap<u64, String>,
-    pub visibility_reason: Option<VisibilityReason>,
-}
-
-/// Visibility reason
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
-pub struct VisibilityReason {
-    pub filtered_reason: Option<FilteredReason>,
-}
-
-/// Scored posts query
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
-pub struct ScoredPostsQuery {
-    pub viewer_id: u64,
-    pub client_app_id: i64,
-    pub country_code: String,
-    pub language_code: String,
-    pub seen_ids: Vec<i64>,
-    pub served_ids: Vec<i64>,
-    pub in_network_only: bool,
-    pub is_bottom_request: bool,
-    pub bloom_filter_entries: Vec<ImpressionBloomFilterEntry>,
-}
-
-// ============================================================================
-// Visibility Filtering Types
-// ============================================================================
-
-/// Filtered reason enum
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
-pub enum FilteredReason {
-    #[default]
-    None,
-    Blocked,
-    Muted,
-    Nsfw,
-    Spam,
-    LowQuality,
-    Hidden,
-}
-
-/// Visibility action
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
-pub enum Action {
-    #[default]
-    Allow,
-    Drop,
-    Interstitial,
-    LocalizedInterstitial,
-    SoftIntervention,
-}
-
-// ============================================================================
-// RecSys Proto Types
-// ============================================================================
-
-/// Action name enum for predictions
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
-#[repr(usize)]
-pub enum ActionName {
-    ServerTweetFav = 0,
-    ServerTweetReply = 1,
-    ServerTweetRetweet = 2,
-    ClientTweetPhotoExpand = 3,
-    ClientTweetClick = 4,
-    ClientTweetClickProfile = 5,
-    ClientTweetVideoQualityView = 6,
-    ClientTweetShare = 7,
-    ClientTweetClickSendViaDirectMessage = 8,
-    ClientTweetShareViaCopyLink = 9,
-    ClientTweetRecapDwelled = 10,
-    ServerTweetQuote = 11,
-    ClientQuotedTweetClick = 12,
-    ClientTweetFollowAuthor = 13,
-    ClientTweetNotInterestedIn = 14,
-    ClientTweetBlockAuthor = 15,
-    ClientTweetMuteAuthor = 16,
-    ClientTweetReport = 17,
-}
-
-/// Continuous action name enum
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
-#[repr(usize)]
-pub enum ContinuousActionName {
-    DwellTime = 0,
-}
-
-/// User action sequence
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
-pub struct UserActionSequence {
-    pub actions: Vec<UserAction>,
-}
 
-/// Individual user action
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
-pub struct UserAction {
-    pub action_type: i32,
-    pub tweet_id: u64,
-    pub timestamp_ms: u64,
-}
-
-/// Tweet info for predictions
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
-pub struct TweetInfo {
-    pub tweet_id: u64,
-    pub author_id: u64,
-}
-
-/// Prediction response
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
-pub struct PredictNextActionsResponse {
-    pub distribution_sets: Vec<DistributionSet>,
-}
-
-/// Distribution set
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
-pub struct DistributionSet {
-    pub candidate_distributions: Vec<CandidateDistribution>,
+tonic::include_proto!("home_mixer");
+tonic::include_proto!("recsys");
+
+/// Encoded descriptors of the messages and services above
+pub const FILE_DESCRIPTOR_SET: &[u8] =
+    tonic::include_file_descriptor_set!("home_mixer_descriptor");
+
+impl From<provenance::Provenance> for Provenance {
+    fn from(provenance: provenance::Provenance) -> Self {
+        Self {
+            sources: provenance.sources,
+            hydrators: provenance.hydrators,
+            scores: provenance
+                .scores
+                .into_iter()
+                .map(|step| ScoreStep {
+                    scorer: step.scorer,
+                    before: step.before,
+                    after: step.after,
+                })
+                .collect(),
+            filters: provenance
+                .filters
+                .into_iter()
+                .map(|step| FilterStep {
+                    filter: step.filter,
+                    removed: step.removed,
+                })
+                .collect(),
+        }
+    }
 }
 
-/// Candidate distribution
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
-pub struct CandidateDistribution {
-    pub candidate: Option<TweetInfo>,
-    pub top_log_probs: Vec<f32>,
-    pub continuous_actions_values: Vec<f32>,
+impl From<config::LocaleParams> for LocaleParams {
+    fn from(params: config::LocaleParams) -> Self {
+        let overrides = params.overrides;
+        Self {
+            overrides: Some(ParamOverrides {
+                weights: overrides.weights,
+                spam_max_report_rate: overrides.spam_max_report_rate,
+                toxicity_threshold: overrides.toxicity_threshold,
+            }),
+            sources: params.sources,
+        }
+    }
 }
 
-// ============================================================================
-// Twitter Context Types
-// ============================================================================
-
 /// Twitter context viewer
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct TwitterContextViewer {
@@ -198,53 +70,26 @@ pub trait GetTwitterContextViewer {
     fn get_viewer(&self) -> Option<TwitterContextViewer>;
 }
 
-// ============================================================================
-// gRPC Service Definitions (Mock)
-// ============================================================================
-
-pub mod scored_posts_service_server {
+#[cfg(test)]
+mod tests {
     use super::*;
-    use tonic::{Request, Response, Status};
-
-    #[tonic::async_trait]
-    pub trait ScoredPostsService: Send + Sync + 'static {
-        async fn get_scored_posts(
-            &self,
-            request: Request<ScoredPostsQuery>,
-        ) -> Result<Response<ScoredPostsResponse>, Status>;
-    }
-
-    #[derive(Clone)]
-    #[allow(dead_code)]
-    pub struct ScoredPostsServiceServer<T: ScoredPostsService> {
-        inner: std::sync::Arc<T>,
-    }
-
-    impl<T: ScoredPostsService> ScoredPostsServiceServer<T> {
-        pub fn new(inner: T) -> Self {
-            Self {
-                inner: std::sync::Arc::new(inner),
-            }
-        }
-
-        pub fn max_decoding_message_size(self, _size: usize) -> Self {
-            self
-        }
-
-        pub fn max_encoding_message_size(self, _size: usize) -> Self {
-            self
-        }
-
-        pub fn accept_compressed(self, _encoding: tonic::codec::CompressionEncoding) -> Self {
-            self
-        }
-
-        pub fn send_compressed(self, _encoding: tonic::codec::CompressionEncoding) -> Self {
-            self
-        }
-    }
-
-    impl<T: ScoredPostsService> tonic::server::NamedService for ScoredPostsServiceServer<T> {
-        const NAME: &'static str = "home_mixer.ScoredPostsService";
+    use prost::Message;
+
+    #[test]
+    fn test_queries_survive_the_wire() {
+        let query = ScoredPostsQuery {
+            viewer_id: 7,
+            utc_offset_minutes: Some(-300),
+            feature_overrides: [("caching".to_string(), "off".to_string())].into(),
+            ..Default::default()
+        };
+        let decoded = ScoredPostsQuery::decode(query.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(decoded, query);
+        assert_eq!(decoded.previous_request_id, None);
+
+        let reflection = tonic_reflection::server::Builder::configure()
+            .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+            .build();
+        assert!(reflection.is_ok());
     }
 }
@@ -0,0 +1,81 @@
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::config::CohortContext;
+use crate::experiments::ExperimentAssignments;
+use crate::util::config_watcher::ConfigWatcher;
+use crate::util::sticky_buckets::StickyBuckets;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::query_hydrator::QueryHydrator;
+use std::sync::Arc;
+use tonic::async_trait;
+
+/// Assigns the viewer their variant of each experiment in the config in use,
+/// so experiments changed by a reload apply from the next request, then
+/// applies the request's feature overrides
+pub struct ExperimentsQueryHydrator {
+    pub config: Arc<ConfigWatcher>,
+    /// Variants users of sticky experiments keep
+    pub sticky_buckets: Arc<StickyBuckets>,
+}
+
+impl ExperimentsQueryHydrator {
+    pub fn new(config: Arc<ConfigWatcher>) -> Self {
+        Self {
+            config,
+            sticky_buckets: Arc::new(StickyBuckets::new()),
+        }
+    }
+
+    pub fn with_sticky_buckets(mut self, sticky_buckets: Arc<StickyBuckets>) -> Self {
+        self.sticky_buckets = sticky_buckets;
+        self
+    }
+}
+
+#[async_trait]
+impl QueryHydrator<ScoredPostsQuery> for ExperimentsQueryHydrator {
+    async fn hydrate(&self, query: &ScoredPostsQuery) -> Result<ScoredPostsQuery, PipelineError> {
+        let ctx = CohortContext::new(query.user_id as u64, query.country_code.as_str());
+        let experiments = self.config.config().experiments();
+        let assignments =
+            ExperimentAssignments::assign_sticky(&experiments, &ctx, &self.sticky_buckets)
+                .with_overrides(&query.feature_overrides);
+        Ok(ScoredPostsQuery {
+            experiments: Arc::new(assignments),
+            ..Default::default()
+        })
+    }
+
+    fn update(&self, query: &mut ScoredPostsQuery, hydrated: ScoredPostsQuery) {
+        query.experiments = hydrated.experiments;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::experiments::{Experiment, ENABLED};
+
+    #[tokio::test]
+    async fn test_assigns_configured_experiments_and_rollouts() {
+        let mut config = Config::default();
+        config.features.caching_rollout_percent = 100;
+        config.features.experiments.push(Experiment::rollout("bait_filter_v2", 0));
+        let hydrator = ExperimentsQueryHydrator::new(Arc::new(ConfigWatcher::new(config)));
+
+        let mut query = ScoredPostsQuery {
+            user_id: 42,
+            ..Default::default()
+        };
+        let hydrated = hydrator.hydrate(&query).await.unwrap();
+        hydrator.update(&mut query, hydrated);
+
+        assert_eq!(query.experiment("caching"), Some(ENABLED));
+        assert_eq!(query.experiment("bait_filter_v2"), None);
+        assert_eq!(query.experiment("batching"), None);
+        // Clones share exposures
+        let exposures = query.clone().experiments.exposures();
+        assert_eq!(exposures.len(), 1);
+        assert_eq!(exposures[0].experiment, "caching");
+    }
+}
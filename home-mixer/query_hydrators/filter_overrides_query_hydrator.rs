@@ -0,0 +1,106 @@
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::config::SafetyFilterModes;
+use crate::util::config_watcher::ConfigWatcher;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::query_hydrator::QueryHydrator;
+use std::sync::Arc;
+use tonic::async_trait;
+
+/// Resolves the effective safety filter modes for the request by applying the viewer's
+/// `filter_overrides` on top of the `SafetyConfig` policy in use.
+pub struct FilterOverridesQueryHydrator {
+    pub config: Arc<ConfigWatcher>,
+}
+
+impl FilterOverridesQueryHydrator {
+    pub fn new(config: Arc<ConfigWatcher>) -> Self {
+        Self { config }
+    }
+}
+
+#[async_trait]
+impl QueryHydrator<ScoredPostsQuery> for FilterOverridesQueryHydrator {
+    async fn hydrate(&self, query: &ScoredPostsQuery) -> Result<ScoredPostsQuery, PipelineError> {
+        let safety = &self.config.config().safety;
+        let modes = SafetyFilterModes::resolve(safety, query.user_preferences.as_ref());
+        Ok(ScoredPostsQuery {
+            safety_filter_modes: Some(modes),
+            ..Default::default()
+        })
+    }
+
+    fn update(&self, query: &mut ScoredPostsQuery, hydrated: ScoredPostsQuery) {
+        query.safety_filter_modes = hydrated.safety_filter_modes;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::candidate_pipeline::query_features::{
+        FilterOverride, SafetyFilterKind, UserPreferences,
+    };
+    use crate::config::{Config, FilterMode, SafetyConfig};
+
+    fn query_with(overrides: &[(SafetyFilterKind, FilterOverride)]) -> ScoredPostsQuery {
+        ScoredPostsQuery {
+            user_preferences: Some(UserPreferences {
+                filter_overrides: overrides.iter().copied().collect(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    async fn resolve(safety: SafetyConfig, query: ScoredPostsQuery) -> SafetyFilterModes {
+        let config = Config {
+            safety,
+            ..Default::default()
+        };
+        let hydrator = FilterOverridesQueryHydrator::new(Arc::new(ConfigWatcher::new(config)));
+        let mut query = query;
+        let hydrated = hydrator.hydrate(&query).await.unwrap();
+        hydrator.update(&mut query, hydrated);
+        query.safety_filter_modes.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_overrides_respect_policy() {
+        let query = query_with(&[
+            (SafetyFilterKind::EngagementBait, FilterOverride::Disabled),
+            (SafetyFilterKind::Nsfw, FilterOverride::Disabled),
+            (SafetyFilterKind::Spam, FilterOverride::Strict),
+        ]);
+        let safety = SafetyConfig {
+            nsfw_strict_mode: false,
+            ..Default::default()
+        };
+
+        let modes = resolve(safety, query).await;
+
+        assert_eq!(modes.engagement_bait, FilterMode::Off);
+        // NSFW is not user-disableable by default
+        assert_eq!(modes.nsfw, FilterMode::Standard);
+        assert_eq!(modes.spam, FilterMode::Strict);
+        assert_eq!(modes.toxicity, FilterMode::Standard);
+    }
+
+    #[tokio::test]
+    async fn test_globally_disabled_filters_stay_off() {
+        let query = query_with(&[(SafetyFilterKind::Spam, FilterOverride::Strict)]);
+        let safety = SafetyConfig {
+            enable_spam_filter: false,
+            ..Default::default()
+        };
+
+        let modes = resolve(safety, query).await;
+        assert_eq!(modes.spam, FilterMode::Off);
+    }
+
+    #[test]
+    fn test_query_falls_back_to_default_policy() {
+        let query = query_with(&[(SafetyFilterKind::Toxicity, FilterOverride::Disabled)]);
+        assert_eq!(query.filter_mode(SafetyFilterKind::Toxicity), FilterMode::Off);
+        assert_eq!(query.filter_mode(SafetyFilterKind::Nsfw), FilterMode::Strict);
+    }
+}
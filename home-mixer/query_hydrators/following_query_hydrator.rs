@@ -0,0 +1,296 @@
+//! Following-list hydration with a per-user TTL cache
+//!
+//! Every request needs the viewer's following list, which only changes when
+//! they follow or unfollow someone. Lists are cached per user for `ttl`; follow
+//! and unfollow events invalidate the follower's entry right away. An expired
+//! entry is kept for up to `stale_ttl` and served when the upstream fails, so a
+//! Strato outage degrades to slightly stale in-network candidates instead of
+//! none.
+
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::query_hydrator::QueryHydrator;
+use moka::sync::Cache;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tonic::async_trait;
+
+/// Upstream of the viewer's following list (Strato in production)
+#[async_trait]
+pub trait FollowingListProvider: Send + Sync {
+    async fn following_ids(&self, user_id: i64) -> Result<Vec<i64>, String>;
+}
+
+/// Fetches following lists from an HTTP endpoint serving `{url}/{user_id}` as
+/// a JSON array of user ids
+pub struct ApiFollowingListProvider {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl ApiFollowingListProvider {
+    pub fn new(url: impl Into<String>, timeout: Duration) -> Result<Self, String> {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let url: String = url.into();
+        Ok(Self {
+            client,
+            url: url.trim_end_matches('/').to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl FollowingListProvider for ApiFollowingListProvider {
+    async fn following_ids(&self, user_id: i64) -> Result<Vec<i64>, String> {
+        let url = format!("{}/{}", self.url, user_id);
+        self.client
+            .get(&url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("{}: {}", url, e))?
+            .json()
+            .await
+            .map_err(|e| format!("{}: {}", url, e))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FollowAction {
+    Follow,
+    Unfollow,
+}
+
+/// A follow graph change, as posted to `/api/follow_events`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FollowEvent {
+    pub follower_id: i64,
+    pub followee_id: i64,
+    pub action: FollowAction,
+}
+
+#[derive(Clone, Debug)]
+pub struct FollowingCacheConfig {
+    /// How long a fetched list is served without asking the upstream again
+    pub ttl: Duration,
+    /// How long an expired list is kept as a fallback for upstream failures
+    pub stale_ttl: Duration,
+    /// Users cached at once (least recently used evicted)
+    pub max_users: u64,
+}
+
+impl Default for FollowingCacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(300),
+            stale_ttl: Duration::from_secs(24 * 60 * 60),
+            max_users: 1_000_000,
+        }
+    }
+}
+
+/// Lookups since startup, by outcome
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct FollowingCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Misses answered with an expired entry because the upstream failed
+    pub stale_served: u64,
+    /// Misses that failed with nothing cached to fall back on
+    pub errors: u64,
+    pub invalidations: u64,
+    pub cached_users: u64,
+    pub hit_rate: f64,
+}
+
+struct CachedFollowing {
+    ids: Arc<Vec<i64>>,
+    fetched: Instant,
+}
+
+/// Per-user cache in front of a `FollowingListProvider`
+pub struct FollowingListCache {
+    provider: Arc<dyn FollowingListProvider>,
+    ttl: Duration,
+    entries: Cache<i64, Arc<CachedFollowing>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    stale_served: AtomicU64,
+    errors: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+impl FollowingListCache {
+    pub fn new(provider: Arc<dyn FollowingListProvider>, config: FollowingCacheConfig) -> Self {
+        let entries = Cache::builder()
+            .max_capacity(config.max_users)
+            .time_to_live(config.stale_ttl.max(config.ttl))
+            .build();
+        Self {
+            provider,
+            ttl: config.ttl,
+            entries,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            stale_served: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            invalidations: AtomicU64::new(0),
+        }
+    }
+
+    /// `user_id`'s following list, from the cache while it is fresh
+    pub async fn following_ids(&self, user_id: i64) -> Result<Arc<Vec<i64>>, String> {
+        let cached = self.entries.get(&user_id);
+        if let Some(entry) = cached.as_ref().filter(|e| e.fetched.elapsed() < self.ttl) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(entry.ids.clone());
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        match self.provider.following_ids(user_id).await {
+            Ok(ids) => {
+                let ids = Arc::new(ids);
+                let entry = CachedFollowing {
+                    ids: ids.clone(),
+                    fetched: Instant::now(),
+                };
+                self.entries.insert(user_id, Arc::new(entry));
+                Ok(ids)
+            }
+            Err(e) => match cached {
+                Some(stale) => {
+                    log::warn!("Serving a stale following list for {}: {}", user_id, e);
+                    self.stale_served.fetch_add(1, Ordering::Relaxed);
+                    Ok(stale.ids.clone())
+                }
+                None => {
+                    self.errors.fetch_add(1, Ordering::Relaxed);
+                    Err(e)
+                }
+            },
+        }
+    }
+
+    /// Drop `user_id`'s list, so the next request fetches it again
+    pub fn invalidate(&self, user_id: i64) {
+        self.entries.invalidate(&user_id);
+        self.invalidations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Invalidate the list of the user whose follows changed
+    pub fn apply_follow_event(&self, event: &FollowEvent) {
+        self.invalidate(event.follower_id);
+    }
+
+    pub fn stats(&self) -> FollowingCacheStats {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let lookups = hits + misses;
+        FollowingCacheStats {
+            hits,
+            misses,
+            stale_served: self.stale_served.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            invalidations: self.invalidations.load(Ordering::Relaxed),
+            cached_users: self.entries.entry_count(),
+            hit_rate: if lookups == 0 { 0.0 } else { hits as f64 / lookups as f64 },
+        }
+    }
+}
+
+/// Populates `user_features.followed_user_ids` through a `FollowingListCache`
+pub struct FollowingQueryHydrator {
+    pub cache: Arc<FollowingListCache>,
+}
+
+#[async_trait]
+impl QueryHydrator<ScoredPostsQuery> for FollowingQueryHydrator {
+    async fn hydrate(&self, query: &ScoredPostsQuery) -> Result<ScoredPostsQuery, PipelineError> {
+        let following = self
+            .cache
+            .following_ids(query.user_id)
+            .await
+            .map_err(PipelineError::unavailable)?;
+        let mut hydrated = ScoredPostsQuery::default();
+        hydrated.user_features.followed_user_ids = following.as_ref().clone();
+        Ok(hydrated)
+    }
+
+    fn update(&self, query: &mut ScoredPostsQuery, hydrated: ScoredPostsQuery) {
+        query.user_features.followed_user_ids = hydrated.user_features.followed_user_ids;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Serves a fixed list per user, counting fetches, or fails when down
+    #[derive(Default)]
+    struct FakeGraph {
+        following: Mutex<Vec<i64>>,
+        down: Mutex<bool>,
+        fetches: AtomicU64,
+    }
+
+    #[async_trait]
+    impl FollowingListProvider for FakeGraph {
+        async fn following_ids(&self, _user_id: i64) -> Result<Vec<i64>, String> {
+            self.fetches.fetch_add(1, Ordering::Relaxed);
+            if *self.down.lock().unwrap() {
+                return Err("upstream unavailable".to_string());
+            }
+            Ok(self.following.lock().unwrap().clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cache_invalidates_and_falls_back_to_stale_lists() {
+        let graph = Arc::new(FakeGraph::default());
+        *graph.following.lock().unwrap() = vec![10, 20];
+        let config = FollowingCacheConfig {
+            ttl: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let cache = Arc::new(FollowingListCache::new(graph.clone(), config));
+        let hydrator = FollowingQueryHydrator {
+            cache: cache.clone(),
+        };
+        let query = ScoredPostsQuery {
+            user_id: 1,
+            ..Default::default()
+        };
+
+        let hydrated = hydrator.hydrate(&query).await.unwrap();
+        assert_eq!(hydrated.user_features.followed_user_ids, vec![10, 20]);
+        hydrator.hydrate(&query).await.unwrap();
+        assert_eq!(graph.fetches.load(Ordering::Relaxed), 1);
+
+        // Following someone new takes effect on the next request
+        graph.following.lock().unwrap().push(30);
+        cache.apply_follow_event(&FollowEvent {
+            follower_id: 1,
+            followee_id: 30,
+            action: FollowAction::Follow,
+        });
+        assert_eq!(*cache.following_ids(1).await.unwrap(), vec![10, 20, 30]);
+
+        // Once expired, a failing upstream gets the stale list instead
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        *graph.down.lock().unwrap() = true;
+        assert_eq!(*cache.following_ids(1).await.unwrap(), vec![10, 20, 30]);
+        assert!(cache.following_ids(2).await.is_err());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 4));
+        assert_eq!((stats.stale_served, stats.errors, stats.invalidations), (1, 1, 1));
+        assert!((stats.hit_rate - 0.2).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,77 @@
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::util::config_watcher::ConfigWatcher;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::query_hydrator::QueryHydrator;
+use std::sync::Arc;
+use tonic::async_trait;
+
+/// Resolves the weight and threshold overrides for the request's country and
+/// language from the config in use; scorers and filters fall back to the
+/// global values for everything not overridden
+pub struct LocaleParamsQueryHydrator {
+    pub config: Arc<ConfigWatcher>,
+}
+
+impl LocaleParamsQueryHydrator {
+    pub fn new(config: Arc<ConfigWatcher>) -> Self {
+        Self { config }
+    }
+}
+
+#[async_trait]
+impl QueryHydrator<ScoredPostsQuery> for LocaleParamsQueryHydrator {
+    async fn hydrate(&self, query: &ScoredPostsQuery) -> Result<ScoredPostsQuery, PipelineError> {
+        let overrides = &self.config.config().locale_overrides;
+        Ok(ScoredPostsQuery {
+            locale_params: Arc::new(overrides.resolve(&query.country_code, &query.language_code)),
+            ..Default::default()
+        })
+    }
+
+    fn update(&self, query: &mut ScoredPostsQuery, hydrated: ScoredPostsQuery) {
+        query.locale_params = hydrated.locale_params;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[tokio::test]
+    async fn test_country_overrides_win_over_language_overrides() {
+        let config = Config {
+            locale_overrides: serde_json::from_str(
+                r#"{"countries": {"BR": {"spam_max_report_rate": 0.01}},
+                    "languages": {"pt": {"spam_max_report_rate": 0.02, "toxicity_threshold": 0.5,
+                                         "weights": {"reply": 20.0}}}}"#,
+            )
+            .unwrap(),
+            ..Default::default()
+        };
+        let hydrator = LocaleParamsQueryHydrator::new(Arc::new(ConfigWatcher::new(config)));
+        let resolve = |country: &str, language: &str| {
+            let mut query = ScoredPostsQuery {
+                country_code: country.to_string(),
+                language_code: language.to_string(),
+                ..Default::default()
+            };
+            let hydrator = &hydrator;
+            async move {
+                let hydrated = hydrator.hydrate(&query).await.unwrap();
+                hydrator.update(&mut query, hydrated);
+                query.locale_params
+            }
+        };
+
+        let params = resolve("br", "PT").await;
+        assert_eq!(params.overrides.spam_max_report_rate, Some(0.01));
+        assert_eq!(params.overrides.toxicity_threshold, Some(0.5));
+        assert_eq!(params.sources["spam_max_report_rate"], "country BR");
+        assert_eq!(params.sources["weights.reply"], "language pt");
+
+        let params = resolve("US", "en").await;
+        assert_eq!(params.overrides, Default::default());
+        assert!(params.sources.is_empty());
+    }
+}
@@ -1,2 +1,14 @@
-pub mod user_action_seq_query_hydrator;
-pub mod user_features_query_hydrator;
+//! Query hydrators
+//!
+//! Note: Some query hydrators require internal clients and are disabled for open-source compatibility.
+
+pub mod experiments_query_hydrator;
+pub mod filter_overrides_query_hydrator;
+pub mod following_query_hydrator;
+pub mod locale_params_query_hydrator;
+pub mod user_interest_topics_query_hydrator;
+pub mod weight_preset_query_hydrator;
+
+// The following modules require internal clients and are commented out for open-source builds:
+// pub mod user_action_seq_query_hydrator;
+// pub mod user_features_query_hydrator;
@@ -4,6 +4,7 @@ use crate::params as p;
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tonic::async_trait;
+use xai_candidate_pipeline::error::PipelineError;
 use xai_candidate_pipeline::query_hydrator::QueryHydrator;
 use xai_recsys_aggregation::aggregation::{DefaultAggregator, UserActionAggregator};
 use xai_recsys_aggregation::filters::{
@@ -43,12 +44,14 @@ impl UserActionSeqQueryHydrator {
 #[async_trait]
 impl QueryHydrator<ScoredPostsQuery> for UserActionSeqQueryHydrator {
     #[xai_stats_macro::receive_stats]
-    async fn hydrate(&self, query: &ScoredPostsQuery) -> Result<ScoredPostsQuery, String> {
+    async fn hydrate(&self, query: &ScoredPostsQuery) -> Result<ScoredPostsQuery, PipelineError> {
         let uas_thrift = self
             .uas_fetcher
             .get_by_user_id(query.user_id)
             .await
-            .map_err(|e| format!("Failed to fetch user action sequence: {}", e))?;
+            .map_err(|e| {
+                PipelineError::unavailable(format!("Failed to fetch user action sequence: {}", e))
+            })?;
 
         let aggregated_uas_proto =
             self.aggregate_user_action_sequence(query.user_id, uas_thrift)?;
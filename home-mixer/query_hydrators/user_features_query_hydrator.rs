@@ -3,6 +3,7 @@ use crate::candidate_pipeline::query_features::UserFeatures;
 use crate::clients::strato_client::StratoClient;
 use std::sync::Arc;
 use tonic::async_trait;
+use xai_candidate_pipeline::error::PipelineError;
 use xai_candidate_pipeline::query_hydrator::QueryHydrator;
 use xai_strato::{StratoResult, StratoValue, decode};
 
@@ -13,11 +14,13 @@ pub struct UserFeaturesQueryHydrator {
 #[async_trait]
 impl QueryHydrator<ScoredPostsQuery> for UserFeaturesQueryHydrator {
     #[xai_stats_macro::receive_stats]
-    async fn hydrate(&self, query: &ScoredPostsQuery) -> Result<ScoredPostsQuery, String> {
+    async fn hydrate(&self, query: &ScoredPostsQuery) -> Result<ScoredPostsQuery, PipelineError> {
         let user_id = query.user_id;
         let client = &self.strato_client;
         let result = client.get_user_features(user_id);
-        let result = result.await.map_err(|e| e.to_string())?;
+        let result = result
+            .await
+            .map_err(|e| PipelineError::unavailable(e.to_string()))?;
         let decoded: StratoResult<StratoValue<UserFeatures>> = decode(&result);
         match decoded {
             StratoResult::Ok(v) => {
@@ -27,7 +30,7 @@ impl QueryHydrator<ScoredPostsQuery> for UserFeaturesQueryHydrator {
                     ..Default::default()
                 })
             }
-            StratoResult::Err(_) => Err("Error received from strato".to_string()),
+            StratoResult::Err(_) => Err(PipelineError::unavailable("Error received from strato")),
         }
     }
 
@@ -0,0 +1,145 @@
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::personalization::topic_extractor::TopicExtractor;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::query_hydrator::QueryHydrator;
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::async_trait;
+
+/// Source of the viewer's recent engagements (likes, replies, reposts)
+#[async_trait]
+pub trait EngagementHistoryProvider: Send + Sync {
+    /// Texts of the posts the user most recently engaged with, newest first
+    async fn recent_engaged_texts(
+        &self,
+        user_id: i64,
+        limit: usize,
+    ) -> Result<Vec<String>, String>;
+}
+
+/// Fetches engagement histories from an HTTP endpoint serving
+/// `{url}/{user_id}?limit={limit}` as a JSON array of post texts, newest first
+pub struct ApiEngagementHistoryProvider {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl ApiEngagementHistoryProvider {
+    pub fn new(url: impl Into<String>, timeout: Duration) -> Result<Self, String> {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let url: String = url.into();
+        Ok(Self {
+            client,
+            url: url.trim_end_matches('/').to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl EngagementHistoryProvider for ApiEngagementHistoryProvider {
+    async fn recent_engaged_texts(
+        &self,
+        user_id: i64,
+        limit: usize,
+    ) -> Result<Vec<String>, String> {
+        let url = format!("{}/{}?limit={}", self.url, user_id, limit);
+        self.client
+            .get(&url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("{}: {}", url, e))?
+            .json()
+            .await
+            .map_err(|e| format!("{}: {}", url, e))
+    }
+}
+
+/// Derives `user_interest_topics` from the topics of the viewer's engagement history
+pub struct UserInterestTopicsQueryHydrator {
+    pub extractor: Arc<TopicExtractor>,
+    pub history: Arc<dyn EngagementHistoryProvider>,
+    /// How many recent engagements to look at
+    pub history_size: usize,
+    /// How many topics to keep as the user's interests
+    pub top_k: usize,
+}
+
+impl UserInterestTopicsQueryHydrator {
+    pub fn new(
+        extractor: Arc<TopicExtractor>,
+        history: Arc<dyn EngagementHistoryProvider>,
+    ) -> Self {
+        Self {
+            extractor,
+            history,
+            history_size: 200,
+            top_k: 10,
+        }
+    }
+}
+
+#[async_trait]
+impl QueryHydrator<ScoredPostsQuery> for UserInterestTopicsQueryHydrator {
+    async fn hydrate(&self, query: &ScoredPostsQuery) -> Result<ScoredPostsQuery, PipelineError> {
+        let texts = self
+            .history
+            .recent_engaged_texts(query.user_id, self.history_size)
+            .await
+            .map_err(PipelineError::unavailable)?;
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+        let topics = self.extractor.extract_batch(&texts).await;
+
+        Ok(ScoredPostsQuery {
+            user_interest_topics: Some(TopicExtractor::aggregate_interests(&topics, self.top_k)),
+            ..Default::default()
+        })
+    }
+
+    fn update(&self, query: &mut ScoredPostsQuery, hydrated: ScoredPostsQuery) {
+        query.user_interest_topics = hydrated.user_interest_topics;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedHistory(Vec<&'static str>);
+
+    #[async_trait]
+    impl EngagementHistoryProvider for FixedHistory {
+        async fn recent_engaged_texts(
+            &self,
+            _user_id: i64,
+            limit: usize,
+        ) -> Result<Vec<String>, String> {
+            Ok(self.0.iter().take(limit).map(|t| t.to_string()).collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_interests_from_history() {
+        let history = FixedHistory(vec![
+            "Great NBA game tonight",
+            "Soccer highlights",
+            "New album drop",
+        ]);
+        let extractor = Arc::new(TopicExtractor::new());
+        let hydrator = UserInterestTopicsQueryHydrator {
+            top_k: 1,
+            ..UserInterestTopicsQueryHydrator::new(extractor, Arc::new(history))
+        };
+
+        let mut query = ScoredPostsQuery::default();
+        let hydrated = hydrator.hydrate(&query).await.unwrap();
+        hydrator.update(&mut query, hydrated);
+
+        let interests = query.user_interest_topics.unwrap();
+        assert_eq!(interests.len(), 1);
+        assert!(interests.contains("sports"));
+    }
+}
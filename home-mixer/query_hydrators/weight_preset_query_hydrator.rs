@@ -0,0 +1,65 @@
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::personalization::user_clusters::UserClusteringService;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::query_hydrator::QueryHydrator;
+use std::sync::Arc;
+use tonic::async_trait;
+
+/// Looks up the weight preset of the viewer's cluster, which `WeightedScorer`
+/// scores with in place of the default weights
+pub struct WeightPresetQueryHydrator {
+    pub clustering: Arc<UserClusteringService>,
+}
+
+impl WeightPresetQueryHydrator {
+    pub fn new(clustering: Arc<UserClusteringService>) -> Self {
+        Self { clustering }
+    }
+}
+
+#[async_trait]
+impl QueryHydrator<ScoredPostsQuery> for WeightPresetQueryHydrator {
+    async fn hydrate(&self, query: &ScoredPostsQuery) -> Result<ScoredPostsQuery, PipelineError> {
+        let profile = self.clustering.get_user_cluster(query.user_id as u64).await;
+        Ok(ScoredPostsQuery {
+            weight_preset: profile.weight_preset,
+            ..Default::default()
+        })
+    }
+
+    fn update(&self, query: &mut ScoredPostsQuery, hydrated: ScoredPostsQuery) {
+        query.weight_preset = hydrated.weight_preset;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::personalization::user_clusters::ClusterProfile;
+
+    #[tokio::test]
+    async fn test_preset_of_the_viewers_cluster_is_hydrated() {
+        let clustering = Arc::new(UserClusteringService::new(2));
+        let profile = ClusterProfile {
+            cluster_id: 1,
+            weight_preset: Some("video_heavy".to_string()),
+            ..Default::default()
+        };
+        clustering.assign_user_cluster(7, profile).await;
+        let hydrator = WeightPresetQueryHydrator::new(clustering);
+
+        let mut query = ScoredPostsQuery {
+            user_id: 7,
+            ..Default::default()
+        };
+        let hydrated = hydrator.hydrate(&query).await.unwrap();
+        hydrator.update(&mut query, hydrated);
+        assert_eq!(query.weight_preset.as_deref(), Some("video_heavy"));
+
+        let stranger = ScoredPostsQuery {
+            user_id: 8,
+            ..Default::default()
+        };
+        assert_eq!(hydrator.hydrate(&stranger).await.unwrap().weight_preset, None);
+    }
+}
@@ -0,0 +1,139 @@
+//! Follow-graph affinity scoring
+//!
+//! Reweights in-network candidates by how much the viewer actually interacts with
+//! each followed author (see `AuthorAffinityStore`), so accounts the viewer engages
+//! with outrank accounts they follow but ignore. Out-of-network candidates are left
+//! alone; discovery has its own scorers.
+
+use crate::candidate_pipeline::candidate::PostCandidate;
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::personalization::author_affinity::AuthorAffinityStore;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::scorer::Scorer;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tonic::async_trait;
+
+/// Multiplier at full positive affinity is `1 + MAX_AFFINITY_BOOST`
+const MAX_AFFINITY_BOOST: f64 = 0.5;
+/// Multiplier at full negative affinity is `1 - MAX_AFFINITY_PENALTY`
+const MAX_AFFINITY_PENALTY: f64 = 0.3;
+/// Followed authors the viewer hasn't interacted with recently
+const NO_INTERACTION_MULTIPLIER: f64 = 0.9;
+
+pub struct AuthorAffinityScorer {
+    affinity_store: Arc<AuthorAffinityStore>,
+}
+
+impl AuthorAffinityScorer {
+    pub fn new(affinity_store: Arc<AuthorAffinityStore>) -> Self {
+        Self { affinity_store }
+    }
+
+    /// Score multiplier for a followed author given the viewer's affinity in [-1, 1]
+    pub fn multiplier(affinity: Option<f64>) -> f64 {
+        match affinity {
+            Some(a) if a >= 0.0 => 1.0 + MAX_AFFINITY_BOOST * a.min(1.0),
+            Some(a) => 1.0 + MAX_AFFINITY_PENALTY * a.max(-1.0),
+            None => NO_INTERACTION_MULTIPLIER,
+        }
+    }
+}
+
+#[async_trait]
+impl Scorer<ScoredPostsQuery, PostCandidate> for AuthorAffinityScorer {
+    fn enable(&self, query: &ScoredPostsQuery) -> bool {
+        !query.user_features.followed_user_ids.is_empty()
+    }
+
+    async fn score(
+        &self,
+        query: &ScoredPostsQuery,
+        candidates: &[PostCandidate],
+    ) -> Result<Vec<PostCandidate>, PipelineError> {
+        let followed: HashSet<u64> = query
+            .user_features
+            .followed_user_ids
+            .iter()
+            .map(|id| *id as u64)
+            .collect();
+        let author_ids: Vec<u64> = candidates
+            .iter()
+            .map(|c| c.author_id)
+            .filter(|id| followed.contains(id))
+            .collect();
+        let affinities = self.affinity_store.affinities(
+            query.user_id as u64,
+            &author_ids,
+            chrono::Utc::now().timestamp_millis(),
+        );
+
+        let scored = candidates
+            .iter()
+            .map(|c| {
+                let multiplier = if followed.contains(&c.author_id) {
+                    Self::multiplier(affinities.get(&c.author_id).copied())
+                } else {
+                    1.0
+                };
+                PostCandidate {
+                    weighted_score: c.weighted_score.map(|s| s * multiplier),
+                    ..Default::default()
+                }
+            })
+            .collect();
+
+        Ok(scored)
+    }
+
+    fn update(&self, candidate: &mut PostCandidate, scored: PostCandidate) {
+        candidate.weighted_score = scored.weighted_score;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::candidate_pipeline::query_features::UserFeatures;
+    use crate::personalization::engagement_events::{EngagementEvent, EngagementEventType};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_engaged_followed_authors_outrank_ignored_ones() {
+        let store = Arc::new(AuthorAffinityStore::new(10, 10, Duration::from_secs(86_400)));
+        store.record(&EngagementEvent {
+            user_id: 1,
+            tweet_id: 5,
+            author_id: Some(10),
+            event_type: EngagementEventType::Reply,
+            media_kind: None,
+            timestamp_ms: chrono::Utc::now().timestamp_millis(),
+            dwell_ms: None,
+            topics: Vec::new(),
+        });
+        let scorer = AuthorAffinityScorer::new(store);
+
+        let query = ScoredPostsQuery {
+            user_id: 1,
+            user_features: UserFeatures {
+                followed_user_ids: vec![10, 20],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let candidate = |author_id| PostCandidate {
+            author_id,
+            weighted_score: Some(1.0),
+            ..Default::default()
+        };
+        let scored = scorer
+            .score(&query, &[candidate(10), candidate(20), candidate(30)])
+            .await
+            .unwrap();
+
+        let scores: Vec<f64> = scored.iter().map(|c| c.weighted_score.unwrap()).collect();
+        assert!(scores[0] > 1.0);
+        assert_eq!(scores[1], NO_INTERACTION_MULTIPLIER);
+        assert_eq!(scores[2], 1.0);
+    }
+}
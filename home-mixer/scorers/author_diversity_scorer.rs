@@ -4,6 +4,7 @@ use crate::params as p;
 use std::cmp::Ordering;
 use std::collections::HashMap;
 use tonic::async_trait;
+use xai_candidate_pipeline::error::PipelineError;
 use xai_candidate_pipeline::scorer::Scorer;
 
 /// Diversify authors served within a single feed response
@@ -38,7 +39,7 @@ impl Scorer<ScoredPostsQuery, PostCandidate> for AuthorDiversityScorer {
         &self,
         _query: &ScoredPostsQuery,
         candidates: &[PostCandidate],
-    ) -> Result<Vec<PostCandidate>, String> {
+    ) -> Result<Vec<PostCandidate>, PipelineError> {
         let mut author_counts: HashMap<u64, usize> = HashMap::new();
         let mut scored = vec![PostCandidate::default(); candidates.len()];
 
@@ -4,6 +4,27 @@
 //! data layouts for maximum throughput on large candidate sets.
 
 use crate::params;
+use crate::scorers::scoring_matrix::{Column, ScoringMatrix};
+
+/// `ScoringMatrix` column of each weight; Phoenix has no bookmark score
+const WEIGHT_COLUMNS: [Option<Column>; 16] = [
+    Some(Column::Favorite),
+    Some(Column::Reply),
+    Some(Column::Retweet),
+    Some(Column::PhotoExpand),
+    Some(Column::Click),
+    Some(Column::ProfileClick),
+    Some(Column::Vqv),
+    Some(Column::Share),
+    Some(Column::ShareViaDm),
+    Some(Column::ShareViaCopyLink),
+    Some(Column::Dwell),
+    Some(Column::Quote),
+    Some(Column::QuotedClick),
+    Some(Column::DwellTime),
+    Some(Column::FollowAuthor),
+    None,
+];
 
 /// Batch score result
 #[derive(Debug, Clone)]
@@ -85,6 +106,26 @@ impl BatchScorer {
         }
     }
 
+    /// Score a batch of candidates laid out column by column
+    ///
+    /// Each weight is applied over a whole column at once, so no per-candidate
+    /// probability array is built.
+    pub fn score_matrix(&self, matrix: &ScoringMatrix) -> BatchScoreResult {
+        let start = std::time::Instant::now();
+
+        let weights: Vec<(Column, f64)> = WEIGHT_COLUMNS
+            .iter()
+            .zip(self.weights)
+            .filter_map(|(column, weight)| column.map(|column| (column, weight)))
+            .collect();
+        let scores = matrix.weighted_sum(&weights);
+
+        BatchScoreResult {
+            scores,
+            processing_time_us: start.elapsed().as_micros() as u64,
+        }
+    }
+
     /// Score a single candidate from its probability array
     #[inline(always)]
     fn score_single_candidate(&self, probs: &[f64]) -> f64 {
@@ -169,6 +210,26 @@ mod tests {
         assert!(result.scores.iter().all(|&s| s >= 0.0));
     }
 
+    #[test]
+    fn test_matrix_scoring_matches_flat_scoring() {
+        use crate::candidate_pipeline::candidate::PostCandidate;
+
+        let scorer = BatchScorer::new();
+        let mut candidate = PostCandidate::default();
+        candidate.phoenix_scores.favorite_score = Some(0.5);
+        candidate.phoenix_scores.dwell_time = Some(0.25);
+        candidate.phoenix_scores.follow_author_score = Some(0.1);
+        let mut probabilities = [0.0; 16];
+        probabilities[0] = 0.5;
+        probabilities[13] = 0.25;
+        probabilities[14] = 0.1;
+
+        let matrix = ScoringMatrix::from_candidates(&[candidate]);
+        let flat = scorer.score_batch(&probabilities, 1).scores[0];
+        let columns = scorer.score_matrix(&matrix).scores[0];
+        assert!((flat - columns).abs() < 1e-12);
+    }
+
     #[test]
     fn test_freshness_decay() {
         let scorer = BatchScorer::new();
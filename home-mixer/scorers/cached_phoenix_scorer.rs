@@ -5,6 +5,7 @@
 
 use crate::candidate_pipeline::candidate::{PhoenixScores, PostCandidate};
 use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::candidate_pipeline::shared_candidates::ScoreOverlay;
 use crate::scorers::phoenix_scorer::PhoenixScorer;
 use lru::LruCache;
 use std::num::NonZeroUsize;
@@ -12,6 +13,7 @@ use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::sync::RwLock;
 use tonic::async_trait;
+use xai_candidate_pipeline::error::PipelineError;
 use xai_candidate_pipeline::scorer::Scorer;
 
 /// Configuration for the caching layer
@@ -159,11 +161,12 @@ impl Scorer<ScoredPostsQuery, PostCandidate> for CachedPhoenixScorer {
         &self,
         query: &ScoredPostsQuery,
         candidates: &[PostCandidate],
-    ) -> Result<Vec<PostCandidate>, String> {
+    ) -> Result<Vec<PostCandidate>, PipelineError> {
         let user_id = query.user_id as u64;
         
-        // Step 1: Check cache for each candidate
-        let mut cached_results = Vec::with_capacity(candidates.len());
+        // Step 1: Check cache for each candidate, writing hits straight into
+        // the overlay and borrowing misses for the inner scorer
+        let mut overlay = ScoreOverlay::with_len(candidates.len());
         let mut uncached_candidates = Vec::new();
         let mut uncached_indices = Vec::new();
         
@@ -180,9 +183,9 @@ impl Scorer<ScoredPostsQuery, PostCandidate> for CachedPhoenixScorer {
                         // Cache hit!
                         self.cache_hits.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
                         
-                        let mut cached_candidate = candidate.clone();
-                        cached_candidate.phoenix_scores = entry.value.clone();
-                        cached_results.push((idx, cached_candidate));
+                        overlay.phoenix_scores[idx] = entry.value.clone();
+                        overlay.prediction_request_ids[idx] = candidate.prediction_request_id;
+                        overlay.last_scored_at_ms[idx] = candidate.last_scored_at_ms;
                         continue;
                     } else {
                         // Expired, remove it
@@ -192,44 +195,28 @@ impl Scorer<ScoredPostsQuery, PostCandidate> for CachedPhoenixScorer {
                 
                 // Cache miss
                 self.cache_misses.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                uncached_candidates.push(candidate.clone());
+                uncached_candidates.push(candidate);
                 uncached_indices.push(idx);
             }
         }
         
         // Step 2: Score uncached candidates using inner scorer
-        let newly_scored = if !uncached_candidates.is_empty() {
-            self.inner.score(query, &uncached_candidates).await?
-        } else {
-            Vec::new()
-        };
-        
-        // Step 3: Update cache with new scores
-        if !newly_scored.is_empty() {
-            let mut user_cache = self.user_cache.write().await;
-            
-            for candidate in &newly_scored {
-                let tweet_id = candidate.tweet_id as u64;
-                let key = (user_id, tweet_id);
-                user_cache.put(key, CacheEntry::new(candidate.phoenix_scores.clone()));
-            }
-        }
-        
-        // Step 4: Merge cached and newly scored results in original order
-        let mut all_results = vec![None; candidates.len()];
-        
-        // Insert cached results
-        for (idx, candidate) in cached_results {
-            all_results[idx] = Some(candidate);
+        if uncached_candidates.is_empty() {
+            return Ok(overlay.into_scored());
         }
+        let newly_scored = self.inner.predict_scores(query, &uncached_candidates).await?;
         
-        // Insert newly scored results
+        // Step 3: Update cache with new scores and merge them into the overlay
+        // in original order
+        let mut user_cache = self.user_cache.write().await;
         for (result_idx, original_idx) in uncached_indices.iter().enumerate() {
-            all_results[*original_idx] = Some(newly_scored[result_idx].clone());
+            let tweet_id = candidates[*original_idx].tweet_id as u64;
+            let scores = newly_scored.phoenix_scores[result_idx].clone();
+            user_cache.put((user_id, tweet_id), CacheEntry::new(scores));
+            overlay.copy_from(*original_idx, &newly_scored, result_idx);
         }
         
-        // Unwrap all (safe because we filled all indices)
-        Ok(all_results.into_iter().map(|c| c.unwrap()).collect())
+        Ok(overlay.into_scored())
     }
     
     fn update(&self, candidate: &mut PostCandidate, scored: PostCandidate) {
@@ -0,0 +1,110 @@
+//! Diversity boost scorer
+//!
+//! Boosts candidates from outside the viewer's usual interests to counter echo
+//! chambers. Runs behind `diversity_boost_gate`, which checks
+//! `enable_diversity_boost` and the rollout cohort per request. Scales the final
+//! score too when one is already set, like `SessionDiversityScorer`.
+
+use crate::candidate_pipeline::candidate::PostCandidate;
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::personalization::exploration::is_exploratory;
+use crate::personalization::user_clusters::ClusterProfile;
+use crate::util::config_watcher::ConfigWatcher;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::scorer::Scorer;
+use std::sync::Arc;
+use tonic::async_trait;
+
+pub struct DiversityBoostScorer {
+    /// Source of `diversity_boost_multiplier`, read per request
+    config: Arc<ConfigWatcher>,
+}
+
+impl DiversityBoostScorer {
+    pub fn new(config: Arc<ConfigWatcher>) -> Self {
+        Self { config }
+    }
+
+    /// Prefers the `exploratory` flag from `ExplorationHydrator`: the same posts fill
+    /// the exploration quota, and engagement on them feeds back into interest learning.
+    /// Without it, posts mostly off the viewer's interest topics count.
+    pub fn is_outside_bubble(candidate: &PostCandidate, query: &ScoredPostsQuery) -> bool {
+        if let Some(exploratory) = candidate.exploratory {
+            return exploratory;
+        }
+        let (Some(interests), Some(topics)) = (&query.user_interest_topics, &candidate.topics)
+        else {
+            return false;
+        };
+        is_exploratory(topics, &ClusterProfile::default(), Some(interests))
+    }
+}
+
+#[async_trait]
+impl Scorer<ScoredPostsQuery, PostCandidate> for DiversityBoostScorer {
+    async fn score(
+        &self,
+        query: &ScoredPostsQuery,
+        candidates: &[PostCandidate],
+    ) -> Result<Vec<PostCandidate>, PipelineError> {
+        let boost = self.config.config().safety.diversity_boost_multiplier;
+
+        let scored = candidates
+            .iter()
+            .map(|c| {
+                let multiplier = if Self::is_outside_bubble(c, query) {
+                    boost
+                } else {
+                    1.0
+                };
+                PostCandidate {
+                    weighted_score: c.weighted_score.map(|s| s * multiplier),
+                    score: c.score.map(|s| s * multiplier),
+                    ..Default::default()
+                }
+            })
+            .collect();
+
+        Ok(scored)
+    }
+
+    fn update(&self, candidate: &mut PostCandidate, scored: PostCandidate) {
+        candidate.weighted_score = scored.weighted_score;
+        candidate.score = scored.score;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[tokio::test]
+    async fn test_posts_outside_the_bubble_are_boosted() {
+        let mut config = Config::default();
+        config.safety.diversity_boost_multiplier = 1.5;
+        let scorer = DiversityBoostScorer::new(Arc::new(ConfigWatcher::new(config)));
+        let query = ScoredPostsQuery {
+            user_interest_topics: Some(["sports".to_string()].into_iter().collect()),
+            ..Default::default()
+        };
+        let candidate = |exploratory, topic: &str| PostCandidate {
+            weighted_score: Some(1.0),
+            score: Some(1.0),
+            exploratory,
+            topics: Some(vec![topic.to_string()]),
+            ..Default::default()
+        };
+        let candidates = vec![
+            candidate(Some(true), "sports"),
+            candidate(Some(false), "science"),
+            candidate(None, "science"),
+            candidate(None, "sports"),
+        ];
+
+        let scored = scorer.score(&query, &candidates).await.unwrap();
+        let scores: Vec<Option<f64>> = scored.iter().map(|c| c.weighted_score).collect();
+        assert_eq!(scores, vec![Some(1.5), Some(1.0), Some(1.5), Some(1.0)]);
+        assert!(scored.iter().all(|c| c.score == c.weighted_score));
+    }
+}
@@ -0,0 +1,36 @@
+//! Final score scorer
+//!
+//! The weighted scorer and the scorers adjusting its output work on
+//! `weighted_score`, while selectors, the response and the side effects use
+//! `score`. This copies one into the other, so it must be the last scorer.
+
+use crate::candidate_pipeline::candidate::PostCandidate;
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::scorer::Scorer;
+use tonic::async_trait;
+
+pub struct FinalScoreScorer;
+
+#[async_trait]
+impl Scorer<ScoredPostsQuery, PostCandidate> for FinalScoreScorer {
+    async fn score(
+        &self,
+        _query: &ScoredPostsQuery,
+        candidates: &[PostCandidate],
+    ) -> Result<Vec<PostCandidate>, PipelineError> {
+        // Candidates the weighted scorer skipped keep the score they came with
+        let scored = candidates
+            .iter()
+            .map(|c| PostCandidate {
+                score: c.weighted_score.or(c.score),
+                ..Default::default()
+            })
+            .collect();
+        Ok(scored)
+    }
+
+    fn update(&self, candidate: &mut PostCandidate, scored: PostCandidate) {
+        candidate.score = scored.score;
+    }
+}
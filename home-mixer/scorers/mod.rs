@@ -3,7 +3,14 @@
 //! Note: Some scorers require internal clients and are disabled for open-source compatibility.
 
 pub mod weighted_scorer;
+pub mod author_affinity_scorer;
 pub mod batch_scorer;
+pub mod diversity_boost_scorer;
+pub mod final_score_scorer;
+pub mod scoring_matrix;
+pub mod session_diversity_scorer;
+pub mod time_of_day_scorer;
+pub mod weight_sets;
 
 // The following modules require internal clients and are commented out for open-source builds:
 // pub mod author_diversity_scorer;
@@ -2,6 +2,7 @@ use crate::candidate_pipeline::candidate::PostCandidate;
 use crate::candidate_pipeline::query::ScoredPostsQuery;
 use crate::params as p;
 use tonic::async_trait;
+use xai_candidate_pipeline::error::PipelineError;
 use xai_candidate_pipeline::scorer::Scorer;
 
 // Prioritize in-network candidates over out-of-network candidates
@@ -13,7 +14,7 @@ impl Scorer<ScoredPostsQuery, PostCandidate> for OONScorer {
         &self,
         _query: &ScoredPostsQuery,
         candidates: &[PostCandidate],
-    ) -> Result<Vec<PostCandidate>, String> {
+    ) -> Result<Vec<PostCandidate>, PipelineError> {
         let scored = candidates
             .iter()
             .map(|c| {
@@ -7,19 +7,31 @@ use crate::candidate_pipeline::candidate::{PhoenixScores, PostCandidate};
 use crate::candidate_pipeline::query::ScoredPostsQuery;
 use crate::params as p;
 use crate::personalization::user_clusters::{ClusterProfile, UserClusteringService};
+use crate::scorers::weight_sets::{WeightSet, WeightSetRegistry};
 use crate::util::score_normalizer::normalize_score;
 use std::sync::Arc;
 use tonic::async_trait;
+use xai_candidate_pipeline::error::PipelineError;
 use xai_candidate_pipeline::scorer::Scorer;
 
-/// Personalized weighted scorer that adjusts weights based on user cluster
+/// Personalized weighted scorer that adjusts weights based on user cluster.
+/// Starts from the cluster's weight preset, then applies per-user multipliers.
 pub struct PersonalizedWeightedScorer {
     clustering_service: Arc<UserClusteringService>,
+    weight_sets: Arc<WeightSetRegistry>,
 }
 
 impl PersonalizedWeightedScorer {
     pub fn new(clustering_service: Arc<UserClusteringService>) -> Self {
-        Self { clustering_service }
+        Self {
+            clustering_service,
+            weight_sets: Arc::new(WeightSetRegistry::default()),
+        }
+    }
+
+    pub fn with_weight_sets(mut self, weight_sets: Arc<WeightSetRegistry>) -> Self {
+        self.weight_sets = weight_sets;
+        self
     }
 }
 
@@ -30,14 +42,16 @@ impl Scorer<ScoredPostsQuery, PostCandidate> for PersonalizedWeightedScorer {
         &self,
         query: &ScoredPostsQuery,
         candidates: &[PostCandidate],
-    ) -> Result<Vec<PostCandidate>, String> {
+    ) -> Result<Vec<PostCandidate>, PipelineError> {
         // Get user's cluster profile
         let cluster = self.clustering_service.get_user_cluster(query.user_id as u64).await;
+        let preset = self.weight_sets.resolve(cluster.weight_preset.as_deref());
+        let weights = &query.locale_params.overrides.weight_set(preset);
         
         let scored = candidates
             .iter()
             .map(|c| {
-                let weighted_score = Self::compute_personalized_score(c, &cluster);
+                let weighted_score = Self::compute_personalized_score(c, &cluster, weights);
                 let normalized_score = normalize_score(c, weighted_score);
 
                 PostCandidate {
@@ -57,22 +71,26 @@ impl Scorer<ScoredPostsQuery, PostCandidate> for PersonalizedWeightedScorer {
 
 impl PersonalizedWeightedScorer {
     /// Compute personalized weighted score based on user cluster
-    fn compute_personalized_score(candidate: &PostCandidate, cluster: &ClusterProfile) -> f64 {
+    fn compute_personalized_score(
+        candidate: &PostCandidate,
+        cluster: &ClusterProfile,
+        w: &WeightSet,
+    ) -> f64 {
         let s: &PhoenixScores = &candidate.phoenix_scores;
 
-        // Base weights adjusted by cluster preferences
-        let favorite_weight = p::FAVORITE_WEIGHT * cluster.engagement_multiplier;
-        let reply_weight = p::REPLY_WEIGHT * cluster.engagement_multiplier;
-        let retweet_weight = p::RETWEET_WEIGHT * cluster.engagement_multiplier;
+        // Preset weights adjusted by cluster preferences
+        let favorite_weight = w.favorite * cluster.engagement_multiplier;
+        let reply_weight = w.reply * cluster.engagement_multiplier;
+        let retweet_weight = w.retweet * cluster.engagement_multiplier;
         
         // Video weight adjusted by user's video preference
-        let vqv_weight = Self::personalized_vqv_weight(candidate, cluster);
+        let vqv_weight = Self::personalized_vqv_weight(candidate, cluster, w);
         
         // Share weights adjusted for highly engaged users
         let share_multiplier = if cluster.engagement_multiplier > 1.2 { 1.5 } else { 1.0 };
         
         // Negative feedback weights adjusted by user's sensitivity
-        let negative_multiplier = 1.0 + cluster.negative_feedback_rate * 10.0;
+        let negative_multiplier = cluster.negative_weight_multiplier();
         
         // Pre-extract scores for vectorization
         let scores = [
@@ -101,22 +119,22 @@ impl PersonalizedWeightedScorer {
             favorite_weight,
             reply_weight,
             retweet_weight,
-            p::PHOTO_EXPAND_WEIGHT * cluster.image_preference,
-            p::CLICK_WEIGHT,
-            p::PROFILE_CLICK_WEIGHT,
+            w.photo_expand * cluster.image_preference,
+            w.click,
+            w.profile_click,
             vqv_weight,
-            p::SHARE_WEIGHT * share_multiplier,
-            p::SHARE_VIA_DM_WEIGHT * share_multiplier,
-            p::SHARE_VIA_COPY_LINK_WEIGHT * share_multiplier,
-            p::DWELL_WEIGHT,
-            p::QUOTE_WEIGHT,
-            p::QUOTED_CLICK_WEIGHT,
-            p::CONT_DWELL_TIME_WEIGHT,
-            p::FOLLOW_AUTHOR_WEIGHT,
-            p::NOT_INTERESTED_WEIGHT * negative_multiplier,
-            p::BLOCK_AUTHOR_WEIGHT * negative_multiplier,
-            p::MUTE_AUTHOR_WEIGHT * negative_multiplier,
-            p::REPORT_WEIGHT * negative_multiplier,
+            w.share * share_multiplier,
+            w.share_via_dm * share_multiplier,
+            w.share_via_copy_link * share_multiplier,
+            w.dwell,
+            w.quote,
+            w.quoted_click,
+            w.cont_dwell_time,
+            w.follow_author,
+            w.not_interested * negative_multiplier,
+            w.block_author * negative_multiplier,
+            w.mute_author * negative_multiplier,
+            w.report * negative_multiplier,
         ];
 
         // Compute weighted sum
@@ -129,12 +147,16 @@ impl PersonalizedWeightedScorer {
     }
 
     /// Personalized VQV weight based on user's video preference
-    fn personalized_vqv_weight(candidate: &PostCandidate, cluster: &ClusterProfile) -> f64 {
+    fn personalized_vqv_weight(
+        candidate: &PostCandidate,
+        cluster: &ClusterProfile,
+        w: &WeightSet,
+    ) -> f64 {
         if candidate
             .video_duration_ms
             .is_some_and(|ms| ms > p::MIN_VIDEO_DURATION_MS)
         {
-            p::VQV_WEIGHT * cluster.video_preference * 2.0 // Boost for video lovers
+            w.vqv * cluster.video_preference * 2.0 // Boost for video lovers
         } else {
             0.0
         }
@@ -171,18 +193,45 @@ mod tests {
         let mut candidate = PostCandidate::default();
         candidate.video_duration_ms = Some(p::MIN_VIDEO_DURATION_MS + 1000);
         
+        let weights = WeightSet::default();
         let weight_lover = PersonalizedWeightedScorer::personalized_vqv_weight(
             &candidate,
-            &video_lover_cluster
+            &video_lover_cluster,
+            &weights
         );
         let weight_hater = PersonalizedWeightedScorer::personalized_vqv_weight(
             &candidate,
-            &video_hater_cluster
+            &video_hater_cluster,
+            &weights
         );
         
         assert!(weight_lover > weight_hater * 5.0);
     }
     
+    #[test]
+    fn test_cluster_preset_replaces_weight_vector() {
+        let cluster = ClusterProfile {
+            weight_preset: Some("video_heavy".to_string()),
+            ..Default::default()
+        };
+        let registry = WeightSetRegistry::default();
+        
+        let mut candidate = PostCandidate::default();
+        candidate.video_duration_ms = Some(p::MIN_VIDEO_DURATION_MS + 1000);
+        candidate.phoenix_scores.vqv_score = Some(0.5);
+        
+        let preset = registry.resolve(cluster.weight_preset.as_deref());
+        let with_preset =
+            PersonalizedWeightedScorer::compute_personalized_score(&candidate, &cluster, preset);
+        let without_preset = PersonalizedWeightedScorer::compute_personalized_score(
+            &candidate,
+            &cluster,
+            &WeightSet::default(),
+        );
+        
+        assert!(with_preset > without_preset * 3.0);
+    }
+    
     #[tokio::test]
     async fn test_personalized_scoring() {
         let service = Arc::new(UserClusteringService::new(10));
@@ -1,11 +1,13 @@
 use crate::candidate_pipeline::candidate::{PhoenixScores, PostCandidate};
 use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::candidate_pipeline::shared_candidates::ScoreOverlay;
 use crate::clients::phoenix_prediction_client::PhoenixPredictionClient;
 use crate::util::request_util;
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tonic::async_trait;
+use xai_candidate_pipeline::error::PipelineError;
 use xai_candidate_pipeline::scorer::Scorer;
 use xai_recsys_proto::{ActionName, ContinuousActionName};
 
@@ -20,7 +22,24 @@ impl Scorer<ScoredPostsQuery, PostCandidate> for PhoenixScorer {
         &self,
         query: &ScoredPostsQuery,
         candidates: &[PostCandidate],
-    ) -> Result<Vec<PostCandidate>, String> {
+    ) -> Result<Vec<PostCandidate>, PipelineError> {
+        Ok(self.predict_scores(query, candidates).await?.into_scored())
+    }
+
+    fn update(&self, candidate: &mut PostCandidate, scored: PostCandidate) {
+        candidate.phoenix_scores = scored.phoenix_scores;
+        candidate.prediction_request_id = scored.prediction_request_id;
+        candidate.last_scored_at_ms = scored.last_scored_at_ms;
+    }
+}
+
+impl PhoenixScorer {
+    /// Score `candidates`, owned or shared, into an overlay parallel to them
+    pub async fn predict_scores<P: AsRef<PostCandidate> + Sync>(
+        &self,
+        query: &ScoredPostsQuery,
+        candidates: &[P],
+    ) -> Result<ScoreOverlay, PipelineError> {
         let user_id = query.user_id as u64;
         let prediction_request_id = request_util::generate_request_id();
         let last_scored_at_ms = Self::current_timestamp_millis();
@@ -29,6 +48,7 @@ impl Scorer<ScoredPostsQuery, PostCandidate> for PhoenixScorer {
             let tweet_infos: Vec<xai_recsys_proto::TweetInfo> = candidates
                 .iter()
                 .map(|c| {
+                    let c = c.as_ref();
                     let tweet_id = c.retweeted_tweet_id.unwrap_or(c.tweet_id as u64);
                     let author_id = c.retweeted_user_id.unwrap_or(c.author_id);
                     xai_recsys_proto::TweetInfo {
@@ -47,42 +67,28 @@ impl Scorer<ScoredPostsQuery, PostCandidate> for PhoenixScorer {
             if let Ok(response) = result {
                 let predictions_map = self.build_predictions_map(&response);
 
-                let scored_candidates = candidates
-                    .iter()
-                    .map(|c| {
-                        // For retweets, look up predictions using the original tweet id
-                        let lookup_tweet_id = c.retweeted_tweet_id.unwrap_or(c.tweet_id as u64);
-
-                        let phoenix_scores = predictions_map
-                            .get(&lookup_tweet_id)
-                            .map(|preds| self.extract_phoenix_scores(preds))
-                            .unwrap_or_default();
-
-                        PostCandidate {
-                            phoenix_scores,
-                            prediction_request_id: Some(prediction_request_id),
-                            last_scored_at_ms,
-                            ..Default::default()
-                        }
-                    })
-                    .collect();
-
-                return Ok(scored_candidates);
+                let mut overlay = ScoreOverlay::with_len(candidates.len());
+                for (index, c) in candidates.iter().enumerate() {
+                    let c = c.as_ref();
+                    // For retweets, look up predictions using the original tweet id
+                    let lookup_tweet_id = c.retweeted_tweet_id.unwrap_or(c.tweet_id as u64);
+
+                    overlay.phoenix_scores[index] = predictions_map
+                        .get(&lookup_tweet_id)
+                        .map(|preds| self.extract_phoenix_scores(preds))
+                        .unwrap_or_default();
+                    overlay.prediction_request_ids[index] = Some(prediction_request_id);
+                    overlay.last_scored_at_ms[index] = last_scored_at_ms;
+                }
+
+                return Ok(overlay);
             }
         }
 
-        // Return candidates unchanged if no scoring could be done
-        Ok(candidates.to_vec())
-    }
-
-    fn update(&self, candidate: &mut PostCandidate, scored: PostCandidate) {
-        candidate.phoenix_scores = scored.phoenix_scores;
-        candidate.prediction_request_id = scored.prediction_request_id;
-        candidate.last_scored_at_ms = scored.last_scored_at_ms;
+        // Keep the candidates' scores if no scoring could be done
+        Ok(ScoreOverlay::of(candidates.iter()))
     }
-}
 
-impl PhoenixScorer {
     /// Builds Map[tweet_id -> ActionPredictions]
     fn build_predictions_map(
         &self,
@@ -0,0 +1,172 @@
+//! Struct-of-Arrays Scoring Buffer
+//!
+//! Lays out the Phoenix scores of a request's candidates as one contiguous
+//! column per action, converted once from the candidates. Weighted sums then
+//! run over flat slices the compiler can vectorize, instead of gathering each
+//! candidate's scores into an array of its own, and results are scattered back
+//! onto the candidates at the end.
+
+use crate::candidate_pipeline::candidate::{PhoenixScores, PostCandidate};
+
+/// A Phoenix score, one column of a `ScoringMatrix`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Column {
+    Favorite,
+    Reply,
+    Retweet,
+    PhotoExpand,
+    Click,
+    ProfileClick,
+    Vqv,
+    Share,
+    ShareViaDm,
+    ShareViaCopyLink,
+    Dwell,
+    Quote,
+    QuotedClick,
+    DwellTime,
+    FollowAuthor,
+    NotInterested,
+    BlockAuthor,
+    MuteAuthor,
+    Report,
+}
+
+impl Column {
+    /// Every column, in layout order
+    pub const ALL: [Column; 19] = [
+        Column::Favorite,
+        Column::Reply,
+        Column::Retweet,
+        Column::PhotoExpand,
+        Column::Click,
+        Column::ProfileClick,
+        Column::Vqv,
+        Column::Share,
+        Column::ShareViaDm,
+        Column::ShareViaCopyLink,
+        Column::Dwell,
+        Column::Quote,
+        Column::QuotedClick,
+        Column::DwellTime,
+        Column::FollowAuthor,
+        Column::NotInterested,
+        Column::BlockAuthor,
+        Column::MuteAuthor,
+        Column::Report,
+    ];
+
+    fn get(self, s: &PhoenixScores) -> Option<f64> {
+        match self {
+            Column::Favorite => s.favorite_score,
+            Column::Reply => s.reply_score,
+            Column::Retweet => s.retweet_score,
+            Column::PhotoExpand => s.photo_expand_score,
+            Column::Click => s.click_score,
+            Column::ProfileClick => s.profile_click_score,
+            Column::Vqv => s.vqv_score,
+            Column::Share => s.share_score,
+            Column::ShareViaDm => s.share_via_dm_score,
+            Column::ShareViaCopyLink => s.share_via_copy_link_score,
+            Column::Dwell => s.dwell_score,
+            Column::Quote => s.quote_score,
+            Column::QuotedClick => s.quoted_click_score,
+            Column::DwellTime => s.dwell_time,
+            Column::FollowAuthor => s.follow_author_score,
+            Column::NotInterested => s.not_interested_score,
+            Column::BlockAuthor => s.block_author_score,
+            Column::MuteAuthor => s.mute_author_score,
+            Column::Report => s.report_score,
+        }
+    }
+}
+
+/// Phoenix scores of a batch of candidates, column by column; missing scores
+/// are 0.0
+#[derive(Clone, Debug, Default)]
+pub struct ScoringMatrix {
+    len: usize,
+    /// Column-major: column `c` is `data[c * len..(c + 1) * len]`
+    data: Vec<f64>,
+}
+
+impl ScoringMatrix {
+    pub fn from_candidates(candidates: &[PostCandidate]) -> Self {
+        let len = candidates.len();
+        let mut data = Vec::with_capacity(len * Column::ALL.len());
+        for column in Column::ALL {
+            data.extend(
+                candidates
+                    .iter()
+                    .map(|c| column.get(&c.phoenix_scores).unwrap_or(0.0)),
+            );
+        }
+        Self { len, data }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn column(&self, column: Column) -> &[f64] {
+        let start = column as usize * self.len;
+        &self.data[start..start + self.len]
+    }
+
+    pub fn column_mut(&mut self, column: Column) -> &mut [f64] {
+        let start = column as usize * self.len;
+        &mut self.data[start..start + self.len]
+    }
+
+    /// Each candidate's sum of its scores times their column's weight
+    pub fn weighted_sum(&self, weights: &[(Column, f64)]) -> Vec<f64> {
+        let mut sums = vec![0.0; self.len];
+        for &(column, weight) in weights {
+            for (sum, score) in sums.iter_mut().zip(self.column(column)) {
+                *sum += score * weight;
+            }
+        }
+        sums
+    }
+
+    /// Write one value per candidate into the field `field` selects
+    pub fn scatter(
+        values: &[f64],
+        candidates: &mut [PostCandidate],
+        field: fn(&mut PostCandidate) -> &mut Option<f64>,
+    ) {
+        debug_assert_eq!(values.len(), candidates.len());
+        for (candidate, value) in candidates.iter_mut().zip(values) {
+            *field(candidate) = Some(*value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_columns_are_contiguous_and_weighted_per_candidate() {
+        let mut candidates = vec![PostCandidate::default(); 3];
+        candidates[0].phoenix_scores.favorite_score = Some(0.5);
+        candidates[1].phoenix_scores.favorite_score = Some(0.25);
+        candidates[1].phoenix_scores.reply_score = Some(1.0);
+        candidates[2].phoenix_scores.report_score = Some(1.0);
+
+        let matrix = ScoringMatrix::from_candidates(&candidates);
+        assert_eq!(matrix.column(Column::Favorite), &[0.5, 0.25, 0.0]);
+        assert_eq!(matrix.column(Column::Report), &[0.0, 0.0, 1.0]);
+
+        let weights = [(Column::Favorite, 2.0), (Column::Reply, 3.0), (Column::Report, -4.0)];
+        let sums = matrix.weighted_sum(&weights);
+        assert_eq!(sums, vec![1.0, 3.5, -4.0]);
+
+        ScoringMatrix::scatter(&sums, &mut candidates, |c| &mut c.weighted_score);
+        assert_eq!(candidates[1].weighted_score, Some(3.5));
+    }
+}
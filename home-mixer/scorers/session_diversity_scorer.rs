@@ -0,0 +1,137 @@
+//! Session diversity scorer
+//!
+//! Demotes candidates that repeat authors or topics already served earlier in the
+//! viewer's session, and near-duplicates of posts served earlier. The session is
+//! the request_id chain the query continues, see `SessionStore`. Scales the final
+//! score too when one is already set, so the demotion holds whichever of the two
+//! later stages rank by.
+
+use crate::candidate_pipeline::candidate::PostCandidate;
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::personalization::session_store::{SessionState, SessionStore};
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::scorer::Scorer;
+use std::sync::Arc;
+use tonic::async_trait;
+
+/// Applied once per earlier post by the same author
+const AUTHOR_REPEAT_DECAY: f64 = 0.7;
+/// Applied once per topic already served at least this many times
+const TOPIC_SATURATION_COUNT: usize = 3;
+const TOPIC_REPEAT_DECAY: f64 = 0.85;
+/// Similarity above which a candidate counts as a near-duplicate
+const NEAR_DUPLICATE_SIMILARITY: f64 = 0.6;
+const NEAR_DUPLICATE_DECAY: f64 = 0.1;
+
+pub struct SessionDiversityScorer {
+    store: Arc<SessionStore>,
+}
+
+impl SessionDiversityScorer {
+    pub fn new(store: Arc<SessionStore>) -> Self {
+        Self { store }
+    }
+
+    pub fn multiplier(candidate: &PostCandidate, session: &SessionState) -> f64 {
+        if session.served_tweet_ids.contains(&candidate.tweet_id) {
+            return NEAR_DUPLICATE_DECAY;
+        }
+
+        let author_repeats = session
+            .author_counts
+            .get(&candidate.author_id)
+            .copied()
+            .unwrap_or(0);
+        let saturated_topics = candidate
+            .topics
+            .iter()
+            .flatten()
+            .filter(|t| {
+                session.topic_counts.get(*t).copied().unwrap_or(0) >= TOPIC_SATURATION_COUNT
+            })
+            .count();
+
+        let mut multiplier = AUTHOR_REPEAT_DECAY.powi(author_repeats as i32)
+            * TOPIC_REPEAT_DECAY.powi(saturated_topics as i32);
+        if session.max_similarity(&candidate.tweet_text) >= NEAR_DUPLICATE_SIMILARITY {
+            multiplier *= NEAR_DUPLICATE_DECAY;
+        }
+        multiplier
+    }
+}
+
+#[async_trait]
+impl Scorer<ScoredPostsQuery, PostCandidate> for SessionDiversityScorer {
+    async fn score(
+        &self,
+        query: &ScoredPostsQuery,
+        candidates: &[PostCandidate],
+    ) -> Result<Vec<PostCandidate>, PipelineError> {
+        let session = self.store.get(query);
+        let session = session.as_ref().map(|s| s.lock().unwrap());
+
+        let scored = candidates
+            .iter()
+            .map(|c| {
+                let multiplier = session.as_ref().map_or(1.0, |s| Self::multiplier(c, s));
+                PostCandidate {
+                    weighted_score: c.weighted_score.map(|s| s * multiplier),
+                    score: c.score.map(|s| s * multiplier),
+                    ..Default::default()
+                }
+            })
+            .collect();
+
+        Ok(scored)
+    }
+
+    fn update(&self, candidate: &mut PostCandidate, scored: PostCandidate) {
+        candidate.weighted_score = scored.weighted_score;
+        candidate.score = scored.score;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn candidate(tweet_id: i64, author_id: u64, text: &str) -> PostCandidate {
+        PostCandidate {
+            tweet_id,
+            author_id,
+            tweet_text: text.to_string(),
+            weighted_score: Some(1.0),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_second_page_is_diversified() {
+        let store = Arc::new(SessionStore::new(100, Duration::from_secs(60)));
+        let first = ScoredPostsQuery {
+            user_id: 7,
+            request_id: "r1".to_string(),
+            ..Default::default()
+        };
+        let served = candidate(1, 100, "big trade rumours ahead of the deadline");
+        store.record_served(&first, &[served]);
+        let query = ScoredPostsQuery {
+            request_id: "r2".to_string(),
+            previous_request_id: Some("r1".to_string()),
+            ..first
+        };
+
+        let scorer = SessionDiversityScorer::new(store);
+        let page = vec![
+            candidate(2, 100, "something else entirely"),
+            candidate(3, 200, "Big trade rumours ahead of the deadline!!"),
+            candidate(4, 300, "fresh voice, fresh take"),
+        ];
+        let scored = scorer.score(&query, &page).await.unwrap();
+
+        assert_eq!(scored[0].weighted_score, Some(AUTHOR_REPEAT_DECAY));
+        assert_eq!(scored[1].weighted_score, Some(NEAR_DUPLICATE_DECAY));
+        assert_eq!(scored[2].weighted_score, Some(1.0));
+    }
+}
@@ -0,0 +1,229 @@
+//! Time-of-day and day-of-week aware scoring
+//!
+//! Uses the viewer's local time (from `utc_offset_minutes`) together with their
+//! cluster's `peak_activity_hours` to tune freshness tolerance and content mix:
+//! fresh and breaking posts are favoured during peak hours, long-form content in
+//! the evening and at weekends. Peak hours are learned in UTC, so they are
+//! matched against the UTC hour; evenings and weekends are the viewer's own.
+
+use crate::candidate_pipeline::candidate::PostCandidate;
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::personalization::user_clusters::{ClusterProfile, UserClusteringService};
+use crate::util::snowflake;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::scorer::Scorer;
+use chrono::{DateTime, Datelike, FixedOffset, Timelike, Utc, Weekday};
+use std::sync::Arc;
+use tonic::async_trait;
+
+/// Freshness tolerance is scaled by this during the viewer's peak hours
+const PEAK_FRESHNESS_FACTOR: f64 = 0.5;
+/// ... and by this outside of them
+const OFF_PEAK_FRESHNESS_FACTOR: f64 = 1.5;
+/// Extra tolerance at weekends
+const WEEKEND_FRESHNESS_FACTOR: f64 = 1.25;
+/// Floor for the staleness penalty
+const MIN_FRESHNESS_MULTIPLIER: f64 = 0.5;
+
+/// Boost for fresh news posts during peak hours
+const BREAKING_NEWS_BOOST: f64 = 1.2;
+const BREAKING_NEWS_MAX_AGE_HOURS: f64 = 1.0;
+
+/// Boost for long-form content in the evening / at weekends
+const LONG_FORM_BOOST: f64 = 1.15;
+const LONG_FORM_VIDEO_MS: i32 = 120_000;
+const LONG_FORM_TEXT_CHARS: usize = 500;
+const EVENING_HOURS: std::ops::RangeInclusive<u32> = 18..=23;
+
+pub struct TimeOfDayScorer {
+    clustering_service: Arc<UserClusteringService>,
+}
+
+impl TimeOfDayScorer {
+    pub fn new(clustering_service: Arc<UserClusteringService>) -> Self {
+        Self { clustering_service }
+    }
+
+    /// Viewer's local time, or `None` when the client sent no timezone
+    pub fn local_time(
+        query: &ScoredPostsQuery,
+        now: DateTime<Utc>,
+    ) -> Option<DateTime<FixedOffset>> {
+        let offset = FixedOffset::east_opt(query.utc_offset_minutes? * 60)?;
+        Some(now.with_timezone(&offset))
+    }
+
+    fn is_long_form(candidate: &PostCandidate) -> bool {
+        candidate
+            .video_duration_ms
+            .is_some_and(|ms| ms >= LONG_FORM_VIDEO_MS)
+            || candidate.tweet_text.chars().count() >= LONG_FORM_TEXT_CHARS
+    }
+
+    fn is_news(candidate: &PostCandidate) -> bool {
+        candidate
+            .topics
+            .as_ref()
+            .is_some_and(|topics| topics.iter().any(|t| t == "news"))
+    }
+
+    /// Score multiplier for `candidate` shown at `local` time to a viewer in `cluster`
+    pub fn multiplier(
+        candidate: &PostCandidate,
+        cluster: &ClusterProfile,
+        local: DateTime<FixedOffset>,
+    ) -> f64 {
+        let hour = local.hour();
+        let utc_hour = local.with_timezone(&Utc).hour();
+        let in_peak = cluster.peak_activity_hours.contains(&(utc_hour as u8));
+        let weekend = matches!(local.weekday(), Weekday::Sat | Weekday::Sun);
+
+        let age_ms = local.timestamp_millis() - snowflake::timestamp_millis(candidate.tweet_id);
+        let age_hours = age_ms.max(0) as f64 / 3_600_000.0;
+
+        let mut tolerance = cluster.optimal_post_age_hours.max(1.0);
+        tolerance *= if in_peak {
+            PEAK_FRESHNESS_FACTOR
+        } else {
+            OFF_PEAK_FRESHNESS_FACTOR
+        };
+        if weekend {
+            tolerance *= WEEKEND_FRESHNESS_FACTOR;
+        }
+
+        let mut multiplier = if age_hours <= tolerance {
+            1.0
+        } else {
+            (tolerance / age_hours).max(MIN_FRESHNESS_MULTIPLIER)
+        };
+
+        if in_peak && Self::is_news(candidate) && age_hours <= BREAKING_NEWS_MAX_AGE_HOURS {
+            multiplier *= BREAKING_NEWS_BOOST;
+        }
+
+        if (EVENING_HOURS.contains(&hour) || weekend) && Self::is_long_form(candidate) {
+            multiplier *= LONG_FORM_BOOST;
+        }
+
+        multiplier
+    }
+}
+
+#[async_trait]
+impl Scorer<ScoredPostsQuery, PostCandidate> for TimeOfDayScorer {
+    fn enable(&self, query: &ScoredPostsQuery) -> bool {
+        query.utc_offset_minutes.is_some()
+    }
+
+    async fn score(
+        &self,
+        query: &ScoredPostsQuery,
+        candidates: &[PostCandidate],
+    ) -> Result<Vec<PostCandidate>, PipelineError> {
+        let Some(local) = Self::local_time(query, Utc::now()) else {
+            return Ok(candidates
+                .iter()
+                .map(|c| PostCandidate {
+                    weighted_score: c.weighted_score,
+                    ..Default::default()
+                })
+                .collect());
+        };
+        let cluster = self.clustering_service.resolve_profile(query).await;
+
+        let scored = candidates
+            .iter()
+            .map(|c| PostCandidate {
+                weighted_score: c
+                    .weighted_score
+                    .map(|s| s * Self::multiplier(c, &cluster, local)),
+                ..Default::default()
+            })
+            .collect();
+
+        Ok(scored)
+    }
+
+    fn update(&self, candidate: &mut PostCandidate, scored: PostCandidate) {
+        candidate.weighted_score = scored.weighted_score;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(day: u32, hour: u32) -> DateTime<FixedOffset> {
+        // October 2026: the 14th is a Wednesday, the 17th a Saturday
+        FixedOffset::east_opt(0)
+            .unwrap()
+            .with_ymd_and_hms(2026, 10, day, hour, 0, 0)
+            .unwrap()
+    }
+
+    fn candidate(created: DateTime<FixedOffset>, text: &str, topics: &[&str]) -> PostCandidate {
+        PostCandidate {
+            tweet_id: snowflake::from_timestamp(created.timestamp_millis()),
+            tweet_text: text.to_string(),
+            topics: Some(topics.iter().map(|t| t.to_string()).collect()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_local_time_uses_offset() {
+        let query = ScoredPostsQuery {
+            utc_offset_minutes: Some(-300),
+            ..Default::default()
+        };
+        let now = Utc.with_ymd_and_hms(2026, 10, 14, 3, 0, 0).unwrap();
+        assert_eq!(TimeOfDayScorer::local_time(&query, now).unwrap().hour(), 22);
+        assert!(TimeOfDayScorer::local_time(&ScoredPostsQuery::default(), now).is_none());
+    }
+
+    #[test]
+    fn test_freshness_tolerance_tracks_peak_hours() {
+        let cluster = ClusterProfile::default(); // peak 9-20, optimal age 24h
+        let post = candidate(at(13, 12), "", &[]);
+
+        // Peak hours halve the 24h tolerance; off-peak stretches it to 36h
+        assert!(TimeOfDayScorer::multiplier(&post, &cluster, at(14, 12)) < 1.0);
+        assert_eq!(TimeOfDayScorer::multiplier(&post, &cluster, at(14, 6)), 1.0);
+    }
+
+    #[test]
+    fn test_peak_hours_are_matched_in_utc() {
+        let cluster = ClusterProfile::default(); // peak 9-20 UTC
+        let post = candidate(at(13, 12), "", &[]);
+        let new_york = FixedOffset::west_opt(5 * 3600).unwrap();
+
+        // 06:00 in New York is 11:00 UTC, a peak hour
+        let morning = at(14, 11).with_timezone(&new_york);
+        assert_eq!(morning.hour(), 6);
+        assert!(TimeOfDayScorer::multiplier(&post, &cluster, morning) < 1.0);
+        // 17:00 in New York is 22:00 UTC, off peak
+        let evening = at(14, 22).with_timezone(&new_york);
+        assert_eq!(TimeOfDayScorer::multiplier(&post, &cluster, evening), 1.0);
+    }
+
+    #[test]
+    fn test_breaking_news_and_long_form() {
+        let cluster = ClusterProfile::default();
+
+        let news = candidate(at(14, 11), "", &["news"]);
+        let multiplier = TimeOfDayScorer::multiplier(&news, &cluster, at(14, 11));
+        assert_eq!(multiplier, BREAKING_NEWS_BOOST);
+
+        let essay = candidate(at(14, 21), &"word ".repeat(120), &[]);
+        assert_eq!(TimeOfDayScorer::multiplier(&essay, &cluster, at(14, 9)), 1.0);
+        let multiplier = TimeOfDayScorer::multiplier(&essay, &cluster, at(14, 21));
+        assert_eq!(multiplier, LONG_FORM_BOOST);
+        // Weekend mornings count too
+        let weekend_essay = candidate(at(17, 9), &"word ".repeat(120), &[]);
+        assert_eq!(
+            TimeOfDayScorer::multiplier(&weekend_essay, &cluster, at(17, 9)),
+            LONG_FORM_BOOST
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//! Named weight vectors for the weighted scorers
+//!
+//! `params` holds the global defaults; a `WeightSet` is a full copy of those weights
+//! under a name such as `video_heavy`, so a scorer can swap the whole vector for a
+//! cluster instead of nudging a few weights.
+
+use crate::params as p;
+use candidate_pipeline::memo::fingerprint;
+use std::collections::HashMap;
+
+/// Name of the set built from `params`, used when no preset is requested
+pub const DEFAULT_WEIGHT_SET: &str = "default";
+
+/// Field names in `to_array` order, which ends with the negative actions
+const FIELDS: [&str; 19] = [
+    "favorite",
+    "reply",
+    "retweet",
+    "photo_expand",
+    "click",
+    "profile_click",
+    "vqv",
+    "share",
+    "share_via_dm",
+    "share_via_copy_link",
+    "dwell",
+    "quote",
+    "quoted_click",
+    "cont_dwell_time",
+    "follow_author",
+    "not_interested",
+    "block_author",
+    "mute_author",
+    "report",
+];
+const NEGATIVE_ACTIONS: usize = 4;
+
+/// Weights by field name
+pub type NamedWeights = Vec<(&'static str, f64)>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WeightSet {
+    pub favorite: f64,
+    pub reply: f64,
+    pub retweet: f64,
+    pub photo_expand: f64,
+    pub click: f64,
+    pub profile_click: f64,
+    pub vqv: f64,
+    pub share: f64,
+    pub share_via_dm: f64,
+    pub share_via_copy_link: f64,
+    pub dwell: f64,
+    pub quote: f64,
+    pub quoted_click: f64,
+    pub cont_dwell_time: f64,
+    pub follow_author: f64,
+    pub not_interested: f64,
+    pub block_author: f64,
+    pub mute_author: f64,
+    pub report: f64,
+}
+
+impl Default for WeightSet {
+    fn default() -> Self {
+        Self {
+            favorite: p::FAVORITE_WEIGHT,
+            reply: p::REPLY_WEIGHT,
+            retweet: p::RETWEET_WEIGHT,
+            photo_expand: p::PHOTO_EXPAND_WEIGHT,
+            click: p::CLICK_WEIGHT,
+            profile_click: p::PROFILE_CLICK_WEIGHT,
+            vqv: p::VQV_WEIGHT,
+            share: p::SHARE_WEIGHT,
+            share_via_dm: p::SHARE_VIA_DM_WEIGHT,
+            share_via_copy_link: p::SHARE_VIA_COPY_LINK_WEIGHT,
+            dwell: p::DWELL_WEIGHT,
+            quote: p::QUOTE_WEIGHT,
+            quoted_click: p::QUOTED_CLICK_WEIGHT,
+            cont_dwell_time: p::CONT_DWELL_TIME_WEIGHT,
+            follow_author: p::FOLLOW_AUTHOR_WEIGHT,
+            not_interested: p::NOT_INTERESTED_WEIGHT,
+            block_author: p::BLOCK_AUTHOR_WEIGHT,
+            mute_author: p::MUTE_AUTHOR_WEIGHT,
+            report: p::REPORT_WEIGHT,
+        }
+    }
+}
+
+impl WeightSet {
+    /// Video watchers: quality views and dwell count for much more
+    pub fn video_heavy() -> Self {
+        Self {
+            vqv: p::VQV_WEIGHT * 4.0,
+            dwell: p::DWELL_WEIGHT * 3.0,
+            cont_dwell_time: p::CONT_DWELL_TIME_WEIGHT * 4.0,
+            photo_expand: p::PHOTO_EXPAND_WEIGHT * 0.5,
+            ..Self::default()
+        }
+    }
+
+    /// Users who come for replies and quote threads
+    pub fn conversation() -> Self {
+        Self {
+            reply: p::REPLY_WEIGHT * 1.5,
+            quote: p::QUOTE_WEIGHT * 3.0,
+            quoted_click: p::QUOTED_CLICK_WEIGHT * 2.0,
+            vqv: p::VQV_WEIGHT * 0.5,
+            ..Self::default()
+        }
+    }
+
+    /// The weight of the field `name`, if there is one
+    pub fn get(&self, name: &str) -> Option<f64> {
+        let index = FIELDS.iter().position(|field| *field == name)?;
+        Some(self.to_array()[index])
+    }
+
+    /// Set the weight of the field `name`; false if there is none
+    pub fn set(&mut self, name: &str, weight: f64) -> bool {
+        let field = match name {
+            "favorite" => &mut self.favorite,
+            "reply" => &mut self.reply,
+            "retweet" => &mut self.retweet,
+            "photo_expand" => &mut self.photo_expand,
+            "click" => &mut self.click,
+            "profile_click" => &mut self.profile_click,
+            "vqv" => &mut self.vqv,
+            "share" => &mut self.share,
+            "share_via_dm" => &mut self.share_via_dm,
+            "share_via_copy_link" => &mut self.share_via_copy_link,
+            "dwell" => &mut self.dwell,
+            "quote" => &mut self.quote,
+            "quoted_click" => &mut self.quoted_click,
+            "cont_dwell_time" => &mut self.cont_dwell_time,
+            "follow_author" => &mut self.follow_author,
+            "not_interested" => &mut self.not_interested,
+            "block_author" => &mut self.block_author,
+            "mute_author" => &mut self.mute_author,
+            "report" => &mut self.report,
+            _ => return false,
+        };
+        *field = weight;
+        true
+    }
+
+    /// Weights of positive and of negative actions, by field name
+    pub fn by_action(&self) -> (NamedWeights, NamedWeights) {
+        let weights: Vec<_> = FIELDS.into_iter().zip(self.to_array()).collect();
+        let (positive, negative) = weights.split_at(FIELDS.len() - NEGATIVE_ACTIONS);
+        (positive.to_vec(), negative.to_vec())
+    }
+
+    /// Weights in the order the scorers lay out `PhoenixScores`
+    pub fn to_array(&self) -> [f64; 19] {
+        [
+            self.favorite,
+            self.reply,
+            self.retweet,
+            self.photo_expand,
+            self.click,
+            self.profile_click,
+            self.vqv,
+            self.share,
+            self.share_via_dm,
+            self.share_via_copy_link,
+            self.dwell,
+            self.quote,
+            self.quoted_click,
+            self.cont_dwell_time,
+            self.follow_author,
+            self.not_interested,
+            self.block_author,
+            self.mute_author,
+            self.report,
+        ]
+    }
+}
+
+/// Weight sets by name. Always contains `DEFAULT_WEIGHT_SET`.
+#[derive(Clone, Debug)]
+pub struct WeightSetRegistry {
+    sets: HashMap<String, WeightSet>,
+}
+
+impl Default for WeightSetRegistry {
+    fn default() -> Self {
+        let mut registry = Self {
+            sets: HashMap::new(),
+        };
+        registry.register(DEFAULT_WEIGHT_SET, WeightSet::default());
+        registry.register("video_heavy", WeightSet::video_heavy());
+        registry.register("conversation", WeightSet::conversation());
+        registry
+    }
+}
+
+impl WeightSetRegistry {
+    pub fn register(&mut self, name: &str, set: WeightSet) {
+        self.sets.insert(name.to_string(), set);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&WeightSet> {
+        self.sets.get(name)
+    }
+
+    /// Every set, by name
+    pub fn sets(&self) -> Vec<(&str, &WeightSet)> {
+        let mut sets: Vec<_> = self.sets.iter().map(|(name, set)| (name.as_str(), set)).collect();
+        sets.sort_by_key(|(name, _)| *name);
+        sets
+    }
+
+    /// Set for `name`, falling back to the default set for `None` or unknown names
+    pub fn resolve(&self, name: Option<&str>) -> &WeightSet {
+        name.and_then(|name| self.sets.get(name))
+            .unwrap_or_else(|| &self.sets[DEFAULT_WEIGHT_SET])
+    }
+
+    /// Changes whenever a set is added, removed or reweighted
+    pub fn fingerprint(&self) -> u64 {
+        let mut sets: Vec<_> = self
+            .sets
+            .iter()
+            .map(|(name, set)| (name, set.to_array().map(f64::to_bits)))
+            .collect();
+        sets.sort();
+        fingerprint(&sets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_falls_back_to_default() {
+        let registry = WeightSetRegistry::default();
+
+        assert_eq!(registry.resolve(None), &WeightSet::default());
+        assert_eq!(registry.resolve(Some("unknown")), &WeightSet::default());
+        assert_eq!(registry.resolve(Some("video_heavy")).vqv, p::VQV_WEIGHT * 4.0);
+        assert_eq!(registry.resolve(Some("video_heavy")).reply, p::REPLY_WEIGHT);
+
+        let mut reweighted = registry.clone();
+        assert_eq!(reweighted.fingerprint(), registry.fingerprint());
+        reweighted.register("video_heavy", WeightSet::conversation());
+        assert_ne!(reweighted.fingerprint(), registry.fingerprint());
+    }
+}
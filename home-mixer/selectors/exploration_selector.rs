@@ -0,0 +1,98 @@
+use crate::candidate_pipeline::candidate::PostCandidate;
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use candidate_pipeline::selector::Selector;
+
+/// Top-K by score, except that `quota` of the K slots go to the best-scoring
+/// `exploratory` candidates when there are any. The result stays in score order.
+pub struct ExplorationSelector {
+    size: usize,
+    quota: f64,
+}
+
+impl ExplorationSelector {
+    pub fn new(size: usize, quota: f64) -> Self {
+        Self {
+            size,
+            quota: quota.clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl Selector<ScoredPostsQuery, PostCandidate> for ExplorationSelector {
+    fn select(
+        &self,
+        _query: &ScoredPostsQuery,
+        candidates: Vec<PostCandidate>,
+    ) -> Vec<PostCandidate> {
+        let sorted = self.sort(candidates);
+        let mut keep = vec![false; sorted.len()];
+
+        let reserved = (self.size as f64 * self.quota).round() as usize;
+        let exploratory = sorted
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.exploratory == Some(true))
+            .take(reserved)
+            .map(|(i, _)| i);
+        let mut remaining = self.size;
+        for i in exploratory {
+            keep[i] = true;
+            remaining -= 1;
+        }
+        for kept in keep.iter_mut().filter(|k| !**k).take(remaining) {
+            *kept = true;
+        }
+
+        sorted
+            .into_iter()
+            .zip(keep)
+            .filter_map(|(candidate, kept)| kept.then_some(candidate))
+            .collect()
+    }
+
+    fn score(&self, candidate: &PostCandidate) -> f64 {
+        candidate.score.unwrap_or(f64::NEG_INFINITY)
+    }
+
+    fn size(&self) -> Option<usize> {
+        Some(self.size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(tweet_id: i64, score: f64, exploratory: bool) -> PostCandidate {
+        PostCandidate {
+            tweet_id,
+            score: Some(score),
+            exploratory: Some(exploratory),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_quota_reserves_slots_for_exploration() {
+        let candidates = vec![
+            candidate(1, 0.9, false),
+            candidate(2, 0.8, false),
+            candidate(3, 0.7, false),
+            candidate(4, 0.2, true),
+            candidate(5, 0.1, true),
+        ];
+        let query = ScoredPostsQuery::default();
+
+        let selected = ExplorationSelector::new(4, 0.25).select(&query, candidates.clone());
+        let ids: Vec<i64> = selected.iter().map(|c| c.tweet_id).collect();
+        assert_eq!(ids, vec![1, 2, 3, 4]);
+
+        let selected = ExplorationSelector::new(3, 0.34).select(&query, candidates.clone());
+        let ids: Vec<i64> = selected.iter().map(|c| c.tweet_id).collect();
+        assert_eq!(ids, vec![1, 2, 4]);
+
+        let selected = ExplorationSelector::new(3, 0.0).select(&query, candidates);
+        let ids: Vec<i64> = selected.iter().map(|c| c.tweet_id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+    }
+}
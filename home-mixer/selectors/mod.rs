@@ -1,3 +1,6 @@
-mod top_k_score_selector;
+pub mod exploration_selector;
 
-pub use top_k_score_selector::TopKScoreSelector;
+// The following modules require internal clients and are commented out for open-source builds:
+// mod top_k_score_selector;
+//
+// pub use top_k_score_selector::TopKScoreSelector;
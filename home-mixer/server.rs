@@ -1,54 +1,107 @@
 //! HomeMixer Server Implementation
 
 use crate::candidate_pipeline::candidate::CandidateHelpers;
-use crate::candidate_pipeline::phoenix_candidate_pipeline::PhoenixCandidatePipeline;
+use crate::candidate_pipeline::phoenix_candidate_pipeline::{
+    self, PhoenixCandidatePipeline, ProdServices,
+};
 use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::experiments::FeatureOverrides;
 use crate::proto;
+use crate::scorers::weight_sets::WeightSetRegistry;
+use crate::util::config_watcher::ConfigWatcher;
+use crate::util::debug_traces::{DebugTrace, DebugTraceStore};
+use crate::util::decision_sampling::{self, DecisionSampler};
+use crate::util::request_util::constant_time_eq;
 use candidate_pipeline::candidate_pipeline::CandidatePipeline;
-use log::info;
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Instant;
+use tonic::metadata::MetadataMap;
 use tonic::{Request, Response, Status};
+use tracing::Instrument;
 
 pub struct HomeMixerServer {
     phx_candidate_pipeline: Arc<PhoenixCandidatePipeline>,
+    /// Where responses to debug requests are written, one JSON file per request
+    debug_dump_dir: Option<PathBuf>,
+    /// Users whose requests are traced, and their traces
+    debug_traces: Option<Arc<DebugTraceStore>>,
+    /// Samples requests for their filter and scorer decisions
+    decision_sampler: Option<DecisionSampler>,
+    /// The config the pipeline uses, whose version each request's span carries
+    config: Arc<ConfigWatcher>,
+    /// Fingerprint of the weight sets the scorers use
+    weight_set_hash: u64,
+    /// Token trusted callers present to force flags and variants per request
+    feature_override_token: Option<String>,
 }
 
+/// Header trusted callers force variants with for one request, e.g.
+/// `caching=off,bait_filter_v2=treatment`
+pub const FEATURE_OVERRIDES_HEADER: &str = "x-feature-overrides";
+
 impl HomeMixerServer {
-    pub async fn new() -> Self {
+    /// Serving the production pipeline over `services`, sampling decisions to
+    /// `DECISION_SINK` when it is set, and honoring feature overrides from
+    /// callers presenting `FEATURE_OVERRIDES_TOKEN`
+    pub async fn new(services: ProdServices) -> Self {
+        let config = services.config.config();
         HomeMixerServer {
-            phx_candidate_pipeline: Arc::new(PhoenixCandidatePipeline::prod().await),
+            phx_candidate_pipeline: Arc::new(phoenix_candidate_pipeline::prod(&services).await),
+            debug_dump_dir: None,
+            debug_traces: None,
+            decision_sampler: DecisionSampler::from_config(&config.decision_sampling),
+            config: services.config,
+            weight_set_hash: WeightSetRegistry::default().fingerprint(),
+            feature_override_token: config.features.override_token.clone(),
         }
     }
-}
 
-#[tonic::async_trait]
-impl proto::scored_posts_service_server::ScoredPostsService for HomeMixerServer {
-    async fn get_scored_posts(
-        &self,
-        request: Request<proto::ScoredPostsQuery>,
-    ) -> Result<Response<proto::ScoredPostsResponse>, Status> {
-        let proto_query = request.into_inner();
+    /// Dump the response to every debug request to `{dir}/{request_id}.json`
+    pub fn with_debug_dump_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.debug_dump_dir = Some(dir.into());
+        self
+    }
 
-        if proto_query.viewer_id == 0 {
-            return Err(Status::invalid_argument("viewer_id must be specified"));
+    /// Trace the requests of the users `store` lists into it
+    pub fn with_debug_traces(mut self, store: Arc<DebugTraceStore>) -> Self {
+        self.debug_traces = Some(store);
+        self
+    }
+
+    /// The weight sets, config, experiments and pipeline that serve `query`
+    fn algorithm_version(&self, query: &ScoredPostsQuery) -> proto::AlgorithmVersion {
+        proto::AlgorithmVersion {
+            weight_set_hash: format!("{:016x}", self.weight_set_hash),
+            config_version: self.config.version(),
+            experiments: query.experiments.variants().clone().into_iter().collect(),
+            pipeline_config_hash: format!("{:016x}", self.phx_candidate_pipeline.fingerprint()),
         }
+    }
 
-        let start = Instant::now();
-        let query = ScoredPostsQuery::new(
-            proto_query.viewer_id as i64,
-            proto_query.client_app_id as i32,
-            proto_query.country_code,
-            proto_query.language_code,
-            proto_query.seen_ids,
-            proto_query.served_ids,
-            proto_query.in_network_only,
-            proto_query.is_bottom_request,
-            proto_query.bloom_filter_entries,
-        );
+    /// Run `query` through the pipeline, inside the request's span. `debug` is
+    /// whether the client asked for provenance, `traced` whether the request is
+    /// kept as a debug trace, and `sampled` whether its decisions are published.
+    async fn score_posts(
+        &self,
+        query: ScoredPostsQuery,
+        start: Instant,
+        debug: bool,
+        traced: bool,
+        sampled: bool,
+    ) -> Result<Response<proto::ScoredPostsResponse>, Status> {
         info!("Scored Posts request - request_id {}", query.request_id);
-        let pipeline_result = self.phx_candidate_pipeline.execute(query).await;
+        let pipeline_result = self
+            .phx_candidate_pipeline
+            .execute(query)
+            .await
+            .map_err(Status::from)?;
 
+        // Filtered candidates' provenance is only kept in traces and samples
+        let recorded_provenance = (traced || sampled).then(|| pipeline_result.provenance.clone());
+        let mut provenance = pipeline_result.provenance;
         let scored_posts: Vec<proto::ScoredPost> = pipeline_result
             .selected_candidates
             .into_iter()
@@ -66,20 +119,228 @@ impl proto::scored_posts_service_server::ScoredPostsService for HomeMixerServer
                     last_scored_timestamp_ms: candidate.last_scored_at_ms.unwrap_or(0),
                     prediction_request_id: candidate.prediction_request_id.unwrap_or(0),
                     ancestors: candidate.ancestors,
-                    screen_names,
+                    screen_names: screen_names.into_iter().collect(),
                     visibility_reason: candidate.visibility_reason.map(|r| proto::VisibilityReason {
-                        filtered_reason: Some(r),
+                        filtered_reason: r as i32,
                     }),
+                    subscription_preview: candidate.subscription_preview.unwrap_or(false),
+                    provenance: provenance
+                        .remove(&(candidate.tweet_id as u64))
+                        .filter(|_| debug)
+                        .map(proto::Provenance::from),
                 }
             })
             .collect();
 
+        let stage_errors: Vec<proto::StageError> = pipeline_result
+            .errors
+            .iter()
+            .map(|e| proto::StageError {
+                stage: format!("{:?}", e.stage),
+                component: e.component.to_string(),
+                kind: e.error.kind.as_str().to_string(),
+                message: e.error.message.clone(),
+                retryable: e.error.retryable,
+                outcome: e.outcome.as_str().to_string(),
+            })
+            .collect();
+
         info!(
-            "Scored Posts response - request_id {} - {} posts ({} ms)",
+            "Scored Posts response - request_id {} - {} posts, {} stage errors ({} ms)",
             pipeline_result.query.request_id,
             scored_posts.len(),
+            stage_errors.len(),
             start.elapsed().as_millis()
         );
-        Ok(Response::new(proto::ScoredPostsResponse { scored_posts }))
+        let response = proto::ScoredPostsResponse {
+            scored_posts,
+            stage_errors,
+            partial: pipeline_result.partial,
+            request_id: pipeline_result.query.request_id.clone(),
+            algorithm_version: Some(self.algorithm_version(&pipeline_result.query)),
+            locale_params: debug
+                .then(|| pipeline_result.query.locale_params.as_ref().clone().into()),
+        };
+        if let Some(provenance) = recorded_provenance {
+            let query = &pipeline_result.query;
+            let now_ms = chrono::Utc::now().timestamp_millis();
+            let selected: Vec<u64> = response.scored_posts.iter().map(|p| p.tweet_id).collect();
+            if let Some(sampler) = self.decision_sampler.as_ref().filter(|_| sampled) {
+                sampler.publish(decision_sampling::decisions(
+                    &query.request_id,
+                    query.user_id,
+                    now_ms,
+                    &provenance,
+                    &selected,
+                ));
+            }
+            if let Some(store) = self.debug_traces.as_ref().filter(|_| traced) {
+                store.record(DebugTrace {
+                    request_id: query.request_id.clone(),
+                    user_id: query.user_id as u64,
+                    captured_at_ms: now_ms,
+                    selected,
+                    provenance,
+                    stage_errors: response.stage_errors.clone(),
+                    partial: response.partial,
+                    locale_params: query.locale_params.as_ref().clone(),
+                });
+            }
+        }
+        if let Some(dir) = self.debug_dump_dir.as_deref().filter(|_| debug) {
+            let request_id = &pipeline_result.query.request_id;
+            if let Err(e) = dump_response(dir, request_id, &response) {
+                warn!("request_id={} failed to dump debug response: {}", request_id, e);
+            }
+        }
+        Ok(Response::new(response))
+    }
+}
+
+fn dump_response(
+    dir: &Path,
+    request_id: &str,
+    response: &proto::ScoredPostsResponse,
+) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let json = serde_json::to_vec_pretty(response).map_err(|e| e.to_string())?;
+    std::fs::write(dir.join(format!("{}.json", request_id)), json).map_err(|e| e.to_string())
+}
+
+#[tonic::async_trait]
+impl proto::scored_posts_service_server::ScoredPostsService for HomeMixerServer {
+    async fn get_scored_posts(
+        &self,
+        request: Request<proto::ScoredPostsQuery>,
+    ) -> Result<Response<proto::ScoredPostsResponse>, Status> {
+        let (metadata, _, mut proto_query) = request.into_parts();
+
+        if proto_query.viewer_id == 0 {
+            return Err(Status::invalid_argument("viewer_id must be specified"));
+        }
+        let feature_overrides = feature_overrides(
+            &metadata,
+            std::mem::take(&mut proto_query.feature_overrides),
+            self.feature_override_token.as_deref(),
+        )?;
+
+        let start = Instant::now();
+        let utc_offset_minutes = proto_query.utc_offset_minutes;
+        let previous_request_id = proto_query.previous_request_id;
+        let debug = proto_query.debug;
+        let mut query = ScoredPostsQuery::new(
+            proto_query.viewer_id as i64,
+            proto_query.client_app_id as i32,
+            proto_query.country_code,
+            proto_query.language_code,
+            proto_query.seen_ids,
+            proto_query.served_ids,
+            proto_query.in_network_only,
+            proto_query.is_bottom_request,
+            proto_query.bloom_filter_entries,
+        );
+        query.utc_offset_minutes = utc_offset_minutes;
+        query.previous_request_id = previous_request_id;
+        if !feature_overrides.is_empty() {
+            info!(
+                "request_id={} user_id={} feature overrides {:?}",
+                query.request_id,
+                query.user_id,
+                feature_overrides.overrides()
+            );
+        }
+        query.feature_overrides = Arc::new(feature_overrides);
+        let traced = self
+            .debug_traces
+            .as_ref()
+            .is_some_and(|store| store.is_traced(proto_query.viewer_id));
+        let sampled = self
+            .decision_sampler
+            .as_ref()
+            .is_some_and(|sampler| sampler.sample());
+        query.debug = debug || traced || sampled;
+        // Everything logged while serving the request, in the pipeline or not,
+        // carries its ids and the config version
+        let span = tracing::info_span!(
+            "scored_posts",
+            request_id = %query.request_id,
+            user_id = query.user_id,
+            config_version = self.config.version(),
+        );
+        self.score_posts(query, start, debug, traced, sampled)
+            .instrument(span)
+            .await
+    }
+}
+
+/// The variants a request forces with the `x-feature-overrides` header and the
+/// `feature_overrides` field, the header winning; refused unless the caller
+/// presents `token` as `authorization: Bearer {token}`
+#[allow(clippy::result_large_err)]
+fn feature_overrides(
+    metadata: &MetadataMap,
+    requested: BTreeMap<String, String>,
+    token: Option<&str>,
+) -> Result<FeatureOverrides, Status> {
+    let header = match metadata.get(FEATURE_OVERRIDES_HEADER) {
+        Some(value) => Some(value.to_str().map_err(|_| {
+            Status::invalid_argument(format!("{} must be ASCII", FEATURE_OVERRIDES_HEADER))
+        })?),
+        None => None,
+    };
+    if header.is_none() && requested.is_empty() {
+        return Ok(FeatureOverrides::default());
+    }
+    let presented = metadata
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let trusted = match (presented, token) {
+        (Some(presented), Some(token)) => constant_time_eq(presented.as_bytes(), token.as_bytes()),
+        _ => false,
+    };
+    if !trusted {
+        return Err(Status::permission_denied("feature overrides are for trusted callers only"));
+    }
+    let mut overrides = FeatureOverrides::from_map(requested).map_err(Status::invalid_argument)?;
+    if let Some(header) = header {
+        overrides.extend(FeatureOverrides::parse(header).map_err(Status::invalid_argument)?);
+    }
+    Ok(overrides)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    #[test]
+    fn test_only_trusted_callers_may_override_features() {
+        let mut metadata = MetadataMap::new();
+        let none = feature_overrides(&metadata, BTreeMap::new(), None).unwrap();
+        assert!(none.is_empty());
+
+        metadata.insert(FEATURE_OVERRIDES_HEADER, "caching=off".parse().unwrap());
+        let requested = BTreeMap::from([
+            ("caching".to_string(), "on".to_string()),
+            ("bait_filter_v2".to_string(), "treatment".to_string()),
+        ]);
+        let refused = feature_overrides(&metadata, requested.clone(), Some("secret"));
+        assert_eq!(refused.unwrap_err().code(), Code::PermissionDenied);
+
+        metadata.insert("authorization", "Bearer secreT".parse().unwrap());
+        let refused = feature_overrides(&metadata, requested.clone(), Some("secret"));
+        assert_eq!(refused.unwrap_err().code(), Code::PermissionDenied);
+        let refused = feature_overrides(&metadata, requested.clone(), None);
+        assert_eq!(refused.unwrap_err().code(), Code::PermissionDenied);
+
+        metadata.insert("authorization", "Bearer secret".parse().unwrap());
+        let overrides = feature_overrides(&metadata, requested, Some("secret")).unwrap();
+        assert_eq!(overrides.flag("caching"), Some(false), "the header wins");
+        assert_eq!(overrides.overrides()["bait_filter_v2"], "treatment");
+
+        metadata.insert(FEATURE_OVERRIDES_HEADER, "caching".parse().unwrap());
+        let invalid = feature_overrides(&metadata, BTreeMap::new(), Some("secret"));
+        assert_eq!(invalid.unwrap_err().code(), Code::InvalidArgument);
     }
 }
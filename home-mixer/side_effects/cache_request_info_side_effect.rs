@@ -4,6 +4,7 @@ use crate::clients::strato_client::StratoClient;
 use std::env;
 use std::sync::Arc;
 use tonic::async_trait;
+use xai_candidate_pipeline::error::PipelineError;
 use xai_candidate_pipeline::side_effect::{SideEffect, SideEffectInput};
 use xai_strato::{StratoResult, StratoValue, decode};
 
@@ -20,7 +21,7 @@ impl SideEffect<ScoredPostsQuery, PostCandidate> for CacheRequestInfoSideEffect
     async fn run(
         &self,
         input: Arc<SideEffectInput<ScoredPostsQuery, PostCandidate>>,
-    ) -> Result<(), String> {
+    ) -> Result<(), PipelineError> {
         let user_id: i64 = input.query.user_id;
 
         let post_ids: Vec<i64> = input
@@ -32,11 +33,11 @@ impl SideEffect<ScoredPostsQuery, PostCandidate> for CacheRequestInfoSideEffect
         let res = client
             .store_request_info(user_id, post_ids)
             .await
-            .map_err(|e| e.to_string())?;
+            .map_err(|e| PipelineError::unavailable(e.to_string()))?;
         let decoded: StratoResult<StratoValue<()>> = decode(&res);
         match decoded {
             StratoResult::Ok(_) => Ok(()),
-            StratoResult::Err(_) => Err("error received from strato".to_string()),
+            StratoResult::Err(_) => Err(PipelineError::unavailable("error received from strato")),
         }
     }
 }
@@ -0,0 +1,32 @@
+use crate::candidate_pipeline::candidate::PostCandidate;
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::side_effect::{SideEffect, SideEffectInput};
+use log::info;
+use std::sync::Arc;
+use tonic::async_trait;
+
+/// Logs the experiments whose variant a component read while serving the
+/// request, one line per exposure, for analysis to join with engagement
+pub struct ExperimentExposureSideEffect;
+
+#[async_trait]
+impl SideEffect<ScoredPostsQuery, PostCandidate> for ExperimentExposureSideEffect {
+    fn enable(&self, query: Arc<ScoredPostsQuery>) -> bool {
+        !query.experiments.variants().is_empty()
+    }
+
+    async fn run(
+        &self,
+        input: Arc<SideEffectInput<ScoredPostsQuery, PostCandidate>>,
+    ) -> Result<(), PipelineError> {
+        let query = &input.query;
+        for exposure in query.experiments.exposures() {
+            info!(
+                "request_id={} user_id={} experiment={} variant={} exposed",
+                query.request_id, query.user_id, exposure.experiment, exposure.variant
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,24 @@
+use crate::candidate_pipeline::candidate::PostCandidate;
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::personalization::exploration::ExplorationTracker;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::side_effect::{SideEffect, SideEffectInput};
+use std::sync::Arc;
+use tonic::async_trait;
+
+/// Remembers the exploratory posts served so engagement on them can be credited
+pub struct RecordExplorationSideEffect {
+    pub tracker: Arc<ExplorationTracker>,
+}
+
+#[async_trait]
+impl SideEffect<ScoredPostsQuery, PostCandidate> for RecordExplorationSideEffect {
+    async fn run(
+        &self,
+        input: Arc<SideEffectInput<ScoredPostsQuery, PostCandidate>>,
+    ) -> Result<(), PipelineError> {
+        self.tracker
+            .record_served(input.query.user_id as u64, &input.selected_candidates);
+        Ok(())
+    }
+}
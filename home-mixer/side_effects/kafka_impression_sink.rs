@@ -0,0 +1,54 @@
+//! Kafka sink for served impressions
+//!
+//! Keys each record by viewer, so a viewer's impressions stay in order on one
+//! partition for consumers that rebuild per-user state from the topic.
+
+use std::time::Duration;
+
+use futures::future::join_all;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::ClientConfig;
+use tonic::async_trait;
+
+use super::served_impressions_side_effect::{ImpressionSink, ServedImpression};
+
+/// How long a record may wait in the producer queue before it fails
+const QUEUE_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct KafkaImpressionSink {
+    producer: FutureProducer,
+    topic: String,
+}
+
+impl KafkaImpressionSink {
+    pub fn open(brokers: &str, topic: &str) -> Result<Self, String> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("compression.type", "lz4")
+            .create()
+            .map_err(|e| format!("failed to create Kafka producer: {}", e))?;
+        Ok(Self {
+            producer,
+            topic: topic.to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl ImpressionSink for KafkaImpressionSink {
+    async fn publish(&self, impressions: &[ServedImpression]) -> Result<(), String> {
+        let mut records = Vec::with_capacity(impressions.len());
+        for impression in impressions {
+            let payload = serde_json::to_vec(impression).map_err(|e| e.to_string())?;
+            records.push((impression.user_id.to_string(), payload));
+        }
+        let sends = records.iter().map(|(key, payload)| {
+            let record = FutureRecord::to(&self.topic).key(key).payload(payload);
+            self.producer.send(record, QUEUE_TIMEOUT)
+        });
+        for result in join_all(sends).await {
+            result.map_err(|(e, _)| format!("failed to publish to {}: {}", self.topic, e))?;
+        }
+        Ok(())
+    }
+}
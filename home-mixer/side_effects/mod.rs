@@ -1 +1,14 @@
-pub mod cache_request_info_side_effect;
+//! Side effects run after selection
+//!
+//! Note: Some side effects require internal clients and are disabled for open-source compatibility.
+
+pub mod experiment_exposure_side_effect;
+pub mod exploration_side_effect;
+#[cfg(feature = "kafka")]
+pub mod kafka_impression_sink;
+pub mod served_impressions_side_effect;
+pub mod session_side_effect;
+pub mod timeline_export_side_effect;
+
+// The following modules require internal clients and are commented out for open-source builds:
+// pub mod cache_request_info_side_effect;
@@ -0,0 +1,191 @@
+//! Served-impression logging
+//!
+//! Publishes every served page, one record per post with its position, so the
+//! previously-seen filter, the bandit tuner and offline evaluation all read the
+//! same account of what users were shown. Production publishes to Kafka (with
+//! the `kafka` feature); open-source builds append JSON lines to a file.
+
+use crate::candidate_pipeline::candidate::PostCandidate;
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::side_effect::{SideEffect, SideEffectInput};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tonic::async_trait;
+
+/// One post as served on a page
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ServedImpression {
+    pub request_id: String,
+    pub user_id: i64,
+    pub tweet_id: i64,
+    /// Zero-based position on the page
+    pub position: usize,
+    pub score: f64,
+    pub served_type: i32,
+    pub served_at_ms: u64,
+}
+
+/// Where served impressions are published
+#[async_trait]
+pub trait ImpressionSink: Send + Sync {
+    async fn publish(&self, impressions: &[ServedImpression]) -> Result<(), String>;
+}
+
+/// Appends impressions to a file as JSON lines
+pub struct FileImpressionSink {
+    path: PathBuf,
+    file: Mutex<tokio::fs::File>,
+}
+
+impl FileImpressionSink {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Mutex::new(tokio::fs::File::from_std(file)),
+        })
+    }
+}
+
+#[async_trait]
+impl ImpressionSink for FileImpressionSink {
+    async fn publish(&self, impressions: &[ServedImpression]) -> Result<(), String> {
+        let mut lines = Vec::new();
+        for impression in impressions {
+            serde_json::to_writer(&mut lines, impression).map_err(|e| e.to_string())?;
+            lines.push(b'\n');
+        }
+        // One write per page keeps a page's lines together
+        let mut file = self.file.lock().await;
+        file.write_all(&lines)
+            .await
+            .and(file.flush().await)
+            .map_err(|e| format!("{}: {}", self.path.display(), e))
+    }
+}
+
+/// Sink named by `spec`: `kafka://{brokers}/{topic}`, or a file path
+pub fn open_impression_sink(spec: &str) -> Result<Arc<dyn ImpressionSink>, String> {
+    match spec.strip_prefix("kafka://") {
+        #[cfg(feature = "kafka")]
+        Some(target) => {
+            let (brokers, topic) = target
+                .rsplit_once('/')
+                .ok_or_else(|| format!("{} is not kafka://brokers/topic", spec))?;
+            let sink = super::kafka_impression_sink::KafkaImpressionSink::open(brokers, topic)?;
+            Ok(Arc::new(sink))
+        },
+        #[cfg(not(feature = "kafka"))]
+        Some(_) => Err("home-mixer was built without kafka support".to_string()),
+        None => {
+            let path = spec.strip_prefix("file://").unwrap_or(spec);
+            Ok(Arc::new(FileImpressionSink::open(path)?))
+        },
+    }
+}
+
+/// Publishes the served page of each request to an `ImpressionSink`
+pub struct ServedImpressionsSideEffect {
+    pub sink: Arc<dyn ImpressionSink>,
+}
+
+#[async_trait]
+impl SideEffect<ScoredPostsQuery, PostCandidate> for ServedImpressionsSideEffect {
+    async fn run(
+        &self,
+        input: Arc<SideEffectInput<ScoredPostsQuery, PostCandidate>>,
+    ) -> Result<(), PipelineError> {
+        if input.selected_candidates.is_empty() {
+            return Ok(());
+        }
+        let served_at_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        let impressions: Vec<ServedImpression> = input
+            .selected_candidates
+            .iter()
+            .enumerate()
+            .map(|(position, candidate)| ServedImpression {
+                request_id: input.query.request_id.clone(),
+                user_id: input.query.user_id,
+                tweet_id: candidate.tweet_id,
+                position,
+                score: candidate.score.unwrap_or(0.0),
+                served_type: candidate.served_type.map(|t| t as i32).unwrap_or_default(),
+                served_at_ms,
+            })
+            .collect();
+        self.sink
+            .publish(&impressions)
+            .await
+            .map_err(PipelineError::unavailable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::ServedType;
+
+    #[tokio::test]
+    async fn test_served_pages_are_appended_as_json_lines() {
+        let path = std::env::temp_dir()
+            .join(format!("impressions_{}", std::process::id()))
+            .join("served.jsonl");
+        let _ = std::fs::remove_file(&path);
+        let sink = open_impression_sink(path.to_str().unwrap()).unwrap();
+        let side_effect = ServedImpressionsSideEffect { sink };
+
+        let query = ScoredPostsQuery {
+            user_id: 7,
+            request_id: "req-1".to_string(),
+            ..Default::default()
+        };
+        let candidates = vec![
+            PostCandidate {
+                tweet_id: 10,
+                score: Some(0.9),
+                served_type: Some(ServedType::InNetwork),
+                ..Default::default()
+            },
+            PostCandidate {
+                tweet_id: 20,
+                score: Some(0.4),
+                ..Default::default()
+            },
+        ];
+        let input = Arc::new(SideEffectInput {
+            query: Arc::new(query),
+            selected_candidates: candidates,
+        });
+        side_effect.run(input.clone()).await.unwrap();
+        side_effect.run(input).await.unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let served: Vec<ServedImpression> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(served.len(), 4);
+        assert_eq!((served[0].tweet_id, served[0].position), (10, 0));
+        assert_eq!(served[0].served_type, ServedType::InNetwork as i32);
+        assert_eq!((served[1].tweet_id, served[1].position, served[1].score), (20, 1, 0.4));
+        assert_eq!(served[3].request_id, "req-1");
+        assert_eq!(served[3].user_id, 7);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
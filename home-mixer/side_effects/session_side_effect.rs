@@ -0,0 +1,23 @@
+use crate::candidate_pipeline::candidate::PostCandidate;
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::personalization::session_store::SessionStore;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::side_effect::{SideEffect, SideEffectInput};
+use std::sync::Arc;
+use tonic::async_trait;
+
+/// Records the served page in the viewer's session so later pages can diversify
+pub struct RecordSessionSideEffect {
+    pub store: Arc<SessionStore>,
+}
+
+#[async_trait]
+impl SideEffect<ScoredPostsQuery, PostCandidate> for RecordSessionSideEffect {
+    async fn run(
+        &self,
+        input: Arc<SideEffectInput<ScoredPostsQuery, PostCandidate>>,
+    ) -> Result<(), PipelineError> {
+        self.store.record_served(&input.query, &input.selected_candidates);
+        Ok(())
+    }
+}
@@ -0,0 +1,710 @@
+//! Served-timeline export
+//!
+//! Writes every served page for training and offline evaluation jobs, one row
+//! per post with the query's context, the post's features and predictions, its
+//! scores and its rank, so they read production data rather than scraping
+//! logs. Rows go to Parquet files in `TIMELINE_EXPORT_DIR` (with the `parquet`
+//! feature). A file is written once its rows reach
+//! `TIMELINE_EXPORT_MAX_FILE_BYTES` or its first row is
+//! `TIMELINE_EXPORT_MAX_FILE_AGE_SECS` old, since a Parquet file can't be read
+//! before it is complete. With `TIMELINE_EXPORT_UPLOAD_URL` set, written files
+//! are uploaded to S3 or GCS in the background (with the `object-store`
+//! feature), files a previous run left behind included.
+
+use crate::candidate_pipeline::candidate::{PhoenixScores, PostCandidate};
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::config::TimelineExportConfig;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::side_effect::{SideEffect, SideEffectInput};
+use std::sync::Arc;
+use tonic::async_trait;
+
+/// Prediction columns, in `PhoenixScores` order
+pub const PREDICTIONS: [&str; 19] = [
+    "favorite_score",
+    "reply_score",
+    "retweet_score",
+    "photo_expand_score",
+    "click_score",
+    "profile_click_score",
+    "vqv_score",
+    "share_score",
+    "share_via_dm_score",
+    "share_via_copy_link_score",
+    "dwell_score",
+    "quote_score",
+    "quoted_click_score",
+    "follow_author_score",
+    "not_interested_score",
+    "block_author_score",
+    "mute_author_score",
+    "report_score",
+    "dwell_time",
+];
+
+fn predictions(scores: &PhoenixScores) -> [Option<f64>; PREDICTIONS.len()] {
+    [
+        scores.favorite_score,
+        scores.reply_score,
+        scores.retweet_score,
+        scores.photo_expand_score,
+        scores.click_score,
+        scores.profile_click_score,
+        scores.vqv_score,
+        scores.share_score,
+        scores.share_via_dm_score,
+        scores.share_via_copy_link_score,
+        scores.dwell_score,
+        scores.quote_score,
+        scores.quoted_click_score,
+        scores.follow_author_score,
+        scores.not_interested_score,
+        scores.block_author_score,
+        scores.mute_author_score,
+        scores.report_score,
+        scores.dwell_time,
+    ]
+}
+
+/// One served post with what it was ranked on
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportedPost {
+    pub request_id: String,
+    pub user_id: i64,
+    pub served_at_ms: i64,
+    pub client_app_id: i32,
+    pub country_code: String,
+    pub language_code: String,
+    pub in_network_only: bool,
+    pub is_bottom_request: bool,
+    /// `{experiment}={variant}` of each experiment the request was in,
+    /// comma-separated
+    pub experiments: String,
+    pub tweet_id: i64,
+    pub author_id: u64,
+    /// Zero-based position on the page
+    pub rank: usize,
+    pub served_type: i32,
+    pub in_network: bool,
+    pub is_reply: bool,
+    pub is_retweet: bool,
+    pub video_duration_ms: Option<i32>,
+    pub author_followers_count: Option<i32>,
+    pub toxicity_score: Option<f64>,
+    /// Phoenix's predictions, named by `PREDICTIONS`
+    pub predictions: [Option<f64>; PREDICTIONS.len()],
+    pub weighted_score: Option<f64>,
+    /// The score the page was ranked by
+    pub score: Option<f64>,
+}
+
+impl ExportedPost {
+    /// About how many bytes the row takes uncompressed
+    pub fn size(&self) -> u64 {
+        let text = self.request_id.len()
+            + self.country_code.len()
+            + self.language_code.len()
+            + self.experiments.len();
+        text as u64 + 8 * (16 + PREDICTIONS.len() as u64)
+    }
+}
+
+/// The rows of a page served for `query` at `served_at_ms`
+pub fn exported_posts(
+    query: &ScoredPostsQuery,
+    served: &[PostCandidate],
+    served_at_ms: i64,
+) -> Vec<ExportedPost> {
+    let experiments = query
+        .experiments
+        .variants()
+        .iter()
+        .map(|(experiment, variant)| format!("{}={}", experiment, variant))
+        .collect::<Vec<_>>()
+        .join(",");
+    served
+        .iter()
+        .enumerate()
+        .map(|(rank, candidate)| ExportedPost {
+            request_id: query.request_id.clone(),
+            user_id: query.user_id,
+            served_at_ms,
+            client_app_id: query.client_app_id,
+            country_code: query.country_code.clone(),
+            language_code: query.language_code.clone(),
+            in_network_only: query.in_network_only,
+            is_bottom_request: query.is_bottom_request,
+            experiments: experiments.clone(),
+            tweet_id: candidate.tweet_id,
+            author_id: candidate.author_id,
+            rank,
+            served_type: candidate.served_type.map(|t| t as i32).unwrap_or_default(),
+            in_network: candidate.in_network.unwrap_or(false),
+            is_reply: candidate.in_reply_to_tweet_id.is_some(),
+            is_retweet: candidate.retweeted_tweet_id.is_some(),
+            video_duration_ms: candidate.video_duration_ms,
+            author_followers_count: candidate.author_followers_count,
+            toxicity_score: candidate.toxicity_score,
+            predictions: predictions(&candidate.phoenix_scores),
+            weighted_score: candidate.weighted_score,
+            score: candidate.score,
+        })
+        .collect()
+}
+
+/// Where exported rows are written
+#[async_trait]
+pub trait TimelineExportSink: Send + Sync {
+    async fn publish(&self, rows: Vec<ExportedPost>) -> Result<(), String>;
+}
+
+/// Sink writing Parquet files to `dir` as `config` describes, uploading them
+/// when it names an upload URL. Spawns the task rotating and uploading files,
+/// so it must be called within a runtime.
+pub fn open_timeline_export_sink(
+    dir: &str,
+    config: &TimelineExportConfig,
+) -> Result<Arc<dyn TimelineExportSink>, String> {
+    #[cfg(feature = "parquet")]
+    {
+        let max_file_age = std::time::Duration::from_secs(config.max_file_age_secs);
+        #[allow(unused_mut)]
+        let mut sink =
+            parquet_sink::ParquetTimelineSink::open(dir, config.max_file_bytes, max_file_age)?;
+        if let Some(url) = &config.upload_url {
+            #[cfg(feature = "object-store")]
+            {
+                sink = sink.with_uploader(upload::Uploader::open(url, config.keep_uploaded)?);
+            }
+            #[cfg(not(feature = "object-store"))]
+            return Err(format!("home-mixer was built without object-store support for {}", url));
+        }
+        let sink = Arc::new(sink);
+        sink.clone().spawn_rotator(parquet_sink::ROTATION_INTERVAL);
+        Ok(sink)
+    }
+    #[cfg(not(feature = "parquet"))]
+    {
+        let _ = (dir, config);
+        Err("home-mixer was built without parquet support".to_string())
+    }
+}
+
+/// Exports the served page of each request to a `TimelineExportSink`
+pub struct TimelineExportSideEffect {
+    pub sink: Arc<dyn TimelineExportSink>,
+}
+
+#[async_trait]
+impl SideEffect<ScoredPostsQuery, PostCandidate> for TimelineExportSideEffect {
+    async fn run(
+        &self,
+        input: Arc<SideEffectInput<ScoredPostsQuery, PostCandidate>>,
+    ) -> Result<(), PipelineError> {
+        if input.selected_candidates.is_empty() {
+            return Ok(());
+        }
+        let served_at_ms = chrono::Utc::now().timestamp_millis();
+        let rows = exported_posts(&input.query, &input.selected_candidates, served_at_ms);
+        self.sink.publish(rows).await.map_err(PipelineError::unavailable)
+    }
+}
+
+#[cfg(feature = "parquet")]
+pub mod parquet_sink {
+    //! Exported rows as Parquet files named after when their first row was
+    //! served. Files are written under a temporary name and renamed once
+    //! complete, so readers and the uploader only ever see whole files.
+
+    use super::{ExportedPost, TimelineExportSink, PREDICTIONS};
+    use crate::util::decision_sampling::parquet_sink::write_column;
+    use parquet::basic::Compression;
+    use parquet::data_type::{BoolType, ByteArray, ByteArrayType, DoubleType, Int32Type, Int64Type};
+    use parquet::errors::ParquetError;
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::parser::parse_message_type;
+    use std::fs::File;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use tonic::async_trait;
+
+    /// How often files are checked for age and written files uploaded
+    pub const ROTATION_INTERVAL: Duration = Duration::from_secs(10);
+
+    /// Rows waiting for their file to be written
+    #[derive(Default)]
+    struct Buffer {
+        rows: Vec<ExportedPost>,
+        bytes: u64,
+        /// When the first row was buffered
+        opened_at: Option<Instant>,
+    }
+
+    /// Writes `timelines-{served_at_ms}-{n}.parquet` files to a directory
+    pub struct ParquetTimelineSink {
+        dir: PathBuf,
+        max_file_bytes: u64,
+        max_file_age: Duration,
+        buffer: Mutex<Buffer>,
+        files: AtomicU64,
+        #[cfg(feature = "object-store")]
+        uploader: Option<super::upload::Uploader>,
+    }
+
+    impl ParquetTimelineSink {
+        pub fn open(
+            dir: impl AsRef<Path>,
+            max_file_bytes: u64,
+            max_file_age: Duration,
+        ) -> Result<Self, String> {
+            let dir = dir.as_ref();
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+            Ok(Self {
+                dir: dir.to_path_buf(),
+                max_file_bytes: max_file_bytes.max(1),
+                max_file_age,
+                buffer: Mutex::new(Buffer::default()),
+                files: AtomicU64::new(0),
+                #[cfg(feature = "object-store")]
+                uploader: None,
+            })
+        }
+
+        /// Upload written files with `uploader`
+        #[cfg(feature = "object-store")]
+        pub fn with_uploader(mut self, uploader: super::upload::Uploader) -> Self {
+            self.uploader = Some(uploader);
+            self
+        }
+
+        /// Write the buffered rows as a file if they reached the size or age
+        /// limit, or if `force` and there are any. Returns whether it wrote.
+        pub async fn rotate(&self, force: bool) -> Result<bool, String> {
+            let Some(rows) = self.take_due(force) else {
+                return Ok(false);
+            };
+            let path = self.next_path(&rows);
+            tokio::task::spawn_blocking(move || write_parquet(&path, &rows))
+                .await
+                .map_err(|e| e.to_string())??;
+            Ok(true)
+        }
+
+        /// Upload the files written so far, if uploading. Returns how many.
+        pub async fn upload_pending(&self) -> Result<usize, String> {
+            #[cfg(feature = "object-store")]
+            if let Some(uploader) = &self.uploader {
+                return uploader.upload_pending(&self.dir).await;
+            }
+            Ok(0)
+        }
+
+        /// Rotate every `interval`, then upload, logging failures. Files whose
+        /// upload failed are retried at the next interval.
+        pub fn spawn_rotator(self: Arc<Self>, interval: Duration) {
+            tokio::spawn(async move {
+                loop {
+                    if let Err(e) = self.rotate(false).await {
+                        log::warn!("Failed to write exported timelines: {}", e);
+                    }
+                    if let Err(e) = self.upload_pending().await {
+                        log::warn!("Failed to upload exported timelines: {}", e);
+                    }
+                    tokio::time::sleep(interval).await;
+                }
+            });
+        }
+
+        fn take_due(&self, force: bool) -> Option<Vec<ExportedPost>> {
+            let mut buffer = self.buffer.lock().unwrap();
+            let opened_at = buffer.opened_at?;
+            let due = force
+                || buffer.bytes >= self.max_file_bytes
+                || opened_at.elapsed() >= self.max_file_age;
+            due.then(|| std::mem::take(&mut *buffer).rows)
+        }
+
+        /// Where to write `rows`, named after when the first was served
+        fn next_path(&self, rows: &[ExportedPost]) -> PathBuf {
+            let n = self.files.fetch_add(1, Ordering::Relaxed);
+            let served_at_ms = rows.first().map_or(0, |row| row.served_at_ms);
+            self.dir.join(format!("timelines-{}-{}.parquet", served_at_ms, n))
+        }
+    }
+
+    #[async_trait]
+    impl TimelineExportSink for ParquetTimelineSink {
+        async fn publish(&self, rows: Vec<ExportedPost>) -> Result<(), String> {
+            {
+                let mut buffer = self.buffer.lock().unwrap();
+                buffer.bytes += rows.iter().map(ExportedPost::size).sum::<u64>();
+                buffer.opened_at.get_or_insert_with(Instant::now);
+                buffer.rows.extend(rows);
+            }
+            self.rotate(false).await.map(|_| ())
+        }
+    }
+
+    impl Drop for ParquetTimelineSink {
+        fn drop(&mut self) {
+            let Some(rows) = self.take_due(true) else {
+                return;
+            };
+            if let Err(e) = write_parquet(&self.next_path(&rows), &rows) {
+                log::warn!("Failed to write exported timelines: {}", e);
+            }
+        }
+    }
+
+    fn schema() -> String {
+        let predictions: String = PREDICTIONS
+            .iter()
+            .map(|name| format!("OPTIONAL DOUBLE {};\n", name))
+            .collect();
+        format!(
+            "message timeline {{
+                REQUIRED BYTE_ARRAY request_id (UTF8);
+                REQUIRED INT64 user_id;
+                REQUIRED INT64 served_at_ms;
+                REQUIRED INT32 client_app_id;
+                REQUIRED BYTE_ARRAY country_code (UTF8);
+                REQUIRED BYTE_ARRAY language_code (UTF8);
+                REQUIRED BOOLEAN in_network_only;
+                REQUIRED BOOLEAN is_bottom_request;
+                REQUIRED BYTE_ARRAY experiments (UTF8);
+                REQUIRED INT64 tweet_id;
+                REQUIRED INT64 author_id (INTEGER(64, false));
+                REQUIRED INT32 rank;
+                REQUIRED INT32 served_type;
+                REQUIRED BOOLEAN in_network;
+                REQUIRED BOOLEAN is_reply;
+                REQUIRED BOOLEAN is_retweet;
+                OPTIONAL INT32 video_duration_ms;
+                OPTIONAL INT32 author_followers_count;
+                OPTIONAL DOUBLE toxicity_score;
+                {}
+                OPTIONAL DOUBLE weighted_score;
+                OPTIONAL DOUBLE score;
+            }}",
+            predictions
+        )
+    }
+
+    fn write_parquet(path: &Path, rows: &[ExportedPost]) -> Result<(), String> {
+        let tmp = path.with_extension("parquet.tmp");
+        write_row_group(&tmp, rows)
+            .map_err(|e| e.to_string())
+            .and_then(|_| std::fs::rename(&tmp, path).map_err(|e| e.to_string()))
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn write_row_group(path: &Path, rows: &[ExportedPost]) -> Result<(), ParquetError> {
+        let schema = Arc::new(parse_message_type(&schema())?);
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let file = File::create(path)?;
+        let mut writer = SerializedFileWriter::new(file, schema, Arc::new(properties))?;
+        let mut row_group = writer.next_row_group()?;
+
+        let strings = |value: fn(&ExportedPost) -> &str| -> Vec<ByteArray> {
+            rows.iter().map(|row| ByteArray::from(value(row))).collect()
+        };
+        let longs =
+            |value: fn(&ExportedPost) -> i64| -> Vec<i64> { rows.iter().map(value).collect() };
+        let ints =
+            |value: fn(&ExportedPost) -> i32| -> Vec<i32> { rows.iter().map(value).collect() };
+        let bools =
+            |value: fn(&ExportedPost) -> bool| -> Vec<bool> { rows.iter().map(value).collect() };
+        // Values of the rows with one, and which rows have one
+        fn optional<T>(
+            rows: &[ExportedPost],
+            value: impl Fn(&ExportedPost) -> Option<T>,
+        ) -> (Vec<T>, Vec<i16>) {
+            let values = rows.iter().filter_map(&value).collect();
+            let levels = rows.iter().map(|row| value(row).is_some() as i16).collect();
+            (values, levels)
+        }
+
+        let columns = &mut row_group;
+        write_column::<ByteArrayType>(columns, &strings(|row| &row.request_id), None)?;
+        write_column::<Int64Type>(columns, &longs(|row| row.user_id), None)?;
+        write_column::<Int64Type>(columns, &longs(|row| row.served_at_ms), None)?;
+        write_column::<Int32Type>(columns, &ints(|row| row.client_app_id), None)?;
+        write_column::<ByteArrayType>(columns, &strings(|row| &row.country_code), None)?;
+        write_column::<ByteArrayType>(columns, &strings(|row| &row.language_code), None)?;
+        write_column::<BoolType>(columns, &bools(|row| row.in_network_only), None)?;
+        write_column::<BoolType>(columns, &bools(|row| row.is_bottom_request), None)?;
+        write_column::<ByteArrayType>(columns, &strings(|row| &row.experiments), None)?;
+        write_column::<Int64Type>(columns, &longs(|row| row.tweet_id), None)?;
+        write_column::<Int64Type>(columns, &longs(|row| row.author_id as i64), None)?;
+        write_column::<Int32Type>(columns, &ints(|row| row.rank as i32), None)?;
+        write_column::<Int32Type>(columns, &ints(|row| row.served_type), None)?;
+        write_column::<BoolType>(columns, &bools(|row| row.in_network), None)?;
+        write_column::<BoolType>(columns, &bools(|row| row.is_reply), None)?;
+        write_column::<BoolType>(columns, &bools(|row| row.is_retweet), None)?;
+        let (values, levels) = optional(rows, |row| row.video_duration_ms);
+        write_column::<Int32Type>(columns, &values, Some(&levels))?;
+        let (values, levels) = optional(rows, |row| row.author_followers_count);
+        write_column::<Int32Type>(columns, &values, Some(&levels))?;
+        let (values, levels) = optional(rows, |row| row.toxicity_score);
+        write_column::<DoubleType>(columns, &values, Some(&levels))?;
+        for i in 0..PREDICTIONS.len() {
+            let (values, levels) = optional(rows, |row| row.predictions[i]);
+            write_column::<DoubleType>(columns, &values, Some(&levels))?;
+        }
+        let (values, levels) = optional(rows, |row| row.weighted_score);
+        write_column::<DoubleType>(columns, &values, Some(&levels))?;
+        let (values, levels) = optional(rows, |row| row.score);
+        write_column::<DoubleType>(columns, &values, Some(&levels))?;
+
+        row_group.close()?;
+        writer.close()?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "object-store")]
+pub mod upload {
+    //! Uploads of written files to S3 or GCS. Credentials and regions come from
+    //! the environment, as the AWS and Google SDKs read them.
+
+    use object_store::aws::AmazonS3Builder;
+    use object_store::gcp::GoogleCloudStorageBuilder;
+    use object_store::path::Path as ObjectPath;
+    use object_store::ObjectStore;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    /// Uploads files to a bucket, under a prefix
+    pub struct Uploader {
+        store: Arc<dyn ObjectStore>,
+        prefix: ObjectPath,
+        /// Move uploaded files to `uploaded/` rather than deleting them
+        keep_uploaded: bool,
+    }
+
+    impl Uploader {
+        /// Uploads to `s3://{bucket}/{prefix}` or `gs://{bucket}/{prefix}`
+        pub fn open(url: &str, keep_uploaded: bool) -> Result<Self, String> {
+            let (scheme, location) = url
+                .split_once("://")
+                .ok_or_else(|| format!("{} is not an s3:// or gs:// URL", url))?;
+            let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+            let store: Arc<dyn ObjectStore> = match scheme {
+                "s3" => Arc::new(
+                    AmazonS3Builder::from_env()
+                        .with_bucket_name(bucket)
+                        .build()
+                        .map_err(|e| e.to_string())?,
+                ),
+                "gs" => Arc::new(
+                    GoogleCloudStorageBuilder::from_env()
+                        .with_bucket_name(bucket)
+                        .build()
+                        .map_err(|e| e.to_string())?,
+                ),
+                _ => return Err(format!("{} is not an s3:// or gs:// URL", url)),
+            };
+            Ok(Self::new(store, prefix, keep_uploaded))
+        }
+
+        pub fn new(store: Arc<dyn ObjectStore>, prefix: &str, keep_uploaded: bool) -> Self {
+            Self {
+                store,
+                prefix: ObjectPath::from(prefix),
+                keep_uploaded,
+            }
+        }
+
+        /// Upload the Parquet files in `dir`, oldest first, each removed from
+        /// `dir` once uploaded. Returns how many were uploaded.
+        pub async fn upload_pending(&self, dir: &Path) -> Result<usize, String> {
+            let entries = std::fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+            let mut files: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|e| e == "parquet"))
+                .collect();
+            files.sort();
+            for path in &files {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                let bytes = tokio::fs::read(path)
+                    .await
+                    .map_err(|e| format!("{}: {}", path.display(), e))?;
+                let location = self.prefix.child(name.as_ref());
+                self.store
+                    .put(&location, bytes.into())
+                    .await
+                    .map_err(|e| format!("{}: {}", location, e))?;
+                let removed = if self.keep_uploaded {
+                    let uploaded = dir.join("uploaded");
+                    std::fs::create_dir_all(&uploaded)
+                        .and_then(|_| std::fs::rename(path, uploaded.join(name.as_ref())))
+                } else {
+                    std::fs::remove_file(path)
+                };
+                removed.map_err(|e| format!("{}: {}", path.display(), e))?;
+            }
+            Ok(files.len())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiments::{ExperimentAssignments, FeatureOverrides};
+    use crate::proto::ServedType;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct RecordingSink {
+        published: Mutex<Vec<ExportedPost>>,
+    }
+
+    #[async_trait]
+    impl TimelineExportSink for RecordingSink {
+        async fn publish(&self, rows: Vec<ExportedPost>) -> Result<(), String> {
+            self.published.lock().unwrap().extend(rows);
+            Ok(())
+        }
+    }
+
+    fn served_page() -> Arc<SideEffectInput<ScoredPostsQuery, PostCandidate>> {
+        let overrides = FeatureOverrides::parse("bait_filter_v2=treatment,caching=on").unwrap();
+        let query = ScoredPostsQuery {
+            user_id: 7,
+            request_id: "req-1".to_string(),
+            country_code: "BR".to_string(),
+            experiments: Arc::new(ExperimentAssignments::default().with_overrides(&overrides)),
+            ..Default::default()
+        };
+        let candidates = vec![
+            PostCandidate {
+                tweet_id: 10,
+                author_id: 100,
+                score: Some(0.9),
+                weighted_score: Some(3.5),
+                served_type: Some(ServedType::InNetwork),
+                in_network: Some(true),
+                phoenix_scores: PhoenixScores {
+                    favorite_score: Some(0.2),
+                    dwell_time: Some(4.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            PostCandidate {
+                tweet_id: 20,
+                author_id: 200,
+                in_reply_to_tweet_id: Some(10),
+                score: Some(0.4),
+                ..Default::default()
+            },
+        ];
+        Arc::new(SideEffectInput {
+            query: Arc::new(query),
+            selected_candidates: candidates,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_one_row_per_served_post() {
+        let sink = Arc::new(RecordingSink::default());
+        let side_effect = TimelineExportSideEffect { sink: sink.clone() };
+        side_effect.run(served_page()).await.unwrap();
+
+        let rows = sink.published.lock().unwrap();
+        let ranked: Vec<_> = rows.iter().map(|row| (row.rank, row.tweet_id, row.score)).collect();
+        assert_eq!(ranked, [(0, 10, Some(0.9)), (1, 20, Some(0.4))]);
+        assert_eq!(rows[0].experiments, "bait_filter_v2=treatment,caching=enabled");
+        assert_eq!((rows[0].country_code.as_str(), rows[0].user_id), ("BR", 7));
+        assert_eq!(rows[0].served_type, ServedType::InNetwork as i32);
+        assert_eq!((rows[0].predictions[0], rows[0].predictions[18]), (Some(0.2), Some(4.0)));
+        assert!(rows[1].is_reply && !rows[1].in_network);
+        assert_eq!(rows[1].predictions, [None; PREDICTIONS.len()]);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[tokio::test]
+    async fn test_files_rotate_by_size_and_age() {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+        use parquet_sink::ParquetTimelineSink;
+        use std::time::Duration;
+
+        let dir = std::env::temp_dir().join(format!("timelines_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let page = served_page();
+        let rows = || exported_posts(&page.query, &page.selected_candidates, 1_000);
+        let page_bytes: u64 = rows().iter().map(ExportedPost::size).sum();
+        let files = || -> Vec<std::path::PathBuf> {
+            let mut files: Vec<_> = std::fs::read_dir(&dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .collect();
+            files.sort();
+            files
+        };
+
+        let sink = ParquetTimelineSink::open(&dir, 2 * page_bytes, Duration::from_secs(3600));
+        let sink = sink.unwrap();
+        sink.publish(rows()).await.unwrap();
+        assert!(files().is_empty(), "buffered until full");
+        sink.publish(rows()).await.unwrap();
+        assert_eq!(files().len(), 1);
+        sink.publish(rows()).await.unwrap();
+        assert!(!sink.rotate(false).await.unwrap(), "neither full nor old");
+        drop(sink);
+
+        let sink = ParquetTimelineSink::open(&dir, u64::MAX, Duration::ZERO).unwrap();
+        let mut later = rows();
+        later.iter_mut().for_each(|row| row.served_at_ms = 2_000);
+        sink.publish(later).await.unwrap();
+        let counts: Vec<i64> = files()
+            .iter()
+            .map(|path| {
+                let file = std::fs::File::open(path).unwrap();
+                let reader = SerializedFileReader::new(file).unwrap();
+                reader.metadata().file_metadata().num_rows()
+            })
+            .collect();
+        assert_eq!(counts, [4, 2, 2], "full, flushed when dropped, old");
+        let file = std::fs::File::open(&files()[0]).unwrap();
+        let schema = SerializedFileReader::new(file).unwrap();
+        let columns = schema.metadata().file_metadata().schema_descr().num_columns();
+        assert_eq!(columns, 21 + PREDICTIONS.len());
+        assert_eq!(sink.upload_pending().await.unwrap(), 0, "not uploading");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(all(feature = "parquet", feature = "object-store"))]
+    #[tokio::test]
+    async fn test_written_files_are_uploaded_once() {
+        use object_store::memory::InMemory;
+        use object_store::ObjectStore;
+        use parquet_sink::ParquetTimelineSink;
+        use std::time::Duration;
+
+        let dir = std::env::temp_dir().join(format!("timelines_upload_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = Arc::new(InMemory::new());
+        let uploader = upload::Uploader::new(store.clone(), "exports/home", true);
+        let sink = ParquetTimelineSink::open(&dir, 1, Duration::from_secs(3600))
+            .unwrap()
+            .with_uploader(uploader);
+        let page = served_page();
+        sink.publish(exported_posts(&page.query, &page.selected_candidates, 1_000))
+            .await
+            .unwrap();
+
+        assert_eq!(sink.upload_pending().await.unwrap(), 1);
+        assert_eq!(sink.upload_pending().await.unwrap(), 0, "moved to uploaded/");
+        let location = "exports/home/timelines-1000-0.parquet".into();
+        assert!(store.head(&location).await.is_ok());
+        assert!(dir.join("uploaded/timelines-1000-0.parquet").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
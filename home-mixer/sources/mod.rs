@@ -1,2 +1,5 @@
-pub mod phoenix_source;
-pub mod thunder_source;
+pub mod similar_authors_source;
+
+// The following modules require internal clients and are commented out for open-source builds:
+// pub mod phoenix_source;
+// pub mod thunder_source;
@@ -4,6 +4,7 @@ use crate::clients::phoenix_retrieval_client::PhoenixRetrievalClient;
 use crate::params as p;
 use std::sync::Arc;
 use tonic::async_trait;
+use xai_candidate_pipeline::error::PipelineError;
 use xai_candidate_pipeline::source::Source;
 use xai_home_mixer_proto as pb;
 
@@ -18,19 +19,24 @@ impl Source<ScoredPostsQuery, PostCandidate> for PhoenixSource {
     }
 
     #[xai_stats_macro::receive_stats]
-    async fn get_candidates(&self, query: &ScoredPostsQuery) -> Result<Vec<PostCandidate>, String> {
+    async fn get_candidates(
+        &self,
+        query: &ScoredPostsQuery,
+    ) -> Result<Vec<PostCandidate>, PipelineError> {
         let user_id = query.user_id as u64;
 
         let sequence = query
             .user_action_sequence
             .as_ref()
-            .ok_or_else(|| "PhoenixSource: missing user_action_sequence".to_string())?;
+            .ok_or_else(|| {
+                PipelineError::invalid_input("PhoenixSource: missing user_action_sequence")
+            })?;
 
         let response = self
             .phoenix_retrieval_client
             .retrieve(user_id, sequence.clone(), p::PHOENIX_MAX_RESULTS)
             .await
-            .map_err(|e| format!("PhoenixSource: {}", e))?;
+            .map_err(|e| PipelineError::unavailable(format!("PhoenixSource: {}", e)))?;
 
         let candidates: Vec<PostCandidate> = response
             .top_k_candidates
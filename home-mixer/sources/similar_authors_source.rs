@@ -0,0 +1,202 @@
+use crate::candidate_pipeline::candidate::PostCandidate;
+use crate::candidate_pipeline::query::ScoredPostsQuery;
+use crate::personalization::embedding_store::EmbeddingStore;
+use crate::proto::ServedType;
+use candidate_pipeline::error::PipelineError;
+use candidate_pipeline::source::Source;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::async_trait;
+
+/// Similar authors looked up per request
+const MAX_SIMILAR_AUTHORS: usize = 50;
+/// Recent posts fetched per similar author
+const POSTS_PER_AUTHOR: usize = 3;
+
+/// Recent posts per author (backed by Thunder's per-author post store)
+#[async_trait]
+pub trait AuthorPostsProvider: Send + Sync {
+    async fn recent_posts(&self, author_ids: &[u64]) -> Result<Vec<PostCandidate>, String>;
+}
+
+/// Fetches recent posts from Thunder's JSON gateway at `{url}/v1/in_network_posts`,
+/// the authors standing in for the accounts followed
+pub struct ThunderAuthorPostsProvider {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl ThunderAuthorPostsProvider {
+    pub fn new(url: impl Into<String>, timeout: Duration) -> Result<Self, String> {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let url: String = url.into();
+        Ok(Self {
+            client,
+            url: format!("{}/v1/in_network_posts", url.trim_end_matches('/')),
+        })
+    }
+}
+
+/// The fields of a `GetInNetworkPostsResponse` read here
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct ThunderPostsPage {
+    posts: Vec<ThunderPost>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct ThunderPost {
+    post_id: i64,
+    author_id: i64,
+    content: String,
+    reply_to_id: Option<i64>,
+}
+
+#[async_trait]
+impl AuthorPostsProvider for ThunderAuthorPostsProvider {
+    async fn recent_posts(&self, author_ids: &[u64]) -> Result<Vec<PostCandidate>, String> {
+        let request = serde_json::json!({
+            "following_ids": author_ids,
+            "limit": author_ids.len() * POSTS_PER_AUTHOR,
+            "max_per_author": POSTS_PER_AUTHOR,
+        });
+        let page: ThunderPostsPage = self
+            .client
+            .post(&self.url)
+            .json(&request)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("{}: {}", self.url, e))?
+            .json()
+            .await
+            .map_err(|e| format!("{}: {}", self.url, e))?;
+        Ok(page
+            .posts
+            .into_iter()
+            .map(|post| PostCandidate {
+                tweet_id: post.post_id,
+                author_id: post.author_id as u64,
+                tweet_text: post.content,
+                in_reply_to_tweet_id: post.reply_to_id.map(|id| id as u64),
+                ..Default::default()
+            })
+            .collect())
+    }
+}
+
+/// Discovery candidates: recent posts from authors whose embeddings are close to the
+/// viewer's but whom the viewer doesn't follow yet
+pub struct SimilarAuthorsSource {
+    pub embeddings: Arc<EmbeddingStore>,
+    pub posts: Arc<dyn AuthorPostsProvider>,
+}
+
+impl SimilarAuthorsSource {
+    pub fn new(embeddings: Arc<EmbeddingStore>, posts: Arc<dyn AuthorPostsProvider>) -> Self {
+        Self { embeddings, posts }
+    }
+}
+
+#[async_trait]
+impl Source<ScoredPostsQuery, PostCandidate> for SimilarAuthorsSource {
+    fn enable(&self, query: &ScoredPostsQuery) -> bool {
+        !query.in_network_only
+    }
+
+    async fn get_candidates(
+        &self,
+        query: &ScoredPostsQuery,
+    ) -> Result<Vec<PostCandidate>, PipelineError> {
+        let features = &query.user_features;
+        let excluded: HashSet<u64> = features
+            .followed_user_ids
+            .iter()
+            .chain(&features.blocked_user_ids)
+            .chain(&features.muted_user_ids)
+            .map(|id| *id as u64)
+            .collect();
+
+        let author_ids: Vec<u64> = self
+            .embeddings
+            .similar_unfollowed_authors(query.user_id as u64, &excluded, MAX_SIMILAR_AUTHORS)
+            .into_iter()
+            .map(|(author_id, _)| author_id)
+            .collect();
+        if author_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let posts = self
+            .posts
+            .recent_posts(&author_ids)
+            .await
+            .map_err(|e| PipelineError::unavailable(format!("SimilarAuthorsSource: {}", e)))?;
+
+        Ok(posts
+            .into_iter()
+            .filter(|post| !excluded.contains(&post.author_id))
+            .map(|post| PostCandidate {
+                served_type: Some(ServedType::OutOfNetwork),
+                in_network: Some(false),
+                discovery: Some(true),
+                ..post
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::candidate_pipeline::query_features::UserFeatures;
+    use crate::personalization::embedding_store::HnswConfig;
+
+    struct OnePostPerAuthor;
+
+    #[async_trait]
+    impl AuthorPostsProvider for OnePostPerAuthor {
+        async fn recent_posts(&self, author_ids: &[u64]) -> Result<Vec<PostCandidate>, String> {
+            Ok(author_ids
+                .iter()
+                .map(|author_id| PostCandidate {
+                    tweet_id: *author_id as i64 * 100,
+                    author_id: *author_id,
+                    ..Default::default()
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_returns_posts_from_unfollowed_similar_authors() {
+        let mut embeddings = EmbeddingStore::new(2, HnswConfig::default());
+        embeddings.insert_user(1, vec![1.0, 0.0]).unwrap();
+        embeddings.insert_author(10, &[1.0, 0.1]).unwrap();
+        embeddings.insert_author(20, &[1.0, 0.2]).unwrap();
+        embeddings.insert_author(30, &[0.9, 0.3]).unwrap();
+        let source = SimilarAuthorsSource::new(Arc::new(embeddings), Arc::new(OnePostPerAuthor));
+
+        let query = ScoredPostsQuery {
+            user_id: 1,
+            user_features: UserFeatures {
+                followed_user_ids: vec![10],
+                muted_user_ids: vec![30],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let candidates = source.get_candidates(&query).await.unwrap();
+
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].author_id, 20);
+        assert_eq!(candidates[0].served_type, Some(ServedType::OutOfNetwork));
+        assert_eq!(candidates[0].discovery, Some(true));
+    }
+}
@@ -4,6 +4,7 @@ use crate::clients::thunder_client::{ThunderClient, ThunderCluster};
 use crate::params as p;
 use std::sync::Arc;
 use tonic::async_trait;
+use xai_candidate_pipeline::error::PipelineError;
 use xai_candidate_pipeline::source::Source;
 use xai_home_mixer_proto as pb;
 use xai_thunder_proto::GetInNetworkPostsRequest;
@@ -16,12 +17,15 @@ pub struct ThunderSource {
 #[async_trait]
 impl Source<ScoredPostsQuery, PostCandidate> for ThunderSource {
     #[xai_stats_macro::receive_stats]
-    async fn get_candidates(&self, query: &ScoredPostsQuery) -> Result<Vec<PostCandidate>, String> {
+    async fn get_candidates(
+        &self,
+        query: &ScoredPostsQuery,
+    ) -> Result<Vec<PostCandidate>, PipelineError> {
         let cluster = ThunderCluster::Amp;
         let channel = self
             .thunder_client
             .get_random_channel(cluster)
-            .ok_or_else(|| "ThunderSource: no available channel".to_string())?;
+            .ok_or_else(|| PipelineError::unavailable("ThunderSource: no available channel"))?;
 
         let mut client = InNetworkPostsServiceClient::new(channel.clone());
         let following_list = &query.user_features.followed_user_ids;
@@ -38,7 +42,7 @@ impl Source<ScoredPostsQuery, PostCandidate> for ThunderSource {
         let response = client
             .get_in_network_posts(request)
             .await
-            .map_err(|e| format!("ThunderSource: {}", e))?;
+            .map_err(|e| PipelineError::unavailable(format!("ThunderSource: {}", e)))?;
 
         let candidates: Vec<PostCandidate> = response
             .into_inner()
@@ -9,7 +9,7 @@ use home_mixer::scorers::weighted_scorer::WeightedScorer;
 /// Test that the weighted scorer produces non-zero scores
 #[tokio::test]
 async fn test_weighted_scorer_basic() {
-    let scorer = WeightedScorer;
+    let scorer = WeightedScorer::new();
 
     let query = ScoredPostsQuery::default();
     let candidate = PostCandidate {
@@ -32,7 +32,7 @@ async fn test_weighted_scorer_basic() {
 /// Test that the scorer handles empty input
 #[tokio::test]
 async fn test_weighted_scorer_empty_input() {
-    let scorer = WeightedScorer;
+    let scorer = WeightedScorer::new();
 
     let query = ScoredPostsQuery::default();
     let candidates: Vec<PostCandidate> = vec![];
@@ -45,7 +45,7 @@ async fn test_weighted_scorer_empty_input() {
 /// Test that video duration affects VQV weight
 #[tokio::test]
 async fn test_weighted_scorer_video_boost() {
-    let scorer = WeightedScorer;
+    let scorer = WeightedScorer::new();
 
     let query = ScoredPostsQuery::default();
 
@@ -0,0 +1,296 @@
+//! Alerting
+//!
+//! Samples the pipeline's health every `ALERT_INTERVAL_SECS` and posts to
+//! `ALERT_WEBHOOK_URL` (a Slack incoming webhook works as is) when an indicator
+//! breaches its threshold, and again when it recovers: failed components per
+//! request, and the hit rates of the memoized stages' caches and of the
+//! following-list cache, which collapse when an upstream starts churning. A
+//! firing alert is announced once, and an alert is announced firing at most
+//! once per `ALERT_COOLDOWN_SECS`, so one that flaps across its threshold
+//! doesn't flood the channel. Resolutions are only announced for alerts whose
+//! firing was.
+
+use crate::config::AlertingConfig;
+use crate::query_hydrators::following_query_hydrator::FollowingListCache;
+use candidate_pipeline::candidate_pipeline::PipelineStage;
+use candidate_pipeline::metrics::PipelineMetrics;
+use log::{info, warn};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Fewest requests or cache lookups between two checks for a rate to be judged
+const MIN_SAMPLES: u64 = 20;
+/// Longest wait for the webhook to accept a notification
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Totals the readings are computed from, sampled every check
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HealthSample {
+    pub requests: u64,
+    pub failed_components: u64,
+    pub memo_hits: u64,
+    pub memo_misses: u64,
+    pub following_hits: u64,
+    pub following_misses: u64,
+}
+
+impl HealthSample {
+    pub fn take(metrics: &PipelineMetrics, following: Option<&FollowingListCache>) -> Self {
+        let memo = metrics.memo_total();
+        let following = following.map(|cache| cache.stats()).unwrap_or_default();
+        Self {
+            requests: metrics.stage(PipelineStage::QueryHydrator).map_or(0, |s| s.count),
+            failed_components: metrics.error_counts().iter().map(|e| e.3).sum(),
+            memo_hits: memo.hits,
+            memo_misses: memo.misses,
+            following_hits: following.hits,
+            following_misses: following.misses,
+        }
+    }
+}
+
+/// Readings of the change between two samples
+pub fn readings(
+    config: &AlertingConfig,
+    previous: &HealthSample,
+    current: &HealthSample,
+) -> Vec<Reading> {
+    let mut readings = Vec::new();
+    let requests = current.requests.saturating_sub(previous.requests);
+    if requests >= MIN_SAMPLES {
+        let failed = current.failed_components.saturating_sub(previous.failed_components);
+        let rate = failed as f64 / requests as f64;
+        readings.push(Reading::above("pipeline_error_rate", rate, config.max_error_rate));
+    }
+    let caches = [
+        (
+            "memo_hit_rate",
+            current.memo_hits.saturating_sub(previous.memo_hits),
+            current.memo_misses.saturating_sub(previous.memo_misses),
+        ),
+        (
+            "following_cache_hit_rate",
+            current.following_hits.saturating_sub(previous.following_hits),
+            current.following_misses.saturating_sub(previous.following_misses),
+        ),
+    ];
+    for (alert, hits, misses) in caches {
+        if hits + misses >= MIN_SAMPLES {
+            let rate = hits as f64 / (hits + misses) as f64;
+            readings.push(Reading::below(alert, rate, config.min_cache_hit_rate));
+        }
+    }
+    readings
+}
+
+/// One indicator against its threshold, at one check
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reading {
+    pub alert: &'static str,
+    pub value: f64,
+    pub threshold: f64,
+    pub breached: bool,
+}
+
+impl Reading {
+    pub fn above(alert: &'static str, value: f64, max: f64) -> Self {
+        Self {
+            alert,
+            value,
+            threshold: max,
+            breached: value > max,
+        }
+    }
+
+    pub fn below(alert: &'static str, value: f64, min: f64) -> Self {
+        Self {
+            alert,
+            value,
+            threshold: min,
+            breached: value < min,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+/// What is posted to the webhook; `text` is what Slack shows
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Notification {
+    pub text: String,
+    pub service: &'static str,
+    pub alert: &'static str,
+    pub state: AlertState,
+    pub value: f64,
+    pub threshold: f64,
+}
+
+#[derive(Default)]
+struct Tracked {
+    firing: bool,
+    /// Whether the current firing was announced
+    announced: bool,
+    last_announced: Option<Instant>,
+}
+
+/// Turns readings into notifications, remembering what is firing
+pub struct Alerter {
+    service: &'static str,
+    cooldown: Duration,
+    tracked: Mutex<HashMap<&'static str, Tracked>>,
+}
+
+impl Alerter {
+    pub fn new(service: &'static str, cooldown: Duration) -> Self {
+        Self {
+            service,
+            cooldown,
+            tracked: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The notifications `readings` taken at `now` call for
+    pub fn notifications(&self, readings: &[Reading], now: Instant) -> Vec<Notification> {
+        let mut tracked = self.tracked.lock().unwrap();
+        let mut notifications = Vec::new();
+        for reading in readings {
+            let alert = tracked.entry(reading.alert).or_default();
+            let cooled_down = alert
+                .last_announced
+                .is_none_or(|at| now.duration_since(at) >= self.cooldown);
+            if reading.breached {
+                alert.firing = true;
+                if !alert.announced && cooled_down {
+                    alert.announced = true;
+                    alert.last_announced = Some(now);
+                    notifications.push(self.notification(reading, AlertState::Firing));
+                }
+            } else if alert.firing {
+                alert.firing = false;
+                if alert.announced {
+                    alert.announced = false;
+                    notifications.push(self.notification(reading, AlertState::Resolved));
+                }
+            }
+        }
+        notifications
+    }
+
+    fn notification(&self, reading: &Reading, state: AlertState) -> Notification {
+        let label = match state {
+            AlertState::Firing => "FIRING",
+            AlertState::Resolved => "RESOLVED",
+        };
+        Notification {
+            text: format!(
+                "[{}] {} {}: {:.3} (threshold {})",
+                self.service, label, reading.alert, reading.value, reading.threshold
+            ),
+            service: self.service,
+            alert: reading.alert,
+            state,
+            value: reading.value,
+            threshold: reading.threshold,
+        }
+    }
+}
+
+/// Check the pipeline's health every interval, posting to `webhook_url` as
+/// alerts fire and resolve
+pub fn spawn_monitor(
+    config: AlertingConfig,
+    webhook_url: String,
+    metrics: Arc<PipelineMetrics>,
+    following: Option<Arc<FollowingListCache>>,
+) {
+    let alerter = Alerter::new("home-mixer", Duration::from_secs(config.cooldown_secs));
+    let client = reqwest::Client::builder()
+        .timeout(WEBHOOK_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    let interval = Duration::from_secs(config.interval_secs);
+    tokio::spawn(async move {
+        let mut previous = HealthSample::take(&metrics, following.as_deref());
+        loop {
+            tokio::time::sleep(interval).await;
+            let current = HealthSample::take(&metrics, following.as_deref());
+            let readings = readings(&config, &previous, &current);
+            for notification in alerter.notifications(&readings, Instant::now()) {
+                info!("Alert: {}", notification.text);
+                let sent = client
+                    .post(&webhook_url)
+                    .json(&notification)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+                if let Err(e) = sent {
+                    warn!("Failed to post alert {}: {}", notification.alert, e);
+                }
+            }
+            previous = current;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flapping_alert_is_announced_once_per_cooldown() {
+        let alerter = Alerter::new("home-mixer", Duration::from_secs(600));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let hit_rate = |value| [Reading::below("memo_hit_rate", value, 0.5)];
+        let states = |notifications: Vec<Notification>| {
+            notifications.into_iter().map(|n| n.state).collect::<Vec<_>>()
+        };
+
+        let fired = alerter.notifications(&hit_rate(0.1), at(0));
+        assert_eq!(fired[0].text, "[home-mixer] FIRING memo_hit_rate: 0.100 (threshold 0.5)");
+        assert_eq!(states(alerter.notifications(&hit_rate(0.2), at(30))), vec![]);
+        let resolved = alerter.notifications(&hit_rate(0.9), at(60));
+        assert_eq!(states(resolved), vec![AlertState::Resolved]);
+
+        // Fires again within the cooldown: held back, and so is its resolution
+        assert_eq!(states(alerter.notifications(&hit_rate(0.1), at(90))), vec![]);
+        assert_eq!(states(alerter.notifications(&hit_rate(0.9), at(120))), vec![]);
+        let late = alerter.notifications(&hit_rate(0.1), at(600));
+        assert_eq!(states(late), vec![AlertState::Firing]);
+    }
+
+    #[test]
+    fn test_readings_from_samples() {
+        let config = AlertingConfig::default();
+        let previous = HealthSample {
+            requests: 100,
+            failed_components: 5,
+            memo_hits: 900,
+            memo_misses: 100,
+            following_hits: 50,
+            following_misses: 50,
+        };
+        let current = HealthSample {
+            requests: 200,
+            failed_components: 25,
+            memo_hits: 910,
+            memo_misses: 190,
+            following_hits: 55,
+            following_misses: 55,
+        };
+
+        let readings = readings(&config, &previous, &current);
+        let alerts: Vec<_> = readings.iter().map(|r| (r.alert, r.breached)).collect();
+        // Too few following-list lookups to judge
+        assert_eq!(alerts, [("pipeline_error_rate", true), ("memo_hit_rate", true)]);
+        assert_eq!(readings[0].value, 0.2);
+        assert_eq!(readings[1].value, 0.1);
+    }
+}
@@ -0,0 +1,233 @@
+//! Scheduled config changes
+//!
+//! `CONFIG_SCHEDULES` lists overrides of config settings applied on a cron
+//! schedule, e.g. enabling the diversity boost during a weekly live event and
+//! loosening it again overnight. Each schedule starts whenever its cron
+//! expression matches and stays active for `duration_mins`; while active, its
+//! overrides take the place of the settings in the config file, later
+//! schedules winning over earlier ones. Configs are read with the schedules
+//! active at the time, and `ConfigWatcher::spawn_scheduler` reloads the config
+//! as schedules start and end, logging each transition and the settings it
+//! changed.
+
+use chrono::{DateTime, Datelike, Duration, DurationRound, Timelike, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+/// Longest a schedule may stay active after it starts
+pub const MAX_DURATION_MINS: u64 = 7 * 24 * 60;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ConfigSchedule {
+    pub name: String,
+    /// When the schedule starts: `minute hour day-of-month month day-of-week`, in UTC
+    pub cron: String,
+    /// How long the overrides stay applied after each start
+    pub duration_mins: u64,
+    /// Setting values while active, by key as in the config file
+    pub overrides: BTreeMap<String, String>,
+}
+
+impl ConfigSchedule {
+    /// Whether the schedule started within `duration_mins` before `now`
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        let Ok(cron) = Cron::parse(&self.cron) else {
+            return false;
+        };
+        let Ok(minute) = now.duration_trunc(Duration::minutes(1)) else {
+            return false;
+        };
+        (0..self.duration_mins.min(MAX_DURATION_MINS) as i64)
+            .any(|ago| cron.matches(minute - Duration::minutes(ago)))
+    }
+}
+
+/// Names of the schedules active at `now`, in the order they apply
+pub fn active(schedules: &[ConfigSchedule], now: DateTime<Utc>) -> Vec<String> {
+    schedules
+        .iter()
+        .filter(|schedule| schedule.is_active(now))
+        .map(|schedule| schedule.name.clone())
+        .collect()
+}
+
+/// The overrides of the schedules active at `now`, later schedules winning
+pub fn overrides(schedules: &[ConfigSchedule], now: DateTime<Utc>) -> BTreeMap<String, String> {
+    schedules
+        .iter()
+        .filter(|schedule| schedule.is_active(now))
+        .flat_map(|schedule| schedule.overrides.clone())
+        .collect()
+}
+
+/// Reject schedules that could never apply, naming each
+pub fn validate(schedules: &[ConfigSchedule], errors: &mut Vec<String>) {
+    let mut names = HashSet::new();
+    for schedule in schedules {
+        let name = &schedule.name;
+        if name.is_empty() {
+            errors.push("config schedule names must not be empty".to_string());
+        } else if !names.insert(name) {
+            errors.push(format!("config schedule {} is defined twice", name));
+        }
+        if let Err(e) = Cron::parse(&schedule.cron) {
+            errors.push(format!("config schedule {} cron {:?}: {}", name, schedule.cron, e));
+        }
+        if !(1..=MAX_DURATION_MINS).contains(&schedule.duration_mins) {
+            errors.push(format!(
+                "config schedule {} duration must be in [1, {}] minutes",
+                name, MAX_DURATION_MINS
+            ));
+        }
+        if schedule.overrides.contains_key("CONFIG_SCHEDULES") {
+            errors.push(format!("config schedule {} must not override schedules", name));
+        }
+    }
+}
+
+/// A parsed cron expression; days match on day of month or day of week when
+/// both are restricted, as in crontab
+#[derive(Clone, Debug, PartialEq)]
+struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    fn parse(expression: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err("expected 5 fields".to_string());
+        };
+        // Sunday is 0 or 7
+        let weekday_mask = field(weekdays, 0, 7)?;
+        Ok(Self {
+            minutes: field(minutes, 0, 59)?,
+            hours: field(hours, 0, 23)?,
+            days: field(days, 1, 31)?,
+            months: field(months, 1, 12)?,
+            weekdays: (weekday_mask | weekday_mask >> 7) & 0x7f,
+            any_day: days == "*",
+            any_weekday: weekdays == "*",
+        })
+    }
+
+    fn matches(&self, time: DateTime<Utc>) -> bool {
+        let day = self.days >> time.day() & 1 == 1;
+        let weekday = self.weekdays >> time.weekday().num_days_from_sunday() & 1 == 1;
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+        self.minutes >> time.minute() & 1 == 1
+            && self.hours >> time.hour() & 1 == 1
+            && self.months >> time.month() & 1 == 1
+            && day_matches
+    }
+}
+
+/// Bitmask of the values a field matches: `*`, `5`, `1-5`, `*/15`, `0-30/10`
+/// or a comma-separated list of them
+fn field(spec: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut mask = 0;
+    for part in spec.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse().map_err(|_| bad(part))?),
+            None => (part, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (value(start, part)?, value(end, part)?),
+            None => (value(range, part)?, value(range, part)?),
+        };
+        if step == 0 || start < min || end > max || start > end {
+            return Err(bad(part));
+        }
+        for v in (start..=end).step_by(step) {
+            mask |= 1 << v;
+        }
+    }
+    Ok(mask)
+}
+
+fn value(s: &str, part: &str) -> Result<u32, String> {
+    s.parse().map_err(|_| bad(part))
+}
+
+fn bad(part: &str) -> String {
+    format!("invalid field {:?}", part)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        // 2026-03-07 is a Saturday
+        Utc.with_ymd_and_hms(2026, 3, day, hour, minute, 30).unwrap()
+    }
+
+    fn schedule(name: &str, cron: &str, duration_mins: u64, key: &str) -> ConfigSchedule {
+        ConfigSchedule {
+            name: name.to_string(),
+            cron: cron.to_string(),
+            duration_mins,
+            overrides: [(key.to_string(), name.to_string())].into(),
+        }
+    }
+
+    #[test]
+    fn test_schedules_are_active_for_their_duration_after_each_start() {
+        let live = schedule("live_event", "0 18 * * 6", 180, "ENABLE_DIVERSITY_BOOST");
+        assert!(!live.is_active(at(7, 17, 59)));
+        assert!(live.is_active(at(7, 18, 0)));
+        assert!(live.is_active(at(7, 20, 59)));
+        assert!(!live.is_active(at(7, 21, 0)));
+        assert!(!live.is_active(at(8, 18, 0)), "Sunday");
+
+        // Spans midnight
+        let overnight = schedule("overnight", "30 23 * * *", 60, "ENABLE_DIVERSITY_BOOST");
+        assert!(overnight.is_active(at(8, 0, 15)));
+        assert!(!overnight.is_active(at(8, 0, 30)));
+
+        let schedules = [live, overnight];
+        assert_eq!(active(&schedules, at(7, 23, 45)), ["overnight"]);
+        let both = [schedule("a", "* * * * *", 1, "K"), schedule("b", "*/15 * * * *", 1, "K")];
+        assert_eq!(overrides(&both, at(7, 0, 15))["K"], "b");
+        assert_eq!(overrides(&both, at(7, 0, 16))["K"], "a");
+    }
+
+    #[test]
+    fn test_cron_fields() {
+        let cron = Cron::parse("0-30/10 9,17 1 * 0").unwrap();
+        assert_eq!(cron.minutes, 1 | 1 << 10 | 1 << 20 | 1 << 30);
+        assert!(cron.matches(at(1, 9, 20)), "first of the month");
+        assert!(cron.matches(at(8, 17, 0)), "Sunday");
+        assert!(!cron.matches(at(7, 17, 0)));
+        assert_eq!(Cron::parse("* * * * 7").unwrap().weekdays, 1);
+
+        let mut errors = Vec::new();
+        let invalid = [
+            schedule("a", "60 * * * *", 10, "K"),
+            schedule("a", "* * *", 0, "CONFIG_SCHEDULES"),
+        ];
+        validate(&invalid, &mut errors);
+        assert_eq!(
+            errors,
+            [
+                "config schedule a cron \"60 * * * *\": invalid field \"60\"",
+                "config schedule a is defined twice",
+                "config schedule a cron \"* * *\": expected 5 fields",
+                "config schedule a duration must be in [1, 10080] minutes",
+                "config schedule a must not override schedules",
+            ]
+        );
+    }
+}
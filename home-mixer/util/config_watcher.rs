@@ -0,0 +1,307 @@
+//! Config reloads
+//!
+//! Holds the config requests are served with and swaps in a freshly read one
+//! on SIGHUP or `POST /admin/config/reload`. A reloaded config that fails
+//! validation is rejected and the current one kept. Each applied reload bumps
+//! the config version and logs every setting it changed; the version is
+//! exported as the `config_version` metric and carried by every request's
+//! span, so the settings that served a request can be told from its logs.
+//! Settings read per request, such as rollouts, cohorts and the diversity
+//! boost, take effect at once; those used to build components at startup,
+//! such as cache sizes, still need a restart. Reloads also apply and revert
+//! `CONFIG_SCHEDULES` overrides as schedules start and end.
+
+use crate::config::Config;
+use crate::util::config_schedule;
+use arc_swap::ArcSwap;
+use log::{error, info};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// One config and when it was applied
+#[derive(Clone, Debug, Serialize)]
+pub struct VersionedConfig {
+    /// 1 for the config read at startup, plus one per applied reload
+    pub version: u64,
+    pub applied_at_ms: i64,
+    #[serde(serialize_with = "serialize_config")]
+    pub config: Arc<Config>,
+}
+
+fn serialize_config<S: serde::Serializer>(config: &Arc<Config>, s: S) -> Result<S::Ok, S::Error> {
+    config.as_ref().serialize(s)
+}
+
+/// A setting a reload changed, keyed by its path in the config, e.g.
+/// `features.caching_rollout_percent`
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ConfigChange {
+    pub key: String,
+    pub old: serde_json::Value,
+    pub new: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReloadOutcome {
+    pub version: u64,
+    /// Empty when the config read was the one in use
+    pub changes: Vec<ConfigChange>,
+}
+
+pub struct ConfigWatcher {
+    current: ArcSwap<VersionedConfig>,
+    /// Held while reloading, so concurrent reloads apply in turn
+    reloading: Mutex<()>,
+    applied: AtomicU64,
+    rejected: AtomicU64,
+    /// Reload with `Config::load_strict`
+    strict: bool,
+}
+
+impl ConfigWatcher {
+    pub fn new(config: Config) -> Self {
+        Self {
+            current: ArcSwap::from_pointee(VersionedConfig {
+                version: 1,
+                applied_at_ms: chrono::Utc::now().timestamp_millis(),
+                config: Arc::new(config),
+            }),
+            reloading: Mutex::new(()),
+            applied: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            strict: false,
+        }
+    }
+
+    /// Reject reloaded configs with settings no component reads, as at a
+    /// strict startup
+    pub fn with_strict_loading(self, strict: bool) -> Self {
+        Self { strict, ..self }
+    }
+
+    pub fn current(&self) -> Arc<VersionedConfig> {
+        self.current.load_full()
+    }
+
+    /// The config in use
+    pub fn config(&self) -> Arc<Config> {
+        self.current.load().config.clone()
+    }
+
+    pub fn version(&self) -> u64 {
+        self.current.load().version
+    }
+
+    /// Read the config again, and use it if it is valid
+    pub fn reload(&self) -> Result<ReloadOutcome, String> {
+        self.apply(if self.strict {
+            Config::load_strict()
+        } else {
+            Config::load()
+        })
+    }
+
+    /// Use `loaded` if it was read and is valid, logging what it changes;
+    /// `Config::load` validates already, but configs built otherwise may not be
+    pub fn apply(&self, loaded: Result<Config, String>) -> Result<ReloadOutcome, String> {
+        let _reloading = self.reloading.lock().unwrap();
+        let config = match loaded.and_then(|config| config.validate().map(|()| config)) {
+            Ok(config) => config,
+            Err(e) => {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                error!("Rejected config reload, keeping version {}: {}", self.version(), e);
+                return Err(e);
+            },
+        };
+        let current = self.current();
+        let changes = diff(&current.config, &config);
+        if changes.is_empty() {
+            info!("Config reloaded unchanged at version {}", current.version);
+            return Ok(ReloadOutcome {
+                version: current.version,
+                changes,
+            });
+        }
+        let version = current.version + 1;
+        for change in &changes {
+            info!("Config version {}: {} {} -> {}", version, change.key, change.old, change.new);
+        }
+        self.current.store(Arc::new(VersionedConfig {
+            version,
+            applied_at_ms: chrono::Utc::now().timestamp_millis(),
+            config: Arc::new(config),
+        }));
+        self.applied.fetch_add(1, Ordering::Relaxed);
+        Ok(ReloadOutcome { version, changes })
+    }
+
+    /// Reload on every SIGHUP
+    #[cfg(unix)]
+    pub fn spawn_sighup_reloader(self: Arc<Self>) -> std::io::Result<()> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangups = signal(SignalKind::hangup())?;
+        tokio::spawn(async move {
+            while hangups.recv().await.is_some() {
+                info!("Received SIGHUP, reloading config");
+                // Rejections are logged
+                let _ = self.reload();
+            }
+        });
+        Ok(())
+    }
+
+    /// Reload whenever a `CONFIG_SCHEDULES` entry starts or ends, checking at
+    /// the start of every minute
+    pub fn spawn_scheduler(self: Arc<Self>) {
+        tokio::spawn(async move {
+            loop {
+                let into_minute = chrono::Utc::now().timestamp_millis().rem_euclid(60_000);
+                let until_next = std::time::Duration::from_millis((60_000 - into_minute) as u64);
+                tokio::time::sleep(until_next).await;
+                if self.schedules_changed(chrono::Utc::now()) {
+                    // Rejections are logged
+                    let _ = self.reload();
+                }
+            }
+        });
+    }
+
+    /// Whether the schedules active at `now` aren't those the config in use
+    /// was read with, logging each that started or ended
+    pub fn schedules_changed(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        let config = self.config();
+        let active = config_schedule::active(&config.schedules, now);
+        for name in active.iter().filter(|name| !config.active_schedules.contains(name)) {
+            info!("Config schedule {} started at {}", name, now);
+        }
+        for name in config.active_schedules.iter().filter(|name| !active.contains(name)) {
+            info!("Config schedule {} ended at {}", name, now);
+        }
+        active != config.active_schedules
+    }
+
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# HELP config_version Version of the config in use");
+        let _ = writeln!(out, "# TYPE config_version gauge");
+        let _ = writeln!(out, "config_version {}", self.version());
+        let _ = writeln!(out, "# HELP config_reloads_total Config reloads by result");
+        let _ = writeln!(out, "# TYPE config_reloads_total counter");
+        for (result, count) in [("applied", &self.applied), ("rejected", &self.rejected)] {
+            let count = count.load(Ordering::Relaxed);
+            let _ = writeln!(out, "config_reloads_total{{result=\"{}\"}} {}", result, count);
+        }
+        out
+    }
+}
+
+/// Settings that differ between `old` and `new`, by key. Secrets aren't
+/// serialized, so they never show up.
+pub fn diff(old: &Config, new: &Config) -> Vec<ConfigChange> {
+    let mut old_settings = BTreeMap::new();
+    let mut new_settings = BTreeMap::new();
+    flatten("", serde_json::to_value(old).unwrap_or_default(), &mut old_settings);
+    flatten("", serde_json::to_value(new).unwrap_or_default(), &mut new_settings);
+
+    let mut keys: Vec<&String> = old_settings.keys().chain(new_settings.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    keys.into_iter()
+        .filter_map(|key| {
+            let old = old_settings.get(key).cloned().unwrap_or_default();
+            let new = new_settings.get(key).cloned().unwrap_or_default();
+            (old != new).then(|| ConfigChange {
+                key: key.clone(),
+                old,
+                new,
+            })
+        })
+        .collect()
+}
+
+/// Leaves of `value` by dotted path; lists are leaves
+fn flatten(prefix: &str, value: serde_json::Value, out: &mut BTreeMap<String, serde_json::Value>) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            for (key, value) in fields {
+                let path = if prefix.is_empty() { key } else { format!("{}.{}", prefix, key) };
+                flatten(&path, value, out);
+            }
+        },
+        leaf => {
+            out.insert(prefix.to_string(), leaf);
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reload_swaps_valid_configs_and_bumps_the_version() {
+        let watcher = ConfigWatcher::new(Config::default());
+        let mut config = Config::default();
+        config.features.caching_rollout_percent = 20;
+        config.safety.enable_diversity_boost = true;
+
+        let outcome = watcher.apply(Ok(config.clone())).unwrap();
+        assert_eq!(outcome.version, 2);
+        let keys: Vec<_> = outcome.changes.iter().map(|c| c.key.as_str()).collect();
+        assert_eq!(keys, ["features.caching_rollout_percent", "safety.enable_diversity_boost"]);
+        assert_eq!(outcome.changes[0].old, serde_json::json!(0));
+        assert_eq!(outcome.changes[0].new, serde_json::json!(20));
+        assert_eq!(watcher.config().features.caching_rollout_percent, 20);
+
+        // Unchanged: same version
+        assert_eq!(watcher.apply(Ok(config.clone())).unwrap().version, 2);
+
+        config.features.caching_rollout_percent = 120;
+        assert!(watcher.apply(Ok(config)).is_err());
+        assert!(watcher.apply(Err("unreadable".to_string())).is_err());
+        assert_eq!(watcher.version(), 2);
+        assert_eq!(watcher.config().features.caching_rollout_percent, 20);
+        let metrics = watcher.to_prometheus();
+        assert!(metrics.contains("config_version 2\n"));
+        assert!(metrics.contains("config_reloads_total{result=\"rejected\"} 2\n"));
+    }
+
+    #[test]
+    fn test_schedules_apply_their_overrides_while_active() {
+        use crate::config::ConfigSource;
+        use chrono::TimeZone;
+
+        let source = ConfigSource::parse(concat!(
+            "ENABLE_DIVERSITY_BOOST=false\n",
+            r#"CONFIG_SCHEDULES=[{"name": "live_event", "cron": "0 18 * * 6","#,
+            r#" "duration_mins": 180, "overrides": {"ENABLE_DIVERSITY_BOOST": "true"}}]"#,
+        ))
+        .unwrap();
+        // 2026-03-07 is a Saturday
+        let before = chrono::Utc.with_ymd_and_hms(2026, 3, 7, 17, 59, 0).unwrap();
+        let during = chrono::Utc.with_ymd_and_hms(2026, 3, 7, 18, 30, 0).unwrap();
+        let after = chrono::Utc.with_ymd_and_hms(2026, 3, 7, 21, 0, 0).unwrap();
+
+        let config = Config::from_source_at(&source, before);
+        assert!(!config.safety.enable_diversity_boost);
+        assert!(config.active_schedules.is_empty());
+        let scheduled = Config::from_source_at(&source, during);
+        assert!(scheduled.safety.enable_diversity_boost);
+        assert_eq!(scheduled.active_schedules, ["live_event"]);
+        assert!(source.invalid().is_empty());
+
+        let watcher = ConfigWatcher::new(config.clone());
+        assert!(!watcher.schedules_changed(before));
+        assert!(watcher.schedules_changed(during));
+        let outcome = watcher.apply(Ok(scheduled)).unwrap();
+        let keys: Vec<_> = outcome.changes.iter().map(|c| c.key.as_str()).collect();
+        assert_eq!(keys, ["active_schedules", "safety.enable_diversity_boost"]);
+        assert!(!watcher.schedules_changed(during));
+        assert!(watcher.schedules_changed(after));
+        assert_eq!(watcher.apply(Ok(config)).unwrap().version, 3);
+    }
+}
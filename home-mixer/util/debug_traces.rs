@@ -0,0 +1,171 @@
+//! Per-user debug traces
+//!
+//! Support can add a user to the traced list through the admin HTTP API, after
+//! which each of their requests records the provenance of every candidate,
+//! including the ones filtered out, as a debug request would. The traces go
+//! into a ring buffer shared by all traced users, so the oldest are dropped
+//! first and memory stays bounded however long someone stays on the list.
+//! Responses only carry provenance when the client asked for it.
+
+use crate::config::LocaleParams;
+use crate::proto;
+use candidate_pipeline::provenance::Provenance;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, RwLock};
+
+/// A user whose requests are traced
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TracedUser {
+    pub user_id: u64,
+    pub added_at_ms: i64,
+    /// Tracing stops once this time has passed. `None` means no expiry.
+    pub expires_at_ms: Option<i64>,
+}
+
+impl TracedUser {
+    fn is_expired(&self, now_ms: i64) -> bool {
+        self.expires_at_ms.is_some_and(|expires| expires <= now_ms)
+    }
+}
+
+/// Everything recorded for one traced request
+#[derive(Clone, Debug, Serialize)]
+pub struct DebugTrace {
+    pub request_id: String,
+    pub user_id: u64,
+    pub captured_at_ms: i64,
+    /// Posts returned, in order
+    pub selected: Vec<u64>,
+    /// How every candidate fared, selected or not, by post id
+    pub provenance: HashMap<u64, Provenance>,
+    pub stage_errors: Vec<proto::StageError>,
+    pub partial: bool,
+    /// Locale overrides the request was served with
+    pub locale_params: LocaleParams,
+}
+
+pub struct DebugTraceStore {
+    users: RwLock<HashMap<u64, TracedUser>>,
+    /// Most traces kept, across users
+    capacity: usize,
+    traces: Mutex<VecDeque<DebugTrace>>,
+}
+
+impl DebugTraceStore {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            users: RwLock::new(HashMap::new()),
+            capacity,
+            traces: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Trace `user_id`'s requests, for `ttl_secs` when given
+    pub fn add_user(&self, user_id: u64, ttl_secs: Option<u64>) -> TracedUser {
+        let now = now_ms();
+        let user = TracedUser {
+            user_id,
+            added_at_ms: now,
+            expires_at_ms: ttl_secs.map(|ttl| now + ttl as i64 * 1000),
+        };
+        self.users.write().unwrap().insert(user_id, user.clone());
+        user
+    }
+
+    /// Stop tracing `user_id` and drop their traces; false if they weren't traced
+    pub fn remove_user(&self, user_id: u64) -> bool {
+        self.traces.lock().unwrap().retain(|trace| trace.user_id != user_id);
+        self.users.write().unwrap().remove(&user_id).is_some()
+    }
+
+    /// Users being traced, by id
+    pub fn users(&self) -> Vec<TracedUser> {
+        let now = now_ms();
+        let mut users: Vec<_> = self
+            .users
+            .read()
+            .unwrap()
+            .values()
+            .filter(|user| !user.is_expired(now))
+            .cloned()
+            .collect();
+        users.sort_by_key(|user| user.user_id);
+        users
+    }
+
+    pub fn is_traced(&self, user_id: u64) -> bool {
+        self.users
+            .read()
+            .unwrap()
+            .get(&user_id)
+            .is_some_and(|user| !user.is_expired(now_ms()))
+    }
+
+    /// Keep `trace`, dropping the oldest once full
+    pub fn record(&self, trace: DebugTrace) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut traces = self.traces.lock().unwrap();
+        if traces.len() == self.capacity {
+            traces.pop_front();
+        }
+        traces.push_back(trace);
+    }
+
+    /// Traces kept for `user_id`, newest first
+    pub fn traces(&self, user_id: u64) -> Vec<DebugTrace> {
+        let traces = self.traces.lock().unwrap();
+        traces
+            .iter()
+            .rev()
+            .filter(|trace| trace.user_id == user_id)
+            .cloned()
+            .collect()
+    }
+}
+
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace(user_id: u64, request_id: &str) -> DebugTrace {
+        DebugTrace {
+            request_id: request_id.to_string(),
+            user_id,
+            captured_at_ms: 0,
+            selected: vec![],
+            provenance: HashMap::new(),
+            stage_errors: vec![],
+            partial: false,
+            locale_params: LocaleParams::default(),
+        }
+    }
+
+    #[test]
+    fn test_traces_are_kept_in_a_ring_shared_by_users() {
+        let store = DebugTraceStore::new(3);
+        store.add_user(1, None);
+        store.add_user(2, Some(0));
+        assert!(store.is_traced(1));
+        assert!(!store.is_traced(2), "expired right away");
+        assert!(!store.is_traced(3));
+
+        for (user, request) in [(1, "a"), (3, "b"), (1, "c"), (1, "d")] {
+            store.record(trace(user, request));
+        }
+        let ids: Vec<_> = store.traces(1).into_iter().map(|t| t.request_id).collect();
+        assert_eq!(ids, ["d", "c"], "newest first, oldest dropped");
+        assert_eq!(store.traces(3).len(), 1);
+
+        assert!(store.remove_user(1));
+        assert!(store.traces(1).is_empty());
+        assert!(!store.remove_user(1));
+        assert!(store.users().is_empty());
+    }
+}
@@ -0,0 +1,444 @@
+//! Decision sampling
+//!
+//! Records every filter verdict and scorer output for one request in
+//! `DECISION_SAMPLE_EVERY`, one row per candidate and decision, so the safety
+//! filters' precision and recall can be measured offline against labels. A
+//! sampled request records its provenance as a debug request would, and its
+//! decisions are published in the background once it is served. Rows go to
+//! `DECISION_SINK`: `kafka://{brokers}/{topic}` (with the `kafka` feature), or
+//! a directory Parquet files are written to (with the `parquet` feature).
+
+use crate::config::DecisionSamplingConfig;
+use candidate_pipeline::provenance::Provenance;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tonic::async_trait;
+
+/// What kind of component made a decision
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DecisionStage {
+    Filter,
+    Scorer,
+}
+
+impl DecisionStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DecisionStage::Filter => "filter",
+            DecisionStage::Scorer => "scorer",
+        }
+    }
+}
+
+/// One component's decision on one candidate of a sampled request
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Decision {
+    pub request_id: String,
+    pub user_id: i64,
+    pub sampled_at_ms: i64,
+    pub candidate_id: u64,
+    pub stage: DecisionStage,
+    pub component: String,
+    /// The filter's verdict; unset for scorers
+    pub removed: Option<bool>,
+    /// The selection score around the scorer; unset for filters
+    pub score_before: Option<f64>,
+    pub score_after: Option<f64>,
+    /// Whether the candidate was served
+    pub selected: bool,
+}
+
+/// The decisions `provenance` records, ordered by candidate and then by when
+/// they were made, filters before scorers
+pub fn decisions(
+    request_id: &str,
+    user_id: i64,
+    sampled_at_ms: i64,
+    provenance: &HashMap<u64, Provenance>,
+    selected: &[u64],
+) -> Vec<Decision> {
+    let selected: HashSet<u64> = selected.iter().copied().collect();
+    let mut candidate_ids: Vec<u64> = provenance.keys().copied().collect();
+    candidate_ids.sort_unstable();
+    let mut decisions = Vec::new();
+    for candidate_id in candidate_ids {
+        let record = &provenance[&candidate_id];
+        let decision = |stage, component: &str| Decision {
+            request_id: request_id.to_string(),
+            user_id,
+            sampled_at_ms,
+            candidate_id,
+            stage,
+            component: component.to_string(),
+            removed: None,
+            score_before: None,
+            score_after: None,
+            selected: selected.contains(&candidate_id),
+        };
+        for step in &record.filters {
+            decisions.push(Decision {
+                removed: Some(step.removed),
+                ..decision(DecisionStage::Filter, &step.filter)
+            });
+        }
+        for step in &record.scores {
+            decisions.push(Decision {
+                score_before: Some(step.before),
+                score_after: Some(step.after),
+                ..decision(DecisionStage::Scorer, &step.scorer)
+            });
+        }
+    }
+    decisions
+}
+
+/// Where sampled decisions are published
+#[async_trait]
+pub trait DecisionSink: Send + Sync {
+    async fn publish(&self, decisions: &[Decision]) -> Result<(), String>;
+}
+
+/// Sink named by `spec`: `kafka://{brokers}/{topic}`, or a directory to write
+/// Parquet files to
+pub fn open_decision_sink(spec: &str) -> Result<Arc<dyn DecisionSink>, String> {
+    match spec.strip_prefix("kafka://") {
+        #[cfg(feature = "kafka")]
+        Some(target) => {
+            let (brokers, topic) = target
+                .rsplit_once('/')
+                .ok_or_else(|| format!("{} is not kafka://brokers/topic", spec))?;
+            let sink = super::kafka_decision_sink::KafkaDecisionSink::open(brokers, topic)?;
+            Ok(Arc::new(sink))
+        },
+        #[cfg(not(feature = "kafka"))]
+        Some(_) => Err("home-mixer was built without kafka support".to_string()),
+        #[cfg(feature = "parquet")]
+        None => {
+            let dir = spec.strip_prefix("file://").unwrap_or(spec);
+            Ok(Arc::new(parquet_sink::ParquetDecisionSink::open(dir)?))
+        },
+        #[cfg(not(feature = "parquet"))]
+        None => Err("home-mixer was built without parquet support".to_string()),
+    }
+}
+
+/// Picks the requests to sample and publishes their decisions
+pub struct DecisionSampler {
+    sample_every: u64,
+    requests: AtomicU64,
+    sink: Arc<dyn DecisionSink>,
+}
+
+impl DecisionSampler {
+    /// Sample one request in `sample_every`; none when it is 0
+    pub fn new(sample_every: u64, sink: Arc<dyn DecisionSink>) -> Self {
+        Self {
+            sample_every,
+            requests: AtomicU64::new(0),
+            sink,
+        }
+    }
+
+    /// The sampler `config` describes, if it names a sink that opens
+    pub fn from_config(config: &DecisionSamplingConfig) -> Option<Self> {
+        let spec = config.sink.as_deref()?;
+        match open_decision_sink(spec) {
+            Ok(sink) => Some(Self::new(config.sample_every, sink)),
+            Err(e) => {
+                warn!("Not sampling filter and scorer decisions to {}: {}", spec, e);
+                None
+            },
+        }
+    }
+
+    /// Whether to sample the request being served
+    pub fn sample(&self) -> bool {
+        let request = self.requests.fetch_add(1, Ordering::Relaxed);
+        self.sample_every > 0 && request.is_multiple_of(self.sample_every)
+    }
+
+    /// Publish the decisions of a sampled request without waiting for the sink
+    pub fn publish(&self, decisions: Vec<Decision>) {
+        if decisions.is_empty() {
+            return;
+        }
+        let sink = self.sink.clone();
+        tokio::spawn(async move {
+            if let Err(e) = sink.publish(&decisions).await {
+                warn!(
+                    "request_id={} failed to publish sampled decisions: {}",
+                    decisions[0].request_id, e
+                );
+            }
+        });
+    }
+}
+
+#[cfg(feature = "parquet")]
+pub mod parquet_sink {
+    //! Sampled decisions as Parquet files, one row per decision. Rows are
+    //! buffered and written out as a file once `ROWS_PER_FILE` have
+    //! accumulated, or when the sink is dropped, since a Parquet file can't be
+    //! read before it is complete.
+
+    use super::{Decision, DecisionSink};
+    use parquet::basic::Compression;
+    use parquet::data_type::{
+        BoolType, ByteArray, ByteArrayType, DataType, DoubleType, Int64Type,
+    };
+    use parquet::errors::ParquetError;
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::{SerializedFileWriter, SerializedRowGroupWriter};
+    use parquet::schema::parser::parse_message_type;
+    use std::fs::File;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+    use tonic::async_trait;
+
+    /// Rows per file; a sampled request has a few per candidate
+    pub const ROWS_PER_FILE: usize = 100_000;
+
+    const SCHEMA: &str = "
+        message decision {
+            REQUIRED BYTE_ARRAY request_id (UTF8);
+            REQUIRED INT64 user_id;
+            REQUIRED INT64 sampled_at_ms;
+            REQUIRED INT64 candidate_id (INTEGER(64, false));
+            REQUIRED BYTE_ARRAY stage (UTF8);
+            REQUIRED BYTE_ARRAY component (UTF8);
+            OPTIONAL BOOLEAN removed;
+            OPTIONAL DOUBLE score_before;
+            OPTIONAL DOUBLE score_after;
+            REQUIRED BOOLEAN selected;
+        }
+    ";
+
+    /// Writes `decisions-{sampled_at_ms}-{n}.parquet` files to a directory
+    pub struct ParquetDecisionSink {
+        dir: PathBuf,
+        rows_per_file: usize,
+        buffer: Mutex<Vec<Decision>>,
+        files: AtomicU64,
+    }
+
+    impl ParquetDecisionSink {
+        pub fn open(dir: impl AsRef<Path>) -> Result<Self, String> {
+            Self::with_rows_per_file(dir, ROWS_PER_FILE)
+        }
+
+        pub fn with_rows_per_file(dir: impl AsRef<Path>, rows: usize) -> Result<Self, String> {
+            let dir = dir.as_ref();
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+            Ok(Self {
+                dir: dir.to_path_buf(),
+                rows_per_file: rows.max(1),
+                buffer: Mutex::new(Vec::new()),
+                files: AtomicU64::new(0),
+            })
+        }
+
+        /// Write out the buffered rows, if any, as a file
+        pub fn flush(&self) -> Result<(), String> {
+            let rows = std::mem::take(&mut *self.buffer.lock().unwrap());
+            if rows.is_empty() {
+                return Ok(());
+            }
+            write_parquet(&self.next_path(&rows), &rows)
+        }
+
+        /// Where to write `rows`, named after when the first was sampled
+        fn next_path(&self, rows: &[Decision]) -> PathBuf {
+            let n = self.files.fetch_add(1, Ordering::Relaxed);
+            let sampled_at_ms = rows.first().map_or(0, |row| row.sampled_at_ms);
+            self.dir.join(format!("decisions-{}-{}.parquet", sampled_at_ms, n))
+        }
+    }
+
+    #[async_trait]
+    impl DecisionSink for ParquetDecisionSink {
+        async fn publish(&self, decisions: &[Decision]) -> Result<(), String> {
+            let full = {
+                let mut buffer = self.buffer.lock().unwrap();
+                buffer.extend_from_slice(decisions);
+                if buffer.len() < self.rows_per_file {
+                    return Ok(());
+                }
+                std::mem::take(&mut *buffer)
+            };
+            let path = self.next_path(&full);
+            tokio::task::spawn_blocking(move || write_parquet(&path, &full))
+                .await
+                .map_err(|e| e.to_string())?
+        }
+    }
+
+    impl Drop for ParquetDecisionSink {
+        fn drop(&mut self) {
+            if let Err(e) = self.flush() {
+                log::warn!("Failed to write sampled decisions: {}", e);
+            }
+        }
+    }
+
+    fn write_parquet(path: &Path, rows: &[Decision]) -> Result<(), String> {
+        write_row_group(path, rows).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn write_row_group(path: &Path, rows: &[Decision]) -> Result<(), ParquetError> {
+        let schema = Arc::new(parse_message_type(SCHEMA)?);
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let file = File::create(path)?;
+        let mut writer = SerializedFileWriter::new(file, schema, Arc::new(properties))?;
+        let mut row_group = writer.next_row_group()?;
+
+        let strings = |value: fn(&Decision) -> &str| -> Vec<ByteArray> {
+            rows.iter().map(|row| ByteArray::from(value(row))).collect()
+        };
+        let ints = |value: fn(&Decision) -> i64| -> Vec<i64> { rows.iter().map(value).collect() };
+        // Values of the rows with one, and which rows have one
+        fn optional<T>(rows: &[Decision], value: fn(&Decision) -> Option<T>) -> (Vec<T>, Vec<i16>) {
+            let values = rows.iter().filter_map(value).collect();
+            let levels = rows.iter().map(|row| value(row).is_some() as i16).collect();
+            (values, levels)
+        }
+
+        write_column::<ByteArrayType>(&mut row_group, &strings(|row| &row.request_id), None)?;
+        write_column::<Int64Type>(&mut row_group, &ints(|row| row.user_id), None)?;
+        write_column::<Int64Type>(&mut row_group, &ints(|row| row.sampled_at_ms), None)?;
+        write_column::<Int64Type>(&mut row_group, &ints(|row| row.candidate_id as i64), None)?;
+        write_column::<ByteArrayType>(&mut row_group, &strings(|row| row.stage.as_str()), None)?;
+        write_column::<ByteArrayType>(&mut row_group, &strings(|row| &row.component), None)?;
+        let (removed, levels) = optional(rows, |row| row.removed);
+        write_column::<BoolType>(&mut row_group, &removed, Some(&levels))?;
+        let (before, levels) = optional(rows, |row| row.score_before);
+        write_column::<DoubleType>(&mut row_group, &before, Some(&levels))?;
+        let (after, levels) = optional(rows, |row| row.score_after);
+        write_column::<DoubleType>(&mut row_group, &after, Some(&levels))?;
+        let selected: Vec<bool> = rows.iter().map(|row| row.selected).collect();
+        write_column::<BoolType>(&mut row_group, &selected, None)?;
+
+        row_group.close()?;
+        writer.close()?;
+        Ok(())
+    }
+
+    /// Write the next column of `row_group`, which must be of type `T`
+    pub(crate) fn write_column<T: DataType>(
+        row_group: &mut SerializedRowGroupWriter<'_, File>,
+        values: &[T::T],
+        def_levels: Option<&[i16]>,
+    ) -> Result<(), ParquetError> {
+        let mut column = row_group
+            .next_column()?
+            .ok_or_else(|| ParquetError::General("more columns than the schema".to_string()))?;
+        column.typed::<T>().write_batch(values, def_levels, None)?;
+        column.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candidate_pipeline::provenance::{FilterStep, ScoreStep};
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct RecordingSink {
+        published: Mutex<Vec<Decision>>,
+    }
+
+    #[async_trait]
+    impl DecisionSink for RecordingSink {
+        async fn publish(&self, decisions: &[Decision]) -> Result<(), String> {
+            self.published.lock().unwrap().extend_from_slice(decisions);
+            Ok(())
+        }
+    }
+
+    fn provenance() -> HashMap<u64, Provenance> {
+        let filter = |name: &str, removed| FilterStep {
+            filter: name.to_string(),
+            removed,
+        };
+        let kept = Provenance {
+            filters: vec![filter("NsfwFilter", false)],
+            scores: vec![ScoreStep {
+                scorer: "WeightedScorer".to_string(),
+                before: 0.0,
+                after: 2.5,
+            }],
+            ..Default::default()
+        };
+        let removed = Provenance {
+            filters: vec![filter("NsfwFilter", true)],
+            ..Default::default()
+        };
+        HashMap::from([(20, kept), (10, removed)])
+    }
+
+    #[test]
+    fn test_one_row_per_candidate_and_decision() {
+        let decisions = decisions("req-1", 7, 1_000, &provenance(), &[20]);
+        let rows: Vec<_> = decisions
+            .iter()
+            .map(|d| (d.candidate_id, d.stage, d.component.as_str(), d.removed, d.selected))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                (10, DecisionStage::Filter, "NsfwFilter", Some(true), false),
+                (20, DecisionStage::Filter, "NsfwFilter", Some(false), true),
+                (20, DecisionStage::Scorer, "WeightedScorer", None, true),
+            ]
+        );
+        assert_eq!((decisions[2].score_before, decisions[2].score_after), (Some(0.0), Some(2.5)));
+    }
+
+    #[tokio::test]
+    async fn test_one_request_in_n_is_sampled() {
+        let sink = Arc::new(RecordingSink::default());
+        let sampler = DecisionSampler::new(3, sink.clone());
+        let sampled: Vec<bool> = (0..6).map(|_| sampler.sample()).collect();
+        assert_eq!(sampled, [true, false, false, true, false, false]);
+        assert!(!DecisionSampler::new(0, sink.clone()).sample());
+
+        sampler.publish(decisions("req-1", 7, 1_000, &provenance(), &[20]));
+        tokio::task::yield_now().await;
+        assert_eq!(sink.published.lock().unwrap().len(), 3);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[tokio::test]
+    async fn test_decisions_are_written_as_parquet_files() {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+        use parquet_sink::ParquetDecisionSink;
+
+        let dir = std::env::temp_dir().join(format!("decisions_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let sink = ParquetDecisionSink::with_rows_per_file(&dir, 4).unwrap();
+        let rows = decisions("req-1", 7, 1_000, &provenance(), &[20]);
+        sink.publish(&rows).await.unwrap();
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0, "buffered until full");
+        sink.publish(&rows).await.unwrap();
+        drop(sink);
+
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().path()).collect();
+        let counts: Vec<i64> = files
+            .iter()
+            .map(|path| {
+                let file = std::fs::File::open(path).unwrap();
+                let reader = SerializedFileReader::new(file).unwrap();
+                reader.metadata().file_metadata().num_rows()
+            })
+            .collect();
+        assert_eq!(counts, [6], "written once full; nothing left to flush");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
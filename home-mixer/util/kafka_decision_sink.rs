@@ -0,0 +1,54 @@
+//! Kafka sink for sampled filter and scorer decisions
+//!
+//! Keys each record by request, so a request's decisions stay together on one
+//! partition.
+
+use std::time::Duration;
+
+use futures::future::join_all;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::ClientConfig;
+use tonic::async_trait;
+
+use super::decision_sampling::{Decision, DecisionSink};
+
+/// How long a record may wait in the producer queue before it fails
+const QUEUE_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct KafkaDecisionSink {
+    producer: FutureProducer,
+    topic: String,
+}
+
+impl KafkaDecisionSink {
+    pub fn open(brokers: &str, topic: &str) -> Result<Self, String> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("compression.type", "lz4")
+            .create()
+            .map_err(|e| format!("failed to create Kafka producer: {}", e))?;
+        Ok(Self {
+            producer,
+            topic: topic.to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl DecisionSink for KafkaDecisionSink {
+    async fn publish(&self, decisions: &[Decision]) -> Result<(), String> {
+        let mut records = Vec::with_capacity(decisions.len());
+        for decision in decisions {
+            let payload = serde_json::to_vec(decision).map_err(|e| e.to_string())?;
+            records.push((decision.request_id.as_str(), payload));
+        }
+        let sends = records.iter().map(|(key, payload)| {
+            let record = FutureRecord::to(&self.topic).key(*key).payload(payload);
+            self.producer.send(record, QUEUE_TIMEOUT)
+        });
+        for result in join_all(sends).await {
+            result.map_err(|(e, _)| format!("failed to publish to {}: {}", self.topic, e))?;
+        }
+        Ok(())
+    }
+}
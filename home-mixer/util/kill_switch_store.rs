@@ -0,0 +1,112 @@
+//! Kill switch persistence
+//!
+//! Backs the production pipeline's kill switches with `KILL_SWITCH_PATH`, so
+//! components switched off at `/admin/kill_switches` stay off across restarts,
+//! and switches off the components listed in `KILL_SWITCHES` at startup.
+
+use candidate_pipeline::kill_switch::{KillSwitch, KillSwitches};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Reason recorded for components switched off by `KILL_SWITCHES`
+pub const ENV_REASON: &str = "KILL_SWITCHES";
+
+/// Kill switches, optionally written to a file on every change
+pub struct KillSwitchStore {
+    switches: Arc<KillSwitches>,
+    path: Option<PathBuf>,
+}
+
+impl KillSwitchStore {
+    /// In-memory store (nothing is persisted)
+    pub fn new(switches: Arc<KillSwitches>) -> Self {
+        Self { switches, path: None }
+    }
+
+    /// Switch off the components saved at `path`, none if the file does not
+    /// exist yet. Every subsequent change is written back to the same file.
+    pub fn load(switches: Arc<KillSwitches>, path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let saved = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice::<Vec<KillSwitch>>(&bytes)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        switches.replace(saved);
+        Ok(Self {
+            switches,
+            path: Some(path),
+        })
+    }
+
+    /// Switch off `components`, e.g. from `KILL_SWITCHES`, without persisting
+    /// them: they are switched off again on every start while configured
+    pub fn kill_configured(&self, components: &[String]) {
+        for component in components {
+            self.switches.kill(component, ENV_REASON);
+        }
+    }
+
+    /// Switch `component` off. Returns whether it was on.
+    pub fn kill(&self, component: &str, reason: &str) -> std::io::Result<bool> {
+        let killed = self.switches.kill(component, reason);
+        if killed {
+            self.persist()?;
+        }
+        Ok(killed)
+    }
+
+    /// Switch `component` back on. Returns whether it was off.
+    pub fn restore(&self, component: &str) -> std::io::Result<bool> {
+        let restored = self.switches.restore(component);
+        if restored {
+            self.persist()?;
+        }
+        Ok(restored)
+    }
+
+    pub fn switches(&self) -> Vec<KillSwitch> {
+        self.switches.switches()
+    }
+
+    fn persist(&self) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let saved: Vec<_> =
+            self.switches().into_iter().filter(|s| s.reason != ENV_REASON).collect();
+        let bytes = serde_json::to_vec_pretty(&saved)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        // Write to a sibling file and rename so a crash never leaves a torn file behind
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, bytes)?;
+        std::fs::rename(&tmp, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_switches_survive_a_restart_except_configured_ones() {
+        let path = std::env::temp_dir().join(format!("kill_switches_{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let store = KillSwitchStore::load(Arc::new(KillSwitches::new()), &path).unwrap();
+        store.kill_configured(&["ThunderSource".to_string()]);
+        assert!(store.kill("RecencyScorer", "scores spiking").unwrap());
+        assert!(store.kill("GeoFilter", "").unwrap());
+        assert!(store.restore("GeoFilter").unwrap());
+        assert!(!store.restore("GeoFilter").unwrap());
+
+        let switches = Arc::new(KillSwitches::new());
+        let restarted = KillSwitchStore::load(switches.clone(), &path).unwrap();
+        let killed: Vec<_> = restarted.switches().into_iter().map(|s| s.component).collect();
+        assert_eq!(killed, ["RecencyScorer"]);
+        assert_eq!(restarted.switches()[0].reason, "scores spiking");
+        assert!(switches.is_killed("RecencyScorer"));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
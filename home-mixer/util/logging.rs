@@ -0,0 +1,106 @@
+//! Logging
+//!
+//! Everything logged, through `log` or `tracing`, goes to one `tracing`
+//! subscriber that writes text or one JSON object per line. Lines logged
+//! inside a request's span carry that span's fields, so every line of a scored
+//! posts request names its `request_id` and `user_id`. Levels are `RUST_LOG`
+//! style directives such as `info,home_mixer::scorers=debug`, replaceable
+//! while running through `LogLevels`, which `/admin/log_levels` exposes.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tracing_subscriber::layer::{Layered, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Layer, Registry};
+
+/// The subscriber further layers, such as trace export, are added to
+pub type Filtered = Layered<reload::Layer<EnvFilter, Registry>, Registry>;
+
+pub type BoxedLayer = Box<dyn Layer<Filtered> + Send + Sync>;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    /// `json`, or text for anything else
+    pub fn parse(value: &str) -> Self {
+        if value.eq_ignore_ascii_case("json") {
+            LogFormat::Json
+        } else {
+            LogFormat::Text
+        }
+    }
+}
+
+/// The log levels of the running process
+#[derive(Clone)]
+pub struct LogLevels {
+    handle: reload::Handle<EnvFilter, Registry>,
+    directives: Arc<Mutex<String>>,
+}
+
+impl LogLevels {
+    pub fn current(&self) -> String {
+        self.directives.lock().unwrap().clone()
+    }
+
+    /// Replace every level with `directives`, leaving them as they were when
+    /// `directives` don't parse
+    pub fn set(&self, directives: &str) -> Result<(), String> {
+        let filter = EnvFilter::try_new(directives).map_err(|e| e.to_string())?;
+        self.handle.reload(filter).map_err(|e| e.to_string())?;
+        *self.directives.lock().unwrap() = directives.to_string();
+        Ok(())
+    }
+}
+
+/// Install the global subscriber, writing `format` at the levels of
+/// `directives`, plus `layers`
+pub fn init(
+    format: LogFormat,
+    directives: &str,
+    mut layers: Vec<BoxedLayer>,
+) -> Result<LogLevels, String> {
+    let filter = EnvFilter::try_new(directives).map_err(|e| e.to_string())?;
+    let (filter, handle) = reload::Layer::new(filter);
+    let output: BoxedLayer = match format {
+        LogFormat::Text => fmt::layer().boxed(),
+        LogFormat::Json => fmt::layer().json().boxed(),
+    };
+    layers.insert(0, output);
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(layers)
+        .try_init()
+        .map_err(|e| e.to_string())?;
+    Ok(LogLevels {
+        handle,
+        directives: Arc::new(Mutex::new(directives.to_string())),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels_are_replaced_only_by_valid_directives() {
+        let (_filter, handle) = reload::Layer::<EnvFilter, Registry>::new(EnvFilter::new("info"));
+        let levels = LogLevels {
+            handle,
+            directives: Arc::new(Mutex::new("info".to_string())),
+        };
+
+        levels.set("warn,home_mixer::scorers=debug").unwrap();
+        assert_eq!(levels.current(), "warn,home_mixer::scorers=debug");
+        assert!(levels.set("home_mixer=loud").is_err());
+        assert_eq!(levels.current(), "warn,home_mixer::scorers=debug");
+        assert_eq!(LogFormat::parse("JSON"), LogFormat::Json);
+    }
+}
@@ -0,0 +1,252 @@
+//! Metrics sinks
+//!
+//! Where the pipeline's metrics go besides `/metrics`. Prometheus scrapes that
+//! endpoint, so its sink has nothing to push; infrastructure that only speaks
+//! StatsD gets a `DogStatsdSink` instead, which sends what changed since the
+//! last flush over UDP, tagged by pipeline, stage and component (`scorer:…`,
+//! `filter:…`). Lines are packed into as few datagrams as fit, and lines that
+//! cannot be sent, because the socket is saturated or a line is too long for
+//! a datagram, are dropped and counted rather than retried.
+
+use candidate_pipeline::candidate_pipeline::PipelineStage;
+use candidate_pipeline::metrics::PipelineMetrics;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Largest datagram sent, leaving room for headers within a 1500 byte MTU
+pub const DEFAULT_MAX_PACKET_BYTES: usize = 1432;
+
+pub trait MetricsSink: Send + Sync {
+    /// Publish `metrics`, called every flush interval
+    fn flush(&self, metrics: &PipelineMetrics);
+}
+
+/// Sink named by `spec`: `prometheus`, or `dogstatsd://{host}:{port}`
+pub fn open_metrics_sink(spec: &str) -> Result<Arc<dyn MetricsSink>, String> {
+    match spec.strip_prefix("dogstatsd://") {
+        Some(addr) => Ok(Arc::new(DogStatsdSink::connect(addr)?)),
+        None if spec == "prometheus" => Ok(Arc::new(PrometheusSink)),
+        None => Err(format!("unknown metrics sink {}", spec)),
+    }
+}
+
+/// Flush `metrics` to `sink` every `interval`
+pub fn spawn_flusher(
+    sink: Arc<dyn MetricsSink>,
+    metrics: Arc<PipelineMetrics>,
+    interval: Duration,
+) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            sink.flush(&metrics);
+        }
+    });
+}
+
+/// Prometheus scrapes `/metrics`, so there is nothing to push
+pub struct PrometheusSink;
+
+impl MetricsSink for PrometheusSink {
+    fn flush(&self, _metrics: &PipelineMetrics) {}
+}
+
+/// Sends metrics in the DogStatsD format over UDP
+pub struct DogStatsdSink {
+    socket: UdpSocket,
+    max_packet_bytes: usize,
+    /// Totals at the last flush, by metric and tags, to send counters as
+    /// increments
+    last: Mutex<HashMap<String, u64>>,
+    dropped: AtomicU64,
+}
+
+impl DogStatsdSink {
+    pub fn connect(addr: &str) -> Result<Self, String> {
+        let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| e.to_string())?;
+        socket.connect(addr).map_err(|e| format!("{}: {}", addr, e))?;
+        socket.set_nonblocking(true).map_err(|e| e.to_string())?;
+        Ok(Self {
+            socket,
+            max_packet_bytes: DEFAULT_MAX_PACKET_BYTES,
+            last: Mutex::new(HashMap::new()),
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    pub fn with_max_packet_bytes(mut self, max_packet_bytes: usize) -> Self {
+        self.max_packet_bytes = max_packet_bytes;
+        self
+    }
+
+    /// Lines dropped since startup
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// DogStatsD lines for what changed in `metrics` since the last call
+    fn lines(&self, metrics: &PipelineMetrics) -> Vec<String> {
+        let pipeline = format!("pipeline:{}", metrics.pipeline());
+        let mut last = self.last.lock().unwrap();
+        let mut lines = Vec::new();
+        let mut counter = |name: &str, tags: String, total: u64| -> u64 {
+            let previous = last.insert(format!("{}|{}", name, tags), total).unwrap_or(0);
+            let delta = total.saturating_sub(previous);
+            if delta > 0 {
+                lines.push(format!("{}:{}|c|#{}", name, delta, tags));
+            }
+            delta
+        };
+
+        let mut latencies = Vec::new();
+        for (stage, stats) in metrics.stages() {
+            let tags = format!("{},stage:{}", pipeline, stage.as_str());
+            let requests = counter("pipeline_stage_requests", tags.clone(), stats.count);
+            counter("pipeline_stage_candidates_in", tags.clone(), stats.candidates_in);
+            counter("pipeline_stage_candidates_out", tags.clone(), stats.candidates_out);
+            let latency_us = stats.latency.as_micros() as u64;
+            let latency_us = counter("pipeline_stage_latency_us", tags.clone(), latency_us);
+            if requests > 0 {
+                let mean_ms = latency_us as f64 / 1000.0 / requests as f64;
+                latencies.push(format!("pipeline_stage_latency_ms:{:.3}|g|#{}", mean_ms, tags));
+            }
+        }
+        for (stage, component, kind, count) in metrics.error_counts() {
+            let tags = format!(
+                "{},stage:{},{}:{},kind:{}",
+                pipeline,
+                stage.as_str(),
+                component_tag(stage),
+                component,
+                kind.as_str()
+            );
+            counter("pipeline_component_errors", tags, count);
+        }
+        for (source, count) in metrics.source_timeout_counts() {
+            counter("pipeline_source_timeouts", format!("{},source:{}", pipeline, source), count);
+        }
+        lines.extend(latencies);
+        lines
+    }
+
+    /// Send `lines`, as many to a datagram as fit
+    fn send(&self, lines: &[String]) {
+        let mut packet = String::new();
+        for line in lines {
+            if line.len() > self.max_packet_bytes {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            if !packet.is_empty() && packet.len() + 1 + line.len() > self.max_packet_bytes {
+                self.send_packet(&packet);
+                packet.clear();
+            }
+            if !packet.is_empty() {
+                packet.push('\n');
+            }
+            packet.push_str(line);
+        }
+        if !packet.is_empty() {
+            self.send_packet(&packet);
+        }
+    }
+
+    fn send_packet(&self, packet: &str) {
+        if let Err(e) = self.socket.send(packet.as_bytes()) {
+            let lines = packet.lines().count() as u64;
+            self.dropped.fetch_add(lines, Ordering::Relaxed);
+            if e.kind() != ErrorKind::WouldBlock {
+                log::warn!("Failed to send {} metrics to dogstatsd: {}", lines, e);
+            }
+        }
+    }
+}
+
+impl MetricsSink for DogStatsdSink {
+    fn flush(&self, metrics: &PipelineMetrics) {
+        let dropped = self.dropped();
+        self.send(&self.lines(metrics));
+        let newly_dropped = self.dropped() - dropped;
+        if newly_dropped > 0 {
+            log::warn!("Dropped {} metrics sending to dogstatsd", newly_dropped);
+        }
+    }
+}
+
+/// Tag naming a failed component by what it is, e.g. `scorer`
+fn component_tag(stage: PipelineStage) -> &'static str {
+    match stage {
+        PipelineStage::QueryHydrator => "query_hydrator",
+        PipelineStage::Source => "source",
+        PipelineStage::Hydrator | PipelineStage::PostSelectionHydrator => "hydrator",
+        PipelineStage::Filter | PipelineStage::PostSelectionFilter => "filter",
+        PipelineStage::Scorer => "scorer",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candidate_pipeline::error::PipelineError;
+    use candidate_pipeline::error_policy::{ErrorOutcome, StageError};
+
+    fn receiver() -> (UdpSocket, String) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        let addr = socket.local_addr().unwrap().to_string();
+        (socket, addr)
+    }
+
+    fn receive(socket: &UdpSocket) -> String {
+        let mut buf = [0; 2048];
+        let len = socket.recv(&mut buf).unwrap();
+        String::from_utf8(buf[..len].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_dogstatsd_sends_increments_tagged_by_component() {
+        let (socket, addr) = receiver();
+        let sink = DogStatsdSink::connect(&addr).unwrap();
+        let metrics = PipelineMetrics::new("phoenix");
+        metrics.record_stage(PipelineStage::Filter, Duration::from_millis(4), 10, 6);
+        metrics.record_errors(&[StageError {
+            stage: PipelineStage::Scorer,
+            component: "WeightedScorer",
+            error: PipelineError::unavailable("model server"),
+            outcome: ErrorOutcome::Skipped,
+        }]);
+
+        sink.flush(&metrics);
+        let packet = receive(&socket);
+        let tags = "#pipeline:phoenix,stage:filter";
+        assert!(packet.contains(&format!("pipeline_stage_candidates_in:10|c|{}", tags)));
+        assert!(packet.contains(&format!("pipeline_stage_latency_ms:4.000|g|{}", tags)));
+        let errors = "pipeline_component_errors:1|c|#pipeline:phoenix,stage:scorer,\
+                      scorer:WeightedScorer,kind:unavailable";
+        assert!(packet.contains(errors));
+
+        metrics.record_stage(PipelineStage::Filter, Duration::from_millis(2), 5, 5);
+        sink.flush(&metrics);
+        let packet = receive(&socket);
+        assert!(packet.contains("pipeline_stage_candidates_in:5|c|"));
+        assert!(!packet.contains("pipeline_component_errors"));
+        assert_eq!(sink.dropped(), 0);
+    }
+
+    #[test]
+    fn test_lines_are_batched_into_packets_and_oversized_ones_dropped() {
+        let (socket, addr) = receiver();
+        let sink = DogStatsdSink::connect(&addr).unwrap().with_max_packet_bytes(12);
+        let lines = ["a:1|c", "b:2|c", "c:3|c", "much_too_long:4|c"].map(String::from);
+
+        sink.send(&lines);
+        assert_eq!(receive(&socket), "a:1|c\nb:2|c");
+        assert_eq!(receive(&socket), "c:3|c");
+        assert_eq!(sink.dropped(), 1);
+        assert!(open_metrics_sink("graphite://localhost").is_err());
+    }
+}
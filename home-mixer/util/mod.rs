@@ -1,5 +1,22 @@
 //! Utility modules
 
+pub mod admin_auth;
+pub mod alerting;
+pub mod config_schedule;
+pub mod config_watcher;
+pub mod debug_traces;
+pub mod decision_sampling;
+#[cfg(feature = "kafka")]
+pub mod kafka_decision_sink;
+pub mod kill_switch_store;
+pub mod logging;
+pub mod metrics_sink;
+#[cfg(feature = "otlp")]
+pub mod observability;
+#[cfg(feature = "profiling")]
+pub mod profiling;
 pub mod request_util;
 pub mod score_normalizer;
 pub mod snowflake;
+pub mod sticky_buckets;
+pub mod user_auth;
@@ -0,0 +1,142 @@
+//! OTLP export
+//!
+//! Pushes the spans the pipeline opens for each request and stage, and the
+//! pipeline's metrics, to an OpenTelemetry collector over OTLP/gRPC, so
+//! backends that ingest OTLP (Grafana Cloud, Datadog, Honeycomb) need no
+//! Prometheus scraper. The collector is the configured endpoint when one is
+//! given, else the standard `OTEL_EXPORTER_OTLP_ENDPOINT` variable, else
+//! `http://localhost:4317`. Only built with the `otlp` feature.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use candidate_pipeline::metrics::{PipelineMetrics, StageStats};
+use opentelemetry::metrics::MeterProvider;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{TonicExporterBuilder, WithExportConfig};
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use opentelemetry_sdk::trace::config;
+use opentelemetry_sdk::{runtime, Resource};
+use tracing_subscriber::Layer;
+
+use crate::util::logging::BoxedLayer;
+
+/// A per-stage counter: name, description and the total it reports
+type StageCounter = (&'static str, &'static str, fn(&StageStats) -> u64);
+
+fn resource(service: &str) -> Resource {
+    Resource::new(vec![KeyValue::new("service.name", service.to_string())])
+}
+
+fn exporter(endpoint: Option<&str>) -> TonicExporterBuilder {
+    let exporter = opentelemetry_otlp::new_exporter().tonic();
+    match endpoint {
+        Some(endpoint) => exporter.with_endpoint(endpoint),
+        None => exporter,
+    }
+}
+
+/// A layer exporting spans in batches as `service`, for `logging::init`; must
+/// be called inside the tokio runtime
+pub fn tracing_layer(service: &str, endpoint: Option<&str>) -> Result<BoxedLayer, String> {
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(exporter(endpoint))
+        .with_trace_config(config().with_resource(resource(service)))
+        .install_batch(runtime::Tokio)
+        .map_err(|e| e.to_string())?;
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer).boxed())
+}
+
+/// Export `metrics` as `service` every `interval`, named as on `/metrics` but
+/// with stage latency as a running sum; must be called inside the tokio
+/// runtime. Export stops when the returned provider is dropped.
+pub fn init_metrics(
+    service: &str,
+    endpoint: Option<&str>,
+    interval: Duration,
+    metrics: Arc<PipelineMetrics>,
+) -> Result<SdkMeterProvider, String> {
+    let provider = opentelemetry_otlp::new_pipeline()
+        .metrics(runtime::Tokio)
+        .with_exporter(exporter(endpoint))
+        .with_resource(resource(service))
+        .with_period(interval)
+        .build()
+        .map_err(|e| e.to_string())?;
+    register_pipeline_metrics(&provider, metrics);
+    Ok(provider)
+}
+
+/// Observe the totals of `metrics` each time the provider collects
+fn register_pipeline_metrics(provider: &SdkMeterProvider, metrics: Arc<PipelineMetrics>) {
+    let meter = provider.meter("home-mixer");
+    let stage_counters: [StageCounter; 3] = [
+        ("pipeline_stage_requests_total", "Requests that completed each stage", |s| s.count),
+        ("pipeline_stage_candidates_in_total", "Candidates entering each stage", |s| {
+            s.candidates_in
+        }),
+        ("pipeline_stage_candidates_out_total", "Candidates leaving each stage", |s| {
+            s.candidates_out
+        }),
+    ];
+    for (name, description, value) in stage_counters {
+        let metrics = metrics.clone();
+        meter
+            .u64_observable_counter(name)
+            .with_description(description)
+            .with_callback(move |observer| {
+                for (stage, stats) in metrics.stages() {
+                    observer.observe(value(&stats), &stage_labels(&metrics, stage.as_str()));
+                }
+            })
+            .init();
+    }
+
+    let latency = metrics.clone();
+    meter
+        .f64_observable_counter("pipeline_stage_latency_ms_sum")
+        .with_description("Time spent in each stage")
+        .with_callback(move |observer| {
+            for (stage, stats) in latency.stages() {
+                let sum_ms = stats.latency.as_secs_f64() * 1000.0;
+                observer.observe(sum_ms, &stage_labels(&latency, stage.as_str()));
+            }
+        })
+        .init();
+
+    let errors = metrics.clone();
+    meter
+        .u64_observable_counter("pipeline_component_errors_total")
+        .with_description("Failed pipeline components")
+        .with_callback(move |observer| {
+            for (stage, component, kind, count) in errors.error_counts() {
+                let mut labels = stage_labels(&errors, stage.as_str());
+                labels.push(KeyValue::new("component", component));
+                labels.push(KeyValue::new("kind", kind.as_str()));
+                observer.observe(count, &labels);
+            }
+        })
+        .init();
+
+    meter
+        .u64_observable_counter("pipeline_source_timeouts_total")
+        .with_description("Sources requests went on without")
+        .with_callback(move |observer| {
+            for (source, count) in metrics.source_timeout_counts() {
+                let labels = [
+                    KeyValue::new("pipeline", metrics.pipeline().to_string()),
+                    KeyValue::new("source", source),
+                ];
+                observer.observe(count, &labels);
+            }
+        })
+        .init();
+}
+
+fn stage_labels(metrics: &PipelineMetrics, stage: &'static str) -> Vec<KeyValue> {
+    vec![
+        KeyValue::new("pipeline", metrics.pipeline().to_string()),
+        KeyValue::new("stage", stage),
+    ]
+}
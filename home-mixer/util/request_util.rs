@@ -8,3 +8,9 @@ static REQUEST_COUNTER: AtomicU64 = AtomicU64::new(1);
 pub fn generate_request_id() -> u64 {
     REQUEST_COUNTER.fetch_add(1, Ordering::Relaxed)
 }
+
+/// Compare without stopping at the first difference, so response times don't
+/// tell how much of a guessed token was right
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
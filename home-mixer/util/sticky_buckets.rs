@@ -0,0 +1,122 @@
+//! Sticky experiment buckets
+//!
+//! Remembers the variant each user was first assigned of experiments marked
+//! `sticky`, so `ExperimentAssignments::assign_sticky` keeps it when the
+//! experiment's allocation, weights or salt change. With `STICKY_BUCKETS_PATH`
+//! set, the remembered variants are read at startup and saved back every
+//! `SAVE_INTERVAL`, so users keep them across restarts too.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// How often remembered variants are saved, when they changed
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Variants by experiment and user, optionally saved to a file
+#[derive(Debug, Default)]
+pub struct StickyBuckets {
+    variants: RwLock<HashMap<String, HashMap<u64, String>>>,
+    path: Option<PathBuf>,
+    /// Whether anything was recorded since the last save
+    dirty: AtomicBool,
+}
+
+impl StickyBuckets {
+    /// In-memory buckets (nothing is saved)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The variants saved at `path`, none if the file does not exist yet.
+    /// `save` writes back to the same file.
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let variants = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            variants: RwLock::new(variants),
+            path: Some(path),
+            dirty: AtomicBool::new(false),
+        })
+    }
+
+    /// The variant of `experiment` `user_id` was first assigned, if any
+    pub fn get(&self, experiment: &str, user_id: u64) -> Option<String> {
+        self.variants.read().unwrap().get(experiment)?.get(&user_id).cloned()
+    }
+
+    /// Remember `variant` as the one of `experiment` `user_id` keeps
+    pub fn record(&self, experiment: &str, user_id: u64, variant: &str) {
+        self.variants
+            .write()
+            .unwrap()
+            .entry(experiment.to_string())
+            .or_default()
+            .insert(user_id, variant.to_string());
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// Write the remembered variants to the file they were loaded from, if
+    /// any changed since the last save. Returns whether it wrote.
+    pub fn save(&self) -> std::io::Result<bool> {
+        let Some(path) = &self.path else {
+            return Ok(false);
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(false);
+        }
+        let bytes = serde_json::to_vec(&*self.variants.read().unwrap())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        // Write to a sibling file and rename so a crash never leaves a torn file behind
+        let tmp = path.with_extension("tmp");
+        let written = std::fs::write(&tmp, bytes).and_then(|_| std::fs::rename(&tmp, path));
+        if written.is_err() {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        written.map(|_| true)
+    }
+
+    /// Save every `interval`, logging failures
+    pub fn spawn_saver(self: Arc<Self>, interval: Duration) {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                if let Err(e) = self.save() {
+                    log::warn!("Failed to save sticky buckets: {}", e);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variants_survive_a_restart() {
+        let path = std::env::temp_dir().join(format!("sticky_buckets_{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let buckets = StickyBuckets::load(&path).unwrap();
+        assert!(!buckets.save().unwrap(), "nothing recorded");
+        buckets.record("bait_filter_v2", 7, "treatment");
+        buckets.record("bait_filter_v2", 8, "control");
+        assert!(buckets.save().unwrap());
+        assert!(!buckets.save().unwrap(), "unchanged since");
+
+        let restarted = StickyBuckets::load(&path).unwrap();
+        assert_eq!(restarted.get("bait_filter_v2", 7).as_deref(), Some("treatment"));
+        assert_eq!(restarted.get("bait_filter_v2", 9), None);
+        assert_eq!(restarted.get("bait_filter_v2", 8).as_deref(), Some("control"));
+        assert!(!StickyBuckets::new().save().unwrap(), "in memory");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,170 @@
+//! User Authentication
+//!
+//! Endpoints that read or write a user's data answer only authenticated
+//! callers. A user presents `Authorization: Bearer {user_id}.{signature}`,
+//! where the signature is the hex HMAC-SHA256 of the decimal user id under
+//! `USER_TOKEN_SECRET`, shared with the login service issuing the tokens, and
+//! may only act for themselves. A backend acting for any user presents the
+//! service token the router was built with instead. Everyone else is refused,
+//! and so is everyone when neither is set.
+
+use crate::util::admin_auth;
+use crate::util::request_util::constant_time_eq;
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::sync::Arc;
+
+/// Who an authenticated request comes from, in the request's extensions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Caller {
+    /// A backend trusted to act for any user
+    Service,
+    User(u64),
+}
+
+impl Caller {
+    /// Whether the caller may read or change `user_id`'s data
+    pub fn may_act_for(&self, user_id: u64) -> bool {
+        match self {
+            Caller::Service => true,
+            Caller::User(id) => *id == user_id,
+        }
+    }
+}
+
+/// The user token secret and the service token requests are checked against
+#[derive(Clone, Default)]
+pub struct UserAuth {
+    user_token_secret: Option<Arc<[u8]>>,
+    service_token: Option<Arc<str>>,
+}
+
+impl UserAuth {
+    /// Checking user tokens signed with `user_token_secret`; none are accepted
+    /// without one
+    pub fn new(user_token_secret: Option<&str>) -> Self {
+        Self {
+            user_token_secret: user_token_secret
+                .filter(|secret| !secret.is_empty())
+                .map(|secret| Arc::from(secret.as_bytes())),
+            service_token: None,
+        }
+    }
+
+    /// Also accepting `service_token` from backends acting for any user
+    pub fn with_service_token(mut self, service_token: Option<&str>) -> Self {
+        self.service_token = service_token.filter(|token| !token.is_empty()).map(Arc::from);
+        self
+    }
+
+    /// The token `user_id` authenticates with, when user tokens are accepted
+    pub fn user_token(&self, user_id: u64) -> Option<String> {
+        let secret = self.user_token_secret.as_ref()?;
+        Some(format!("{}.{}", user_id, signature(secret, user_id)))
+    }
+
+    /// Who `headers` authenticate as, if anyone
+    pub fn caller(&self, headers: &HeaderMap) -> Option<Caller> {
+        if let Some(token) = &self.service_token {
+            if admin_auth::authorized(headers, token) {
+                return Some(Caller::Service);
+            }
+        }
+        let secret = self.user_token_secret.as_ref()?;
+        let bearer = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))?;
+        let (user_id, presented) = bearer.split_once('.')?;
+        let user_id: u64 = user_id.parse().ok()?;
+        let expected = signature(secret, user_id);
+        constant_time_eq(presented.as_bytes(), expected.as_bytes()).then_some(Caller::User(user_id))
+    }
+}
+
+fn signature(secret: &[u8], user_id: u64) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes keys of any length");
+    mac.update(user_id.to_string().as_bytes());
+    mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// `router`, answering only requests `auth` authenticates, with their `Caller`
+pub fn authenticated(router: Router, auth: UserAuth) -> Router {
+    router.route_layer(middleware::from_fn_with_state(auth, require_caller))
+}
+
+async fn require_caller(
+    State(auth): State<UserAuth>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    match auth.caller(request.headers()) {
+        Some(caller) => {
+            request.extensions_mut().insert(caller);
+            next.run(request).await
+        },
+        None => StatusCode::UNAUTHORIZED.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::routing::get;
+    use axum::Extension;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_callers_are_the_users_their_tokens_sign() {
+        let auth = UserAuth::new(Some("secret")).with_service_token(Some("service"));
+        let caller = |bearer: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::AUTHORIZATION, format!("Bearer {}", bearer).parse().unwrap());
+            auth.caller(&headers)
+        };
+        let token = auth.user_token(7).unwrap();
+
+        assert_eq!(caller(&token), Some(Caller::User(7)));
+        assert_eq!(caller("service"), Some(Caller::Service));
+        // Another user's signature, or one under another secret, doesn't pass
+        let (_, signature) = token.split_once('.').unwrap();
+        assert_eq!(caller(&format!("8.{}", signature)), None);
+        assert_eq!(caller(&UserAuth::new(Some("other")).user_token(7).unwrap()), None);
+        assert_eq!(caller("7"), None);
+        assert!(Caller::User(7).may_act_for(7) && !Caller::User(7).may_act_for(8));
+        assert!(Caller::Service.may_act_for(8));
+    }
+
+    #[tokio::test]
+    async fn test_routes_refuse_unauthenticated_callers() {
+        let auth = UserAuth::new(Some("secret"));
+        let app = authenticated(
+            Router::new().route(
+                "/me",
+                get(|Extension(caller): Extension<Caller>| async move { format!("{:?}", caller) }),
+            ),
+            auth.clone(),
+        );
+        let status = |bearer: Option<String>| {
+            let mut request = axum::http::Request::get("/me");
+            if let Some(bearer) = bearer {
+                request = request.header(header::AUTHORIZATION, format!("Bearer {}", bearer));
+            }
+            let request = request.body(Body::empty()).unwrap();
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+
+        assert_eq!(status(auth.user_token(7)).await, StatusCode::OK);
+        assert_eq!(status(Some("7.guess".to_string())).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(None).await, StatusCode::UNAUTHORIZED);
+        // Without a secret nobody gets in
+        assert_eq!(UserAuth::new(Some("")).user_token(7), None);
+    }
+}
@@ -0,0 +1,83 @@
+//! Admin Authentication
+//!
+//! Admin endpoints answer only requests presenting `admin_token` as
+//! `Authorization: Bearer {token}`, and refuse every request when it isn't
+//! set, so an unconfigured server never exposes them.
+
+use std::sync::Arc;
+
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+
+/// `router`, answering only requests bearing `admin_token`
+pub fn admin_only(router: Router, admin_token: Option<&str>) -> Router {
+    let admin_token = admin_token.filter(|token| !token.is_empty()).map(Arc::<str>::from);
+    router.route_layer(middleware::from_fn_with_state(admin_token, require_admin_token))
+}
+
+async fn require_admin_token(
+    State(admin_token): State<Option<Arc<str>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match admin_token {
+        Some(token) if authorized(request.headers(), &token) => next.run(request).await,
+        Some(_) => StatusCode::UNAUTHORIZED.into_response(),
+        None => (StatusCode::UNAUTHORIZED, "admin_token is not set").into_response(),
+    }
+}
+
+/// Whether `headers` present `admin_token` as a bearer token
+pub fn authorized(headers: &HeaderMap, admin_token: &str) -> bool {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| constant_time_eq(token.as_bytes(), admin_token.as_bytes()))
+}
+
+/// Compare without stopping at the first difference, so response times don't
+/// tell how much of a guessed token was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::routing::get;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_only_the_admin_token_is_authorized() {
+        let mut headers = HeaderMap::new();
+        assert!(!authorized(&headers, "secret"));
+        headers.insert(header::AUTHORIZATION, "Bearer secreT".parse().unwrap());
+        assert!(!authorized(&headers, "secret"));
+        headers.insert(header::AUTHORIZATION, "secret".parse().unwrap());
+        assert!(!authorized(&headers, "secret"));
+        headers.insert(header::AUTHORIZATION, "Bearer secret".parse().unwrap());
+        assert!(authorized(&headers, "secret"));
+    }
+
+    #[tokio::test]
+    async fn test_admin_routes_refuse_callers_without_the_token() {
+        let status = |admin_token: Option<&str>, bearer: Option<&str>| {
+            let app = admin_only(Router::new().route("/admin", get(|| async {})), admin_token);
+            let mut request = axum::http::Request::get("/admin");
+            if let Some(bearer) = bearer {
+                request = request.header(header::AUTHORIZATION, format!("Bearer {}", bearer));
+            }
+            let request = request.body(Body::empty()).unwrap();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+
+        assert_eq!(status(Some("secret"), Some("secret")).await, StatusCode::OK);
+        assert_eq!(status(Some("secret"), None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(None, Some("")).await, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,363 @@
+//! Alerting for Thunder
+//!
+//! Samples Thunder's health every check interval and posts to a webhook (a
+//! Slack incoming webhook works as is) when an indicator breaches its
+//! threshold, and again when it recovers: consumer lag once caught up, the
+//! rate post events are applied at, the share of queries shed or timed out,
+//! and the post store's estimated memory. A firing alert is announced once,
+//! and an alert is announced firing at most once per cooldown, so one that
+//! flaps across its threshold doesn't flood the channel. Resolutions are only
+//! announced for alerts whose firing was.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::config::Secret;
+use crate::ingest_source::CatchUpStatus;
+use crate::request_limiter::RequestLimiter;
+use crate::sharded_store::ShardedPostStore;
+
+/// Fewest queries between two checks for their error rate to be judged
+const MIN_QUERIES_FOR_ERROR_RATE: u64 = 20;
+/// Longest wait for the webhook to accept a notification
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `[alerting]` settings; nothing is checked without the section, and each
+/// indicator only with its threshold set
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlertingConfig {
+    /// Where notifications are posted as JSON
+    pub webhook_url: Secret,
+    /// Interval between health checks in seconds
+    pub interval_seconds: u64,
+    /// Least time between two announcements that an alert fired, in seconds
+    pub cooldown_seconds: u64,
+    /// Most messages the consumer may be behind the stream once caught up
+    pub max_lag: Option<u64>,
+    /// Fewest post events applied per second while consuming
+    pub min_ingest_rate: Option<f64>,
+    /// Largest share of queries shed or timed out between two checks
+    pub max_error_rate: Option<f64>,
+    /// Most bytes the post store may hold, by its estimate
+    pub max_store_bytes: Option<u64>,
+}
+
+impl Default for AlertingConfig {
+    fn default() -> Self {
+        Self {
+            webhook_url: Secret::default(),
+            interval_seconds: 30,
+            cooldown_seconds: 15 * 60,
+            max_lag: Some(100_000),
+            min_ingest_rate: None,
+            max_error_rate: Some(0.05),
+            max_store_bytes: None,
+        }
+    }
+}
+
+impl AlertingConfig {
+    /// Readings of the indicators with a threshold, from the change between
+    /// two samples taken `elapsed` apart
+    pub fn readings(
+        &self,
+        previous: &HealthSample,
+        current: &HealthSample,
+        elapsed: Duration,
+    ) -> Vec<Reading> {
+        let mut readings = Vec::new();
+        if let (Some(max_lag), Some(lag)) = (self.max_lag, current.lag) {
+            readings.push(Reading::above("consumer_lag", lag as f64, max_lag as f64));
+        }
+        if let Some(min_rate) = self.min_ingest_rate {
+            // Only while consuming a caught-up stream; catching up is fast anyway
+            if previous.lag.is_some() && current.lag.is_some() && !elapsed.is_zero() {
+                let events = current.events.saturating_sub(previous.events);
+                let rate = events as f64 / elapsed.as_secs_f64();
+                readings.push(Reading::below("ingest_rate", rate, min_rate));
+            }
+        }
+        if let Some(max_rate) = self.max_error_rate {
+            let queries = current.queries.saturating_sub(previous.queries);
+            if queries >= MIN_QUERIES_FOR_ERROR_RATE {
+                let failed = current.failed_queries.saturating_sub(previous.failed_queries);
+                let rate = failed as f64 / queries as f64;
+                readings.push(Reading::above("query_error_rate", rate, max_rate));
+            }
+        }
+        if let Some(max_bytes) = self.max_store_bytes {
+            let bytes = current.store_bytes as f64;
+            readings.push(Reading::above("post_store_bytes", bytes, max_bytes as f64));
+        }
+        readings
+    }
+}
+
+/// Totals the readings are computed from, sampled every check
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HealthSample {
+    /// Messages behind the stream; unset without one or until caught up
+    pub lag: Option<u64>,
+    /// Post events applied since startup
+    pub events: u64,
+    /// Queries received since startup, and those shed or timed out
+    pub queries: u64,
+    pub failed_queries: u64,
+    pub store_bytes: u64,
+}
+
+impl HealthSample {
+    pub fn take(
+        store: &ShardedPostStore,
+        catch_up: Option<&watch::Receiver<CatchUpStatus>>,
+        limiter: Option<&RequestLimiter>,
+    ) -> Self {
+        let lag = catch_up.and_then(|status| {
+            let status = status.borrow();
+            status.caught_up.then_some(status.lag)
+        });
+        let counts = store.event_counts();
+        let limits = limiter.map(|limiter| limiter.stats()).unwrap_or_default();
+        Self {
+            lag,
+            events: counts.created + counts.edited + counts.deleted + counts.engagement_updates,
+            queries: limits.admitted + limits.rejected,
+            failed_queries: limits.rejected + limits.timed_out,
+            store_bytes: store.bytes() as u64,
+        }
+    }
+}
+
+/// One indicator against its threshold, at one check
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reading {
+    pub alert: &'static str,
+    pub value: f64,
+    pub threshold: f64,
+    pub breached: bool,
+}
+
+impl Reading {
+    pub fn above(alert: &'static str, value: f64, max: f64) -> Self {
+        Self {
+            alert,
+            value,
+            threshold: max,
+            breached: value > max,
+        }
+    }
+
+    pub fn below(alert: &'static str, value: f64, min: f64) -> Self {
+        Self {
+            alert,
+            value,
+            threshold: min,
+            breached: value < min,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+/// What is posted to the webhook; `text` is what Slack shows
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Notification {
+    pub text: String,
+    pub service: &'static str,
+    pub alert: &'static str,
+    pub state: AlertState,
+    pub value: f64,
+    pub threshold: f64,
+}
+
+#[derive(Default)]
+struct Tracked {
+    firing: bool,
+    /// Whether the current firing was announced
+    announced: bool,
+    last_announced: Option<Instant>,
+}
+
+/// Turns readings into notifications, remembering what is firing
+pub struct Alerter {
+    service: &'static str,
+    cooldown: Duration,
+    tracked: Mutex<HashMap<&'static str, Tracked>>,
+}
+
+impl Alerter {
+    pub fn new(service: &'static str, cooldown: Duration) -> Self {
+        Self {
+            service,
+            cooldown,
+            tracked: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The notifications `readings` taken at `now` call for
+    pub fn notifications(&self, readings: &[Reading], now: Instant) -> Vec<Notification> {
+        let mut tracked = self.tracked.lock().unwrap();
+        let mut notifications = Vec::new();
+        for reading in readings {
+            let alert = tracked.entry(reading.alert).or_default();
+            let cooled_down = alert
+                .last_announced
+                .is_none_or(|at| now.duration_since(at) >= self.cooldown);
+            if reading.breached {
+                alert.firing = true;
+                if !alert.announced && cooled_down {
+                    alert.announced = true;
+                    alert.last_announced = Some(now);
+                    notifications.push(self.notification(reading, AlertState::Firing));
+                }
+            } else if alert.firing {
+                alert.firing = false;
+                if alert.announced {
+                    alert.announced = false;
+                    notifications.push(self.notification(reading, AlertState::Resolved));
+                }
+            }
+        }
+        notifications
+    }
+
+    fn notification(&self, reading: &Reading, state: AlertState) -> Notification {
+        let label = match state {
+            AlertState::Firing => "FIRING",
+            AlertState::Resolved => "RESOLVED",
+        };
+        Notification {
+            text: format!(
+                "[{}] {} {}: {} (threshold {})",
+                self.service, label, reading.alert, reading.value, reading.threshold
+            ),
+            service: self.service,
+            alert: reading.alert,
+            state,
+            value: reading.value,
+            threshold: reading.threshold,
+        }
+    }
+}
+
+/// Check Thunder's health every `interval_seconds`, posting to the webhook as
+/// alerts fire and resolve
+pub fn spawn_monitor(
+    config: AlertingConfig,
+    store: Arc<ShardedPostStore>,
+    catch_up: Option<watch::Receiver<CatchUpStatus>>,
+    limiter: Option<Arc<RequestLimiter>>,
+) {
+    let alerter = Alerter::new("thunder", Duration::from_secs(config.cooldown_seconds));
+    let client = reqwest::Client::builder()
+        .timeout(WEBHOOK_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    let interval = Duration::from_secs(config.interval_seconds);
+    tokio::spawn(async move {
+        let mut previous = HealthSample::take(&store, catch_up.as_ref(), limiter.as_deref());
+        let mut previous_at = Instant::now();
+        loop {
+            tokio::time::sleep(interval).await;
+            let current = HealthSample::take(&store, catch_up.as_ref(), limiter.as_deref());
+            let now = Instant::now();
+            let readings = config.readings(&previous, &current, now - previous_at);
+            for notification in alerter.notifications(&readings, now) {
+                info!("Alert: {}", notification.text);
+                let sent = client
+                    .post(config.webhook_url.expose())
+                    .json(&notification)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+                if let Err(e) = sent {
+                    warn!("Failed to post alert {}: {}", notification.alert, e);
+                }
+            }
+            (previous, previous_at) = (current, now);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flapping_alert_is_announced_once_per_cooldown() {
+        let alerter = Alerter::new("thunder", Duration::from_secs(600));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let lag = |value| [Reading::above("consumer_lag", value, 100.0)];
+        let states = |notifications: Vec<Notification>| {
+            notifications.into_iter().map(|n| n.state).collect::<Vec<_>>()
+        };
+
+        assert_eq!(states(alerter.notifications(&lag(50.0), at(0))), vec![]);
+        let fired = alerter.notifications(&lag(500.0), at(30));
+        assert_eq!(fired[0].text, "[thunder] FIRING consumer_lag: 500 (threshold 100)");
+        // Still breached: already announced
+        assert_eq!(states(alerter.notifications(&lag(600.0), at(60))), vec![]);
+        let resolved = alerter.notifications(&lag(10.0), at(90));
+        assert_eq!(states(resolved), vec![AlertState::Resolved]);
+
+        // Fires again within the cooldown: held back, and so is its resolution
+        assert_eq!(states(alerter.notifications(&lag(500.0), at(120))), vec![]);
+        assert_eq!(states(alerter.notifications(&lag(10.0), at(150))), vec![]);
+        assert_eq!(states(alerter.notifications(&lag(500.0), at(180))), vec![]);
+        // Still firing once the cooldown is over: announced then
+        let late = alerter.notifications(&lag(500.0), at(630));
+        assert_eq!(states(late), vec![AlertState::Firing]);
+    }
+
+    #[test]
+    fn test_readings_from_samples() {
+        let config = AlertingConfig {
+            min_ingest_rate: Some(10.0),
+            max_store_bytes: Some(1_000),
+            ..AlertingConfig::default()
+        };
+        let previous = HealthSample {
+            lag: Some(0),
+            events: 1_000,
+            queries: 100,
+            failed_queries: 0,
+            store_bytes: 500,
+        };
+        let current = HealthSample {
+            lag: Some(200_000),
+            events: 1_100,
+            queries: 200,
+            failed_queries: 10,
+            store_bytes: 2_000,
+        };
+
+        let readings = config.readings(&previous, &current, Duration::from_secs(30));
+        let breached: Vec<_> = readings.iter().filter(|r| r.breached).map(|r| r.alert).collect();
+        let all = ["consumer_lag", "ingest_rate", "query_error_rate", "post_store_bytes"];
+        assert_eq!(breached, all);
+        assert_eq!(readings[2].value, 0.1);
+
+        // Too few queries to judge, and no ingest rate before catching up
+        let catching_up = HealthSample {
+            lag: None,
+            queries: 105,
+            ..current
+        };
+        let readings = config.readings(&previous, &catching_up, Duration::from_secs(30));
+        let alerts: Vec<_> = readings.iter().map(|r| r.alert).collect();
+        assert_eq!(alerts, ["post_store_bytes"]);
+    }
+}
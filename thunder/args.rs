@@ -1,40 +1,64 @@
 //! Command line arguments for Thunder service
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
-/// Command line arguments for the Thunder in-memory post store service
+/// Command line arguments for the Thunder in-memory post store service; every
+/// other setting lives in `ThunderConfig`
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
-    /// Post retention period in seconds
-    #[arg(long, default_value = "604800")] // 7 days
-    pub post_retention_seconds: u64,
+    #[command(subcommand)]
+    pub command: Option<Command>,
 
-    /// Request timeout in milliseconds
-    #[arg(long, default_value = "5000")]
-    pub request_timeout_ms: u64,
+    /// TOML, YAML or JSON config file; defaults apply to settings it leaves out,
+    /// and `THUNDER_*` environment variables override it
+    #[arg(long)]
+    pub config: Option<PathBuf>,
 
-    /// Maximum concurrent requests
-    #[arg(long, default_value = "1000")]
-    pub max_concurrent_requests: usize,
+    /// gRPC server port, overriding the config
+    #[arg(long)]
+    pub grpc_port: Option<u16>,
 
-    /// gRPC server port
-    #[arg(long, default_value = "50051")]
-    pub grpc_port: u16,
+    /// HTTP server port, overriding the config
+    #[arg(long)]
+    pub http_port: Option<u16>,
 
-    /// HTTP server port
-    #[arg(long, default_value = "8080")]
-    pub http_port: u16,
+    /// Refuse to start when a `THUNDER_*` environment variable matches no
+    /// setting, instead of ignoring it
+    #[arg(long)]
+    pub strict_config: bool,
 
-    /// Enable profiling server
-    #[arg(long, default_value = "false")]
-    pub enable_profiling: bool,
+    /// Load and validate the config strictly, then exit; fails with every
+    /// problem found, for checking configs before rollout
+    #[arg(long)]
+    pub validate_config: bool,
 
-    /// Maximum result limit for queries
-    #[arg(long, default_value = "100")]
-    pub result_limit: usize,
+    /// Print the JSON schema of the config file and exit
+    #[arg(long)]
+    pub config_schema: bool,
+}
 
-    /// Whether to serve requests
-    #[arg(long, default_value = "true")]
-    pub is_serving: bool,
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Bulk-load posts from archives into the post store, then start as usual
+    Backfill {
+        /// JSONL (or, with the parquet feature, Parquet) archives of posts, loaded
+        /// in order; resuming an interrupted backfill needs the same order
+        #[arg(required = true)]
+        archives: Vec<PathBuf>,
+    },
+    /// Read the post-event stream from the start without storing or serving
+    /// anything, check every event, and print a report per stream
+    Validate {
+        /// Streams to read instead of the configured one, with the same settings
+        #[arg(long = "stream")]
+        streams: Vec<String>,
+        /// Stop after this many messages per stream
+        #[arg(long)]
+        max_messages: Option<u64>,
+        /// Exit with an error when any stream's error rate is above this
+        #[arg(long, default_value_t = 0.0)]
+        max_error_rate: f64,
+    },
 }
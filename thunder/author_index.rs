@@ -0,0 +1,456 @@
+//! Author Index for Thunder
+//!
+//! Posts are grouped by author and ordered by creation time within each author,
+//! so a query touches only the followed authors' posts instead of scanning the
+//! whole store. The newest posts across those authors are produced with a k-way
+//! merge, which stops as soon as `limit` posts have been taken. The index also
+//! keeps posts in creation order, replies by parent, and a search index over
+//! post content, updating all of them on every change. With a text codec set,
+//! post content is held compressed and restored as posts are read.
+
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet};
+use std::ops::Bound;
+use std::sync::Arc;
+
+use crate::candidate_source::{PostCursor, ThunderCandidate};
+use crate::search_index::{tokenize, SearchIndex};
+use crate::storage::PostStorage;
+use crate::text_compression::{CompressionStats, TextCodec, TextStore};
+
+/// Position of a post within its author's timeline
+type TimelineKey = (u64, i64);
+
+/// Rough per-post cost of the index entries themselves
+const INDEX_OVERHEAD_BYTES: usize = 96;
+
+/// Posts indexed by author, newest last within each author
+#[derive(Debug, Default)]
+pub struct AuthorIndex {
+    by_author: HashMap<i64, BTreeMap<TimelineKey, ThunderCandidate>>,
+    /// Author and timeline key of every post, for upserts and deletes by post id
+    locations: HashMap<i64, (i64, TimelineKey)>,
+    /// Every post in creation order, for oldest-first eviction
+    by_age: BTreeSet<TimelineKey>,
+    /// Replies by the post they reply to, oldest first
+    replies: HashMap<i64, BTreeSet<TimelineKey>>,
+    search: SearchIndex,
+    /// Compressed content of every post, when compression is on; the posts
+    /// themselves then hold no content
+    text: Option<TextStore>,
+    /// Estimated memory held by the posts and their index entries
+    bytes: usize,
+}
+
+fn post_bytes(post: &ThunderCandidate) -> usize {
+    post.estimated_bytes() + INDEX_OVERHEAD_BYTES
+}
+
+impl AuthorIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hold post content compressed with `codec` from now on, compressing the
+    /// posts already held
+    pub fn set_text_codec(&mut self, codec: Arc<dyn TextCodec>) {
+        let posts: Vec<ThunderCandidate> = self.iter().collect();
+        *self = Self {
+            text: Some(TextStore::new(codec)),
+            ..Self::default()
+        };
+        for post in posts {
+            self.insert(post);
+        }
+    }
+
+    pub fn compression_stats(&self) -> Option<CompressionStats> {
+        self.text.as_ref().map(TextStore::stats)
+    }
+
+    /// Insert `post`, replacing any post with the same id; returns the replaced post
+    pub fn insert(&mut self, mut post: ThunderCandidate) -> Option<ThunderCandidate> {
+        let replaced = self.remove(post.post_id);
+        let key = (post.created_at, post.post_id);
+        self.locations.insert(post.post_id, (post.author_id, key));
+        self.by_age.insert(key);
+        if let Some(parent_id) = post.reply_to_id {
+            self.replies.entry(parent_id).or_default().insert(key);
+        }
+        self.search.add(&post);
+        if let Some(text) = &mut self.text {
+            text.insert(post.post_id, &std::mem::take(&mut post.content));
+        }
+        self.bytes += self.stored_bytes(&post);
+        self.by_author.entry(post.author_id).or_default().insert(key, post);
+        replaced
+    }
+
+    /// Memory held by `post` as stored, with its compressed content if any
+    fn stored_bytes(&self, post: &ThunderCandidate) -> usize {
+        let compressed = self.text.as_ref().map_or(0, |text| text.compressed_len(post.post_id));
+        post_bytes(post) + compressed
+    }
+
+    /// The stored form of `post_id`, without its content when compressed
+    fn stored(&self, post_id: i64) -> Option<&ThunderCandidate> {
+        let (author_id, key) = self.locations.get(&post_id)?;
+        self.by_author.get(author_id)?.get(key)
+    }
+
+    /// A copy of the stored `post` with its content restored
+    fn load(&self, post: &ThunderCandidate) -> ThunderCandidate {
+        let mut post = post.clone();
+        if let Some(text) = &self.text {
+            post.content = text.read(post.post_id);
+        }
+        post
+    }
+
+    pub fn contains(&self, post_id: i64) -> bool {
+        self.locations.contains_key(&post_id)
+    }
+
+    pub fn get(&self, post_id: i64) -> Option<ThunderCandidate> {
+        self.stored(post_id).map(|post| self.load(post))
+    }
+
+    /// Replies to `post_id`, oldest first
+    pub fn replies_to(&self, post_id: i64) -> impl Iterator<Item = ThunderCandidate> + '_ {
+        self.replies
+            .get(&post_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|(_, reply_id)| self.get(*reply_id))
+    }
+
+    /// Change a post in place; returns false if the index doesn't hold it. `change`
+    /// must not touch the author, creation time or parent, which place the post in
+    /// the index.
+    pub fn update(&mut self, post_id: i64, change: impl FnOnce(&mut ThunderCandidate)) -> bool {
+        let Some((author_id, key)) = self.locations.get(&post_id) else {
+            return false;
+        };
+        let Some(post) = self.by_author.get_mut(author_id).and_then(|t| t.get_mut(key)) else {
+            return false;
+        };
+        let compressed = |text: &Option<TextStore>| {
+            text.as_ref().map_or(0, |text| text.compressed_len(post_id))
+        };
+        let before = post_bytes(post) + compressed(&self.text);
+        let content = self.text.as_ref().map(|text| text.peek(post_id));
+        if let Some(content) = &content {
+            post.content = content.clone();
+        }
+        self.search.remove(post);
+        change(post);
+        self.search.add(post);
+        if let (Some(text), Some(content)) = (&mut self.text, content) {
+            // Engagement updates leave the content alone, so skip recompressing it
+            let changed = std::mem::take(&mut post.content);
+            if changed != content {
+                text.insert(post_id, &changed);
+            }
+        }
+        self.bytes = self.bytes - before + post_bytes(post) + compressed(&self.text);
+        true
+    }
+
+    pub fn remove(&mut self, post_id: i64) -> Option<ThunderCandidate> {
+        let (author_id, key) = self.locations.remove(&post_id)?;
+        self.by_age.remove(&key);
+        let timeline = self.by_author.get_mut(&author_id)?;
+        let mut removed = timeline.remove(&key);
+        if timeline.is_empty() {
+            self.by_author.remove(&author_id);
+        }
+        if let Some(post) = &mut removed {
+            self.bytes -= self.stored_bytes(post);
+            if let Some(text) = &mut self.text {
+                post.content = text.remove(post_id).unwrap_or_default();
+            }
+            self.search.remove(post);
+            if let Some(parent_id) = post.reply_to_id {
+                if let Some(siblings) = self.replies.get_mut(&parent_id) {
+                    siblings.remove(&key);
+                    if siblings.is_empty() {
+                        self.replies.remove(&parent_id);
+                    }
+                }
+            }
+        }
+        removed
+    }
+
+    /// Up to `limit` posts matching every token of `query`, created at or after
+    /// `min_created_at`, newest first
+    pub fn search(&self, query: &str, min_created_at: u64, limit: usize) -> Vec<ThunderCandidate> {
+        self.search
+            .search(&tokenize(query), min_created_at, limit)
+            .into_iter()
+            .filter_map(|(_, post_id)| self.get(post_id))
+            .collect()
+    }
+
+    /// The post created first
+    pub fn oldest(&self) -> Option<ThunderCandidate> {
+        let (_, post_id) = self.by_age.first()?;
+        self.get(*post_id)
+    }
+
+    /// Estimated memory held by the posts and their index entries
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Keep only the posts matching `keep`; returns how many were removed
+    pub fn retain(&mut self, mut keep: impl FnMut(&ThunderCandidate) -> bool) -> usize {
+        let removed: Vec<i64> = self
+            .iter()
+            .filter(|post| !keep(post))
+            .map(|post| post.post_id)
+            .collect();
+        for post_id in &removed {
+            self.remove(*post_id);
+        }
+        removed.len()
+    }
+
+    /// Every post, for snapshots and bulk changes; unlike queries, this isn't
+    /// counted towards decompression stats
+    pub fn iter(&self) -> impl Iterator<Item = ThunderCandidate> + '_ {
+        self.by_author.values().flat_map(|timeline| timeline.values()).map(|post| {
+            let mut post = post.clone();
+            if let Some(text) = &self.text {
+                post.content = text.peek(post.post_id);
+            }
+            post
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+
+    /// Up to `limit` of the newest posts by `author_ids` older than `cursor`,
+    /// newest first, taking at most `max_per_author` from any one author
+    pub fn newest_by_authors(
+        &self,
+        author_ids: &[i64],
+        limit: usize,
+        max_per_author: Option<usize>,
+        cursor: Option<PostCursor>,
+    ) -> Vec<ThunderCandidate> {
+        let before = match cursor {
+            Some(cursor) => Bound::Excluded((cursor.created_at, cursor.post_id)),
+            None => Bound::Unbounded,
+        };
+        let authors: HashSet<i64> = author_ids.iter().copied().collect();
+        let per_author = max_per_author.unwrap_or(usize::MAX);
+        let mut timelines: Vec<_> = authors
+            .iter()
+            .filter_map(|author_id| self.by_author.get(author_id))
+            .map(|timeline| {
+                timeline
+                    .range((Bound::Unbounded, before))
+                    .rev()
+                    .take(per_author)
+                    .peekable()
+            })
+            .collect();
+
+        let mut heads: BinaryHeap<(TimelineKey, usize)> = timelines
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, timeline)| timeline.peek().map(|(key, _)| (**key, i)))
+            .collect();
+
+        let mut posts = Vec::with_capacity(limit.min(self.len()));
+        while posts.len() < limit {
+            let Some((_, i)) = heads.pop() else {
+                break;
+            };
+            if let Some((_, post)) = timelines[i].next() {
+                posts.push(self.load(post));
+            }
+            if let Some((key, _)) = timelines[i].peek() {
+                heads.push((**key, i));
+            }
+        }
+        posts
+    }
+}
+
+impl PostStorage for AuthorIndex {
+    fn insert(&mut self, post: ThunderCandidate) -> Option<ThunderCandidate> {
+        AuthorIndex::insert(self, post)
+    }
+
+    fn update(&mut self, post_id: i64, change: &mut dyn FnMut(&mut ThunderCandidate)) -> bool {
+        AuthorIndex::update(self, post_id, change)
+    }
+
+    fn remove(&mut self, post_id: i64) -> Option<ThunderCandidate> {
+        AuthorIndex::remove(self, post_id)
+    }
+
+    fn get(&self, post_id: i64) -> Option<ThunderCandidate> {
+        AuthorIndex::get(self, post_id)
+    }
+
+    fn contains(&self, post_id: i64) -> bool {
+        AuthorIndex::contains(self, post_id)
+    }
+
+    fn oldest(&self) -> Option<ThunderCandidate> {
+        AuthorIndex::oldest(self)
+    }
+
+    fn replies_to(&self, post_id: i64) -> Vec<ThunderCandidate> {
+        AuthorIndex::replies_to(self, post_id).collect()
+    }
+
+    fn search(&self, query: &str, min_created_at: u64, limit: usize) -> Vec<ThunderCandidate> {
+        AuthorIndex::search(self, query, min_created_at, limit)
+    }
+
+    fn newest_by_authors(
+        &self,
+        author_ids: &[i64],
+        limit: usize,
+        max_per_author: Option<usize>,
+        cursor: Option<PostCursor>,
+    ) -> Vec<ThunderCandidate> {
+        AuthorIndex::newest_by_authors(self, author_ids, limit, max_per_author, cursor)
+    }
+
+    fn posts(&self) -> Box<dyn Iterator<Item = ThunderCandidate> + '_> {
+        Box::new(self.iter())
+    }
+
+    fn posts_by_age(&self) -> Box<dyn Iterator<Item = ThunderCandidate> + '_> {
+        Box::new(self.by_age.iter().filter_map(|(_, post_id)| self.get(*post_id)))
+    }
+
+    fn set_text_codec(&mut self, codec: Arc<dyn TextCodec>) -> Result<(), String> {
+        AuthorIndex::set_text_codec(self, codec);
+        Ok(())
+    }
+
+    fn compression_stats(&self) -> Option<CompressionStats> {
+        AuthorIndex::compression_stats(self)
+    }
+
+    fn bytes(&self) -> usize {
+        AuthorIndex::bytes(self)
+    }
+
+    fn len(&self) -> usize {
+        AuthorIndex::len(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_newest_posts_across_followed_authors() {
+        let mut index = AuthorIndex::new();
+        index.insert(ThunderCandidate::new(1, 100, "Post 1".into(), 1000));
+        index.insert(ThunderCandidate::new(2, 200, "Post 2".into(), 1003));
+        index.insert(ThunderCandidate::new(3, 100, "Post 3".into(), 1002));
+        index.insert(ThunderCandidate::new(4, 300, "Post 4".into(), 1004));
+        index.insert(ThunderCandidate::new(5, 200, "Post 5".into(), 1001));
+
+        let ids = |posts: Vec<ThunderCandidate>| -> Vec<i64> {
+            posts.iter().map(|p| p.post_id).collect()
+        };
+        let newest = index.newest_by_authors(&[100, 200, 100], 10, None, None);
+        assert_eq!(ids(newest), vec![2, 3, 5, 1]);
+        assert_eq!(ids(index.newest_by_authors(&[100, 200], 2, None, None)), vec![2, 3]);
+        assert!(index.newest_by_authors(&[999], 10, None, None).is_empty());
+        let capped = index.newest_by_authors(&[100, 200], 10, Some(1), None);
+        assert_eq!(ids(capped), vec![2, 3]);
+
+        // Upserting a post under a new author moves it
+        index.insert(ThunderCandidate::new(4, 100, "Post 4".into(), 1004));
+        assert_eq!(index.len(), 5);
+        assert_eq!(ids(index.newest_by_authors(&[100], 10, None, None)), vec![4, 3, 1]);
+        assert!(index.newest_by_authors(&[300], 10, None, None).is_empty());
+
+        let cursor = PostCursor {
+            created_at: 1004,
+            post_id: 4,
+        };
+        assert_eq!(ids(index.newest_by_authors(&[100], 10, None, Some(cursor))), vec![3, 1]);
+
+        assert_eq!(index.oldest().unwrap().post_id, 1);
+        let bytes = index.bytes();
+        assert!(index.update(1, |p| p.content.push_str(" (edited)")));
+        assert_eq!(index.bytes(), bytes + " (edited)".len());
+
+        assert_eq!(index.retain(|p| p.created_at >= 1002), 2);
+        assert_eq!(index.oldest().unwrap().post_id, 3);
+        assert!(index.remove(2).is_some());
+        assert_eq!(ids(index.newest_by_authors(&[100, 200], 10, None, None)), vec![4, 3]);
+    }
+
+    /// Run-length encoding: each run of a byte as the byte and its length
+    struct RunLength;
+
+    impl TextCodec for RunLength {
+        fn compress(&self, text: &str) -> Vec<u8> {
+            let mut encoded: Vec<u8> = Vec::new();
+            for byte in text.bytes() {
+                match encoded.len() {
+                    len if len >= 2 && encoded[len - 2] == byte && encoded[len - 1] < 255 => {
+                        encoded[len - 1] += 1
+                    }
+                    _ => encoded.extend([byte, 1]),
+                }
+            }
+            encoded
+        }
+
+        fn decompress(&self, bytes: &[u8], raw_len: usize) -> Result<String, String> {
+            let mut raw = Vec::with_capacity(raw_len);
+            for run in bytes.chunks(2) {
+                raw.extend(std::iter::repeat_n(run[0], run[1] as usize));
+            }
+            String::from_utf8(raw).map_err(|e| e.to_string())
+        }
+    }
+
+    #[test]
+    fn test_compressed_content_is_restored_on_read() {
+        let mut index = AuthorIndex::new();
+        index.insert(ThunderCandidate::new(1, 100, "Goooooooooal!".into(), 1000));
+        let plain_bytes = index.bytes();
+        index.set_text_codec(Arc::new(RunLength));
+        assert!(index.bytes() < plain_bytes);
+        index.insert(ThunderCandidate::new(2, 100, "Hmmmmmmmm #match".into(), 1001));
+
+        assert_eq!(index.get(1).unwrap().content, "Goooooooooal!");
+        let found = index.search("#match", 0, 10);
+        assert_eq!(found[0].content, "Hmmmmmmmm #match");
+        let stats = index.compression_stats().unwrap();
+        assert_eq!((stats.posts, stats.raw_bytes, stats.decompressions), (2, 29, 2));
+        assert!(stats.ratio() > 1.0);
+
+        // Engagement updates keep the content; edits recompress and reindex it
+        assert!(index.update(2, |p| p.engagement.likes += 1));
+        assert_eq!(index.get(2).unwrap().content, "Hmmmmmmmm #match");
+        assert!(index.update(2, |p| p.content = "Full time".into()));
+        assert!(index.search("#match", 0, 10).is_empty());
+        assert_eq!(index.search("full", 0, 10)[0].content, "Full time");
+
+        assert_eq!(index.remove(1).unwrap().content, "Goooooooooal!");
+        assert_eq!(index.remove(2).unwrap().content, "Full time");
+        assert_eq!(index.bytes(), 0);
+        assert_eq!(index.compression_stats().unwrap().raw_bytes, 0);
+    }
+}
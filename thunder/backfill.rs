@@ -0,0 +1,200 @@
+//! Backfill for Thunder
+//!
+//! Bulk-loads posts from archives (JSONL, or Parquet with the `parquet`
+//! feature) into the post store before live consumption starts, so a fresh
+//! instance serves realistic data right away. Archived posts are applied as
+//! records of a dedicated partition numbered by their position across the
+//! archives, so progress lands in the store's watermark and is persisted with
+//! it: a backfill restarted after a snapshot, or on disk-backed storage, skips
+//! the posts already loaded. Archives must be given in the same order to resume.
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use log::info;
+use serde::Serialize;
+
+use crate::candidate_source::ThunderCandidate;
+use crate::retention::RetentionPolicy;
+use crate::sharded_store::ShardedPostStore;
+use crate::wal::{PostEvent, WalRecord};
+
+/// Partition backfilled posts are recorded under; stream partitions start at 0
+pub const BACKFILL_PARTITION: i32 = -1;
+/// Posts between progress log lines
+const PROGRESS_INTERVAL: u64 = 100_000;
+
+/// Archived posts handled by a backfill
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct BackfillCounts {
+    pub loaded: u64,
+    /// Read by an earlier run of the backfill
+    pub already_loaded: u64,
+    /// Past their author's retention
+    pub expired: u64,
+}
+
+/// Load the posts of `archives`, in order, into `store`, skipping posts an
+/// earlier run already loaded and posts past their author's retention
+pub fn backfill(
+    store: &ShardedPostStore,
+    archives: &[impl AsRef<Path>],
+    now: u64,
+    retention: &RetentionPolicy,
+) -> Result<BackfillCounts, String> {
+    let resume_at = store
+        .resume_offsets()
+        .get(&BACKFILL_PARTITION)
+        .copied()
+        .unwrap_or(0);
+    let mut offset = 0;
+    let mut counts = BackfillCounts::default();
+    for archive in archives {
+        let archive = archive.as_ref();
+        info!("Backfilling from {}", archive.display());
+        read_archive(archive, &mut |post| {
+            if offset < resume_at {
+                counts.already_loaded += 1;
+            } else if !retention.retains(&post, now) {
+                counts.expired += 1;
+            } else {
+                store.apply_record(WalRecord {
+                    partition: BACKFILL_PARTITION,
+                    offset,
+                    event: PostEvent::Upsert { post },
+                });
+                counts.loaded += 1;
+            }
+            offset += 1;
+            if (offset as u64).is_multiple_of(PROGRESS_INTERVAL) {
+                info!("Backfill progress: {} posts read, {:?}", offset, counts);
+            }
+        })?;
+    }
+    Ok(counts)
+}
+
+/// Feed every post of `archive` to `visit`, picking the format by extension
+fn read_archive(archive: &Path, visit: &mut dyn FnMut(ThunderCandidate)) -> Result<(), String> {
+    match archive.extension().and_then(|ext| ext.to_str()) {
+        Some("jsonl" | "ndjson" | "json") => read_jsonl(archive, visit),
+        #[cfg(feature = "parquet")]
+        Some("parquet") => read_parquet(archive, visit),
+        _ => Err(format!("unsupported archive format: {}", archive.display())),
+    }
+}
+
+/// One JSON-encoded post per line, as in snapshots
+fn read_jsonl(archive: &Path, visit: &mut dyn FnMut(ThunderCandidate)) -> Result<(), String> {
+    let file = fs::File::open(archive)
+        .map_err(|e| format!("failed to open {}: {}", archive.display(), e))?;
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("failed to read {}: {}", archive.display(), e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let post = serde_json::from_str(&line)
+            .map_err(|e| format!("{}:{}: {}", archive.display(), number + 1, e))?;
+        visit(post);
+    }
+    Ok(())
+}
+
+/// One post per row, with columns named after the post's fields; engagement
+/// columns (`likes`, `replies`, `reposts`, `bookmarks`, `views`) are optional
+#[cfg(feature = "parquet")]
+fn read_parquet(archive: &Path, visit: &mut dyn FnMut(ThunderCandidate)) -> Result<(), String> {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::Field;
+
+    fn int(field: &Field) -> Option<i64> {
+        match field {
+            Field::Byte(v) => Some(*v as i64),
+            Field::Short(v) => Some(*v as i64),
+            Field::Int(v) => Some(*v as i64),
+            Field::Long(v) => Some(*v),
+            Field::UByte(v) => Some(*v as i64),
+            Field::UShort(v) => Some(*v as i64),
+            Field::UInt(v) => Some(*v as i64),
+            Field::ULong(v) => Some(*v as i64),
+            _ => None,
+        }
+    }
+
+    let error = |e: parquet::errors::ParquetError| format!("{}: {}", archive.display(), e);
+    let file = fs::File::open(archive)
+        .map_err(|e| format!("failed to open {}: {}", archive.display(), e))?;
+    let reader = SerializedFileReader::new(file).map_err(error)?;
+    for row in reader.get_row_iter(None).map_err(error)? {
+        let row = row.map_err(error)?;
+        let mut post = ThunderCandidate::new(0, 0, String::new(), 0);
+        for (name, field) in row.get_column_iter() {
+            match (name.as_str(), field) {
+                ("author_handle", Field::Str(v)) => post.author_handle = v.clone(),
+                ("content", Field::Str(v)) => post.content = v.clone(),
+                ("has_media", Field::Bool(v)) => post.has_media = *v,
+                ("is_reply", Field::Bool(v)) => post.is_reply = *v,
+                ("has_link", Field::Bool(v)) => post.has_link = *v,
+                (name, field) => {
+                    let Some(v) = int(field) else { continue };
+                    match name {
+                        "post_id" => post.post_id = v,
+                        "author_id" => post.author_id = v,
+                        "created_at" => post.created_at = v as u64,
+                        "reply_to_id" => post.reply_to_id = Some(v),
+                        "likes" => post.engagement.likes = v as u32,
+                        "replies" => post.engagement.replies = v as u32,
+                        "reposts" => post.engagement.reposts = v as u32,
+                        "bookmarks" => post.engagement.bookmarks = v as u32,
+                        "views" => post.engagement.views = v as u64,
+                        _ => {}
+                    }
+                }
+            }
+        }
+        visit(post);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backfill_resumes_from_the_watermark() {
+        let dir = std::env::temp_dir().join(format!("thunder_backfill_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let archive = |name: &str, posts: &[(i64, u64)]| {
+            let lines: Vec<String> = posts
+                .iter()
+                .map(|(post_id, created_at)| {
+                    let post = ThunderCandidate::new(*post_id, 100, "Post".into(), *created_at);
+                    serde_json::to_string(&post).unwrap()
+                })
+                .collect();
+            let path = dir.join(name);
+            fs::write(&path, lines.join("\n")).unwrap();
+            path
+        };
+        let first = archive("day1.jsonl", &[(1, 1000), (2, 100)]);
+        let second = archive("day2.jsonl", &[(3, 1001)]);
+
+        let retention = RetentionPolicy::new(500);
+        let store = ShardedPostStore::new(2);
+        let counts = backfill(&store, &[&first], 1100, &retention).unwrap();
+        assert_eq!(counts, BackfillCounts { loaded: 1, already_loaded: 0, expired: 1 });
+
+        // A restart from a snapshot picks up where the last run stopped
+        let store = ShardedPostStore::from_snapshot(store.to_snapshot(1100), 2);
+        let counts = backfill(&store, &[&first, &second], 1100, &retention).unwrap();
+        assert_eq!(counts, BackfillCounts { loaded: 1, already_loaded: 1, expired: 1 });
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.resume_offsets().get(&BACKFILL_PARTITION), Some(&3));
+
+        assert!(backfill(&store, &[dir.join("day3.csv")], 1100, &retention).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,130 @@
+//! Bloom Filter for Impression Exclusion
+//!
+//! Clients send the posts a viewer has already seen as a compact bloom filter
+//! (`ImpressionBloomFilterEntry`) rather than a list of thousands of ids. Bits
+//! are chosen by double hashing of the post id: bit `i` of `num_hashes` is
+//! `(h1 + i * h2) % num_bits`, with `h1` and `h2` derived from SplitMix64, and
+//! bit `n` lives in byte `n / 8` at position `n % 8`.
+
+use crate::error::ThunderError;
+use crate::proto::ImpressionBloomFilterEntry;
+
+/// More hashes than this only raise the false positive rate of any filter a
+/// client could sensibly send, while costing a loop per candidate
+pub const MAX_NUM_HASHES: u32 = 32;
+/// 8M bits, enough for a million impressions at a 2% false positive rate
+pub const MAX_FILTER_BYTES: usize = 1 << 20;
+
+#[derive(Clone, Debug)]
+pub struct BloomFilter {
+    bits: Vec<u8>,
+    num_bits: u64,
+    num_hashes: u32,
+}
+
+impl BloomFilter {
+    /// A filter of `num_bits`, with `num_hashes` clamped to `MAX_NUM_HASHES`
+    pub fn new(num_bits: u64, num_hashes: u32) -> Self {
+        Self {
+            bits: vec![0; num_bits.div_ceil(8) as usize],
+            num_bits,
+            num_hashes: num_hashes.min(MAX_NUM_HASHES),
+        }
+    }
+
+    /// Read a filter sent by a client. A `num_bits` larger than the data is
+    /// clamped; a filter without bits or hashes matches nothing. More than
+    /// `MAX_NUM_HASHES` hashes or `MAX_FILTER_BYTES` of data are rejected.
+    pub fn from_entry(entry: &ImpressionBloomFilterEntry) -> Result<Self, ThunderError> {
+        if entry.num_hashes > MAX_NUM_HASHES as i32 {
+            let reason = format!("a bloom filter may use at most {} hashes", MAX_NUM_HASHES);
+            return Err(ThunderError::InvalidArgument(reason));
+        }
+        if entry.filter_data.len() > MAX_FILTER_BYTES {
+            let reason = format!("a bloom filter may hold at most {} bytes", MAX_FILTER_BYTES);
+            return Err(ThunderError::InvalidArgument(reason));
+        }
+        let available = entry.filter_data.len() as u64 * 8;
+        Ok(Self {
+            bits: entry.filter_data.clone(),
+            num_bits: (entry.num_bits.max(0) as u64).min(available),
+            num_hashes: entry.num_hashes.max(0) as u32,
+        })
+    }
+
+    pub fn to_entry(&self) -> ImpressionBloomFilterEntry {
+        ImpressionBloomFilterEntry {
+            filter_data: self.bits.clone(),
+            num_bits: self.num_bits as i32,
+            num_hashes: self.num_hashes as i32,
+        }
+    }
+
+    pub fn insert(&mut self, post_id: u64) {
+        for bit in self.bit_indexes(post_id) {
+            self.bits[(bit / 8) as usize] |= 1 << (bit % 8);
+        }
+    }
+
+    /// False means the post was definitely not added; true may be a false positive
+    pub fn may_contain(&self, post_id: u64) -> bool {
+        if self.num_bits == 0 || self.num_hashes == 0 {
+            return false;
+        }
+        self.bit_indexes(post_id)
+            .all(|bit| self.bits[(bit / 8) as usize] & (1 << (bit % 8)) != 0)
+    }
+
+    fn bit_indexes(&self, post_id: u64) -> impl Iterator<Item = u64> {
+        let h1 = splitmix64(post_id);
+        let h2 = splitmix64(h1) | 1;
+        let num_bits = self.num_bits.max(1);
+        let num_hashes = self.num_hashes.min(MAX_NUM_HASHES) as u64;
+        (0..num_hashes).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip_through_entry() {
+        let mut filter = BloomFilter::new(8 * 1024, 4);
+        for post_id in 0..500 {
+            filter.insert(post_id * 7);
+        }
+
+        let received = BloomFilter::from_entry(&filter.to_entry()).unwrap();
+        assert!((0..500).all(|post_id| received.may_contain(post_id * 7)));
+        let false_positives = (10_000..20_000).filter(|id| received.may_contain(*id)).count();
+        assert!(false_positives < 200, "{} false positives", false_positives);
+
+        let empty = BloomFilter::from_entry(&ImpressionBloomFilterEntry::default()).unwrap();
+        assert!(!empty.may_contain(7));
+    }
+
+    #[test]
+    fn test_oversized_filters_are_rejected() {
+        let entry = |filter_data: Vec<u8>, num_hashes: i32| ImpressionBloomFilterEntry {
+            num_bits: filter_data.len() as i32 * 8,
+            filter_data,
+            num_hashes,
+        };
+
+        assert!(BloomFilter::from_entry(&entry(vec![0xFF; 64], MAX_NUM_HASHES as i32)).is_ok());
+        let too_many_hashes = BloomFilter::from_entry(&entry(vec![0xFF; 64], i32::MAX));
+        assert!(matches!(too_many_hashes, Err(ThunderError::InvalidArgument(_))));
+        let too_large = BloomFilter::from_entry(&entry(vec![0; MAX_FILTER_BYTES + 1], 4));
+        assert!(matches!(too_large, Err(ThunderError::InvalidArgument(_))));
+        // Filters built here are clamped instead
+        assert_eq!(BloomFilter::new(64, u32::MAX).to_entry().num_hashes, MAX_NUM_HASHES as i32);
+    }
+}
@@ -0,0 +1,18 @@
+//! Compiles the gRPC definitions in `protos/` into `proto`
+
+use std::path::PathBuf;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    if std::env::var_os("PROTOC").is_none() {
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    }
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR")?);
+    tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("thunder_descriptor.bin"))
+        .btree_map(["."])
+        // The HTTP gateway speaks the same messages as JSON
+        .type_attribute(".", "#[derive(serde::Serialize, serde::Deserialize)]")
+        .message_attribute(".", "#[serde(default)]")
+        .compile(&["protos/thunder.proto"], &["protos"])?;
+    Ok(())
+}
@@ -3,7 +3,14 @@
 //! Provides in-network post candidates for the home timeline.
 //! These are posts from accounts the user follows.
 
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
 use serde::{Deserialize, Serialize};
+use tonic::async_trait;
+
+use crate::author_index::AuthorIndex;
 
 /// Post candidate from Thunder (in-network)
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -28,6 +35,13 @@ pub struct ThunderCandidate {
     pub has_link: bool,
     /// Engagement metrics snapshot
     pub engagement: EngagementSnapshot,
+    /// Author or post drawing anomalous volume; set on query results only
+    #[serde(default)]
+    pub trending_in_network: bool,
+    /// Score the post was picked by when results were pre-ranked; set on query
+    /// results only
+    #[serde(default)]
+    pub pre_rank_score: Option<f64>,
 }
 
 /// Snapshot of engagement metrics at retrieval time
@@ -38,6 +52,32 @@ pub struct EngagementSnapshot {
     pub reposts: u32,
     pub bookmarks: u32,
     pub views: u64,
+    /// When the counts last changed (Unix epoch seconds; 0 if never updated)
+    #[serde(default)]
+    pub updated_at: u64,
+}
+
+/// Engagement gained by a post since the previous update
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EngagementDelta {
+    pub likes: u32,
+    pub replies: u32,
+    pub reposts: u32,
+    pub bookmarks: u32,
+    pub views: u64,
+}
+
+impl EngagementSnapshot {
+    /// Add `delta` to the counts, recording `now` as the update time
+    pub fn apply(&mut self, delta: &EngagementDelta, now: u64) {
+        self.likes = self.likes.saturating_add(delta.likes);
+        self.replies = self.replies.saturating_add(delta.replies);
+        self.reposts = self.reposts.saturating_add(delta.reposts);
+        self.bookmarks = self.bookmarks.saturating_add(delta.bookmarks);
+        self.views = self.views.saturating_add(delta.views);
+        self.updated_at = self.updated_at.max(now);
+    }
 }
 
 impl ThunderCandidate {
@@ -54,6 +94,8 @@ impl ThunderCandidate {
             reply_to_id: None,
             has_link: false,
             engagement: EngagementSnapshot::default(),
+            trending_in_network: false,
+            pre_rank_score: None,
         }
     }
 
@@ -62,27 +104,231 @@ impl ThunderCandidate {
         now.saturating_sub(self.created_at)
     }
 
+    /// Approximate memory held by the candidate, including its strings
+    pub fn estimated_bytes(&self) -> usize {
+        std::mem::size_of::<Self>() + self.author_handle.len() + self.content.len()
+    }
+
     /// Check if post is within max age (7 days)
     pub fn is_fresh(&self, now: u64, max_age_seconds: u64) -> bool {
         self.age_seconds(now) < max_age_seconds
     }
 }
 
+/// Position in a newest-first listing of posts. A page fetched with a cursor
+/// holds only posts that sort after it, i.e. older posts (ties broken by post id).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct PostCursor {
+    pub created_at: u64,
+    pub post_id: i64,
+}
+
+impl PostCursor {
+    /// Cursor just past `post`
+    pub fn after(post: &ThunderCandidate) -> Self {
+        Self {
+            created_at: post.created_at,
+            post_id: post.post_id,
+        }
+    }
+}
+
 /// Source of in-network candidates
 pub trait CandidateSource: Send + Sync {
-    /// Fetch candidates for a user's following list
+    /// Fetch candidates for a user's following list, newest first, taking at
+    /// most `max_per_author` posts from any one account and, given a `cursor`,
+    /// only posts older than it
     fn fetch_candidates(
         &self,
         user_id: i64,
         following_ids: &[i64],
         limit: usize,
+        max_per_author: Option<usize>,
+        cursor: Option<PostCursor>,
     ) -> Vec<ThunderCandidate>;
+
+    /// Like `fetch_candidates`, but a source gathering from several parts may
+    /// stop once `deadline` passes and return what it has; the flag is false
+    /// when the result is partial
+    fn fetch_candidates_until(
+        &self,
+        user_id: i64,
+        following_ids: &[i64],
+        limit: usize,
+        max_per_author: Option<usize>,
+        cursor: Option<PostCursor>,
+        _deadline: Instant,
+    ) -> (Vec<ThunderCandidate>, bool) {
+        let candidates =
+            self.fetch_candidates(user_id, following_ids, limit, max_per_author, cursor);
+        (candidates, true)
+    }
+}
+
+impl<S: CandidateSource> CandidateSource for RwLock<S> {
+    fn fetch_candidates(
+        &self,
+        user_id: i64,
+        following_ids: &[i64],
+        limit: usize,
+        max_per_author: Option<usize>,
+        cursor: Option<PostCursor>,
+    ) -> Vec<ThunderCandidate> {
+        self.read()
+            .unwrap()
+            .fetch_candidates(user_id, following_ids, limit, max_per_author, cursor)
+    }
+
+    fn fetch_candidates_until(
+        &self,
+        user_id: i64,
+        following_ids: &[i64],
+        limit: usize,
+        max_per_author: Option<usize>,
+        cursor: Option<PostCursor>,
+        deadline: Instant,
+    ) -> (Vec<ThunderCandidate>, bool) {
+        self.read().unwrap().fetch_candidates_until(
+            user_id,
+            following_ids,
+            limit,
+            max_per_author,
+            cursor,
+            deadline,
+        )
+    }
+}
+
+impl<S: CandidateSource + ?Sized> CandidateSource for Arc<S> {
+    fn fetch_candidates(
+        &self,
+        user_id: i64,
+        following_ids: &[i64],
+        limit: usize,
+        max_per_author: Option<usize>,
+        cursor: Option<PostCursor>,
+    ) -> Vec<ThunderCandidate> {
+        (**self).fetch_candidates(user_id, following_ids, limit, max_per_author, cursor)
+    }
+
+    fn fetch_candidates_until(
+        &self,
+        user_id: i64,
+        following_ids: &[i64],
+        limit: usize,
+        max_per_author: Option<usize>,
+        cursor: Option<PostCursor>,
+        deadline: Instant,
+    ) -> (Vec<ThunderCandidate>, bool) {
+        (**self).fetch_candidates_until(
+            user_id,
+            following_ids,
+            limit,
+            max_per_author,
+            cursor,
+            deadline,
+        )
+    }
+}
+
+/// Why a candidate source could not produce candidates
+#[derive(Clone, Debug, PartialEq)]
+pub enum SourceError {
+    /// The backing store or service could not be reached
+    Unavailable(String),
+    /// The source did not answer within its deadline
+    Timeout,
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceError::Unavailable(reason) => {
+                write!(f, "candidate source unavailable: {}", reason)
+            }
+            SourceError::Timeout => write!(f, "candidate source timed out"),
+        }
+    }
+}
+
+impl std::error::Error for SourceError {}
+
+/// Source of in-network candidates that may be remote and may fail
+#[async_trait]
+pub trait AsyncCandidateSource: Send + Sync {
+    /// Fetch candidates for a user's following list, newest first, taking at
+    /// most `max_per_author` posts from any one account and, given a `cursor`,
+    /// only posts older than it
+    async fn fetch_candidates(
+        &self,
+        user_id: i64,
+        following_ids: &[i64],
+        limit: usize,
+        max_per_author: Option<usize>,
+        cursor: Option<PostCursor>,
+    ) -> Result<Vec<ThunderCandidate>, SourceError>;
+
+    /// Like `fetch_candidates`, but may return early with partial results once
+    /// `deadline` passes; the flag is false when the result is partial
+    async fn fetch_candidates_until(
+        &self,
+        user_id: i64,
+        following_ids: &[i64],
+        limit: usize,
+        max_per_author: Option<usize>,
+        cursor: Option<PostCursor>,
+        _deadline: Instant,
+    ) -> Result<(Vec<ThunderCandidate>, bool), SourceError> {
+        let candidates = self
+            .fetch_candidates(user_id, following_ids, limit, max_per_author, cursor)
+            .await?;
+        Ok((candidates, true))
+    }
+}
+
+/// Adapter serving a synchronous, in-process `CandidateSource` as an
+/// `AsyncCandidateSource`
+pub struct SyncSource<S>(pub S);
+
+#[async_trait]
+impl<S: CandidateSource> AsyncCandidateSource for SyncSource<S> {
+    async fn fetch_candidates(
+        &self,
+        user_id: i64,
+        following_ids: &[i64],
+        limit: usize,
+        max_per_author: Option<usize>,
+        cursor: Option<PostCursor>,
+    ) -> Result<Vec<ThunderCandidate>, SourceError> {
+        Ok(self
+            .0
+            .fetch_candidates(user_id, following_ids, limit, max_per_author, cursor))
+    }
+
+    async fn fetch_candidates_until(
+        &self,
+        user_id: i64,
+        following_ids: &[i64],
+        limit: usize,
+        max_per_author: Option<usize>,
+        cursor: Option<PostCursor>,
+        deadline: Instant,
+    ) -> Result<(Vec<ThunderCandidate>, bool), SourceError> {
+        Ok(self.0.fetch_candidates_until(
+            user_id,
+            following_ids,
+            limit,
+            max_per_author,
+            cursor,
+            deadline,
+        ))
+    }
 }
 
 /// In-memory candidate source for testing/development
 #[derive(Default)]
 pub struct InMemoryCandidateSource {
-    posts: Vec<ThunderCandidate>,
+    posts: AuthorIndex,
 }
 
 impl InMemoryCandidateSource {
@@ -91,7 +337,7 @@ impl InMemoryCandidateSource {
     }
 
     pub fn add_post(&mut self, post: ThunderCandidate) {
-        self.posts.push(post);
+        self.posts.insert(post);
     }
 }
 
@@ -101,13 +347,10 @@ impl CandidateSource for InMemoryCandidateSource {
         _user_id: i64,
         following_ids: &[i64],
         limit: usize,
+        max_per_author: Option<usize>,
+        cursor: Option<PostCursor>,
     ) -> Vec<ThunderCandidate> {
-        self.posts
-            .iter()
-            .filter(|p| following_ids.contains(&p.author_id))
-            .take(limit)
-            .cloned()
-            .collect()
+        self.posts.newest_by_authors(following_ids, limit, max_per_author, cursor)
     }
 }
 
@@ -132,7 +375,12 @@ mod tests {
         source.add_post(ThunderCandidate::new(2, 200, "Post 2".into(), 1001));
         source.add_post(ThunderCandidate::new(3, 100, "Post 3".into(), 1002));
 
-        let candidates = source.fetch_candidates(1, &[100], 10);
+        let candidates = source.fetch_candidates(1, &[100], 10, None, None);
         assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].post_id, 3);
+
+        let capped = source.fetch_candidates(1, &[100, 200], 10, Some(1), None);
+        let ids: Vec<i64> = capped.iter().map(|c| c.post_id).collect();
+        assert_eq!(ids, vec![3, 2]);
     }
 }
@@ -1,9 +1,524 @@
 //! Thunder configuration
+//!
+//! Settings are read from a TOML, YAML or JSON file, chosen by its extension,
+//! with defaults for anything it leaves out. `THUNDER_*` environment variables
+//! override the file, e.g. `THUNDER_RETENTION_SECONDS` for `retention_seconds`
+//! or `THUNDER_TRENDING_MIN_COUNT` for `min_count` of the `[trending]` section.
+//! The result is validated before Thunder starts.
+//!
+//! Strict loading, used by `--strict-config` and `--validate-config`, also
+//! rejects `THUNDER_*` variables that match no setting, which are otherwise
+//! ignored with a warning. `ThunderConfig::json_schema` describes the file.
 
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::warn;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::alerting::AlertingConfig;
+use crate::ingest_source::IngestConfig;
+use crate::over_fetch::OverFetchConfig;
+use crate::pre_rank::PreRankConfig;
+use crate::storage::StorageBackend;
+use crate::text_compression::TextCompression;
+use crate::trending::TrendingConfig;
+
+/// Prefix of environment variables overriding settings
+const ENV_PREFIX: &str = "THUNDER_";
+/// Most post store shards
+const MAX_SHARDS: usize = 1024;
+/// Prefixes of the variables Kubernetes sets for a service named `thunder`,
+/// which strict loading leaves alone, e.g. `THUNDER_SERVICE_HOST`
+const SERVICE_LINK_PREFIXES: [&str; 2] = ["SERVICE_", "PORT"];
 
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
 pub struct ThunderConfig {
+    pub grpc_port: u16,
+    pub http_port: u16,
+    /// Serve queries; otherwise only ingest
+    pub is_serving: bool,
+    /// Serve `/debug/pprof` profiles (with the `profiling` feature)
+    pub enable_profiling: bool,
+    /// Bearer token admin endpoints such as `/debug/pprof` and
+    /// `/api/engagement` require
+    pub admin_token: Option<Secret>,
+    /// Most updates one `/api/engagement` request may carry
+    pub max_engagement_batch: usize,
+    /// Maximum results per query
     pub max_posts: usize,
+    /// Post retention period in seconds
     pub retention_seconds: u64,
+    /// JSON file extending the retention period for chosen authors or author
+    /// tiers; every post is kept for the longest period that applies to it
+    pub retention_config: Option<PathBuf>,
+    /// Interval between trims of expired posts in seconds
+    pub trim_interval_seconds: u64,
+    pub request_timeout_ms: u64,
+    /// Queries handled at once; more are shed
+    pub max_concurrent_requests: usize,
+    /// Interval between stats log lines in seconds
+    pub stats_interval_seconds: u64,
+    /// Most messages the consumer may lag behind the stream before `/ready`
+    /// reports the replica unhealthy; unlimited when unset
+    pub max_ready_lag: Option<u64>,
+    /// Post-event stream to consume; none when unset
+    pub ingest: Option<IngestConfig>,
+    /// gRPC address of a leader to mirror, e.g. `http://thunder-0:50051`. The
+    /// post stream is consumed only once promoted at `/admin/replication/promote`.
+    pub replicate_from: Option<String>,
+    /// Replay newline-delimited JSON post events from this file instead of
+    /// consuming a stream, for local development
+    pub replay_file: Option<PathBuf>,
+    /// Speed multiplier for the replay's `at_ms` timing; 0 replays everything at once
+    pub replay_speed: f64,
+    /// Number of post store shards; posts are sharded by author id
+    pub post_store_shards: usize,
+    pub storage_backend: StorageBackend,
+    /// Directory for disk-backed post storage, one subdirectory per shard
+    pub storage_dir: Option<PathBuf>,
+    /// Compression of stored post text (in-memory storage only)
+    pub text_compression: TextCompression,
+    /// Dictionary of typical post text to prime the text compression with
+    pub compression_dictionary: Option<PathBuf>,
+    /// Estimated memory budget for the post store in bytes, across all shards;
+    /// the oldest posts are evicted first when exceeded. Unlimited when unset.
+    pub max_store_bytes: Option<u64>,
+    /// Directory for post store snapshots; snapshotting is disabled when unset
+    pub snapshot_dir: Option<PathBuf>,
+    pub snapshot_interval_seconds: u64,
+    pub snapshots_to_keep: usize,
+    /// Directory for the ingestion write-ahead log; disabled when unset
+    pub wal_dir: Option<PathBuf>,
+    /// Size at which the active WAL segment is rotated, in bytes
+    pub wal_segment_bytes: u64,
+    pub trending: TrendingConfig,
+    /// Pick the best posts instead of the newest when the limit cuts results
+    pub pre_rank: Option<PreRankConfig>,
+    /// How many more posts than the limit queries fetch, to make up for filtering
+    pub over_fetch: OverFetchConfig,
+    /// Post to a webhook when health indicators breach their thresholds
+    pub alerting: Option<AlertingConfig>,
+}
+
+impl Default for ThunderConfig {
+    fn default() -> Self {
+        Self {
+            grpc_port: 50051,
+            http_port: 8080,
+            is_serving: true,
+            enable_profiling: false,
+            admin_token: None,
+            max_engagement_batch: 1000,
+            max_posts: 100,
+            retention_seconds: 7 * 24 * 60 * 60,
+            retention_config: None,
+            trim_interval_seconds: 60,
+            request_timeout_ms: 5000,
+            max_concurrent_requests: 1000,
+            stats_interval_seconds: 60,
+            max_ready_lag: None,
+            ingest: None,
+            replicate_from: None,
+            replay_file: None,
+            replay_speed: 1.0,
+            post_store_shards: 8,
+            storage_backend: StorageBackend::Memory,
+            storage_dir: None,
+            text_compression: TextCompression::None,
+            compression_dictionary: None,
+            max_store_bytes: None,
+            snapshot_dir: None,
+            snapshot_interval_seconds: 300,
+            snapshots_to_keep: 3,
+            wal_dir: None,
+            wal_segment_bytes: 64 * 1024 * 1024,
+            trending: TrendingConfig::default(),
+            pre_rank: None,
+            over_fetch: OverFetchConfig::default(),
+            alerting: None,
+        }
+    }
+}
+
+impl ThunderConfig {
+    /// Read the config file at `path`, or the defaults without one, and apply
+    /// the environment's overrides; `validate` the result once it is complete
+    pub fn load(path: Option<&Path>) -> Result<Self, String> {
+        Self::read(path)?.with_overrides(std::env::vars())
+    }
+
+    /// `load`, rejecting `THUNDER_*` variables that match no setting
+    pub fn load_strict(path: Option<&Path>) -> Result<Self, String> {
+        Self::read(path)?.with_strict_overrides(std::env::vars())
+    }
+
+    /// JSON schema of the config file
+    pub fn json_schema() -> Value {
+        serde_json::to_value(schemars::schema_for!(ThunderConfig)).unwrap_or_default()
+    }
+
+    fn read(path: Option<&Path>) -> Result<Self, String> {
+        match path {
+            Some(path) => Self::from_file(path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Read a TOML, YAML or JSON config file, by its extension
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        let config = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => toml::from_str(&contents).map_err(|e| e.to_string()),
+            Some("yaml" | "yml") => serde_yaml::from_str(&contents).map_err(|e| e.to_string()),
+            Some("json") => serde_json::from_str(&contents).map_err(|e| e.to_string()),
+            _ => Err("expected a .toml, .yaml, .yml or .json file".to_string()),
+        };
+        config.map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Apply the `THUNDER_*` variables of `vars`. Values are read as JSON where
+    /// they parse, except for text settings, so an unset section can be set
+    /// whole, e.g. `THUNDER_PRE_RANK={}`. Variables matching no setting are
+    /// ignored.
+    pub fn with_overrides(
+        self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, String> {
+        self.apply_overrides(vars, false)
+    }
+
+    /// `with_overrides`, rejecting variables that match no setting
+    pub fn with_strict_overrides(
+        self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, String> {
+        self.apply_overrides(vars, true)
+    }
+
+    fn apply_overrides(
+        self,
+        vars: impl IntoIterator<Item = (String, String)>,
+        strict: bool,
+    ) -> Result<Self, String> {
+        let mut settings = serde_json::to_value(&self).map_err(|e| e.to_string())?;
+        let mut errors = Vec::new();
+        for (name, raw) in vars {
+            let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let Some(setting) = find_setting(&mut settings, &key.to_ascii_lowercase()) else {
+                if strict && !SERVICE_LINK_PREFIXES.iter().any(|p| key.starts_with(p)) {
+                    errors.push(format!("{} matches no setting{}", name, suggestion(key)));
+                } else if !strict {
+                    warn!("Ignoring {}, which matches no setting", name);
+                }
+                continue;
+            };
+            let previous = setting.clone();
+            *setting = match setting {
+                Value::String(_) => Value::String(raw.clone()),
+                _ => serde_json::from_str(&raw).unwrap_or(Value::String(raw.clone())),
+            };
+            // Checked one at a time to name the variable at fault
+            if let Err(e) = Self::deserialize(&settings) {
+                errors.push(format!("{}={:?}: {}", name, raw, e));
+                if let Some(setting) = find_setting(&mut settings, &key.to_ascii_lowercase()) {
+                    *setting = previous;
+                }
+            }
+        }
+        if !errors.is_empty() {
+            return Err(format!("environment overrides: {}", errors.join("; ")));
+        }
+        serde_json::from_value(settings).map_err(|e| format!("environment overrides: {}", e))
+    }
+
+    /// Check that every setting is within a sane range, listing all that aren't
+    pub fn validate(&self) -> Result<(), String> {
+        let mut errors = Vec::new();
+        let mut check = |valid: bool, error: &str| {
+            if !valid {
+                errors.push(error.to_string());
+            }
+        };
+        check(self.retention_seconds > 0, "retention_seconds must be positive");
+        check(self.max_posts > 0, "max_posts must be positive");
+        check(self.max_engagement_batch > 0, "max_engagement_batch must be positive");
+        check(self.trim_interval_seconds > 0, "trim_interval_seconds must be positive");
+        check(self.request_timeout_ms > 0, "request_timeout_ms must be positive");
+        check(self.max_concurrent_requests > 0, "max_concurrent_requests must be positive");
+        check(self.stats_interval_seconds > 0, "stats_interval_seconds must be positive");
+        check(
+            self.replay_speed.is_finite() && self.replay_speed >= 0.0,
+            "replay_speed must not be negative",
+        );
+        check(
+            (1..=MAX_SHARDS).contains(&self.post_store_shards),
+            &format!("post_store_shards must be between 1 and {}", MAX_SHARDS),
+        );
+        check(
+            self.storage_backend == StorageBackend::Memory || self.storage_dir.is_some(),
+            "the rocksdb storage backend requires storage_dir",
+        );
+        check(
+            self.snapshot_interval_seconds > 0,
+            "snapshot_interval_seconds must be positive",
+        );
+        check(self.snapshots_to_keep > 0, "snapshots_to_keep must be positive");
+        check(self.wal_segment_bytes > 0, "wal_segment_bytes must be positive");
+        check(
+            !self.is_serving || self.grpc_port != self.http_port,
+            "grpc_port and http_port must differ",
+        );
+        check(self.trending.window_seconds > 0, "trending.window_seconds must be positive");
+        check(self.trending.factor > 0.0, "trending.factor must be positive");
+        if let Some(pre_rank) = &self.pre_rank {
+            check(
+                pre_rank.half_life_seconds > 0,
+                "pre_rank.half_life_seconds must be positive",
+            );
+            check(pre_rank.max_per_author > 0, "pre_rank.max_per_author must be positive");
+            check(pre_rank.pool_factor > 0, "pre_rank.pool_factor must be positive");
+        }
+        check(
+            !self.enable_profiling || self.admin_token.as_ref().is_some_and(|t| !t.is_empty()),
+            "enable_profiling requires admin_token",
+        );
+        if let Some(leader) = &self.replicate_from {
+            check(!leader.is_empty(), "replicate_from must not be empty");
+            // Followers are promoted over HTTP by an admin
+            check(
+                self.is_serving && self.admin_token.as_ref().is_some_and(|t| !t.is_empty()),
+                "replicate_from requires is_serving and admin_token",
+            );
+        }
+        if let Some(alerting) = &self.alerting {
+            check(!alerting.webhook_url.is_empty(), "alerting.webhook_url must be set");
+            check(alerting.interval_seconds > 0, "alerting.interval_seconds must be positive");
+        }
+        let over_fetch = &self.over_fetch;
+        check(over_fetch.factor >= 1.0, "over_fetch.factor must be at least 1");
+        check(
+            over_fetch.min_factor >= 1.0 && over_fetch.min_factor <= over_fetch.max_factor,
+            "over_fetch.min_factor must be at least 1 and at most over_fetch.max_factor",
+        );
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("invalid config: {}", errors.join("; ")))
+        }
+    }
+}
+
+/// A setting kept out of logs
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Secret(..)")
+    }
+}
+
+/// The setting named `key`, where a section's settings are named after the
+/// section and the setting joined by `_`
+fn find_setting<'a>(settings: &'a mut Value, key: &str) -> Option<&'a mut Value> {
+    let settings = settings.as_object_mut()?;
+    if settings.contains_key(key) {
+        return settings.get_mut(key);
+    }
+    let (section, rest) = settings
+        .iter()
+        .filter(|(_, value)| value.is_object())
+        .find_map(|(section, _)| {
+            let rest = key.strip_prefix(section.as_str())?.strip_prefix('_')?;
+            Some((section.clone(), rest.to_string()))
+        })?;
+    find_setting(settings.get_mut(&section)?, &rest)
+}
+
+/// What to do about `THUNDER_{key}`, which matches no setting: name the close
+/// setting it may be a typo of, or the unset section it belongs to
+fn suggestion(key: &str) -> String {
+    let key = key.to_ascii_lowercase();
+    let every_section = ThunderConfig {
+        ingest: Some(IngestConfig::default()),
+        pre_rank: Some(PreRankConfig::default()),
+        alerting: Some(AlertingConfig::default()),
+        ..ThunderConfig::default()
+    };
+    let Ok(settings) = serde_json::to_value(every_section) else {
+        return String::new();
+    };
+    let mut names = Vec::new();
+    setting_names(&settings, "", &mut names);
+    if names.contains(&key) {
+        let section = names.iter().find(|name| key.starts_with(&format!("{}_", name)));
+        return section.map_or_else(String::new, |section| {
+            format!(
+                " (add a {} section to the config file or set {}{} instead)",
+                section,
+                ENV_PREFIX,
+                section.to_ascii_uppercase()
+            )
+        });
+    }
+    names
+        .iter()
+        .map(|name| (strsim::levenshtein(&key, name), name))
+        .filter(|(distance, _)| *distance <= 3)
+        .min()
+        .map_or_else(String::new, |(_, name)| {
+            format!(" (did you mean {}{}?)", ENV_PREFIX, name.to_ascii_uppercase())
+        })
+}
+
+/// Names of every setting in `settings` and its sections, as `find_setting` takes them
+fn setting_names(settings: &Value, prefix: &str, names: &mut Vec<String>) {
+    let Some(settings) = settings.as_object() else {
+        return;
+    };
+    for (key, value) in settings {
+        let name = format!("{}{}", prefix, key);
+        setting_names(value, &format!("{}_", name), names);
+        names.push(name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ingest_source::IngestProtocol;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn test_file_with_env_overrides() {
+        let dir = std::env::temp_dir().join(format!("thunder_config_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let toml_path = dir.join("thunder.toml");
+        let toml = "retention_seconds = 86400\nstorage_dir = \"/data\"\n\n\
+                    [trending]\nmin_count = 5\n\n[ingest]\nprotocol = \"nats\"\n";
+        fs::write(&toml_path, toml).unwrap();
+
+        let config = ThunderConfig::from_file(&toml_path).unwrap();
+        assert_eq!((config.retention_seconds, config.trending.min_count), (86400, 5));
+        assert_eq!(config.trending.window_seconds, 300);
+        let ingest = config.ingest.as_ref().unwrap();
+        assert_eq!((ingest.protocol, ingest.group.as_str()), (IngestProtocol::Nats, "thunder"));
+
+        let config = config
+            .with_overrides(vars(&[
+                ("THUNDER_TRENDING_MIN_COUNT", "7"),
+                ("THUNDER_INGEST_STREAM", "42"),
+                ("THUNDER_STORAGE_BACKEND", "rocksdb"),
+                ("THUNDER_PRE_RANK", "{\"max_per_author\": 2}"),
+                ("THUNDER_SERVICE_HOST", "10.0.0.1"),
+                ("PATH", "/usr/bin"),
+            ]))
+            .unwrap();
+        assert_eq!(config.trending.min_count, 7);
+        assert_eq!(config.ingest.as_ref().unwrap().stream, "42");
+        assert_eq!(config.storage_backend, StorageBackend::RocksDb);
+        let pre_rank = config.pre_rank.unwrap();
+        assert_eq!((pre_rank.max_per_author, pre_rank.pool_factor), (2, 4));
+        assert!(config.validate().is_ok());
+
+        let yaml_path = dir.join("thunder.yaml");
+        fs::write(&yaml_path, "retention_seconds: 0\npost_store_shards: 0\n").unwrap();
+        let error = ThunderConfig::from_file(&yaml_path).unwrap().validate().unwrap_err();
+        assert!(error.contains("retention_seconds") && error.contains("post_store_shards"));
+
+        fs::write(&yaml_path, "retention_secs: 10\n").unwrap();
+        assert!(ThunderConfig::from_file(&yaml_path).is_err());
+        let bad_port = vars(&[("THUNDER_GRPC_PORT", "x")]);
+        assert!(ThunderConfig::default().with_overrides(bad_port).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_strict_overrides_name_each_problem() {
+        let overrides = vars(&[
+            ("THUNDER_RETENTION_SECOND", "60"),
+            ("THUNDER_PRE_RANK_MAX_PER_AUTHOR", "2"),
+            ("THUNDER_GRPC_PORT", "x"),
+            ("THUNDER_SERVICE_HOST", "10.0.0.1"),
+            ("THUNDER_MAX_POSTS", "50"),
+        ]);
+        let error = ThunderConfig::default().with_strict_overrides(overrides.clone()).unwrap_err();
+        let errors: Vec<_> =
+            error.trim_start_matches("environment overrides: ").split("; ").collect();
+        assert_eq!(
+            errors,
+            [
+                "THUNDER_RETENTION_SECOND matches no setting \
+                 (did you mean THUNDER_RETENTION_SECONDS?)",
+                "THUNDER_PRE_RANK_MAX_PER_AUTHOR matches no setting \
+                 (add a pre_rank section to the config file or set THUNDER_PRE_RANK instead)",
+                "THUNDER_GRPC_PORT=\"x\": invalid type: string \"x\", expected u16",
+            ]
+        );
+
+        // Ignored unless strict, as are variables Kubernetes sets
+        let lenient = &overrides[..2];
+        let config = ThunderConfig::default().with_overrides(lenient.to_vec()).unwrap();
+        assert_eq!(config.retention_seconds, ThunderConfig::default().retention_seconds);
+        let config = ThunderConfig::default().with_strict_overrides(overrides[3..].to_vec());
+        assert_eq!(config.unwrap().max_posts, 50);
+
+        let schema = ThunderConfig::json_schema();
+        assert!(schema["properties"]["retention_seconds"].is_object());
+        assert!(schema["definitions"]["TrendingConfig"]["properties"]["min_count"].is_object());
+    }
+
+    #[test]
+    fn test_profiling_requires_an_admin_token_kept_out_of_logs() {
+        let config = ThunderConfig::default()
+            .with_overrides(vars(&[("THUNDER_ENABLE_PROFILING", "true")]))
+            .unwrap();
+        assert!(config.validate().unwrap_err().contains("admin_token"));
+
+        let config = config
+            .with_overrides(vars(&[("THUNDER_ADMIN_TOKEN", "s3cr3t")]))
+            .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.admin_token.as_ref().unwrap().expose(), "s3cr3t");
+        assert!(!format!("{:?}", config).contains("s3cr3t"));
+    }
+
+    #[test]
+    fn test_followers_need_an_admin_token_to_be_promoted() {
+        let config = ThunderConfig::default()
+            .with_overrides(vars(&[("THUNDER_REPLICATE_FROM", "http://thunder-0:50051")]))
+            .unwrap();
+        assert!(config.validate().unwrap_err().contains("replicate_from"));
+
+        let config = config
+            .with_overrides(vars(&[("THUNDER_ADMIN_TOKEN", "s3cr3t")]))
+            .unwrap();
+        assert!(config.validate().is_ok());
+    }
 }
@@ -0,0 +1,111 @@
+//! Conversation Retrieval for Thunder
+//!
+//! Rebuilds a thread from `reply_to_id` links: the ancestors of a post up to
+//! the thread root, and its replies breadth-first, bounded in depth and size so
+//! a viral thread can't produce an unbounded response.
+
+use serde::{Deserialize, Serialize};
+
+use crate::candidate_source::ThunderCandidate;
+
+/// Upper bounds applied to any conversation request
+pub const MAX_CONVERSATION_DEPTH: usize = 10;
+pub const MAX_CONVERSATION_REPLIES: usize = 500;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConversationLimits {
+    /// Reply levels below the post to include, and ancestors above it
+    pub max_depth: usize,
+    /// Replies to include in total
+    pub max_replies: usize,
+}
+
+impl Default for ConversationLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 5,
+            max_replies: 100,
+        }
+    }
+}
+
+impl ConversationLimits {
+    /// Limits from a request, where 0 means the default; capped at the maximums
+    pub fn from_request(max_depth: u32, max_replies: u32) -> Self {
+        let defaults = Self::default();
+        let pick = |requested: u32, default: usize, max: usize| match requested {
+            0 => default,
+            n => (n as usize).min(max),
+        };
+        Self {
+            max_depth: pick(max_depth, defaults.max_depth, MAX_CONVERSATION_DEPTH),
+            max_replies: pick(max_replies, defaults.max_replies, MAX_CONVERSATION_REPLIES),
+        }
+    }
+}
+
+/// A reply and its distance from the requested post (direct replies are 1)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConversationReply {
+    pub depth: usize,
+    pub post: ThunderCandidate,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Conversation {
+    pub post: ThunderCandidate,
+    /// Posts above `post`, nearest first; stops at the first one not in the store
+    pub ancestors: Vec<ThunderCandidate>,
+    /// Replies breadth-first, oldest first within each parent
+    pub replies: Vec<ConversationReply>,
+    /// Whether replies were left out because of the limits
+    pub truncated: bool,
+}
+
+/// Assemble the conversation around `post_id` from a post lookup and a lookup of
+/// the replies to a post (oldest first)
+pub fn build_conversation(
+    post_id: i64,
+    limits: ConversationLimits,
+    get: impl Fn(i64) -> Option<ThunderCandidate>,
+    replies_to: impl Fn(i64) -> Vec<ThunderCandidate>,
+) -> Option<Conversation> {
+    let post = get(post_id)?;
+
+    let mut ancestors = Vec::new();
+    let mut parent_id = post.reply_to_id;
+    while let Some(id) = parent_id.filter(|_| ancestors.len() < limits.max_depth) {
+        let Some(parent) = get(id) else {
+            break;
+        };
+        parent_id = parent.reply_to_id;
+        ancestors.push(parent);
+    }
+
+    let mut replies = Vec::new();
+    let mut truncated = false;
+    let mut frontier = vec![post.post_id];
+    let mut depth = 1;
+    'levels: while !frontier.is_empty() {
+        let mut next = Vec::new();
+        for parent in frontier {
+            for reply in replies_to(parent) {
+                if depth > limits.max_depth || replies.len() >= limits.max_replies {
+                    truncated = true;
+                    break 'levels;
+                }
+                next.push(reply.post_id);
+                replies.push(ConversationReply { depth, post: reply });
+            }
+        }
+        frontier = next;
+        depth += 1;
+    }
+
+    Some(Conversation {
+        post,
+        ancestors,
+        replies,
+        truncated,
+    })
+}
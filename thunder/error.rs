@@ -0,0 +1,193 @@
+//! Errors of Thunder Queries
+//!
+//! Every way a query can fail, so callers can tell failures worth retrying
+//! (shed load, timeouts, a store still catching up) from those that will fail
+//! the same way again. Each error maps to a gRPC status and an HTTP status
+//! code. Several errors share a gRPC code, so the error's kind also travels in
+//! the `thunder-error` metadata of the status.
+
+use std::fmt;
+
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Json, Response};
+use serde::Serialize;
+use tonic::metadata::{MetadataMap, MetadataValue};
+use tonic::{Code, Status};
+
+use crate::candidate_source::SourceError;
+
+/// gRPC metadata key carrying `ThunderError::kind`
+pub const ERROR_KIND_METADATA_KEY: &str = "thunder-error";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ThunderError {
+    /// The request is malformed
+    InvalidArgument(String),
+    /// The query came without the viewer's following list
+    FollowingListUnavailable,
+    /// The store is still loading or catching up with the post stream
+    StoreUninitialized,
+    /// Shed because `max_concurrent` queries were already in flight
+    TooManyRequests { max_concurrent: usize },
+    /// The query ran past its deadline
+    Timeout,
+    /// The requested post isn't in the store
+    PostNotFound(i64),
+    /// The candidate source could not be reached
+    SourceUnavailable(String),
+    /// A replication follower missed changes and has to resync
+    ReplicationLagged { missed: u64 },
+    Internal(String),
+}
+
+impl ThunderError {
+    /// Stable name of the error, e.g. `too_many_requests`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ThunderError::InvalidArgument(_) => "invalid_argument",
+            ThunderError::FollowingListUnavailable => "following_list_unavailable",
+            ThunderError::StoreUninitialized => "store_uninitialized",
+            ThunderError::TooManyRequests { .. } => "too_many_requests",
+            ThunderError::Timeout => "timeout",
+            ThunderError::PostNotFound(_) => "post_not_found",
+            ThunderError::SourceUnavailable(_) => "source_unavailable",
+            ThunderError::ReplicationLagged { .. } => "replication_lagged",
+            ThunderError::Internal(_) => "internal",
+        }
+    }
+
+    /// Whether the same request may succeed if sent again after a backoff
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ThunderError::StoreUninitialized
+                | ThunderError::TooManyRequests { .. }
+                | ThunderError::Timeout
+                | ThunderError::SourceUnavailable(_)
+                | ThunderError::ReplicationLagged { .. }
+        )
+    }
+
+    pub fn grpc_code(&self) -> Code {
+        match self {
+            ThunderError::InvalidArgument(_) => Code::InvalidArgument,
+            ThunderError::FollowingListUnavailable => Code::FailedPrecondition,
+            ThunderError::StoreUninitialized => Code::Unavailable,
+            ThunderError::TooManyRequests { .. } => Code::ResourceExhausted,
+            ThunderError::Timeout => Code::DeadlineExceeded,
+            ThunderError::PostNotFound(_) => Code::NotFound,
+            ThunderError::SourceUnavailable(_) => Code::Unavailable,
+            ThunderError::ReplicationLagged { .. } => Code::DataLoss,
+            ThunderError::Internal(_) => Code::Internal,
+        }
+    }
+
+    pub fn http_status(&self) -> StatusCode {
+        match self {
+            ThunderError::InvalidArgument(_) => StatusCode::BAD_REQUEST,
+            ThunderError::FollowingListUnavailable => StatusCode::UNPROCESSABLE_ENTITY,
+            ThunderError::StoreUninitialized => StatusCode::SERVICE_UNAVAILABLE,
+            ThunderError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ThunderError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            ThunderError::PostNotFound(_) => StatusCode::NOT_FOUND,
+            ThunderError::SourceUnavailable(_) => StatusCode::BAD_GATEWAY,
+            ThunderError::ReplicationLagged { .. } => StatusCode::GONE,
+            ThunderError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl fmt::Display for ThunderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThunderError::InvalidArgument(reason) => write!(f, "{}", reason),
+            ThunderError::FollowingListUnavailable => {
+                write!(f, "following_ids must not be empty")
+            }
+            ThunderError::StoreUninitialized => {
+                write!(f, "post store is still catching up with the post stream")
+            }
+            ThunderError::TooManyRequests { max_concurrent } => {
+                write!(f, "more than {} queries in flight", max_concurrent)
+            }
+            ThunderError::Timeout => write!(f, "query timed out"),
+            ThunderError::PostNotFound(post_id) => write!(f, "post {} not found", post_id),
+            ThunderError::SourceUnavailable(reason) => {
+                write!(f, "candidate source unavailable: {}", reason)
+            }
+            ThunderError::ReplicationLagged { missed } => {
+                write!(f, "follower fell {} changes behind, resync required", missed)
+            }
+            ThunderError::Internal(reason) => write!(f, "internal error: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ThunderError {}
+
+impl From<SourceError> for ThunderError {
+    fn from(error: SourceError) -> Self {
+        match error {
+            SourceError::Unavailable(reason) => ThunderError::SourceUnavailable(reason),
+            SourceError::Timeout => ThunderError::Timeout,
+        }
+    }
+}
+
+impl From<ThunderError> for Status {
+    fn from(error: ThunderError) -> Self {
+        let mut metadata = MetadataMap::new();
+        metadata.insert(ERROR_KIND_METADATA_KEY, MetadataValue::from_static(error.kind()));
+        Status::with_metadata(error.grpc_code(), error.to_string(), metadata)
+    }
+}
+
+/// The `ThunderError::kind` a status was built from, if any
+pub fn error_kind(status: &Status) -> Option<&str> {
+    status
+        .metadata()
+        .get(ERROR_KIND_METADATA_KEY)
+        .and_then(|kind| kind.to_str().ok())
+}
+
+/// Body of an HTTP error response
+#[derive(Debug, Serialize)]
+struct ErrorBody {
+    error: &'static str,
+    message: String,
+    retryable: bool,
+}
+
+impl IntoResponse for ThunderError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            error: self.kind(),
+            message: self.to_string(),
+            retryable: self.is_retryable(),
+        };
+        (self.http_status(), Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_carries_the_error_kind() {
+        let status = Status::from(ThunderError::StoreUninitialized);
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(error_kind(&status), Some("store_uninitialized"));
+
+        // Same gRPC code, told apart by kind
+        let status = Status::from(ThunderError::from(SourceError::Unavailable("down".into())));
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(error_kind(&status), Some("source_unavailable"));
+        assert_eq!(error_kind(&Status::unavailable("plain")), None);
+
+        let shed = ThunderError::TooManyRequests { max_concurrent: 4 };
+        assert!(shed.is_retryable());
+        assert_eq!(shed.into_response().status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(!ThunderError::FollowingListUnavailable.is_retryable());
+    }
+}
@@ -0,0 +1,150 @@
+//! JSON Gateway to the Query RPCs
+//!
+//! Serves `GetInNetworkPosts`, `GetPostsBatch`, `GetConversation` and
+//! `SearchPosts` over HTTP, for web frontends and scripts without a gRPC
+//! client. Requests and responses are the JSON form of the proto messages,
+//! with unset fields taking their proto defaults. Requests are handled by the
+//! same `ThunderServer` methods as gRPC calls, so they are validated, limited
+//! and refused before catch-up alike. Failures are a `ThunderError` body with
+//! its HTTP status.
+//!
+//! | Route | RPC |
+//! |-------|-----|
+//! | `POST /v1/in_network_posts` | `GetInNetworkPosts` |
+//! | `POST /v1/posts_batch` | `GetPostsBatch` |
+//! | `GET /v1/conversations/:post_id?max_depth=&max_replies=` | `GetConversation` |
+//! | `GET /v1/search?query=&limit=&max_age_seconds=` | `SearchPosts` |
+
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use axum::extract::{Json, Path, Query, State};
+use axum::routing::{get, post};
+use axum::Router;
+
+use crate::error::ThunderError;
+use crate::proto;
+use crate::server::ThunderServer;
+
+/// Routes of the gateway, answered by `server`
+pub fn router(server: ThunderServer) -> Router {
+    Router::new()
+        .route("/v1/in_network_posts", post(in_network_posts))
+        .route("/v1/posts_batch", post(posts_batch))
+        .route("/v1/conversations/:post_id", get(conversation))
+        .route("/v1/search", get(search))
+        .with_state(server)
+}
+
+async fn in_network_posts(
+    State(server): State<ThunderServer>,
+    request: Result<Json<proto::GetInNetworkPostsRequest>, JsonRejection>,
+) -> Result<Json<proto::GetInNetworkPostsResponse>, ThunderError> {
+    let Json(request) = request.map_err(|e| invalid(e.body_text()))?;
+    server.in_network_posts(request).await.map(Json)
+}
+
+async fn posts_batch(
+    State(server): State<ThunderServer>,
+    request: Result<Json<proto::GetPostsBatchRequest>, JsonRejection>,
+) -> Result<Json<proto::GetPostsBatchResponse>, ThunderError> {
+    let Json(request) = request.map_err(|e| invalid(e.body_text()))?;
+    server.posts_batch(request).await.map(Json)
+}
+
+async fn conversation(
+    State(server): State<ThunderServer>,
+    post_id: Result<Path<i64>, PathRejection>,
+    request: Result<Query<proto::GetConversationRequest>, QueryRejection>,
+) -> Result<Json<proto::Conversation>, ThunderError> {
+    let Path(post_id) = post_id.map_err(|e| invalid(e.body_text()))?;
+    let Query(request) = request.map_err(|e| invalid(e.body_text()))?;
+    let request = proto::GetConversationRequest { post_id, ..request };
+    server.conversation(request).await.map(Json)
+}
+
+async fn search(
+    State(server): State<ThunderServer>,
+    request: Result<Query<proto::SearchPostsRequest>, QueryRejection>,
+) -> Result<Json<proto::SearchPostsResponse>, ThunderError> {
+    let Query(request) = request.map_err(|e| invalid(e.body_text()))?;
+    server.search(request).await.map(Json)
+}
+
+fn invalid(reason: String) -> ThunderError {
+    ThunderError::InvalidArgument(reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::candidate_source::ThunderCandidate;
+    use crate::ingest::Ingestor;
+    use crate::sharded_store::ShardedPostStore;
+    use crate::snapshot::now_seconds;
+    use crate::wal::{PostEvent, WalRecord};
+
+    async fn call(app: &Router, request: Request<Body>) -> (StatusCode, Value) {
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    fn post_json(uri: &str, body: Value) -> Request<Body> {
+        Request::post(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_gateway_validates_like_grpc() {
+        let ingestor = Arc::new(Ingestor::new(Arc::new(ShardedPostStore::new(2)), None));
+        let now = now_seconds();
+        let thread = [(1, 100, "Shipping #rust", None), (2, 101, "rust is fun", Some(1))];
+        for (offset, author_id, content, reply_to_id) in thread {
+            let mut post = ThunderCandidate::new(offset, author_id, content.into(), now - 10);
+            post.reply_to_id = reply_to_id;
+            let event = PostEvent::Upsert { post };
+            ingestor.ingest(WalRecord { partition: 0, offset, event }).unwrap();
+        }
+        let app = router(ThunderServer::new(ingestor));
+
+        let query = json!({"user_id": 7, "following_ids": [100, 101]});
+        let (status, page) = call(&app, post_json("/v1/in_network_posts", query)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(page["posts"][0]["post_id"], 2);
+        assert_eq!(page["posts"][1]["engagement"]["likes"], 0);
+
+        let query = json!({"user_id": 7});
+        let (status, error) = call(&app, post_json("/v1/in_network_posts", query)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error["error"], "following_list_unavailable");
+
+        let invalid = (StatusCode::BAD_REQUEST, json!("invalid_argument"));
+        let (status, error) = call(&app, post_json("/v1/posts_batch", json!({}))).await;
+        assert_eq!((status, error["error"].clone()), invalid);
+        let malformed = json!({"user_id": "seven"});
+        let (status, error) = call(&app, post_json("/v1/in_network_posts", malformed)).await;
+        assert_eq!((status, error["error"].clone()), invalid);
+
+        let get = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+        let (status, thread) = call(&app, get("/v1/conversations/2?max_depth=5")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(thread["ancestors"][0]["post_id"], 1);
+        let (status, _) = call(&app, get("/v1/conversations/9")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, found) = call(&app, get("/v1/search?query=rust&limit=1")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(found["posts"].as_array().unwrap().len(), 1);
+        let (status, _) = call(&app, get("/v1/search?query=!!")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,343 @@
+//! Ingestion for Thunder
+//!
+//! Applies post-stream events to the post store, logging them to the
+//! write-ahead log first when one is configured, one fsync per batch, and
+//! coordinates snapshots with WAL truncation. Newly applied posts are also published to live
+//! subscribers, and counted towards trending detection when a detector is set.
+//! Every applied change, engagement included, is also streamed to replication
+//! followers.
+
+use std::sync::{Arc, Mutex, RwLock};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::candidate_source::{EngagementDelta, ThunderCandidate};
+use crate::sharded_store::ShardedPostStore;
+use crate::snapshot::{now_seconds, PostStoreSnapshot, SnapshotDir, SnapshotInfo};
+use crate::trending::TrendingDetector;
+use crate::wal::{PostEvent, WalRecord, WriteAheadLog};
+
+/// New posts buffered per subscriber before a slow one starts missing posts
+const SUBSCRIBER_BUFFER: usize = 1024;
+/// Changes buffered per replication follower before a slow one has to resync
+const REPLICATION_BUFFER: usize = 65536;
+
+/// A change applied to the store, as streamed to replication followers
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AppliedChange {
+    Record { record: WalRecord },
+    Engagement {
+        post_id: i64,
+        delta: EngagementDelta,
+        now: u64,
+    },
+}
+
+pub struct Ingestor {
+    store: Arc<ShardedPostStore>,
+    /// Held only while events are logged; they are applied after it is released
+    wal: Option<Mutex<WriteAheadLog>>,
+    /// Where a store restored from a replication leader is saved right away
+    snapshots: Option<Arc<SnapshotDir>>,
+    new_posts: broadcast::Sender<Arc<ThunderCandidate>>,
+    trending: Option<Arc<TrendingDetector>>,
+    changes: broadcast::Sender<AppliedChange>,
+    /// Held shared while a change is logged, applied and sent, and exclusively
+    /// while the store is copied for a follower or a snapshot, so every change
+    /// lands in exactly one of the copy and the stream or WAL segments after it
+    changes_gate: RwLock<()>,
+}
+
+impl Ingestor {
+    pub fn new(store: Arc<ShardedPostStore>, wal: Option<WriteAheadLog>) -> Self {
+        Self {
+            store,
+            wal: wal.map(Mutex::new),
+            snapshots: None,
+            new_posts: broadcast::channel(SUBSCRIBER_BUFFER).0,
+            trending: None,
+            changes: broadcast::channel(REPLICATION_BUFFER).0,
+            changes_gate: RwLock::new(()),
+        }
+    }
+
+    /// Count applied posts towards trending detection by `trending`
+    pub fn with_trending(mut self, trending: Arc<TrendingDetector>) -> Self {
+        self.trending = Some(trending);
+        self
+    }
+
+    /// Save stores restored from a replication leader to `snapshots`
+    pub fn with_snapshots(mut self, snapshots: Arc<SnapshotDir>) -> Self {
+        self.snapshots = Some(snapshots);
+        self
+    }
+
+    pub fn store(&self) -> &Arc<ShardedPostStore> {
+        &self.store
+    }
+
+    pub fn trending(&self) -> Option<&Arc<TrendingDetector>> {
+        self.trending.as_ref()
+    }
+
+    /// Receive every post applied from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<ThunderCandidate>> {
+        self.new_posts.subscribe()
+    }
+
+    /// Copy the store and receive every change applied after the copy
+    pub fn replicate(&self, now: u64) -> (PostStoreSnapshot, broadcast::Receiver<AppliedChange>) {
+        let _gate = self.changes_gate.write().unwrap();
+        (self.store.to_snapshot(now), self.changes.subscribe())
+    }
+
+    /// Log `record` (when a WAL is configured), then apply it to the store
+    pub fn ingest(&self, record: WalRecord) -> Result<bool, String> {
+        Ok(self.ingest_batch(vec![record])? == 1)
+    }
+
+    /// Log `records` with a single fsync (when a WAL is configured), then apply
+    /// them to the store in order; returns how many were applied. Batches of
+    /// one partition must not be ingested concurrently, as they may be applied
+    /// out of order.
+    pub fn ingest_batch(&self, records: Vec<WalRecord>) -> Result<usize, String> {
+        let _gate = self.changes_gate.read().unwrap();
+        if let Some(wal) = &self.wal {
+            let mut wal = wal.lock().unwrap();
+            for record in &records {
+                wal.append(record)?;
+            }
+            wal.sync()?;
+        }
+        Ok(records
+            .into_iter()
+            .map(|record| self.apply(record))
+            .filter(|&applied| applied)
+            .count())
+    }
+
+    /// Apply a logged record and publish it; call with `changes_gate` held
+    fn apply(&self, record: WalRecord) -> bool {
+        let replicated = (self.changes.receiver_count() > 0).then(|| record.clone());
+        let new_post = match &record.event {
+            PostEvent::Upsert { post } if self.new_posts.receiver_count() > 0 => {
+                Some(Arc::new(post.clone()))
+            }
+            _ => None,
+        };
+        let author_id = match &record.event {
+            PostEvent::Upsert { post } => Some(post.author_id),
+            _ => None,
+        };
+        let applied = self.store.apply_record(record);
+        if let (Some(trending), Some(author_id)) = (&self.trending, author_id.filter(|_| applied)) {
+            trending.record_published(author_id, now_seconds());
+        }
+        if let Some(post) = new_post.filter(|_| applied) {
+            // Fails only when every subscriber has gone away
+            let _ = self.new_posts.send(post);
+        }
+        if let Some(record) = replicated.filter(|_| applied) {
+            let _ = self.changes.send(AppliedChange::Record { record });
+        }
+        applied
+    }
+
+    /// Add engagement to a post in the store. Updates are sent to followers but
+    /// deliberately not written to the WAL: they carry no stream offset to
+    /// dedupe replays by, and counts are only a ranking signal, so a restart
+    /// losing those gained since the last snapshot is accepted.
+    pub fn update_engagement(&self, post_id: i64, delta: &EngagementDelta, now: u64) -> bool {
+        let _gate = self.changes_gate.read().unwrap();
+        let updated = self.store.update_engagement(post_id, delta, now);
+        if updated && self.changes.receiver_count() > 0 {
+            let delta = delta.clone();
+            let _ = self.changes.send(AppliedChange::Engagement { post_id, delta, now });
+        }
+        updated
+    }
+
+    /// Replace the store's posts and offsets with those of `snapshot`, as
+    /// copied from a replication leader. The restored store is snapshotted
+    /// locally and the WAL dropped, so a restart recovers it rather than the
+    /// store it replaced; without a snapshot dir it isn't durable until the
+    /// changes that follow are logged.
+    pub fn restore(&self, snapshot: PostStoreSnapshot, now: u64) -> Result<(), String> {
+        let _gate = self.changes_gate.write().unwrap();
+        let mut wal = self.wal.as_ref().map(|wal| wal.lock().unwrap());
+        self.store.restore(snapshot);
+        let Some(wal) = wal.as_mut() else {
+            return Ok(());
+        };
+        let next_segment = wal.rotate()?;
+        if let Some(snapshots) = &self.snapshots {
+            snapshots.write(&self.store.to_snapshot(now))?;
+        }
+        wal.remove_segments_before(next_segment)?;
+        Ok(())
+    }
+
+    /// Write a snapshot of the store, then drop the WAL segments it covers
+    pub fn snapshot(&self, snapshots: &SnapshotDir, now: u64) -> Result<SnapshotInfo, String> {
+        let (snapshot, next_segment) = self.capture(now)?;
+        let info = snapshots.write(&snapshot)?;
+        if let (Some(wal), Some(segment)) = (&self.wal, next_segment) {
+            wal.lock().unwrap().remove_segments_before(segment)?;
+        }
+        Ok(info)
+    }
+
+    /// Copy the store and start a new WAL segment at the same point
+    fn capture(&self, now: u64) -> Result<(PostStoreSnapshot, Option<u64>), String> {
+        let _gate = self.changes_gate.write().unwrap();
+        let mut wal = self.wal.as_ref().map(|wal| wal.lock().unwrap());
+        let snapshot = self.store.to_snapshot(now);
+        let next_segment = wal.as_mut().map(|wal| wal.rotate()).transpose()?;
+        Ok((snapshot, next_segment))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::post_store::PostStore;
+    use crate::wal::PostEvent;
+
+    #[test]
+    fn test_recovery_from_snapshot_plus_wal() {
+        let root = std::env::temp_dir().join(format!("thunder_ingest_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let snapshots = SnapshotDir::open(root.join("snapshots"), 2).unwrap();
+        let upsert = |offset: i64| WalRecord {
+            partition: 0,
+            offset,
+            event: PostEvent::Upsert {
+                post: ThunderCandidate::new(offset, 100, "Post".into(), 1000),
+            },
+        };
+
+        let wal = WriteAheadLog::open(root.join("wal"), 1 << 20).unwrap();
+        let ingestor = Ingestor::new(Arc::new(ShardedPostStore::new(2)), Some(wal));
+        ingestor.ingest(upsert(0)).unwrap();
+        ingestor.ingest(upsert(1)).unwrap();
+        ingestor.snapshot(&snapshots, 2000).unwrap();
+        ingestor.ingest(upsert(2)).unwrap();
+        drop(ingestor);
+
+        // Restart: snapshot first, then whatever the WAL holds beyond it
+        let (snapshot, _) = snapshots.latest().unwrap().unwrap();
+        let mut store = PostStore::from_snapshot(snapshot);
+        assert_eq!(store.len(), 2);
+        let wal = WriteAheadLog::open(root.join("wal"), 1 << 20).unwrap();
+        let mut applied = 0;
+        assert_eq!(wal.replay(|r| applied += store.apply_record(r) as usize).unwrap(), 1);
+        assert_eq!(applied, 1);
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.resume_offsets().get(&0), Some(&3));
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_batches_are_logged_together_then_applied() {
+        let root = std::env::temp_dir().join(format!("thunder_batch_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let upsert = |offset: i64| WalRecord {
+            partition: 0,
+            offset,
+            event: PostEvent::Upsert {
+                post: ThunderCandidate::new(offset, 100, "Post".into(), 1000),
+            },
+        };
+
+        let wal = WriteAheadLog::open(root.join("wal"), 1 << 20).unwrap();
+        let ingestor = Ingestor::new(Arc::new(ShardedPostStore::new(2)), Some(wal));
+        // The repeated offset is logged but already applied
+        let batch = vec![upsert(0), upsert(1), upsert(1), upsert(2)];
+        assert_eq!(ingestor.ingest_batch(batch).unwrap(), 3);
+        assert_eq!(ingestor.store().len(), 3);
+        assert_eq!(ingestor.ingest_batch(Vec::new()).unwrap(), 0);
+
+        // Synced already: readable while the ingestor still holds the log open
+        let wal = WriteAheadLog::open(root.join("wal"), 1 << 20).unwrap();
+        let mut store = PostStore::new();
+        let mut applied = 0;
+        assert_eq!(wal.replay(|r| applied += store.apply_record(r) as usize).unwrap(), 4);
+        assert_eq!(applied, 3);
+        drop(ingestor);
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_restart_after_promotion_recovers_the_restored_store() {
+        let root = std::env::temp_dir().join(format!("thunder_restore_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let snapshots = Arc::new(SnapshotDir::open(root.join("snapshots"), 2).unwrap());
+        let upsert = |partition: i32, offset: i64, post_id: i64| WalRecord {
+            partition,
+            offset,
+            event: PostEvent::Upsert {
+                post: ThunderCandidate::new(post_id, 100, "Post".into(), 1000),
+            },
+        };
+        let leader = Ingestor::new(Arc::new(ShardedPostStore::new(2)), None);
+        leader.ingest(upsert(0, 0, 1)).unwrap();
+        leader.ingest(upsert(0, 1, 2)).unwrap();
+
+        // The follower logged and snapshotted posts of its own before connecting
+        let wal = WriteAheadLog::open(root.join("wal"), 1 << 20).unwrap();
+        let follower = Ingestor::new(Arc::new(ShardedPostStore::new(2)), Some(wal))
+            .with_snapshots(snapshots.clone());
+        follower.ingest(upsert(1, 0, 7)).unwrap();
+        follower.snapshot(&snapshots, 1000).unwrap();
+        follower.ingest(upsert(1, 1, 8)).unwrap();
+        follower.restore(leader.store().to_snapshot(2000), 2000).unwrap();
+        follower.ingest(upsert(0, 2, 3)).unwrap();
+        // Promoted, then restarted
+        drop(follower);
+
+        let (snapshot, _) = snapshots.latest().unwrap().unwrap();
+        let mut store = PostStore::from_snapshot(snapshot);
+        let wal = WriteAheadLog::open(root.join("wal"), 1 << 20).unwrap();
+        let mut applied = 0;
+        assert_eq!(wal.replay(|r| applied += store.apply_record(r) as usize).unwrap(), 1);
+        assert_eq!(applied, 1);
+        let mut post_ids: Vec<i64> = (0..10).filter(|id| store.get(*id).is_some()).collect();
+        post_ids.sort();
+        assert_eq!(post_ids, vec![1, 2, 3]);
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_subscribers_receive_applied_posts() {
+        let ingestor = Ingestor::new(Arc::new(ShardedPostStore::new(2)), None);
+        let mut subscriber = ingestor.subscribe();
+        let upsert = |offset: i64| WalRecord {
+            partition: 0,
+            offset,
+            event: PostEvent::Upsert {
+                post: ThunderCandidate::new(offset, 100, "Post".into(), 1000),
+            },
+        };
+
+        ingestor.ingest(upsert(5)).unwrap();
+        ingestor.ingest(upsert(5)).unwrap(); // already applied
+        ingestor
+            .ingest(WalRecord {
+                partition: 0,
+                offset: 6,
+                event: PostEvent::Delete { post_id: 5 },
+            })
+            .unwrap();
+        ingestor.ingest(upsert(7)).unwrap();
+
+        assert_eq!(subscriber.try_recv().unwrap().post_id, 5);
+        assert_eq!(subscriber.try_recv().unwrap().post_id, 7);
+        assert!(subscriber.try_recv().is_err());
+    }
+}
@@ -0,0 +1,420 @@
+//! Ingest Sources for Thunder
+//!
+//! The post-event stream can be read over Kafka, NATS JetStream or Redis
+//! Streams, each behind its own feature. A source only positions itself and
+//! hands over raw messages; decoding, logging and applying them, and deciding
+//! when Thunder has caught up with the stream, happen in one consumer loop
+//! shared by every protocol. Every message carries one JSON-encoded `PostEvent`.
+//! The loop publishes its lag per partition, refreshing the stream's end
+//! offsets periodically, along with how much of the startup backlog it has read.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::{error, info, warn};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tonic::async_trait;
+
+use crate::ingest::Ingestor;
+use crate::wal::{PostEvent, WalRecord};
+
+/// How long a poll waits for new messages before returning an empty batch
+pub const POLL_TIMEOUT: Duration = Duration::from_millis(500);
+/// Most messages returned by one poll
+pub const MAX_POLL_MESSAGES: usize = 500;
+/// Pause before polling again after a source error
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+/// Interval between refreshes of the stream's end offsets, and between
+/// progress log lines while catching up
+const LAG_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Protocol the post-event stream is read over
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum IngestProtocol {
+    #[default]
+    Kafka,
+    Nats,
+    Redis,
+}
+
+/// Where to read the post-event stream from
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct IngestConfig {
+    pub protocol: IngestProtocol,
+    /// Kafka bootstrap servers, or the NATS or Redis server URL
+    pub url: String,
+    /// Kafka topic, JetStream stream, or Redis stream key
+    pub stream: String,
+    /// Kafka consumer group
+    pub group: String,
+}
+
+impl Default for IngestConfig {
+    fn default() -> Self {
+        Self {
+            protocol: IngestProtocol::Kafka,
+            url: "localhost:9092".to_string(),
+            stream: "thunder-post-events".to_string(),
+            group: "thunder".to_string(),
+        }
+    }
+}
+
+/// A message read from the stream, still encoded
+#[derive(Clone, Debug)]
+pub struct StreamMessage {
+    pub partition: i32,
+    pub offset: i64,
+    pub payload: Vec<u8>,
+}
+
+/// A partitioned, offset-addressed stream of post events
+#[async_trait]
+pub trait IngestSource: Send {
+    /// Start reading each partition at its offset in `offsets`; partitions
+    /// missing from `offsets` are read from the start
+    async fn seek(&mut self, offsets: &BTreeMap<i32, i64>) -> Result<(), String>;
+
+    /// Offset after the newest message of each partition, as of now
+    async fn end_offsets(&mut self) -> Result<BTreeMap<i32, i64>, String>;
+
+    /// Up to `MAX_POLL_MESSAGES` messages, or none once `POLL_TIMEOUT` passes
+    /// without any
+    async fn poll(&mut self) -> Result<Vec<StreamMessage>, String>;
+}
+
+/// Connect to the stream described by `config`
+pub async fn open_source(config: &IngestConfig) -> Result<Box<dyn IngestSource>, String> {
+    match config.protocol {
+        #[cfg(feature = "kafka")]
+        IngestProtocol::Kafka => Ok(Box::new(crate::kafka_source::KafkaSource::open(config)?)),
+        #[cfg(feature = "nats")]
+        IngestProtocol::Nats => Ok(Box::new(crate::nats_source::NatsSource::open(config).await?)),
+        #[cfg(feature = "redis")]
+        IngestProtocol::Redis => {
+            Ok(Box::new(crate::redis_source::RedisSource::open(config).await?))
+        }
+        #[allow(unreachable_patterns)]
+        protocol => Err(format!("thunder was built without {:?} support", protocol)),
+    }
+}
+
+/// Read position and lag of one partition
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct PartitionLag {
+    pub partition: i32,
+    /// Offset of the next message to read
+    pub position: i64,
+    /// Offset after the newest message, as of the last refresh
+    pub end_offset: i64,
+    pub lag: u64,
+}
+
+/// How far the consumer is behind the stream
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct CatchUpStatus {
+    /// Whether every message published before startup has been read
+    pub caught_up: bool,
+    /// Share of the startup backlog read so far
+    pub percent_complete: f64,
+    /// Seconds until caught up at the rate read so far; unknown until the
+    /// first messages are read
+    pub eta_seconds: Option<u64>,
+    /// Messages behind, across partitions
+    pub lag: u64,
+    pub partitions: Vec<PartitionLag>,
+}
+
+impl CatchUpStatus {
+    /// Whether the replica is fit to serve: caught up and, when `max_lag` is
+    /// set, no more than that many messages behind
+    pub fn is_ready(&self, max_lag: Option<u64>) -> bool {
+        self.caught_up && max_lag.is_none_or(|max_lag| self.lag <= max_lag)
+    }
+}
+
+/// Positions of the consumer against the stream's end offsets
+struct ConsumerProgress {
+    started: Instant,
+    /// Positions and end offsets at startup, bounding the backlog to catch up on
+    start_positions: BTreeMap<i32, i64>,
+    startup_end_offsets: BTreeMap<i32, i64>,
+    positions: BTreeMap<i32, i64>,
+    end_offsets: BTreeMap<i32, i64>,
+    caught_up: bool,
+}
+
+impl ConsumerProgress {
+    fn new(positions: BTreeMap<i32, i64>, end_offsets: BTreeMap<i32, i64>) -> Self {
+        Self {
+            started: Instant::now(),
+            start_positions: positions.clone(),
+            startup_end_offsets: end_offsets.clone(),
+            positions,
+            end_offsets,
+            caught_up: false,
+        }
+    }
+
+    fn position(&self, partition: i32) -> i64 {
+        self.positions.get(&partition).copied().unwrap_or(0)
+    }
+
+    fn read(&mut self, partition: i32, offset: i64) {
+        let next = self.positions.entry(partition).or_insert(0);
+        *next = (*next).max(offset + 1);
+    }
+
+    /// Messages of the startup backlog read, and left to read
+    fn backlog(&self) -> (u64, u64) {
+        let (mut read, mut left) = (0, 0);
+        for (partition, end) in &self.startup_end_offsets {
+            let start = self.start_positions.get(partition).copied().unwrap_or(0).min(*end);
+            let position = self.position(*partition).clamp(start, *end);
+            read += (position - start) as u64;
+            left += (end - position) as u64;
+        }
+        (read, left)
+    }
+
+    /// Latch `caught_up` once the startup backlog is read; true the first time
+    fn check_caught_up(&mut self) -> bool {
+        if self.caught_up || self.backlog().1 > 0 {
+            return false;
+        }
+        self.caught_up = true;
+        true
+    }
+
+    fn status(&self) -> CatchUpStatus {
+        let (read, left) = self.backlog();
+        let percent_complete = if read + left == 0 {
+            100.0
+        } else {
+            read as f64 * 100.0 / (read + left) as f64
+        };
+        let eta_seconds = match (left, read) {
+            (0, _) => Some(0),
+            (_, 0) => None,
+            _ => Some((self.started.elapsed().as_secs_f64() * left as f64 / read as f64) as u64),
+        };
+        let partitions: Vec<PartitionLag> = self
+            .end_offsets
+            .iter()
+            .map(|(partition, end_offset)| {
+                let position = self.position(*partition);
+                PartitionLag {
+                    partition: *partition,
+                    position,
+                    end_offset: *end_offset,
+                    lag: (end_offset - position).max(0) as u64,
+                }
+            })
+            .collect();
+        CatchUpStatus {
+            caught_up: self.caught_up,
+            percent_complete,
+            eta_seconds,
+            lag: partitions.iter().map(|p| p.lag).sum(),
+            partitions,
+        }
+    }
+}
+
+/// Consume `source` into `ingestor` from the store's resume offsets. The
+/// returned status turns `caught_up` once the stream's end offsets at startup
+/// have been read, i.e. Thunder holds everything published before it started,
+/// and keeps tracking the lag from then on.
+pub fn spawn_consumer(
+    mut source: Box<dyn IngestSource>,
+    ingestor: Arc<Ingestor>,
+) -> watch::Receiver<CatchUpStatus> {
+    let (status_tx, status_rx) = watch::channel(CatchUpStatus::default());
+    tokio::spawn(async move {
+        if let Err(e) = consume(source.as_mut(), &ingestor, &status_tx).await {
+            error!("Post stream consumer stopped: {}", e);
+        }
+    });
+    status_rx
+}
+
+async fn consume(
+    source: &mut dyn IngestSource,
+    ingestor: &Ingestor,
+    status_tx: &watch::Sender<CatchUpStatus>,
+) -> Result<(), String> {
+    let positions = ingestor.store().resume_offsets();
+    source.seek(&positions).await?;
+    let end_offsets = source.end_offsets().await?;
+    info!("Consuming post stream from {:?} up to {:?}", positions, end_offsets);
+    let mut progress = ConsumerProgress::new(positions, end_offsets);
+    let mut refreshed = Instant::now();
+
+    loop {
+        if progress.check_caught_up() {
+            info!("Caught up with the post stream at {:?}", progress.positions);
+        }
+        if refreshed.elapsed() >= LAG_REFRESH_INTERVAL {
+            refreshed = Instant::now();
+            match source.end_offsets().await {
+                Ok(end_offsets) => progress.end_offsets = end_offsets,
+                Err(e) => warn!("Failed to refresh the post stream's end offsets: {}", e),
+            }
+            let status = progress.status();
+            if !status.caught_up {
+                info!(
+                    "Catching up with the post stream: {:.1}% (lag: {}, ETA: {:?}s)",
+                    status.percent_complete, status.lag, status.eta_seconds
+                );
+            }
+        }
+        status_tx.send_replace(progress.status());
+
+        let messages = match source.poll().await {
+            Ok(messages) => messages,
+            Err(e) => {
+                warn!("Failed to poll the post stream: {}", e);
+                tokio::time::sleep(RETRY_DELAY).await;
+                continue;
+            }
+        };
+        let mut records = Vec::with_capacity(messages.len());
+        for message in messages {
+            progress.read(message.partition, message.offset);
+            let event: PostEvent = match serde_json::from_slice(&message.payload) {
+                Ok(event) => event,
+                Err(e) => {
+                    warn!(
+                        "Skipping undecodable event at {}:{}: {}",
+                        message.partition, message.offset, e
+                    );
+                    continue;
+                }
+            };
+            records.push(WalRecord {
+                partition: message.partition,
+                offset: message.offset,
+                event,
+            });
+        }
+        // One fsync covers the whole poll
+        ingestor.ingest_batch(records)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backfill::BACKFILL_PARTITION;
+    use crate::candidate_source::ThunderCandidate;
+    use crate::sharded_store::ShardedPostStore;
+
+    /// Messages on partition 0, with offsets from 0
+    struct VecSource {
+        messages: Vec<Vec<u8>>,
+        next: usize,
+    }
+
+    #[async_trait]
+    impl IngestSource for VecSource {
+        async fn seek(&mut self, offsets: &BTreeMap<i32, i64>) -> Result<(), String> {
+            self.next = offsets.get(&0).copied().unwrap_or(0) as usize;
+            Ok(())
+        }
+
+        async fn end_offsets(&mut self) -> Result<BTreeMap<i32, i64>, String> {
+            Ok(BTreeMap::from([(0, self.messages.len() as i64)]))
+        }
+
+        async fn poll(&mut self) -> Result<Vec<StreamMessage>, String> {
+            let Some(payload) = self.messages.get(self.next) else {
+                tokio::time::sleep(POLL_TIMEOUT).await;
+                return Ok(Vec::new());
+            };
+            let message = StreamMessage {
+                partition: 0,
+                offset: self.next as i64,
+                payload: payload.clone(),
+            };
+            self.next += 1;
+            Ok(vec![message])
+        }
+    }
+
+    fn upsert(post_id: i64) -> Vec<u8> {
+        let post = ThunderCandidate::new(post_id, 100, "Post".into(), 1000);
+        serde_json::to_vec(&PostEvent::Upsert { post }).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_consumer_resumes_and_signals_catch_up() {
+        let store = Arc::new(ShardedPostStore::new(2));
+        let ingestor = Arc::new(Ingestor::new(store.clone(), None));
+        // Offset 0 was applied before a restart
+        let record = WalRecord {
+            partition: 0,
+            offset: 0,
+            event: serde_json::from_slice(&upsert(1)).unwrap(),
+        };
+        ingestor.ingest(record).unwrap();
+
+        // The last message can't be decoded but still counts towards catching up
+        let source = VecSource {
+            messages: vec![upsert(1), upsert(2), upsert(3), b"{".to_vec()],
+            next: 0,
+        };
+        let mut status = spawn_consumer(Box::new(source), ingestor.clone());
+        let status = status.wait_for(|status| status.caught_up).await.unwrap().clone();
+        assert_eq!(status.percent_complete, 100.0);
+        assert_eq!(status.lag, 0);
+        assert!(status.is_ready(Some(0)));
+
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.event_counts().skipped, 0);
+        assert_eq!(store.resume_offsets().get(&0), Some(&3));
+    }
+
+    #[test]
+    fn test_progress_reports_backlog_and_lag() {
+        // Partition 1 resumes at 10 of 30; partition 2 is new with 10 messages
+        let positions = BTreeMap::from([(BACKFILL_PARTITION, 5), (1, 10)]);
+        let mut progress = ConsumerProgress::new(positions, BTreeMap::from([(1, 30), (2, 10)]));
+        for offset in 10..20 {
+            progress.read(1, offset);
+        }
+        assert!(!progress.check_caught_up());
+        let status = progress.status();
+        assert_eq!(status.percent_complete.round(), 33.0);
+        assert!(status.eta_seconds.is_some());
+        assert_eq!(status.lag, 20);
+        assert!(!status.is_ready(None));
+
+        for offset in 20..30 {
+            progress.read(1, offset);
+        }
+        for offset in 0..10 {
+            progress.read(2, offset);
+        }
+        assert!(progress.check_caught_up());
+        assert!(!progress.check_caught_up());
+
+        // Messages published since startup count towards the lag only
+        progress.end_offsets.insert(1, 45);
+        let status = progress.status();
+        assert_eq!((status.percent_complete, status.eta_seconds), (100.0, Some(0)));
+        let partition = PartitionLag {
+            partition: 1,
+            position: 30,
+            end_offset: 45,
+            lag: 15,
+        };
+        assert_eq!(status.partitions[0], partition);
+        assert!(status.is_ready(Some(15)));
+        assert!(!status.is_ready(Some(10)));
+    }
+}
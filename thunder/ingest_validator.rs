@@ -0,0 +1,305 @@
+//! Ingest Validator for Thunder
+//!
+//! A dry run over post-event streams, for vetting a stream before a production
+//! Thunder consumes it. Each stream is read from the start up to its end
+//! offsets at startup and every event is checked, without applying anything.
+//! Events that don't decode are malformed, ones that decode but carry
+//! impossible values are invalid, and ones that go back within their partition
+//! (in offset, in creation time, or touching a post already deleted) are out of
+//! order. The result is a report per stream with its error rate and the first
+//! problems found.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::candidate_source::ThunderCandidate;
+use crate::config::ThunderConfig;
+use crate::ingest_source::{open_source, IngestConfig, IngestSource, StreamMessage};
+use crate::replay_source::ReplaySource;
+use crate::snapshot::now_seconds;
+use crate::wal::PostEvent;
+
+/// Leeway for producers' clocks: posts may be created this far in the future,
+/// or this far before the newest post of their partition
+const MAX_CLOCK_SKEW_SECONDS: u64 = 300;
+/// Problems kept per stream as examples
+const MAX_SAMPLES: usize = 20;
+
+/// What a dry run found in one stream
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ValidationReport {
+    pub stream: String,
+    pub messages: u64,
+    pub upserts: u64,
+    pub edits: u64,
+    pub deletes: u64,
+    pub malformed: u64,
+    pub invalid: u64,
+    pub out_of_order: u64,
+    /// Share of messages with a problem
+    pub error_rate: f64,
+    /// The first problems found, as `partition:offset: problem`
+    pub samples: Vec<String>,
+}
+
+impl ValidationReport {
+    pub fn errors(&self) -> u64 {
+        self.malformed + self.invalid + self.out_of_order
+    }
+}
+
+enum Problem {
+    Malformed(String),
+    Invalid(String),
+    OutOfOrder(String),
+}
+
+#[derive(Default)]
+struct PartitionState {
+    last_offset: Option<i64>,
+    newest_created_at: u64,
+}
+
+/// Checks the messages of one stream in the order they are read
+pub struct StreamValidator {
+    report: ValidationReport,
+    partitions: HashMap<i32, PartitionState>,
+    deleted: HashSet<i64>,
+}
+
+impl StreamValidator {
+    pub fn new(stream: impl Into<String>) -> Self {
+        Self {
+            report: ValidationReport {
+                stream: stream.into(),
+                ..Default::default()
+            },
+            partitions: HashMap::new(),
+            deleted: HashSet::new(),
+        }
+    }
+
+    /// Check the next message read, as of `now` (Unix seconds)
+    pub fn check(&mut self, message: &StreamMessage, now: u64) {
+        self.report.messages += 1;
+        let problem = match self.problem(message, now) {
+            Some(Problem::Malformed(e)) => {
+                self.report.malformed += 1;
+                format!("malformed: {}", e)
+            }
+            Some(Problem::Invalid(e)) => {
+                self.report.invalid += 1;
+                format!("invalid: {}", e)
+            }
+            Some(Problem::OutOfOrder(e)) => {
+                self.report.out_of_order += 1;
+                format!("out of order: {}", e)
+            }
+            None => return,
+        };
+        if self.report.samples.len() < MAX_SAMPLES {
+            let sample = format!("{}:{}: {}", message.partition, message.offset, problem);
+            self.report.samples.push(sample);
+        }
+    }
+
+    fn problem(&mut self, message: &StreamMessage, now: u64) -> Option<Problem> {
+        let partition = self.partitions.entry(message.partition).or_default();
+        let previous_offset = partition.last_offset;
+        partition.last_offset = previous_offset.max(Some(message.offset));
+
+        let event: PostEvent = match serde_json::from_slice(&message.payload) {
+            Ok(event) => event,
+            Err(e) => return Some(Problem::Malformed(e.to_string())),
+        };
+        let post_id = match &event {
+            PostEvent::Upsert { post } => {
+                self.report.upserts += 1;
+                post.post_id
+            }
+            PostEvent::Edit { post_id, .. } => {
+                self.report.edits += 1;
+                *post_id
+            }
+            PostEvent::Delete { post_id } => {
+                self.report.deletes += 1;
+                *post_id
+            }
+        };
+        if post_id <= 0 {
+            return Some(Problem::Invalid(format!("post_id {} is not positive", post_id)));
+        }
+        if let PostEvent::Upsert { post } = &event {
+            if let Some(e) = invalid_post(post, now) {
+                return Some(Problem::Invalid(e));
+            }
+        }
+
+        if let Some(previous) = previous_offset.filter(|&previous| message.offset <= previous) {
+            return Some(Problem::OutOfOrder(format!("offset after {}", previous)));
+        }
+        if self.deleted.contains(&post_id) {
+            return Some(Problem::OutOfOrder(format!("post {} was already deleted", post_id)));
+        }
+        match &event {
+            PostEvent::Upsert { post } => {
+                let newest = partition.newest_created_at;
+                partition.newest_created_at = newest.max(post.created_at);
+                if post.created_at + MAX_CLOCK_SKEW_SECONDS < newest {
+                    let behind = newest - post.created_at;
+                    let e = format!("created {}s before the partition's newest post", behind);
+                    return Some(Problem::OutOfOrder(e));
+                }
+            }
+            PostEvent::Delete { .. } => {
+                self.deleted.insert(post_id);
+            }
+            PostEvent::Edit { .. } => {}
+        }
+        None
+    }
+
+    pub fn finish(mut self) -> ValidationReport {
+        if self.report.messages > 0 {
+            self.report.error_rate = self.report.errors() as f64 / self.report.messages as f64;
+        }
+        self.report
+    }
+}
+
+/// Why `post` couldn't have been produced, if anything
+fn invalid_post(post: &ThunderCandidate, now: u64) -> Option<String> {
+    if post.author_id <= 0 {
+        return Some(format!("author_id {} is not positive", post.author_id));
+    }
+    if post.created_at == 0 {
+        return Some("created_at is not set".to_string());
+    }
+    if post.created_at > now + MAX_CLOCK_SKEW_SECONDS {
+        return Some(format!("created_at {} is in the future", post.created_at));
+    }
+    if post.is_reply != post.reply_to_id.is_some() {
+        return Some("is_reply disagrees with reply_to_id".to_string());
+    }
+    None
+}
+
+/// Read `source` from the start up to its end offsets as of now, or until
+/// `max_messages` are read, checking every message
+pub async fn validate_stream(
+    source: &mut dyn IngestSource,
+    mut validator: StreamValidator,
+    max_messages: Option<u64>,
+    now: u64,
+) -> Result<ValidationReport, String> {
+    source.seek(&BTreeMap::new()).await?;
+    let end_offsets = source.end_offsets().await?;
+    let mut positions: BTreeMap<i32, i64> = BTreeMap::new();
+    let read_all = |positions: &BTreeMap<i32, i64>| {
+        end_offsets
+            .iter()
+            .all(|(partition, end)| positions.get(partition).copied().unwrap_or(0) >= *end)
+    };
+
+    let read_max = |validator: &StreamValidator| {
+        max_messages.is_some_and(|max| validator.report.messages >= max)
+    };
+
+    while !read_all(&positions) && !read_max(&validator) {
+        for message in source.poll().await? {
+            if read_max(&validator) {
+                break;
+            }
+            let position = positions.entry(message.partition).or_insert(0);
+            *position = (*position).max(message.offset + 1);
+            validator.check(&message, now);
+        }
+    }
+    Ok(validator.finish())
+}
+
+/// Dry-run the replay file when one is configured, otherwise the configured
+/// stream or each of `streams` in its place
+pub async fn validate(
+    config: &ThunderConfig,
+    streams: &[String],
+    max_messages: Option<u64>,
+) -> Result<Vec<ValidationReport>, String> {
+    if let Some(path) = &config.replay_file {
+        let mut source = ReplaySource::open(path, 0.0)?;
+        let validator = StreamValidator::new(path.display().to_string());
+        let report = validate_stream(&mut source, validator, max_messages, now_seconds()).await?;
+        return Ok(vec![report]);
+    }
+    let ingest = config
+        .ingest
+        .as_ref()
+        .ok_or("neither a replay file nor a post-event stream is configured")?;
+    let streams = match streams {
+        [] => vec![ingest.stream.clone()],
+        streams => streams.to_vec(),
+    };
+    let mut reports = Vec::new();
+    for stream in streams {
+        let config = IngestConfig {
+            stream: stream.clone(),
+            ..ingest.clone()
+        };
+        let mut source = open_source(&config).await?;
+        let validator = StreamValidator::new(stream);
+        let report =
+            validate_stream(source.as_mut(), validator, max_messages, now_seconds()).await?;
+        reports.push(report);
+    }
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(partition: i32, offset: i64, payload: &str) -> StreamMessage {
+        StreamMessage {
+            partition,
+            offset,
+            payload: payload.as_bytes().to_vec(),
+        }
+    }
+
+    const EDIT: &str =
+        r#"{"type":"edit","post_id":1,"content":"x","has_media":false,"has_link":false}"#;
+
+    fn upsert(post_id: i64, created_at: u64) -> String {
+        let post = ThunderCandidate::new(post_id, 100, "Post".into(), created_at);
+        serde_json::to_string(&PostEvent::Upsert { post }).unwrap()
+    }
+
+    #[test]
+    fn test_flags_malformed_invalid_and_out_of_order_events() {
+        let now = 10_000;
+        let mut validator = StreamValidator::new("posts");
+        let messages = [
+            message(0, 0, &upsert(1, 9_000)),
+            message(0, 1, r#"{"type":"delete"}"#),
+            message(0, 2, &upsert(2, now + 3_600)),
+            message(0, 3, r#"{"type":"delete","post_id":1}"#),
+            message(0, 4, EDIT),
+            message(0, 4, &upsert(3, 9_000)),
+            message(0, 5, &upsert(4, 8_000)),
+            // Partitions are ordered independently
+            message(1, 0, &upsert(5, 8_000)),
+        ];
+        for message in &messages {
+            validator.check(message, now);
+        }
+
+        let report = validator.finish();
+        assert_eq!((report.messages, report.upserts, report.edits, report.deletes), (8, 5, 1, 1));
+        assert_eq!((report.malformed, report.invalid, report.out_of_order), (1, 1, 3));
+        assert_eq!(report.error_rate, 5.0 / 8.0);
+        assert_eq!(report.samples.len(), 5);
+        assert!(report.samples[0].starts_with("0:1: malformed"));
+        assert_eq!(report.samples[3], "0:4: out of order: offset after 4");
+    }
+}
@@ -0,0 +1,92 @@
+//! Kafka Ingest Source for Thunder
+//!
+//! Reads the post-event topic with partitions assigned manually rather than
+//! through group rebalancing, since Thunder tracks its own offsets in the
+//! store's watermark and every replica needs every partition.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::{ClientConfig, Message, Offset, TopicPartitionList};
+use tonic::async_trait;
+
+use crate::ingest_source::{IngestConfig, IngestSource, StreamMessage, POLL_TIMEOUT};
+
+/// Timeout for metadata and watermark requests to the brokers
+const METADATA_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct KafkaSource {
+    consumer: StreamConsumer,
+    topic: String,
+}
+
+impl KafkaSource {
+    pub fn open(config: &IngestConfig) -> Result<Self, String> {
+        let consumer = ClientConfig::new()
+            .set("bootstrap.servers", &config.url)
+            .set("group.id", &config.group)
+            .set("enable.auto.commit", "false")
+            .create()
+            .map_err(|e| format!("failed to create Kafka consumer: {}", e))?;
+        Ok(Self {
+            consumer,
+            topic: config.stream.clone(),
+        })
+    }
+
+    fn partitions(&self) -> Result<Vec<i32>, String> {
+        let metadata = self
+            .consumer
+            .fetch_metadata(Some(&self.topic), METADATA_TIMEOUT)
+            .map_err(|e| format!("failed to fetch metadata of {}: {}", self.topic, e))?;
+        let topic = metadata
+            .topics()
+            .first()
+            .ok_or_else(|| format!("topic {} not found", self.topic))?;
+        Ok(topic.partitions().iter().map(|p| p.id()).collect())
+    }
+}
+
+#[async_trait]
+impl IngestSource for KafkaSource {
+    async fn seek(&mut self, offsets: &BTreeMap<i32, i64>) -> Result<(), String> {
+        let mut assignment = TopicPartitionList::new();
+        for partition in self.partitions()? {
+            let offset = match offsets.get(&partition) {
+                Some(offset) => Offset::Offset(*offset),
+                None => Offset::Beginning,
+            };
+            assignment
+                .add_partition_offset(&self.topic, partition, offset)
+                .map_err(|e| format!("failed to seek partition {}: {}", partition, e))?;
+        }
+        self.consumer
+            .assign(&assignment)
+            .map_err(|e| format!("failed to assign {}: {}", self.topic, e))
+    }
+
+    async fn end_offsets(&mut self) -> Result<BTreeMap<i32, i64>, String> {
+        let mut end_offsets = BTreeMap::new();
+        for partition in self.partitions()? {
+            let (_, high) = self
+                .consumer
+                .fetch_watermarks(&self.topic, partition, METADATA_TIMEOUT)
+                .map_err(|e| format!("failed to fetch watermarks of {}: {}", partition, e))?;
+            end_offsets.insert(partition, high);
+        }
+        Ok(end_offsets)
+    }
+
+    async fn poll(&mut self) -> Result<Vec<StreamMessage>, String> {
+        let message = match tokio::time::timeout(POLL_TIMEOUT, self.consumer.recv()).await {
+            Ok(message) => message.map_err(|e| format!("failed to read {}: {}", self.topic, e))?,
+            Err(_) => return Ok(Vec::new()),
+        };
+        Ok(vec![StreamMessage {
+            partition: message.partition(),
+            offset: message.offset(),
+            payload: message.payload().unwrap_or_default().to_vec(),
+        }])
+    }
+}
@@ -4,7 +4,46 @@
 //! post storage and retrieval system. It handles posts from followed accounts
 //! and provides them to the home mixer for ranking.
 
+pub mod admin_auth;
+pub mod alerting;
 pub mod args;
+pub mod author_index;
+pub mod backfill;
+pub mod bloom_filter;
 pub mod config;
+pub mod conversation;
+pub mod error;
+pub mod gateway;
 pub mod candidate_source;
+pub mod ingest;
+pub mod ingest_source;
+pub mod ingest_validator;
+#[cfg(feature = "kafka")]
+pub mod kafka_source;
+pub mod logging;
+#[cfg(feature = "nats")]
+pub mod nats_source;
+pub mod over_fetch;
+pub mod post_store;
+pub mod pre_rank;
+#[cfg(feature = "profiling")]
+pub mod profiling;
+pub mod proto;
 pub mod realtime_query;
+pub mod replay_source;
+pub mod replication;
+pub mod request_limiter;
+pub mod retention;
+#[cfg(feature = "redis")]
+pub mod redis_source;
+#[cfg(feature = "rocksdb")]
+pub mod rocksdb_storage;
+pub mod search_index;
+pub mod server;
+pub mod sharded_store;
+pub mod snapshot;
+pub mod storage;
+pub mod text_compression;
+pub mod trending;
+pub mod ttl_index;
+pub mod wal;
@@ -0,0 +1,101 @@
+//! Logging
+//!
+//! Everything logged, through `log` or `tracing`, goes to one `tracing`
+//! subscriber writing text, or one JSON object per line when `LOG_FORMAT` is
+//! `json`. Lines logged while serving a query carry its span's fields, such as
+//! the `user_id` it was made for. Levels start as `RUST_LOG` (`info` when
+//! unset) and can be replaced while running through `/admin/log_levels`.
+
+use std::sync::{Arc, Mutex};
+
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Layer, Registry};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    /// `json`, or text for anything else
+    pub fn parse(value: &str) -> Self {
+        if value.eq_ignore_ascii_case("json") {
+            LogFormat::Json
+        } else {
+            LogFormat::Text
+        }
+    }
+}
+
+/// The log levels of the running process
+#[derive(Clone)]
+pub struct LogLevels {
+    handle: reload::Handle<EnvFilter, Registry>,
+    directives: Arc<Mutex<String>>,
+}
+
+impl LogLevels {
+    pub fn current(&self) -> String {
+        self.directives.lock().unwrap().clone()
+    }
+
+    /// Replace every level with `directives`, leaving them as they were when
+    /// `directives` don't parse
+    pub fn set(&self, directives: &str) -> Result<(), String> {
+        let filter = EnvFilter::try_new(directives).map_err(|e| e.to_string())?;
+        self.handle.reload(filter).map_err(|e| e.to_string())?;
+        *self.directives.lock().unwrap() = directives.to_string();
+        Ok(())
+    }
+}
+
+/// Install the global subscriber as `LOG_FORMAT` and `RUST_LOG` say
+pub fn init_from_env() -> Result<LogLevels, String> {
+    let format = std::env::var("LOG_FORMAT").map_or(LogFormat::Text, |v| LogFormat::parse(&v));
+    let directives = std::env::var("RUST_LOG")
+        .ok()
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| "info".to_string());
+    init(format, &directives)
+}
+
+/// Install the global subscriber, writing `format` at the levels of `directives`
+pub fn init(format: LogFormat, directives: &str) -> Result<LogLevels, String> {
+    let filter = EnvFilter::try_new(directives).map_err(|e| e.to_string())?;
+    let (filter, handle) = reload::Layer::new(filter);
+    let output = match format {
+        LogFormat::Text => fmt::layer().boxed(),
+        LogFormat::Json => fmt::layer().json().boxed(),
+    };
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(output)
+        .try_init()
+        .map_err(|e| e.to_string())?;
+    Ok(LogLevels {
+        handle,
+        directives: Arc::new(Mutex::new(directives.to_string())),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_directives_leave_levels_unchanged() {
+        let (_filter, handle) = reload::Layer::<EnvFilter, Registry>::new(EnvFilter::new("info"));
+        let levels = LogLevels {
+            handle,
+            directives: Arc::new(Mutex::new("info".to_string())),
+        };
+
+        levels.set("warn,thunder::ingest=debug").unwrap();
+        assert_eq!(levels.current(), "warn,thunder::ingest=debug");
+        assert!(levels.set("thunder=loud").is_err());
+        assert_eq!(levels.current(), "warn,thunder::ingest=debug");
+    }
+}
@@ -3,56 +3,527 @@
 //! This is a simplified entrypoint for the Thunder service,
 //! demonstrating the architecture of X's in-network post storage.
 
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
 use anyhow::Result;
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, post},
+    Router,
+};
 use clap::Parser;
-use log::info;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
 
+use thunder::admin_auth::admin_only;
+use thunder::alerting;
 use thunder::args;
-use thunder::candidate_source::InMemoryCandidateSource;
+use thunder::backfill::backfill;
+use thunder::candidate_source::{EngagementDelta, SyncSource};
 use thunder::config::ThunderConfig;
+use thunder::gateway;
+use thunder::ingest::Ingestor;
+use thunder::ingest_source::{open_source, spawn_consumer, CatchUpStatus};
+use thunder::ingest_validator;
+use thunder::logging::{self, LogLevels};
+#[cfg(feature = "profiling")]
+use thunder::profiling;
+use thunder::proto::{self, thunder_service_server::ThunderServiceServer};
 use thunder::realtime_query::{execute_query, RealtimeQuery};
+use thunder::replay_source::ReplaySource;
+use thunder::replication;
+use thunder::request_limiter::{self, RequestLimiter};
+use thunder::retention::{spawn_trimmer, RetentionPolicy};
+use thunder::server::ThunderServer;
+use thunder::sharded_store::{spawn_stats_logger, ShardedPostStore};
+use thunder::snapshot::{now_seconds, spawn_snapshotter, SnapshotDir};
+use thunder::storage::StorageBackend;
+use thunder::text_compression::open_codec;
+use thunder::trending::TrendingDetector;
+use thunder::wal::WriteAheadLog;
+
+/// Engagement gained by one post, as posted to `/api/engagement`
+#[derive(Debug, Deserialize)]
+struct EngagementUpdate {
+    post_id: i64,
+    #[serde(flatten)]
+    delta: EngagementDelta,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum EngagementRequest {
+    Batch(Vec<EngagementUpdate>),
+    Single(EngagementUpdate),
+}
+
+#[derive(Debug, Serialize)]
+struct EngagementResponse {
+    applied: usize,
+    ignored: usize,
+}
+
+#[derive(Clone)]
+struct EngagementState {
+    ingestor: Arc<Ingestor>,
+    max_batch: usize,
+}
+
+async fn update_engagement(
+    State(state): State<EngagementState>,
+    Json(req): Json<EngagementRequest>,
+) -> impl IntoResponse {
+    let updates = match req {
+        EngagementRequest::Batch(updates) => updates,
+        EngagementRequest::Single(update) => vec![update],
+    };
+    if updates.len() > state.max_batch {
+        let message = format!("at most {} updates per request", state.max_batch);
+        return (StatusCode::PAYLOAD_TOO_LARGE, message).into_response();
+    }
+
+    let now = now_seconds();
+    let applied = updates
+        .iter()
+        .filter(|u| state.ingestor.update_engagement(u.post_id, &u.delta, now))
+        .count();
+
+    let response = EngagementResponse {
+        applied,
+        ignored: updates.len() - applied,
+    };
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// Consumer progress behind `/catchup` and `/ready`
+#[derive(Clone)]
+struct CatchUpState {
+    /// None when no post stream is consumed
+    status: Option<watch::Receiver<CatchUpStatus>>,
+    max_lag: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct ReadyResponse {
+    ready: bool,
+    lag: u64,
+}
+
+async fn catch_up_status(State(state): State<CatchUpState>) -> impl IntoResponse {
+    match &state.status {
+        Some(status) => (StatusCode::OK, Json(status.borrow().clone())).into_response(),
+        None => (StatusCode::NOT_FOUND, "no post stream is consumed").into_response(),
+    }
+}
+
+async fn ready(State(state): State<CatchUpState>) -> impl IntoResponse {
+    let (ready, lag) = match &state.status {
+        Some(status) => {
+            let status = status.borrow();
+            (status.is_ready(state.max_lag), status.lag)
+        }
+        None => (true, 0),
+    };
+    let code = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (code, Json(ReadyResponse { ready, lag }))
+}
+
+async fn trending(State(trending): State<Arc<TrendingDetector>>) -> impl IntoResponse {
+    Json(trending.report(now_seconds()))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LogLevelsBody {
+    /// `RUST_LOG` style directives, e.g. `info,thunder::ingest=debug`
+    levels: String,
+}
+
+async fn get_log_levels(State(levels): State<LogLevels>) -> impl IntoResponse {
+    Json(LogLevelsBody {
+        levels: levels.current(),
+    })
+}
+
+async fn set_log_levels(
+    State(levels): State<LogLevels>,
+    Json(req): Json<LogLevelsBody>,
+) -> impl IntoResponse {
+    match levels.set(&req.levels) {
+        Ok(()) => {
+            info!("Log levels set to {}", req.levels);
+            (StatusCode::OK, Json(req)).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+/// Consume the post stream (or replay file) from where the store left off;
+/// None when neither is configured
+async fn start_consumer(
+    config: &ThunderConfig,
+    ingestor: &Arc<Ingestor>,
+) -> Result<Option<watch::Receiver<CatchUpStatus>>> {
+    Ok(match (&config.replay_file, &config.ingest) {
+        (Some(path), _) => {
+            let source = ReplaySource::open(path, config.replay_speed).map_err(anyhow::Error::msg)?;
+            info!("Replaying {} at {}x", path.display(), config.replay_speed);
+            Some(spawn_consumer(Box::new(source), ingestor.clone()))
+        }
+        (None, Some(ingest)) => {
+            let source = open_source(ingest).await.map_err(anyhow::Error::msg)?;
+            info!("Consuming {} over {:?}", ingest.stream, ingest.protocol);
+            Some(spawn_consumer(source, ingestor.clone()))
+        }
+        (None, None) => None,
+    })
+}
+
+/// Counts allocations for `/debug/pprof/heap`
+#[cfg(feature = "profiling")]
+#[global_allocator]
+static ALLOCATOR: profiling::CountingAllocator = profiling::CountingAllocator;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    env_logger::init();
+    let log_levels = logging::init_from_env().map_err(anyhow::Error::msg)?;
     let args = args::Args::parse();
+    if args.config_schema {
+        println!("{}", serde_json::to_string_pretty(&ThunderConfig::json_schema())?);
+        return Ok(());
+    }
+    let mut config = if args.strict_config || args.validate_config {
+        ThunderConfig::load_strict(args.config.as_deref())
+    } else {
+        ThunderConfig::load(args.config.as_deref())
+    }
+    .map_err(anyhow::Error::msg)?;
+    config.grpc_port = args.grpc_port.unwrap_or(config.grpc_port);
+    config.http_port = args.http_port.unwrap_or(config.http_port);
+    config.validate().map_err(anyhow::Error::msg)?;
+    if args.validate_config {
+        println!("Config OK");
+        return Ok(());
+    }
+    info!("Thunder config: {:?}", config);
+
+    if let Some(args::Command::Validate {
+        streams,
+        max_messages,
+        max_error_rate,
+    }) = &args.command
+    {
+        let reports = ingest_validator::validate(&config, streams, *max_messages)
+            .await
+            .map_err(anyhow::Error::msg)?;
+        for report in &reports {
+            println!("{}", serde_json::to_string_pretty(report)?);
+        }
+        let failed: Vec<&str> = reports
+            .iter()
+            .filter(|report| report.error_rate > *max_error_rate)
+            .map(|report| report.stream.as_str())
+            .collect();
+        if !failed.is_empty() {
+            anyhow::bail!("error rate above {} in {}", max_error_rate, failed.join(", "));
+        }
+        return Ok(());
+    }
 
     info!(
         "Thunder Service starting (retention: {} seconds / {:.1} days)",
-        args.post_retention_seconds,
-        args.post_retention_seconds as f64 / 86400.0
+        config.retention_seconds,
+        config.retention_seconds as f64 / 86400.0
     );
 
-    // Initialize in-memory candidate source
-    let source = InMemoryCandidateSource::new();
-    let config = ThunderConfig {
-        max_posts: args.result_limit,
-        retention_seconds: args.post_retention_seconds,
+    let retention = Arc::new(match &config.retention_config {
+        Some(path) => RetentionPolicy::load(path, config.retention_seconds)
+            .map_err(anyhow::Error::msg)?,
+        None => RetentionPolicy::new(config.retention_seconds),
+    });
+    if retention.overrides() > 0 {
+        info!(
+            "Keeping posts of {} authors longer (up to {} seconds)",
+            retention.overrides(),
+            retention.longest_seconds()
+        );
+    }
+
+    // Restore the post store from the latest snapshot, if any. Disk-backed
+    // storage already holds its posts and offsets, so it only needs opening.
+    let snapshots = match &config.snapshot_dir {
+        Some(dir) => Some(Arc::new(
+            SnapshotDir::open(dir, config.snapshots_to_keep).map_err(anyhow::Error::msg)?,
+        )),
+        None => None,
     };
+    let mut store = ShardedPostStore::open(
+        config.storage_backend,
+        config.storage_dir.as_deref(),
+        config.post_store_shards,
+    )
+    .map_err(anyhow::Error::msg)?;
+    info!(
+        "Opened {:?} post storage with {} posts",
+        config.storage_backend,
+        store.len()
+    );
+    let in_memory = config.storage_backend == StorageBackend::Memory;
+    if let Some(snapshots) = snapshots.as_ref().filter(|_| in_memory) {
+        if let Some((snapshot, snapshot_info)) = snapshots.latest().map_err(anyhow::Error::msg)? {
+            let now = now_seconds();
+            info!(
+                "Restored {} posts from {} (age: {}s, size: {} bytes)",
+                snapshot_info.posts,
+                snapshot_info.path.display(),
+                snapshot_info.age_seconds(now),
+                snapshot_info.size_bytes
+            );
+            store = ShardedPostStore::from_snapshot(snapshot, config.post_store_shards);
+        }
+    }
+    let evicted = store.evict_expired(now_seconds(), &retention);
+    info!("Evicted {} expired posts on startup", evicted);
+    let codec = open_codec(config.text_compression, config.compression_dictionary.as_deref())
+        .map_err(anyhow::Error::msg)?;
+    if let Some(codec) = codec {
+        store = store.with_text_codec(codec).map_err(anyhow::Error::msg)?;
+        info!("Compressing post text with {:?}", config.text_compression);
+    }
+    let store = store.with_max_bytes(config.max_store_bytes);
+    // Replay events logged after the snapshot was taken
+    let wal = match &config.wal_dir {
+        Some(dir) => {
+            let wal =
+                WriteAheadLog::open(dir, config.wal_segment_bytes).map_err(anyhow::Error::msg)?;
+            let mut applied = 0;
+            let replayed = wal
+                .replay(|record| applied += store.apply_record(record) as usize)
+                .map_err(anyhow::Error::msg)?;
+            info!("Replayed {} WAL records ({} not covered by the snapshot)", replayed, applied);
+            Some(wal)
+        }
+        None => None,
+    };
+    // Consumption resumes from here rather than the start of the stream
+    info!("Post stream resume offsets: {:?}", store.resume_offsets());
+    info!("Post events applied during recovery: {:?}", store.event_counts());
+    info!(
+        "Post store holds {} posts ({} bytes), evictions: {:?}",
+        store.len(),
+        store.bytes(),
+        store.eviction_counts()
+    );
+    let trending_detector = Arc::new(TrendingDetector::new(config.trending));
+    let mut ingestor = Ingestor::new(Arc::new(store), wal).with_trending(trending_detector.clone());
+    if let Some(snapshots) = &snapshots {
+        ingestor = ingestor.with_snapshots(snapshots.clone());
+    }
+    let ingestor = Arc::new(ingestor);
+    spawn_stats_logger(
+        ingestor.store().clone(),
+        Duration::from_secs(config.stats_interval_seconds),
+    );
+    spawn_trimmer(
+        ingestor.store().clone(),
+        retention.clone(),
+        Duration::from_secs(config.trim_interval_seconds),
+    );
+    if let Some(snapshots) = &snapshots {
+        spawn_snapshotter(
+            ingestor.clone(),
+            snapshots.clone(),
+            Duration::from_secs(config.snapshot_interval_seconds),
+        );
+    }
 
-    info!("Thunder config: {:?}", config);
+    // Bulk-load archived posts before consuming the live stream
+    if let Some(args::Command::Backfill { archives }) = &args.command {
+        let store = ingestor.store().clone();
+        let archives = archives.clone();
+        let retention = retention.clone();
+        let counts = tokio::task::spawn_blocking(move || {
+            backfill(&store, &archives, now_seconds(), &retention)
+        })
+        .await?
+        .map_err(anyhow::Error::msg)?;
+        info!("Backfill finished: {:?}", counts);
+        // Persist the progress right away rather than at the next interval
+        if let Some(snapshots) = &snapshots {
+            let info = ingestor
+                .snapshot(snapshots, now_seconds())
+                .map_err(anyhow::Error::msg)?;
+            info!("Snapshotted the backfilled store to {}", info.path.display());
+        }
+    }
+
+    // A follower mirrors its leader instead, consuming only once promoted
+    let admin_token = config.admin_token.as_ref().map(|token| token.expose());
+    let (consumer_status, replication_routes) = match &config.replicate_from {
+        Some(leader) => {
+            let name = std::env::var("HOSTNAME").unwrap_or_else(|_| "thunder".into());
+            let follower = replication::spawn_grpc_follower(leader.clone(), name, ingestor.clone());
+            info!("Replicating from {} (status at /replication)", leader);
+            let (routes, promoted) = replication::router(follower, admin_token);
+            let (config, ingestor) = (config.clone(), ingestor.clone());
+            tokio::spawn(async move {
+                if promoted.await.is_err() {
+                    return;
+                }
+                if let Err(e) = start_consumer(&config, &ingestor).await {
+                    error!("Failed to consume the post stream after promotion: {}", e);
+                }
+            });
+            (None, Some(routes))
+        }
+        None => (start_consumer(&config, &ingestor).await?, None),
+    };
+
+    // Queries are limited once served; created here so alerts can watch them
+    let limiter = Arc::new(RequestLimiter::new(
+        config.max_concurrent_requests,
+        Duration::from_millis(config.request_timeout_ms),
+    ));
+    if let Some(alerting) = &config.alerting {
+        alerting::spawn_monitor(
+            alerting.clone(),
+            ingestor.store().clone(),
+            consumer_status.clone(),
+            config.is_serving.then(|| limiter.clone()),
+        );
+        info!("Alerting on health every {}s", alerting.interval_seconds);
+    }
 
     // Example query demonstration
     let query = RealtimeQuery::new(1, vec![100, 200, 300])
         .with_limit(50)
-        .with_max_age(7 * 24 * 60 * 60);
+        .with_max_age(7 * 24 * 60 * 60)
+        .with_max_per_author(5);
 
-    let response = execute_query(&source, &query, &config);
+    let source = SyncSource(ingestor.store().clone());
+    let response = execute_query(&source, &query, &config).await?;
     info!(
         "Example query: {} candidates in {}ms",
         response.candidates.len(),
         response.query_time_ms
     );
 
-    if args.is_serving {
-        info!("Starting gRPC server on port {}...", args.grpc_port);
-        // In a full implementation, this would start the gRPC service
-        // For now, we just log the configuration
-        info!("Thunder service configured for gRPC on 0.0.0.0:{}", args.grpc_port);
-        
-        // Keep the service running
-        tokio::signal::ctrl_c().await?;
-        info!("Received shutdown signal");
+    if config.is_serving {
+        // Serve HTTP right away so catch-up progress can be followed, but keep
+        // `/ready` failing until the consumer has caught up
+        let catch_up = CatchUpState {
+            status: consumer_status.clone(),
+            max_lag: config.max_ready_lag,
+        };
+        // The JSON gateway shares the gRPC server, refusing queries until caught up too
+        let mut server = ThunderServer::new(ingestor.clone())
+            .with_limiter(limiter.clone())
+            .with_config(config.clone());
+        if let Some(status) = consumer_status.clone() {
+            server = server.with_catch_up(status);
+        }
+        let engagement = EngagementState {
+            ingestor: ingestor.clone(),
+            max_batch: config.max_engagement_batch,
+        };
+        #[allow(unused_mut)]
+        let mut app = Router::new()
+            // Counts feed ranking, so only the engagement pipeline holding the
+            // admin token may add to them
+            .merge(admin_only(
+                Router::new()
+                    .route("/api/engagement", post(update_engagement))
+                    .with_state(engagement),
+                admin_token,
+            ))
+            .merge(
+                Router::new()
+                    .route("/catchup", get(catch_up_status))
+                    .route("/ready", get(ready))
+                    .with_state(catch_up),
+            )
+            .merge(
+                Router::new()
+                    .route("/trending", get(trending))
+                    .with_state(trending_detector),
+            )
+            .merge(admin_only(
+                Router::new()
+                    .route("/admin/log_levels", get(get_log_levels).put(set_log_levels))
+                    .with_state(log_levels),
+                admin_token,
+            ))
+            .merge(gateway::router(server.clone()));
+        if let Some(routes) = replication_routes {
+            app = app.merge(routes);
+        }
+        if config.enable_profiling {
+            // `validate` made sure there is a token
+            #[cfg(feature = "profiling")]
+            if let Some(token) = &config.admin_token {
+                app = app.merge(profiling::router(token.expose()));
+                info!("Serving profiles at /debug/pprof");
+            }
+            #[cfg(not(feature = "profiling"))]
+            log::warn!("thunder was built without the profiling feature; not serving profiles");
+        }
+        let addr: SocketAddr = format!("0.0.0.0:{}", config.http_port).parse()?;
+        info!("HTTP server listening on {}", addr);
+
+        // Keep the service running until shutdown
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        let mut http = tokio::spawn(async move {
+            axum::serve(listener, app)
+                .with_graceful_shutdown(async {
+                    let _ = tokio::signal::ctrl_c().await;
+                    info!("Received shutdown signal");
+                })
+                .await
+        });
+
+        // Serve queries only once the events published before startup are applied
+        if let Some(mut status) = consumer_status.clone() {
+            info!("Waiting to catch up with the post stream (progress at /catchup)...");
+            tokio::select! {
+                caught_up = status.wait_for(|status| status.caught_up) => {
+                    caught_up?;
+                }
+                served = &mut http => {
+                    served??;
+                    info!("Thunder service terminated");
+                    return Ok(());
+                }
+            }
+        }
+        request_limiter::spawn_stats_logger(
+            limiter,
+            Duration::from_secs(config.stats_interval_seconds),
+        );
+        // Reflection lets grpcurl and the like call the service without the .proto files
+        let reflection = tonic_reflection::server::Builder::configure()
+            .register_encoded_file_descriptor_set(proto::FILE_DESCRIPTOR_SET)
+            .build()?;
+        let grpc_addr: SocketAddr = format!("0.0.0.0:{}", config.grpc_port).parse()?;
+        info!("gRPC server listening on {}", grpc_addr);
+        let grpc = tonic::transport::Server::builder()
+            .add_service(ThunderServiceServer::new(server))
+            .add_service(reflection)
+            .serve_with_shutdown(grpc_addr, async {
+                let _ = tokio::signal::ctrl_c().await;
+            });
+
+        tokio::select! {
+            served = grpc => served?,
+            served = &mut http => served??,
+        }
     }
 
     info!("Thunder service terminated");
@@ -0,0 +1,91 @@
+//! NATS JetStream Ingest Source for Thunder
+//!
+//! Reads a JetStream stream through an ephemeral pull consumer that starts at
+//! the store's resume offset. A stream has a single partition (0) whose offsets
+//! are the JetStream sequence numbers, which start at 1.
+
+use std::collections::BTreeMap;
+
+use async_nats::jetstream::consumer::{pull, AckPolicy, DeliverPolicy};
+use async_nats::jetstream::{self, stream::Stream};
+use futures::StreamExt;
+use tonic::async_trait;
+
+use crate::ingest_source::{IngestConfig, IngestSource, StreamMessage, POLL_TIMEOUT};
+
+const PARTITION: i32 = 0;
+
+pub struct NatsSource {
+    stream: Stream,
+    messages: Option<pull::Stream>,
+}
+
+impl NatsSource {
+    pub async fn open(config: &IngestConfig) -> Result<Self, String> {
+        let client = async_nats::connect(&config.url)
+            .await
+            .map_err(|e| format!("failed to connect to {}: {}", config.url, e))?;
+        let stream = jetstream::new(client)
+            .get_stream(&config.stream)
+            .await
+            .map_err(|e| format!("failed to open stream {}: {}", config.stream, e))?;
+        Ok(Self {
+            stream,
+            messages: None,
+        })
+    }
+}
+
+#[async_trait]
+impl IngestSource for NatsSource {
+    async fn seek(&mut self, offsets: &BTreeMap<i32, i64>) -> Result<(), String> {
+        let deliver_policy = match offsets.get(&PARTITION) {
+            Some(offset) => DeliverPolicy::ByStartSequence {
+                start_sequence: (*offset).max(1) as u64,
+            },
+            None => DeliverPolicy::All,
+        };
+        let consumer = self
+            .stream
+            .create_consumer(pull::Config {
+                deliver_policy,
+                ack_policy: AckPolicy::None,
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| format!("failed to create JetStream consumer: {}", e))?;
+        let messages = consumer
+            .messages()
+            .await
+            .map_err(|e| format!("failed to read JetStream messages: {}", e))?;
+        self.messages = Some(messages);
+        Ok(())
+    }
+
+    async fn end_offsets(&mut self) -> Result<BTreeMap<i32, i64>, String> {
+        let info = self
+            .stream
+            .info()
+            .await
+            .map_err(|e| format!("failed to fetch stream info: {}", e))?;
+        Ok(BTreeMap::from([(PARTITION, info.state.last_sequence as i64 + 1)]))
+    }
+
+    async fn poll(&mut self) -> Result<Vec<StreamMessage>, String> {
+        let messages = self.messages.as_mut().ok_or("poll before seek")?;
+        let message = match tokio::time::timeout(POLL_TIMEOUT, messages.next()).await {
+            Ok(Some(message)) => message.map_err(|e| format!("failed to read: {}", e))?,
+            Ok(None) => return Err("JetStream consumer closed".to_string()),
+            Err(_) => return Ok(Vec::new()),
+        };
+        let sequence = message
+            .info()
+            .map_err(|e| format!("message without JetStream info: {}", e))?
+            .stream_sequence;
+        Ok(vec![StreamMessage {
+            partition: PARTITION,
+            offset: sequence as i64,
+            payload: message.payload.to_vec(),
+        }])
+    }
+}
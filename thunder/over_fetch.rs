@@ -0,0 +1,132 @@
+//! Adaptive Over-Fetching for Thunder
+//!
+//! A query fetches more posts than its limit, since some are filtered out as
+//! too old or already seen. How many varies a lot between users: someone who
+//! scrolls all day has seen most of what their follows posted. With adaptive
+//! over-fetching, Thunder keeps a moving average of the share of fetched posts
+//! passing each user's filters and fetches `limit / pass_rate` posts for them,
+//! within bounds, so heavy filterers still fill a page and light filterers
+//! don't scan posts they will never get.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Weight of the latest query in a user's moving pass rate
+const SMOOTHING: f64 = 0.2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct OverFetchConfig {
+    /// Posts fetched per returned post, for users without history or for
+    /// everyone when not adaptive
+    pub factor: f64,
+    /// Learn each user's factor from the share of posts passing their filters
+    pub adaptive: bool,
+    /// Bounds of a learned factor
+    pub min_factor: f64,
+    pub max_factor: f64,
+    /// Users whose pass rate is remembered at once
+    pub max_users: usize,
+}
+
+impl Default for OverFetchConfig {
+    fn default() -> Self {
+        Self {
+            factor: 2.0,
+            adaptive: false,
+            min_factor: 1.0,
+            max_factor: 10.0,
+            max_users: 1_000_000,
+        }
+    }
+}
+
+/// Moving pass rate of each user's queries
+pub struct PassRateTracker {
+    config: OverFetchConfig,
+    rates: Mutex<HashMap<i64, f64>>,
+}
+
+impl PassRateTracker {
+    pub fn new(config: OverFetchConfig) -> Self {
+        Self {
+            config,
+            rates: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Posts to fetch per returned post for `user_id`
+    pub fn factor(&self, user_id: i64) -> f64 {
+        if !self.config.adaptive {
+            return self.config.factor;
+        }
+        match self.rates.lock().unwrap().get(&user_id) {
+            Some(rate) => (1.0 / rate.max(f64::EPSILON))
+                .clamp(self.config.min_factor, self.config.max_factor),
+            None => self.config.factor,
+        }
+    }
+
+    /// Note that `passed` of the `fetched` posts of a query by `user_id` passed
+    /// its filters
+    pub fn record(&self, user_id: i64, fetched: usize, passed: usize) {
+        if !self.config.adaptive || fetched == 0 {
+            return;
+        }
+        let observed = passed.min(fetched) as f64 / fetched as f64;
+        let mut rates = self.rates.lock().unwrap();
+        if !rates.contains_key(&user_id) && rates.len() >= self.config.max_users {
+            // Forget someone to make room; they start over from the default factor
+            if let Some(&forgotten) = rates.keys().next() {
+                rates.remove(&forgotten);
+            }
+        }
+        let rate = rates.entry(user_id).or_insert(observed);
+        *rate += SMOOTHING * (observed - *rate);
+    }
+
+    /// Users with a learned pass rate
+    pub fn len(&self) -> usize {
+        self.rates.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_factor_follows_pass_rate_within_bounds() {
+        let tracker = PassRateTracker::new(OverFetchConfig {
+            adaptive: true,
+            max_factor: 5.0,
+            max_users: 2,
+            ..Default::default()
+        });
+        assert_eq!(tracker.factor(1), 2.0);
+
+        // A quarter of user 1's posts pass, none of user 2's, all of user 3's
+        tracker.record(1, 100, 25);
+        tracker.record(2, 100, 0);
+        assert!((tracker.factor(1) - 4.0).abs() < 1e-9);
+        assert_eq!(tracker.factor(2), 5.0);
+        tracker.record(3, 100, 100);
+        assert_eq!(tracker.factor(3), 1.0);
+        assert_eq!(tracker.len(), 2);
+
+        // Later queries move the rate gradually
+        tracker.record(3, 100, 50);
+        assert!((tracker.factor(3) - 1.0 / 0.9).abs() < 1e-9);
+
+        let fixed = PassRateTracker::new(OverFetchConfig::default());
+        fixed.record(1, 100, 10);
+        assert_eq!((fixed.factor(1), fixed.len()), (2.0, 0));
+    }
+}
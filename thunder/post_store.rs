@@ -0,0 +1,580 @@
+//! Post Store for Thunder
+//!
+//! Holds the posts consumed from the post-event stream together with the
+//! offset watermark per partition, so a store restored from a snapshot knows
+//! where to resume consuming instead of replaying from scratch. Posts past their
+//! author's retention period are trimmed, found through a `TtlIndex` rather than
+//! by walking the posts, and an optional memory budget evicts the oldest posts
+//! first when a burst of new posts would exceed it. The posts themselves live
+//! in a `PostStorage`, in memory unless another backend is given.
+
+use std::collections::BTreeMap;
+use std::ops::AddAssign;
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::author_index::AuthorIndex;
+use crate::conversation::{build_conversation, Conversation, ConversationLimits};
+use crate::candidate_source::{
+    CandidateSource, EngagementDelta, EngagementSnapshot, PostCursor, ThunderCandidate,
+};
+use crate::retention::RetentionPolicy;
+use crate::snapshot::{PostStoreSnapshot, SNAPSHOT_VERSION};
+use crate::storage::PostStorage;
+use crate::text_compression::{CompressionStats, TextCodec};
+use crate::ttl_index::TtlIndex;
+use crate::wal::{PostEvent, WalRecord};
+
+/// Post events applied to the store since startup, by type
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct PostEventCounts {
+    pub created: u64,
+    pub edited: u64,
+    pub deleted: u64,
+    pub engagement_updates: u64,
+    /// Events already applied, or edits and deletes of posts the store doesn't hold
+    pub skipped: u64,
+}
+
+impl AddAssign for PostEventCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.created += other.created;
+        self.edited += other.edited;
+        self.deleted += other.deleted;
+        self.engagement_updates += other.engagement_updates;
+        self.skipped += other.skipped;
+    }
+}
+
+/// Posts evicted from the store since startup, by reason
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct EvictionCounts {
+    /// Past their author's retention
+    pub expired: u64,
+    /// Evicted oldest-first to stay within the memory budget
+    pub over_budget: u64,
+}
+
+impl AddAssign for EvictionCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.expired += other.expired;
+        self.over_budget += other.over_budget;
+    }
+}
+
+/// Post store fed from the post-event stream
+pub struct PostStore {
+    posts: Box<dyn PostStorage>,
+    /// Next offset to consume per partition
+    watermark: BTreeMap<i32, i64>,
+    counts: PostEventCounts,
+    /// Estimated bytes the posts may hold; unlimited when unset
+    max_bytes: Option<usize>,
+    evictions: EvictionCounts,
+    /// Post ids by creation time, for trimming
+    ttl: TtlIndex,
+}
+
+impl Default for PostStore {
+    fn default() -> Self {
+        Self::with_storage(Box::new(AuthorIndex::new()))
+    }
+}
+
+impl PostStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A store over `posts`, resuming from the watermark persisted with them
+    pub fn with_storage(posts: Box<dyn PostStorage>) -> Self {
+        let mut ttl = TtlIndex::new();
+        for post in posts.posts() {
+            ttl.insert(post.post_id, post.created_at);
+        }
+        Self {
+            watermark: posts.watermark(),
+            posts,
+            counts: PostEventCounts::default(),
+            max_bytes: None,
+            evictions: EvictionCounts::default(),
+            ttl,
+        }
+    }
+
+    /// Rebuild an in-memory store from a snapshot
+    pub fn from_snapshot(snapshot: PostStoreSnapshot) -> Self {
+        let mut store = Self::new();
+        for post in snapshot.posts {
+            store.ttl.insert(post.post_id, post.created_at);
+            store.posts.insert(post);
+        }
+        store.watermark = snapshot.watermark;
+        store
+    }
+
+    /// Replace the posts and watermark with those of `snapshot`, keeping the
+    /// storage, memory budget and text codec
+    pub fn restore(&mut self, snapshot: PostStoreSnapshot) {
+        let held: Vec<i64> = self.posts.posts().map(|post| post.post_id).collect();
+        for post_id in held {
+            self.posts.remove(post_id);
+        }
+        self.ttl.clear();
+        for post in snapshot.posts {
+            self.ttl.insert(post.post_id, post.created_at);
+            self.posts.insert(post);
+        }
+        for (partition, next_offset) in &snapshot.watermark {
+            self.posts.set_watermark(*partition, *next_offset);
+        }
+        self.watermark = snapshot.watermark;
+        self.enforce_budget();
+    }
+
+    /// Cap the estimated memory held by the posts, evicting the oldest right away
+    /// if the store is already over; returns how many were evicted
+    pub fn set_max_bytes(&mut self, max_bytes: Option<usize>) -> usize {
+        self.max_bytes = max_bytes;
+        self.enforce_budget()
+    }
+
+    fn enforce_budget(&mut self) -> usize {
+        let Some(max_bytes) = self.max_bytes else {
+            return 0;
+        };
+        let mut evicted = 0;
+        while self.posts.bytes() > max_bytes && self.evict_oldest().is_some() {
+            evicted += 1;
+        }
+        evicted
+    }
+
+    /// Evict the oldest post to make room; returns its id, or None when empty
+    pub fn evict_oldest(&mut self) -> Option<i64> {
+        let oldest = self.posts.oldest()?.post_id;
+        self.posts.remove(oldest);
+        self.evictions.over_budget += 1;
+        Some(oldest)
+    }
+
+    /// Apply the post read at `offset` of `partition`
+    pub fn apply(&mut self, partition: i32, offset: i64, post: ThunderCandidate) {
+        self.ttl.insert(post.post_id, post.created_at);
+        if self.posts.insert(post).is_some() {
+            self.counts.edited += 1;
+        } else {
+            self.counts.created += 1;
+        }
+        self.advance(partition, offset);
+        self.enforce_budget();
+    }
+
+    /// Replace a post's content and attachments, recorded at `offset` of
+    /// `partition`. Engagement gathered on the old content is reset; views are kept.
+    pub fn edit(
+        &mut self,
+        partition: i32,
+        offset: i64,
+        post_id: i64,
+        content: String,
+        has_media: bool,
+        has_link: bool,
+    ) {
+        let edited = self.posts.update(post_id, &mut |post| {
+            post.content.clone_from(&content);
+            post.has_media = has_media;
+            post.has_link = has_link;
+            post.engagement = EngagementSnapshot {
+                views: post.engagement.views,
+                ..EngagementSnapshot::default()
+            };
+        });
+        if edited {
+            self.counts.edited += 1;
+        } else {
+            self.counts.skipped += 1;
+        }
+        self.advance(partition, offset);
+        self.enforce_budget();
+    }
+
+    /// Add engagement to a post in place; returns false if the store doesn't hold it.
+    /// Engagement updates are not logged: a restart loses at most the counts gained
+    /// since the last snapshot.
+    pub fn update_engagement(&mut self, post_id: i64, delta: &EngagementDelta, now: u64) -> bool {
+        let updated = self
+            .posts
+            .update(post_id, &mut |post| post.engagement.apply(delta, now));
+        if updated {
+            self.counts.engagement_updates += 1;
+        }
+        updated
+    }
+
+    /// Remove a deleted post, recorded at `offset` of `partition`. Queries read the
+    /// store under its lock, so no fetch that starts after this returns the post.
+    pub fn delete(&mut self, partition: i32, offset: i64, post_id: i64) {
+        if self.posts.remove(post_id).is_some() {
+            self.counts.deleted += 1;
+        } else {
+            self.counts.skipped += 1;
+        }
+        self.advance(partition, offset);
+    }
+
+    /// Apply a logged event unless the watermark shows it was already applied;
+    /// returns whether it was applied
+    pub fn apply_record(&mut self, record: WalRecord) -> bool {
+        let applied_up_to = self.watermark.get(&record.partition).copied().unwrap_or(0);
+        if record.offset < applied_up_to {
+            self.counts.skipped += 1;
+            return false;
+        }
+        let (partition, offset) = (record.partition, record.offset);
+        match record.event {
+            PostEvent::Upsert { post } => self.apply(partition, offset, post),
+            PostEvent::Edit {
+                post_id,
+                content,
+                has_media,
+                has_link,
+            } => self.edit(partition, offset, post_id, content, has_media, has_link),
+            PostEvent::Delete { post_id } => self.delete(partition, offset, post_id),
+        }
+        true
+    }
+
+    pub fn event_counts(&self) -> PostEventCounts {
+        self.counts
+    }
+
+    fn advance(&mut self, partition: i32, offset: i64) {
+        let next = self.watermark.entry(partition).or_insert(0);
+        if offset + 1 > *next {
+            *next = offset + 1;
+            self.posts.set_watermark(partition, *next);
+        }
+    }
+
+    /// Offsets to resume consuming from, per partition
+    pub fn resume_offsets(&self) -> &BTreeMap<i32, i64> {
+        &self.watermark
+    }
+
+    /// Drop posts past their author's retention; returns how many were dropped
+    pub fn evict_expired(&mut self, now: u64, retention: &RetentionPolicy) -> usize {
+        self.evict_expired_ids(now, retention).len()
+    }
+
+    /// Drop posts past their author's retention; returns their ids
+    pub fn evict_expired_ids(&mut self, now: u64, retention: &RetentionPolicy) -> Vec<i64> {
+        // Posts are filed as due at creation, so those due a default retention
+        // ago are past it. Posts of authors kept longer are filed again, as due
+        // when they are as far from their own retention as the rest are from the
+        // default one.
+        let default_seconds = retention.default_seconds();
+        let mut expired = Vec::new();
+        for post_id in self.ttl.take_due((now + 1).saturating_sub(default_seconds)) {
+            // Posts removed since they were filed, or filed again when replaced
+            let Some(post) = self.posts.get(post_id) else {
+                continue;
+            };
+            if retention.retains(&post, now) {
+                let extra = retention.seconds_for(post.author_id) - default_seconds;
+                self.ttl.insert(post_id, post.created_at + extra);
+            } else {
+                self.posts.remove(post_id);
+                expired.push(post_id);
+            }
+        }
+        self.evictions.expired += expired.len() as u64;
+        expired
+    }
+
+    pub fn eviction_counts(&self) -> EvictionCounts {
+        self.evictions
+    }
+
+    /// Hold post content compressed with `codec`, evicting down to the memory
+    /// budget again afterwards in case the compressed form is larger
+    pub fn set_text_codec(&mut self, codec: Arc<dyn TextCodec>) -> Result<(), String> {
+        self.posts.set_text_codec(codec)?;
+        self.enforce_budget();
+        Ok(())
+    }
+
+    pub fn compression_stats(&self) -> Option<CompressionStats> {
+        self.posts.compression_stats()
+    }
+
+    /// Estimated memory held by the posts
+    pub fn bytes(&self) -> usize {
+        self.posts.bytes()
+    }
+
+    pub fn get(&self, post_id: i64) -> Option<ThunderCandidate> {
+        self.posts.get(post_id)
+    }
+
+    /// Replies to `post_id`, oldest first
+    pub fn replies_to(&self, post_id: i64) -> Vec<ThunderCandidate> {
+        self.posts.replies_to(post_id)
+    }
+
+    /// `root_id` with its ancestors and reply tree, or None if the store doesn't hold it
+    pub fn get_conversation(
+        &self,
+        root_id: i64,
+        limits: ConversationLimits,
+    ) -> Option<Conversation> {
+        build_conversation(
+            root_id,
+            limits,
+            |post_id| self.get(post_id),
+            |post_id| self.replies_to(post_id),
+        )
+    }
+
+    /// Up to `limit` posts matching every token of `query`, created at or after
+    /// `min_created_at`, newest first
+    pub fn search(&self, query: &str, min_created_at: u64, limit: usize) -> Vec<ThunderCandidate> {
+        self.posts.search(query, min_created_at, limit)
+    }
+
+    pub fn contains(&self, post_id: i64) -> bool {
+        self.posts.contains(post_id)
+    }
+
+    /// The post created first, which is evicted first
+    pub fn oldest(&self) -> Option<ThunderCandidate> {
+        self.posts.oldest()
+    }
+
+    pub fn post_ids(&self) -> Vec<i64> {
+        self.posts.posts().map(|post| post.post_id).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.posts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.posts.is_empty()
+    }
+
+    /// Point-in-time copy of the posts and watermark
+    pub fn to_snapshot(&self, now: u64) -> PostStoreSnapshot {
+        PostStoreSnapshot {
+            version: SNAPSHOT_VERSION,
+            created_at: now,
+            watermark: self.watermark.clone(),
+            posts: self.posts.posts().collect(),
+        }
+    }
+}
+
+impl CandidateSource for PostStore {
+    fn fetch_candidates(
+        &self,
+        _user_id: i64,
+        following_ids: &[i64],
+        limit: usize,
+        max_per_author: Option<usize>,
+        cursor: Option<PostCursor>,
+    ) -> Vec<ThunderCandidate> {
+        self.posts.newest_by_authors(following_ids, limit, max_per_author, cursor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watermark_and_snapshot_roundtrip() {
+        let mut store = PostStore::new();
+        store.apply(0, 41, ThunderCandidate::new(1, 100, "Post 1".into(), 1000));
+        store.apply(0, 40, ThunderCandidate::new(2, 100, "Post 2".into(), 1001));
+        store.apply(3, 7, ThunderCandidate::new(3, 200, "Post 3".into(), 10));
+
+        assert_eq!(store.resume_offsets().get(&0), Some(&42));
+        assert_eq!(store.resume_offsets().get(&3), Some(&8));
+
+        assert_eq!(store.evict_expired(1100, &RetentionPolicy::new(500)), 1);
+        let restored = PostStore::from_snapshot(store.to_snapshot(1100));
+        assert_eq!(restored.len(), 2);
+        assert_eq!(restored.resume_offsets(), store.resume_offsets());
+        assert_eq!(restored.fetch_candidates(1, &[100], 10, None, None).len(), 2);
+    }
+
+    #[test]
+    fn test_expiry_skips_posts_kept_longer() {
+        let config = r#"{"authors": [{"author_id": 100, "retention_seconds": 1000}]}"#;
+        let config = serde_json::from_str(config).unwrap();
+        let retention = RetentionPolicy::with_config(100, &config).unwrap();
+        let mut store = PostStore::new();
+        store.apply(0, 0, ThunderCandidate::new(1, 100, "Kept".into(), 500));
+        store.apply(0, 1, ThunderCandidate::new(2, 200, "Expired".into(), 600));
+        store.apply(0, 2, ThunderCandidate::new(3, 200, "Fresh".into(), 950));
+
+        assert_eq!(store.evict_expired(1000, &retention), 1);
+        assert!(store.get(1).is_some() && store.get(2).is_none());
+        assert_eq!(store.evict_expired(1500, &retention), 2);
+        assert_eq!(store.len(), 0);
+    }
+
+    #[test]
+    fn test_memory_budget_evicts_oldest_first() {
+        let mut store = PostStore::new();
+        for post_id in 0..4 {
+            let post = ThunderCandidate::new(post_id, 100, "Post".into(), 1000 + post_id as u64);
+            store.apply(0, post_id, post);
+        }
+        let per_post = store.bytes() / 4;
+
+        assert_eq!(store.set_max_bytes(Some(per_post * 3)), 1);
+        store.apply(0, 4, ThunderCandidate::new(4, 200, "Post".into(), 1004));
+
+        let ids: Vec<i64> = store
+            .fetch_candidates(1, &[100, 200], 10, None, None)
+            .iter()
+            .map(|p| p.post_id)
+            .collect();
+        assert_eq!(ids, vec![4, 3, 2]);
+        assert!(store.bytes() <= per_post * 3);
+        assert_eq!(store.evict_expired(1102, &RetentionPolicy::new(100)), 1);
+        assert_eq!(
+            store.eviction_counts(),
+            EvictionCounts {
+                expired: 1,
+                over_budget: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_conversation_follows_reply_links_within_limits() {
+        let mut store = PostStore::new();
+        let reply = |post_id: i64, parent: i64, created_at: u64| {
+            let mut post = ThunderCandidate::new(post_id, 100, "Reply".into(), created_at);
+            post.is_reply = true;
+            post.reply_to_id = Some(parent);
+            post
+        };
+        // 1 <- 2 <- {4, 3} ; 4 <- 5
+        store.apply(0, 0, ThunderCandidate::new(1, 100, "Root".into(), 1000));
+        store.apply(0, 1, reply(2, 1, 1001));
+        store.apply(0, 2, reply(4, 2, 1002));
+        store.apply(0, 3, reply(3, 2, 1003));
+        store.apply(0, 4, reply(5, 4, 1004));
+
+        let thread = store.get_conversation(2, ConversationLimits::default()).unwrap();
+        assert_eq!(thread.ancestors.iter().map(|p| p.post_id).collect::<Vec<_>>(), vec![1]);
+        let replies: Vec<(usize, i64)> =
+            thread.replies.iter().map(|r| (r.depth, r.post.post_id)).collect();
+        assert_eq!(replies, vec![(1, 4), (1, 3), (2, 5)]);
+        assert!(!thread.truncated);
+
+        let limits = ConversationLimits {
+            max_depth: 1,
+            max_replies: 100,
+        };
+        let shallow = store.get_conversation(1, limits).unwrap();
+        assert_eq!(shallow.replies.len(), 1);
+        assert!(shallow.truncated);
+
+        store.delete(0, 5, 4);
+        assert_eq!(store.replies_to(2).len(), 1);
+        assert!(store.get_conversation(99, limits).is_none());
+    }
+
+    #[test]
+    fn test_engagement_updates_accumulate_in_place() {
+        let mut store = PostStore::new();
+        store.apply(0, 0, ThunderCandidate::new(1, 100, "Post".into(), 1000));
+        let delta = EngagementDelta {
+            likes: 3,
+            views: 50,
+            ..Default::default()
+        };
+
+        assert!(store.update_engagement(1, &delta, 1010));
+        assert!(store.update_engagement(1, &delta, 1020));
+        assert!(!store.update_engagement(2, &delta, 1020));
+
+        let engagement = &store.fetch_candidates(1, &[100], 10, None, None)[0].engagement;
+        assert_eq!(engagement.likes, 6);
+        assert_eq!(engagement.views, 100);
+        assert_eq!(engagement.updated_at, 1020);
+        assert_eq!(store.event_counts().engagement_updates, 2);
+    }
+
+    #[test]
+    fn test_apply_record_skips_already_applied_offsets() {
+        let mut store = PostStore::new();
+        let upsert = |offset, post_id| WalRecord {
+            partition: 0,
+            offset,
+            event: PostEvent::Upsert {
+                post: ThunderCandidate::new(post_id, 100, "Post".into(), 1000),
+            },
+        };
+
+        assert!(store.apply_record(upsert(0, 1)));
+        assert!(store.apply_record(upsert(1, 2)));
+        assert!(!store.apply_record(upsert(1, 2)));
+        assert!(store.apply_record(WalRecord {
+            partition: 0,
+            offset: 2,
+            event: PostEvent::Delete { post_id: 1 },
+        }));
+
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.resume_offsets().get(&0), Some(&3));
+    }
+
+    #[test]
+    fn test_edits_replace_content_and_reset_engagement() {
+        let mut store = PostStore::new();
+        let mut post = ThunderCandidate::new(1, 100, "Original".into(), 1000);
+        post.engagement.likes = 40;
+        post.engagement.views = 900;
+        store.apply(0, 0, post);
+
+        let edit = |offset, post_id| WalRecord {
+            partition: 0,
+            offset,
+            event: PostEvent::Edit {
+                post_id,
+                content: "Edited".into(),
+                has_media: true,
+                has_link: false,
+            },
+        };
+        assert!(store.apply_record(edit(1, 1)));
+        assert!(store.apply_record(edit(2, 99)));
+        assert!(store.apply_record(WalRecord {
+            partition: 0,
+            offset: 3,
+            event: PostEvent::Delete { post_id: 99 },
+        }));
+
+        let edited = &store.fetch_candidates(1, &[100], 10, None, None)[0];
+        assert_eq!(edited.content, "Edited");
+        assert!(edited.has_media);
+        assert_eq!(edited.engagement.likes, 0);
+        assert_eq!(edited.engagement.views, 900);
+        assert_eq!(
+            store.event_counts(),
+            PostEventCounts {
+                created: 1,
+                edited: 1,
+                deleted: 0,
+                engagement_updates: 0,
+                skipped: 2,
+            }
+        );
+    }
+}
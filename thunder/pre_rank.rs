@@ -0,0 +1,127 @@
+//! Pre-Ranking for Thunder
+//!
+//! When a query's limit cuts its results short, picks the best posts of the
+//! fetched window rather than simply the newest. Each post is scored by its
+//! recency, halving every half-life, times a factor growing with the log of its
+//! engagement, and no author gets more than a few of the picked posts. The
+//! score is returned on each candidate so home-mixer can reuse it.
+
+use std::collections::HashMap;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::candidate_source::ThunderCandidate;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct PreRankConfig {
+    /// Age at which a post's recency weight halves
+    pub half_life_seconds: u64,
+    /// Most posts picked per author; posts beyond it are only picked if too
+    /// few others are left to fill the page
+    pub max_per_author: usize,
+    /// Posts fetched per returned post, to rank from
+    pub pool_factor: usize,
+}
+
+impl Default for PreRankConfig {
+    fn default() -> Self {
+        Self {
+            half_life_seconds: 6 * 60 * 60,
+            max_per_author: 3,
+            pool_factor: 4,
+        }
+    }
+}
+
+impl PreRankConfig {
+    /// Recency decay times engagement boost of `post` at `now`
+    pub fn score(&self, post: &ThunderCandidate, now: u64) -> f64 {
+        let half_lives = post.age_seconds(now) as f64 / self.half_life_seconds.max(1) as f64;
+        let engagement = &post.engagement;
+        // Replies, reposts and bookmarks take more intent than a like or a view
+        let weighted = engagement.likes as f64
+            + 2.0 * (engagement.replies + engagement.reposts + engagement.bookmarks) as f64
+            + engagement.views as f64 / 100.0;
+        0.5f64.powf(half_lives) * (1.0 + weighted.ln_1p())
+    }
+
+    /// The best `limit` of `posts`, highest score first, with `pre_rank_score` set
+    pub fn shape(
+        &self,
+        posts: Vec<ThunderCandidate>,
+        limit: usize,
+        now: u64,
+    ) -> Vec<ThunderCandidate> {
+        let mut scored: Vec<ThunderCandidate> = posts
+            .into_iter()
+            .map(|mut post| {
+                post.pre_rank_score = Some(self.score(&post, now));
+                post
+            })
+            .collect();
+        scored.sort_by(|a, b| {
+            b.pre_rank_score
+                .partial_cmp(&a.pre_rank_score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(b.created_at.cmp(&a.created_at))
+                .then(b.post_id.cmp(&a.post_id))
+        });
+
+        let mut per_author: HashMap<i64, usize> = HashMap::new();
+        let (mut picked, mut over_cap) = (Vec::new(), Vec::new());
+        for post in scored {
+            let count = per_author.entry(post.author_id).or_insert(0);
+            *count += 1;
+            if *count <= self.max_per_author {
+                picked.push(post);
+            } else {
+                over_cap.push(post);
+            }
+        }
+        picked.extend(over_cap);
+        picked.truncate(limit);
+        picked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_engaged_posts_outrank_newer_ones_within_author_cap() {
+        let config = PreRankConfig {
+            half_life_seconds: 3600,
+            max_per_author: 2,
+            pool_factor: 4,
+        };
+        let now = 100_000;
+        let post = |post_id, author_id, age, likes| {
+            let mut post = ThunderCandidate::new(post_id, author_id, "Post".into(), now - age);
+            post.engagement.likes = likes;
+            post
+        };
+        let posts = vec![
+            post(1, 100, 60, 0),
+            post(2, 100, 600, 1000),
+            post(3, 100, 300, 200),
+            post(4, 100, 120, 50),
+            post(5, 200, 1800, 0),
+        ];
+
+        let shaped = config.shape(posts.clone(), 3, now);
+        let ids: Vec<i64> = shaped.iter().map(|p| p.post_id).collect();
+        // Author 100's third-best post only fills in after everyone else's
+        assert_eq!(ids, vec![2, 3, 5]);
+        assert!(shaped.iter().all(|p| p.pre_rank_score.is_some()));
+
+        let ids: Vec<i64> = config.shape(posts, 5, now).iter().map(|p| p.post_id).collect();
+        assert_eq!(ids, vec![2, 3, 5, 4, 1]);
+
+        // An hour old scores half as much as new
+        let fresh = config.score(&post(6, 300, 0, 10), now);
+        assert!((config.score(&post(7, 300, 3600, 10), now) - fresh / 2.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,186 @@
+//! Profiling
+//!
+//! Endpoints for profiling a running server without attaching a debugger,
+//! answering only requests that present the admin token as
+//! `Authorization: Bearer {token}`:
+//!
+//! - `/debug/pprof/profile?seconds=N` samples every thread's stack for `N`
+//!   seconds (10 by default, at most 60) and returns the flamegraph as SVG.
+//! - `/debug/pprof/heap` returns the allocation counters `CountingAllocator`
+//!   keeps, once the binary installs it as its global allocator.
+//!
+//! Only built with the `profiling` feature, and served with `enable_profiling`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+
+use crate::admin_auth::authorized;
+
+const DEFAULT_PROFILE_SECONDS: u64 = 10;
+const MAX_PROFILE_SECONDS: u64 = 60;
+/// Samples per second, off the round numbers other timers fire at
+const PROFILE_FREQUENCY: i32 = 99;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static DEALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+static IN_USE_BYTES: AtomicU64 = AtomicU64::new(0);
+static PEAK_IN_USE_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// The system allocator, counting what goes through it; a reallocation counts
+/// as a deallocation and an allocation
+pub struct CountingAllocator;
+
+fn record_alloc(size: usize) {
+    let size = size as u64;
+    ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    ALLOCATED_BYTES.fetch_add(size, Ordering::Relaxed);
+    let in_use = IN_USE_BYTES.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK_IN_USE_BYTES.fetch_max(in_use, Ordering::Relaxed);
+}
+
+fn record_dealloc(size: usize) {
+    DEALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    IN_USE_BYTES.fetch_sub(size as u64, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        record_dealloc(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            record_dealloc(layout.size());
+            record_alloc(new_size);
+        }
+        new_ptr
+    }
+}
+
+/// Totals since startup, all zero unless `CountingAllocator` is installed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct HeapStats {
+    pub allocations: u64,
+    pub deallocations: u64,
+    pub allocated_bytes: u64,
+    pub in_use_bytes: u64,
+    pub peak_in_use_bytes: u64,
+}
+
+impl HeapStats {
+    pub fn current() -> Self {
+        Self {
+            allocations: ALLOCATIONS.load(Ordering::Relaxed),
+            deallocations: DEALLOCATIONS.load(Ordering::Relaxed),
+            allocated_bytes: ALLOCATED_BYTES.load(Ordering::Relaxed),
+            in_use_bytes: IN_USE_BYTES.load(Ordering::Relaxed),
+            peak_in_use_bytes: PEAK_IN_USE_BYTES.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The `/debug/pprof` routes, answering only requests bearing `admin_token`
+pub fn router(admin_token: &str) -> Router {
+    Router::new()
+        .route("/debug/pprof/profile", get(cpu_profile))
+        .route("/debug/pprof/heap", get(heap_stats))
+        .with_state(Arc::<str>::from(admin_token))
+}
+
+#[derive(Debug, Deserialize)]
+struct ProfileParams {
+    seconds: Option<u64>,
+}
+
+async fn cpu_profile(
+    State(admin_token): State<Arc<str>>,
+    headers: HeaderMap,
+    Query(params): Query<ProfileParams>,
+) -> Response {
+    if !authorized(&headers, &admin_token) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let seconds = params
+        .seconds
+        .unwrap_or(DEFAULT_PROFILE_SECONDS)
+        .clamp(1, MAX_PROFILE_SECONDS);
+    match tokio::task::spawn_blocking(move || flamegraph(Duration::from_secs(seconds))).await {
+        // Nothing ran on the CPU while sampling
+        Ok(Ok(svg)) if svg.is_empty() => StatusCode::NO_CONTENT.into_response(),
+        Ok(Ok(svg)) => ([(header::CONTENT_TYPE, "image/svg+xml")], svg).into_response(),
+        Ok(Err(pprof::Error::Running)) => {
+            (StatusCode::CONFLICT, "a profile is already being taken").into_response()
+        }
+        Ok(Err(e)) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+async fn heap_stats(State(admin_token): State<Arc<str>>, headers: HeaderMap) -> Response {
+    if !authorized(&headers, &admin_token) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    Json(HeapStats::current()).into_response()
+}
+
+/// Sample for `duration`, blocking, and render the samples as a flamegraph
+fn flamegraph(duration: Duration) -> Result<Vec<u8>, pprof::Error> {
+    let guard = pprof::ProfilerGuardBuilder::default()
+        .frequency(PROFILE_FREQUENCY)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()?;
+    std::thread::sleep(duration);
+    let mut svg = Vec::new();
+    guard.report().build()?.flamegraph(&mut svg)?;
+    Ok(svg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counting_allocator_tracks_bytes_in_use() {
+        let before = HeapStats::current();
+        let layout = Layout::from_size_align(4096, 8).unwrap();
+        unsafe {
+            let ptr = CountingAllocator.alloc(layout);
+            let during = HeapStats::current();
+            assert_eq!(during.in_use_bytes - before.in_use_bytes, 4096);
+            assert!(during.peak_in_use_bytes >= during.in_use_bytes);
+            CountingAllocator.dealloc(ptr, layout);
+        }
+        let after = HeapStats::current();
+        assert_eq!(after.allocations - before.allocations, 1);
+        assert_eq!(after.deallocations - before.deallocations, 1);
+        assert_eq!(after.in_use_bytes, before.in_use_bytes);
+    }
+}
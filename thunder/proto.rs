@@ -0,0 +1,281 @@
+//! Thunder gRPC types
+//!
+//! Generated from `protos/thunder.proto` by the build script, along with the
+//! `ThunderService` server and client, plus conversions to and from the
+//! store's own types. `FILE_DESCRIPTOR_SET` describes the file for gRPC
+//! reflection, so tools like grpcurl can call the service without a copy of
+//! the definitions.
+
+use crate::{candidate_source, conversation, ingest, snapshot, wal};
+
+tonic::include_proto!("thunder");
+
+/// Encoded descriptors of the messages and service above
+pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("thunder_descriptor");
+
+impl From<candidate_source::ThunderCandidate> for ThunderCandidate {
+    fn from(post: candidate_source::ThunderCandidate) -> Self {
+        Self {
+            post_id: post.post_id,
+            author_id: post.author_id,
+            author_handle: post.author_handle,
+            content: post.content,
+            created_at: post.created_at,
+            has_media: post.has_media,
+            is_reply: post.is_reply,
+            reply_to_id: post.reply_to_id,
+            has_link: post.has_link,
+            engagement: Some(post.engagement.into()),
+            trending_in_network: post.trending_in_network,
+            pre_rank_score: post.pre_rank_score,
+        }
+    }
+}
+
+impl From<ThunderCandidate> for candidate_source::ThunderCandidate {
+    fn from(post: ThunderCandidate) -> Self {
+        Self {
+            post_id: post.post_id,
+            author_id: post.author_id,
+            author_handle: post.author_handle,
+            content: post.content,
+            created_at: post.created_at,
+            has_media: post.has_media,
+            is_reply: post.is_reply,
+            reply_to_id: post.reply_to_id,
+            has_link: post.has_link,
+            engagement: post.engagement.map(Into::into).unwrap_or_default(),
+            trending_in_network: post.trending_in_network,
+            pre_rank_score: post.pre_rank_score,
+        }
+    }
+}
+
+impl From<candidate_source::EngagementSnapshot> for EngagementSnapshot {
+    fn from(engagement: candidate_source::EngagementSnapshot) -> Self {
+        Self {
+            likes: engagement.likes,
+            replies: engagement.replies,
+            reposts: engagement.reposts,
+            bookmarks: engagement.bookmarks,
+            views: engagement.views,
+            updated_at: engagement.updated_at,
+        }
+    }
+}
+
+impl From<EngagementSnapshot> for candidate_source::EngagementSnapshot {
+    fn from(engagement: EngagementSnapshot) -> Self {
+        Self {
+            likes: engagement.likes,
+            replies: engagement.replies,
+            reposts: engagement.reposts,
+            bookmarks: engagement.bookmarks,
+            views: engagement.views,
+            updated_at: engagement.updated_at,
+        }
+    }
+}
+
+impl From<candidate_source::EngagementDelta> for EngagementDelta {
+    fn from(delta: candidate_source::EngagementDelta) -> Self {
+        Self {
+            likes: delta.likes,
+            replies: delta.replies,
+            reposts: delta.reposts,
+            bookmarks: delta.bookmarks,
+            views: delta.views,
+        }
+    }
+}
+
+impl From<EngagementDelta> for candidate_source::EngagementDelta {
+    fn from(delta: EngagementDelta) -> Self {
+        Self {
+            likes: delta.likes,
+            replies: delta.replies,
+            reposts: delta.reposts,
+            bookmarks: delta.bookmarks,
+            views: delta.views,
+        }
+    }
+}
+
+impl From<candidate_source::PostCursor> for PostCursor {
+    fn from(cursor: candidate_source::PostCursor) -> Self {
+        Self {
+            created_at: cursor.created_at,
+            post_id: cursor.post_id,
+        }
+    }
+}
+
+impl From<PostCursor> for candidate_source::PostCursor {
+    fn from(cursor: PostCursor) -> Self {
+        Self {
+            created_at: cursor.created_at,
+            post_id: cursor.post_id,
+        }
+    }
+}
+
+impl From<conversation::Conversation> for Conversation {
+    fn from(conversation: conversation::Conversation) -> Self {
+        Self {
+            post: Some(conversation.post.into()),
+            ancestors: conversation.ancestors.into_iter().map(Into::into).collect(),
+            replies: conversation
+                .replies
+                .into_iter()
+                .map(|reply| ConversationReply {
+                    depth: reply.depth as u32,
+                    post: Some(reply.post.into()),
+                })
+                .collect(),
+            truncated: conversation.truncated,
+        }
+    }
+}
+
+impl From<snapshot::PostStoreSnapshot> for PostStoreSnapshot {
+    fn from(snapshot: snapshot::PostStoreSnapshot) -> Self {
+        Self {
+            version: snapshot.version,
+            created_at: snapshot.created_at,
+            watermark: snapshot.watermark,
+            posts: snapshot.posts.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<PostStoreSnapshot> for snapshot::PostStoreSnapshot {
+    fn from(snapshot: PostStoreSnapshot) -> Self {
+        Self {
+            version: snapshot.version,
+            created_at: snapshot.created_at,
+            watermark: snapshot.watermark,
+            posts: snapshot.posts.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<ingest::AppliedChange> for AppliedChange {
+    fn from(change: ingest::AppliedChange) -> Self {
+        let change = match change {
+            ingest::AppliedChange::Record { record } => applied_change::Change::Record(WalRecord {
+                partition: record.partition,
+                offset: record.offset,
+                event: Some(record.event.into()),
+            }),
+            ingest::AppliedChange::Engagement {
+                post_id,
+                delta,
+                now,
+            } => applied_change::Change::Engagement(EngagementUpdate {
+                post_id,
+                delta: Some(delta.into()),
+                now,
+            }),
+        };
+        Self {
+            change: Some(change),
+        }
+    }
+}
+
+impl TryFrom<AppliedChange> for ingest::AppliedChange {
+    type Error = String;
+
+    fn try_from(change: AppliedChange) -> Result<Self, String> {
+        match change.change.ok_or("change without a record or engagement")? {
+            applied_change::Change::Record(record) => {
+                let event = record.event.ok_or("record without an event")?;
+                Ok(Self::Record {
+                    record: wal::WalRecord {
+                        partition: record.partition,
+                        offset: record.offset,
+                        event: event.try_into()?,
+                    },
+                })
+            }
+            applied_change::Change::Engagement(update) => Ok(Self::Engagement {
+                post_id: update.post_id,
+                delta: update.delta.map(Into::into).unwrap_or_default(),
+                now: update.now,
+            }),
+        }
+    }
+}
+
+impl From<wal::PostEvent> for PostEvent {
+    fn from(event: wal::PostEvent) -> Self {
+        let event = match event {
+            wal::PostEvent::Upsert { post } => post_event::Event::Upsert(post.into()),
+            wal::PostEvent::Edit {
+                post_id,
+                content,
+                has_media,
+                has_link,
+            } => post_event::Event::Edit(PostEdit {
+                post_id,
+                content,
+                has_media,
+                has_link,
+            }),
+            wal::PostEvent::Delete { post_id } => post_event::Event::Delete(post_id),
+        };
+        Self { event: Some(event) }
+    }
+}
+
+impl TryFrom<PostEvent> for wal::PostEvent {
+    type Error = String;
+
+    fn try_from(event: PostEvent) -> Result<Self, String> {
+        Ok(match event.event.ok_or("event without an upsert, edit or delete")? {
+            post_event::Event::Upsert(post) => Self::Upsert { post: post.into() },
+            post_event::Event::Edit(edit) => Self::Edit {
+                post_id: edit.post_id,
+                content: edit.content,
+                has_media: edit.has_media,
+                has_link: edit.has_link,
+            },
+            post_event::Event::Delete(post_id) => Self::Delete { post_id },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+
+    #[test]
+    fn test_changes_survive_the_wire() {
+        let mut post = candidate_source::ThunderCandidate::new(7, 100, "Post".into(), 1000);
+        post.reply_to_id = Some(3);
+        let event = wal::PostEvent::Upsert { post };
+        let record = wal::WalRecord {
+            partition: 1,
+            offset: 42,
+            event,
+        };
+        let bytes = AppliedChange::from(ingest::AppliedChange::Record { record }).encode_to_vec();
+        let decoded = AppliedChange::decode(bytes.as_slice()).unwrap();
+        let Ok(ingest::AppliedChange::Record { record }) = decoded.try_into() else {
+            panic!("not a record");
+        };
+        assert_eq!((record.partition, record.offset), (1, 42));
+        let wal::PostEvent::Upsert { post } = record.event else {
+            panic!("not an upsert");
+        };
+        assert_eq!((post.post_id, post.reply_to_id), (7, Some(3)));
+
+        let empty = ingest::AppliedChange::try_from(AppliedChange::default());
+        assert_eq!(empty.unwrap_err(), "change without a record or engagement");
+        let reflection = tonic_reflection::server::Builder::configure()
+            .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+            .build();
+        assert!(reflection.is_ok());
+    }
+}
@@ -1,10 +1,20 @@
 //! Realtime Query for Thunder
 //!
 //! Provides realtime query capabilities for fetching recent posts
-//! from followed accounts with freshness filtering.
+//! from followed accounts with freshness filtering, optionally picking the
+//! best of them rather than the newest (see `pre_rank`).
 
-use crate::candidate_source::{CandidateSource, ThunderCandidate};
+use std::collections::HashSet;
+use std::time::Instant;
+
+use crate::bloom_filter::BloomFilter;
+use crate::candidate_source::{AsyncCandidateSource, PostCursor, ThunderCandidate};
 use crate::config::ThunderConfig;
+use crate::error::ThunderError;
+use crate::proto::ImpressionBloomFilterEntry;
+
+/// Exclusion lists up to this size are scanned; larger ones are hashed
+const MAX_SCANNED_EXCLUSIONS: usize = 32;
 
 /// Query parameters for fetching in-network posts
 #[derive(Clone, Debug)]
@@ -17,8 +27,18 @@ pub struct RealtimeQuery {
     pub limit: usize,
     /// Maximum post age in seconds (default: 7 days)
     pub max_age_seconds: u64,
-    /// Exclude posts already seen (IDs)
+    /// Exclude posts already seen (IDs); send large sets as bloom filters instead
     pub exclude_post_ids: Vec<i64>,
+    /// Exclude posts in any of these impression bloom filters
+    pub bloom_filter_entries: Vec<ImpressionBloomFilterEntry>,
+    /// Maximum posts fetched from any one account per page (default: unlimited)
+    pub max_per_author: Option<usize>,
+    /// Continue after a previous page's `next_cursor`
+    pub cursor: Option<PostCursor>,
+    /// Return whatever has been gathered once this passes (default: none)
+    pub deadline: Option<Instant>,
+    /// Posts fetched per returned post, overriding the configured factor
+    pub fetch_factor: Option<f64>,
 }
 
 impl RealtimeQuery {
@@ -30,6 +50,11 @@ impl RealtimeQuery {
             limit: 100,
             max_age_seconds: 7 * 24 * 60 * 60, // 7 days
             exclude_post_ids: Vec::new(),
+            bloom_filter_entries: Vec::new(),
+            max_per_author: None,
+            cursor: None,
+            deadline: None,
+            fetch_factor: None,
         }
     }
 
@@ -50,6 +75,36 @@ impl RealtimeQuery {
         self.exclude_post_ids = post_ids;
         self
     }
+
+    /// Exclude posts in these impression bloom filters
+    pub fn exclude_seen(mut self, entries: Vec<ImpressionBloomFilterEntry>) -> Self {
+        self.bloom_filter_entries = entries;
+        self
+    }
+
+    /// Fetch the page after `cursor`
+    pub fn after(mut self, cursor: PostCursor) -> Self {
+        self.cursor = Some(cursor);
+        self
+    }
+
+    /// Cap the posts fetched per account
+    pub fn with_max_per_author(mut self, max: usize) -> Self {
+        self.max_per_author = Some(max);
+        self
+    }
+
+    /// Settle for partial results once `deadline` passes
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Fetch `factor` posts per returned post, e.g. as learned for the user
+    pub fn with_fetch_factor(mut self, factor: f64) -> Self {
+        self.fetch_factor = Some(factor);
+        self
+    }
 }
 
 /// Response from a realtime query
@@ -59,53 +114,161 @@ pub struct RealtimeQueryResponse {
     pub candidates: Vec<ThunderCandidate>,
     /// Total candidates available (before limit)
     pub total_available: usize,
+    /// Candidates fetched from the source before filtering
+    pub fetched: usize,
     /// Query execution time in ms
     pub query_time_ms: u64,
+    /// Cursor for the next page, or None when no older posts remain
+    pub next_cursor: Option<PostCursor>,
+    /// The deadline passed before every part of the source was read, so newer
+    /// posts may be missing
+    pub partial: bool,
+}
+
+/// Posts a query excludes, prepared once per query
+enum ExactExclusions {
+    Scanned(Vec<i64>),
+    Hashed(HashSet<i64>),
+}
+
+struct Exclusions {
+    exact: ExactExclusions,
+    bloom_filters: Vec<BloomFilter>,
+}
+
+impl Exclusions {
+    fn new(query: &RealtimeQuery) -> Result<Self, ThunderError> {
+        let exact = if query.exclude_post_ids.len() <= MAX_SCANNED_EXCLUSIONS {
+            ExactExclusions::Scanned(query.exclude_post_ids.clone())
+        } else {
+            ExactExclusions::Hashed(query.exclude_post_ids.iter().copied().collect())
+        };
+        Ok(Self {
+            exact,
+            bloom_filters: query
+                .bloom_filter_entries
+                .iter()
+                .map(BloomFilter::from_entry)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    fn contains(&self, post_id: i64) -> bool {
+        let exact = match &self.exact {
+            ExactExclusions::Scanned(ids) => ids.contains(&post_id),
+            ExactExclusions::Hashed(ids) => ids.contains(&post_id),
+        };
+        exact
+            || self
+                .bloom_filters
+                .iter()
+                .any(|filter| filter.may_contain(post_id as u64))
+    }
 }
 
 /// Execute a realtime query against the candidate source
-pub fn execute_query<S: CandidateSource>(
+pub async fn execute_query<S: AsyncCandidateSource + ?Sized>(
     source: &S,
     query: &RealtimeQuery,
-    _config: &ThunderConfig,
-) -> RealtimeQueryResponse {
+    config: &ThunderConfig,
+) -> Result<RealtimeQueryResponse, ThunderError> {
     let start = std::time::Instant::now();
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap()
         .as_secs();
+    // Malformed bloom filters fail the query before anything is fetched
+    let exclusions = Exclusions::new(query)?;
 
     // Fetch candidates from source
-    let all_candidates = source.fetch_candidates(
-        query.user_id,
-        &query.following_ids,
-        query.limit * 2, // Fetch extra to allow for filtering
-    );
+    // Fetch extra to allow for filtering, and a pool to pick from when pre-ranking
+    let factor = query.fetch_factor.unwrap_or(config.over_fetch.factor);
+    let over_fetched = ((query.limit as f64 * factor).ceil() as usize).max(query.limit);
+    let fetch_limit = match &config.pre_rank {
+        Some(pre_rank) => over_fetched.max(query.limit * pre_rank.pool_factor.max(2)),
+        None => over_fetched,
+    };
+    let (all_candidates, complete) = match query.deadline {
+        Some(deadline) => {
+            source
+                .fetch_candidates_until(
+                    query.user_id,
+                    &query.following_ids,
+                    fetch_limit,
+                    query.max_per_author,
+                    query.cursor,
+                    deadline,
+                )
+                .await?
+        }
+        None => {
+            let candidates = source
+                .fetch_candidates(
+                    query.user_id,
+                    &query.following_ids,
+                    fetch_limit,
+                    query.max_per_author,
+                    query.cursor,
+                )
+                .await?;
+            (candidates, true)
+        }
+    };
+
+    // Candidates come newest first, so the last one tells whether older posts
+    // may remain within the max age
+    let last_fetched = all_candidates.last().map(PostCursor::after);
+    let fetched = all_candidates.len();
+    let more_available = all_candidates.len() == fetch_limit
+        && all_candidates
+            .last()
+            .is_some_and(|c| c.is_fresh(now, query.max_age_seconds));
 
     // Filter by freshness and exclusions
     let filtered: Vec<_> = all_candidates
         .into_iter()
         .filter(|c| c.is_fresh(now, query.max_age_seconds))
-        .filter(|c| !query.exclude_post_ids.contains(&c.post_id))
+        .filter(|c| !exclusions.contains(c.post_id))
         .collect();
 
     let total = filtered.len();
-    let candidates: Vec<_> = filtered.into_iter().take(query.limit).collect();
+    let candidates: Vec<_> = match &config.pre_rank {
+        Some(pre_rank) => pre_rank.shape(filtered, query.limit, now),
+        None => filtered.into_iter().take(query.limit).collect(),
+    };
+
+    // Resume after the last post served if some were cut by the limit, otherwise
+    // after everything fetched, so excluded posts are skipped for good. Pre-ranked
+    // pages pick from the whole window, so the next one starts past it.
+    let next_cursor = if config.pre_rank.is_some() {
+        last_fetched.filter(|_| more_available)
+    } else if total > candidates.len() {
+        candidates.last().map(PostCursor::after)
+    } else if more_available {
+        last_fetched
+    } else {
+        None
+    };
 
-    RealtimeQueryResponse {
+    Ok(RealtimeQueryResponse {
         candidates,
         total_available: total,
+        fetched,
         query_time_ms: start.elapsed().as_millis() as u64,
-    }
+        next_cursor,
+        partial: !complete,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::candidate_source::InMemoryCandidateSource;
+    use crate::candidate_source::{InMemoryCandidateSource, SourceError, SyncSource};
+    use crate::pre_rank::PreRankConfig;
+    use tonic::async_trait;
 
-    #[test]
-    fn test_realtime_query() {
+    #[tokio::test]
+    async fn test_realtime_query() {
         let mut source = InMemoryCandidateSource::new();
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
@@ -121,13 +284,13 @@ mod tests {
 
         let query = RealtimeQuery::new(1, vec![100]);
         let config = ThunderConfig::default();
-        let response = execute_query(&source, &query, &config);
+        let response = execute_query(&SyncSource(source), &query, &config).await.unwrap();
 
         assert_eq!(response.candidates.len(), 2); // Only fresh posts
     }
 
-    #[test]
-    fn test_query_exclusions() {
+    #[tokio::test]
+    async fn test_query_exclusions() {
         let mut source = InMemoryCandidateSource::new();
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
@@ -140,9 +303,177 @@ mod tests {
 
         let query = RealtimeQuery::new(1, vec![100]).exclude(vec![2]);
         let config = ThunderConfig::default();
-        let response = execute_query(&source, &query, &config);
+        let response = execute_query(&SyncSource(source), &query, &config).await.unwrap();
 
         assert_eq!(response.candidates.len(), 2);
         assert!(!response.candidates.iter().any(|c| c.post_id == 2));
     }
+
+    #[tokio::test]
+    async fn test_prolific_author_is_capped() {
+        let mut source = InMemoryCandidateSource::new();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        for i in 0..10 {
+            source.add_post(ThunderCandidate::new(i, 100, "Prolific".into(), now - 10 - i as u64));
+        }
+        source.add_post(ThunderCandidate::new(99, 200, "Quiet".into(), now - 1000));
+
+        let query = RealtimeQuery::new(1, vec![100, 200]).with_limit(3).with_max_per_author(2);
+        let config = ThunderConfig::default();
+        let response = execute_query(&SyncSource(source), &query, &config).await.unwrap();
+
+        let ids: Vec<i64> = response.candidates.iter().map(|c| c.post_id).collect();
+        assert_eq!(ids, vec![0, 1, 99]);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_factor_makes_up_for_filtered_posts() {
+        let mut source = InMemoryCandidateSource::new();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        for post_id in 1..=10 {
+            let created_at = now - 100 + post_id as u64;
+            source.add_post(ThunderCandidate::new(post_id, 100, "Post".into(), created_at));
+        }
+        let source = SyncSource(source);
+        let config = ThunderConfig::default();
+        let query = RealtimeQuery::new(1, vec![100]).with_limit(2).exclude(vec![10, 9, 8, 7]);
+
+        // Twice the limit only reaches posts that are all excluded
+        let response = execute_query(&source, &query, &config).await.unwrap();
+        assert!(response.candidates.is_empty());
+        assert_eq!((response.fetched, response.total_available), (4, 0));
+
+        let query = query.with_fetch_factor(4.0);
+        let response = execute_query(&source, &query, &config).await.unwrap();
+        let ids: Vec<i64> = response.candidates.iter().map(|c| c.post_id).collect();
+        assert_eq!(ids, vec![6, 5]);
+        assert_eq!((response.fetched, response.total_available), (8, 4));
+    }
+
+    #[tokio::test]
+    async fn test_bloom_filter_and_large_exclusion_sets() {
+        let mut source = InMemoryCandidateSource::new();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        for post_id in 1..=4 {
+            source.add_post(ThunderCandidate::new(post_id, 100, "Post".into(), now - 100));
+        }
+
+        let mut seen = BloomFilter::new(4096, 3);
+        seen.insert(1);
+        let query = RealtimeQuery::new(1, vec![100])
+            .exclude(vec![2])
+            .exclude_seen(vec![seen.to_entry()]);
+        let config = ThunderConfig::default();
+        let response = execute_query(&SyncSource(source), &query, &config).await.unwrap();
+        let mut ids: Vec<i64> = response.candidates.iter().map(|c| c.post_id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![3, 4]);
+
+        let many: Vec<i64> = (3..1000).collect();
+        let exclusions = Exclusions::new(&RealtimeQuery::new(1, vec![100]).exclude(many)).unwrap();
+        assert!(matches!(exclusions.exact, ExactExclusions::Hashed(_)));
+        assert!(exclusions.contains(3) && !exclusions.contains(2));
+    }
+
+    #[tokio::test]
+    async fn test_cursor_pages_through_posts_once() {
+        let mut source = InMemoryCandidateSource::new();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        for post_id in 0..7 {
+            let author_id = 100 + post_id % 2;
+            // Two posts share each timestamp, so ties are broken by post id
+            let created_at = now - 100 - (post_id / 2) as u64;
+            source.add_post(ThunderCandidate::new(post_id, author_id, "Post".into(), created_at));
+        }
+        let source = SyncSource(source);
+        let config = ThunderConfig::default();
+
+        let mut pages = Vec::new();
+        let mut query = RealtimeQuery::new(1, vec![100, 101]).with_limit(3).exclude(vec![4]);
+        loop {
+            let response = execute_query(&source, &query, &config).await.unwrap();
+            pages.push(response.candidates.iter().map(|c| c.post_id).collect::<Vec<_>>());
+            match response.next_cursor {
+                Some(cursor) => query = query.after(cursor),
+                None => break,
+            }
+        }
+
+        assert_eq!(pages, vec![vec![1, 0, 3], vec![2, 5, 6]]);
+    }
+
+    #[tokio::test]
+    async fn test_pre_ranked_pages_pick_the_best_of_each_window() {
+        let mut source = InMemoryCandidateSource::new();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        for post_id in 0..12 {
+            let created_at = now - 10 - post_id as u64;
+            let author_id = 100 + post_id % 3;
+            let mut post = ThunderCandidate::new(post_id, author_id, "Post".into(), created_at);
+            post.engagement.likes = if post_id % 4 == 3 { 1000 } else { 0 };
+            source.add_post(post);
+        }
+        let source = SyncSource(source);
+        let config = ThunderConfig {
+            pre_rank: Some(PreRankConfig {
+                pool_factor: 3,
+                ..PreRankConfig::default()
+            }),
+            ..ThunderConfig::default()
+        };
+
+        // The window holds the 6 newest posts; the liked one comes first
+        let query = RealtimeQuery::new(1, vec![100, 101, 102]).with_limit(2);
+        let response = execute_query(&source, &query, &config).await.unwrap();
+        let ids: Vec<i64> = response.candidates.iter().map(|c| c.post_id).collect();
+        assert_eq!(ids, vec![3, 0]);
+        assert!(response.candidates.iter().all(|c| c.pre_rank_score.is_some()));
+
+        // The next page starts past the window rather than the posts served
+        let query = query.after(response.next_cursor.unwrap());
+        let response = execute_query(&source, &query, &config).await.unwrap();
+        let ids: Vec<i64> = response.candidates.iter().map(|c| c.post_id).collect();
+        assert_eq!(ids, vec![7, 11]);
+    }
+
+    struct UnreachableSource;
+
+    #[async_trait]
+    impl AsyncCandidateSource for UnreachableSource {
+        async fn fetch_candidates(
+            &self,
+            _user_id: i64,
+            _following_ids: &[i64],
+            _limit: usize,
+            _max_per_author: Option<usize>,
+            _cursor: Option<PostCursor>,
+        ) -> Result<Vec<ThunderCandidate>, SourceError> {
+            Err(SourceError::Timeout)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_source_errors_are_propagated() {
+        let query = RealtimeQuery::new(1, vec![100]);
+        let config = ThunderConfig::default();
+        let result = execute_query(&UnreachableSource, &query, &config).await;
+
+        assert!(matches!(result, Err(ThunderError::Timeout)));
+    }
 }
@@ -0,0 +1,119 @@
+//! Redis Streams Ingest Source for Thunder
+//!
+//! Reads a Redis stream with `XREAD`, carrying each event in the `event` field
+//! of an entry. A stream has a single partition (0). Entry ids (`ms-seq`) map to
+//! offsets as `ms << 16 | seq`, which keeps their order as long as fewer than
+//! 65536 entries share a millisecond.
+
+use std::collections::BTreeMap;
+
+use redis::aio::MultiplexedConnection;
+use redis::streams::{StreamRangeReply, StreamReadOptions, StreamReadReply};
+use redis::AsyncCommands;
+use tonic::async_trait;
+
+use crate::ingest_source::{
+    IngestConfig, IngestSource, StreamMessage, MAX_POLL_MESSAGES, POLL_TIMEOUT,
+};
+
+const PARTITION: i32 = 0;
+/// Entry field holding the JSON-encoded event
+const EVENT_FIELD: &str = "event";
+
+/// Offset of the entry with id `id`
+fn offset_of(id: &str) -> Result<i64, String> {
+    let (ms, seq) = id
+        .split_once('-')
+        .and_then(|(ms, seq)| Some((ms.parse::<i64>().ok()?, seq.parse::<i64>().ok()?)))
+        .ok_or_else(|| format!("invalid stream entry id {}", id))?;
+    Ok(ms << 16 | seq)
+}
+
+/// Id of the entry at `offset`
+fn id_of(offset: i64) -> String {
+    format!("{}-{}", offset >> 16, offset & 0xffff)
+}
+
+pub struct RedisSource {
+    connection: MultiplexedConnection,
+    key: String,
+    /// Id of the last entry read; `XREAD` returns the entries after it
+    last_id: String,
+}
+
+impl RedisSource {
+    pub async fn open(config: &IngestConfig) -> Result<Self, String> {
+        let connection = redis::Client::open(config.url.as_str())
+            .map_err(|e| format!("invalid Redis URL {}: {}", config.url, e))?
+            .get_multiplexed_tokio_connection()
+            .await
+            .map_err(|e| format!("failed to connect to {}: {}", config.url, e))?;
+        Ok(Self {
+            connection,
+            key: config.stream.clone(),
+            last_id: "0-0".to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl IngestSource for RedisSource {
+    async fn seek(&mut self, offsets: &BTreeMap<i32, i64>) -> Result<(), String> {
+        self.last_id = match offsets.get(&PARTITION) {
+            Some(offset) if *offset > 0 => id_of(offset - 1),
+            _ => "0-0".to_string(),
+        };
+        Ok(())
+    }
+
+    async fn end_offsets(&mut self) -> Result<BTreeMap<i32, i64>, String> {
+        let newest: StreamRangeReply = self
+            .connection
+            .xrevrange_count(&self.key, "+", "-", 1)
+            .await
+            .map_err(|e| format!("failed to read {}: {}", self.key, e))?;
+        let mut end_offsets = BTreeMap::new();
+        if let Some(entry) = newest.ids.first() {
+            end_offsets.insert(PARTITION, offset_of(&entry.id)? + 1);
+        }
+        Ok(end_offsets)
+    }
+
+    async fn poll(&mut self) -> Result<Vec<StreamMessage>, String> {
+        let options = StreamReadOptions::default()
+            .block(POLL_TIMEOUT.as_millis() as usize)
+            .count(MAX_POLL_MESSAGES);
+        let reply: Option<StreamReadReply> = self
+            .connection
+            .xread_options(&[&self.key], &[&self.last_id], &options)
+            .await
+            .map_err(|e| format!("failed to read {}: {}", self.key, e))?;
+
+        let mut messages = Vec::new();
+        for entry in reply.into_iter().flat_map(|r| r.keys).flat_map(|k| k.ids) {
+            messages.push(StreamMessage {
+                partition: PARTITION,
+                offset: offset_of(&entry.id)?,
+                payload: entry.get::<Vec<u8>>(EVENT_FIELD).unwrap_or_default(),
+            });
+            self.last_id = entry.id;
+        }
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_ids_map_to_ordered_offsets() {
+        let offsets: Vec<i64> = ["0-1", "1700000000000-0", "1700000000000-7", "1700000000001-0"]
+            .iter()
+            .map(|id| offset_of(id).unwrap())
+            .collect();
+        assert!(offsets.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(id_of(offsets[2]), "1700000000000-7");
+        assert!(offset_of("latest").is_err());
+    }
+}
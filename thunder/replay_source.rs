@@ -0,0 +1,164 @@
+//! File Replay Source for Thunder
+//!
+//! Feeds Thunder from a file of newline-delimited JSON post events instead of a
+//! live stream, so the full stack can run locally on sample data. Each line is
+//! a `PostEvent`, optionally with an `at_ms` field giving when to emit it,
+//! measured from the start of the replay; lines without one follow the
+//! previous line immediately. A speed multiplier scales the timing, and a
+//! speed of 0 replays everything at once. The file is a single partition (0)
+//! whose offsets number its events from 0, skipping blank lines.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::time::Instant;
+use tonic::async_trait;
+
+use crate::ingest_source::{IngestSource, StreamMessage, POLL_TIMEOUT};
+
+const PARTITION: i32 = 0;
+
+/// The timing field of a replay line; the rest of the line is the event
+#[derive(Debug, Deserialize)]
+struct ReplayTiming {
+    #[serde(default)]
+    at_ms: u64,
+}
+
+struct ReplayLine {
+    /// When to emit the line, from the start of the replay
+    at: Duration,
+    payload: Vec<u8>,
+}
+
+pub struct ReplaySource {
+    lines: Vec<ReplayLine>,
+    speed: f64,
+    next: usize,
+    /// When the replay (re)started, and the timing of the line it started at
+    started: (Instant, Duration),
+}
+
+impl ReplaySource {
+    pub fn open(path: &Path, speed: f64) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        let mut at = Duration::ZERO;
+        let mut lines = Vec::new();
+        for (number, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let timing: ReplayTiming = serde_json::from_str(line)
+                .map_err(|e| format!("{}:{}: {}", path.display(), number + 1, e))?;
+            // Events never go back in time; late ones are emitted right away
+            at = at.max(Duration::from_millis(timing.at_ms));
+            lines.push(ReplayLine {
+                at,
+                payload: line.as_bytes().to_vec(),
+            });
+        }
+        Ok(Self {
+            lines,
+            speed,
+            next: 0,
+            started: (Instant::now(), Duration::ZERO),
+        })
+    }
+
+    /// When the line at `index` is due
+    fn due(&self, index: usize) -> Instant {
+        let (started, base) = self.started;
+        if self.speed <= 0.0 {
+            return started;
+        }
+        let at = self.lines[index].at.saturating_sub(base);
+        started + at.div_f64(self.speed)
+    }
+}
+
+#[async_trait]
+impl IngestSource for ReplaySource {
+    async fn seek(&mut self, offsets: &BTreeMap<i32, i64>) -> Result<(), String> {
+        self.next = offsets.get(&PARTITION).copied().unwrap_or(0).max(0) as usize;
+        // A resumed replay keeps the spacing of the remaining lines
+        let base = self.lines.get(self.next).map_or(Duration::ZERO, |line| line.at);
+        self.started = (Instant::now(), base);
+        Ok(())
+    }
+
+    /// The lines due at the start of the replay, so Thunder serves once they
+    /// are applied while the timed lines keep arriving
+    async fn end_offsets(&mut self) -> Result<BTreeMap<i32, i64>, String> {
+        let base = self.lines.get(self.next).map_or(Duration::ZERO, |line| line.at);
+        let due_now = if self.speed <= 0.0 {
+            self.lines.len()
+        } else {
+            self.lines.iter().take_while(|line| line.at <= base).count()
+        };
+        Ok(BTreeMap::from([(PARTITION, due_now.max(self.next) as i64)]))
+    }
+
+    async fn poll(&mut self) -> Result<Vec<StreamMessage>, String> {
+        if self.next >= self.lines.len() {
+            tokio::time::sleep(POLL_TIMEOUT).await;
+            return Ok(Vec::new());
+        }
+        let due = self.due(self.next);
+        let deadline = Instant::now() + POLL_TIMEOUT;
+        if due > deadline {
+            tokio::time::sleep_until(deadline).await;
+            return Ok(Vec::new());
+        }
+        tokio::time::sleep_until(due).await;
+
+        let now = Instant::now();
+        let mut messages = Vec::new();
+        while self.next < self.lines.len() && self.due(self.next) <= now {
+            messages.push(StreamMessage {
+                partition: PARTITION,
+                offset: self.next as i64,
+                payload: self.lines[self.next].payload.clone(),
+            });
+            self.next += 1;
+        }
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_replay_resumes_with_scaled_timing() {
+        let path = std::env::temp_dir().join(format!("thunder_replay_{}", std::process::id()));
+        let lines = [
+            r#"{"type":"delete","post_id":1}"#,
+            r#"{"type":"delete","post_id":2}"#,
+            r#"{"at_ms":200,"type":"delete","post_id":3}"#,
+            "",
+            r#"{"at_ms":400,"type":"delete","post_id":4}"#,
+        ];
+        fs::write(&path, lines.join("\n")).unwrap();
+
+        let mut source = ReplaySource::open(&path, 10.0).unwrap();
+        source.seek(&BTreeMap::from([(0, 1)])).await.unwrap();
+        assert_eq!(source.end_offsets().await.unwrap().get(&0), Some(&2));
+
+        let start = Instant::now();
+        let mut offsets = Vec::new();
+        while offsets.len() < 3 {
+            offsets.extend(source.poll().await.unwrap().iter().map(|m| m.offset));
+        }
+        assert_eq!(offsets, vec![1, 2, 3]);
+        // Event 3 is due 400ms after event 1, i.e. 40ms at 10x
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(40) && elapsed < Duration::from_millis(400));
+
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,396 @@
+//! Replication for Thunder
+//!
+//! Lets a warm standby mirror the post store of a leader, so it can take over
+//! serving without replaying the post stream from scratch. The follower calls
+//! the leader's `Replicate` method, which sends a snapshot of the store and then
+//! every change applied after it (posts and engagement alike), interleaved with
+//! heartbeats carrying the leader's offsets. The follower replaces its store
+//! with the snapshot and applies the changes through its own ingestor, so they
+//! reach its WAL and subscribers. Whenever the stream ends, the follower
+//! reconnects and starts over from a new snapshot. Promoting the follower stops
+//! replication; consuming the post stream then resumes from the offsets the
+//! store has reached.
+//!
+//! With `replicate_from` set, Thunder starts as a follower of the leader's gRPC
+//! server, reports its status at `/replication` and is promoted with an admin
+//! `POST /admin/replication/promote`.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures::{Stream, StreamExt};
+use log::{info, warn};
+use serde::Serialize;
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinHandle;
+use tonic::Status;
+
+use crate::admin_auth::admin_only;
+use crate::ingest::{AppliedChange, Ingestor};
+use crate::proto::replication_message::Message;
+use crate::proto::thunder_service_client::ThunderServiceClient;
+use crate::proto::{ReplicateRequest, ReplicationMessage};
+use crate::snapshot::{now_seconds, PostStoreSnapshot};
+
+/// Wait before reconnecting to the leader after the stream ends
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Unix epoch milliseconds
+pub fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+/// How far a follower trails its leader
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ReplicationStatus {
+    /// Connected to the leader and past the snapshot
+    pub connected: bool,
+    /// Events the leader had applied and the follower hadn't, at the last heartbeat
+    pub lag: u64,
+    /// Time from the leader sending the last heartbeat to the follower reaching
+    /// it, which includes the changes queued ahead of it; leader and follower
+    /// clocks are assumed to agree
+    pub lag_ms: Option<u64>,
+    /// Snapshots restored, one per connection
+    pub snapshots: u64,
+    /// Changes applied since startup
+    pub changes: u64,
+}
+
+/// Events the follower still has to apply to reach `leader`
+fn offset_lag(leader: &BTreeMap<i32, i64>, follower: &BTreeMap<i32, i64>) -> u64 {
+    leader
+        .iter()
+        .map(|(partition, next)| {
+            let applied = follower.get(partition).copied().unwrap_or(0);
+            next.saturating_sub(applied).max(0) as u64
+        })
+        .sum()
+}
+
+/// Apply a replication stream to `ingestor` until it ends, updating `status`
+pub async fn follow<S>(
+    mut messages: S,
+    ingestor: &Arc<Ingestor>,
+    status: &watch::Sender<ReplicationStatus>,
+) -> Result<(), String>
+where
+    S: Stream<Item = Result<ReplicationMessage, Status>> + Unpin,
+{
+    while let Some(message) = messages.next().await {
+        let message = message.map_err(|e| e.to_string())?.message;
+        match message.ok_or("empty replication message")? {
+            Message::Snapshot(snapshot) => {
+                let snapshot = PostStoreSnapshot::from(snapshot);
+                let posts = snapshot.posts.len();
+                let restorer = ingestor.clone();
+                tokio::task::spawn_blocking(move || restorer.restore(snapshot, now_seconds()))
+                    .await
+                    .map_err(|e| e.to_string())??;
+                info!("Restored {} posts from the replication leader", posts);
+                status.send_modify(|status| {
+                    status.connected = true;
+                    status.snapshots += 1;
+                });
+            }
+            Message::Change(change) => {
+                match AppliedChange::try_from(change)? {
+                    AppliedChange::Record { record } => {
+                        ingestor.ingest(record)?;
+                    }
+                    AppliedChange::Engagement {
+                        post_id,
+                        delta,
+                        now,
+                    } => {
+                        ingestor.update_engagement(post_id, &delta, now);
+                    }
+                }
+                status.send_modify(|status| status.changes += 1);
+            }
+            Message::Heartbeat(heartbeat) => {
+                let lag = offset_lag(&heartbeat.offsets, &ingestor.store().resume_offsets());
+                let lag_ms = now_millis().saturating_sub(heartbeat.sent_at_ms);
+                status.send_modify(|status| {
+                    status.lag = lag;
+                    status.lag_ms = Some(lag_ms);
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replication from a leader, running until the follower is promoted
+pub struct Follower {
+    status: watch::Receiver<ReplicationStatus>,
+    task: JoinHandle<()>,
+}
+
+impl Follower {
+    pub fn status(&self) -> watch::Receiver<ReplicationStatus> {
+        self.status.clone()
+    }
+
+    /// Stop replicating so the follower can serve as the leader; returns the
+    /// last status
+    pub fn promote(self) -> ReplicationStatus {
+        self.task.abort();
+        let status = self.status.borrow().clone();
+        info!("Promoted to leader ({} events behind the old leader)", status.lag);
+        status
+    }
+}
+
+/// Mirror the store of the leader reached by `connect` into `ingestor`,
+/// reconnecting whenever the replication stream ends
+pub fn spawn_follower<C, F, S>(connect: C, ingestor: Arc<Ingestor>) -> Follower
+where
+    C: Fn() -> F + Send + 'static,
+    F: Future<Output = Result<S, String>> + Send,
+    S: Stream<Item = Result<ReplicationMessage, Status>> + Unpin + Send,
+{
+    let (sender, status) = watch::channel(ReplicationStatus::default());
+    let task = tokio::spawn(async move {
+        loop {
+            match connect().await {
+                Ok(messages) => match follow(messages, &ingestor, &sender).await {
+                    Ok(()) => warn!("Replication leader ended the stream"),
+                    Err(e) => warn!("Replication stream failed: {}", e),
+                },
+                Err(e) => warn!("Failed to connect to the replication leader: {}", e),
+            }
+            sender.send_modify(|status| status.connected = false);
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+    Follower { status, task }
+}
+
+/// Mirror the leader serving gRPC at `leader`, e.g. `http://thunder-0:50051`,
+/// introducing the follower as `name`
+pub fn spawn_grpc_follower(leader: String, name: String, ingestor: Arc<Ingestor>) -> Follower {
+    let connect = move || {
+        let (leader, follower) = (leader.clone(), name.clone());
+        async move {
+            let mut client = ThunderServiceClient::connect(leader)
+                .await
+                .map_err(|e| e.to_string())?;
+            let response = client
+                .replicate(ReplicateRequest { follower })
+                .await
+                .map_err(|e| e.to_string())?;
+            Ok(response.into_inner())
+        }
+    };
+    spawn_follower(connect, ingestor)
+}
+
+/// Replication status as served at `/replication`
+#[derive(Debug, Serialize)]
+struct ReplicationReport {
+    /// Stopped replicating to serve as the leader
+    promoted: bool,
+    #[serde(flatten)]
+    status: ReplicationStatus,
+}
+
+/// A follower awaiting promotion, with who to tell once it is promoted
+struct Standby {
+    follower: Follower,
+    promoted: oneshot::Sender<ReplicationStatus>,
+}
+
+#[derive(Clone)]
+struct ReplicationState {
+    status: watch::Receiver<ReplicationStatus>,
+    /// Taken when promoted
+    standby: Arc<Mutex<Option<Standby>>>,
+}
+
+/// `GET /replication`, reporting how far `follower` trails its leader, and
+/// `POST /admin/replication/promote`, answering only requests bearing
+/// `admin_token`. The receiver gets the last status once it is promoted.
+pub fn router(
+    follower: Follower,
+    admin_token: Option<&str>,
+) -> (Router, oneshot::Receiver<ReplicationStatus>) {
+    let (sender, promoted) = oneshot::channel();
+    let state = ReplicationState {
+        status: follower.status(),
+        standby: Arc::new(Mutex::new(Some(Standby {
+            follower,
+            promoted: sender,
+        }))),
+    };
+    let router = Router::new()
+        .route("/replication", get(replication_status))
+        .with_state(state.clone())
+        .merge(admin_only(
+            Router::new()
+                .route("/admin/replication/promote", post(promote))
+                .with_state(state),
+            admin_token,
+        ));
+    (router, promoted)
+}
+
+async fn replication_status(State(state): State<ReplicationState>) -> impl IntoResponse {
+    Json(ReplicationReport {
+        promoted: state.standby.lock().unwrap().is_none(),
+        status: state.status.borrow().clone(),
+    })
+}
+
+async fn promote(State(state): State<ReplicationState>) -> impl IntoResponse {
+    let Some(standby) = state.standby.lock().unwrap().take() else {
+        return (StatusCode::CONFLICT, "already promoted").into_response();
+    };
+    let status = standby.follower.promote();
+    // Fails only when nobody waits to take over as the leader
+    let _ = standby.promoted.send(status.clone());
+    Json(ReplicationReport {
+        promoted: true,
+        status,
+    })
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::candidate_source::{EngagementDelta, ThunderCandidate};
+    use crate::proto::thunder_service_server::{ThunderService, ThunderServiceServer};
+    use crate::server::ThunderServer;
+    use crate::sharded_store::ShardedPostStore;
+    use crate::wal::{PostEvent, WalRecord};
+    use axum::body::Body;
+    use axum::http::header;
+    use tonic::transport::server::TcpIncoming;
+    use tonic::Request;
+    use tower::ServiceExt;
+
+    fn upsert(offset: i64, author_id: i64) -> WalRecord {
+        WalRecord {
+            partition: 0,
+            offset,
+            event: PostEvent::Upsert {
+                post: ThunderCandidate::new(offset, author_id, "Post".into(), 1000),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_follower_mirrors_snapshot_and_changes() {
+        let leader = Arc::new(Ingestor::new(Arc::new(ShardedPostStore::new(2)), None));
+        leader.ingest(upsert(0, 100)).unwrap();
+        leader.ingest(upsert(1, 101)).unwrap();
+        let server = Arc::new(ThunderServer::new(leader.clone()));
+
+        // The follower holds a post the leader doesn't, which the snapshot drops
+        let follower = Arc::new(Ingestor::new(Arc::new(ShardedPostStore::new(3)), None));
+        let mut stale = upsert(7, 102);
+        stale.partition = 1;
+        follower.ingest(stale).unwrap();
+        let connect = move || {
+            let server = server.clone();
+            async move {
+                let request = Request::new(ReplicateRequest {
+                    follower: "standby".into(),
+                });
+                let response = server.replicate(request).await.map_err(|e| e.to_string())?;
+                Ok(response.into_inner())
+            }
+        };
+        let replica = spawn_follower(connect, follower.clone());
+        let mut status = replica.status();
+        status.wait_for(|status| status.connected).await.unwrap();
+
+        leader.ingest(upsert(2, 100)).unwrap();
+        let delta = EngagementDelta {
+            likes: 3,
+            ..Default::default()
+        };
+        assert!(leader.update_engagement(0, &delta, 1010));
+        status.wait_for(|status| status.changes == 2).await.unwrap();
+
+        let store = follower.store();
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.resume_offsets(), leader.store().resume_offsets());
+        let post = store.to_snapshot(0).posts.into_iter().find(|p| p.post_id == 0);
+        assert_eq!(post.unwrap().engagement.likes, 3);
+
+        status.wait_for(|status| status.lag_ms.is_some()).await.unwrap();
+        let last = replica.promote();
+        assert_eq!((last.lag, last.snapshots), (0, 1));
+    }
+
+    #[tokio::test]
+    async fn test_grpc_follower_reports_status_and_is_promoted_by_an_admin() {
+        let leader = Arc::new(Ingestor::new(Arc::new(ShardedPostStore::new(2)), None));
+        leader.ingest(upsert(0, 100)).unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        let server = ThunderServer::new(leader.clone());
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(ThunderServiceServer::new(server))
+                .serve_with_incoming(incoming),
+        );
+
+        let follower = Arc::new(Ingestor::new(Arc::new(ShardedPostStore::new(2)), None));
+        let replica =
+            spawn_grpc_follower(format!("http://{}", addr), "standby".into(), follower.clone());
+        let mut status = replica.status();
+        let (app, promoted) = router(replica, Some("secret"));
+        status.wait_for(|status| status.connected).await.unwrap();
+        leader.ingest(upsert(1, 101)).unwrap();
+        status.wait_for(|status| status.changes == 1).await.unwrap();
+        assert_eq!(follower.store().len(), 2);
+
+        let call = |method: &str, uri: &str, bearer: Option<&str>| {
+            let mut request = axum::http::Request::builder().method(method).uri(uri);
+            if let Some(bearer) = bearer {
+                request = request.header(header::AUTHORIZATION, format!("Bearer {}", bearer));
+            }
+            let request = request.body(Body::empty()).unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                (status, serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null))
+            }
+        };
+        let (code, report) = call("GET", "/replication", None).await;
+        assert_eq!(code, StatusCode::OK);
+        assert_eq!(report["connected"], true);
+        assert_eq!(report["promoted"], false);
+
+        let promote = "/admin/replication/promote";
+        assert_eq!(call("POST", promote, None).await.0, StatusCode::UNAUTHORIZED);
+        let (code, report) = call("POST", promote, Some("secret")).await;
+        assert_eq!(code, StatusCode::OK);
+        assert_eq!(report["promoted"], true);
+        assert_eq!(report["changes"], 1);
+        assert_eq!(promoted.await.unwrap().snapshots, 1);
+        assert_eq!(call("POST", promote, Some("secret")).await.0, StatusCode::CONFLICT);
+        assert_eq!(call("GET", "/replication", None).await.1["promoted"], true);
+
+        // Promoted: the leader's changes no longer reach the follower
+        leader.ingest(upsert(2, 100)).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(follower.store().len(), 2);
+    }
+}
@@ -0,0 +1,152 @@
+//! Request Limiter for Thunder
+//!
+//! Caps how many queries Thunder handles at once and how long each may take.
+//! A query arriving while every permit is taken is shed right away with
+//! RESOURCE_EXHAUSTED instead of queueing, so a burst can't build up latency
+//! for everyone. Queries over the store settle for partial results at the
+//! deadline; anything still running past it fails with DEADLINE_EXCEEDED.
+//! Saturation counters are logged with the other stats.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::info;
+use serde::Serialize;
+use tokio::sync::Semaphore;
+
+use crate::error::ThunderError;
+
+/// Saturation of the limiter since startup
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct LimiterStats {
+    pub max_concurrent: usize,
+    pub in_flight: usize,
+    pub admitted: u64,
+    /// Shed with RESOURCE_EXHAUSTED because every permit was taken
+    pub rejected: u64,
+    /// Failed with DEADLINE_EXCEEDED
+    pub timed_out: u64,
+    /// Answered with partial results at the deadline
+    pub partial: u64,
+}
+
+pub struct RequestLimiter {
+    permits: Semaphore,
+    max_concurrent: usize,
+    timeout: Duration,
+    admitted: AtomicU64,
+    rejected: AtomicU64,
+    timed_out: AtomicU64,
+    partial: AtomicU64,
+}
+
+impl RequestLimiter {
+    pub fn new(max_concurrent: usize, timeout: Duration) -> Self {
+        let max_concurrent = max_concurrent.max(1);
+        Self {
+            permits: Semaphore::new(max_concurrent),
+            max_concurrent,
+            timeout,
+            admitted: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            timed_out: AtomicU64::new(0),
+            partial: AtomicU64::new(0),
+        }
+    }
+
+    /// Run `query` under a permit, handing it the deadline to settle for partial
+    /// results by
+    pub async fn run<T, F>(&self, query: impl FnOnce(Instant) -> F) -> Result<T, ThunderError>
+    where
+        F: Future<Output = Result<T, ThunderError>>,
+    {
+        let Ok(_permit) = self.permits.try_acquire() else {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(ThunderError::TooManyRequests {
+                max_concurrent: self.max_concurrent,
+            });
+        };
+        self.admitted.fetch_add(1, Ordering::Relaxed);
+
+        let deadline = Instant::now() + self.timeout;
+        match tokio::time::timeout(self.timeout, query(deadline)).await {
+            Ok(result) => result,
+            Err(_) => {
+                self.timed_out.fetch_add(1, Ordering::Relaxed);
+                Err(ThunderError::Timeout)
+            }
+        }
+    }
+
+    /// Count a query answered with partial results
+    pub fn record_partial(&self) {
+        self.partial.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> LimiterStats {
+        LimiterStats {
+            max_concurrent: self.max_concurrent,
+            in_flight: self.max_concurrent - self.permits.available_permits(),
+            admitted: self.admitted.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            timed_out: self.timed_out.load(Ordering::Relaxed),
+            partial: self.partial.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Log the limiter's saturation every `interval`
+pub fn spawn_stats_logger(limiter: Arc<RequestLimiter>, interval: Duration) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            info!("Query limiter: {:?}", limiter.stats());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sheds_load_and_enforces_the_deadline() {
+        let limiter = Arc::new(RequestLimiter::new(1, Duration::from_millis(50)));
+
+        // Hold the only permit while a second query arrives
+        let (release, released) = tokio::sync::oneshot::channel::<()>();
+        let held = tokio::spawn({
+            let limiter = limiter.clone();
+            async move {
+                limiter
+                    .run(|_| async {
+                        let _ = released.await;
+                        Ok(())
+                    })
+                    .await
+            }
+        });
+        while limiter.stats().in_flight == 0 {
+            tokio::task::yield_now().await;
+        }
+        let shed = limiter.run(|_| async { Ok(()) }).await.unwrap_err();
+        assert_eq!(shed, ThunderError::TooManyRequests { max_concurrent: 1 });
+        release.send(()).unwrap();
+        held.await.unwrap().unwrap();
+
+        let slow = limiter
+            .run(|_| async {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                Ok(())
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(slow, ThunderError::Timeout);
+
+        let stats = limiter.stats();
+        assert_eq!((stats.admitted, stats.rejected, stats.timed_out), (2, 1, 1));
+        assert_eq!(stats.in_flight, 0);
+    }
+}
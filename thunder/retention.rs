@@ -0,0 +1,183 @@
+//! Retention for Thunder
+//!
+//! Decides how long posts are kept. Every post is kept for the default
+//! retention period; a JSON config file can extend it for chosen authors,
+//! either directly or through named tiers, and a post is kept for the longest
+//! period that applies to its author. The trim task drops what has expired at
+//! a fixed interval, so the store doesn't only shrink on restart.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::info;
+use serde::Deserialize;
+
+use crate::candidate_source::ThunderCandidate;
+use crate::sharded_store::ShardedPostStore;
+use crate::snapshot::now_seconds;
+
+/// Retention overrides, as read from the config file:
+///
+/// ```json
+/// {
+///   "tiers": {"news": 1209600, "partner": 2592000},
+///   "authors": [
+///     {"author_id": 12345, "tier": "news"},
+///     {"author_id": 67890, "retention_seconds": 5184000}
+///   ]
+/// }
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionConfig {
+    /// Retention in seconds per tier name
+    #[serde(default)]
+    pub tiers: HashMap<String, u64>,
+    #[serde(default)]
+    pub authors: Vec<AuthorRetention>,
+}
+
+/// Retention of one author, by tier, in seconds, or both
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthorRetention {
+    pub author_id: i64,
+    #[serde(default)]
+    pub tier: Option<String>,
+    #[serde(default)]
+    pub retention_seconds: Option<u64>,
+}
+
+/// How long the posts of each author are kept
+#[derive(Clone, Debug, Default)]
+pub struct RetentionPolicy {
+    default_seconds: u64,
+    /// Authors kept longer than the default
+    authors: HashMap<i64, u64>,
+}
+
+impl RetentionPolicy {
+    /// Keep every post for `default_seconds`
+    pub fn new(default_seconds: u64) -> Self {
+        Self {
+            default_seconds,
+            authors: HashMap::new(),
+        }
+    }
+
+    /// Apply the overrides of `config` on top of `default_seconds`. Overrides
+    /// shorter than the default have no effect.
+    pub fn with_config(default_seconds: u64, config: &RetentionConfig) -> Result<Self, String> {
+        let mut policy = Self::new(default_seconds);
+        for author in &config.authors {
+            let tier_seconds = match &author.tier {
+                Some(tier) => *config
+                    .tiers
+                    .get(tier)
+                    .ok_or_else(|| format!("author {}: unknown tier {}", author.author_id, tier))?,
+                None => 0,
+            };
+            let seconds = tier_seconds.max(author.retention_seconds.unwrap_or(0));
+            if seconds > default_seconds {
+                let longest = policy.authors.entry(author.author_id).or_insert(0);
+                *longest = (*longest).max(seconds);
+            }
+        }
+        Ok(policy)
+    }
+
+    /// Read the overrides from the JSON config file at `path`
+    pub fn load(path: &Path, default_seconds: u64) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        let config: RetentionConfig = serde_json::from_str(&contents)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::with_config(default_seconds, &config)
+    }
+
+    /// The shortest retention, which applies to every author without an override
+    pub fn default_seconds(&self) -> u64 {
+        self.default_seconds
+    }
+
+    /// The longest retention of any author
+    pub fn longest_seconds(&self) -> u64 {
+        self.authors.values().copied().fold(self.default_seconds, u64::max)
+    }
+
+    /// Number of authors kept longer than the default
+    pub fn overrides(&self) -> usize {
+        self.authors.len()
+    }
+
+    pub fn seconds_for(&self, author_id: i64) -> u64 {
+        self.authors.get(&author_id).copied().unwrap_or(self.default_seconds)
+    }
+
+    /// Whether `post` is still within its author's retention at `now`
+    pub fn retains(&self, post: &ThunderCandidate, now: u64) -> bool {
+        post.is_fresh(now, self.seconds_for(post.author_id))
+    }
+}
+
+/// Drop expired posts from `store` every `interval`
+pub fn spawn_trimmer(
+    store: Arc<ShardedPostStore>,
+    policy: Arc<RetentionPolicy>,
+    interval: Duration,
+) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            let trimmed = store.evict_expired(now_seconds(), &policy);
+            if trimmed > 0 {
+                info!("Trimmed {} expired posts", trimmed);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_longest_applicable_retention_wins() {
+        let config: RetentionConfig = serde_json::from_str(
+            r#"{
+                "tiers": {"news": 1000, "short": 50},
+                "authors": [
+                    {"author_id": 1, "tier": "news"},
+                    {"author_id": 1, "retention_seconds": 2000},
+                    {"author_id": 2, "tier": "news", "retention_seconds": 500},
+                    {"author_id": 3, "tier": "short"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let policy = RetentionPolicy::with_config(100, &config).unwrap();
+        assert_eq!(policy.seconds_for(1), 2000);
+        assert_eq!(policy.seconds_for(2), 1000);
+        // Overrides only ever extend the default
+        assert_eq!(policy.seconds_for(3), 100);
+        assert_eq!(policy.seconds_for(4), 100);
+        assert_eq!((policy.overrides(), policy.longest_seconds()), (2, 2000));
+
+        let post = ThunderCandidate::new(10, 2, "Post".into(), 1000);
+        assert!(policy.retains(&post, 1999));
+        assert!(!policy.retains(&post, 2000));
+
+        let unknown = RetentionConfig {
+            authors: vec![AuthorRetention {
+                author_id: 5,
+                tier: Some("gold".into()),
+                retention_seconds: None,
+            }],
+            ..Default::default()
+        };
+        assert!(RetentionPolicy::with_config(100, &unknown).is_err());
+    }
+}
@@ -0,0 +1,353 @@
+//! RocksDB Post Storage for Thunder
+//!
+//! Keeps a shard's posts on disk in column families that mirror the in-memory
+//! `AuthorIndex`: posts by id, and ordered keys for the author timelines,
+//! creation order, replies by parent and search postings. Every change to a
+//! post rewrites its entries in one write batch. Ids are stored big-endian with
+//! the sign bit flipped, so keys sort the same way as the numbers they hold.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use log::error;
+use rocksdb::{ColumnFamily, Direction, IteratorMode, Options, WriteBatch, DB};
+
+use crate::candidate_source::{PostCursor, ThunderCandidate};
+use crate::search_index::tokenize;
+use crate::storage::PostStorage;
+
+const POSTS: &str = "posts";
+const TIMELINES: &str = "timelines";
+const BY_AGE: &str = "by_age";
+const REPLIES: &str = "replies";
+const POSTINGS: &str = "postings";
+const OFFSETS: &str = "offsets";
+const COLUMN_FAMILIES: [&str; 6] = [POSTS, TIMELINES, BY_AGE, REPLIES, POSTINGS, OFFSETS];
+
+/// Creation time and post id, the order every index keeps posts in
+type PostKey = (u64, i64);
+
+fn id_bytes(id: i64) -> [u8; 8] {
+    ((id as u64) ^ (1 << 63)).to_be_bytes()
+}
+
+/// `prefix` followed by the creation time and post id
+fn ordered_key(prefix: &[u8], created_at: u64, post_id: i64) -> Vec<u8> {
+    let mut key = Vec::with_capacity(prefix.len() + 16);
+    key.extend_from_slice(prefix);
+    key.extend_from_slice(&created_at.to_be_bytes());
+    key.extend_from_slice(&id_bytes(post_id));
+    key
+}
+
+/// Creation time and post id from the end of an `ordered_key`
+fn decode_post_key(key: &[u8]) -> PostKey {
+    let (created_at, post_id) = key[key.len() - 16..].split_at(8);
+    let created_at = u64::from_be_bytes(created_at.try_into().unwrap());
+    let post_id = (u64::from_be_bytes(post_id.try_into().unwrap()) ^ (1 << 63)) as i64;
+    (created_at, post_id)
+}
+
+/// Postings are prefixed by the term and a separator tokens never contain
+fn term_prefix(term: &str) -> Vec<u8> {
+    let mut prefix = term.as_bytes().to_vec();
+    prefix.push(0);
+    prefix
+}
+
+/// A shard's posts in a RocksDB database
+pub struct RocksDbStorage {
+    db: DB,
+    len: usize,
+    bytes: usize,
+}
+
+impl RocksDbStorage {
+    /// Open or create the database at `path`, counting the posts it already holds
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+        let db = DB::open_cf(&options, path, COLUMN_FAMILIES)
+            .map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+
+        let mut storage = Self { db, len: 0, bytes: 0 };
+        let (len, bytes) = storage
+            .posts()
+            .fold((0, 0), |(len, bytes), post| (len + 1, bytes + post.estimated_bytes()));
+        storage.len = len;
+        storage.bytes = bytes;
+        Ok(storage)
+    }
+
+    fn cf(&self, name: &str) -> &ColumnFamily {
+        self.db
+            .cf_handle(name)
+            .expect("column families are created on open")
+    }
+
+    /// Add (or, with `delete`, remove) every entry of `post` to `batch`
+    fn write_entries(&self, batch: &mut WriteBatch, post: &ThunderCandidate, delete: bool) {
+        let (created_at, post_id) = (post.created_at, post.post_id);
+        let mut keys = vec![
+            (self.cf(TIMELINES), ordered_key(&id_bytes(post.author_id), created_at, post_id)),
+            (self.cf(BY_AGE), ordered_key(&[], created_at, post_id)),
+        ];
+        if let Some(parent_id) = post.reply_to_id {
+            keys.push((self.cf(REPLIES), ordered_key(&id_bytes(parent_id), created_at, post_id)));
+        }
+        for term in tokenize(&post.content) {
+            keys.push((self.cf(POSTINGS), ordered_key(&term_prefix(&term), created_at, post_id)));
+        }
+
+        if delete {
+            batch.delete_cf(self.cf(POSTS), id_bytes(post_id));
+            for (cf, key) in keys {
+                batch.delete_cf(cf, key);
+            }
+        } else {
+            match serde_json::to_vec(post) {
+                Ok(value) => batch.put_cf(self.cf(POSTS), id_bytes(post_id), value),
+                Err(e) => error!("Failed to serialize post {}: {}", post_id, e),
+            }
+            for (cf, key) in keys {
+                batch.put_cf(cf, key, b"");
+            }
+        }
+    }
+
+    fn write(&self, batch: WriteBatch) -> bool {
+        match self.db.write(batch) {
+            Ok(()) => true,
+            Err(e) => {
+                error!("Failed to write to post storage: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Keys of `cf` starting with `prefix`, walking from `from` in `direction`
+    fn keys_from<'a>(
+        &'a self,
+        cf: &str,
+        prefix: Vec<u8>,
+        from: &[u8],
+        direction: Direction,
+    ) -> impl Iterator<Item = Box<[u8]>> + 'a {
+        self.db
+            .iterator_cf(self.cf(cf), IteratorMode::From(from, direction))
+            .map_while(|item| match item {
+                Ok((key, _)) => Some(key),
+                Err(e) => {
+                    error!("Failed to read post storage: {}", e);
+                    None
+                }
+            })
+            .take_while(move |key| key.starts_with(&prefix))
+    }
+}
+
+impl PostStorage for RocksDbStorage {
+    fn insert(&mut self, post: ThunderCandidate) -> Option<ThunderCandidate> {
+        let replaced = self.get(post.post_id);
+        let mut batch = WriteBatch::default();
+        if let Some(old) = &replaced {
+            self.write_entries(&mut batch, old, true);
+        }
+        self.write_entries(&mut batch, &post, false);
+        if self.write(batch) {
+            if let Some(old) = &replaced {
+                self.len -= 1;
+                self.bytes -= old.estimated_bytes();
+            }
+            self.len += 1;
+            self.bytes += post.estimated_bytes();
+        }
+        replaced
+    }
+
+    fn update(&mut self, post_id: i64, change: &mut dyn FnMut(&mut ThunderCandidate)) -> bool {
+        let Some(old) = self.get(post_id) else {
+            return false;
+        };
+        let mut post = old.clone();
+        change(&mut post);
+        let mut batch = WriteBatch::default();
+        self.write_entries(&mut batch, &old, true);
+        self.write_entries(&mut batch, &post, false);
+        if !self.write(batch) {
+            return false;
+        }
+        self.bytes = self.bytes - old.estimated_bytes() + post.estimated_bytes();
+        true
+    }
+
+    fn remove(&mut self, post_id: i64) -> Option<ThunderCandidate> {
+        let post = self.get(post_id)?;
+        let mut batch = WriteBatch::default();
+        self.write_entries(&mut batch, &post, true);
+        if !self.write(batch) {
+            return None;
+        }
+        self.len -= 1;
+        self.bytes -= post.estimated_bytes();
+        Some(post)
+    }
+
+    fn get(&self, post_id: i64) -> Option<ThunderCandidate> {
+        match self.db.get_cf(self.cf(POSTS), id_bytes(post_id)) {
+            Ok(value) => serde_json::from_slice(&value?)
+                .map_err(|e| error!("Failed to deserialize post {}: {}", post_id, e))
+                .ok(),
+            Err(e) => {
+                error!("Failed to read post {}: {}", post_id, e);
+                None
+            }
+        }
+    }
+
+    fn contains(&self, post_id: i64) -> bool {
+        self.get(post_id).is_some()
+    }
+
+    fn oldest(&self) -> Option<ThunderCandidate> {
+        let key = self.keys_from(BY_AGE, Vec::new(), &[], Direction::Forward).next()?;
+        self.get(decode_post_key(&key).1)
+    }
+
+    fn replies_to(&self, post_id: i64) -> Vec<ThunderCandidate> {
+        let prefix = id_bytes(post_id).to_vec();
+        self.keys_from(REPLIES, prefix.clone(), &prefix, Direction::Forward)
+            .filter_map(|key| self.get(decode_post_key(&key).1))
+            .collect()
+    }
+
+    /// Walks the first term's postings, which is a hashtag whenever the query has
+    /// one since `#` sorts before letters, and checks the other terms per post
+    fn search(&self, query: &str, min_created_at: u64, limit: usize) -> Vec<ThunderCandidate> {
+        let terms = tokenize(query);
+        let Some(first) = terms.first() else {
+            return Vec::new();
+        };
+        let prefix = term_prefix(first);
+        let newest = ordered_key(&prefix, u64::MAX, i64::MAX);
+        self.keys_from(POSTINGS, prefix, &newest, Direction::Reverse)
+            .map(|key| decode_post_key(&key))
+            .take_while(|(created_at, _)| *created_at >= min_created_at)
+            .filter_map(|(_, post_id)| self.get(post_id))
+            .filter(|post| {
+                let tokens = tokenize(&post.content);
+                terms.iter().all(|term| tokens.binary_search(term).is_ok())
+            })
+            .take(limit)
+            .collect()
+    }
+
+    fn newest_by_authors(
+        &self,
+        author_ids: &[i64],
+        limit: usize,
+        max_per_author: Option<usize>,
+        cursor: Option<PostCursor>,
+    ) -> Vec<ThunderCandidate> {
+        let authors: HashSet<i64> = author_ids.iter().copied().collect();
+        let per_author = max_per_author.unwrap_or(usize::MAX).min(limit);
+        let mut keys: Vec<PostKey> = Vec::new();
+        for author_id in authors {
+            let prefix = id_bytes(author_id).to_vec();
+            let before = match cursor {
+                Some(cursor) => ordered_key(&prefix, cursor.created_at, cursor.post_id),
+                None => ordered_key(&prefix, u64::MAX, i64::MAX),
+            };
+            keys.extend(
+                self.keys_from(TIMELINES, prefix, &before, Direction::Reverse)
+                    .filter(|key| **key != *before)
+                    .map(|key| decode_post_key(&key))
+                    .take(per_author),
+            );
+        }
+
+        keys.sort_unstable_by(|a, b| b.cmp(a));
+        keys.truncate(limit);
+        keys.into_iter()
+            .filter_map(|(_, post_id)| self.get(post_id))
+            .collect()
+    }
+
+    fn posts(&self) -> Box<dyn Iterator<Item = ThunderCandidate> + '_> {
+        Box::new(
+            self.db
+                .iterator_cf(self.cf(POSTS), IteratorMode::Start)
+                .filter_map(|item| item.ok())
+                .filter_map(|(_, value)| serde_json::from_slice(&value).ok()),
+        )
+    }
+
+    fn posts_by_age(&self) -> Box<dyn Iterator<Item = ThunderCandidate> + '_> {
+        Box::new(
+            self.keys_from(BY_AGE, Vec::new(), &[], Direction::Forward)
+                .filter_map(|key| self.get(decode_post_key(&key).1)),
+        )
+    }
+
+    fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn watermark(&self) -> BTreeMap<i32, i64> {
+        self.db
+            .iterator_cf(self.cf(OFFSETS), IteratorMode::Start)
+            .filter_map(|item| item.ok())
+            .filter_map(|(key, value)| {
+                let partition = i32::from_be_bytes((*key).try_into().ok()?);
+                let offset = i64::from_be_bytes((*value).try_into().ok()?);
+                Some((partition, offset))
+            })
+            .collect()
+    }
+
+    fn set_watermark(&mut self, partition: i32, next_offset: i64) {
+        let cf = self.cf(OFFSETS);
+        if let Err(e) = self.db.put_cf(cf, partition.to_be_bytes(), next_offset.to_be_bytes()) {
+            error!("Failed to persist offset of partition {}: {}", partition, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_posts_and_offsets_survive_reopening() {
+        let dir = std::env::temp_dir().join(format!("thunder_rocksdb_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut storage = RocksDbStorage::open(&dir).unwrap();
+        storage.insert(ThunderCandidate::new(1, 100, "Hello #rust".into(), 1000));
+        storage.insert(ThunderCandidate::new(2, 100, "Hello world".into(), 1001));
+        storage.insert(ThunderCandidate::new(3, 200, "Rust again".into(), 1002));
+        storage.set_watermark(0, 3);
+        drop(storage);
+
+        let mut storage = RocksDbStorage::open(&dir).unwrap();
+        assert_eq!(storage.len(), 3);
+        assert_eq!(storage.watermark().get(&0), Some(&3));
+        let ids = |posts: Vec<ThunderCandidate>| -> Vec<i64> {
+            posts.iter().map(|p| p.post_id).collect()
+        };
+        assert_eq!(ids(storage.newest_by_authors(&[100, 200], 10, None, None)), vec![3, 2, 1]);
+        assert_eq!(ids(storage.search("rust", 0, 10)), vec![3, 1]);
+        assert_eq!(ids(storage.search("#rust hello", 0, 10)), vec![1]);
+
+        assert!(storage.remove(1).is_some());
+        assert_eq!(storage.oldest().unwrap().post_id, 2);
+        assert!(storage.search("#rust", 0, 10).is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
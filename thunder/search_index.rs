@@ -0,0 +1,117 @@
+//! Search Index for Thunder
+//!
+//! A hand-rolled inverted index over post content. Text is split into
+//! lowercase word tokens; a hashtag is indexed both as `#tag` and as the plain
+//! word, so `#rust` finds only hashtagged posts while `rust` finds both.
+//! Postings are kept in creation order, so a search walks the rarest term's
+//! postings newest first and stops at the age limit.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::candidate_source::ThunderCandidate;
+
+/// Creation time and post id, the order postings are kept in
+type PostingKey = (u64, i64);
+
+/// Tokens of `text`, deduplicated
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    for word in text.split(|c: char| !(c.is_alphanumeric() || c == '#' || c == '_')) {
+        let term = word.trim_start_matches('#').replace('#', "").to_lowercase();
+        if term.is_empty() {
+            continue;
+        }
+        if word.starts_with('#') {
+            tokens.push(format!("#{}", term));
+        }
+        tokens.push(term);
+    }
+    tokens.sort();
+    tokens.dedup();
+    tokens
+}
+
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    postings: HashMap<String, BTreeSet<PostingKey>>,
+}
+
+impl SearchIndex {
+    pub fn add(&mut self, post: &ThunderCandidate) {
+        let key = (post.created_at, post.post_id);
+        for token in tokenize(&post.content) {
+            self.postings.entry(token).or_default().insert(key);
+        }
+    }
+
+    pub fn remove(&mut self, post: &ThunderCandidate) {
+        let key = (post.created_at, post.post_id);
+        for token in tokenize(&post.content) {
+            if let Some(posts) = self.postings.get_mut(&token) {
+                posts.remove(&key);
+                if posts.is_empty() {
+                    self.postings.remove(&token);
+                }
+            }
+        }
+    }
+
+    /// Up to `limit` posts containing every term, created at or after
+    /// `min_created_at`, newest first
+    pub fn search(&self, terms: &[String], min_created_at: u64, limit: usize) -> Vec<PostingKey> {
+        let mut postings = Vec::with_capacity(terms.len());
+        for term in terms {
+            match self.postings.get(term) {
+                Some(posts) => postings.push(posts),
+                None => return Vec::new(),
+            }
+        }
+        postings.sort_by_key(|posts| posts.len());
+        let Some((rarest, rest)) = postings.split_first() else {
+            return Vec::new();
+        };
+
+        rarest
+            .iter()
+            .rev()
+            .take_while(|(created_at, _)| *created_at >= min_created_at)
+            .filter(|key| rest.iter().all(|posts| posts.contains(key)))
+            .take(limit)
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hashtags_and_words_match_newest_first() {
+        assert_eq!(tokenize("Learning #Rust, rust!"), vec!["#rust", "learning", "rust"]);
+
+        let mut index = SearchIndex::default();
+        let post = |post_id, content: &str, created_at| {
+            ThunderCandidate::new(post_id, 100, content.into(), created_at)
+        };
+        let posts = [
+            post(1, "Learning #Rust today", 1000),
+            post(2, "rust belt news", 1001),
+            post(3, "More #rust and #tokio", 1002),
+        ];
+        for p in &posts {
+            index.add(p);
+        }
+
+        let ids = |keys: Vec<PostingKey>| keys.into_iter().map(|(_, id)| id).collect::<Vec<_>>();
+        assert_eq!(ids(index.search(&tokenize("rust"), 0, 10)), vec![3, 2, 1]);
+        assert_eq!(ids(index.search(&tokenize("#rust"), 0, 10)), vec![3, 1]);
+        assert_eq!(ids(index.search(&tokenize("#rust tokio"), 0, 10)), vec![3]);
+        assert_eq!(ids(index.search(&tokenize("rust"), 1001, 10)), vec![3, 2]);
+        assert!(index.search(&tokenize("python"), 0, 10).is_empty());
+
+        index.remove(&posts[2]);
+        assert_eq!(ids(index.search(&tokenize("#rust"), 0, 10)), vec![1]);
+        assert!(index.search(&tokenize("tokio"), 0, 10).is_empty());
+    }
+}
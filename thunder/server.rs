@@ -0,0 +1,562 @@
+//! Thunder gRPC Server Implementation
+//!
+//! The query RPCs are served by methods returning `ThunderError`, which the
+//! JSON gateway in `gateway` calls as well, so both validate requests alike.
+
+use std::collections::HashSet;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::join_all;
+use futures::{stream, Stream, StreamExt};
+use log::{info, warn};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::watch;
+use tonic::{Request, Response, Status};
+
+use crate::candidate_source::SyncSource;
+use crate::config::ThunderConfig;
+use crate::conversation::ConversationLimits;
+use crate::error::ThunderError;
+use crate::ingest::Ingestor;
+use crate::ingest_source::CatchUpStatus;
+use crate::over_fetch::PassRateTracker;
+use crate::proto;
+use crate::proto::replication_message::Message;
+use crate::realtime_query::{execute_query, RealtimeQuery};
+use crate::replication::now_millis;
+use crate::request_limiter::RequestLimiter;
+use crate::search_index::tokenize;
+use crate::snapshot::now_seconds;
+
+/// Queries handled at once and per-query timeout unless configured otherwise
+const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 1000;
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_millis(5000);
+/// Posts returned per page when the request doesn't set a limit, and at most
+const DEFAULT_QUERY_LIMIT: usize = 100;
+const MAX_QUERY_LIMIT: usize = 1000;
+/// Age limit of queried posts when the request doesn't set one (7 days)
+const DEFAULT_QUERY_MAX_AGE_SECONDS: u64 = 7 * 24 * 60 * 60;
+
+/// Results returned by a search when the request doesn't set a limit, and at most
+const DEFAULT_SEARCH_LIMIT: usize = 50;
+const MAX_SEARCH_LIMIT: usize = 500;
+/// Most queries in one `GetPostsBatch` request
+const MAX_BATCH_QUERIES: usize = 100;
+/// Age limit of searched posts when the request doesn't set one (7 days)
+const DEFAULT_SEARCH_MAX_AGE_SECONDS: u64 = 7 * 24 * 60 * 60;
+
+/// Interval between heartbeats on a replication stream
+const REPLICATION_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+pub type PostStream =
+    Pin<Box<dyn Stream<Item = Result<proto::ThunderCandidate, Status>> + Send>>;
+pub type ReplicationStream =
+    Pin<Box<dyn Stream<Item = Result<proto::ReplicationMessage, Status>> + Send>>;
+
+#[derive(Clone)]
+pub struct ThunderServer {
+    ingestor: Arc<Ingestor>,
+    limiter: Arc<RequestLimiter>,
+    config: ThunderConfig,
+    pass_rates: Arc<PassRateTracker>,
+    /// Consumer progress; queries are refused until it has caught up
+    catch_up: Option<watch::Receiver<CatchUpStatus>>,
+}
+
+impl ThunderServer {
+    pub fn new(ingestor: Arc<Ingestor>) -> Self {
+        let limiter = RequestLimiter::new(DEFAULT_MAX_CONCURRENT_REQUESTS, DEFAULT_REQUEST_TIMEOUT);
+        Self {
+            ingestor,
+            limiter: Arc::new(limiter),
+            config: ThunderConfig::default(),
+            pass_rates: Arc::new(PassRateTracker::new(Default::default())),
+            catch_up: None,
+        }
+    }
+
+    /// Run queries with `config`, e.g. to pre-rank their results
+    pub fn with_config(mut self, config: ThunderConfig) -> Self {
+        self.pass_rates = Arc::new(PassRateTracker::new(config.over_fetch));
+        self.config = config;
+        self
+    }
+
+    /// Gate queries through `limiter` instead of the default limits
+    pub fn with_limiter(mut self, limiter: Arc<RequestLimiter>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    /// Refuse queries with `StoreUninitialized` until the consumer behind
+    /// `status` has caught up with the post stream
+    pub fn with_catch_up(mut self, status: watch::Receiver<CatchUpStatus>) -> Self {
+        self.catch_up = Some(status);
+        self
+    }
+
+    fn check_ready(&self) -> Result<(), ThunderError> {
+        match &self.catch_up {
+            Some(status) if !status.borrow().caught_up => Err(ThunderError::StoreUninitialized),
+            _ => Ok(()),
+        }
+    }
+
+    /// Run one in-network query under the limiter, logging within a span naming
+    /// the user it is for
+    #[tracing::instrument(skip_all, fields(user_id = request.user_id))]
+    pub async fn in_network_posts(
+        &self,
+        request: proto::GetInNetworkPostsRequest,
+    ) -> Result<proto::GetInNetworkPostsResponse, ThunderError> {
+        if request.following_ids.is_empty() {
+            return Err(ThunderError::FollowingListUnavailable);
+        }
+        self.check_ready()?;
+        let limit = match request.limit {
+            0 => DEFAULT_QUERY_LIMIT,
+            limit => (limit as usize).min(MAX_QUERY_LIMIT),
+        };
+        let max_age = match request.max_age_seconds {
+            0 => DEFAULT_QUERY_MAX_AGE_SECONDS,
+            max_age => max_age,
+        };
+        let mut query = RealtimeQuery::new(request.user_id, request.following_ids)
+            .with_fetch_factor(self.pass_rates.factor(request.user_id))
+            .with_limit(limit)
+            .with_max_age(max_age)
+            .exclude(request.exclude_post_ids)
+            .exclude_seen(request.bloom_filter_entries);
+        if request.max_per_author > 0 {
+            query = query.with_max_per_author(request.max_per_author as usize);
+        }
+        if let Some(cursor) = request.cursor {
+            query = query.after(cursor.into());
+        }
+
+        let source = SyncSource(self.ingestor.store().clone());
+        let config = &self.config;
+        let response = self
+            .limiter
+            .run(|deadline| async move {
+                let query = query.with_deadline(deadline);
+                execute_query(&source, &query, config).await
+            })
+            .await?;
+        if response.partial {
+            self.limiter.record_partial();
+        }
+        self.pass_rates
+            .record(request.user_id, response.fetched, response.total_available);
+        let mut posts = response.candidates;
+        if let Some(trending) = self.ingestor.trending() {
+            trending.observe_served(&mut posts, now_seconds());
+        }
+        Ok(proto::GetInNetworkPostsResponse {
+            posts: posts.into_iter().map(Into::into).collect(),
+            next_cursor: response.next_cursor.map(Into::into),
+            partial: response.partial,
+        })
+    }
+
+    /// Run each query of a batch on its own, failing only the malformed batch
+    /// as a whole
+    pub async fn posts_batch(
+        &self,
+        request: proto::GetPostsBatchRequest,
+    ) -> Result<proto::GetPostsBatchResponse, ThunderError> {
+        let queries = request.queries;
+        if queries.is_empty() || queries.len() > MAX_BATCH_QUERIES {
+            let reason = format!("a batch must hold between 1 and {} queries", MAX_BATCH_QUERIES);
+            return Err(ThunderError::InvalidArgument(reason));
+        }
+        // Queries run as tasks of their own, each under its own permit, so a
+        // saturated service sheds a batch query by query rather than as a whole
+        let tasks = queries.into_iter().map(|query| {
+            let server = self.clone();
+            let user_id = query.user_id;
+            let task = tokio::spawn(async move { server.in_network_posts(query).await });
+            async move {
+                match task.await {
+                    Ok(result) => batch_result(user_id, result),
+                    Err(e) => batch_result(user_id, Err(ThunderError::Internal(e.to_string()))),
+                }
+            }
+        });
+        let results = join_all(tasks).await;
+        Ok(proto::GetPostsBatchResponse { results })
+    }
+
+    /// The thread around one post
+    pub async fn conversation(
+        &self,
+        request: proto::GetConversationRequest,
+    ) -> Result<proto::Conversation, ThunderError> {
+        let limits = ConversationLimits::from_request(request.max_depth, request.max_replies);
+        self.check_ready()?;
+        let store = self.ingestor.store();
+        let conversation = self
+            .limiter
+            .run(|_| async { Ok(store.get_conversation(request.post_id, limits)) })
+            .await?;
+        match conversation {
+            Some(conversation) => Ok(conversation.into()),
+            None => Err(ThunderError::PostNotFound(request.post_id)),
+        }
+    }
+
+    /// Recent posts matching a full-text query
+    pub async fn search(
+        &self,
+        request: proto::SearchPostsRequest,
+    ) -> Result<proto::SearchPostsResponse, ThunderError> {
+        if tokenize(&request.query).is_empty() {
+            let reason = "query must contain a word or hashtag".to_string();
+            return Err(ThunderError::InvalidArgument(reason));
+        }
+        self.check_ready()?;
+        let limit = match request.limit {
+            0 => DEFAULT_SEARCH_LIMIT,
+            limit => (limit as usize).min(MAX_SEARCH_LIMIT),
+        };
+        let max_age = match request.max_age_seconds {
+            0 => DEFAULT_SEARCH_MAX_AGE_SECONDS,
+            max_age => max_age,
+        };
+
+        let min_created_at = now_seconds().saturating_sub(max_age);
+        let store = self.ingestor.store();
+        let posts = self
+            .limiter
+            .run(|_| async { Ok(store.search(&request.query, min_created_at, limit)) })
+            .await?;
+        Ok(proto::SearchPostsResponse {
+            posts: posts.into_iter().map(Into::into).collect(),
+        })
+    }
+}
+
+/// The entry of a `GetPostsBatch` response for the query of `user_id`
+fn batch_result(
+    user_id: i64,
+    result: Result<proto::GetInNetworkPostsResponse, ThunderError>,
+) -> proto::UserPostsResult {
+    match result {
+        Ok(response) => proto::UserPostsResult {
+            user_id,
+            posts: response.posts,
+            next_cursor: response.next_cursor,
+            partial: response.partial,
+            error: None,
+        },
+        Err(error) => proto::UserPostsResult {
+            user_id,
+            error: Some(proto::QueryError {
+                code: error.grpc_code() as i32,
+                kind: error.kind().to_string(),
+                retryable: error.is_retryable(),
+                message: error.to_string(),
+            }),
+            ..Default::default()
+        },
+    }
+}
+
+fn replication_message(message: Message) -> proto::ReplicationMessage {
+    proto::ReplicationMessage {
+        message: Some(message),
+    }
+}
+
+#[tonic::async_trait]
+impl proto::thunder_service_server::ThunderService for ThunderServer {
+    type SubscribePostsStream = PostStream;
+    type ReplicateStream = ReplicationStream;
+
+    async fn get_in_network_posts(
+        &self,
+        request: Request<proto::GetInNetworkPostsRequest>,
+    ) -> Result<Response<proto::GetInNetworkPostsResponse>, Status> {
+        let response = self.in_network_posts(request.into_inner()).await?;
+        Ok(Response::new(response))
+    }
+
+    async fn get_posts_batch(
+        &self,
+        request: Request<proto::GetPostsBatchRequest>,
+    ) -> Result<Response<proto::GetPostsBatchResponse>, Status> {
+        let response = self.posts_batch(request.into_inner()).await?;
+        Ok(Response::new(response))
+    }
+
+    async fn subscribe_posts(
+        &self,
+        request: Request<proto::SubscribePostsRequest>,
+    ) -> Result<Response<PostStream>, Status> {
+        let following: HashSet<i64> = request.into_inner().following_ids.into_iter().collect();
+        if following.is_empty() {
+            return Err(ThunderError::FollowingListUnavailable.into());
+        }
+
+        let receiver = self.ingestor.subscribe();
+        let posts = stream::unfold(receiver, move |mut receiver| {
+            let following = following.clone();
+            async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(post) if following.contains(&post.author_id) => {
+                            return Some((Ok((*post).clone().into()), receiver));
+                        }
+                        Ok(_) => {}
+                        // A slow subscriber skips what it missed rather than blocking ingestion
+                        Err(RecvError::Lagged(missed)) => {
+                            warn!("Post subscriber fell behind, skipped {} posts", missed);
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(posts)))
+    }
+
+    async fn get_conversation(
+        &self,
+        request: Request<proto::GetConversationRequest>,
+    ) -> Result<Response<proto::Conversation>, Status> {
+        let response = self.conversation(request.into_inner()).await?;
+        Ok(Response::new(response))
+    }
+
+    async fn search_posts(
+        &self,
+        request: Request<proto::SearchPostsRequest>,
+    ) -> Result<Response<proto::SearchPostsResponse>, Status> {
+        let response = self.search(request.into_inner()).await?;
+        Ok(Response::new(response))
+    }
+
+    async fn replicate(
+        &self,
+        request: Request<proto::ReplicateRequest>,
+    ) -> Result<Response<ReplicationStream>, Status> {
+        let follower = request.into_inner().follower;
+        let ingestor = self.ingestor.clone();
+        let (snapshot, changes) =
+            tokio::task::spawn_blocking(move || ingestor.replicate(now_seconds()))
+                .await
+                .map_err(|e| ThunderError::Internal(e.to_string()))?;
+        info!("Replicating {} posts to {}", snapshot.posts.len(), follower);
+
+        let first = replication_message(Message::Snapshot(snapshot.into()));
+        let heartbeats = tokio::time::interval(REPLICATION_HEARTBEAT_INTERVAL);
+        let state = Some((changes, heartbeats, self.ingestor.clone()));
+        let rest = stream::unfold(state, move |state| {
+            let follower = follower.clone();
+            async move {
+                let (mut changes, mut heartbeats, ingestor) = state?;
+                let message = tokio::select! {
+                    change = changes.recv() => match change {
+                        Ok(change) => replication_message(Message::Change(change.into())),
+                        // Skipping changes would leave the follower diverged for good,
+                        // so end the stream and let it resync from a new snapshot
+                        Err(RecvError::Lagged(missed)) => {
+                            warn!("Replication follower {} missed {} changes", follower, missed);
+                            let error = ThunderError::ReplicationLagged { missed };
+                            return Some((Err(error.into()), None));
+                        }
+                        Err(RecvError::Closed) => return None,
+                    },
+                    _ = heartbeats.tick() => replication_message(Message::Heartbeat(
+                        proto::Heartbeat {
+                            offsets: ingestor.store().resume_offsets(),
+                            sent_at_ms: now_millis(),
+                        },
+                    )),
+                };
+                Some((Ok(message), Some((changes, heartbeats, ingestor))))
+            }
+        });
+        Ok(Response::new(Box::pin(stream::once(async { Ok(first) }).chain(rest))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::candidate_source::ThunderCandidate;
+    use crate::error::error_kind;
+    use crate::proto::thunder_service_server::ThunderService;
+    use crate::sharded_store::ShardedPostStore;
+    use crate::wal::{PostEvent, WalRecord};
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_subscribers_only_see_followed_authors() {
+        let ingestor = Arc::new(Ingestor::new(Arc::new(ShardedPostStore::new(2)), None));
+        let server = ThunderServer::new(ingestor.clone());
+        let request = Request::new(proto::SubscribePostsRequest {
+            following_ids: vec![100],
+        });
+        let mut posts = server.subscribe_posts(request).await.unwrap().into_inner();
+
+        for (offset, author_id) in [(0, 200), (1, 100), (2, 300), (3, 100)] {
+            let post = ThunderCandidate::new(offset, author_id, "Post".into(), 1000);
+            let event = PostEvent::Upsert { post };
+            ingestor.ingest(WalRecord { partition: 0, offset, event }).unwrap();
+        }
+
+        assert_eq!(posts.next().await.unwrap().unwrap().post_id, 1);
+        assert_eq!(posts.next().await.unwrap().unwrap().post_id, 3);
+
+        let empty = Request::new(proto::SubscribePostsRequest::default());
+        assert!(server.subscribe_posts(empty).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_in_network_posts_settle_for_partial_results() {
+        let ingestor = Arc::new(Ingestor::new(Arc::new(ShardedPostStore::new(2)), None));
+        let now = now_seconds();
+        for (offset, author_id) in [(0, 100), (1, 101), (2, 100)] {
+            let post = ThunderCandidate::new(offset, author_id, "Post".into(), now - 10);
+            let event = PostEvent::Upsert { post };
+            ingestor.ingest(WalRecord { partition: 0, offset, event }).unwrap();
+        }
+        let request = || {
+            Request::new(proto::GetInNetworkPostsRequest {
+                user_id: 1,
+                following_ids: vec![100, 101],
+                exclude_post_ids: vec![2],
+                ..Default::default()
+            })
+        };
+
+        let server = ThunderServer::new(ingestor.clone());
+        let page = server.get_in_network_posts(request()).await.unwrap().into_inner();
+        let ids: Vec<i64> = page.posts.iter().map(|p| p.post_id).collect();
+        assert_eq!(ids, vec![1, 0]);
+        assert!(!page.partial);
+
+        // With no time at all, every shard is skipped
+        let limiter = Arc::new(RequestLimiter::new(10, Duration::ZERO));
+        let server = ThunderServer::new(ingestor).with_limiter(limiter.clone());
+        let page = server.get_in_network_posts(request()).await.unwrap().into_inner();
+        assert!(page.partial && page.posts.is_empty());
+        assert_eq!(limiter.stats().partial, 1);
+    }
+
+    #[tokio::test]
+    async fn test_batch_queries_fail_independently() {
+        let ingestor = Arc::new(Ingestor::new(Arc::new(ShardedPostStore::new(2)), None));
+        let now = now_seconds();
+        for (offset, author_id) in [(0, 100), (1, 101), (2, 102)] {
+            let post = ThunderCandidate::new(offset, author_id, "Post".into(), now - 10);
+            let event = PostEvent::Upsert { post };
+            ingestor.ingest(WalRecord { partition: 0, offset, event }).unwrap();
+        }
+        let server = ThunderServer::new(ingestor);
+        let query = |user_id, following_ids: Vec<i64>| proto::GetInNetworkPostsRequest {
+            user_id,
+            following_ids,
+            ..Default::default()
+        };
+        let request = Request::new(proto::GetPostsBatchRequest {
+            queries: vec![query(1, vec![100, 101]), query(2, vec![]), query(3, vec![102])],
+        });
+
+        let results = server.get_posts_batch(request).await.unwrap().into_inner().results;
+        let ids = |result: &proto::UserPostsResult| -> Vec<i64> {
+            result.posts.iter().map(|p| p.post_id).collect()
+        };
+        assert_eq!((results[0].user_id, ids(&results[0])), (1, vec![1, 0]));
+        let error = results[1].error.as_ref().unwrap();
+        assert_eq!(error.code, tonic::Code::FailedPrecondition as i32);
+        assert_eq!((error.kind.as_str(), error.retryable), ("following_list_unavailable", false));
+        assert_eq!((results[2].user_id, ids(&results[2])), (3, vec![2]));
+        assert!(results[2].error.is_none());
+
+        let empty = Request::new(proto::GetPostsBatchRequest::default());
+        assert!(server.get_posts_batch(empty).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_queries_are_refused_until_caught_up() {
+        let ingestor = Arc::new(Ingestor::new(Arc::new(ShardedPostStore::new(2)), None));
+        let (progress, status) = watch::channel(CatchUpStatus::default());
+        let server = ThunderServer::new(ingestor).with_catch_up(status);
+        let request = || {
+            Request::new(proto::GetInNetworkPostsRequest {
+                user_id: 1,
+                following_ids: vec![100],
+                ..Default::default()
+            })
+        };
+
+        let refused = server.get_in_network_posts(request()).await.unwrap_err();
+        assert_eq!(refused.code(), tonic::Code::Unavailable);
+        assert_eq!(error_kind(&refused), Some("store_uninitialized"));
+
+        progress.send_modify(|status| status.caught_up = true);
+        assert!(server.get_in_network_posts(request()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_conversation_spans_shards() {
+        let ingestor = Arc::new(Ingestor::new(Arc::new(ShardedPostStore::new(4)), None));
+        let server = ThunderServer::new(ingestor.clone());
+        let thread = [(1, 100, None), (2, 101, Some(1)), (3, 102, Some(2))];
+        for (offset, author_id, reply_to_id) in thread {
+            let mut post = ThunderCandidate::new(offset, author_id, "Post".into(), 1000);
+            post.reply_to_id = reply_to_id;
+            let event = PostEvent::Upsert { post };
+            ingestor.ingest(WalRecord { partition: 0, offset, event }).unwrap();
+        }
+
+        let request = |post_id| {
+            Request::new(proto::GetConversationRequest {
+                post_id,
+                ..Default::default()
+            })
+        };
+        let thread = server.get_conversation(request(2)).await.unwrap().into_inner();
+        assert_eq!(thread.ancestors[0].post_id, 1);
+        assert_eq!(thread.replies[0].post.as_ref().unwrap().post_id, 3);
+
+        let missing = server.get_conversation(request(9)).await.unwrap_err();
+        assert_eq!(missing.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_search_across_shards_within_max_age() {
+        let ingestor = Arc::new(Ingestor::new(Arc::new(ShardedPostStore::new(4)), None));
+        let server = ThunderServer::new(ingestor.clone());
+        let now = now_seconds();
+        let posts = [
+            (1, 100, "Shipping #rust 1.80", now - 10),
+            (2, 101, "rust is fun", now - 20),
+            (3, 102, "old #rust news", now - 10_000),
+            (4, 103, "nothing to see", now - 5),
+        ];
+        for (offset, author_id, content, created_at) in posts {
+            let post = ThunderCandidate::new(offset, author_id, content.into(), created_at);
+            let event = PostEvent::Upsert { post };
+            ingestor.ingest(WalRecord { partition: 0, offset, event }).unwrap();
+        }
+
+        let search = |query: &str| {
+            Request::new(proto::SearchPostsRequest {
+                query: query.into(),
+                limit: 0,
+                max_age_seconds: 3600,
+            })
+        };
+        let found = server.search_posts(search("Rust")).await.unwrap().into_inner();
+        let ids: Vec<i64> = found.posts.iter().map(|p| p.post_id).collect();
+        assert_eq!(ids, vec![1, 2]);
+
+        let invalid = server.search_posts(search("!!")).await.unwrap_err();
+        assert_eq!(invalid.code(), tonic::Code::InvalidArgument);
+    }
+}
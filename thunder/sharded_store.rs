@@ -0,0 +1,814 @@
+//! Sharded Post Store for Thunder
+//!
+//! Splits the post store into shards by author id, each behind its own lock, so
+//! consumer threads writing different authors' posts don't contend and queries
+//! only lock the shards holding followed authors. A query fans out to those
+//! shards and merges their newest posts. The shards are snapshotted together as
+//! one `PostStoreSnapshot`, so the shard count can change between restarts.
+//! Disk-backed shards keep their posts across restarts instead, so their
+//! shard count is fixed once the storage directory is created.
+//!
+//! An index from post id to shard sends edits, deletes and engagement updates,
+//! which only carry the post id, straight to their shard. The memory budget
+//! covers all shards together: a running byte count is kept in step with every
+//! write, and once it passes the budget the oldest posts across all shards are
+//! evicted first.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use log::info;
+use serde::Serialize;
+
+use crate::candidate_source::{CandidateSource, EngagementDelta, PostCursor, ThunderCandidate};
+use crate::conversation::{build_conversation, Conversation, ConversationLimits};
+use crate::post_store::{EvictionCounts, PostEventCounts, PostStore};
+use crate::retention::RetentionPolicy;
+use crate::snapshot::{PostStoreSnapshot, SNAPSHOT_VERSION};
+use crate::storage::{open_storage, StorageBackend};
+use crate::text_compression::{CompressionStats, TextCodec};
+use crate::wal::{PostEvent, WalRecord};
+
+/// Locks the post index is split over, by post id, so writers rarely share one
+const INDEX_STRIPES: usize = 64;
+
+/// Size and latency of one shard, for the stats logger
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ShardStats {
+    pub shard: usize,
+    pub posts: usize,
+    pub bytes: usize,
+    pub queries: u64,
+    pub mean_query_micros: f64,
+    pub writes: u64,
+    pub mean_write_micros: f64,
+}
+
+/// Trimming of expired posts since startup, for the stats logger
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct TrimStats {
+    pub trims: u64,
+    pub mean_micros: f64,
+    /// Posts dropped and time taken by the last trim
+    pub last_trimmed: u64,
+    pub last_micros: u64,
+}
+
+#[derive(Default)]
+struct Latency {
+    count: AtomicU64,
+    total_micros: AtomicU64,
+}
+
+impl Latency {
+    fn record(&self, start: Instant) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_micros
+            .fetch_add(start.elapsed().as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    fn mean_micros(&self) -> f64 {
+        match self.count() {
+            0 => 0.0,
+            count => self.total_micros.load(Ordering::Relaxed) as f64 / count as f64,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Shard {
+    store: RwLock<PostStore>,
+    queries: Latency,
+    writes: Latency,
+}
+
+/// Shard holding each post
+struct PostIndex {
+    stripes: Vec<RwLock<HashMap<i64, usize>>>,
+}
+
+impl PostIndex {
+    fn new() -> Self {
+        Self {
+            stripes: (0..INDEX_STRIPES).map(|_| RwLock::default()).collect(),
+        }
+    }
+
+    fn stripe(&self, post_id: i64) -> &RwLock<HashMap<i64, usize>> {
+        &self.stripes[post_id.rem_euclid(INDEX_STRIPES as i64) as usize]
+    }
+
+    fn get(&self, post_id: i64) -> Option<usize> {
+        self.stripe(post_id).read().unwrap().get(&post_id).copied()
+    }
+
+    fn insert(&self, post_id: i64, shard: usize) {
+        self.stripe(post_id).write().unwrap().insert(post_id, shard);
+    }
+
+    fn remove(&self, post_id: i64) {
+        self.stripe(post_id).write().unwrap().remove(&post_id);
+    }
+
+    fn clear(&self) {
+        for stripe in &self.stripes {
+            stripe.write().unwrap().clear();
+        }
+    }
+}
+
+/// Post store split into shards by author id
+pub struct ShardedPostStore {
+    shards: Vec<Shard>,
+    index: PostIndex,
+    /// Estimated bytes held by the posts of all shards
+    bytes: AtomicU64,
+    /// Estimated bytes all shards together may hold; unlimited when unset
+    max_bytes: Option<u64>,
+    /// Held while evicting down to `max_bytes`, so writers don't evict twice
+    evicting: Mutex<()>,
+    trims: Latency,
+    last_trimmed: AtomicU64,
+    last_trim_micros: AtomicU64,
+}
+
+impl ShardedPostStore {
+    pub fn new(shards: usize) -> Self {
+        Self::with_shards((0..shards.max(1)).map(|_| Shard::default()).collect())
+    }
+
+    fn with_shards(shards: Vec<Shard>) -> Self {
+        let store = Self {
+            shards,
+            index: PostIndex::new(),
+            bytes: AtomicU64::new(0),
+            max_bytes: None,
+            evicting: Mutex::new(()),
+            trims: Latency::default(),
+            last_trimmed: AtomicU64::new(0),
+            last_trim_micros: AtomicU64::new(0),
+        };
+        store.reindex_all();
+        store
+    }
+
+    /// Open a store with `shards` shards on `backend`; disk backends keep the
+    /// shards under `dir` and resume from the posts and offsets stored there
+    pub fn open(
+        backend: StorageBackend,
+        dir: Option<&Path>,
+        shards: usize,
+    ) -> Result<Self, String> {
+        let shards = shards.max(1);
+        if let Some(dir) = dir.filter(|_| backend != StorageBackend::Memory) {
+            let existing = existing_shards(dir)?;
+            if existing != 0 && existing != shards {
+                return Err(format!(
+                    "{} holds {} shards but {} were requested",
+                    dir.display(),
+                    existing,
+                    shards
+                ));
+            }
+        }
+        let shards = (0..shards)
+            .map(|shard| {
+                let storage = open_storage(backend, dir, shard)?;
+                Ok(Shard {
+                    store: RwLock::new(PostStore::with_storage(storage)),
+                    ..Shard::default()
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self::with_shards(shards))
+    }
+
+    /// Rebuild an in-memory store from a snapshot, spreading its posts over `shards` shards
+    pub fn from_snapshot(snapshot: PostStoreSnapshot, shards: usize) -> Self {
+        let store = Self::new(shards);
+        let mut posts: Vec<Vec<ThunderCandidate>> = vec![Vec::new(); store.shards.len()];
+        for post in snapshot.posts {
+            posts[store.shard_for_author(post.author_id)].push(post);
+        }
+        // Every shard has seen the stream up to the snapshot's watermark
+        for (shard, posts) in store.shards.iter().zip(posts) {
+            *shard.store.write().unwrap() = PostStore::from_snapshot(PostStoreSnapshot {
+                version: snapshot.version,
+                created_at: snapshot.created_at,
+                watermark: snapshot.watermark.clone(),
+                posts,
+            });
+        }
+        store.reindex_all();
+        store
+    }
+
+    /// Replace the posts and offsets of every shard with those of `snapshot`.
+    /// Every shard is write-locked first, so queries see either store whole.
+    pub fn restore(&self, snapshot: PostStoreSnapshot) {
+        let mut shards: Vec<_> = self
+            .shards
+            .iter()
+            .map(|shard| shard.store.write().unwrap())
+            .collect();
+        let mut posts: Vec<Vec<ThunderCandidate>> = vec![Vec::new(); shards.len()];
+        for post in snapshot.posts {
+            posts[self.shard_for_author(post.author_id)].push(post);
+        }
+        for (shard, posts) in shards.iter_mut().zip(posts) {
+            shard.restore(PostStoreSnapshot {
+                version: snapshot.version,
+                created_at: snapshot.created_at,
+                watermark: snapshot.watermark.clone(),
+                posts,
+            });
+        }
+        self.reindex(shards.iter().map(|shard| &**shard));
+        drop(shards);
+        self.enforce_budget();
+    }
+
+    /// Cap the estimated memory held by all shards together at `max_bytes`,
+    /// evicting the oldest posts across shards if the store is already over
+    pub fn with_max_bytes(mut self, max_bytes: Option<u64>) -> Self {
+        self.max_bytes = max_bytes;
+        self.enforce_budget();
+        self
+    }
+
+    /// Hold post content compressed with `codec` in every shard
+    pub fn with_text_codec(self, codec: Arc<dyn TextCodec>) -> Result<Self, String> {
+        for shard in 0..self.shards.len() {
+            self.write_shard(shard, |store| store.set_text_codec(codec.clone()))?;
+        }
+        // The compressed form of short posts may be larger
+        self.enforce_budget();
+        Ok(self)
+    }
+
+    /// Rebuild the post index and byte count from every shard
+    fn reindex_all(&self) {
+        let stores: Vec<_> = self
+            .shards
+            .iter()
+            .map(|shard| shard.store.read().unwrap())
+            .collect();
+        self.reindex(stores.iter().map(|store| &**store));
+    }
+
+    /// Rebuild the post index and byte count from `stores`, the shards in order
+    fn reindex<'a>(&self, stores: impl Iterator<Item = &'a PostStore>) {
+        self.index.clear();
+        let mut bytes = 0;
+        for (shard, store) in stores.enumerate() {
+            for post_id in store.post_ids() {
+                self.index.insert(post_id, shard);
+            }
+            bytes += store.bytes();
+        }
+        self.bytes.store(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Run `write` on a shard under its lock, keeping the byte count in step
+    fn write_shard<T>(&self, shard: usize, write: impl FnOnce(&mut PostStore) -> T) -> T {
+        let mut store = self.shards[shard].store.write().unwrap();
+        let before = store.bytes() as u64;
+        let result = write(&mut store);
+        let after = store.bytes() as u64;
+        if after >= before {
+            self.bytes.fetch_add(after - before, Ordering::Relaxed);
+        } else {
+            self.bytes.fetch_sub(before - after, Ordering::Relaxed);
+        }
+        result
+    }
+
+    /// Evict the oldest posts across all shards until the store is within its
+    /// budget; returns how many were evicted
+    fn enforce_budget(&self) -> usize {
+        let Some(max_bytes) = self.max_bytes else {
+            return 0;
+        };
+        if self.bytes.load(Ordering::Relaxed) <= max_bytes {
+            return 0;
+        }
+        let _evicting = self.evicting.lock().unwrap();
+        let mut evicted = 0;
+        while self.bytes.load(Ordering::Relaxed) > max_bytes {
+            let oldest = self
+                .shards
+                .iter()
+                .enumerate()
+                .filter_map(|(i, shard)| {
+                    let oldest = shard.store.read().unwrap().oldest()?;
+                    Some(((oldest.created_at, oldest.post_id), i))
+                })
+                .min();
+            let Some((_, shard)) = oldest else {
+                break;
+            };
+            if let Some(post_id) = self.write_shard(shard, |store| store.evict_oldest()) {
+                self.index.remove(post_id);
+                evicted += 1;
+            }
+        }
+        evicted
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    fn shard_for_author(&self, author_id: i64) -> usize {
+        author_id.rem_euclid(self.shards.len() as i64) as usize
+    }
+
+    /// Shard holding `post_id`; edits, deletes and engagement only carry the post id
+    fn shard_holding(&self, post_id: i64) -> Option<usize> {
+        self.index.get(post_id)
+    }
+
+    /// Apply a logged event to the shard it belongs to; returns whether it was
+    /// applied. Events for posts no shard holds still advance a shard's watermark.
+    pub fn apply_record(&self, record: WalRecord) -> bool {
+        let (shard, post_id) = match &record.event {
+            PostEvent::Upsert { post } => (self.shard_for_author(post.author_id), post.post_id),
+            PostEvent::Edit { post_id, .. } | PostEvent::Delete { post_id } => (
+                self.shard_holding(*post_id)
+                    .unwrap_or_else(|| post_id.rem_euclid(self.shards.len() as i64) as usize),
+                *post_id,
+            ),
+        };
+        let start = Instant::now();
+        // The index changes under the shard's lock, in the order the shard does
+        let applied = self.write_shard(shard, |store| {
+            let applied = store.apply_record(record);
+            if store.contains(post_id) {
+                self.index.insert(post_id, shard);
+            } else {
+                self.index.remove(post_id);
+            }
+            applied
+        });
+        self.shards[shard].writes.record(start);
+        self.enforce_budget();
+        applied
+    }
+
+    /// Add engagement to a post in place; returns false if no shard holds it
+    pub fn update_engagement(&self, post_id: i64, delta: &EngagementDelta, now: u64) -> bool {
+        let Some(shard) = self.shard_holding(post_id) else {
+            return false;
+        };
+        let start = Instant::now();
+        let updated = self.write_shard(shard, |store| store.update_engagement(post_id, delta, now));
+        self.shards[shard].writes.record(start);
+        updated
+    }
+
+    /// `root_id` with its ancestors and reply tree, gathered across shards since
+    /// replies usually come from other authors
+    pub fn get_conversation(
+        &self,
+        root_id: i64,
+        limits: ConversationLimits,
+    ) -> Option<Conversation> {
+        build_conversation(
+            root_id,
+            limits,
+            |post_id| {
+                let shard = self.shard_holding(post_id)?;
+                self.shards[shard].store.read().unwrap().get(post_id)
+            },
+            |post_id| {
+                let mut replies: Vec<ThunderCandidate> = self
+                    .shards
+                    .iter()
+                    .flat_map(|shard| shard.store.read().unwrap().replies_to(post_id))
+                    .collect();
+                replies.sort_by_key(|reply| (reply.created_at, reply.post_id));
+                replies
+            },
+        )
+    }
+
+    /// Up to `limit` posts matching every token of `query`, created at or after
+    /// `min_created_at`, newest first across all shards
+    pub fn search(&self, query: &str, min_created_at: u64, limit: usize) -> Vec<ThunderCandidate> {
+        let mut posts: Vec<ThunderCandidate> = self
+            .shards
+            .iter()
+            .flat_map(|shard| shard.store.read().unwrap().search(query, min_created_at, limit))
+            .collect();
+        posts.sort_by_key(|c| Reverse((c.created_at, c.post_id)));
+        posts.truncate(limit);
+        posts
+    }
+
+    /// Drop posts past their author's retention; returns how many were dropped
+    pub fn evict_expired(&self, now: u64, retention: &RetentionPolicy) -> usize {
+        let start = Instant::now();
+        let mut trimmed = 0;
+        for shard in 0..self.shards.len() {
+            let expired = self.write_shard(shard, |store| {
+                let expired = store.evict_expired_ids(now, retention);
+                for post_id in &expired {
+                    self.index.remove(*post_id);
+                }
+                expired
+            });
+            trimmed += expired.len();
+        }
+        self.last_trim_micros
+            .store(start.elapsed().as_micros() as u64, Ordering::Relaxed);
+        self.last_trimmed.store(trimmed as u64, Ordering::Relaxed);
+        self.trims.record(start);
+        trimmed
+    }
+
+    pub fn trim_stats(&self) -> TrimStats {
+        TrimStats {
+            trims: self.trims.count(),
+            mean_micros: self.trims.mean_micros(),
+            last_trimmed: self.last_trimmed.load(Ordering::Relaxed),
+            last_micros: self.last_trim_micros.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Offsets to resume consuming from, per partition
+    pub fn resume_offsets(&self) -> BTreeMap<i32, i64> {
+        let mut offsets = BTreeMap::new();
+        for shard in &self.shards {
+            merge_watermark(&mut offsets, shard.store.read().unwrap().resume_offsets());
+        }
+        offsets
+    }
+
+    /// Post events applied across all shards since startup
+    pub fn event_counts(&self) -> PostEventCounts {
+        let mut counts = PostEventCounts::default();
+        for shard in &self.shards {
+            counts += shard.store.read().unwrap().event_counts();
+        }
+        counts
+    }
+
+    /// Posts evicted across all shards since startup
+    pub fn eviction_counts(&self) -> EvictionCounts {
+        let mut counts = EvictionCounts::default();
+        for shard in &self.shards {
+            counts += shard.store.read().unwrap().eviction_counts();
+        }
+        counts
+    }
+
+    /// Compression of the post content across shards, when it is compressed
+    pub fn compression_stats(&self) -> Option<CompressionStats> {
+        let mut total: Option<CompressionStats> = None;
+        for shard in &self.shards {
+            if let Some(stats) = shard.store.read().unwrap().compression_stats() {
+                *total.get_or_insert_with(CompressionStats::default) += stats;
+            }
+        }
+        total
+    }
+
+    /// Estimated memory held by the posts of all shards
+    pub fn bytes(&self) -> usize {
+        self.bytes.load(Ordering::Relaxed) as usize
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.store.read().unwrap().len())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Point-in-time copy of all shards. Every shard is read-locked before any is
+    /// copied, so the snapshot never holds a later event without an earlier one.
+    pub fn to_snapshot(&self, now: u64) -> PostStoreSnapshot {
+        let shards: Vec<_> = self
+            .shards
+            .iter()
+            .map(|shard| shard.store.read().unwrap())
+            .collect();
+        let mut snapshot = PostStoreSnapshot {
+            version: SNAPSHOT_VERSION,
+            created_at: now,
+            watermark: BTreeMap::new(),
+            posts: Vec::new(),
+        };
+        for shard in &shards {
+            let part = shard.to_snapshot(now);
+            merge_watermark(&mut snapshot.watermark, &part.watermark);
+            snapshot.posts.extend(part.posts);
+        }
+        snapshot
+    }
+
+    pub fn shard_stats(&self) -> Vec<ShardStats> {
+        self.shards
+            .iter()
+            .enumerate()
+            .map(|(i, shard)| ShardStats {
+                shard: i,
+                posts: shard.store.read().unwrap().len(),
+                bytes: shard.store.read().unwrap().bytes(),
+                queries: shard.queries.count(),
+                mean_query_micros: shard.queries.mean_micros(),
+                writes: shard.writes.count(),
+                mean_write_micros: shard.writes.mean_micros(),
+            })
+            .collect()
+    }
+}
+
+/// Number of shard directories under a disk backend's `dir`
+fn existing_shards(dir: &Path) -> Result<usize, String> {
+    if !dir.exists() {
+        return Ok(0);
+    }
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("failed to list {}: {}", dir.display(), e))?;
+    Ok(entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("shard-"))
+        .count())
+}
+
+/// Keep the furthest offset per partition
+fn merge_watermark(into: &mut BTreeMap<i32, i64>, from: &BTreeMap<i32, i64>) {
+    for (partition, offset) in from {
+        let next = into.entry(*partition).or_insert(*offset);
+        *next = (*next).max(*offset);
+    }
+}
+
+impl CandidateSource for ShardedPostStore {
+    fn fetch_candidates(
+        &self,
+        user_id: i64,
+        following_ids: &[i64],
+        limit: usize,
+        max_per_author: Option<usize>,
+        cursor: Option<PostCursor>,
+    ) -> Vec<ThunderCandidate> {
+        self.fetch_from_shards(user_id, following_ids, limit, max_per_author, cursor, None)
+            .0
+    }
+
+    fn fetch_candidates_until(
+        &self,
+        user_id: i64,
+        following_ids: &[i64],
+        limit: usize,
+        max_per_author: Option<usize>,
+        cursor: Option<PostCursor>,
+        deadline: Instant,
+    ) -> (Vec<ThunderCandidate>, bool) {
+        let deadline = Some(deadline);
+        self.fetch_from_shards(user_id, following_ids, limit, max_per_author, cursor, deadline)
+    }
+}
+
+impl ShardedPostStore {
+    /// Read the shards holding `following_ids` one after another and merge their
+    /// newest posts. Once `deadline` passes, the shards not yet read are skipped
+    /// and the result is marked partial.
+    fn fetch_from_shards(
+        &self,
+        user_id: i64,
+        following_ids: &[i64],
+        limit: usize,
+        max_per_author: Option<usize>,
+        cursor: Option<PostCursor>,
+        deadline: Option<Instant>,
+    ) -> (Vec<ThunderCandidate>, bool) {
+        let mut authors_by_shard: Vec<Vec<i64>> = vec![Vec::new(); self.shards.len()];
+        for author_id in following_ids {
+            authors_by_shard[self.shard_for_author(*author_id)].push(*author_id);
+        }
+
+        let mut candidates = Vec::new();
+        let mut complete = true;
+        for (shard, authors) in self.shards.iter().zip(&authors_by_shard) {
+            if authors.is_empty() {
+                continue;
+            }
+            let start = Instant::now();
+            if deadline.is_some_and(|deadline| start >= deadline) {
+                complete = false;
+                break;
+            }
+            candidates.extend(shard.store.read().unwrap().fetch_candidates(
+                user_id,
+                authors,
+                limit,
+                max_per_author,
+                cursor,
+            ));
+            shard.queries.record(start);
+        }
+
+        // Each shard returns its newest first; keep the newest overall
+        candidates.sort_by_key(|c| Reverse((c.created_at, c.post_id)));
+        candidates.truncate(limit);
+        (candidates, complete)
+    }
+}
+
+/// Log per-shard size and latency every `interval`
+pub fn spawn_stats_logger(store: Arc<ShardedPostStore>, interval: Duration) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            info!(
+                "Post store: {} posts ({} bytes) in {} shards, events: {:?}, evictions: {:?}",
+                store.len(),
+                store.bytes(),
+                store.shard_count(),
+                store.event_counts(),
+                store.eviction_counts()
+            );
+            let trims = store.trim_stats();
+            info!(
+                "Trims: {} (mean {:.0}us), last dropped {} posts in {}us",
+                trims.trims, trims.mean_micros, trims.last_trimmed, trims.last_micros
+            );
+            if let Some(compression) = store.compression_stats() {
+                info!(
+                    "Post text: {:.2}x compression ({} of {} bytes), {} reads decompressed \
+                     (mean {:.1}us added)",
+                    compression.ratio(),
+                    compression.compressed_bytes,
+                    compression.raw_bytes,
+                    compression.decompressions,
+                    compression.mean_decompress_micros()
+                );
+            }
+            for stats in store.shard_stats() {
+                info!(
+                    "Shard {}: {} posts ({} bytes), {} queries (mean {:.0}us), \
+                     {} writes (mean {:.0}us)",
+                    stats.shard,
+                    stats.posts,
+                    stats.bytes,
+                    stats.queries,
+                    stats.mean_query_micros,
+                    stats.writes,
+                    stats.mean_write_micros
+                );
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upsert(offset: i64, post_id: i64, author_id: i64, created_at: u64) -> WalRecord {
+        WalRecord {
+            partition: 0,
+            offset,
+            event: PostEvent::Upsert {
+                post: ThunderCandidate::new(post_id, author_id, "Post".into(), created_at),
+            },
+        }
+    }
+
+    #[test]
+    fn test_queries_merge_newest_posts_across_shards() {
+        let store = ShardedPostStore::new(4);
+        store.apply_record(upsert(0, 1, 100, 1000));
+        store.apply_record(upsert(1, 2, 101, 1003));
+        store.apply_record(upsert(2, 3, 102, 1001));
+        store.apply_record(upsert(3, 4, 101, 1002));
+        assert!(store.apply_record(WalRecord {
+            partition: 0,
+            offset: 4,
+            event: PostEvent::Delete { post_id: 3 },
+        }));
+
+        let ids: Vec<i64> = store
+            .fetch_candidates(1, &[100, 101, 102], 10, None, None)
+            .iter()
+            .map(|c| c.post_id)
+            .collect();
+        assert_eq!(ids, vec![2, 4, 1]);
+        assert_eq!(store.fetch_candidates(1, &[100, 101], 2, Some(1), None).len(), 2);
+
+        assert_eq!(store.resume_offsets().get(&0), Some(&5));
+        assert_eq!(store.event_counts().deleted, 1);
+        let stats = store.shard_stats();
+        assert_eq!(stats.iter().map(|s| s.posts).sum::<usize>(), 3);
+        assert_eq!(stats.iter().map(|s| s.writes).sum::<u64>(), 5);
+
+        // The deleted post's id is still filed, and skipped
+        assert_eq!(store.evict_expired(1502, &RetentionPolicy::new(500)), 2);
+        let trims = store.trim_stats();
+        assert_eq!((trims.trims, trims.last_trimmed, store.len()), (1, 2, 1));
+    }
+
+    #[test]
+    fn test_snapshot_restores_into_a_different_shard_count() {
+        let store = ShardedPostStore::new(3);
+        for offset in 0..6 {
+            store.apply_record(upsert(offset, offset, 100 + offset, 1000));
+        }
+
+        let restored = ShardedPostStore::from_snapshot(store.to_snapshot(1100), 2);
+        assert_eq!(restored.len(), 6);
+        assert_eq!(restored.resume_offsets(), store.resume_offsets());
+        // Already-applied events are skipped by whichever shard they land on
+        assert!(!restored.apply_record(upsert(5, 5, 105, 1000)));
+        assert!(restored.apply_record(upsert(6, 6, 106, 1000)));
+
+        // A budget for about half the posts evicts the oldest across shards
+        let budget = restored.bytes() as u64 / 2;
+        let restored = restored.with_max_bytes(Some(budget));
+        assert!(restored.bytes() as u64 <= budget);
+        assert_eq!(restored.eviction_counts().over_budget as usize, 7 - restored.len());
+    }
+
+    #[test]
+    fn test_budget_evicts_the_oldest_posts_of_any_shard() {
+        // Author 100's old posts land on shard 0, author 101's new ones on shard 1
+        let store = ShardedPostStore::new(2);
+        for offset in 0..4 {
+            store.apply_record(upsert(offset, offset, 100, 1000 + offset as u64));
+            store.apply_record(upsert(offset + 4, offset + 4, 101, 2000 + offset as u64));
+        }
+        let per_post = store.bytes() as u64 / 8;
+        let shard_bytes = |stats: &[ShardStats]| stats.iter().map(|s| s.bytes).sum::<usize>();
+        assert_eq!(store.bytes(), shard_bytes(&store.shard_stats()));
+
+        // An even split would have evicted two of author 101's posts too
+        let store = store.with_max_bytes(Some(per_post * 6));
+        assert_eq!(store.eviction_counts().over_budget, 2);
+        let ids: Vec<i64> = store
+            .fetch_candidates(1, &[100, 101], 10, None, None)
+            .iter()
+            .map(|c| c.post_id)
+            .collect();
+        assert_eq!(ids, vec![7, 6, 5, 4, 3, 2]);
+
+        // New posts keep the whole store within budget, evicting the oldest first
+        store.apply_record(upsert(8, 8, 101, 3000));
+        assert_eq!(store.len(), 6);
+        assert!(store.get_conversation(2, ConversationLimits::default()).is_none());
+        assert_eq!(store.bytes(), shard_bytes(&store.shard_stats()));
+        assert!(store.bytes() as u64 <= per_post * 6);
+    }
+
+    #[test]
+    fn test_index_routes_changes_to_the_shard_holding_the_post() {
+        let store = ShardedPostStore::new(4);
+        store.apply_record(upsert(0, 1, 101, 1000));
+        let delta = EngagementDelta {
+            likes: 2,
+            ..Default::default()
+        };
+        assert!(store.update_engagement(1, &delta, 1001));
+        assert!(!store.update_engagement(2, &delta, 1001));
+        assert!(store.apply_record(WalRecord {
+            partition: 0,
+            offset: 1,
+            event: PostEvent::Edit {
+                post_id: 1,
+                content: "Edited".into(),
+                has_media: false,
+                has_link: false,
+            },
+        }));
+        assert_eq!(store.fetch_candidates(1, &[101], 1, None, None)[0].content, "Edited");
+
+        store.apply_record(WalRecord {
+            partition: 0,
+            offset: 2,
+            event: PostEvent::Delete { post_id: 1 },
+        });
+        assert!(!store.update_engagement(1, &delta, 1002));
+        assert_eq!(store.event_counts().deleted, 1);
+
+        // Expired posts leave the index with the shard
+        store.apply_record(upsert(3, 3, 102, 1000));
+        assert_eq!(store.evict_expired(2000, &RetentionPolicy::new(500)), 1);
+        assert!(!store.update_engagement(3, &delta, 2000));
+        assert_eq!(store.bytes(), 0);
+    }
+}
@@ -0,0 +1,225 @@
+//! PostStore Snapshots
+//!
+//! The post store is periodically written to a snapshot directory as JSON
+//! (written to a temporary file, then renamed into place). On startup the
+//! newest readable snapshot is loaded, and consumption resumes from its offset
+//! watermark rather than from the start of the stream.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::candidate_source::ThunderCandidate;
+use crate::ingest::Ingestor;
+
+/// Bumped whenever the snapshot layout changes; other versions are skipped
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+const SNAPSHOT_PREFIX: &str = "posts-";
+const SNAPSHOT_EXTENSION: &str = "json";
+
+/// Serialized state of a `PostStore`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PostStoreSnapshot {
+    pub version: u32,
+    /// Unix epoch seconds when the snapshot was taken
+    pub created_at: u64,
+    /// Next offset to consume per partition
+    pub watermark: BTreeMap<i32, i64>,
+    pub posts: Vec<ThunderCandidate>,
+}
+
+/// Metadata of a snapshot on disk, reported as metrics
+#[derive(Clone, Debug, Serialize)]
+pub struct SnapshotInfo {
+    pub path: PathBuf,
+    pub created_at: u64,
+    pub size_bytes: u64,
+    pub posts: usize,
+}
+
+impl SnapshotInfo {
+    pub fn age_seconds(&self, now: u64) -> u64 {
+        now.saturating_sub(self.created_at)
+    }
+}
+
+/// Directory holding the most recent `keep` snapshots
+pub struct SnapshotDir {
+    dir: PathBuf,
+    keep: usize,
+}
+
+impl SnapshotDir {
+    pub fn open(dir: impl AsRef<Path>, keep: usize) -> Result<Self, String> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+        Ok(Self {
+            dir,
+            keep: keep.max(1),
+        })
+    }
+
+    /// Write `snapshot` and prune all but the newest `keep` snapshots
+    pub fn write(&self, snapshot: &PostStoreSnapshot) -> Result<SnapshotInfo, String> {
+        let path = self.dir.join(format!(
+            "{}{:012}.{}",
+            SNAPSHOT_PREFIX, snapshot.created_at, SNAPSHOT_EXTENSION
+        ));
+        let tmp_path = path.with_extension("tmp");
+
+        let file = fs::File::create(&tmp_path)
+            .map_err(|e| format!("failed to create {}: {}", tmp_path.display(), e))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, snapshot)
+            .map_err(|e| format!("failed to serialize snapshot: {}", e))?;
+        writer
+            .flush()
+            .and_then(|_| writer.get_ref().sync_all())
+            .map_err(|e| format!("failed to write {}: {}", tmp_path.display(), e))?;
+        fs::rename(&tmp_path, &path)
+            .map_err(|e| format!("failed to move snapshot into place: {}", e))?;
+
+        for stale in self.snapshot_paths()?.into_iter().skip(self.keep) {
+            if let Err(e) = fs::remove_file(&stale) {
+                warn!("Failed to remove old snapshot {}: {}", stale.display(), e);
+            }
+        }
+
+        Ok(SnapshotInfo {
+            size_bytes: fs::metadata(&path).map(|m| m.len()).unwrap_or(0),
+            path,
+            created_at: snapshot.created_at,
+            posts: snapshot.posts.len(),
+        })
+    }
+
+    /// Newest snapshot that can be read, skipping corrupt or incompatible ones
+    pub fn latest(&self) -> Result<Option<(PostStoreSnapshot, SnapshotInfo)>, String> {
+        for path in self.snapshot_paths()? {
+            match read_snapshot(&path) {
+                Ok(snapshot) if snapshot.version == SNAPSHOT_VERSION => {
+                    let info = SnapshotInfo {
+                        size_bytes: fs::metadata(&path).map(|m| m.len()).unwrap_or(0),
+                        path,
+                        created_at: snapshot.created_at,
+                        posts: snapshot.posts.len(),
+                    };
+                    return Ok(Some((snapshot, info)));
+                }
+                Ok(snapshot) => warn!(
+                    "Skipping snapshot {} with version {}",
+                    path.display(),
+                    snapshot.version
+                ),
+                Err(e) => warn!("Skipping unreadable snapshot: {}", e),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Snapshot files, newest first
+    fn snapshot_paths(&self) -> Result<Vec<PathBuf>, String> {
+        let entries = fs::read_dir(&self.dir)
+            .map_err(|e| format!("failed to list {}: {}", self.dir.display(), e))?;
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| {
+                path.extension().is_some_and(|ext| ext == SNAPSHOT_EXTENSION)
+                    && path
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| name.starts_with(SNAPSHOT_PREFIX))
+            })
+            .collect();
+        // Zero-padded timestamps sort chronologically
+        paths.sort();
+        paths.reverse();
+        Ok(paths)
+    }
+}
+
+fn read_snapshot(path: &Path) -> Result<PostStoreSnapshot, String> {
+    let file = fs::File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    serde_json::from_reader(BufReader::new(file)).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Unix epoch seconds
+pub fn now_seconds() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Snapshot the ingestor's store into `snapshots` every `interval`
+pub fn spawn_snapshotter(ingestor: Arc<Ingestor>, snapshots: Arc<SnapshotDir>, interval: Duration) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            let writer = ingestor.clone();
+            let snapshots = snapshots.clone();
+            let write = move || writer.snapshot(&snapshots, now_seconds());
+            match tokio::task::spawn_blocking(write).await {
+                Ok(Ok(info)) => info!(
+                    "Wrote snapshot {} ({} posts, {} bytes); post events so far: {:?}",
+                    info.path.display(),
+                    info.posts,
+                    info.size_bytes,
+                    ingestor.store().event_counts()
+                ),
+                Ok(Err(e)) => warn!("Failed to write post store snapshot: {}", e),
+                Err(e) => warn!("Snapshot task failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(created_at: u64, posts: usize) -> PostStoreSnapshot {
+        PostStoreSnapshot {
+            version: SNAPSHOT_VERSION,
+            created_at,
+            watermark: BTreeMap::from([(0, created_at as i64)]),
+            posts: (0..posts)
+                .map(|i| ThunderCandidate::new(i as i64, 100, "Post".into(), created_at))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_latest_snapshot_wins_and_old_ones_are_pruned() {
+        let dir = std::env::temp_dir().join(format!("thunder_snapshots_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let snapshots = SnapshotDir::open(&dir, 2).unwrap();
+        assert!(snapshots.latest().unwrap().is_none());
+
+        snapshots.write(&snapshot(100, 1)).unwrap();
+        snapshots.write(&snapshot(200, 2)).unwrap();
+        let info = snapshots.write(&snapshot(300, 3)).unwrap();
+        assert!(info.size_bytes > 0);
+        assert_eq!(info.age_seconds(360), 60);
+        assert_eq!(snapshots.snapshot_paths().unwrap().len(), 2);
+
+        let (latest, info) = snapshots.latest().unwrap().unwrap();
+        assert_eq!(latest.created_at, 300);
+        assert_eq!(latest.watermark.get(&0), Some(&300));
+        assert_eq!(info.posts, 3);
+
+        // A corrupt newest snapshot falls back to the previous one
+        fs::write(&info.path, b"{ truncated").unwrap();
+        assert_eq!(snapshots.latest().unwrap().unwrap().0.created_at, 200);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
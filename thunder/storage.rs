@@ -0,0 +1,144 @@
+//! Storage Backends for Thunder
+//!
+//! `PostStore` keeps the stream bookkeeping (watermark, event counts, memory
+//! budget, edit semantics) and holds its posts in a `PostStorage`. The default
+//! backend is the in-memory `AuthorIndex`; the RocksDB backend (behind the
+//! `rocksdb` feature) keeps posts on disk, for retention periods longer than
+//! RAM allows. Queries and ingestion run through the same code paths either way.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::author_index::AuthorIndex;
+use crate::candidate_source::{PostCursor, ThunderCandidate};
+use crate::text_compression::{CompressionStats, TextCodec};
+
+/// Where a post store keeps its posts
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    #[default]
+    Memory,
+    RocksDb,
+}
+
+/// Posts indexed by author, creation time, parent and content
+pub trait PostStorage: Send + Sync {
+    /// Insert `post`, replacing any post with the same id; returns the replaced post
+    fn insert(&mut self, post: ThunderCandidate) -> Option<ThunderCandidate>;
+
+    /// Change a post in place; returns false if the storage doesn't hold it.
+    /// `change` must not touch the author, creation time or parent.
+    fn update(&mut self, post_id: i64, change: &mut dyn FnMut(&mut ThunderCandidate)) -> bool;
+
+    fn remove(&mut self, post_id: i64) -> Option<ThunderCandidate>;
+
+    fn get(&self, post_id: i64) -> Option<ThunderCandidate>;
+
+    fn contains(&self, post_id: i64) -> bool;
+
+    /// The post created first
+    fn oldest(&self) -> Option<ThunderCandidate>;
+
+    /// Replies to `post_id`, oldest first
+    fn replies_to(&self, post_id: i64) -> Vec<ThunderCandidate>;
+
+    /// Up to `limit` posts matching every token of `query`, created at or after
+    /// `min_created_at`, newest first
+    fn search(&self, query: &str, min_created_at: u64, limit: usize) -> Vec<ThunderCandidate>;
+
+    /// Up to `limit` of the newest posts by `author_ids` older than `cursor`,
+    /// newest first, taking at most `max_per_author` from any one author
+    fn newest_by_authors(
+        &self,
+        author_ids: &[i64],
+        limit: usize,
+        max_per_author: Option<usize>,
+        cursor: Option<PostCursor>,
+    ) -> Vec<ThunderCandidate>;
+
+    /// Every post, in no particular order
+    fn posts(&self) -> Box<dyn Iterator<Item = ThunderCandidate> + '_>;
+
+    /// Every post, oldest first
+    fn posts_by_age(&self) -> Box<dyn Iterator<Item = ThunderCandidate> + '_>;
+
+    /// Estimated bytes held by the posts and their index entries
+    fn bytes(&self) -> usize;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Next offset to consume per partition, as persisted with the posts.
+    /// In-memory storage persists nothing and starts from the snapshot instead.
+    fn watermark(&self) -> BTreeMap<i32, i64> {
+        BTreeMap::new()
+    }
+
+    fn set_watermark(&mut self, _partition: i32, _next_offset: i64) {}
+
+    /// Hold post content compressed with `codec`, including the posts held now
+    fn set_text_codec(&mut self, _codec: Arc<dyn TextCodec>) -> Result<(), String> {
+        Err("this storage backend doesn't compress post text".to_string())
+    }
+
+    /// Compression of the post content, when it is compressed
+    fn compression_stats(&self) -> Option<CompressionStats> {
+        None
+    }
+}
+
+/// Open the storage for shard `shard` of a store. Disk backends keep each shard
+/// in its own directory under `dir`.
+pub fn open_storage(
+    backend: StorageBackend,
+    dir: Option<&Path>,
+    shard: usize,
+) -> Result<Box<dyn PostStorage>, String> {
+    match backend {
+        StorageBackend::Memory => Ok(Box::new(AuthorIndex::new())),
+        StorageBackend::RocksDb => {
+            let dir = dir.ok_or("the rocksdb storage backend requires --storage-dir")?;
+            open_rocksdb(&dir.join(format!("shard-{}", shard)))
+        }
+    }
+}
+
+#[cfg(feature = "rocksdb")]
+fn open_rocksdb(path: &Path) -> Result<Box<dyn PostStorage>, String> {
+    Ok(Box::new(crate::rocksdb_storage::RocksDbStorage::open(path)?))
+}
+
+#[cfg(not(feature = "rocksdb"))]
+fn open_rocksdb(_path: &Path) -> Result<Box<dyn PostStorage>, String> {
+    Err("thunder was built without the rocksdb feature".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_storage_behind_the_trait() {
+        let mut storage = open_storage(StorageBackend::Memory, None, 0).unwrap();
+        storage.insert(ThunderCandidate::new(1, 100, "Post 1".into(), 1000));
+        storage.insert(ThunderCandidate::new(2, 100, "Post 2".into(), 1001));
+        assert!(storage.update(1, &mut |post| post.content.push_str(" #edited")));
+
+        assert_eq!(storage.get(1).unwrap().content, "Post 1 #edited");
+        assert_eq!(storage.search("#edited", 0, 10).len(), 1);
+        assert_eq!(storage.oldest().unwrap().post_id, 1);
+        assert_eq!(storage.newest_by_authors(&[100], 10, None, None)[0].post_id, 2);
+        assert_eq!(storage.posts().count(), 2);
+        assert!(storage.watermark().is_empty());
+
+        assert!(open_storage(StorageBackend::RocksDb, None, 0).is_err());
+    }
+}
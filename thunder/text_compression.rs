@@ -0,0 +1,270 @@
+//! Text Compression for Thunder
+//!
+//! Post text dominates the memory held by the in-memory store, so it can be
+//! kept compressed with LZ4 (`lz4` feature) or Zstandard (`zstd` feature),
+//! optionally primed with a small dictionary of typical post text, without
+//! which short posts barely compress. Text is decompressed only when a post
+//! leaves the store, i.e. for the posts a query returns. The compression ratio
+//! and the time spent decompressing for reads are tracked with the store stats.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::AddAssign;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use log::error;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Compression applied to stored post text
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TextCompression {
+    #[default]
+    None,
+    Lz4,
+    Zstd,
+}
+
+/// A lossless encoding of post text
+pub trait TextCodec: Send + Sync {
+    fn compress(&self, text: &str) -> Vec<u8>;
+
+    /// Decode `bytes`, which hold `raw_len` bytes of text
+    fn decompress(&self, bytes: &[u8], raw_len: usize) -> Result<String, String>;
+}
+
+/// The codec for `compression`, primed with the dictionary at `dictionary`;
+/// None when text is stored as is
+pub fn open_codec(
+    compression: TextCompression,
+    dictionary: Option<&Path>,
+) -> Result<Option<Arc<dyn TextCodec>>, String> {
+    #[allow(unused_variables)]
+    let dictionary = match dictionary {
+        Some(path) => std::fs::read(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?,
+        None => Vec::new(),
+    };
+    match compression {
+        TextCompression::None => Ok(None),
+        #[cfg(feature = "lz4")]
+        TextCompression::Lz4 => Ok(Some(Arc::new(Lz4Codec { dictionary }))),
+        #[cfg(feature = "zstd")]
+        TextCompression::Zstd => Ok(Some(Arc::new(ZstdCodec::new(&dictionary)))),
+        #[allow(unreachable_patterns)]
+        compression => Err(format!("thunder was built without {:?} support", compression)),
+    }
+}
+
+#[cfg(feature = "lz4")]
+struct Lz4Codec {
+    dictionary: Vec<u8>,
+}
+
+#[cfg(feature = "lz4")]
+impl TextCodec for Lz4Codec {
+    fn compress(&self, text: &str) -> Vec<u8> {
+        lz4_flex::block::compress_with_dict(text.as_bytes(), &self.dictionary)
+    }
+
+    fn decompress(&self, bytes: &[u8], raw_len: usize) -> Result<String, String> {
+        let raw = lz4_flex::block::decompress_with_dict(bytes, raw_len, &self.dictionary)
+            .map_err(|e| e.to_string())?;
+        String::from_utf8(raw).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(feature = "zstd")]
+const ZSTD_LEVEL: i32 = 3;
+
+#[cfg(feature = "zstd")]
+type ZstdDictionaries = (
+    zstd::dict::EncoderDictionary<'static>,
+    zstd::dict::DecoderDictionary<'static>,
+);
+
+#[cfg(feature = "zstd")]
+struct ZstdCodec {
+    /// Prepared once, as loading a dictionary costs more than a short post
+    dictionaries: Option<ZstdDictionaries>,
+}
+
+#[cfg(feature = "zstd")]
+impl ZstdCodec {
+    fn new(dictionary: &[u8]) -> Self {
+        let dictionaries = (!dictionary.is_empty()).then(|| {
+            (
+                zstd::dict::EncoderDictionary::copy(dictionary, ZSTD_LEVEL),
+                zstd::dict::DecoderDictionary::copy(dictionary),
+            )
+        });
+        Self { dictionaries }
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl TextCodec for ZstdCodec {
+    fn compress(&self, text: &str) -> Vec<u8> {
+        let compressed = match &self.dictionaries {
+            Some((encoder, _)) => zstd::bulk::Compressor::with_prepared_dictionary(encoder)
+                .and_then(|mut compressor| compressor.compress(text.as_bytes())),
+            None => zstd::bulk::compress(text.as_bytes(), ZSTD_LEVEL),
+        };
+        // Compressing into a growable buffer only fails on allocation
+        compressed.expect("zstd compression failed")
+    }
+
+    fn decompress(&self, bytes: &[u8], raw_len: usize) -> Result<String, String> {
+        let raw = match &self.dictionaries {
+            Some((_, decoder)) => zstd::bulk::Decompressor::with_prepared_dictionary(decoder)
+                .and_then(|mut decompressor| decompressor.decompress(bytes, raw_len)),
+            None => zstd::bulk::decompress(bytes, raw_len),
+        }
+        .map_err(|e| e.to_string())?;
+        String::from_utf8(raw).map_err(|e| e.to_string())
+    }
+}
+
+/// Compression ratio of the stored text and the cost of decompressing it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct CompressionStats {
+    pub posts: u64,
+    pub raw_bytes: u64,
+    pub compressed_bytes: u64,
+    /// Texts decompressed for posts leaving the store
+    pub decompressions: u64,
+    pub decompress_micros: u64,
+}
+
+impl CompressionStats {
+    /// Raw over compressed size; 1 when nothing is stored
+    pub fn ratio(&self) -> f64 {
+        if self.compressed_bytes == 0 {
+            return 1.0;
+        }
+        self.raw_bytes as f64 / self.compressed_bytes as f64
+    }
+
+    /// Latency a decompression adds to a read, on average
+    pub fn mean_decompress_micros(&self) -> f64 {
+        if self.decompressions == 0 {
+            return 0.0;
+        }
+        self.decompress_micros as f64 / self.decompressions as f64
+    }
+}
+
+impl AddAssign for CompressionStats {
+    fn add_assign(&mut self, other: Self) {
+        self.posts += other.posts;
+        self.raw_bytes += other.raw_bytes;
+        self.compressed_bytes += other.compressed_bytes;
+        self.decompressions += other.decompressions;
+        self.decompress_micros += other.decompress_micros;
+    }
+}
+
+struct CompressedText {
+    raw_len: usize,
+    bytes: Box<[u8]>,
+}
+
+/// Compressed text of a store's posts, by post id
+pub struct TextStore {
+    codec: Arc<dyn TextCodec>,
+    texts: HashMap<i64, CompressedText>,
+    raw_bytes: usize,
+    compressed_bytes: usize,
+    decompressions: AtomicU64,
+    decompress_nanos: AtomicU64,
+}
+
+impl fmt::Debug for TextStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TextStore")
+            .field("texts", &self.texts.len())
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl TextStore {
+    pub fn new(codec: Arc<dyn TextCodec>) -> Self {
+        Self {
+            codec,
+            texts: HashMap::new(),
+            raw_bytes: 0,
+            compressed_bytes: 0,
+            decompressions: AtomicU64::new(0),
+            decompress_nanos: AtomicU64::new(0),
+        }
+    }
+
+    /// Store `text` as the text of `post_id`, replacing any it had
+    pub fn insert(&mut self, post_id: i64, text: &str) {
+        let bytes = self.codec.compress(text).into_boxed_slice();
+        self.raw_bytes += text.len();
+        self.compressed_bytes += bytes.len();
+        let compressed = CompressedText {
+            raw_len: text.len(),
+            bytes,
+        };
+        if let Some(replaced) = self.texts.insert(post_id, compressed) {
+            self.raw_bytes -= replaced.raw_len;
+            self.compressed_bytes -= replaced.bytes.len();
+        }
+    }
+
+    /// Drop the text of `post_id`, returning it
+    pub fn remove(&mut self, post_id: i64) -> Option<String> {
+        let text = self.texts.remove(&post_id)?;
+        self.raw_bytes -= text.raw_len;
+        self.compressed_bytes -= text.bytes.len();
+        Some(self.decode(post_id, &text))
+    }
+
+    /// The text of `post_id`, for a post leaving the store
+    pub fn read(&self, post_id: i64) -> String {
+        let start = Instant::now();
+        let text = self.peek(post_id);
+        self.decompressions.fetch_add(1, Ordering::Relaxed);
+        let nanos = start.elapsed().as_nanos() as u64;
+        self.decompress_nanos.fetch_add(nanos, Ordering::Relaxed);
+        text
+    }
+
+    /// The text of `post_id`, for use within the store; not counted as a read
+    pub fn peek(&self, post_id: i64) -> String {
+        self.texts
+            .get(&post_id)
+            .map(|text| self.decode(post_id, text))
+            .unwrap_or_default()
+    }
+
+    fn decode(&self, post_id: i64, text: &CompressedText) -> String {
+        self.codec
+            .decompress(&text.bytes, text.raw_len)
+            .unwrap_or_else(|e| {
+                error!("Failed to decompress the text of post {}: {}", post_id, e);
+                String::new()
+            })
+    }
+
+    pub fn compressed_len(&self, post_id: i64) -> usize {
+        self.texts.get(&post_id).map_or(0, |text| text.bytes.len())
+    }
+
+    pub fn stats(&self) -> CompressionStats {
+        CompressionStats {
+            posts: self.texts.len() as u64,
+            raw_bytes: self.raw_bytes as u64,
+            compressed_bytes: self.compressed_bytes as u64,
+            decompressions: self.decompressions.load(Ordering::Relaxed),
+            decompress_micros: self.decompress_nanos.load(Ordering::Relaxed) / 1000,
+        }
+    }
+}
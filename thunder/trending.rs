@@ -0,0 +1,282 @@
+//! Trending Detection for Thunder
+//!
+//! Counts, over a sliding window, the posts each author publishes and how
+//! often each post and author is served by in-network queries, and flags the
+//! keys whose count stands out: at least a minimum count and a multiple of the
+//! median count of every key seen in the window. The window is kept as a ring
+//! of buckets, so counts age out one bucket at a time. Flagged authors and
+//! posts are listed over HTTP and can be tagged `trending_in_network` on query
+//! results, so home-mixer scorers can boost or throttle them deliberately.
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::candidate_source::ThunderCandidate;
+
+/// Buckets the window is split into
+const WINDOW_BUCKETS: u64 = 10;
+/// Most keys listed per counter in a report
+const MAX_REPORTED: usize = 100;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrendingConfig {
+    /// Length of the sliding window in seconds
+    pub window_seconds: u64,
+    /// Fewest events in the window for a key to be flagged
+    pub min_count: u64,
+    /// How many times the median count a key needs to be flagged
+    pub factor: f64,
+    /// Tag flagged posts `trending_in_network` in query results
+    pub tag_candidates: bool,
+}
+
+impl Default for TrendingConfig {
+    fn default() -> Self {
+        Self {
+            window_seconds: 300,
+            min_count: 20,
+            factor: 10.0,
+            tag_candidates: false,
+        }
+    }
+}
+
+/// A key whose count stands out in the window
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct HotKey {
+    pub id: i64,
+    pub count: u64,
+    /// Multiple of the median count in the window
+    pub ratio: f64,
+}
+
+/// Authors and posts flagged over the current window, busiest first
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct TrendingReport {
+    pub window_seconds: u64,
+    /// Authors by posts published
+    pub authors_publishing: Vec<HotKey>,
+    /// Authors by times their posts were served
+    pub authors_served: Vec<HotKey>,
+    /// Posts by times served
+    pub posts_served: Vec<HotKey>,
+}
+
+/// Event counts per key over the last `WINDOW_BUCKETS` buckets
+#[derive(Default)]
+struct WindowCounter {
+    /// Counts added in each bucket, oldest first, with the bucket's number
+    buckets: VecDeque<(u64, HashMap<i64, u64>)>,
+    /// Counts over the whole window
+    totals: HashMap<i64, u64>,
+}
+
+impl WindowCounter {
+    fn add(&mut self, bucket: u64, key: i64, count: u64) {
+        self.expire(bucket);
+        if self.buckets.back().is_none_or(|(number, _)| *number != bucket) {
+            self.buckets.push_back((bucket, HashMap::new()));
+        }
+        let (_, counts) = self.buckets.back_mut().unwrap();
+        *counts.entry(key).or_insert(0) += count;
+        *self.totals.entry(key).or_insert(0) += count;
+    }
+
+    /// Drop the buckets that fell out of the window ending at `bucket`
+    fn expire(&mut self, bucket: u64) {
+        while let Some((number, _)) = self.buckets.front() {
+            if number + WINDOW_BUCKETS > bucket {
+                break;
+            }
+            let (_, counts) = self.buckets.pop_front().unwrap();
+            for (key, count) in counts {
+                if let Entry::Occupied(mut total) = self.totals.entry(key) {
+                    *total.get_mut() -= count;
+                    if *total.get() == 0 {
+                        total.remove();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Keys counted at least `min_count` times and `factor` times the median
+    fn hot(&mut self, bucket: u64, min_count: u64, factor: f64) -> Vec<HotKey> {
+        self.expire(bucket);
+        let mut counts: Vec<u64> = self.totals.values().copied().collect();
+        if counts.is_empty() {
+            return Vec::new();
+        }
+        let middle = counts.len() / 2;
+        let median = *counts.select_nth_unstable(middle).1 as f64;
+        let threshold = (median * factor).max(min_count as f64);
+        let mut hot: Vec<HotKey> = self
+            .totals
+            .iter()
+            .filter(|(_, count)| **count as f64 >= threshold)
+            .map(|(id, count)| HotKey {
+                id: *id,
+                count: *count,
+                ratio: *count as f64 / median,
+            })
+            .collect();
+        hot.sort_by(|a, b| b.count.cmp(&a.count).then(a.id.cmp(&b.id)));
+        hot
+    }
+}
+
+/// Flagged ids, recomputed once per bucket for tagging query results
+#[derive(Default)]
+struct HotIds {
+    bucket: Option<u64>,
+    authors: Arc<HashSet<i64>>,
+    posts: Arc<HashSet<i64>>,
+}
+
+pub struct TrendingDetector {
+    config: TrendingConfig,
+    authors_publishing: Mutex<WindowCounter>,
+    authors_served: Mutex<WindowCounter>,
+    posts_served: Mutex<WindowCounter>,
+    hot_ids: Mutex<HotIds>,
+}
+
+impl TrendingDetector {
+    pub fn new(config: TrendingConfig) -> Self {
+        Self {
+            config,
+            authors_publishing: Mutex::default(),
+            authors_served: Mutex::default(),
+            posts_served: Mutex::default(),
+            hot_ids: Mutex::default(),
+        }
+    }
+
+    pub fn config(&self) -> &TrendingConfig {
+        &self.config
+    }
+
+    /// Bucket holding Unix time `now`
+    fn bucket(&self, now: u64) -> u64 {
+        now / (self.config.window_seconds / WINDOW_BUCKETS).max(1)
+    }
+
+    /// Count a post published by `author_id`
+    pub fn record_published(&self, author_id: i64, now: u64) {
+        let bucket = self.bucket(now);
+        self.authors_publishing.lock().unwrap().add(bucket, author_id, 1);
+    }
+
+    /// Count `posts` served by a query
+    fn record_served(&self, posts: &[ThunderCandidate], now: u64) {
+        let bucket = self.bucket(now);
+        let mut authors = self.authors_served.lock().unwrap();
+        for post in posts {
+            authors.add(bucket, post.author_id, 1);
+        }
+        drop(authors);
+        let mut served = self.posts_served.lock().unwrap();
+        for post in posts {
+            served.add(bucket, post.post_id, 1);
+        }
+    }
+
+    /// Authors and posts flagged over the window ending at `now`
+    pub fn report(&self, now: u64) -> TrendingReport {
+        let bucket = self.bucket(now);
+        let (min_count, factor) = (self.config.min_count, self.config.factor);
+        let hot = |counter: &Mutex<WindowCounter>| {
+            let mut hot = counter.lock().unwrap().hot(bucket, min_count, factor);
+            hot.truncate(MAX_REPORTED);
+            hot
+        };
+        TrendingReport {
+            window_seconds: self.config.window_seconds,
+            authors_publishing: hot(&self.authors_publishing),
+            authors_served: hot(&self.authors_served),
+            posts_served: hot(&self.posts_served),
+        }
+    }
+
+    /// Set `trending_in_network` on `posts` that are flagged or whose author is
+    fn tag(&self, posts: &mut [ThunderCandidate], now: u64) {
+        let bucket = self.bucket(now);
+        let mut hot_ids = self.hot_ids.lock().unwrap();
+        if hot_ids.bucket != Some(bucket) {
+            let (min_count, factor) = (self.config.min_count, self.config.factor);
+            let ids = |counter: &Mutex<WindowCounter>| {
+                let hot = counter.lock().unwrap().hot(bucket, min_count, factor);
+                hot.into_iter().map(|key| key.id).collect::<Vec<_>>()
+            };
+            let mut authors = ids(&self.authors_publishing);
+            authors.extend(ids(&self.authors_served));
+            *hot_ids = HotIds {
+                bucket: Some(bucket),
+                authors: Arc::new(authors.into_iter().collect()),
+                posts: Arc::new(ids(&self.posts_served).into_iter().collect()),
+            };
+        }
+        let (authors, posts_served) = (hot_ids.authors.clone(), hot_ids.posts.clone());
+        drop(hot_ids);
+        for post in posts {
+            post.trending_in_network =
+                authors.contains(&post.author_id) || posts_served.contains(&post.post_id);
+        }
+    }
+
+    /// Count `posts` as served, tagging them first when configured to
+    pub fn observe_served(&self, posts: &mut [ThunderCandidate], now: u64) {
+        if self.config.tag_candidates {
+            self.tag(posts, now);
+        }
+        self.record_served(posts, now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flags_outliers_until_they_age_out() {
+        let detector = TrendingDetector::new(TrendingConfig {
+            window_seconds: 100,
+            min_count: 5,
+            factor: 4.0,
+            tag_candidates: true,
+        });
+        for author_id in 1..=10 {
+            detector.record_published(author_id, 1000);
+        }
+        for _ in 0..7 {
+            detector.record_published(99, 1005);
+        }
+        let mut posts = vec![
+            ThunderCandidate::new(1, 99, "Post".into(), 1000),
+            ThunderCandidate::new(2, 1, "Post".into(), 1000),
+        ];
+        detector.observe_served(&mut posts, 1010);
+        assert!(posts[0].trending_in_network);
+        assert!(!posts[1].trending_in_network);
+
+        let report = detector.report(1010);
+        let hot = HotKey {
+            id: 99,
+            count: 7,
+            ratio: 7.0,
+        };
+        assert_eq!(report.authors_publishing, vec![hot]);
+        // One query isn't enough to flag what it served
+        assert!(report.posts_served.is_empty());
+
+        // The burst leaves the window ten buckets later
+        assert!(detector.report(1100).authors_publishing.is_empty());
+        detector.observe_served(&mut posts, 1100);
+        assert!(!posts[0].trending_in_network);
+    }
+}
@@ -0,0 +1,122 @@
+//! TTL Index for Thunder
+//!
+//! Files post ids under the time they become due for expiry, in a ring of
+//! per-minute buckets, so trimming takes the buckets of the minutes that have
+//! passed instead of walking the store. The ring starts at the oldest minute
+//! still held and grows at the back as newer posts arrive; only the bucket of
+//! the cutoff minute itself is split. Ids stay filed after their post is
+//! removed some other way, and are simply not found when their bucket is taken.
+
+use std::collections::VecDeque;
+
+const BUCKET_SECONDS: u64 = 60;
+
+/// Post ids by due time, bucketed by minute
+#[derive(Debug, Default)]
+pub struct TtlIndex {
+    /// Minute of the front bucket
+    first_minute: u64,
+    /// Post ids with their due time, per minute from `first_minute` on
+    buckets: VecDeque<Vec<(i64, u64)>>,
+    /// Cutoff of the last `take_due`; anything due before it is overdue
+    taken_up_to: u64,
+    len: usize,
+}
+
+impl TtlIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// File `post_id` as due at `due` (Unix seconds)
+    pub fn insert(&mut self, post_id: i64, due: u64) {
+        // Overdue ids go in the front bucket, which the next `take_due` takes
+        let minute = (due / BUCKET_SECONDS).max(self.taken_up_to / BUCKET_SECONDS);
+        if self.buckets.is_empty() {
+            self.first_minute = minute;
+        }
+        while minute < self.first_minute {
+            self.buckets.push_front(Vec::new());
+            self.first_minute -= 1;
+        }
+        let index = (minute - self.first_minute) as usize;
+        if index >= self.buckets.len() {
+            self.buckets.resize_with(index + 1, Vec::new);
+        }
+        self.buckets[index].push((post_id, due));
+        self.len += 1;
+    }
+
+    /// Remove and return the ids due before `cutoff`
+    pub fn take_due(&mut self, cutoff: u64) -> Vec<i64> {
+        let cutoff_minute = cutoff / BUCKET_SECONDS;
+        let mut due = Vec::new();
+        while self.first_minute < cutoff_minute {
+            let Some(bucket) = self.buckets.pop_front() else {
+                break;
+            };
+            due.extend(bucket.into_iter().map(|(post_id, _)| post_id));
+            self.first_minute += 1;
+        }
+        if self.first_minute == cutoff_minute {
+            if let Some(bucket) = self.buckets.front_mut() {
+                bucket.retain(|&(post_id, at)| {
+                    let keep = at >= cutoff;
+                    if !keep {
+                        due.push(post_id);
+                    }
+                    keep
+                });
+            }
+        }
+        self.taken_up_to = self.taken_up_to.max(cutoff);
+        self.len -= due.len();
+        due
+    }
+
+    /// Filed ids, including those of posts since removed
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Forget every filed id
+    pub fn clear(&mut self) {
+        self.buckets.clear();
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_takes_whole_buckets_and_splits_the_cutoff_minute() {
+        let mut index = TtlIndex::new();
+        index.insert(1, 600);
+        index.insert(2, 659);
+        index.insert(3, 720);
+        // Out of order, before the front of the ring
+        index.insert(4, 130);
+        index.insert(5, 1000);
+
+        let mut due = index.take_due(700);
+        due.sort();
+        assert_eq!(due, vec![1, 2, 4]);
+        assert_eq!(index.take_due(721), vec![3]);
+        assert_eq!(index.len(), 1);
+
+        // Ids already due are taken at the next trim, whatever their time
+        index.insert(6, 100);
+        index.insert(7, 800);
+        assert_eq!(index.take_due(722), vec![6]);
+        let mut due = index.take_due(10_000);
+        due.sort();
+        assert_eq!(due, vec![5, 7]);
+        assert!(index.is_empty() && index.take_due(20_000).is_empty());
+    }
+}
@@ -0,0 +1,233 @@
+//! Write-Ahead Log for Thunder ingestion
+//!
+//! Every post or delete event consumed from the stream is appended here before
+//! it is applied to the in-memory store. Appends are buffered and made durable
+//! together by `sync`, so a batch of events costs one fsync (group commit)
+//! rather than one each. The log is a directory of JSON-lines
+//! segments; a new segment is started once the active one passes the size
+//! limit and after every snapshot, and segments fully covered by a snapshot are
+//! removed. On startup the remaining segments are replayed on top of the
+//! restored snapshot, so recovery does not depend on stream retention.
+
+use std::fs;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::candidate_source::ThunderCandidate;
+
+const SEGMENT_PREFIX: &str = "wal-";
+const SEGMENT_EXTENSION: &str = "log";
+
+/// Change to the post store carried by the post-event stream
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PostEvent {
+    Upsert {
+        post: ThunderCandidate,
+    },
+    /// The author changed the post's text or attachments
+    Edit {
+        post_id: i64,
+        content: String,
+        has_media: bool,
+        has_link: bool,
+    },
+    Delete {
+        post_id: i64,
+    },
+}
+
+/// A post event with its position in the stream
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WalRecord {
+    pub partition: i32,
+    pub offset: i64,
+    pub event: PostEvent,
+}
+
+/// Append-only, segmented log of `WalRecord`s
+pub struct WriteAheadLog {
+    dir: PathBuf,
+    max_segment_bytes: u64,
+    segment: u64,
+    segment_bytes: u64,
+    writer: BufWriter<fs::File>,
+}
+
+impl WriteAheadLog {
+    /// Open the log in `dir`, starting a fresh segment after any existing ones
+    pub fn open(dir: impl AsRef<Path>, max_segment_bytes: u64) -> Result<Self, String> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+        let segment = segment_paths(&dir)?
+            .last()
+            .and_then(|(index, _)| index.checked_add(1))
+            .unwrap_or(0);
+        let writer = create_segment(&dir, segment)?;
+        Ok(Self {
+            dir,
+            max_segment_bytes,
+            segment,
+            segment_bytes: 0,
+            writer,
+        })
+    }
+
+    /// Append `record`, rotating to a new segment when the active one is full.
+    /// The record is durable only once `sync` returns.
+    pub fn append(&mut self, record: &WalRecord) -> Result<(), String> {
+        let mut line = serde_json::to_vec(record)
+            .map_err(|e| format!("failed to serialize WAL record: {}", e))?;
+        line.push(b'\n');
+        self.writer
+            .write_all(&line)
+            .map_err(|e| format!("failed to append to WAL: {}", e))?;
+        self.segment_bytes += line.len() as u64;
+
+        if self.segment_bytes >= self.max_segment_bytes {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    /// Make every record appended so far durable
+    pub fn sync(&mut self) -> Result<(), String> {
+        self.writer
+            .flush()
+            .and_then(|_| self.writer.get_ref().sync_data())
+            .map_err(|e| format!("failed to sync WAL: {}", e))
+    }
+
+    /// Sync the active segment and start a new one; returns its index. Records
+    /// appended from now on land in segments at or after that index.
+    pub fn rotate(&mut self) -> Result<u64, String> {
+        self.sync()?;
+        self.segment += 1;
+        self.writer = create_segment(&self.dir, self.segment)?;
+        self.segment_bytes = 0;
+        Ok(self.segment)
+    }
+
+    /// Delete segments older than `segment`, e.g. once a snapshot covers them
+    pub fn remove_segments_before(&self, segment: u64) -> Result<usize, String> {
+        let mut removed = 0;
+        for (index, path) in segment_paths(&self.dir)? {
+            if index >= segment {
+                break;
+            }
+            fs::remove_file(&path)
+                .map_err(|e| format!("failed to remove {}: {}", path.display(), e))?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    /// Feed every logged record to `apply`, oldest first. A torn final line (a crash
+    /// mid-append) ends that segment's replay. Returns the number of records read.
+    pub fn replay(&self, mut apply: impl FnMut(WalRecord)) -> Result<usize, String> {
+        let mut replayed = 0;
+        for (_, path) in segment_paths(&self.dir)? {
+            let file = fs::File::open(&path)
+                .map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+            for line in BufReader::new(file).lines() {
+                let line = line.map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+                match serde_json::from_str::<WalRecord>(&line) {
+                    Ok(record) => {
+                        apply(record);
+                        replayed += 1;
+                    }
+                    Err(e) => {
+                        warn!("Stopping replay of {} at a torn record: {}", path.display(), e);
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(replayed)
+    }
+}
+
+fn create_segment(dir: &Path, segment: u64) -> Result<BufWriter<fs::File>, String> {
+    let path = dir.join(format!("{}{:020}.{}", SEGMENT_PREFIX, segment, SEGMENT_EXTENSION));
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+    Ok(BufWriter::new(file))
+}
+
+/// Segment files with their index, oldest first
+fn segment_paths(dir: &Path) -> Result<Vec<(u64, PathBuf)>, String> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("failed to list {}: {}", dir.display(), e))?;
+    let mut segments: Vec<(u64, PathBuf)> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == SEGMENT_EXTENSION))
+        .filter_map(|path| {
+            let index = path
+                .file_stem()?
+                .to_str()?
+                .strip_prefix(SEGMENT_PREFIX)?
+                .parse()
+                .ok()?;
+            Some((index, path))
+        })
+        .collect();
+    segments.sort();
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upsert(offset: i64) -> WalRecord {
+        WalRecord {
+            partition: 0,
+            offset,
+            event: PostEvent::Upsert {
+                post: ThunderCandidate::new(offset, 100, "Post".into(), 1000),
+            },
+        }
+    }
+
+    #[test]
+    fn test_segments_rotate_replay_and_truncate() {
+        let dir = std::env::temp_dir().join(format!("thunder_wal_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut wal = WriteAheadLog::open(&dir, 200).unwrap();
+        for offset in 0..5 {
+            wal.append(&upsert(offset)).unwrap();
+        }
+        wal.append(&WalRecord {
+            partition: 0,
+            offset: 5,
+            event: PostEvent::Delete { post_id: 1 },
+        })
+        .unwrap();
+        wal.sync().unwrap();
+        assert!(segment_paths(&dir).unwrap().len() > 1);
+
+        // Simulate a crash mid-append on the active segment
+        let (_, active) = segment_paths(&dir).unwrap().pop().unwrap();
+        let mut file = fs::OpenOptions::new().append(true).open(&active).unwrap();
+        file.write_all(b"{\"partition\":0,\"off").unwrap();
+
+        let mut reopened = WriteAheadLog::open(&dir, 200).unwrap();
+        let mut offsets = Vec::new();
+        assert_eq!(reopened.replay(|r| offsets.push(r.offset)).unwrap(), 6);
+        assert_eq!(offsets, vec![0, 1, 2, 3, 4, 5]);
+
+        let segment = reopened.rotate().unwrap();
+        reopened.remove_segments_before(segment).unwrap();
+        assert_eq!(reopened.replay(|_| {}).unwrap(), 0);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}